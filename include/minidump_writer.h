@@ -0,0 +1,59 @@
+/* C API of the minidump-writer crate, see src/ffi.rs. Linux and Android only. */
+
+#ifndef MINIDUMP_WRITER_H
+#define MINIDUMP_WRITER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MDW_OK 0
+#define MDW_ERROR_INVALID_ARGUMENT -1
+#define MDW_ERROR_OUTPUT -2
+#define MDW_ERROR_DUMP -3
+#define MDW_ERROR_PANIC -4
+
+/*
+ * The options for writing a dump, zero-initialize it and set `struct_size` to
+ * use the defaults. New fields are only ever appended, so callers built
+ * against an older version of this header keep working.
+ */
+typedef struct mdw_options {
+    /* Must be set to sizeof(mdw_options) */
+    size_t struct_size;
+    /* Try to keep the dump below this size in bytes, 0 means no limit */
+    uint64_t minidump_size_limit;
+    /* The maximum number of threads to include, 0 means all of them */
+    uint32_t max_threads;
+    /* Replace the values in the stacks which don't look like pointers */
+    bool sanitize_stacks;
+} mdw_options;
+
+/* Returns the size of the crash context expected by mdw_write_dump(). */
+size_t mdw_crash_context_size(void);
+
+/*
+ * Write a dump of thread `tid` of process `pid` to the file at `path`.
+ *
+ * `crash_ctx` is optional, if not NULL it must point to a crash context as
+ * serialized by the Rust crash-context crate, of mdw_crash_context_size()
+ * bytes. Its process and thread take precedence over `pid` and `tid`.
+ * `options` may be NULL to use the defaults, MDW_ERROR_INVALID_ARGUMENT is
+ * returned if its `struct_size` isn't valid.
+ *
+ * Returns MDW_OK or one of the MDW_ERROR_* codes. Panics of the writer don't
+ * unwind into the caller, they are reported as MDW_ERROR_PANIC.
+ */
+int mdw_write_dump(pid_t pid, pid_t tid, const void *crash_ctx, const char *path,
+                   const mdw_options *options);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MINIDUMP_WRITER_H */
@@ -0,0 +1,234 @@
+//! Writes a minidump of a running process on demand.
+//!
+//! ```text
+//! minidump-writer <pid> [--tid <tid>] [--output <path>] [--size-limit <bytes>]
+//!                 [--max-threads <count>] [--sanitize-stacks]
+//! ```
+//!
+//! The blamed thread defaults to the main thread, and the dump is written to
+//! `<pid>.dmp` in the current directory unless `--output` is given.
+//!
+//! With `--core-pattern <signal> <timestamp>`, the minidump is converted
+//! from the ELF core dump of the process read from the standard input
+//! instead, so that it can be the kernel's `core_pattern` handler, e.g.
+//!
+//! ```text
+//! |/usr/bin/minidump-writer %p --core-pattern %s %t --output /var/crash/%p-%t.dmp
+//! ```
+//!
+//! The signal is blamed if the core doesn't say which one caused it, and the
+//! time of the minidump is the time of the crash. The options other than
+//! `--output` only apply to running processes.
+
+type Error = Box<dyn std::error::Error + std::marker::Send + std::marker::Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+const USAGE: &str = "Usage: minidump-writer <pid> [--tid <tid>] [--output <path>] \
+[--size-limit <bytes>] [--max-threads <count>] [--sanitize-stacks]
+       minidump-writer <pid> --core-pattern <signal> <timestamp> [--output <path>] < core";
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux {
+    use super::*;
+    use minidump_writer::{minidump_writer::MinidumpWriter, Pid};
+    use std::path::{Path, PathBuf};
+
+    /// The parameters of a `core_pattern` handler, `%s` and `%t`
+    #[derive(Debug, PartialEq)]
+    struct CorePattern {
+        signal: u32,
+        timestamp: u64,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Options {
+        pid: Pid,
+        tid: Option<Pid>,
+        output: Option<PathBuf>,
+        size_limit: Option<u64>,
+        max_threads: Option<usize>,
+        sanitize_stacks: bool,
+        core_pattern: Option<CorePattern>,
+    }
+
+    fn parse_args(args: Vec<String>) -> Result<Options> {
+        fn value<T>(args: &mut impl Iterator<Item = String>, name: &str) -> Result<T>
+        where
+            T: std::str::FromStr,
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            let value = args.next().ok_or(format!("Missing value for {name}"))?;
+            Ok(value.parse()?)
+        }
+
+        let mut args = args.into_iter();
+        let mut pid = None;
+        let mut options = Options {
+            pid: 0,
+            tid: None,
+            output: None,
+            size_limit: None,
+            max_threads: None,
+            sanitize_stacks: false,
+            core_pattern: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--tid" => options.tid = Some(value(&mut args, &arg)?),
+                "--output" => options.output = Some(value(&mut args, &arg)?),
+                "--size-limit" => options.size_limit = Some(value(&mut args, &arg)?),
+                "--max-threads" => options.max_threads = Some(value(&mut args, &arg)?),
+                "--sanitize-stacks" => options.sanitize_stacks = true,
+                "--core-pattern" => {
+                    options.core_pattern = Some(CorePattern {
+                        signal: value(&mut args, &arg)?,
+                        timestamp: value(&mut args, &arg)?,
+                    })
+                }
+                _ if pid.is_none() && !arg.starts_with('-') => pid = Some(arg.parse()?),
+                _ => return Err(format!("Unexpected argument {arg}").into()),
+            }
+        }
+        options.pid = pid.ok_or("Missing pid")?;
+        if options.core_pattern.is_some()
+            && (options.tid.is_some()
+                || options.size_limit.is_some()
+                || options.max_threads.is_some()
+                || options.sanitize_stacks)
+        {
+            return Err("Only --output applies with --core-pattern".into());
+        }
+        Ok(options)
+    }
+
+    pub(super) fn real_main(args: Vec<String>) -> Result<()> {
+        let options = parse_args(args).map_err(|e| format!("{e}\n{USAGE}"))?;
+        let output = options
+            .output
+            .unwrap_or_else(|| PathBuf::from(format!("{}.dmp", options.pid)));
+        if let Some(core_pattern) = options.core_pattern {
+            convert_core_dump(&core_pattern, &output)?;
+            println!("{}", output.display());
+            return Ok(());
+        }
+
+        let mut builder =
+            MinidumpWriter::builder().process(options.pid, options.tid.unwrap_or(options.pid));
+        if let Some(limit) = options.size_limit {
+            builder = builder.minidump_size_limit(limit);
+        }
+        if let Some(max_threads) = options.max_threads {
+            builder = builder.max_threads(max_threads);
+        }
+        if options.sanitize_stacks {
+            builder = builder.sanitize_stacks();
+        }
+
+        let mut file = std::fs::File::create(&output)
+            .map_err(|e| format!("Could not create {}: {e}", output.display()))?;
+        builder.write(&mut file)?;
+        println!("{}", output.display());
+        Ok(())
+    }
+
+    /// Convert the core dump read from the standard input to a minidump
+    /// written to `output`.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn convert_core_dump(core_pattern: &CorePattern, output: &Path) -> Result<()> {
+        use minidump_writer::core_dump::CoreDump;
+        use std::{
+            io::Read,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        // The kernel pipes the core, which can't be mapped like a file
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+        let mut core = CoreDump::from_bytes(bytes)?;
+        if core.signal() == 0 {
+            core.set_signal(core_pattern.signal);
+        }
+
+        let mut writer = core.minidump_writer();
+        let crash_time = UNIX_EPOCH + Duration::from_secs(core_pattern.timestamp);
+        writer.deterministic(move || crash_time);
+        let mut file = std::fs::File::create(output)
+            .map_err(|e| format!("Could not create {}: {e}", output.display()))?;
+        core.write_minidump(&mut writer, &mut file)?;
+        Ok(())
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn convert_core_dump(_core_pattern: &CorePattern, _output: &Path) -> Result<()> {
+        Err("Converting core dumps is not supported on this architecture".into())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn args(args: &[&str]) -> Vec<String> {
+            args.iter().map(|arg| arg.to_string()).collect()
+        }
+
+        #[test]
+        fn test_parse_args() {
+            let options = parse_args(args(&[
+                "--max-threads",
+                "4",
+                "123",
+                "--tid",
+                "124",
+                "--sanitize-stacks",
+            ]))
+            .unwrap();
+            assert_eq!(
+                options,
+                Options {
+                    pid: 123,
+                    tid: Some(124),
+                    output: None,
+                    size_limit: None,
+                    max_threads: Some(4),
+                    sanitize_stacks: true,
+                    core_pattern: None,
+                }
+            );
+
+            let options = parse_args(args(&["123", "--core-pattern", "11", "1700000000"])).unwrap();
+            assert_eq!(
+                options.core_pattern,
+                Some(CorePattern {
+                    signal: 11,
+                    timestamp: 1700000000,
+                })
+            );
+
+            assert!(parse_args(args(&[])).is_err());
+            assert!(parse_args(args(&["123", "--tid"])).is_err());
+            assert!(parse_args(args(&["123", "456"])).is_err());
+            assert!(parse_args(args(&["123", "--size-limit", "lots"])).is_err());
+            assert!(parse_args(args(&["123", "--core-pattern", "11"])).is_err());
+            assert!(parse_args(args(&["123", "--core-pattern", "11", "0", "--tid", "1"])).is_err());
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+
+    let result: Result<()> = {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                linux::real_main(args)
+            } else {
+                let _ = args;
+                Err(format!("Dumping other processes is not supported on this platform\n{USAGE}").into())
+            }
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
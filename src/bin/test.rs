@@ -8,7 +8,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 mod linux {
     use super::*;
     use minidump_writer::{
-        minidump_writer::STOP_TIMEOUT, module_reader, ptrace_dumper::PtraceDumper,
+        minidump_writer::STOP_TIMEOUT, module_reader, ptrace_dumper::PtraceDumper, registry,
         LINUX_GATE_LIBRARY_NAME,
     };
     use nix::{
@@ -183,8 +183,11 @@ mod linux {
             if mapping.name == Some(LINUX_GATE_LIBRARY_NAME.into()) {
                 found_linux_gate = true;
                 dumper.suspend_threads()?;
-                let module_reader::BuildId(id) =
-                    PtraceDumper::from_process_memory_for_mapping(&mapping, ppid)?;
+                let module_reader::BuildId(id) = PtraceDumper::from_process_memory_for_mapping(
+                    &mapping,
+                    ppid,
+                    &Default::default(),
+                )?;
                 test!(!id.is_empty(), "id-vec is empty");
                 test!(id.iter().any(|&x| x > 0), "all id elements are 0");
                 dumper.resume_threads()?;
@@ -294,6 +297,123 @@ mod linux {
         }
     }
 
+    fn spawn_log_ring_wait() -> Result<()> {
+        // Log more than fits, so that the ring wraps around
+        let mut ring = [0u8; 16];
+        let mut head = 0u64;
+        for byte in b"one\ntwo\nthree\nfour\n" {
+            ring[head as usize % ring.len()] = *byte;
+            head += 1;
+        }
+
+        println!("{:p} {} {:p}", ring.as_ptr(), ring.len(), &head);
+        loop {
+            std::thread::park();
+        }
+    }
+
+    fn spawn_registry_wait() -> Result<()> {
+        let mut registry = registry::Registry::new(4)?;
+        registry.register("version", registry::BlockKind::Text, b"1.2.3")?;
+
+        println!("{:#x}", registry.address());
+        loop {
+            std::thread::park();
+        }
+    }
+
+    fn spawn_perf_map_wait() -> Result<()> {
+        let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE).unwrap();
+        let memory_size = std::num::NonZeroUsize::new(page_size.unwrap() as usize).unwrap();
+        // Stands in for the code a JIT compiler would generate
+        let code = unsafe {
+            mmap_anonymous(
+                None,
+                memory_size,
+                ProtFlags::PROT_READ | ProtFlags::PROT_EXEC,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANON,
+            )?
+        };
+        let code = code.as_ptr() as usize;
+        std::fs::write(
+            format!("/tmp/perf-{}.map", std::process::id()),
+            format!(
+                "{code:x} 40 jitted_function\n{:x} 20 other_function\n",
+                code + 0x40
+            ),
+        )?;
+
+        println!("{code:#x}");
+        loop {
+            std::thread::park();
+        }
+    }
+
+    fn spawn_altstack_wait() -> Result<()> {
+        use nix::sys::signal::{raise, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+        use std::sync::OnceLock;
+
+        static MESSAGE: OnceLock<String> = OnceLock::new();
+
+        extern "C" fn handler(_: libc::c_int) {
+            // Only async-signal-safe calls in here, the message is formatted
+            // beforehand
+            if let Some(message) = MESSAGE.get() {
+                unsafe { libc::write(1, message.as_ptr().cast(), message.len()) };
+            }
+            loop {
+                unsafe { libc::pause() };
+            }
+        }
+
+        let altstack = Box::leak(vec![0u8; 64 * 1024].into_boxed_slice());
+        let stack = libc::stack_t {
+            ss_sp: altstack.as_mut_ptr().cast(),
+            ss_flags: 0,
+            ss_size: altstack.len(),
+        };
+        test!(
+            unsafe { libc::sigaltstack(&stack, std::ptr::null_mut()) } == 0,
+            "sigaltstack failed"
+        );
+        let _ = MESSAGE.set(format!("{:p} {}\n", altstack.as_ptr(), altstack.len()));
+
+        let action = SigAction::new(
+            SigHandler::Handler(handler),
+            SaFlags::SA_ONSTACK,
+            SigSet::empty(),
+        );
+        unsafe { sigaction(Signal::SIGUSR1, &action)? };
+        raise(Signal::SIGUSR1)?;
+        unreachable!("the signal handler never returns");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn spawn_shadow_stack_wait() -> Result<()> {
+        const ARCH_SHSTK_ENABLE: usize = 0x5001;
+        const ARCH_SHSTK_SHSTK: usize = 1;
+
+        // The shadow stack only holds the frames called once it's enabled, so
+        // this must never return afterwards, nor go through the libc wrapper
+        let ret: isize;
+        unsafe {
+            std::arch::asm!(
+                "syscall",
+                inlateout("rax") libc::SYS_arch_prctl as isize => ret,
+                in("rdi") ARCH_SHSTK_ENABLE,
+                in("rsi") ARCH_SHSTK_SHSTK,
+                lateout("rcx") _,
+                lateout("r11") _,
+                options(nostack),
+            );
+        }
+        // Shadow stacks need support from the CPU and the kernel
+        println!("{}", if ret == 0 { "enabled" } else { "unsupported" });
+        loop {
+            std::thread::park();
+        }
+    }
+
     fn create_files_wait(num: usize) -> Result<()> {
         let mut file_array = Vec::<tempfile::NamedTempFile>::with_capacity(num);
         for id in 0..num {
@@ -325,6 +445,12 @@ mod linux {
                 "linux_gate_mapping_id" => test_linux_gate_mapping_id(),
                 "spawn_mmap_wait" => spawn_mmap_wait(),
                 "spawn_alloc_wait" => spawn_alloc_wait(),
+                "spawn_log_ring_wait" => spawn_log_ring_wait(),
+                "spawn_registry_wait" => spawn_registry_wait(),
+                "spawn_altstack_wait" => spawn_altstack_wait(),
+                "spawn_perf_map_wait" => spawn_perf_map_wait(),
+                #[cfg(target_arch = "x86_64")]
+                "spawn_shadow_stack_wait" => spawn_shadow_stack_wait(),
                 _ => Err("Len 1: Unknown test option".into()),
             },
             2 => match args[0].as_ref() {
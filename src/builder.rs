@@ -0,0 +1,424 @@
+//! A builder for writing minidumps with the same API on every platform.
+//!
+//! ```no_run
+//! # fn write(crash_context: crash_context::CrashContext) {
+//! let mut file = std::fs::File::create("crash.dmp").unwrap();
+//! minidump_writer::minidump_writer::MinidumpWriter::builder()
+//!     .crash_context(crash_context)
+//!     .minidump_size_limit(64 * 1024 * 1024)
+//!     .write(&mut file)
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Options which a platform doesn't support are ignored there, so that they
+//! can be set unconditionally.
+//!
+//! The minidump can be written to any seekable writer, not only to files, e.g.
+//! to a `Cursor<Vec<u8>>` to keep it in memory.
+//!
+//! Writing returns a [`DumpResult`] describing what was written, for
+//! telemetry and for deciding whether dumping again is worth it.
+
+// `WriterError` is large on Linux, see `crate::linux`
+#![allow(clippy::result_large_err)]
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    io::{Seek, Write},
+    time::Duration,
+};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        /// The error returned when writing a minidump fails
+        pub type Error = crate::errors::WriterError;
+    } else if #[cfg(target_os = "windows")] {
+        /// The error returned when writing a minidump fails
+        pub type Error = crate::errors::Error;
+    } else if #[cfg(target_os = "macos")] {
+        /// The error returned when writing a minidump fails
+        pub type Error = crate::errors::WriterError;
+    }
+}
+
+/// A stream listed in the directory of a minidump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub stream_type: u32,
+    pub rva: u32,
+    pub size: u32,
+}
+
+/// What was written by [`MinidumpWriterBuilder::write`], or by the methods of
+/// the platform's `MinidumpWriter` which write a minidump to a destination.
+#[derive(Debug, Clone, Default)]
+pub struct DumpResult {
+    /// The size of the minidump in bytes
+    pub size: u64,
+    /// The streams of the minidump, in directory order, without the unused
+    /// directory entries
+    pub streams: Vec<StreamInfo>,
+    /// The bytes of process memory captured, including the thread stacks
+    pub memory_bytes: u64,
+    /// The time spent in each phase of the dump, in order. Only Linux reports
+    /// separate phases, elsewhere there's a single `write` phase.
+    pub phases: Vec<(&'static str, Duration)>,
+    /// The errors which didn't prevent writing the minidump but may have left
+    /// information out of it. Not reported on Windows.
+    pub soft_errors: Vec<String>,
+    /// The optional parts of the minidump, e.g. the module list, which were
+    /// left out because writing them failed, the reason being among the
+    /// soft errors. Not reported on Windows.
+    pub omitted_sections: Vec<&'static str>,
+    /// The SHA-256 digest of the minidump, computed as it was written. Only
+    /// reported by the Linux `MinidumpWriter::dump_with_digest`.
+    #[cfg(feature = "sha2")]
+    pub sha256: Option<[u8; 32]>,
+}
+
+impl DumpResult {
+    /// Describe the minidump laid out in `dump`, from the entries written to
+    /// its directory. The empty entries of the streams which were left out
+    /// are skipped.
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    pub(crate) fn from_dir_entries(
+        dump: &[u8],
+        entries: &[crate::minidump_format::MDRawDirectory],
+    ) -> Self {
+        let mut result = Self {
+            size: dump.len() as u64,
+            ..Default::default()
+        };
+        for dirent in entries.iter().filter(|dirent| dirent.stream_type != 0) {
+            result.streams.push(StreamInfo {
+                stream_type: dirent.stream_type,
+                rva: dirent.location.rva,
+                size: dirent.location.data_size,
+            });
+            result.memory_bytes +=
+                memory_bytes(dump, dirent.stream_type, dirent.location.rva as usize);
+        }
+        result
+    }
+
+    /// Describe the streams and the memory of the minidump in `dump`, written
+    /// by `MiniDumpWriteDump`, whose directory has to be read back.
+    #[cfg(target_os = "windows")]
+    fn from_minidump(dump: &[u8]) -> Self {
+        let mut result = Self {
+            size: dump.len() as u64,
+            ..Default::default()
+        };
+
+        let stream_count = read_u32(dump, 8).unwrap_or_default() as usize;
+        let directory = read_u32(dump, 12).unwrap_or_default() as usize;
+        for idx in 0..stream_count {
+            let entry = directory + idx * 12;
+            let (Some(stream_type), Some(size), Some(rva)) = (
+                read_u32(dump, entry),
+                read_u32(dump, entry + 4),
+                read_u32(dump, entry + 8),
+            ) else {
+                break;
+            };
+            if stream_type == 0 {
+                continue;
+            }
+            result.streams.push(StreamInfo {
+                stream_type,
+                rva,
+                size,
+            });
+            result.memory_bytes += memory_bytes(dump, stream_type, rva as usize);
+        }
+        result
+    }
+}
+
+/// The bytes of process memory held by a MemoryListStream or
+/// Memory64ListStream at `rva`, 0 for other streams.
+fn memory_bytes(dump: &[u8], stream_type: u32, rva: usize) -> u64 {
+    use crate::minidump_format::MDStreamType;
+
+    if stream_type == MDStreamType::MemoryListStream as u32 {
+        // A count followed by descriptors of 16 bytes, the size being at 8
+        let count = read_u32(dump, rva).unwrap_or_default() as usize;
+        (0..count)
+            .map_while(|idx| read_u32(dump, rva + 4 + idx * 16 + 8))
+            .map(u64::from)
+            .sum()
+    } else if stream_type == MDStreamType::Memory64ListStream as u32 {
+        // A count and the RVA of the memory, followed by descriptors of 16
+        // bytes, the size being at 8
+        let count = read_u64(dump, rva).unwrap_or_default() as usize;
+        (0..count)
+            .map_while(|idx| read_u64(dump, rva + 16 + idx * 16 + 8))
+            .sum()
+    } else {
+        0
+    }
+}
+
+fn read_u32(dump: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        dump.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(dump: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        dump.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Collects the options for writing a minidump, see the [module
+/// documentation](self).
+#[derive(Default)]
+pub struct MinidumpWriterBuilder {
+    crash_context: Option<crash_context::CrashContext>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    process: Option<(crate::Pid, crate::Pid)>,
+    #[cfg(target_os = "windows")]
+    minidump_type: Option<crate::MinidumpType>,
+    minidump_size_limit: Option<u64>,
+    max_threads: Option<usize>,
+    sanitize_stacks: bool,
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    unloaded_modules: Option<crate::unloaded_modules::UnloadedModuleList>,
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    register_memory_budget: Option<usize>,
+}
+
+impl MinidumpWriterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The crash context captured by the exception handler, which identifies
+    /// the crashed process and thread. Without one the current process is
+    /// dumped, which isn't possible on Linux, see [`Self::process`].
+    pub fn crash_context(mut self, crash_context: crash_context::CrashContext) -> Self {
+        self.crash_context = Some(crash_context);
+        self
+    }
+
+    /// Dump process `pid`, blaming thread `blamed_thread`, without a crash
+    /// context. This is ignored if a crash context was provided.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn process(mut self, pid: crate::Pid, blamed_thread: crate::Pid) -> Self {
+        self.process = Some((pid, blamed_thread));
+        self
+    }
+
+    /// The kind of information to include in the minidump.
+    #[cfg(target_os = "windows")]
+    pub fn minidump_type(mut self, minidump_type: crate::MinidumpType) -> Self {
+        self.minidump_type = Some(minidump_type);
+        self
+    }
+
+    /// Try to keep the minidump below `limit` bytes by truncating the stacks
+    /// of the threads. Only supported on Linux.
+    pub fn minidump_size_limit(mut self, limit: u64) -> Self {
+        self.minidump_size_limit = Some(limit);
+        self
+    }
+
+    /// Only include up to `max_threads` threads, picking the most relevant
+    /// ones. Only supported on Linux.
+    pub fn max_threads(mut self, max_threads: usize) -> Self {
+        self.max_threads = Some(max_threads);
+        self
+    }
+
+    /// Replace the values in the stacks which don't look like pointers to code
+    /// or to the stack, to avoid leaking user data. Only supported on Linux.
+    pub fn sanitize_stacks(mut self) -> Self {
+        self.sanitize_stacks = true;
+        self
+    }
+
+    /// The modules which were unloaded from the process, as reported by the
+    /// application, see [`crate::unloaded_modules`]. Ignored on Windows,
+    /// which keeps track of them itself.
+    pub fn unloaded_module_list(
+        mut self,
+        unloaded_modules: crate::unloaded_modules::UnloadedModuleList,
+    ) -> Self {
+        self.unloaded_modules = Some(unloaded_modules);
+        self
+    }
+
+    /// Capture the memory pointed to by the registers of the crashing thread,
+    /// up to `budget` bytes in total. Ignored on Windows.
+    pub fn register_memory(mut self, budget: usize) -> Self {
+        self.register_memory_budget = Some(budget);
+        self
+    }
+
+    /// Write the minidump to `destination`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn write(self, destination: &mut (impl Write + Seek)) -> Result<DumpResult, Error> {
+        use crate::{crash_context::CrashContext, minidump_writer::MinidumpWriter};
+
+        let (pid, blamed_thread) = match &self.crash_context {
+            Some(crash_context) => (crash_context.pid, crash_context.tid),
+            None => self
+                .process
+                .ok_or(crate::errors::InitError::NoProcessToDump)?,
+        };
+
+        let mut writer = MinidumpWriter::new(pid, blamed_thread);
+        if let Some(crash_context) = self.crash_context {
+            writer.set_crash_context(CrashContext {
+                inner: crash_context,
+            });
+        }
+        if let Some(limit) = self.minidump_size_limit {
+            writer.set_minidump_size_limit(limit);
+        }
+        if let Some(max_threads) = self.max_threads {
+            writer.set_max_threads(max_threads);
+        }
+        if self.sanitize_stacks {
+            writer.sanitize_stack();
+        }
+        if let Some(unloaded_modules) = self.unloaded_modules {
+            writer.set_unloaded_module_list(unloaded_modules);
+        }
+        if let Some(budget) = self.register_memory_budget {
+            writer.capture_register_memory(budget);
+        }
+        writer.dump(destination)
+    }
+
+    /// Write the minidump to `destination` without blocking the caller, for
+    /// monitors written with an async runtime which dump several processes
+    /// concurrently.
+    ///
+    /// All the ptrace requests have to come from the thread which attached to
+    /// the process, so the dump is written by a new thread which the returned
+    /// future waits on. The future doesn't depend on any specific runtime.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn write_async<W>(self, mut destination: W) -> WriteFuture
+    where
+        W: Write + Seek + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(WriteState::default()));
+        let writer_shared = shared.clone();
+        std::thread::spawn(move || {
+            let guard = FinishGuard(writer_shared);
+            let result = self.write(&mut destination);
+            guard.0.lock().unwrap().result = Some(result);
+        });
+        WriteFuture { shared }
+    }
+
+    /// Write the minidump to `destination`.
+    ///
+    /// # Safety
+    ///
+    /// The exception pointers of the crash context, if any, must be valid for
+    /// the duration of the call, see
+    /// [`MinidumpWriter::dump_crash_context`](crate::minidump_writer::MinidumpWriter::dump_crash_context).
+    ///
+    /// `MiniDumpWriteDump` can only write to files, so the minidump is written
+    /// to a temporary file first, then copied to `destination`.
+    #[cfg(target_os = "windows")]
+    pub fn write(self, destination: &mut (impl Write + Seek)) -> Result<DumpResult, Error> {
+        use crate::minidump_writer::MinidumpWriter;
+        use std::io::Read;
+
+        let start = std::time::Instant::now();
+        let mut file = tempfile::tempfile()?;
+        match self.crash_context {
+            Some(crash_context) => {
+                MinidumpWriter::dump_crash_context(crash_context, self.minidump_type, &mut file)
+            }
+            None => MinidumpWriter::dump_local_context(None, None, self.minidump_type, &mut file),
+        }?;
+
+        file.rewind()?;
+        let mut dump = Vec::new();
+        file.read_to_end(&mut dump)?;
+        destination.write_all(&dump)?;
+        destination.flush()?;
+
+        Ok(DumpResult {
+            phases: vec![("write", start.elapsed())],
+            ..DumpResult::from_minidump(&dump)
+        })
+    }
+
+    /// Write the minidump to `destination`.
+    #[cfg(target_os = "macos")]
+    pub fn write(self, destination: &mut (impl Write + Seek)) -> Result<DumpResult, Error> {
+        use crate::minidump_writer::MinidumpWriter;
+
+        let mut writer = match self.crash_context {
+            Some(crash_context) => MinidumpWriter::with_crash_context(crash_context),
+            None => MinidumpWriter::new(None, None),
+        };
+        if let Some(unloaded_modules) = self.unloaded_modules {
+            writer.set_unloaded_module_list(unloaded_modules);
+        }
+        if let Some(budget) = self.register_memory_budget {
+            writer.capture_register_memory(budget);
+        }
+        writer.dump(destination)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Default)]
+struct WriteState {
+    result: Option<Result<DumpResult, Error>>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// Marks the write as finished and wakes the future when the writing thread
+/// is done, even if it panicked.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct FinishGuard(Arc<Mutex<WriteState>>);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.finished = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The future returned by [`MinidumpWriterBuilder::write_async`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct WriteFuture {
+    shared: Arc<Mutex<WriteState>>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Future for WriteFuture {
+    type Output = Result<DumpResult, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(result) = state.result.take() {
+            Poll::Ready(result)
+        } else if state.finished {
+            panic!("The thread writing the minidump panicked");
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Limits on the counts and sizes read from the process being dumped.
+//!
+//! The process may well have corrupted the structures the writer walks, e.g.
+//! the list of loaded modules or the headers of a module, so the counts and
+//! sizes read from them can't be trusted to drive allocations. Every such
+//! value is checked against [`CaptureLimits`], the defaults being far above
+//! what a healthy process uses. When a limit is exceeded what it applies to
+//! is read from elsewhere when possible, e.g. the headers of a module from
+//! its file, and otherwise left out or truncated, which the minidump records
+//! as a soft error.
+
+/// The largest counts and sizes read from the process which are trusted,
+/// see `MinidumpWriter::set_capture_limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct CaptureLimits {
+    /// The most threads captured, on Linux
+    pub max_threads: usize,
+    /// The most loaded modules listed by the dynamic linker
+    pub max_modules: usize,
+    /// The longest string read, e.g. the path of a module
+    pub max_string_len: usize,
+    /// The largest segment, section or table of a module read at once
+    pub max_segment_size: usize,
+    /// The largest memory region captured, on Linux, e.g. a stack, an
+    /// application memory region or a mapping with full memory. Larger ones
+    /// are truncated, so that a corrupted length can't make the writer read
+    /// and write terabytes.
+    pub max_region_size: usize,
+}
+
+impl Default for CaptureLimits {
+    fn default() -> Self {
+        Self {
+            max_threads: 16 * 1024,
+            max_modules: 8 * 1024,
+            max_string_len: 8 * 1024,
+            max_segment_size: 64 * 1024 * 1024,
+            max_region_size: (4u64 << 30).min(usize::MAX as u64) as usize,
+        }
+    }
+}
+
+/// A count or size read from the process which was over its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the {what} read from the process, {value}, is over the limit of {limit}")]
+pub struct LimitExceeded {
+    /// What was read, e.g. `"image count"`
+    pub what: &'static str,
+    pub value: u64,
+    pub limit: u64,
+}
+
+/// Check the `what` read from the process, `value`, against its `limit`.
+pub(crate) fn check(what: &'static str, value: u64, limit: usize) -> Result<(), LimitExceeded> {
+    if value <= limit as u64 {
+        Ok(())
+    } else {
+        Err(LimitExceeded {
+            what,
+            value,
+            limit: limit as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("image count", 10, 10), Ok(()));
+        let error = check("image count", u32::MAX.into(), 10).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the image count read from the process, 4294967295, is over the limit of 10"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! zstd-compressed minidumps, available with the `zstd` feature.
+//!
+//! Minidumps including a lot of memory compress very well, so they can be
+//! written compressed to save space and upload bandwidth. The compressed data
+//! is preceded by a [`Header`], which lets tools tell a compressed minidump
+//! apart from a plain one (starting with `MDMP`) and check that it was fully
+//! decompressed.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+/// The first bytes of a compressed minidump
+pub const MAGIC: [u8; 4] = *b"MDZS";
+/// The version of the container written by [`write_compressed`]
+pub const VERSION: u32 = 1;
+/// The default zstd compression level, which favors speed as the minidump is
+/// usually compressed while the crashed process is still suspended
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// The header of a compressed minidump, all fields are little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// The version of the container, see [`VERSION`]
+    pub version: u32,
+    /// The size of the minidump once decompressed
+    pub uncompressed_size: u64,
+}
+
+impl Header {
+    /// The size of the header, including the [`MAGIC`]
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.uncompressed_size.to_le_bytes());
+        bytes
+    }
+
+    /// Parse the header at the start of `bytes`, returning `None` if they
+    /// don't start with the [`MAGIC`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        if bytes[..4] != MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            version: u32::from_le_bytes(bytes[4..8].try_into().ok()?),
+            uncompressed_size: u64::from_le_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+}
+
+/// Return whether `bytes` start like a compressed minidump.
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Write `dump` to `destination` compressed with the given zstd `level`,
+/// preceded by a [`Header`].
+pub fn write_compressed(dump: &[u8], destination: &mut impl Write, level: i32) -> Result<()> {
+    let header = Header {
+        version: VERSION,
+        uncompressed_size: dump.len() as u64,
+    };
+    destination.write_all(&header.to_bytes())?;
+    zstd::stream::copy_encode(dump, &mut *destination, level)?;
+    destination.flush()
+}
+
+/// Read a compressed minidump from `source`, returning the plain minidump.
+///
+/// # Errors
+///
+/// If `source` doesn't contain a compressed minidump, if its version isn't
+/// supported, or if it is truncated.
+pub fn decompress(mut source: impl Read) -> Result<Vec<u8>> {
+    let mut header = [0u8; Header::SIZE];
+    source.read_exact(&mut header)?;
+    let header = Header::from_bytes(&header)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "not a compressed minidump"))?;
+    if header.version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported compressed minidump version {}", header.version),
+        ));
+    }
+
+    let mut dump = Vec::with_capacity(header.uncompressed_size as usize);
+    zstd::stream::copy_decode(source, &mut dump)?;
+    if dump.len() as u64 != header.uncompressed_size {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "compressed minidump is truncated, expected {} bytes but got {}",
+                header.uncompressed_size,
+                dump.len()
+            ),
+        ));
+    }
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dump: Vec<u8> = b"MDMP".iter().copied().chain([0u8; 4096]).collect();
+
+        let mut compressed = Vec::new();
+        write_compressed(&dump, &mut compressed, DEFAULT_LEVEL).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < dump.len());
+        assert_eq!(
+            Header::from_bytes(&compressed),
+            Some(Header {
+                version: VERSION,
+                uncompressed_size: dump.len() as u64,
+            })
+        );
+
+        assert_eq!(decompress(compressed.as_slice()).unwrap(), dump);
+        assert!(decompress(&compressed[..compressed.len() / 2]).is_err());
+        assert!(decompress(dump.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! The reason of a crash given by the application, e.g. the message of a
+//! `MOZ_CRASH()`-style macro, of an abort or of a panic, which crash servers
+//! use to bucket crashes whose signal or exception alone doesn't tell them
+//! apart, see `MinidumpWriter::set_crash_reason`.
+//!
+//! It's written as UTF-8 text to a `CrashReason` stream, truncated to
+//! [`MAX_CRASH_REASON_LEN`] bytes.
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{MemoryArrayWriter, MemoryWriterError},
+    minidump_format::{MDExtraStreamType, MDRawDirectory},
+};
+
+/// The longest crash reason kept, in bytes
+pub const MAX_CRASH_REASON_LEN: usize = 1024;
+
+/// `reason` truncated to [`MAX_CRASH_REASON_LEN`] bytes, without splitting a
+/// character.
+pub(crate) fn truncate(mut reason: String) -> String {
+    if reason.len() > MAX_CRASH_REASON_LEN {
+        let mut len = MAX_CRASH_REASON_LEN;
+        while !reason.is_char_boundary(len) {
+            len -= 1;
+        }
+        reason.truncate(len);
+    }
+    reason
+}
+
+/// Write the `CrashReason` stream. Nothing is written without a reason.
+pub(crate) fn write(
+    buffer: &mut DumpBuf,
+    reason: Option<&str>,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    let Some(reason) = reason else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    let section = MemoryArrayWriter::write_bytes(buffer, reason.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::CrashReason as u32,
+        location: section.location(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("MOZ_CRASH(oops)".to_owned()), "MOZ_CRASH(oops)");
+        let long = "é".repeat(MAX_CRASH_REASON_LEN);
+        let truncated = truncate(format!("x{long}"));
+        // The last character doesn't fit as a whole
+        assert_eq!(truncated.len(), MAX_CRASH_REASON_LEN - 1);
+        assert!(truncated.ends_with('é'));
+    }
+}
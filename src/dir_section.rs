@@ -1,9 +1,11 @@
 use crate::{
     mem_writer::{Buffer, MemoryArrayWriter, MemoryWriterError},
-    minidump_format::MDRawDirectory,
+    minidump_format::{MDLocationDescriptor, MDRawDirectory},
 };
-use std::io::{Error, Seek, Write};
+use scroll::Pread;
+use std::io::{Error, Seek, SeekFrom, Write};
 
+/// The buffer minidumps are laid out in, see [`crate::mem_writer`].
 pub type DumpBuf = Buffer;
 
 #[derive(Debug, thiserror::Error)]
@@ -14,6 +16,92 @@ pub enum FileWriterError {
     MemoryWriterError(#[from] MemoryWriterError),
 }
 
+/// A destination which discards everything written to it, only keeping track
+/// of its position.
+///
+/// The complete minidump is also laid out in the [`DumpBuf`], so writing to
+/// this lets the buffer be emitted later with a single forward-only write.
+#[derive(Debug, Default)]
+pub struct DiscardSink {
+    position: u64,
+    len: u64,
+}
+
+impl Write for DiscardSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.position += buf.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for DiscardSink {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// A destination which computes the SHA-256 digest of everything written to
+/// it on the way to `inner`, so that it doesn't have to be read back.
+///
+/// Only forward-only destinations are supported, as rewriting a part which
+/// was hashed already would invalidate the digest.
+#[cfg(feature = "sha2")]
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: sha2::Sha256,
+}
+
+#[cfg(feature = "sha2")]
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        use sha2::Digest;
+
+        Self {
+            inner,
+            hasher: sha2::Sha256::new(),
+        }
+    }
+
+    /// The digest of the bytes written so far
+    pub fn digest(&self) -> [u8; 32] {
+        use sha2::Digest;
+
+        self.hasher.clone().finalize().into()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        use sha2::Digest;
+
+        // Only hash what the destination actually took
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// Utility that wraps writing minidump directory entries to an I/O stream, generally
 /// a [`std::fs::File`].
 #[derive(Debug)]
@@ -27,6 +115,8 @@ where
     destination_start_offset: u64,
     destination: &'a mut W,
     last_position_written_to_file: u64,
+    #[cfg(feature = "tracing")]
+    last_write: std::time::Instant,
 }
 
 impl<'a, W> DirSection<'a, W>
@@ -47,6 +137,8 @@ where
             destination_start_offset: destination.stream_position()?,
             destination,
             last_position_written_to_file: 0,
+            #[cfg(feature = "tracing")]
+            last_write: std::time::Instant::now(),
         })
     }
 
@@ -55,30 +147,93 @@ where
         self.section.position
     }
 
+    /// The number of directory entries written so far
+    #[inline]
+    pub fn entries_written(&self) -> u32 {
+        self.curr_idx as u32
+    }
+
     pub fn dump_dir_entry(
         &mut self,
         buffer: &mut DumpBuf,
         dirent: MDRawDirectory,
     ) -> std::result::Result<(), FileWriterError> {
-        self.section.set_value_at(buffer, dirent, self.curr_idx)?;
+        self.write_dir_entry_at(buffer, dirent, self.curr_idx)?;
+        self.curr_idx += 1;
+        Ok(())
+    }
+
+    /// The index and contents of the first entry written for `stream_type`
+    pub fn find_dir_entry(
+        &self,
+        buffer: &DumpBuf,
+        stream_type: u32,
+    ) -> Option<(usize, MDRawDirectory)> {
+        (0..self.curr_idx).find_map(|idx| {
+            let dirent = self.dir_entry(buffer, idx)?;
+            (dirent.stream_type == stream_type).then_some((idx, dirent))
+        })
+    }
+
+    /// The entries written so far, in directory order, including the empty
+    /// ones of the streams which were left out
+    pub fn dir_entries(&self, buffer: &DumpBuf) -> Vec<MDRawDirectory> {
+        (0..self.curr_idx)
+            .filter_map(|idx| self.dir_entry(buffer, idx))
+            .collect()
+    }
+
+    fn dir_entry(&self, buffer: &DumpBuf, idx: usize) -> Option<MDRawDirectory> {
+        let offset = self.section.location_of_index(idx).rva as usize;
+        buffer.pread_with(offset, scroll::LE).ok()
+    }
+
+    /// Overwrite the entry at `idx`, which was written already, with `dirent`
+    pub fn replace_dir_entry(
+        &mut self,
+        buffer: &mut DumpBuf,
+        idx: usize,
+        dirent: MDRawDirectory,
+    ) -> std::result::Result<(), FileWriterError> {
+        assert!(
+            idx < self.curr_idx,
+            "replacing an entry which wasn't written"
+        );
+        self.write_dir_entry_at(buffer, dirent, idx)
+    }
+
+    fn write_dir_entry_at(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dirent: MDRawDirectory,
+        idx: usize,
+    ) -> std::result::Result<(), FileWriterError> {
+        self.section.set_value_at(buffer, dirent, idx)?;
 
         // Now write it to file
+        let idx_pos = self.section.location_of_index(idx);
+        self.rewrite(buffer, idx_pos)
+    }
 
+    /// Write the part of the buffer at `location` to the file again, for the
+    /// parts of the minidump which are filled in after being written to it
+    pub fn rewrite(
+        &mut self,
+        buffer: &DumpBuf,
+        location: MDLocationDescriptor,
+    ) -> std::result::Result<(), FileWriterError> {
         // First get all the positions
         let curr_file_pos = self.destination.stream_position()?;
-        let idx_pos = self.section.location_of_index(self.curr_idx);
-        self.curr_idx += 1;
 
-        self.destination.seek(std::io::SeekFrom::Start(
-            self.destination_start_offset + idx_pos.rva as u64,
+        self.destination.seek(SeekFrom::Start(
+            self.destination_start_offset + location.rva as u64,
         ))?;
-        let start = idx_pos.rva as usize;
-        let end = (idx_pos.rva + idx_pos.data_size) as usize;
+        let start = location.rva as usize;
+        let end = (location.rva + location.data_size) as usize;
         self.destination.write_all(&buffer[start..end])?;
 
         // Reset file-position
-        self.destination
-            .seek(std::io::SeekFrom::Start(curr_file_pos))?;
+        self.destination.seek(SeekFrom::Start(curr_file_pos))?;
 
         Ok(())
     }
@@ -91,6 +246,10 @@ where
         buffer: &mut DumpBuf,
         dirent: Option<MDRawDirectory>,
     ) -> std::result::Result<(), FileWriterError> {
+        #[cfg(feature = "tracing")]
+        let stream = dirent
+            .as_ref()
+            .map(|d| (d.stream_type, d.location.data_size));
         if let Some(dirent) = dirent {
             self.dump_dir_entry(buffer, dirent)?;
         }
@@ -98,6 +257,87 @@ where
         let start_pos = self.last_position_written_to_file as usize;
         self.destination.write_all(&buffer[start_pos..])?;
         self.last_position_written_to_file = buffer.position();
+
+        // The time and bytes spent since the previous call, which includes
+        // gathering the contents of the stream
+        event!(
+            DEBUG,
+            stream_type = stream.map(|(stream_type, _)| stream_type),
+            stream_size = stream.map(|(_, size)| size),
+            bytes_written = buffer.position() - start_pos as u64,
+            elapsed_us = self.last_write.elapsed().as_micros() as u64,
+            "stream written"
+        );
+        #[cfg(feature = "tracing")]
+        {
+            self.last_write = std::time::Instant::now();
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discard_sink_tracks_position() {
+        let mut sink = DiscardSink::default();
+        sink.write_all(&[0; 16]).unwrap();
+        assert_eq!(sink.seek(SeekFrom::Start(4)).unwrap(), 4);
+        sink.write_all(&[0; 4]).unwrap();
+        assert_eq!(sink.stream_position().unwrap(), 8);
+        assert_eq!(sink.seek(SeekFrom::End(-2)).unwrap(), 14);
+        assert!(sink.seek(SeekFrom::Current(-20)).is_err());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn hashing_writer() {
+        use sha2::Digest;
+
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.write_all(b"MDMP").unwrap();
+        writer.write_all(&[0; 4096]).unwrap();
+        let digest = writer.digest();
+        assert_eq!(
+            digest,
+            <[u8; 32]>::from(sha2::Sha256::digest(&writer.inner))
+        );
+    }
+
+    #[test]
+    fn replace_dir_entry() {
+        let mut buffer = DumpBuf::with_capacity(0);
+        let mut destination = std::io::Cursor::new(Vec::new());
+        let mut dir_section = DirSection::new(&mut buffer, 3, &mut destination).unwrap();
+        let dirent = |stream_type, rva| MDRawDirectory {
+            stream_type,
+            location: crate::minidump_format::MDLocationDescriptor { data_size: 4, rva },
+        };
+        dir_section
+            .write_to_file(&mut buffer, Some(dirent(7, 100)))
+            .unwrap();
+        dir_section
+            .write_to_file(&mut buffer, Some(dirent(9, 200)))
+            .unwrap();
+        assert!(dir_section.find_dir_entry(&buffer, 8).is_none());
+
+        let (idx, found) = dir_section.find_dir_entry(&buffer, 9).unwrap();
+        assert_eq!((idx, found.location.rva), (1, 200));
+        dir_section
+            .replace_dir_entry(&mut buffer, idx, dirent(9, 300))
+            .unwrap();
+        assert_eq!(dir_section.entries_written(), 2);
+        let (_, found) = dir_section.find_dir_entry(&buffer, 9).unwrap();
+        assert_eq!(found.location.rva, 300);
+        let entries: Vec<_> = dir_section
+            .dir_entries(&buffer)
+            .iter()
+            .map(|dirent| (dirent.stream_type, dirent.location.rva))
+            .collect();
+        assert_eq!(entries, [(7, 100), (9, 300)]);
+        // The destination is updated as well
+        assert_eq!(&destination.get_ref()[..], &buffer[..]);
+    }
+}
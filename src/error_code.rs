@@ -0,0 +1,130 @@
+//! Stable error codes, for reporting why a minidump couldn't be written
+//! without matching on error messages.
+//!
+//! The errors returned by the writer have a `code()` method returning an
+//! [`ErrorCode`], and an `os_error()` method returning the underlying OS error
+//! number, if any.
+
+/// What kind of failure prevented a minidump from being written. The values
+/// won't change between releases, new ones may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u32)]
+pub enum ErrorCode {
+    /// A failure which doesn't fit any other code
+    Other = 0,
+    /// Attaching to or stopping the process failed
+    Attach = 1,
+    /// We aren't allowed to dump the process
+    PermissionDenied = 2,
+    /// The process, or the thread being read, exited
+    ProcessGone = 3,
+    /// Reading the memory of the process failed
+    ReadMemory = 4,
+    /// Reading information about the process, other than its memory, failed
+    ProcessInfo = 5,
+    /// Writing the minidump to its destination failed
+    Output = 6,
+    /// The contents of the minidump couldn't be laid out
+    Format = 7,
+    /// The writer was used incorrectly
+    InvalidInput = 8,
+    /// The dump was cancelled, or didn't finish before its deadline
+    Cancelled = 9,
+    /// The process was dumped too recently, see the rate limiter of the
+    /// Linux writer
+    RateLimited = 10,
+}
+
+impl ErrorCode {
+    /// A short name for the code, e.g. for telemetry.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Other => "other",
+            Self::Attach => "attach",
+            Self::PermissionDenied => "permission_denied",
+            Self::ProcessGone => "process_gone",
+            Self::ReadMemory => "read_memory",
+            Self::ProcessInfo => "process_info",
+            Self::Output => "output",
+            Self::Format => "format",
+            Self::InvalidInput => "invalid_input",
+            Self::Cancelled => "cancelled",
+            Self::RateLimited => "rate_limited",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Return the first OS error number in the chain of sources of `error`,
+/// including `error` itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn os_error_in_chain(error: &(dyn std::error::Error + 'static)) -> Option<i32> {
+    std::iter::successors(Some(error), |e| e.source()).find_map(|e| {
+        if let Some(errno) = e.downcast_ref::<nix::errno::Errno>() {
+            Some(*errno as i32)
+        } else {
+            e.downcast_ref::<std::io::Error>()?.raw_os_error()
+        }
+    })
+}
+
+/// Refine `code` using the OS error which caused it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn refine_with_os_error(code: ErrorCode, os_error: Option<i32>) -> ErrorCode {
+    match (code, os_error) {
+        (_, Some(libc::ESRCH)) => ErrorCode::ProcessGone,
+        (ErrorCode::Attach, Some(libc::EPERM | libc::EACCES)) => ErrorCode::PermissionDenied,
+        (ErrorCode::ProcessInfo, Some(libc::ENOENT)) => ErrorCode::ProcessGone,
+        _ => code,
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use crate::errors::{
+        CancelledError, CopyFromProcessError, DumperError, InitError, SectionMemory64ListError,
+        SectionThreadListError, WriterError,
+    };
+
+    fn copy_error(source: nix::Error) -> DumperError {
+        DumperError::CopyFromProcessError(CopyFromProcessError {
+            child: 1,
+            src: 0x1000,
+            offset: 0,
+            length: 8,
+            source,
+        })
+    }
+
+    #[test]
+    fn test_codes() {
+        let error = WriterError::from(copy_error(nix::Error::EFAULT));
+        assert_eq!(error.code(), ErrorCode::ReadMemory);
+        assert_eq!(error.os_error(), Some(libc::EFAULT));
+
+        // The code is found through the section errors
+        let error = WriterError::from(SectionThreadListError::from(copy_error(nix::Error::ESRCH)));
+        assert_eq!(error.code(), ErrorCode::ProcessGone);
+        assert_eq!(error.os_error(), Some(libc::ESRCH));
+
+        let error = WriterError::from(DumperError::PtraceAttachError(1, nix::Error::EPERM));
+        assert_eq!(error.code(), ErrorCode::PermissionDenied);
+
+        let error = WriterError::from(InitError::NoProcessToDump);
+        assert_eq!(error.code(), ErrorCode::InvalidInput);
+        assert_eq!(error.os_error(), None);
+        assert_eq!(error.code().to_string(), "invalid_input");
+
+        let error = WriterError::from(SectionMemory64ListError::from(
+            CancelledError::DeadlineExceeded,
+        ));
+        assert_eq!(error.code(), ErrorCode::Cancelled);
+    }
+}
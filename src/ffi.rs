@@ -0,0 +1,250 @@
+//! A C API for writing minidumps, for applications which aren't written in
+//! Rust.
+//!
+//! Enable the `ffi` feature and build the crate as a C library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or
+//! `staticlib`). The declarations are in `include/minidump_writer.h`.
+
+#![allow(non_camel_case_types, clippy::result_large_err)]
+
+use crate::{minidump_writer::MinidumpWriter, Pid};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr},
+    fs::File,
+};
+
+/// The dump was written successfully
+pub const MDW_OK: c_int = 0;
+/// A pointer argument was invalid
+pub const MDW_ERROR_INVALID_ARGUMENT: c_int = -1;
+/// The output file could not be created
+pub const MDW_ERROR_OUTPUT: c_int = -2;
+/// Writing the dump failed
+pub const MDW_ERROR_DUMP: c_int = -3;
+/// Writing the dump panicked, this is a bug of the writer
+pub const MDW_ERROR_PANIC: c_int = -4;
+
+/// The options for writing a dump, zero-initialize it and set `struct_size`
+/// to use the defaults. New fields are only ever appended, so callers built
+/// against an older, shorter version of the struct keep working.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct mdw_options {
+    /// The size of the struct known by the caller, `sizeof(mdw_options)`
+    pub struct_size: usize,
+    /// Try to keep the dump below this size in bytes, 0 means no limit
+    pub minidump_size_limit: u64,
+    /// The maximum number of threads to include, 0 means all of them
+    pub max_threads: u32,
+    /// Replace the values in the stacks which don't look like pointers
+    pub sanitize_stacks: bool,
+}
+
+impl Default for mdw_options {
+    fn default() -> Self {
+        Self {
+            struct_size: std::mem::size_of::<Self>(),
+            minidump_size_limit: 0,
+            max_threads: 0,
+            sanitize_stacks: false,
+        }
+    }
+}
+
+impl mdw_options {
+    /// Reads the fields of `options` known by both sides, the others keep
+    /// their defaults. Returns `None` if `struct_size` is too small to be
+    /// valid, or larger than this struct, as the options we don't know about
+    /// can't be honored.
+    ///
+    /// # Safety
+    ///
+    /// `options` must be null or valid for reads of `struct_size` bytes.
+    unsafe fn read(options: *const Self) -> Option<Self> {
+        let mut read = Self::default();
+        if options.is_null() {
+            return Some(read);
+        }
+
+        // `struct_size` comes first in every version of the struct
+        let size = options.cast::<usize>().read_unaligned();
+        if size < std::mem::size_of::<usize>() || size > std::mem::size_of::<Self>() {
+            return None;
+        }
+        std::ptr::copy_nonoverlapping(
+            options.cast::<u8>(),
+            std::ptr::addr_of_mut!(read).cast::<u8>(),
+            size,
+        );
+        read.struct_size = size;
+        Some(read)
+    }
+}
+
+/// Returns the size of the crash context expected by [`mdw_write_dump`].
+#[no_mangle]
+pub extern "C" fn mdw_crash_context_size() -> usize {
+    std::mem::size_of::<crash_context::CrashContext>()
+}
+
+/// Write a dump of thread `tid` of process `pid` to the file at `path`.
+///
+/// `crash_ctx` is optional, if not null it must point to a
+/// `crash_context::CrashContext`, as sent by the `minidumper` crate's clients,
+/// of [`mdw_crash_context_size`] bytes. Its process and thread take
+/// precedence over `pid` and `tid`. `options` is optional as well.
+///
+/// Returns [`MDW_OK`] or one of the `MDW_ERROR_*` codes. Panics don't unwind
+/// into the caller, they are reported as [`MDW_ERROR_PANIC`].
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string, and `crash_ctx` and `options`
+/// must be null or valid for reads of their types, `options` of its
+/// `struct_size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mdw_write_dump(
+    pid: Pid,
+    tid: Pid,
+    crash_ctx: *const c_void,
+    path: *const c_char,
+    options: *const mdw_options,
+) -> c_int {
+    std::panic::catch_unwind(|| write_dump(pid, tid, crash_ctx, path, options)).unwrap_or_else(
+        |_| {
+            log::error!("Writing a dump of {pid} panicked");
+            MDW_ERROR_PANIC
+        },
+    )
+}
+
+/// See [`mdw_write_dump`]
+unsafe fn write_dump(
+    pid: Pid,
+    tid: Pid,
+    crash_ctx: *const c_void,
+    path: *const c_char,
+    options: *const mdw_options,
+) -> c_int {
+    if path.is_null() {
+        return MDW_ERROR_INVALID_ARGUMENT;
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return MDW_ERROR_INVALID_ARGUMENT;
+    };
+    let Some(options) = mdw_options::read(options) else {
+        return MDW_ERROR_INVALID_ARGUMENT;
+    };
+
+    let mut builder = MinidumpWriter::builder().process(pid, tid);
+    if !crash_ctx.is_null() {
+        // The context is serialized bytes, which needn't be aligned
+        let bytes = std::slice::from_raw_parts(crash_ctx.cast::<u8>(), mdw_crash_context_size());
+        let Some(crash_ctx) = crash_context::CrashContext::from_bytes(bytes) else {
+            return MDW_ERROR_INVALID_ARGUMENT;
+        };
+        builder = builder.crash_context(crash_ctx);
+    }
+    if options.minidump_size_limit != 0 {
+        builder = builder.minidump_size_limit(options.minidump_size_limit);
+    }
+    if options.max_threads != 0 {
+        builder = builder.max_threads(options.max_threads as usize);
+    }
+    if options.sanitize_stacks {
+        builder = builder.sanitize_stacks();
+    }
+
+    let mut file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::error!("Could not create {path}: {e}");
+            return MDW_ERROR_OUTPUT;
+        }
+    };
+    match builder.write(&mut file) {
+        Ok(_) => MDW_OK,
+        Err(e) => {
+            log::error!("Could not write a dump of {pid}: {e}");
+            MDW_ERROR_DUMP
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_dump() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as Pid;
+        let dir = tempfile::tempdir().unwrap();
+        let path = std::ffi::CString::new(dir.path().join("dump.dmp").to_str().unwrap()).unwrap();
+
+        let options = mdw_options {
+            max_threads: 1,
+            ..Default::default()
+        };
+        let result = unsafe { mdw_write_dump(pid, pid, std::ptr::null(), path.as_ptr(), &options) };
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(result, MDW_OK);
+        let dump = std::fs::read(dir.path().join("dump.dmp")).unwrap();
+        assert_eq!(&dump[..4], b"MDMP");
+
+        let result = unsafe {
+            mdw_write_dump(
+                pid,
+                pid,
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(result, MDW_ERROR_INVALID_ARGUMENT);
+
+        // Options from a caller built against a newer version of the struct
+        let options = mdw_options {
+            struct_size: std::mem::size_of::<mdw_options>() + 8,
+            ..Default::default()
+        };
+        let result = unsafe { mdw_write_dump(pid, pid, std::ptr::null(), path.as_ptr(), &options) };
+        assert_eq!(result, MDW_ERROR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn test_read_options() {
+        let options = mdw_options {
+            minidump_size_limit: 1024,
+            max_threads: 4,
+            sanitize_stacks: true,
+            ..Default::default()
+        };
+        let read = unsafe { mdw_options::read(&options) }.unwrap();
+        assert_eq!(read.minidump_size_limit, 1024);
+        assert_eq!(read.max_threads, 4);
+        assert!(read.sanitize_stacks);
+
+        // An older caller which only knew about the size limit
+        let older = mdw_options {
+            struct_size: std::mem::size_of::<usize>() + std::mem::size_of::<u64>(),
+            ..options
+        };
+        let read = unsafe { mdw_options::read(&older) }.unwrap();
+        assert_eq!(read.minidump_size_limit, 1024);
+        assert_eq!(read.max_threads, 0);
+        assert!(!read.sanitize_stacks);
+
+        let zeroed = mdw_options {
+            struct_size: 0,
+            ..options
+        };
+        assert!(unsafe { mdw_options::read(&zeroed) }.is_none());
+        assert!(unsafe { mdw_options::read(std::ptr::null()) }.is_some());
+    }
+}
@@ -0,0 +1,129 @@
+//! Recording the GPUs used by the process, as crashes in graphics code are
+//! mostly bucketed by the GPU and the version of its driver. The writer
+//! can't identify them reliably on its own, so the application supplies
+//! what it got from its graphics API, see `MinidumpWriter::set_gpu_info`.
+//!
+//! They are written to a `GpuInfo` stream, see
+//! [`MDRawGpuInfo`](crate::minidump_format::MDRawGpuInfo).
+//!
+//! ```
+//! use minidump_writer::gpu_info::GpuInfo;
+//!
+//! let gpu = GpuInfo::new(0x10de, 0x2684, "550.54.14").with_crash_data(vec![0xde, 0xad]);
+//! assert_eq!(gpu.crash_data.len(), 2);
+//! ```
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{write_string_to_location, MemoryArrayWriter, MemoryWriter, MemoryWriterError},
+    minidump_format::{MDExtraStreamType, MDLocationDescriptor, MDRawDirectory, MDRawGpuInfo},
+};
+
+/// A GPU used by the process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuInfo {
+    /// The PCI vendor id, e.g. `0x10de` for NVIDIA
+    pub vendor_id: u32,
+    /// The PCI device id
+    pub device_id: u32,
+    pub driver_version: String,
+    /// The crash data provided by the driver, e.g. a GPU fault report, if any
+    pub crash_data: Vec<u8>,
+}
+
+impl GpuInfo {
+    pub fn new(vendor_id: u32, device_id: u32, driver_version: impl Into<String>) -> Self {
+        Self {
+            vendor_id,
+            device_id,
+            driver_version: driver_version.into(),
+            crash_data: Vec::new(),
+        }
+    }
+
+    /// Attach the crash data provided by the driver
+    pub fn with_crash_data(mut self, crash_data: Vec<u8>) -> Self {
+        self.crash_data = crash_data;
+        self
+    }
+}
+
+/// Write the `GpuInfo` stream. Nothing is written if there are no GPUs.
+pub(crate) fn write(
+    buffer: &mut DumpBuf,
+    gpus: &[GpuInfo],
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    if gpus.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let count = MemoryWriter::alloc_with_val(buffer, gpus.len() as u32)?;
+    let mut entries = MemoryArrayWriter::<MDRawGpuInfo>::alloc_array(buffer, gpus.len())?;
+    let mut location = count.location();
+    location.data_size += entries.location().data_size;
+
+    for (idx, gpu) in gpus.iter().enumerate() {
+        let driver_version = write_string_to_location(buffer, &gpu.driver_version)?;
+        let crash_data = if gpu.crash_data.is_empty() {
+            MDLocationDescriptor::default()
+        } else {
+            MemoryArrayWriter::write_bytes(buffer, &gpu.crash_data)?.location()
+        };
+        entries.set_value_at(
+            buffer,
+            MDRawGpuInfo {
+                vendor_id: gpu.vendor_id,
+                device_id: gpu.device_id,
+                driver_version_rva: driver_version.rva,
+                crash_data,
+            },
+            idx,
+        )?;
+    }
+
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::GpuInfo as u32,
+        location,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_info() {
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut buffer, &[]).unwrap();
+        assert_eq!(dirent.location.data_size, 0);
+
+        let dirent = write(
+            &mut buffer,
+            &[
+                GpuInfo::new(0x8086, 0x46a6, "24.1"),
+                GpuInfo::new(0x10de, 0x2684, "550.54.14").with_crash_data(vec![1, 2, 3]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(dirent.stream_type, MDExtraStreamType::GpuInfo as u32);
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap());
+
+        // A count followed by the 20 byte entries
+        let rva = dirent.location.rva as usize;
+        assert_eq!(read_u32(rva), 2);
+        assert_eq!(dirent.location.data_size, 4 + 2 * 20);
+
+        let intel = rva + 4;
+        assert_eq!((read_u32(intel), read_u32(intel + 4)), (0x8086, 0x46a6));
+        assert_eq!(read_u32(intel + 12), 0);
+        // The driver version is an MDString, its length in bytes followed by
+        // UTF-16 code units
+        assert_eq!(read_u32(read_u32(intel + 8) as usize), 8);
+
+        let nvidia = intel + 20;
+        assert_eq!((read_u32(nvidia), read_u32(nvidia + 4)), (0x10de, 0x2684));
+        let (size, data) = (read_u32(nvidia + 12), read_u32(nvidia + 16) as usize);
+        assert_eq!(&buffer[data..data + size as usize], [1, 2, 3]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Identifying the machine and the boot the crash happened on, so that
+//! crash storms hitting many hosts at once can be attributed to specific
+//! machines, or to a boot gone wrong, see `MinidumpWriter::set_host_ids`.
+//!
+//! The identifiers are written to a `HostIds` stream, as text:
+//!
+//! ```text
+//! machine_id: 4c4c4544004c4e10804bb4c04f4e5032
+//! boot_id: 9a2f1ea4-6cc4-4c1d-9a47-3b8d1a3e0c55
+//! ```
+//!
+//! On Linux they are `/etc/machine-id` and the kernel's boot id, on macOS the
+//! hardware UUID and the boot session UUID. The machine id is meant to stay
+//! private to the machine, so with the `sha2` feature enabled, it and the
+//! boot id can be written hashed instead, see [`HostIds::Hashed`]: the same
+//! identifiers and salt always hash to the same values, which is all that's
+//! needed to correlate dumps.
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{MemoryArrayWriter, MemoryWriterError},
+    minidump_format::{MDExtraStreamType, MDRawDirectory},
+};
+use std::fmt::Write as _;
+
+/// How the identifiers of the host are recorded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HostIds {
+    /// As they are
+    #[default]
+    Raw,
+    /// As the hex SHA-256 digest of the salt followed by the identifier, so
+    /// that different applications get unrelated values
+    #[cfg(feature = "sha2")]
+    Hashed { salt: Vec<u8> },
+    /// Not at all
+    Omitted,
+}
+
+/// Read the identifiers of the host, the machine id and the boot id.
+fn read() -> (Option<String>, Option<String>) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let read = |path: &str| {
+                let id = std::fs::read_to_string(path).ok()?;
+                let id = id.trim();
+                (!id.is_empty()).then(|| id.to_owned())
+            };
+            // Older systems only have the D-Bus one, Android has neither
+            let machine_id = read("/etc/machine-id").or_else(|| read("/var/lib/dbus/machine-id"));
+            (machine_id, read("/proc/sys/kernel/random/boot_id"))
+        } else {
+            let mut uuid = [0u8; 16];
+            // Don't wait for the UUID if it isn't available right away
+            let timeout = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            // SAFETY: the buffer holds the 16 bytes of a UUID
+            let machine_id = (unsafe { libc::gethostuuid(uuid.as_mut_ptr(), &timeout) } == 0)
+                .then(|| uuid.iter().map(|byte| format!("{byte:02x}")).collect());
+            let boot_id = crate::mach::sysctl_string(b"kern.bootsessionuuid\0");
+            (machine_id, (!boot_id.is_empty()).then_some(boot_id))
+        }
+    }
+}
+
+/// Write the `HostIds` stream, recording the identifiers as `host_ids`
+/// says. Nothing is written if they are omitted, or can't be read.
+pub(crate) fn write(
+    buffer: &mut DumpBuf,
+    host_ids: &HostIds,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    if *host_ids == HostIds::Omitted {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let (machine_id, boot_id) = read();
+    let contents = format_ids(host_ids, machine_id.as_deref(), boot_id.as_deref());
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::HostIds as u32,
+        location: section.location(),
+    })
+}
+
+fn format_ids(host_ids: &HostIds, machine_id: Option<&str>, boot_id: Option<&str>) -> String {
+    let mut contents = String::new();
+    for (name, id) in [("machine_id", machine_id), ("boot_id", boot_id)] {
+        let Some(id) = id else {
+            continue;
+        };
+        let id = match host_ids {
+            #[cfg(feature = "sha2")]
+            HostIds::Hashed { salt } => {
+                use sha2::Digest;
+
+                let mut hasher = sha2::Sha256::new();
+                hasher.update(salt);
+                hasher.update(id);
+                hasher
+                    .finalize()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect()
+            }
+            _ => id.to_owned(),
+        };
+        // Writing to a String is infallible
+        let _ = writeln!(contents, "{name}: {id}");
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_ids() {
+        assert_eq!(
+            format_ids(&HostIds::Raw, Some("4c4c4544"), Some("9a2f1ea4")),
+            "machine_id: 4c4c4544\nboot_id: 9a2f1ea4\n"
+        );
+        assert_eq!(
+            format_ids(&HostIds::Raw, None, Some("9a2f1ea4")),
+            "boot_id: 9a2f1ea4\n"
+        );
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut buffer, &HostIds::Omitted).unwrap();
+        assert_eq!(dirent.location.data_size, 0);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_hashed_ids() {
+        let hashed = |salt: &[u8]| {
+            format_ids(
+                &HostIds::Hashed {
+                    salt: salt.to_vec(),
+                },
+                Some("4c4c4544"),
+                None,
+            )
+        };
+        let contents = hashed(b"app");
+        assert_eq!(contents.len(), "machine_id: \n".len() + 64);
+        assert!(!contents.contains("4c4c4544"));
+        assert_eq!(hashed(b"app"), contents);
+        assert_ne!(hashed(b"other app"), contents);
+    }
+}
@@ -1,3 +1,6 @@
+#[macro_use]
+mod trace;
+
 cfg_if::cfg_if! {
     if #[cfg(any(target_os = "linux", target_os = "android"))] {
         mod linux;
@@ -14,8 +17,50 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "windows",
+    target_os = "macos"
+))]
+pub mod builder;
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod capture_limits;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod crash_reason;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod error_code;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod gpu_info;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod host_ids;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod log_ring_buffer;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod memory_map;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod module_filter;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod module_list;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod process_dumper;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod process_memory;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub(crate) mod scratch;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod signal_dispositions;
+
+#[cfg(all(feature = "ffi", any(target_os = "linux", target_os = "android")))]
+pub mod ffi;
+
+#[cfg(feature = "zstd")]
+pub mod compression;
+
 pub mod minidump_cpu;
 pub mod minidump_format;
 
 pub mod dir_section;
 pub mod mem_writer;
+pub mod unloaded_modules;
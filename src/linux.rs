@@ -5,17 +5,44 @@
 mod android;
 pub mod app_memory;
 pub(crate) mod auxv;
+pub mod cgroup_freezer;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub mod core_dump;
 pub mod crash_context;
+pub mod crash_summary;
+pub mod crash_transport;
 mod dso_debug;
+pub mod dump_config;
 mod dumper_cpu_info;
+pub mod emulation;
 pub mod errors;
+pub mod exploitability;
+pub mod hang_dump;
+pub mod jit_symbols;
 pub mod maps_reader;
 pub mod mem_reader;
+pub mod memory_denylist;
+pub mod microdump;
 pub mod minidump_writer;
+pub mod mmap_writer;
 pub mod module_reader;
+#[cfg(feature = "panic-dump")]
+pub mod panic_dump;
+pub mod pii_scrubber;
+pub mod prerequisites;
+pub mod process_tree;
+pub mod ptrace_access;
 pub mod ptrace_dumper;
+pub mod rate_limit;
+pub mod registry;
 pub(crate) mod sections;
+pub mod socket_writer;
+pub mod stream_selection;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 pub mod thread_info;
+#[cfg(feature = "verify")]
+pub mod verify;
 
 pub use maps_reader::LINUX_GATE_LIBRARY_NAME;
 pub type Pid = i32;
@@ -0,0 +1,110 @@
+//! Suspension of a whole process via the cgroup v2 freezer.
+//!
+//! Attaching to every thread of a process with thousands of them is slow, and
+//! new threads may be spawned while we're doing it. Freezing the cgroup the
+//! process lives in stops all of its threads at once, ptrace is then only
+//! needed to read their registers.
+
+use crate::linux::{errors::CgroupFreezerError, Pid};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const CGROUP_V2_ROOT: &str = "/sys/fs/cgroup";
+
+/// A frozen cgroup, which is thawed again when this is dropped.
+#[derive(Debug)]
+pub struct CgroupFreezer {
+    path: PathBuf,
+    frozen: bool,
+}
+
+impl CgroupFreezer {
+    /// Freeze the cgroup of process `pid`, waiting up to `timeout` for all of
+    /// its tasks to be frozen.
+    ///
+    /// The process must be the only one in its cgroup, as every process in it
+    /// would be frozen otherwise, possibly including the caller.
+    pub fn freeze(pid: Pid, timeout: Duration) -> Result<Self, CgroupFreezerError> {
+        let path = Self::cgroup_path(pid)?;
+
+        let procs = read(path.join("cgroup.procs"))?;
+        let others = procs
+            .lines()
+            .filter(|line| line.trim().parse::<Pid>().ok() != Some(pid))
+            .count();
+        if others != 0 {
+            return Err(CgroupFreezerError::SharedCgroup(path, others));
+        }
+
+        let mut freezer = Self {
+            path,
+            frozen: false,
+        };
+        freezer.write_freeze(true)?;
+
+        // cgroup.events is the canonical way to be notified of the frozen
+        // state, but polling it is simpler and we only wait for a short time
+        const POLL_INTERVAL: Duration = Duration::from_millis(1);
+        let events = freezer.path.join("cgroup.events");
+        let end = Instant::now() + timeout;
+
+        loop {
+            if read(events.clone())?
+                .lines()
+                .any(|line| line.trim() == "frozen 1")
+            {
+                return Ok(freezer);
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+            if Instant::now() > end {
+                // Dropping the freezer thaws the cgroup again
+                return Err(CgroupFreezerError::Timeout);
+            }
+        }
+    }
+
+    /// Thaw the cgroup, this is done automatically on drop.
+    pub fn thaw(mut self) -> Result<(), CgroupFreezerError> {
+        self.write_freeze(false)
+    }
+
+    fn write_freeze(&mut self, frozen: bool) -> Result<(), CgroupFreezerError> {
+        let path = self.path.join("cgroup.freeze");
+        std::fs::write(&path, if frozen { "1" } else { "0" })
+            .map_err(|e| CgroupFreezerError::IOError(path, e))?;
+        self.frozen = frozen;
+        Ok(())
+    }
+
+    /// Find the unified (v2) hierarchy entry in `/proc/<pid>/cgroup`, which is
+    /// the one with hierarchy ID 0 and an empty controller list.
+    fn cgroup_path(pid: Pid) -> Result<PathBuf, CgroupFreezerError> {
+        let cgroups = read(PathBuf::from(format!("/proc/{pid}/cgroup")))?;
+        let relative = cgroups
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .ok_or(CgroupFreezerError::NoCgroupV2)?;
+
+        let path = PathBuf::from(CGROUP_V2_ROOT).join(relative.trim_start_matches('/'));
+        // The root cgroup can't be frozen
+        if !path.join("cgroup.freeze").exists() {
+            return Err(CgroupFreezerError::NoFreezer(path));
+        }
+        Ok(path)
+    }
+}
+
+impl Drop for CgroupFreezer {
+    fn drop(&mut self) {
+        if self.frozen {
+            let _ = self.write_freeze(false);
+        }
+    }
+}
+
+fn read(path: PathBuf) -> Result<String, CgroupFreezerError> {
+    std::fs::read_to_string(&path).map_err(|e| CgroupFreezerError::IOError(path, e))
+}
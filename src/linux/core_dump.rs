@@ -0,0 +1,1161 @@
+//! Converting ELF core dumps, as written by the kernel or by gdb's `gcore`,
+//! to minidumps, so that the crashes which were only caught as core dumps go
+//! through the same processing as those caught by [`MinidumpWriter`].
+//!
+//! ```no_run
+//! use minidump_writer::core_dump::CoreDump;
+//!
+//! let core = CoreDump::from_file("core.1234")?;
+//! let mut writer = core.minidump_writer();
+//! core.write_minidump(&mut writer, &mut std::fs::File::create("core.1234.dmp")?)?;
+//! # Ok::<(), minidump_writer::errors::CoreDumpError>(())
+//! ```
+//!
+//! The threads and their registers come from the `NT_PRSTATUS` notes and
+//! the register sets following them, the first thread being the one which
+//! received the signal, and the signal comes from the `NT_SIGINFO` note.
+//! The mappings are the `PT_LOAD` segments, named after the files of the
+//! `NT_FILE` note. The core only holds the memory the kernel's
+//! `coredump_filter` selected, usually leaving out the contents of the files,
+//! so the build ids of the modules are read from their first page, which is
+//! kept, or failing that from the files on this machine.
+//!
+//! The streams are those of a dump of a live process the core holds the
+//! information of, written by the same code when it isn't specific to live
+//! processes: the thread and module lists, the memory lists, the exception,
+//! the thread names, and the mappings, auxiliary vector and command line of
+//! the process. With [`MinidumpWriter::full_memory`], the mappings whose
+//! memory the core holds are captured. The system information is the one of
+//! the machine doing the conversion, which can only convert core dumps of
+//! processes of its own architecture.
+
+use crate::{
+    dir_section::{DirSection, DumpBuf},
+    linux::{
+        errors::{
+            CopyFromProcessError, CoreDumpError, DumperError, MapsReaderError,
+            SectionExceptionStreamError, SectionMappingsError, SectionThreadListError,
+            ThreadInfoError, WriterError,
+        },
+        maps_reader::{self, MappingInfo},
+        minidump_writer::{CrashingThreadContext, MinidumpWriter},
+        module_reader::{BuildId, ReadFromModule, SoName},
+        ptrace_dumper::Thread,
+        sections::{
+            mappings, memory64_list_stream::Memory64List, memory_list_stream, referenced_memory,
+            register_memory, soft_errors_stream, systeminfo_stream, thread_names_stream,
+        },
+        thread_info::{RegSet, ThreadInfo},
+        Pid,
+    },
+    mem_writer::{MemoryArrayWriter, MemoryWriter},
+    memory_map::MemoryMapping,
+    minidump_cpu::RawContextCPU,
+    minidump_format::*,
+    process_dumper::ProcessDumper,
+};
+use goblin::elf::{header::ET_CORE, program_header};
+use minidump_common::errors::ExceptionCodeLinux;
+use procfs_core::{process::MemoryMaps, FromRead};
+use std::{
+    borrow::Cow,
+    io::{Seek, Write},
+    ops::{Deref, Range},
+    path::Path,
+};
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        const MACHINE: u16 = goblin::elf::header::EM_X86_64;
+    } else if #[cfg(target_arch = "aarch64")] {
+        const MACHINE: u16 = goblin::elf::header::EM_AARCH64;
+    }
+}
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_SIGINFO: u32 = 0x5349_4749;
+const NT_FILE: u32 = 0x4649_4c45;
+
+// The offsets of the fields read from `struct elf_prstatus`, which starts
+// with the signal in a `struct elf_siginfo`
+const PRSTATUS_SIGNO_OFFSET: usize = 0;
+const PRSTATUS_CODE_OFFSET: usize = 4;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_PPID_OFFSET: usize = 36;
+const PRSTATUS_REG_OFFSET: usize = 112;
+// The offsets of the fields read from `struct elf_prpsinfo`
+const PRPSINFO_PID_OFFSET: usize = 24;
+const PRPSINFO_FNAME: Range<usize> = 40..56;
+const PRPSINFO_PSARGS: Range<usize> = 56..136;
+// The offsets of the fields read from `siginfo_t`
+const SIGINFO_SIGNO_OFFSET: usize = 0;
+const SIGINFO_CODE_OFFSET: usize = 8;
+const SIGINFO_ADDR_OFFSET: usize = 16;
+
+const AT_SYSINFO_EHDR: u64 = 33;
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// The memory captured around the crashing instruction, as for live processes
+const IP_MEMORY_SIZE: usize = 256;
+
+/// The number of streams of the minidump, see [`CoreDump::write_minidump`]
+const NUM_STREAMS: u32 = 11;
+
+/// The contents of a core dump, mapped or read into memory
+enum CoreData {
+    Mapped(memmap2::Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for CoreData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// A `PT_LOAD` segment, the memory of a mapping of the process
+struct Segment {
+    address: usize,
+    /// The size of the mapping
+    size: usize,
+    flags: u32,
+    /// The part of the core holding the memory of the mapping, which may be
+    /// shorter than it, or empty, if the kernel left it out
+    data: Range<usize>,
+}
+
+/// A file mapped by the process, as listed in the `NT_FILE` note
+struct MappedFile {
+    start: usize,
+    end: usize,
+    offset: usize,
+    name: Vec<u8>,
+}
+
+/// The signal which caused the core dump
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Signal {
+    number: u32,
+    code: u32,
+    address: u64,
+}
+
+/// An ELF core dump, read as if it were a process, see the
+/// [module documentation](self).
+pub struct CoreDump {
+    data: CoreData,
+    pid: Pid,
+    threads: Vec<Thread>,
+    /// The parent process and the register sets of each thread, in the order
+    /// of [`Self::threads`]
+    thread_states: Vec<(Pid, Vec<RegSet>)>,
+    segments: Vec<Segment>,
+    mappings: Vec<MappingInfo>,
+    /// The mappings, in the format of `/proc/<pid>/maps`
+    maps: Vec<u8>,
+    auxv: Vec<u8>,
+    cmdline: Vec<u8>,
+    signal: Signal,
+    page_size: usize,
+}
+
+impl CoreDump {
+    /// Read the core dump in `path`, which is mapped into memory rather than
+    /// read, as core dumps can be huge.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, CoreDumpError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the core dump isn't expected to change while it's read
+        let map = unsafe { memmap2::Mmap::map(&file) }?;
+        Self::parse(CoreData::Mapped(map))
+    }
+
+    /// Read the core dump held by `bytes`, e.g. as read from a pipe.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, CoreDumpError> {
+        Self::parse(CoreData::Owned(bytes))
+    }
+
+    fn parse(data: CoreData) -> Result<Self, CoreDumpError> {
+        let elf = goblin::elf::Elf::parse(&data)?;
+        if elf.header.e_type != ET_CORE {
+            return Err(CoreDumpError::NotACoreDump);
+        }
+        if !elf.is_64 || elf.header.e_machine != MACHINE {
+            return Err(CoreDumpError::UnsupportedMachine(elf.header.e_machine));
+        }
+
+        let mut core = Self {
+            data: CoreData::Owned(Vec::new()),
+            pid: 0,
+            threads: Vec::new(),
+            thread_states: Vec::new(),
+            segments: Vec::new(),
+            mappings: Vec::new(),
+            maps: Vec::new(),
+            auxv: Vec::new(),
+            cmdline: Vec::new(),
+            signal: Signal::default(),
+            page_size: DEFAULT_PAGE_SIZE,
+        };
+        let mut files = Vec::new();
+        let mut name = None;
+        for header in &elf.program_headers {
+            let offset = header.p_offset as usize;
+            let data_len = (header.p_filesz as usize).min(data.len().saturating_sub(offset));
+            match header.p_type {
+                program_header::PT_LOAD => core.segments.push(Segment {
+                    address: header.p_vaddr as usize,
+                    size: header.p_memsz as usize,
+                    flags: header.p_flags,
+                    data: offset..offset + data_len,
+                }),
+                program_header::PT_NOTE => {
+                    let segment = data.get(offset..offset + data_len).unwrap_or_default();
+                    for (note_type, desc) in notes(segment) {
+                        core.parse_note(note_type, desc, &mut files, &mut name)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        drop(elf);
+
+        if core.threads.is_empty() {
+            return Err(CoreDumpError::NoThreads);
+        }
+        if core.pid == 0 {
+            core.pid = core.threads[0].tid;
+        }
+        // The name of the main thread, the other threads' ones aren't in the
+        // core
+        if let Some(thread) = core
+            .threads
+            .iter_mut()
+            .find(|thread| thread.tid == core.pid)
+        {
+            thread.name = name;
+        }
+        core.read_mappings(&files)?;
+        core.data = data;
+        Ok(core)
+    }
+
+    fn parse_note(
+        &mut self,
+        note_type: u32,
+        desc: &[u8],
+        files: &mut Vec<MappedFile>,
+        name: &mut Option<String>,
+    ) -> Result<(), CoreDumpError> {
+        match note_type {
+            NT_PRSTATUS => {
+                let (Some(tid), Some(ppid), Some(regs)) = (
+                    read_i32(desc, PRSTATUS_PID_OFFSET),
+                    read_i32(desc, PRSTATUS_PPID_OFFSET),
+                    desc.get(PRSTATUS_REG_OFFSET..),
+                ) else {
+                    return Err(CoreDumpError::InvalidNote("NT_PRSTATUS"));
+                };
+                // The first thread is the one which received the signal, the
+                // NT_SIGINFO note tells more about it if there's one
+                if self.threads.is_empty() {
+                    self.signal = Signal {
+                        number: read_i32(desc, PRSTATUS_SIGNO_OFFSET).unwrap_or_default() as u32,
+                        code: read_i32(desc, PRSTATUS_CODE_OFFSET).unwrap_or_default() as u32,
+                        address: 0,
+                    };
+                }
+                self.threads.push(Thread { tid, name: None });
+                self.thread_states.push((
+                    ppid,
+                    vec![RegSet {
+                        note_type,
+                        contents: regs.to_vec(),
+                    }],
+                ));
+            }
+            NT_PRPSINFO => {
+                let (Some(pid), Some(fname), Some(args)) = (
+                    read_i32(desc, PRPSINFO_PID_OFFSET),
+                    desc.get(PRPSINFO_FNAME),
+                    desc.get(PRPSINFO_PSARGS),
+                ) else {
+                    return Err(CoreDumpError::InvalidNote("NT_PRPSINFO"));
+                };
+                self.pid = pid;
+                *name = Some(String::from_utf8_lossy(until_nul(fname)).into_owned());
+                self.cmdline = until_nul(args).to_vec();
+            }
+            NT_SIGINFO => {
+                let (Some(number), Some(code), Some(address)) = (
+                    read_i32(desc, SIGINFO_SIGNO_OFFSET),
+                    read_i32(desc, SIGINFO_CODE_OFFSET),
+                    read_u64(desc, SIGINFO_ADDR_OFFSET),
+                ) else {
+                    return Err(CoreDumpError::InvalidNote("NT_SIGINFO"));
+                };
+                self.signal = Signal {
+                    number: number as u32,
+                    code: code as u32,
+                    address,
+                };
+            }
+            NT_AUXV => self.auxv = desc.to_vec(),
+            NT_FILE => {
+                let (files_read, page_size) =
+                    parse_file_note(desc).ok_or(CoreDumpError::InvalidNote("NT_FILE"))?;
+                *files = files_read;
+                self.page_size = page_size;
+            }
+            // The other register sets of the thread follow its NT_PRSTATUS
+            note_type => {
+                if let Some((_, regsets)) = self.thread_states.last_mut() {
+                    regsets.push(RegSet {
+                        note_type,
+                        contents: desc.to_vec(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe the segments as `/proc/<pid>/maps` would, and read them like
+    /// the mappings of live processes, merging the segments of each module.
+    fn read_mappings(&mut self, files: &[MappedFile]) -> Result<(), CoreDumpError> {
+        let linux_gate_loc = self
+            .auxv
+            .chunks_exact(16)
+            .find(|pair| read_u64(pair, 0) == Some(AT_SYSINFO_EHDR))
+            .and_then(|pair| read_u64(pair, 8))
+            .unwrap_or_default();
+
+        let mut maps = Vec::new();
+        for segment in &self.segments {
+            let end = segment
+                .address
+                .checked_add(segment.size)
+                .ok_or(CoreDumpError::InvalidSegment(segment.address))?;
+            let file = files
+                .iter()
+                .enumerate()
+                .find(|(_, file)| (file.start..file.end).contains(&segment.address));
+            let (offset, inode, name) = match file {
+                Some((idx, file)) => {
+                    // The inode isn't in the core, any number which is the
+                    // same for the mappings of a file will do
+                    let inode = files
+                        .iter()
+                        .position(|other| other.name == file.name)
+                        .unwrap_or(idx)
+                        + 1;
+                    let offset = segment
+                        .address
+                        .checked_sub(file.start)
+                        .and_then(|delta| file.offset.checked_add(delta))
+                        .ok_or(CoreDumpError::InvalidNote("NT_FILE"))?;
+                    (offset, inode, file.name.as_slice())
+                }
+                None if segment.address as u64 == linux_gate_loc => (0, 0, &b"[vdso]"[..]),
+                None => (0, 0, &b""[..]),
+            };
+            let flag = |flag, letter| {
+                if segment.flags & flag != 0 {
+                    letter
+                } else {
+                    '-'
+                }
+            };
+            write!(
+                maps,
+                "{:x}-{:x} {}{}{}p {offset:08x} 00:00 {inode}",
+                segment.address,
+                end,
+                flag(program_header::PF_R, 'r'),
+                flag(program_header::PF_W, 'w'),
+                flag(program_header::PF_X, 'x'),
+            )?;
+            // Like the kernel, separate the name even if there's none
+            maps.push(b' ');
+            maps.extend_from_slice(name);
+            maps.push(b'\n');
+        }
+
+        let memory_maps = procfs_core::process::MemoryMaps::from_read(maps.as_slice())
+            .map_err(|_| CoreDumpError::InvalidNote("NT_FILE"))?;
+        self.mappings = MappingInfo::aggregate(memory_maps, linux_gate_loc)?;
+        self.maps = maps;
+        Ok(())
+    }
+
+    /// The id of the process.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// The thread which received the signal.
+    pub fn crashing_thread(&self) -> Pid {
+        self.threads[0].tid
+    }
+
+    /// The number of the signal which caused the core dump, 0 if the core
+    /// doesn't say, e.g. when it was written by `gcore`.
+    pub fn signal(&self) -> u32 {
+        self.signal.number
+    }
+
+    /// Blame signal `number` for the core dump, e.g. the `%s` parameter of a
+    /// `core_pattern` handler, for cores which don't say which signal caused
+    /// them. Its code and address are only kept if it's the same signal.
+    pub fn set_signal(&mut self, number: u32) -> &mut Self {
+        if number != self.signal.number {
+            self.signal = Signal {
+                number,
+                ..Default::default()
+            };
+        }
+        self
+    }
+
+    /// A writer for the minidump of the core, blaming its crashing thread.
+    pub fn minidump_writer(&self) -> MinidumpWriter {
+        MinidumpWriter::new(self.pid, self.crashing_thread())
+    }
+
+    /// The memory of the process from `address` to the end of the part of
+    /// its mapping the core holds.
+    fn memory_at(&self, address: usize) -> Option<&[u8]> {
+        self.segments.iter().find_map(|segment| {
+            let offset = address.checked_sub(segment.address)?;
+            (offset < segment.data.len())
+                .then(|| &self.data[segment.data.start + offset..segment.data.end])
+        })
+    }
+
+    /// Write the minidump of the core to `destination`, with the options of
+    /// `writer`, e.g. as returned by [`Self::minidump_writer`], which apply
+    /// to what the core holds. Returns the in-memory version of the minidump
+    /// as well, like [`MinidumpWriter::dump`].
+    pub fn write_minidump(
+        &self,
+        writer: &mut MinidumpWriter,
+        destination: &mut (impl Write + Seek),
+    ) -> Result<Vec<u8>, CoreDumpError> {
+        writer.clear_dump_state();
+        writer.denied_ranges = writer.memory_denylist.resolve(&self.mappings);
+        let mut buffer = DumpBuf::with_capacity(0);
+        self.generate_dump(writer, &mut buffer, destination)?;
+        Ok(buffer.into())
+    }
+
+    fn generate_dump(
+        &self,
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+        destination: &mut (impl Write + Seek),
+    ) -> Result<(), WriterError> {
+        let mut header_section = MemoryWriter::<MDRawHeader>::alloc(buffer)?;
+        let mut dir_section = DirSection::new(buffer, NUM_STREAMS, destination)?;
+        let header = MDRawHeader {
+            signature: MD_HEADER_SIGNATURE,
+            version: MD_HEADER_VERSION,
+            stream_count: NUM_STREAMS,
+            stream_directory_rva: dir_section.position(),
+            checksum: 0,
+            time_date_stamp: config
+                .now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs() as u32,
+            flags: 0,
+        };
+        header_section.set_value(buffer, header)?;
+        dir_section.write_to_file(buffer, None)?;
+
+        let dirent = self.write_thread_list(config, buffer)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        let dirent = config.write_optional("ModuleList", |config| {
+            self.write_module_list(config, buffer)
+        })?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        config.write_optional("RegisterMemory", |config| {
+            register_memory::write(config, buffer, self)
+        })?;
+        config.write_optional("ReferencedMemory", |config| {
+            referenced_memory::write(config, buffer, self)
+        })?;
+        let dirent = memory_list_stream::write(config, buffer)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        let dirent = self.write_exception(config, buffer)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        let dirent = systeminfo_stream::write(buffer)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        let dirent =
+            config.write_optional("ThreadNames", |_| thread_names_stream::write(buffer, self))?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        for (stream_type, contents) in [
+            (MDStreamType::LinuxMaps, &self.maps),
+            (MDStreamType::LinuxAuxv, &self.auxv),
+            (MDStreamType::LinuxCmdLine, &self.cmdline),
+        ] {
+            let dirent = if contents.is_empty() {
+                MDRawDirectory::default()
+            } else {
+                MDRawDirectory {
+                    stream_type: stream_type as u32,
+                    location: MemoryArrayWriter::write_bytes(buffer, contents)?.location(),
+                }
+            };
+            dir_section.write_to_file(buffer, Some(dirent))?;
+        }
+
+        let memory64_list = Memory64List::alloc(config, buffer, self)?;
+        dir_section.write_to_file(buffer, None)?;
+
+        let dirent = soft_errors_stream::write(config, buffer)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        let dirent = memory64_list.write(config, buffer, self)?;
+        dir_section.write_to_file(buffer, Some(dirent))?;
+
+        // If you add more directory entries, don't forget to update NUM_STREAMS.
+        Ok(())
+    }
+
+    /// Write the threads with their registers and stacks, and the memory
+    /// around the crashing instruction, as for live processes.
+    fn write_thread_list(
+        &self,
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+    ) -> Result<MDRawDirectory, SectionThreadListError> {
+        let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, self.threads.len() as u32)?;
+        let mut dirent = MDRawDirectory {
+            stream_type: MDStreamType::ThreadListStream as u32,
+            location: list_header.location(),
+        };
+        let mut thread_list =
+            MemoryArrayWriter::<MDRawThread>::alloc_array(buffer, self.threads.len())?;
+        dirent.location.data_size += thread_list.location().data_size;
+
+        for (idx, item) in self.threads.iter().enumerate() {
+            config.check_cancelled()?;
+            let info = self.thread_state(item)?;
+            let mut thread = MDRawThread {
+                thread_id: item.tid.try_into()?,
+                suspend_count: 0,
+                priority_class: 0,
+                priority: 0,
+                teb: info.get_thread_pointer().unwrap_or_default() as u64,
+                stack: MDMemoryDescriptor::default(),
+                thread_context: MDLocationDescriptor::default(),
+            };
+
+            let blamed = item.tid == config.blamed_thread;
+            // Minimal dumps only have the crashing thread's stack
+            if blamed || !config.minimal {
+                self.fill_thread_stack(config, buffer, &mut thread, info.stack_pointer)?;
+            }
+
+            let mut cpu = RawContextCPU::default();
+            info.fill_cpu_context(&mut cpu);
+            if blamed {
+                config.crashing_thread_registers =
+                    crate::linux::crash_context::general_registers(&cpu);
+            }
+            let cpu_section = MemoryWriter::<RawContextCPU>::alloc_with_val(buffer, cpu)?;
+            thread.thread_context = cpu_section.location();
+            if blamed {
+                let instruction_ptr = info.get_instruction_pointer();
+                self.fill_instruction_memory(config, buffer, instruction_ptr)?;
+                config.crashing_thread_context = CrashingThreadContext::CrashContextPlusAddress((
+                    cpu_section.location(),
+                    instruction_ptr,
+                ));
+            }
+            thread_list.set_value_at(buffer, thread, idx)?;
+        }
+        Ok(dirent)
+    }
+
+    /// Capture the stack of `thread` from the page of the stack pointer to
+    /// the end of its mapping, as for live processes.
+    fn fill_thread_stack(
+        &self,
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+        thread: &mut MDRawThread,
+        stack_ptr: usize,
+    ) -> Result<(), SectionThreadListError> {
+        thread.stack.start_of_memory_range = stack_ptr as u64;
+        let Some(mapping) = self
+            .mappings
+            .iter()
+            .find(|mapping| mapping.contains_address(stack_ptr))
+        else {
+            log::warn!(
+                "stack pointer {stack_ptr:#x} of thread {} is not mapped",
+                thread.thread_id
+            );
+            return Ok(());
+        };
+
+        let start = (stack_ptr & !(self.page_size - 1)).max(mapping.start_address);
+        let mut len = mapping.end_address() - start;
+        if let Some(max_bytes) = config.max_stack_bytes(thread.thread_id as Pid) {
+            len = len.min((stack_ptr - start).saturating_add(max_bytes));
+        }
+        let mut stack_bytes = match self.read_memory(start, len) {
+            Ok(stack_bytes) => stack_bytes,
+            Err(e) => {
+                log::warn!(
+                    "the stack of thread {} isn't in the core: {e}",
+                    thread.thread_id
+                );
+                return Ok(());
+            }
+        };
+        config.redact_memory(start, &mut stack_bytes);
+
+        thread.stack = MDMemoryDescriptor {
+            start_of_memory_range: start as u64,
+            memory: MemoryArrayWriter::write_bytes(buffer, &stack_bytes)?.location(),
+        };
+        config.memory_blocks.push(thread.stack);
+        Ok(())
+    }
+
+    /// Capture the memory around the crashing instruction, if the core holds
+    /// it, which it usually doesn't for the code of files.
+    fn fill_instruction_memory(
+        &self,
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+        instruction_ptr: usize,
+    ) -> Result<(), SectionThreadListError> {
+        let Some(mapping) = self
+            .mappings
+            .iter()
+            .find(|mapping| mapping.contains_address(instruction_ptr))
+        else {
+            return Ok(());
+        };
+        let start = mapping
+            .start_address
+            .max(instruction_ptr.saturating_sub(IP_MEMORY_SIZE / 2));
+        let end = mapping
+            .end_address()
+            .min(instruction_ptr.saturating_add(IP_MEMORY_SIZE / 2));
+
+        for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, start..end) {
+            let Ok(mut bytes) = self.read_memory(range.start, range.len()) else {
+                continue;
+            };
+            config.redact_memory(range.start, &mut bytes);
+            let memory = MemoryArrayWriter::write_bytes(buffer, &bytes)?.location();
+            config.memory_blocks.push(MDMemoryDescriptor {
+                start_of_memory_range: range.start as u64,
+                memory,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write the modules, the mappings of ELF files whose build id could be
+    /// read from the core or from the file. Minimal dumps only list the
+    /// modules referenced by the threads, whose build ids are the only ones
+    /// read, see [`MinidumpWriter::minimal`].
+    fn write_module_list(
+        &self,
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+    ) -> Result<MDRawDirectory, SectionMappingsError> {
+        let referenced = config.minimal.then(|| {
+            let thread_infos: Vec<_> = self
+                .threads
+                .iter()
+                .filter_map(|thread| Some((thread.tid, self.thread_state(thread).ok()?)))
+                .collect();
+            let thread_infos = thread_infos.iter().map(|(tid, info)| (*tid, info));
+            mappings::ReferencedCode::from_threads(config, buffer, thread_infos)
+        });
+
+        let mut raw_modules = Vec::new();
+        for mapping in &self.mappings {
+            config.check_cancelled()?;
+            if !mapping.is_interesting() || mapping.is_kernel_page() {
+                continue;
+            }
+            if referenced
+                .as_ref()
+                .is_some_and(|referenced| !referenced.is_referenced(mapping))
+            {
+                continue;
+            }
+
+            // The headers are in the core if the kernel kept the first page
+            // of the files, as it does by default
+            let headers = self.memory_at(mapping.start_address);
+            let from_core = headers.map(|headers| BuildId::read_from_module(headers.into()));
+            let identifier = match (from_core, &mapping.name) {
+                (Some(Ok(identifier)), _) => Ok(identifier),
+                // A deleted file might have been replaced by a different one
+                // with the same name, whose build id would be wrong
+                (_, Some(name)) if !mapping.deleted && Path::new(name).exists() => {
+                    BuildId::read_from_file(Path::new(name))
+                }
+                (Some(Err(e)), _) => Err(e),
+                (None, _) => continue,
+            };
+            let BuildId(identifier) = match identifier {
+                Ok(identifier) => identifier,
+                Err(e) => {
+                    log::warn!("failed to get build id for mapping: {e}");
+                    continue;
+                }
+            };
+            if identifier.iter().all(|&x| x == 0) {
+                continue;
+            }
+            let soname = headers
+                .and_then(|headers| SoName::read_from_module(headers.into()).ok())
+                .map(|SoName(soname)| soname);
+
+            raw_modules.push(mappings::fill_raw_module(
+                buffer,
+                mapping,
+                &identifier,
+                soname,
+                None,
+            )?);
+        }
+
+        config.listed_modules = raw_modules
+            .iter()
+            .map(|module| module.base_of_image)
+            .collect();
+        let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, raw_modules.len() as u32)?;
+        let mut dirent = MDRawDirectory {
+            stream_type: MDStreamType::ModuleListStream as u32,
+            location: list_header.location(),
+        };
+        if !raw_modules.is_empty() {
+            let mapping_list =
+                MemoryArrayWriter::<MDRawModule>::alloc_from_iter(buffer, raw_modules)?;
+            dirent.location.data_size += mapping_list.location().data_size;
+        }
+        Ok(dirent)
+    }
+
+    /// Write the signal which caused the core dump, or a `DUMP_REQUESTED`
+    /// exception at the crashing instruction if there was none, e.g. for the
+    /// cores written by `gcore`.
+    fn write_exception(
+        &self,
+        config: &MinidumpWriter,
+        buffer: &mut DumpBuf,
+    ) -> Result<MDRawDirectory, SectionExceptionStreamError> {
+        let (thread_context, instruction_ptr) = match config.crashing_thread_context {
+            CrashingThreadContext::CrashContextPlusAddress((context, address)) => {
+                (context, address)
+            }
+            _ => (MDLocationDescriptor::default(), 0),
+        };
+        let exception_record = if self.signal.number != 0 {
+            MDException {
+                exception_code: self.signal.number,
+                exception_flags: self.signal.code,
+                exception_address: self.signal.address,
+                ..Default::default()
+            }
+        } else {
+            MDException {
+                exception_code: ExceptionCodeLinux::DUMP_REQUESTED as u32,
+                exception_address: instruction_ptr as u64,
+                ..Default::default()
+            }
+        };
+
+        let stream = MDRawExceptionStream {
+            thread_id: config.blamed_thread as u32,
+            exception_record,
+            __align: 0,
+            thread_context,
+        };
+        let exc = MemoryWriter::alloc_with_val(buffer, stream)?;
+        Ok(MDRawDirectory {
+            stream_type: MDStreamType::ExceptionStream as u32,
+            location: exc.location(),
+        })
+    }
+}
+
+impl ProcessDumper for CoreDump {
+    type Thread = Thread;
+    type Mapping = MappingInfo;
+    type ThreadState = ThreadInfo;
+    type Error = DumperError;
+
+    /// Read the memory the core holds, the parts it doesn't hold can't be
+    /// read, as if they were unmapped.
+    fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>, DumperError> {
+        self.memory_at(address)
+            .and_then(|memory| memory.get(..length))
+            .map(<[u8]>::to_vec)
+            .ok_or(DumperError::CopyFromProcessError(CopyFromProcessError {
+                child: self.pid,
+                src: address,
+                offset: 0,
+                length,
+                source: nix::Error::EFAULT,
+            }))
+    }
+
+    fn threads(&self) -> Result<Cow<'_, [Thread]>, DumperError> {
+        Ok(Cow::Borrowed(&self.threads))
+    }
+
+    fn mappings(&self) -> Result<Cow<'_, [MappingInfo]>, DumperError> {
+        Ok(Cow::Borrowed(&self.mappings))
+    }
+
+    fn memory_map(&self) -> Result<Vec<MemoryMapping>, DumperError> {
+        let maps = MemoryMaps::from_read(self.maps.as_slice()).map_err(MapsReaderError::from)?;
+        Ok(maps_reader::memory_map(maps))
+    }
+
+    fn thread_state(&self, thread: &Thread) -> Result<ThreadInfo, DumperError> {
+        self.threads
+            .iter()
+            .position(|other| other.tid == thread.tid)
+            .and_then(|idx| {
+                let (ppid, regsets) = &self.thread_states[idx];
+                ThreadInfo::from_regsets(self.pid, *ppid, regsets)
+            })
+            .ok_or_else(|| ThreadInfoError::MissingRegisters(thread.tid).into())
+    }
+}
+
+/// The notes of a `PT_NOTE` segment, as (type, contents) pairs. The names and
+/// contents of the notes of core dumps are 4-byte aligned.
+fn notes(mut segment: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let align = |offset: usize| offset.next_multiple_of(4);
+    std::iter::from_fn(move || {
+        let name_size = read_u32(segment, 0)? as usize;
+        let desc_size = read_u32(segment, 4)? as usize;
+        let note_type = read_u32(segment, 8)?;
+        let desc_start = align(12 + name_size);
+        let desc = segment.get(desc_start..desc_start + desc_size)?;
+        segment = segment
+            .get(align(desc_start + desc_size)..)
+            .unwrap_or_default();
+        Some((note_type, desc))
+    })
+}
+
+/// The files of an `NT_FILE` note and the page size their offsets are in. It
+/// holds their number and the page size, the start, end and offset of each,
+/// then their NUL-terminated names.
+fn parse_file_note(desc: &[u8]) -> Option<(Vec<MappedFile>, usize)> {
+    let count = read_u64(desc, 0)? as usize;
+    let page_size = read_u64(desc, 8)? as usize;
+    let names_start = count.checked_mul(24)?.checked_add(16)?;
+    let mut names = desc.get(names_start..)?.split(|&b| b == 0);
+
+    let files = (0..count)
+        .map(|idx| {
+            let entry = 16 + idx * 24;
+            Some(MappedFile {
+                start: read_u64(desc, entry)? as usize,
+                end: read_u64(desc, entry + 8)? as usize,
+                offset: (read_u64(desc, entry + 16)? as usize).checked_mul(page_size)?,
+                name: names.next()?.to_vec(),
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some((files, page_size))
+}
+
+/// `bytes` up to the first NUL, if any.
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> Option<i32> {
+    read_u32(bytes, offset).map(|value| value as i32)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    const PID: Pid = 4321;
+    const STACK: usize = 0x7ffd_0000;
+    const LIBRARY: usize = 0x7f00_0000;
+
+    fn note(note_type: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&5u32.to_ne_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_ne_bytes());
+        note.extend_from_slice(&note_type.to_ne_bytes());
+        note.extend_from_slice(b"CORE\0\0\0\0");
+        note.extend_from_slice(desc);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note
+    }
+
+    fn prstatus(tid: Pid, signal: i32, stack_pointer: u64) -> Vec<u8> {
+        let regs_len = std::mem::size_of::<libc::user_regs_struct>();
+        let mut desc = vec![0u8; PRSTATUS_REG_OFFSET + regs_len + 8];
+        desc[..4].copy_from_slice(&signal.to_ne_bytes());
+        desc[PRSTATUS_PID_OFFSET..][..4].copy_from_slice(&tid.to_ne_bytes());
+        desc[PRSTATUS_PPID_OFFSET..][..4].copy_from_slice(&1i32.to_ne_bytes());
+        // SAFETY: all zeroes is a valid register state
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                regs.rsp = stack_pointer;
+                regs.rip = LIBRARY as u64 + 0x10;
+            } else {
+                regs.sp = stack_pointer;
+                regs.pc = LIBRARY as u64 + 0x10;
+            }
+        }
+        // SAFETY: the registers are a plain structure
+        let regs = unsafe {
+            std::slice::from_raw_parts((&regs as *const libc::user_regs_struct).cast(), regs_len)
+        };
+        desc[PRSTATUS_REG_OFFSET..][..regs_len].copy_from_slice(regs);
+        desc
+    }
+
+    /// A core dump of a process with two threads and a library, whose first
+    /// page only holds an ELF header, and the stack
+    fn core_dump() -> Vec<u8> {
+        let mut prpsinfo = vec![0u8; PRPSINFO_PSARGS.end];
+        prpsinfo[PRPSINFO_PID_OFFSET..][..4].copy_from_slice(&PID.to_ne_bytes());
+        prpsinfo[PRPSINFO_FNAME][..5].copy_from_slice(b"crash");
+        prpsinfo[PRPSINFO_PSARGS][..11].copy_from_slice(b"crash --now");
+
+        let mut siginfo = vec![0u8; 128];
+        siginfo[..4].copy_from_slice(&libc::SIGSEGV.to_ne_bytes());
+        siginfo[SIGINFO_CODE_OFFSET..][..4].copy_from_slice(&1i32.to_ne_bytes());
+        siginfo[SIGINFO_ADDR_OFFSET..][..8].copy_from_slice(&0xdeadu64.to_ne_bytes());
+
+        let mut file = Vec::new();
+        for value in [
+            2,
+            4096,
+            LIBRARY,
+            LIBRARY + 0x1000,
+            0,
+            LIBRARY + 0x1000,
+            LIBRARY + 0x2000,
+            1,
+        ] {
+            file.extend_from_slice(&(value as u64).to_ne_bytes());
+        }
+        file.extend_from_slice(b"/lib/libcrash.so\0/lib/libcrash.so\0");
+
+        let notes = [
+            note(
+                NT_PRSTATUS,
+                &prstatus(PID + 1, libc::SIGSEGV, STACK as u64 + 0x800),
+            ),
+            note(NT_PRPSINFO, &prpsinfo),
+            note(NT_SIGINFO, &siginfo),
+            note(NT_FILE, &file),
+            note(NT_PRSTATUS, &prstatus(PID, 0, STACK as u64 + 0x400)),
+        ]
+        .concat();
+
+        // The ELF header of the library, the only part of it in the core
+        let mut library = vec![0u8; 0x1000];
+        library[..4].copy_from_slice(b"\x7fELF");
+        let mut stack = vec![0u8; 0x1000];
+        stack[0x800..0x808].copy_from_slice(&0x1234u64.to_ne_bytes());
+
+        // The ELF header, then the program headers, then the contents
+        let segments: [(u32, usize, usize, u32, &[u8]); 4] = [
+            (program_header::PT_NOTE, 0, 0, 0, &notes),
+            (program_header::PT_LOAD, LIBRARY, 0x1000, 5, &library),
+            (program_header::PT_LOAD, LIBRARY + 0x1000, 0x1000, 5, &[]),
+            (program_header::PT_LOAD, STACK, 0x1000, 6, &stack),
+        ];
+        let mut core = vec![0u8; 64 + 56 * segments.len()];
+        core[..4].copy_from_slice(b"\x7fELF");
+        core[4] = 2; // ELFCLASS64
+        core[5] = 1; // ELFDATA2LSB
+        core[6] = 1; // EV_CURRENT
+        core[16..18].copy_from_slice(&ET_CORE.to_ne_bytes());
+        core[18..20].copy_from_slice(&MACHINE.to_ne_bytes());
+        core[20..24].copy_from_slice(&1u32.to_ne_bytes());
+        core[32..40].copy_from_slice(&64u64.to_ne_bytes());
+        core[52..54].copy_from_slice(&64u16.to_ne_bytes());
+        core[54..56].copy_from_slice(&56u16.to_ne_bytes());
+        core[56..58].copy_from_slice(&(segments.len() as u16).to_ne_bytes());
+
+        for (idx, (p_type, address, size, flags, contents)) in segments.into_iter().enumerate() {
+            let header = 64 + idx * 56;
+            let offset = core.len();
+            core[header..header + 4].copy_from_slice(&p_type.to_ne_bytes());
+            core[header + 4..header + 8].copy_from_slice(&flags.to_ne_bytes());
+            core[header + 8..header + 16].copy_from_slice(&(offset as u64).to_ne_bytes());
+            core[header + 16..header + 24].copy_from_slice(&(address as u64).to_ne_bytes());
+            core[header + 32..header + 40].copy_from_slice(&(contents.len() as u64).to_ne_bytes());
+            core[header + 40..header + 48].copy_from_slice(&(size as u64).to_ne_bytes());
+            core.extend_from_slice(contents);
+        }
+        core
+    }
+
+    #[test]
+    fn test_parse() {
+        let core = CoreDump::from_bytes(core_dump()).unwrap();
+        assert_eq!(core.pid(), PID);
+        assert_eq!(core.crashing_thread(), PID + 1);
+        let threads: Vec<_> = core
+            .threads
+            .iter()
+            .map(|thread| (thread.tid, thread.name.as_deref()))
+            .collect();
+        assert_eq!(threads, [(PID + 1, None), (PID, Some("crash"))]);
+        assert_eq!(
+            core.signal,
+            Signal {
+                number: libc::SIGSEGV as u32,
+                code: 1,
+                address: 0xdead
+            }
+        );
+        assert_eq!(core.cmdline, b"crash --now");
+
+        // The segments of the library are merged into a module
+        assert_eq!(core.mappings.len(), 2);
+        let library = &core.mappings[0];
+        assert_eq!(library.start_address, LIBRARY);
+        assert_eq!(library.size, 0x2000);
+        assert_eq!(
+            library.name.as_deref(),
+            Some(OsStr::new("/lib/libcrash.so"))
+        );
+        assert!(library.is_executable());
+        assert_eq!(core.mappings[1].name, None);
+        assert!(core.mappings[1].is_writable());
+
+        // Only what the core holds can be read
+        assert_eq!(core.read_memory(LIBRARY, 4).unwrap(), b"\x7fELF");
+        assert!(core.read_memory(LIBRARY + 0x1000, 4).is_err());
+        assert!(core.read_memory(STACK + 0xffc, 8).is_err());
+        assert_eq!(
+            core.read_memory(STACK + 0x800, 8).unwrap(),
+            0x1234u64.to_ne_bytes()
+        );
+
+        let state = core.thread_state(&core.threads[0]).unwrap();
+        assert_eq!(state.stack_pointer, STACK + 0x800);
+        assert_eq!(state.tgid, PID);
+        assert_eq!(state.get_instruction_pointer(), LIBRARY + 0x10);
+    }
+
+    #[test]
+    fn test_set_signal() {
+        let mut core = CoreDump::from_bytes(core_dump()).unwrap();
+        core.set_signal(libc::SIGSEGV as u32);
+        assert_eq!(core.signal.address, 0xdead);
+        core.set_signal(libc::SIGABRT as u32);
+        assert_eq!(core.signal(), libc::SIGABRT as u32);
+        assert_eq!((core.signal.code, core.signal.address), (0, 0));
+    }
+
+    #[test]
+    fn test_not_a_core() {
+        let mut core = core_dump();
+        core[16] = 2; // ET_EXEC
+        assert!(matches!(
+            CoreDump::from_bytes(core),
+            Err(CoreDumpError::NotACoreDump)
+        ));
+    }
+
+    #[test]
+    fn test_segment_overflow() {
+        let mut core = core_dump();
+        // The size of the stack segment, the last one
+        let size = 64 + 3 * 56 + 40;
+        core[size..size + 8].copy_from_slice(&u64::MAX.to_ne_bytes());
+        assert!(matches!(
+            CoreDump::from_bytes(core),
+            Err(CoreDumpError::InvalidSegment(STACK))
+        ));
+    }
+
+    #[test]
+    fn test_write_minidump() {
+        let core = CoreDump::from_bytes(core_dump()).unwrap();
+        let mut writer = core.minidump_writer();
+        let mut destination = std::io::Cursor::new(Vec::new());
+        let dump = core.write_minidump(&mut writer, &mut destination).unwrap();
+        assert_eq!(dump, destination.into_inner());
+
+        let read_u32 = |offset: usize| read_u32(&dump, offset).unwrap();
+        assert_eq!(read_u32(0), MD_HEADER_SIGNATURE);
+        assert_eq!(read_u32(8), NUM_STREAMS);
+        let directory = read_u32(12) as usize;
+        let stream = |stream_type: MDStreamType| {
+            (0..NUM_STREAMS as usize)
+                .map(|idx| directory + idx * 12)
+                .find(|&entry| read_u32(entry) == stream_type as u32)
+                .map(|entry| (read_u32(entry + 4) as usize, read_u32(entry + 8) as usize))
+                .unwrap()
+        };
+
+        // Both threads, with their stacks
+        let (_, threads) = stream(MDStreamType::ThreadListStream);
+        assert_eq!(read_u32(threads), 2);
+        assert_eq!(read_u32(threads + 4), PID as u32 + 1);
+        // And the memory around the crashing instruction
+        let blocks: Vec<_> = writer
+            .memory_blocks
+            .iter()
+            .map(|block| block.start_of_memory_range as usize)
+            .collect();
+        assert_eq!(blocks, [STACK, LIBRARY, STACK]);
+
+        let (_, exception) = stream(MDStreamType::ExceptionStream);
+        assert_eq!(read_u32(exception), PID as u32 + 1);
+        assert_eq!(read_u32(exception + 8), libc::SIGSEGV as u32);
+
+        let (size, maps) = stream(MDStreamType::LinuxMaps);
+        let maps = std::str::from_utf8(&dump[maps..maps + size]).unwrap();
+        assert_eq!(
+            maps.lines().next(),
+            Some("7f000000-7f001000 r-xp 00000000 00:00 1 /lib/libcrash.so")
+        );
+    }
+}
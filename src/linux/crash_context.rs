@@ -15,5 +15,96 @@ cfg_if::cfg_if! {
         mod aarch64;
     } else if #[cfg(target_arch = "arm")] {
         mod arm;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        mod loongarch64;
+    }
+}
+
+impl CrashContext {
+    /// The alternate signal stack of the crashing thread, if it had one when
+    /// the signal was delivered, whether or not the handler runs on it.
+    pub fn alternate_signal_stack(&self) -> Option<std::ops::Range<usize>> {
+        let stack = &self.inner.context.uc_stack;
+        let start = stack.ss_sp as usize;
+        (stack.ss_flags & libc::SS_DISABLE == 0 && stack.ss_size > 0)
+            .then(|| start..start.saturating_add(stack.ss_size))
+    }
+
+    /// Whether the faulting access was a write, for the architectures where
+    /// the kernel reports it: the page fault error code of x86.
+    pub fn is_write_fault(&self) -> Option<bool> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+                const PF_WRITE: u64 = 2;
+                let error_code = self.inner.context.uc_mcontext.gregs[libc::REG_ERR as usize];
+                Some(error_code as u64 & PF_WRITE != 0)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The instruction and stack pointers held by a minidump CPU context.
+pub(crate) fn context_pointers(cpu: &crate::minidump_cpu::RawContextCPU) -> (usize, usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            (cpu.rip as usize, cpu.rsp as usize)
+        } else if #[cfg(target_arch = "x86")] {
+            (cpu.eip as usize, cpu.esp as usize)
+        } else if #[cfg(target_arch = "aarch64")] {
+            (cpu.pc as usize, cpu.sp as usize)
+        } else if #[cfg(target_arch = "arm")] {
+            (cpu.iregs[15] as usize, cpu.iregs[13] as usize)
+        } else if #[cfg(target_arch = "loongarch64")] {
+            (cpu.pc as usize, cpu.iregs[3] as usize)
+        }
+    }
+}
+
+/// The frame pointer held by a minidump CPU context, on the architectures
+/// whose frame records are the caller's frame pointer followed by the return
+/// address.
+pub(crate) fn context_frame_pointer(cpu: &crate::minidump_cpu::RawContextCPU) -> Option<usize> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            Some(cpu.rbp as usize)
+        } else if #[cfg(target_arch = "x86")] {
+            Some(cpu.ebp as usize)
+        } else if #[cfg(target_arch = "aarch64")] {
+            Some(cpu.iregs[29] as usize)
+        } else {
+            let _ = cpu;
+            None
+        }
+    }
+}
+
+/// The general purpose registers held by a minidump CPU context, other than
+/// the instruction and stack pointers.
+pub(crate) fn general_registers(cpu: &crate::minidump_cpu::RawContextCPU) -> Vec<u64> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            vec![
+                cpu.rax, cpu.rcx, cpu.rdx, cpu.rbx, cpu.rbp, cpu.rsi, cpu.rdi, cpu.r8, cpu.r9,
+                cpu.r10, cpu.r11, cpu.r12, cpu.r13, cpu.r14, cpu.r15,
+            ]
+        } else if #[cfg(target_arch = "x86")] {
+            [cpu.eax, cpu.ecx, cpu.edx, cpu.ebx, cpu.ebp, cpu.esi, cpu.edi]
+                .into_iter()
+                .map(u64::from)
+                .collect()
+        } else if #[cfg(target_arch = "aarch64")] {
+            cpu.iregs.to_vec()
+        } else if #[cfg(target_arch = "arm")] {
+            cpu.iregs[..13].iter().copied().map(u64::from).collect()
+        } else if #[cfg(target_arch = "loongarch64")] {
+            cpu.iregs
+                .iter()
+                .enumerate()
+                .filter(|&(idx, _)| idx != 3)
+                .map(|(_, &reg)| reg)
+                .collect()
+        }
     }
 }
@@ -0,0 +1,24 @@
+use super::CrashContext;
+use crate::minidump_cpu::{RawContextCPU, GP_REG_COUNT};
+
+/// The index of the stack pointer in the general purpose registers
+const REG_SP: usize = 3;
+
+impl CrashContext {
+    pub fn get_instruction_pointer(&self) -> usize {
+        self.inner.context.uc_mcontext.sc_pc as usize
+    }
+
+    pub fn get_stack_pointer(&self) -> usize {
+        self.inner.context.uc_mcontext.sc_regs[REG_SP] as usize
+    }
+
+    pub fn fill_cpu_context(&self, out: &mut RawContextCPU) {
+        let mcontext = &self.inner.context.uc_mcontext;
+        out.iregs[..GP_REG_COUNT].copy_from_slice(&mcontext.sc_regs[..GP_REG_COUNT]);
+        out.pc = mcontext.sc_pc;
+        // The floating point registers are stored in the extended context
+        // records following the signal context, which aren't part of the
+        // crash context, so they are left zeroed
+    }
+}
@@ -0,0 +1,235 @@
+//! A compact JSON summary of a crash, written alongside the minidump so that
+//! servers can triage and deduplicate crashes without parsing the minidump.
+
+use crate::{
+    linux::{
+        dumper_cpu_info, exploitability::Rating, minidump_writer::MinidumpWriter,
+        ptrace_dumper::PtraceDumper,
+    },
+    Pid,
+};
+use std::{collections::BTreeMap, fmt::Write as _, io::Write};
+
+/// What caused the dump and where, along with some basic information about
+/// the system, see [`MinidumpWriter::crash_summary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashSummary {
+    /// The name of the signal which crashed the process, e.g. `SIGSEGV`, or
+    /// `DUMP_REQUESTED` if it didn't crash
+    pub crash_reason: String,
+    /// The reason given by the application, see
+    /// [`MinidumpWriter::set_crash_reason`]
+    pub crash_message: Option<String>,
+    /// The `si_code` of the signal, 0 if it didn't crash
+    pub signal_code: i32,
+    /// The faulting address for signals which have one
+    pub crash_address: u64,
+    pub crashing_thread: Pid,
+    /// The path of the module containing the instruction pointer of the
+    /// crashing thread, and the offset of the instruction pointer in it
+    pub top_module: Option<(String, u64)>,
+    /// `Linux` or `Android`
+    pub os: &'static str,
+    /// The kernel name, release, version and machine, as reported by `uname`
+    pub os_version: String,
+    pub cpu_arch: &'static str,
+    pub cpu_count: u32,
+    /// How likely the crash is to be exploitable, if it was assessed, see
+    /// [`MinidumpWriter::assess_exploitability`]
+    pub exploitability: Option<Rating>,
+    /// See [`MinidumpWriter::set_annotation`]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl CrashSummary {
+    pub(crate) fn collect(config: &MinidumpWriter, dumper: &PtraceDumper) -> Self {
+        let (crash_reason, signal_code, crash_address) = match &config.crash_context {
+            Some(context) => {
+                let siginfo = &context.inner.siginfo;
+                let reason = nix::sys::signal::Signal::try_from(siginfo.ssi_signo as i32)
+                    .map_or_else(|_| siginfo.ssi_signo.to_string(), |s| s.as_str().to_owned());
+                (reason, siginfo.ssi_code, siginfo.ssi_addr)
+            }
+            None => ("DUMP_REQUESTED".to_owned(), 0, 0),
+        };
+
+        let instruction_pointer = match config.supplied_blamed_thread_pointers() {
+            Some((instruction_ptr, _)) => Some(instruction_ptr),
+            None => dumper
+                .threads
+                .iter()
+                .position(|thread| thread.tid == config.blamed_thread)
+                .and_then(|idx| dumper.captured_thread_info(idx))
+                .map(|info| info.get_instruction_pointer()),
+        };
+        let top_module = instruction_pointer.and_then(|ip| {
+            let mapping = dumper.find_mapping(ip)?;
+            let name = mapping.name.as_ref()?.to_string_lossy().into_owned();
+            Some((name, (ip - mapping.start_address) as u64))
+        });
+
+        let (platform_id, os_version) = dumper_cpu_info::os_information();
+        // SAFETY: no preconditions
+        let cpu_count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1);
+
+        Self {
+            crash_reason,
+            crash_message: config.crash_reason.clone(),
+            signal_code,
+            crash_address,
+            crashing_thread: config.blamed_thread,
+            top_module,
+            os: if platform_id == minidump_common::format::PlatformId::Android {
+                "Android"
+            } else {
+                "Linux"
+            },
+            os_version,
+            cpu_arch: std::env::consts::ARCH,
+            cpu_count: cpu_count as u32,
+            exploitability: config
+                .exploitability
+                .as_ref()
+                .map(|assessment| assessment.rating),
+            annotations: config.annotations.clone(),
+        }
+    }
+
+    /// The summary as a single line JSON object. Addresses and offsets are
+    /// hexadecimal strings, as 64-bit integers aren't portable in JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        // Writing to a String is infallible
+        let _ = write!(
+            json,
+            r#""crash_reason":{},"#,
+            json_string(&self.crash_reason)
+        );
+        match &self.crash_message {
+            Some(message) => {
+                let _ = write!(json, r#""crash_message":{},"#, json_string(message));
+            }
+            None => json.push_str(r#""crash_message":null,"#),
+        }
+        let _ = write!(
+            json,
+            r#""signal_code":{},"crash_address":"{:#x}","crashing_thread":{}"#,
+            self.signal_code, self.crash_address, self.crashing_thread
+        );
+        match &self.top_module {
+            Some((name, offset)) => {
+                let _ = write!(
+                    json,
+                    r#","top_module":{},"top_module_offset":"{offset:#x}""#,
+                    json_string(name)
+                );
+            }
+            None => json.push_str(r#","top_module":null,"top_module_offset":null"#),
+        }
+        let _ = write!(
+            json,
+            r#","os":{},"os_version":{},"cpu_arch":{},"cpu_count":{}"#,
+            json_string(self.os),
+            json_string(&self.os_version),
+            json_string(self.cpu_arch),
+            self.cpu_count
+        );
+        match self.exploitability {
+            Some(rating) => {
+                let _ = write!(
+                    json,
+                    r#","exploitability":{}"#,
+                    json_string(rating.as_str())
+                );
+            }
+            None => json.push_str(r#","exploitability":null"#),
+        }
+        json.push_str(r#","annotations":{"#);
+        for (idx, (key, value)) in self.annotations.iter().enumerate() {
+            if idx > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{}:{}", json_string(key), json_string(value));
+        }
+        json.push_str("}}");
+        json
+    }
+
+    /// Write the summary to `destination` as JSON, see [`Self::to_json`].
+    pub fn write_json(&self, destination: &mut impl Write) -> std::io::Result<()> {
+        destination.write_all(self.to_json().as_bytes())?;
+        destination.flush()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let summary = CrashSummary {
+            crash_reason: "SIGSEGV".to_owned(),
+            crash_message: Some("MOZ_CRASH(oops)".to_owned()),
+            signal_code: 1,
+            crash_address: 0xdead,
+            crashing_thread: 42,
+            top_module: Some(("/usr/lib/libc.so.6".to_owned(), 0x1234)),
+            os: "Linux",
+            os_version: "Linux 6.1.0 #1 SMP x86_64".to_owned(),
+            cpu_arch: "x86_64",
+            cpu_count: 8,
+            exploitability: Some(Rating::High),
+            annotations: BTreeMap::from([
+                ("channel".to_owned(), "beta".to_owned()),
+                ("note".to_owned(), "a \"quoted\"\nline\u{1}".to_owned()),
+            ]),
+        };
+        assert_eq!(
+            summary.to_json(),
+            concat!(
+                r#"{"crash_reason":"SIGSEGV","crash_message":"MOZ_CRASH(oops)","signal_code":1,"#,
+                r#""crash_address":"0xdead","crashing_thread":42,"#,
+                r#""top_module":"/usr/lib/libc.so.6","top_module_offset":"0x1234","os":"Linux","#,
+                r#""os_version":"Linux 6.1.0 #1 SMP x86_64","cpu_arch":"x86_64","cpu_count":8,"#,
+                r#""exploitability":"high","#,
+                r#""annotations":{"channel":"beta","note":"a \"quoted\"\nline\u0001"}}"#
+            )
+        );
+
+        let summary = CrashSummary {
+            crash_message: None,
+            top_module: None,
+            exploitability: None,
+            annotations: BTreeMap::new(),
+            ..summary
+        };
+        assert!(summary.to_json().contains(r#""crash_message":null,"#));
+        assert!(summary
+            .to_json()
+            .contains(r#""top_module":null,"top_module_offset":null"#));
+        assert!(summary
+            .to_json()
+            .ends_with(r#""exploitability":null,"annotations":{}}"#));
+    }
+}
@@ -0,0 +1,482 @@
+//! Transport of crash information from a crashing process to a monitor process
+//! over a pre-established UNIX datagram socket.
+//!
+//! This follows the pattern used by Breakpad's Linux client: the crashing
+//! process sends its [`CrashContext`] along with its credentials
+//! (`SCM_CREDENTIALS`) and blocks until the monitor has written the minidump
+//! and acknowledged the request. The pid reported by the kernel in the
+//! credentials is what gets dumped, so a process can't request a dump of
+//! another one.
+//!
+//! The crashing process may run in another pid namespace than the monitor,
+//! e.g. in a container. The kernel translates the pid in the credentials to
+//! the monitor's namespace, the crashing thread's id is translated by looking
+//! it up among the threads of that process, see [`CrashRequest`].
+//!
+//! The socket is typically created with [`UnixDatagram::pair`] before the
+//! monitored process is spawned, with one end inherited by it.
+//!
+//! If the signal handler itself crashes while requesting a dump, the nested
+//! request isn't sent, as the monitor may already be dumping the process.
+//! A minimal minidump holding only the exception of the nested crash can be
+//! written instead, see [`CrashClient::set_fallback`].
+
+use crate::{
+    linux::{crash_context::CrashContext, errors::CrashTransportError, Pid},
+    minidump_cpu::RawContextCPU,
+    minidump_format::*,
+};
+use nix::{
+    errno::Errno,
+    sys::socket::{recvmsg, setsockopt, sockopt, ControlMessageOwned, MsgFlags, UnixCredentials},
+};
+use scroll::{ctx::SizeWith, Pwrite};
+use std::{
+    io::IoSliceMut,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+        unix::net::UnixDatagram,
+    },
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The byte sent back to the client once the dump has been written
+const ACK: u8 = 1;
+/// The size of the credentials control message's payload
+const CREDENTIALS_LEN: u32 = std::mem::size_of::<libc::ucred>() as u32;
+/// The 64-bit words holding the credentials control message, including its
+/// header and padding
+const CMSG_BUFFER_WORDS: usize = 8;
+/// The room for the minidump written by [`write_exception_dump`], which is
+/// laid out on the stack
+const EXCEPTION_DUMP_CAPACITY: usize = 2048;
+
+/// Set while a dump is being requested, so that crashes of the signal
+/// handler itself don't send another request
+static REQUEST_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// The crashing process' end of the socket.
+pub struct CrashClient {
+    socket: UnixDatagram,
+    fallback: Option<OwnedFd>,
+}
+
+impl CrashClient {
+    pub fn new(socket: UnixDatagram) -> Self {
+        Self {
+            socket,
+            fallback: None,
+        }
+    }
+
+    /// Write a minimal minidump to `fallback`, see [`write_exception_dump`],
+    /// when a dump is requested while another request is in progress, e.g.
+    /// because the signal handler crashed. `fallback` is typically a file
+    /// opened before any crash happened.
+    pub fn set_fallback(&mut self, fallback: OwnedFd) -> &mut Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    /// Sends the crash context to the monitor and waits for it to acknowledge
+    /// that the minidump has been written.
+    ///
+    /// The thread sending the request is blocked until then, the monitor
+    /// needs the process to be alive to dump it.
+    ///
+    /// This is async-signal-safe, so that it can be called from the signal
+    /// handler: it doesn't allocate nor take locks, which could deadlock if
+    /// the crash corrupted the heap, and only makes system calls.
+    ///
+    /// A request made while another one is in progress in the process, which
+    /// most likely means that the signal handler crashed, isn't sent. It
+    /// fails with [`CrashTransportError::NestedCrash`] instead, after writing
+    /// the fallback minidump if there is one, see [`Self::set_fallback`].
+    pub fn request_dump(&self, crash_context: &CrashContext) -> Result<(), CrashTransportError> {
+        if REQUEST_IN_PROGRESS.swap(true, Ordering::SeqCst) {
+            if let Some(fallback) = &self.fallback {
+                write_exception_dump(fallback.as_fd(), crash_context)?;
+            }
+            return Err(CrashTransportError::NestedCrash);
+        }
+
+        let result = self.send_request(crash_context);
+        REQUEST_IN_PROGRESS.store(false, Ordering::SeqCst);
+        result
+    }
+
+    fn send_request(&self, crash_context: &CrashContext) -> Result<(), CrashTransportError> {
+        let bytes = crash_context.inner.as_bytes();
+        let mut iov = libc::iovec {
+            iov_base: bytes.as_ptr() as *mut libc::c_void,
+            iov_len: bytes.len(),
+        };
+        // The control message lives on the stack, `nix::sys::socket::sendmsg`
+        // would allocate it
+        let mut cmsg_buffer = [0u64; CMSG_BUFFER_WORDS];
+        // SAFETY: all zeroes is a valid `msghdr`
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buffer.as_mut_ptr().cast();
+        // SAFETY: only computes a size
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(CREDENTIALS_LEN) } as _;
+        debug_assert!(msg.msg_controllen as usize <= std::mem::size_of_val(&cmsg_buffer));
+
+        // SAFETY: the buffer is large and aligned enough for a control message
+        // holding the credentials, and getpid(), getuid() and getgid() have
+        // no preconditions
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_CREDENTIALS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(CREDENTIALS_LEN) as _;
+            std::ptr::write_unaligned(
+                libc::CMSG_DATA(cmsg).cast(),
+                libc::ucred {
+                    pid: libc::getpid(),
+                    uid: libc::getuid(),
+                    gid: libc::getgid(),
+                },
+            );
+        }
+
+        // SAFETY: `msg` points to the buffers above, which outlive the call
+        Errno::result(unsafe { libc::sendmsg(self.socket.as_raw_fd(), &msg, 0) })?;
+
+        let mut ack = [0u8; 1];
+        loop {
+            match self.socket.recv(&mut ack) {
+                Ok(_) if ack[0] == ACK => return Ok(()),
+                Ok(len) => return Err(CrashTransportError::InvalidMessage(len)),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Write a minidump made of the header and of an exception stream holding
+/// the signal and the registers of `crash_context` to `fd`. It's laid out on
+/// the stack and written with `write()` only, so that it's async-signal-safe,
+/// for when the process can't be dumped by the monitor.
+pub fn write_exception_dump(
+    fd: BorrowedFd<'_>,
+    crash_context: &CrashContext,
+) -> Result<(), CrashTransportError> {
+    let endian = scroll::Endian::Little;
+    let directory_rva = MDRawHeader::size_with(&endian);
+    let stream_rva = directory_rva + MDRawDirectory::size_with(&endian);
+    let context_rva = stream_rva + MDRawExceptionStream::size_with(&endian);
+    let len = context_rva + RawContextCPU::size_with(&endian);
+
+    // SAFETY: all zeroes is a valid `timespec`, and clock_gettime() is
+    // async-signal-safe
+    let now = unsafe {
+        let mut now: libc::timespec = std::mem::zeroed();
+        libc::clock_gettime(libc::CLOCK_REALTIME, &mut now);
+        now
+    };
+
+    let mut dump = [0u8; EXCEPTION_DUMP_CAPACITY];
+    dump.pwrite_with(
+        MDRawHeader {
+            signature: MD_HEADER_SIGNATURE,
+            version: MD_HEADER_VERSION,
+            stream_count: 1,
+            stream_directory_rva: directory_rva as u32,
+            checksum: 0,
+            time_date_stamp: now.tv_sec as u32,
+            flags: 0,
+        },
+        0,
+        endian,
+    )?;
+    dump.pwrite_with(
+        MDRawDirectory {
+            stream_type: MDStreamType::ExceptionStream as u32,
+            location: MDLocationDescriptor {
+                data_size: (context_rva - stream_rva) as u32,
+                rva: stream_rva as u32,
+            },
+        },
+        directory_rva,
+        endian,
+    )?;
+
+    let siginfo = &crash_context.inner.siginfo;
+    dump.pwrite_with(
+        MDRawExceptionStream {
+            thread_id: crash_context.inner.tid as u32,
+            __align: 0,
+            exception_record: MDException {
+                exception_code: siginfo.ssi_signo,
+                exception_flags: siginfo.ssi_code as u32,
+                exception_address: siginfo.ssi_addr,
+                ..Default::default()
+            },
+            thread_context: MDLocationDescriptor {
+                data_size: (len - context_rva) as u32,
+                rva: context_rva as u32,
+            },
+        },
+        stream_rva,
+        endian,
+    )?;
+
+    let mut cpu = RawContextCPU::default();
+    crash_context.fill_cpu_context(&mut cpu);
+    dump.pwrite_with(cpu, context_rva, endian)?;
+
+    let mut written = 0;
+    while written < len {
+        written += match nix::unistd::write(fd, &dump[written..len]) {
+            Ok(count) => count,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        };
+    }
+    Ok(())
+}
+
+/// A dump request received from a crashing process.
+///
+/// `pid` and `tid` are in the monitor's pid namespace, while the ids held by
+/// `crash_context` are in the crashing process' own, which differs when it
+/// runs in a container.
+pub struct CrashRequest {
+    /// The process to dump, as reported by the kernel
+    pub pid: Pid,
+    /// The thread that crashed
+    pub tid: Pid,
+    pub crash_context: CrashContext,
+}
+
+/// The monitor process' end of the socket.
+pub struct CrashServer {
+    socket: UnixDatagram,
+}
+
+impl CrashServer {
+    pub fn new(socket: UnixDatagram) -> Result<Self, CrashTransportError> {
+        // Without this the kernel won't attach the sender's credentials
+        setsockopt(&socket, sockopt::PassCred, &true)?;
+        Ok(Self { socket })
+    }
+
+    /// Blocks until a crashing process sends a dump request.
+    ///
+    /// The request should be acknowledged with [`Self::ack`] once the minidump
+    /// has been written, so that the crashing process can go on and die.
+    pub fn recv(&self) -> Result<CrashRequest, CrashTransportError> {
+        let mut buf = vec![0u8; std::mem::size_of::<crash_context::CrashContext>()];
+        let mut cmsg_buffer = nix::cmsg_space!(UnixCredentials);
+
+        let (len, creds) = loop {
+            let mut iov = [IoSliceMut::new(&mut buf)];
+            let msg = match recvmsg::<()>(
+                self.socket.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg_buffer),
+                MsgFlags::empty(),
+            ) {
+                Ok(msg) => msg,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            let creds = msg.cmsgs()?.find_map(|cmsg| match cmsg {
+                ControlMessageOwned::ScmCredentials(creds) => Some(creds),
+                _ => None,
+            });
+            break (msg.bytes, creds);
+        };
+
+        let creds = creds.ok_or(CrashTransportError::NoCredentials)?;
+        let inner = crash_context::CrashContext::from_bytes(&buf[..len])
+            .ok_or(CrashTransportError::InvalidMessage(len))?;
+
+        // The context is supplied by the client, only trust the kernel. The
+        // kernel translates the pid to our pid namespace but the tid in the
+        // context is relative to the client's, which differ when the client
+        // runs in a container, so it's looked up among the threads of the
+        // process the kernel reported.
+        let pid = creds.pid();
+        let tid = if inner.pid == pid {
+            inner.tid
+        } else {
+            thread_in_own_namespace(pid, inner.tid)?
+        };
+
+        Ok(CrashRequest {
+            pid,
+            tid,
+            crash_context: CrashContext { inner },
+        })
+    }
+
+    /// Lets the crashing process know that its dump has been written.
+    pub fn ack(&self) -> Result<(), CrashTransportError> {
+        self.socket.send(&[ACK])?;
+        Ok(())
+    }
+}
+
+/// Finds the thread of `pid` whose id in the innermost pid namespace it
+/// belongs to is `tid`, returning its id in our pid namespace.
+///
+/// The ids of a thread in each of the namespaces it belongs to are listed on
+/// the `NSpid` line of its status, from ours to its innermost one. Only the
+/// threads of `pid` are searched, so a client can't name a thread of another
+/// process.
+fn thread_in_own_namespace(pid: Pid, tid: Pid) -> Result<Pid, CrashTransportError> {
+    for entry in std::fs::read_dir(format!("/proc/{pid}/task"))? {
+        let entry = entry?;
+        let Ok(status) = std::fs::read_to_string(entry.path().join("status")) else {
+            // The thread exited
+            continue;
+        };
+        if innermost_ns_id(&status) == Some(tid) {
+            if let Some(own_tid) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+                return Ok(own_tid);
+            }
+        }
+    }
+    Err(CrashTransportError::UnknownThread { pid, tid })
+}
+
+/// The id on the last field of the `NSpid` line of a `/proc/<pid>/status` file
+fn innermost_ns_id(status: &str) -> Option<Pid> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))?
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::socket::{sendmsg, ControlMessage};
+    use std::io::{IoSlice, Read, Seek};
+
+    #[test]
+    fn test_request_roundtrip() {
+        let (client, server) = UnixDatagram::pair().unwrap();
+        let server = CrashServer::new(server).unwrap();
+
+        let pid = std::process::id() as Pid;
+        let tid = nix::unistd::gettid().as_raw();
+        // SAFETY: the context is plain old data
+        let mut inner: crash_context::CrashContext = unsafe { std::mem::zeroed() };
+        inner.pid = pid;
+        inner.tid = tid;
+
+        let client = std::thread::spawn(move || {
+            let client = CrashClient::new(client);
+            let result = client.request_dump(&CrashContext { inner });
+            (client, result)
+        });
+
+        let request = server.recv().unwrap();
+        assert_eq!(request.pid, pid);
+        assert_eq!(request.tid, tid);
+        server.ack().unwrap();
+        let (mut client, result) = client.join().unwrap();
+        result.unwrap();
+
+        // A crash while a request is in progress writes the fallback dump
+        // instead, which is tested here rather than in a separate test as
+        // the flag is shared by the whole process
+        let mut fallback = tempfile::tempfile().unwrap();
+        client.set_fallback(fallback.try_clone().unwrap().into());
+        REQUEST_IN_PROGRESS.store(true, Ordering::SeqCst);
+        let result = client.request_dump(&request.crash_context);
+        REQUEST_IN_PROGRESS.store(false, Ordering::SeqCst);
+        assert!(matches!(result, Err(CrashTransportError::NestedCrash)));
+
+        let mut dump = Vec::new();
+        fallback.rewind().unwrap();
+        fallback.read_to_end(&mut dump).unwrap();
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(dump[offset..offset + 4].try_into().unwrap());
+        assert_eq!(read_u32(0), MD_HEADER_SIGNATURE);
+        // A single directory entry, for the exception stream
+        assert_eq!(read_u32(8), 1);
+        let directory = read_u32(12) as usize;
+        assert_eq!(read_u32(directory), MDStreamType::ExceptionStream as u32);
+        let stream = read_u32(directory + 8) as usize;
+        assert_eq!(read_u32(stream), tid as u32);
+        // The thread context is the last thing in the minidump
+        let context_size = read_u32(stream + 160) as usize;
+        let context_rva = read_u32(stream + 164) as usize;
+        assert_eq!(context_rva + context_size, dump.len());
+    }
+
+    #[test]
+    fn test_spoofed_pid() {
+        let (client, server) = UnixDatagram::pair().unwrap();
+        let server = CrashServer::new(server).unwrap();
+
+        // SAFETY: the context is plain old data
+        let mut inner: crash_context::CrashContext = unsafe { std::mem::zeroed() };
+        inner.pid = 1;
+        inner.tid = 1;
+
+        // Nothing is going to ack this, so just send it directly
+        send_unacked(&client, &inner);
+
+        // Our own pid is dumped, and it has no thread 1
+        let pid = std::process::id() as Pid;
+        assert!(matches!(
+            server.recv(),
+            Err(CrashTransportError::UnknownThread { pid: p, tid: 1 }) if p == pid
+        ));
+    }
+
+    #[test]
+    fn test_other_pid_namespace() {
+        let (client, server) = UnixDatagram::pair().unwrap();
+        let server = CrashServer::new(server).unwrap();
+
+        // A process in a container may see itself as pid 1, while its threads
+        // have ids of their own there too, which are the same as ours as
+        // long as the tests don't run in a nested namespace
+        let pid = std::process::id() as Pid;
+        let tid = nix::unistd::gettid().as_raw();
+        // SAFETY: the context is plain old data
+        let mut inner: crash_context::CrashContext = unsafe { std::mem::zeroed() };
+        inner.pid = 1;
+        inner.tid = tid;
+        send_unacked(&client, &inner);
+
+        let request = server.recv().unwrap();
+        assert_eq!(request.pid, pid);
+        assert_eq!(request.tid, tid);
+        assert_eq!(request.crash_context.inner.pid, 1);
+    }
+
+    #[test]
+    fn test_innermost_ns_id() {
+        let status = "Name:\tsh\nTgid:\t4242\nNgid:\t0\nPid:\t4243\nPPid:\t4200\n\
+            NStgid:\t4242\t1\nNSpid:\t4243\t2\nNSpgid:\t4242\t1\n";
+        assert_eq!(innermost_ns_id(status), Some(2));
+        assert_eq!(innermost_ns_id("NSpid:\t4243\n"), Some(4243));
+        // Kernels before 4.1 don't list the ids
+        assert_eq!(innermost_ns_id("Pid:\t4243\n"), None);
+    }
+
+    fn send_unacked(client: &UnixDatagram, inner: &crash_context::CrashContext) {
+        let creds = UnixCredentials::new();
+        sendmsg::<()>(
+            client.as_raw_fd(),
+            &[IoSlice::new(inner.as_bytes())],
+            &[ControlMessage::ScmCredentials(&creds)],
+            MsgFlags::empty(),
+            None,
+        )
+        .unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    capture_limits::{self, CaptureLimits},
     linux::{auxv::AuxvDumpInfo, errors::SectionDsoDebugError, ptrace_dumper::PtraceDumper},
     mem_writer::{write_string_to_location, Buffer, MemoryArrayWriter, MemoryWriter},
     minidump_format::*,
@@ -77,6 +78,7 @@ pub fn write_dso_debug_stream(
     buffer: &mut Buffer,
     blamed_thread: i32,
     auxv: &AuxvDumpInfo,
+    limits: &CaptureLimits,
 ) -> Result<MDRawDirectory> {
     let phnum_max =
         auxv.get_program_header_count()
@@ -84,6 +86,11 @@ pub fn write_dso_debug_stream(
     let phdr = auxv
         .get_program_header_address()
         .ok_or(SectionDsoDebugError::CouldNotFind("AT_PHDR in auxv"))? as usize;
+    capture_limits::check(
+        "program header table size",
+        (SIZEOF_PHDR * phnum_max) as u64,
+        limits.max_segment_size,
+    )?;
 
     let ph = PtraceDumper::copy_from_process(blamed_thread, phdr, SIZEOF_PHDR * phnum_max)?;
     let program_headers;
@@ -131,6 +138,11 @@ pub fn write_dso_debug_stream(
     // DSOs loaded into the program. If this information is indeed available,
     // dump it to a MD_LINUX_DSO_DEBUG stream.
     loop {
+        capture_limits::check(
+            "dynamic section size",
+            (dynamic_length + dyn_size) as u64,
+            limits.max_segment_size,
+        )?;
         let dyn_data = PtraceDumper::copy_from_process(
             blamed_thread,
             dyn_addr as usize + dynamic_length,
@@ -171,6 +183,12 @@ pub fn write_dso_debug_stream(
     let mut dso_vec = Vec::new();
     let mut curr_map = debug_entry.r_map;
     while curr_map != 0 {
+        // A corrupted list may well loop forever
+        capture_limits::check(
+            "loaded module count",
+            dso_vec.len() as u64 + 1,
+            limits.max_modules,
+        )?;
         let link_map_data = PtraceDumper::copy_from_process(
             blamed_thread,
             curr_map,
@@ -197,13 +215,8 @@ pub fn write_dso_debug_stream(
         for (idx, map) in dso_vec.iter().enumerate() {
             let mut filename = String::new();
             if map.l_name > 0 {
-                let filename_data =
-                    PtraceDumper::copy_from_process(blamed_thread, map.l_name, 256)?;
-
-                // C - string is NULL-terminated
-                if let Some(name) = filename_data.splitn(2, |x| *x == b'\0').next() {
-                    filename = String::from_utf8(name.to_vec())?;
-                }
+                let name = read_c_string(blamed_thread, map.l_name, limits.max_string_len)?;
+                filename = String::from_utf8(name)?;
             }
             let location = write_string_to_location(buffer, &filename)?;
             let entry = MDRawLinkMap {
@@ -235,7 +248,26 @@ pub fn write_dso_debug_stream(
     dirent.location.data_size += dynamic_length as u32;
     let dso_debug_data =
         PtraceDumper::copy_from_process(blamed_thread, dyn_addr as usize, dynamic_length)?;
-    MemoryArrayWriter::write_bytes(buffer, &dso_debug_data);
+    MemoryArrayWriter::write_bytes(buffer, &dso_debug_data)?;
 
     Ok(dirent)
 }
+
+/// Read the NUL-terminated string at `address`, truncated to `max_len` bytes.
+/// It is read at most a page at a time, as the string may end right before
+/// an unmapped page.
+fn read_c_string(pid: i32, mut address: usize, max_len: usize) -> Result<Vec<u8>> {
+    const MIN_PAGE_SIZE: usize = 4096;
+    let mut string = Vec::new();
+    while string.len() < max_len {
+        let len = (MIN_PAGE_SIZE - address % MIN_PAGE_SIZE).min(max_len - string.len());
+        let chunk = PtraceDumper::copy_from_process(pid, address, len)?;
+        if let Some(end) = chunk.iter().position(|&b| b == 0) {
+            string.extend_from_slice(&chunk[..end]);
+            break;
+        }
+        string.extend_from_slice(&chunk);
+        address += len;
+    }
+    Ok(string)
+}
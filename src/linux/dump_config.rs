@@ -0,0 +1,242 @@
+//! A description of how to write minidumps which can be stored separately
+//! from the code writing them.
+//!
+//! With the `serde` feature enabled [`DumpConfig`] can be (de)serialized, so
+//! that monitors can read their dump policy from a JSON or TOML file, and
+//! reload it between dumps. All fields are optional and default to the
+//! writer's defaults.
+
+use crate::{
+    capture_limits::CaptureLimits,
+    memory_denylist::MemoryDenylist,
+    minidump_writer::{FlushMode, MinidumpWriter, DEFAULT_STACK_RED_ZONE, STOP_TIMEOUT},
+    stream_selection::StreamSelection,
+};
+use std::time::Duration;
+
+/// The options of a [`MinidumpWriter`] which don't depend on the process being
+/// dumped, see [`MinidumpWriter::apply_config`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct DumpConfig {
+    /// See [`MinidumpWriter::set_minidump_size_limit`]
+    pub minidump_size_limit: Option<u64>,
+    /// See [`MinidumpWriter::set_max_dump_size`]
+    pub max_dump_size: Option<u64>,
+    /// See [`MinidumpWriter::set_max_threads`]
+    pub max_threads: Option<usize>,
+    /// See [`MinidumpWriter::set_max_full_stacks`]
+    pub max_full_stacks: Option<usize>,
+    /// See [`MinidumpWriter::set_max_thread_stacks`]
+    pub max_thread_stacks: Option<usize>,
+    /// See [`MinidumpWriter::set_stack_red_zone`]
+    pub stack_red_zone: usize,
+    /// See [`MinidumpWriter::set_max_modules`]
+    pub max_modules: Option<usize>,
+    /// See [`MinidumpWriter::set_worker_threads`]
+    pub worker_threads: usize,
+    /// See [`MinidumpWriter::set_max_memory_regions`]
+    pub max_memory_regions: Option<usize>,
+    /// See [`MinidumpWriter::set_capture_limits`]
+    pub capture_limits: CaptureLimits,
+    /// See [`MinidumpWriter::set_memory_denylist`]
+    pub memory_denylist: MemoryDenylist,
+    /// See [`MinidumpWriter::set_stream_selection`]
+    pub stream_selection: StreamSelection,
+    /// See [`MinidumpWriter::capture_jit_memory`]
+    pub jit_memory_window: Option<usize>,
+    /// See [`MinidumpWriter::skip_stacks_if_mapping_unreferenced`]
+    pub skip_stacks_if_mapping_unreferenced: bool,
+    /// See [`MinidumpWriter::sanitize_stack`]
+    pub sanitize_stack: bool,
+    /// See [`MinidumpWriter::scrub_pii`]
+    pub scrub_pii: bool,
+    /// See [`MinidumpWriter::full_memory`]
+    pub full_memory: bool,
+    /// See [`MinidumpWriter::minimal`]
+    pub minimal: bool,
+    /// See [`MinidumpWriter::include_sanitizer_shadow`]
+    pub include_sanitizer_shadow: bool,
+    /// See [`MinidumpWriter::page_align_memory`]
+    pub page_align_memory: bool,
+    /// See [`MinidumpWriter::stop_timeout`], in milliseconds
+    pub stop_timeout_ms: u64,
+    /// See [`MinidumpWriter::set_timeout`], in milliseconds. The deadline of
+    /// [`MinidumpWriter::set_deadline`] is left out, as it's a point in time
+    /// specific to a dump rather than a policy.
+    pub timeout_ms: Option<u64>,
+    /// See [`MinidumpWriter::flush_mode`]
+    pub flush_mode: FlushMode,
+    /// See [`MinidumpWriter::use_cgroup_freezer`]
+    pub use_cgroup_freezer: bool,
+}
+
+impl Default for DumpConfig {
+    fn default() -> Self {
+        Self {
+            minidump_size_limit: None,
+            max_dump_size: None,
+            max_threads: None,
+            max_full_stacks: None,
+            max_thread_stacks: None,
+            stack_red_zone: DEFAULT_STACK_RED_ZONE,
+            max_modules: None,
+            worker_threads: 1,
+            max_memory_regions: None,
+            capture_limits: CaptureLimits::default(),
+            memory_denylist: MemoryDenylist::default(),
+            stream_selection: StreamSelection::default(),
+            jit_memory_window: None,
+            skip_stacks_if_mapping_unreferenced: false,
+            sanitize_stack: false,
+            scrub_pii: false,
+            full_memory: false,
+            minimal: false,
+            include_sanitizer_shadow: false,
+            page_align_memory: false,
+            stop_timeout_ms: STOP_TIMEOUT.as_millis() as u64,
+            timeout_ms: None,
+            flush_mode: FlushMode::default(),
+            use_cgroup_freezer: false,
+        }
+    }
+}
+
+impl MinidumpWriter {
+    /// Set all the options described by `config`, replacing their current
+    /// values.
+    pub fn apply_config(&mut self, config: &DumpConfig) -> &mut Self {
+        self.minidump_size_limit = config.minidump_size_limit;
+        self.max_dump_size = config.max_dump_size;
+        self.max_threads = config.max_threads;
+        self.max_full_stacks = config.max_full_stacks;
+        self.max_thread_stacks = config.max_thread_stacks;
+        self.stack_red_zone = config.stack_red_zone;
+        self.max_modules = config.max_modules;
+        self.worker_threads = config.worker_threads.max(1);
+        self.max_memory_regions = config.max_memory_regions;
+        self.capture_limits = config.capture_limits;
+        self.memory_denylist = config.memory_denylist.clone();
+        self.stream_selection = config.stream_selection.clone();
+        self.jit_memory_window = config.jit_memory_window;
+        self.skip_stacks_if_mapping_unreferenced = config.skip_stacks_if_mapping_unreferenced;
+        self.sanitize_stack = config.sanitize_stack;
+        self.scrub_pii = config.scrub_pii;
+        self.full_memory = config.full_memory;
+        self.minimal = config.minimal;
+        self.include_sanitizer_shadow = config.include_sanitizer_shadow;
+        self.page_align_memory = config.page_align_memory;
+        self.stop_timeout = Duration::from_millis(config.stop_timeout_ms);
+        self.timeout = config.timeout_ms.map(Duration::from_millis);
+        self.flush_mode = config.flush_mode;
+        self.use_cgroup_freezer = config.use_cgroup_freezer;
+        self
+    }
+
+    /// The options described by a [`DumpConfig`], as currently set.
+    pub fn dump_config(&self) -> DumpConfig {
+        DumpConfig {
+            minidump_size_limit: self.minidump_size_limit,
+            max_dump_size: self.max_dump_size,
+            max_threads: self.max_threads,
+            max_full_stacks: self.max_full_stacks,
+            max_thread_stacks: self.max_thread_stacks,
+            stack_red_zone: self.stack_red_zone,
+            max_modules: self.max_modules,
+            worker_threads: self.worker_threads,
+            max_memory_regions: self.max_memory_regions,
+            capture_limits: self.capture_limits,
+            memory_denylist: self.memory_denylist.clone(),
+            stream_selection: self.stream_selection.clone(),
+            jit_memory_window: self.jit_memory_window,
+            skip_stacks_if_mapping_unreferenced: self.skip_stacks_if_mapping_unreferenced,
+            sanitize_stack: self.sanitize_stack,
+            scrub_pii: self.scrub_pii,
+            full_memory: self.full_memory,
+            minimal: self.minimal,
+            include_sanitizer_shadow: self.include_sanitizer_shadow,
+            page_align_memory: self.page_align_memory,
+            stop_timeout_ms: self.stop_timeout.as_millis() as u64,
+            timeout_ms: self.timeout.map(|timeout| timeout.as_millis() as u64),
+            flush_mode: self.flush_mode,
+            use_cgroup_freezer: self.use_cgroup_freezer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_config() {
+        let default = MinidumpWriter::new(1, 1);
+        let mut writer = MinidumpWriter::new(1, 1);
+        let mut denylist = MemoryDenylist::default();
+        denylist.deny_range(0x1000..0x2000);
+        writer
+            .set_max_threads(10)
+            .sanitize_stack()
+            .set_memory_denylist(denylist)
+            .apply_config(&DumpConfig::default());
+        assert!(writer.memory_denylist.is_empty());
+        assert_eq!(writer.max_threads, default.max_threads);
+        assert_eq!(writer.sanitize_stack, default.sanitize_stack);
+        assert_eq!(writer.stop_timeout, default.stop_timeout);
+        assert_eq!(writer.flush_mode, default.flush_mode);
+
+        writer.apply_config(&DumpConfig {
+            minidump_size_limit: Some(1 << 20),
+            max_dump_size: Some(1 << 24),
+            stop_timeout_ms: 250,
+            timeout_ms: Some(2000),
+            capture_limits: CaptureLimits {
+                max_threads: 64,
+                ..Default::default()
+            },
+            flush_mode: FlushMode::Sync,
+            ..Default::default()
+        });
+        assert_eq!(writer.capture_limits.max_threads, 64);
+        assert_eq!(writer.minidump_size_limit, Some(1 << 20));
+        assert_eq!(writer.max_dump_size, Some(1 << 24));
+        assert_eq!(writer.stop_timeout, Duration::from_millis(250));
+        assert_eq!(writer.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(writer.flush_mode, FlushMode::Sync);
+    }
+
+    #[test]
+    fn test_dump_config() {
+        assert_eq!(
+            MinidumpWriter::new(1, 1).dump_config(),
+            DumpConfig::default()
+        );
+
+        let mut config = DumpConfig {
+            max_threads: Some(10),
+            sanitize_stack: true,
+            stop_timeout_ms: 250,
+            timeout_ms: Some(2000),
+            capture_limits: CaptureLimits {
+                max_string_len: 256,
+                ..Default::default()
+            },
+            flush_mode: FlushMode::None,
+            ..Default::default()
+        };
+        config
+            .memory_denylist
+            .deny_range(0x1000..0x2000)
+            .deny_mapping("*/libkeystore.so*");
+        config
+            .stream_selection
+            .deny(crate::minidump_format::MDStreamType::LinuxEnviron as u32);
+        let mut writer = MinidumpWriter::new(1, 1);
+        writer.apply_config(&config);
+        assert_eq!(writer.dump_config(), config);
+    }
+}
@@ -3,7 +3,8 @@ cfg_if::cfg_if! {
         target_arch = "x86_64",
         target_arch = "x86",
         target_arch = "mips",
-        target_arch = "mips64"
+        target_arch = "mips64",
+        target_arch = "loongarch64"
     ))]
     {
         pub mod x86_mips;
@@ -50,6 +51,8 @@ pub fn os_information() -> (PlatformId, String) {
                 "aarch64"
             } else if cfg!(target_arch = "arm") {
                 "arm"
+            } else if cfg!(target_arch = "loongarch64") {
+                "loongarch64"
             } else {
                 "<unknown>"
             };
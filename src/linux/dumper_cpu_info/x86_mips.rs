@@ -40,6 +40,9 @@ pub fn write_cpu_information(sys_info: &mut MDRawSystemInfo) -> Result<()> {
         MDCPUArchitecture::PROCESSOR_ARCHITECTURE_MIPS64
     } else if cfg!(target_arch = "x86") {
         MDCPUArchitecture::PROCESSOR_ARCHITECTURE_INTEL
+    } else if cfg!(target_arch = "loongarch64") {
+        // There's no architecture value for LoongArch64 yet
+        MDCPUArchitecture::PROCESSOR_ARCHITECTURE_UNKNOWN
     } else {
         MDCPUArchitecture::PROCESSOR_ARCHITECTURE_AMD64
     } as u16;
@@ -0,0 +1,137 @@
+//! Detecting processes running under a usermode emulator, such as
+//! `qemu-user` registered with `binfmt_misc` to run the binaries of other
+//! architectures.
+//!
+//! The kernel loads the emulator rather than the emulated program, so the
+//! threads ptrace sees are the emulator's, running host code, while most
+//! modules mapped in the process are built for the emulated architecture.
+//! The thread contexts only make sense along with the host modules, so the
+//! emulated ones are left out of the module list and recorded in a
+//! LinuxEmulation stream instead, along with both architectures.
+
+use crate::linux::{module_reader::Machine, ptrace_dumper::PtraceDumper};
+use goblin::elf::header;
+use std::collections::HashMap;
+
+/// The ELF machine of the architecture the writer was built for, the one of
+/// the thread contexts it writes
+#[cfg(target_arch = "x86")]
+pub const HOST_MACHINE: u16 = header::EM_386;
+#[cfg(target_arch = "x86_64")]
+pub const HOST_MACHINE: u16 = header::EM_X86_64;
+#[cfg(target_arch = "arm")]
+pub const HOST_MACHINE: u16 = header::EM_ARM;
+#[cfg(target_arch = "aarch64")]
+pub const HOST_MACHINE: u16 = header::EM_AARCH64;
+#[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+pub const HOST_MACHINE: u16 = header::EM_MIPS;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub const HOST_MACHINE: u16 = header::EM_RISCV;
+#[cfg(target_arch = "loongarch64")]
+pub const HOST_MACHINE: u16 = EM_LOONGARCH;
+
+/// goblin doesn't know about LoongArch yet
+const EM_LOONGARCH: u16 = 258;
+
+/// A process found to run under a usermode emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emulation {
+    /// The ELF machine of the emulated architecture
+    pub emulated_machine: u16,
+    /// The path of the emulator, the executable the entry point of the
+    /// auxiliary vector lies in
+    pub interpreter: Option<String>,
+    /// The start addresses of the mappings of the modules built for another
+    /// architecture than the host's
+    pub emulated_modules: Vec<usize>,
+}
+
+impl Emulation {
+    /// Read the architecture of the modules of the process, which runs under
+    /// emulation if some of them weren't built for the host.
+    pub fn detect(dumper: &PtraceDumper) -> Option<Self> {
+        let machines = dumper
+            .mappings
+            .iter()
+            .enumerate()
+            .filter(|(_, mapping)| mapping.is_interesting() && !mapping.is_kernel_page())
+            .filter_map(|(idx, mapping)| {
+                let Machine(machine) = dumper.from_process_memory_for_index(idx).ok()?;
+                Some((mapping.start_address, machine))
+            });
+        let (emulated_machine, emulated_modules) = foreign_modules(machines, HOST_MACHINE)?;
+
+        let interpreter = dumper
+            .auxv
+            .get_entry_address()
+            .and_then(|entry| dumper.find_mapping(entry as usize))
+            .and_then(|mapping| mapping.name.as_ref())
+            .map(|name| name.to_string_lossy().into_owned());
+        Some(Self {
+            emulated_machine,
+            interpreter,
+            emulated_modules,
+        })
+    }
+
+    /// Whether the mapping starting at `start_address` holds an emulated
+    /// module
+    pub fn is_emulated_module(&self, start_address: usize) -> bool {
+        self.emulated_modules.contains(&start_address)
+    }
+}
+
+/// The name of the architecture of the ELF `machine`, e.g. `X86_64`
+pub fn machine_name(machine: u16) -> &'static str {
+    match machine {
+        EM_LOONGARCH => "LOONGARCH",
+        machine => header::machine_to_str(machine),
+    }
+}
+
+/// The machine most of the `modules` not built for `host_machine` were built
+/// for, and the addresses of all of those, if there are any. The modules are
+/// given as (address, machine) pairs.
+fn foreign_modules(
+    modules: impl Iterator<Item = (usize, u16)>,
+    host_machine: u16,
+) -> Option<(u16, Vec<usize>)> {
+    let mut counts = HashMap::<u16, usize>::new();
+    let mut foreign = Vec::new();
+    for (address, machine) in modules {
+        if machine != host_machine {
+            *counts.entry(machine).or_default() += 1;
+            foreign.push(address);
+        }
+    }
+    let (machine, _) = counts
+        .into_iter()
+        .max_by_key(|&(machine, count)| (count, machine))?;
+    Some((machine, foreign))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_foreign_modules() {
+        let native = [(0x1000, header::EM_X86_64), (0x2000, header::EM_X86_64)];
+        assert_eq!(foreign_modules(native.into_iter(), header::EM_X86_64), None);
+
+        // qemu-aarch64 and the libraries it links to are native, the rest
+        // belongs to the emulated program
+        let emulated = [
+            (0x1000, header::EM_X86_64),
+            (0x2000, header::EM_AARCH64),
+            (0x3000, header::EM_X86_64),
+            (0x4000, header::EM_AARCH64),
+            (0x5000, header::EM_ARM),
+        ];
+        assert_eq!(
+            foreign_modules(emulated.into_iter(), header::EM_X86_64),
+            Some((header::EM_AARCH64, vec![0x2000, 0x4000, 0x5000]))
+        );
+        assert_eq!(machine_name(header::EM_AARCH64), "AARCH64");
+    }
+}
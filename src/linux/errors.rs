@@ -1,5 +1,10 @@
 use crate::{
-    dir_section::FileWriterError, maps_reader::MappingInfo, mem_writer::MemoryWriterError, Pid,
+    capture_limits::LimitExceeded,
+    dir_section::FileWriterError,
+    error_code::{os_error_in_chain, refine_with_os_error, ErrorCode},
+    maps_reader::MappingInfo,
+    mem_writer::MemoryWriterError,
+    Pid,
 };
 use goblin;
 use nix::errno::Errno;
@@ -20,6 +25,51 @@ pub enum InitError {
     PageSizeError(#[from] Errno),
     #[error("Ptrace does not function within the same process")]
     CannotPtraceSameProcess,
+    #[error("Neither a crash context nor a process to dump were provided")]
+    NoProcessToDump,
+    #[error("Not allowed to ptrace the process")]
+    PtraceAccessError(#[from] PtraceAccessError),
+}
+
+impl InitError {
+    /// The stable code of the error, see [`crate::error_code`].
+    pub fn code(&self) -> ErrorCode {
+        let code = match self {
+            Self::ReadAuxvFailed(_) | Self::IOError(..) | Self::AndroidLateInitError(_) => {
+                ErrorCode::ProcessInfo
+            }
+            Self::PrincipalMappingNotReferenced | Self::PageSizeError(_) => ErrorCode::Other,
+            Self::CannotPtraceSameProcess | Self::NoProcessToDump => ErrorCode::InvalidInput,
+            Self::PtraceAccessError(PtraceAccessError::IOError(..)) => ErrorCode::ProcessInfo,
+            Self::PtraceAccessError(_) => ErrorCode::PermissionDenied,
+        };
+        refine_with_os_error(code, self.os_error())
+    }
+
+    /// The OS error number which caused the error, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        os_error_in_chain(self)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PtraceAccessError {
+    #[error("ptrace is disabled system-wide (kernel.yama.ptrace_scope = 3) until the next reboot")]
+    AttachDisabled,
+    #[error("only processes with CAP_SYS_PTRACE may use ptrace (kernel.yama.ptrace_scope = 2)")]
+    CapSysPtraceRequired,
+    #[error("process {0} is not a descendant of the dumper (kernel.yama.ptrace_scope = 1): it must allow the dumper with prctl(PR_SET_PTRACER), or the dumper needs CAP_SYS_PTRACE, or kernel.yama.ptrace_scope must be set to 0")]
+    NotDescendant(Pid),
+    #[error(
+        "process {0} runs with different credentials than the dumper, which needs CAP_SYS_PTRACE"
+    )]
+    DifferentUser(Pid),
+    #[error("process {0} is not dumpable (see PR_SET_DUMPABLE), the dumper needs CAP_SYS_PTRACE")]
+    NotDumpable(Pid),
+    #[error("ptrace access to process {0} was denied, possibly by a security module")]
+    Denied(Pid),
+    #[error("IO error for file {0}")]
+    IOError(String, #[source] std::io::Error),
 }
 
 #[derive(Error, Debug)]
@@ -46,6 +96,8 @@ pub enum MapsReaderError {
     MmapSanityCheckFailed,
     #[error("Symlink does not match ({0} vs. {1})")]
     SymlinkError(std::path::PathBuf, std::path::PathBuf),
+    #[error("Failed to read the mappings from procfs")]
+    ProcError(#[from] procfs_core::ProcError),
 }
 
 #[derive(Debug, Error)]
@@ -74,6 +126,19 @@ pub enum ThreadInfoError {
     PtraceError(#[from] nix::Error),
     #[error("Invalid line in /proc/{0}/status: {1}")]
     InvalidProcStatusFile(Pid, String),
+    #[error("No registers for thread {0}")]
+    MissingRegisters(Pid),
+}
+
+impl ThreadInfoError {
+    /// Return whether the error was caused by the thread having exited.
+    pub fn is_thread_gone(&self) -> bool {
+        match self {
+            Self::PtraceError(e) => *e == nix::Error::ESRCH,
+            Self::IOError(e) => e.kind() == std::io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -112,6 +177,8 @@ pub enum DumperError {
     DetachSkippedThread(Pid),
     #[error("No threads left to suspend out of {0}")]
     SuspendNoThreadsLeft(usize),
+    #[error("Not allowed to ptrace the process")]
+    PtraceAccessError(#[from] PtraceAccessError),
     #[error("No mapping for stack pointer found")]
     NoStackPointerMapping,
     #[error("Failed slice conversion")]
@@ -126,6 +193,116 @@ pub enum DumperError {
     TryFromIntError(#[from] std::num::TryFromIntError),
     #[error("Maps reader error")]
     MapsReaderError(#[from] MapsReaderError),
+    #[error("Failed to read thread info")]
+    ThreadInfoError(#[from] ThreadInfoError),
+}
+
+impl DumperError {
+    /// The stable code of the error, see [`crate::error_code`].
+    pub fn code(&self) -> ErrorCode {
+        let code = match self {
+            Self::WaitPidError(..)
+            | Self::PtraceAttachError(..)
+            | Self::PtraceDetachError(..)
+            | Self::DetachSkippedThread(_)
+            | Self::SuspendNoThreadsLeft(_) => ErrorCode::Attach,
+            Self::PtraceAccessError(_) => ErrorCode::PermissionDenied,
+            Self::CopyFromProcessError(_) => ErrorCode::ReadMemory,
+            Self::NoStackPointerMapping
+            | Self::ELFParsingFailed(_)
+            | Self::ModuleReaderError(_)
+            | Self::NotSafeToOpenMapping(_)
+            | Self::MapsReaderError(_)
+            | Self::ThreadInfoError(_) => ErrorCode::ProcessInfo,
+            Self::SysConfError(_) | Self::TryFromSliceError(_) | Self::TryFromIntError(_) => {
+                ErrorCode::Other
+            }
+        };
+        refine_with_os_error(code, self.os_error())
+    }
+
+    /// The OS error number which caused the error, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        os_error_in_chain(self)
+    }
+}
+
+#[cfg(feature = "verify")]
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("IO error for file {}", .0.display())]
+    IOError(std::path::PathBuf, #[source] std::io::Error),
+    #[error("Failed to read the minidump")]
+    ReadError(#[from] minidump::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum CgroupFreezerError {
+    #[error("IO error for file {}", .0.display())]
+    IOError(std::path::PathBuf, #[source] std::io::Error),
+    #[error("The process is not part of a cgroup v2 hierarchy")]
+    NoCgroupV2,
+    #[error("The cgroup {} does not support freezing", .0.display())]
+    NoFreezer(std::path::PathBuf),
+    #[error("The cgroup {} is shared with {1} other process(es)", .0.display())]
+    SharedCgroup(std::path::PathBuf, usize),
+    #[error("Timeout waiting for the cgroup to be frozen")]
+    Timeout,
+}
+
+#[derive(Debug, Error)]
+pub enum CrashTransportError {
+    #[error("Socket IO failed")]
+    IOError(#[from] std::io::Error),
+    #[error("Socket operation failed")]
+    SocketError(#[from] nix::Error),
+    #[error("Received a malformed message of {0} bytes")]
+    InvalidMessage(usize),
+    #[error("The message did not carry the sender's credentials")]
+    NoCredentials,
+    #[error("Process {pid} has no thread {tid} in its pid namespace")]
+    UnknownThread { pid: Pid, tid: Pid },
+    #[error("A dump was requested while another request was in progress")]
+    NestedCrash,
+    #[error("Failed to lay out the fallback minidump")]
+    FallbackDumpError(#[from] scroll::Error),
+}
+
+#[cfg(feature = "panic-dump")]
+#[derive(Debug, Error)]
+pub enum PanicDumpError {
+    #[error("Failed to create the minidump or the socket to the dumping process")]
+    IOError(#[from] std::io::Error),
+    #[error("Failed to fork the dumping process")]
+    ForkFailed(#[source] nix::Error),
+    #[error("Failed to wait for the dumping process")]
+    WaitFailed(#[source] nix::Error),
+    #[error("The dumping process failed: {0:?}")]
+    DumpFailed(nix::sys::wait::WaitStatus),
+    #[error("Another panic is being dumped")]
+    DumpInProgress,
+}
+
+#[derive(Debug, Error)]
+pub enum CoreDumpError {
+    #[error("Failed to read the core dump")]
+    IOError(#[from] std::io::Error),
+    #[error("Couldn't parse the core dump as an ELF file")]
+    ELFParsingFailed(#[from] goblin::error::Error),
+    #[error("Not an ELF core dump")]
+    NotACoreDump,
+    #[error("The core dump is of a process of another architecture (machine {0})")]
+    UnsupportedMachine(u16),
+    #[error("Invalid {0} note in the core dump")]
+    InvalidNote(&'static str),
+    #[error("The segment at {0:#x} of the core dump overflows the address space")]
+    InvalidSegment(usize),
+    #[error("The core dump has no threads")]
+    NoThreads,
+    #[error("Failed to read the mappings of the core dump")]
+    MapsReaderError(#[from] MapsReaderError),
+    #[error("Failed to write the minidump")]
+    WriterError(#[from] WriterError),
 }
 
 #[derive(Debug, Error)]
@@ -134,6 +311,66 @@ pub enum SectionAppMemoryError {
     CopyFromProcessError(#[from] DumperError),
     #[error("Failed to write to memory")]
     MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the application memory")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionRegisterMemoryError {
+    #[error("Failed to get the mappings of the process")]
+    DumperError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the memory pointed to by the registers")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionReferencedMemoryError {
+    #[error("Failed to get the mappings of the process")]
+    DumperError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the referenced memory")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionAlternateSignalStackError {
+    #[error("Failed to copy memory from process")]
+    CopyFromProcessError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the alternate signal stack")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionFaultAddressMemoryError {
+    #[error("Failed to copy memory from process")]
+    CopyFromProcessError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the memory around the fault address")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionThreadLocalStorageError {
+    #[error("Failed to copy memory from process")]
+    CopyFromProcessError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the thread-local storage")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionJitMemoryError {
+    #[error("Failed to copy memory from process")]
+    CopyFromProcessError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
 }
 
 #[derive(Debug, Error)]
@@ -158,12 +395,20 @@ pub enum SectionMappingsError {
     MemoryWriterError(#[from] MemoryWriterError),
     #[error("Failed to get effective path of mapping ({0:?})")]
     GetEffectivePathError(MappingInfo, #[source] MapsReaderError),
+    #[error("Stopped reading the modules")]
+    Cancelled(#[from] CancelledError),
 }
 
 #[derive(Debug, Error)]
 pub enum SectionMemInfoListError {
     #[error("Failed to write to memory")]
     MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Failed to read the mappings")]
+    MapsReaderError(#[from] MapsReaderError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionModuleImagesError {
     #[error("Failed to read from procfs")]
     ProcfsError(#[from] procfs_core::ProcError),
 }
@@ -174,6 +419,16 @@ pub enum SectionMemListError {
     MemoryWriterError(#[from] MemoryWriterError),
 }
 
+#[derive(Debug, Error)]
+pub enum SectionMemory64ListError {
+    #[error("Failed to get the mappings of the process")]
+    DumperError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped copying the memory")]
+    Cancelled(#[from] CancelledError),
+}
+
 #[derive(Debug, Error)]
 pub enum SectionSystemInfoError {
     #[error("Failed to write to memory")]
@@ -182,6 +437,96 @@ pub enum SectionSystemInfoError {
     CpuInfoError(#[from] CpuInfoError),
 }
 
+#[derive(Debug, Error)]
+pub enum SectionGoBuildInfoError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionCrashpadInfoError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionReplacedModulesError {
+    #[error("Failed to list the mappings")]
+    DumperError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionEmulationError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionHeapSummaryError {
+    #[error("Failed to list the mappings")]
+    DumperError(#[from] DumperError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionJitSymbolsError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionRegisteredBlocksError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error(transparent)]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionExploitabilityError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionThreadWaitStatesError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionSecondaryExceptionsError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionSoftErrorsError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionMiscInfoError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionAssertionInfoError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionTimestampsError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
 #[derive(Debug, Error)]
 pub enum SectionThreadListError {
     #[error("Failed to write to memory")]
@@ -194,10 +539,57 @@ pub enum SectionThreadListError {
     ThreadInfoError(#[from] ThreadInfoError),
     #[error("Failed to write to memory buffer")]
     IOError(#[from] std::io::Error),
+    #[error("Stopped capturing the threads")]
+    Cancelled(#[from] CancelledError),
+}
+
+impl SectionThreadListError {
+    /// Return whether the error was caused by the thread having exited.
+    pub fn is_thread_gone(&self) -> bool {
+        match self {
+            Self::ThreadInfoError(e) => e.is_thread_gone(),
+            Self::CopyFromProcessError(DumperError::CopyFromProcessError(e)) => {
+                e.source == nix::Error::ESRCH
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SectionThreadSignalsError {
+    #[error("Failed to list the threads")]
+    DumperError(#[from] DumperError),
+    #[error("Failed integer conversion")]
+    TryFromIntError(#[from] std::num::TryFromIntError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionBacktracesError {
+    #[error("Failed integer conversion")]
+    TryFromIntError(#[from] std::num::TryFromIntError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+    #[error("Stopped walking the stacks")]
+    Cancelled(#[from] CancelledError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionThreadInfoListError {
+    #[error("Failed to list the threads")]
+    DumperError(#[from] DumperError),
+    #[error("Failed integer conversion")]
+    TryFromIntError(#[from] std::num::TryFromIntError),
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
 }
 
 #[derive(Debug, Error)]
 pub enum SectionThreadNamesError {
+    #[error("Failed to list the threads")]
+    DumperError(#[from] DumperError),
     #[error("Failed integer conversion")]
     TryFromIntError(#[from] std::num::TryFromIntError),
     #[error("Failed to write to memory")]
@@ -206,6 +598,18 @@ pub enum SectionThreadNamesError {
     IOError(#[from] std::io::Error),
 }
 
+#[derive(Debug, Error)]
+pub enum SectionXStateError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
+#[derive(Debug, Error)]
+pub enum SectionThreadStacksError {
+    #[error("Failed to write to memory")]
+    MemoryWriterError(#[from] MemoryWriterError),
+}
+
 #[derive(Debug, Error)]
 pub enum SectionDsoDebugError {
     #[error("Failed to write to memory")]
@@ -216,6 +620,88 @@ pub enum SectionDsoDebugError {
     CopyFromProcessError(#[from] DumperError),
     #[error("Failed to copy memory from process")]
     FromUTF8Error(#[from] std::string::FromUtf8Error),
+    #[error(transparent)]
+    CaptureLimitExceeded(#[from] LimitExceeded),
+}
+
+/// A copy of the memory of the process which failed although the memory was
+/// expected to be there, e.g. because another process sharing it unmapped it,
+/// or because the file backing it was truncated. It is recorded as a
+/// secondary exception rather than failing the whole dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecondaryFault {
+    pub address: usize,
+    pub length: usize,
+    pub error: nix::Error,
+}
+
+/// Problems which didn't prevent the minidump from being written but left it
+/// incomplete, these are recorded in the minidump itself.
+#[derive(Debug, Error)]
+pub enum SoftError {
+    #[error("thread {0} exited before it could be captured")]
+    ThreadExited(Pid),
+    #[error("{0} threads were left out because of the thread limit")]
+    ThreadsOmitted(usize),
+    #[error("{0} thread stacks were left out because of the dump size limit")]
+    StacksOmitted(usize),
+    #[error("{0} memory regions were left out because of the dump size limit")]
+    MemoryRegionsOmitted(usize),
+    #[error("{0} thread stacks were truncated because of the full stack limit")]
+    StacksTruncated(usize),
+    #[error("{0} thread stacks were left out because of the thread stack limit")]
+    ThreadStacksLimitReached(usize),
+    #[error("{0} modules were left out because of the module limit")]
+    ModulesOmitted(usize),
+    #[error("{0} memory regions were left out because of the memory region limit")]
+    MemoryRegionLimitReached(usize),
+    #[error("{1} unreadable bytes were left out of the memory region at {0:#x}")]
+    MemoryRegionTrimmed(usize, usize),
+    #[error("the memory region at {0:#x}, {1} bytes long, was truncated to the region size limit")]
+    MemoryRegionTruncated(usize, usize),
+    #[error("the sanitizer shadow memory at {0:#x}, {1} bytes long, was left out")]
+    SanitizerShadowOmitted(usize, usize),
+    #[error("{0} registered data blocks were left out because of the dump size limit")]
+    RegisteredBlocksOmitted(usize),
+    #[error("{0} JIT functions were left out because of the symbol or dump size limit")]
+    JitSymbolsOmitted(usize),
+    #[error(
+        "the mappings of the process couldn't be read, only the provided ones are listed: {0}"
+    )]
+    MappingsUnavailable(InitError),
+    #[error(transparent)]
+    CaptureLimitExceeded(LimitExceeded),
+    #[error("{0} was left out because writing it failed: {1}")]
+    SectionOmitted(&'static str, Box<WriterError>),
+    #[error("the remaining streams were left out: {0}")]
+    Truncated(CancelledError),
+}
+
+/// Why a dump was stopped before it was complete, see
+/// [`crate::minidump_writer::MinidumpWriter::set_cancellation_token`] and
+/// [`crate::minidump_writer::MinidumpWriter::set_deadline`]
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum CancelledError {
+    #[error("The dump was cancelled")]
+    Cancelled,
+    #[error("The deadline of the dump has passed")]
+    DeadlineExceeded,
+}
+
+/// Why a dump was refused by the
+/// [`crate::rate_limit::RateLimiter`] of the writer
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RateLimitError {
+    #[error("{key} was dumped too recently, retry in {retry_after:?}")]
+    TooSoon {
+        key: String,
+        retry_after: std::time::Duration,
+    },
+    #[error("{key} was dumped too many times, retry in {retry_after:?}")]
+    TooManyDumps {
+        key: String,
+        retry_after: std::time::Duration,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -226,6 +712,16 @@ pub enum WriterError {
     DumperError(#[from] DumperError),
     #[error("Failed when writing section AppMemory")]
     SectionAppMemoryError(#[from] SectionAppMemoryError),
+    #[error("Failed when writing the memory around the fault address")]
+    SectionFaultAddressMemoryError(#[from] SectionFaultAddressMemoryError),
+    #[error("Failed when writing the alternate signal stack")]
+    SectionAlternateSignalStackError(#[from] SectionAlternateSignalStackError),
+    #[error("Failed when writing the thread-local storage")]
+    SectionThreadLocalStorageError(#[from] SectionThreadLocalStorageError),
+    #[error("Failed when writing the referenced memory")]
+    SectionReferencedMemoryError(#[from] SectionReferencedMemoryError),
+    #[error("Failed when writing the memory pointed to by the registers")]
+    SectionRegisterMemoryError(#[from] SectionRegisterMemoryError),
     #[error("Failed when writing section ExceptionStream")]
     SectionExceptionStreamError(#[from] SectionExceptionStreamError),
     #[error("Failed when writing section HandleDataStream")]
@@ -234,14 +730,58 @@ pub enum WriterError {
     SectionMappingsError(#[from] SectionMappingsError),
     #[error("Failed when writing section MemList")]
     SectionMemListError(#[from] SectionMemListError),
+    #[error("Failed when writing section Memory64List")]
+    SectionMemory64ListError(#[from] SectionMemory64ListError),
     #[error("Failed when writing section SystemInfo")]
     SectionSystemInfoError(#[from] SectionSystemInfoError),
     #[error("Failed when writing section MemoryInfoList")]
     SectionMemoryInfoListError(#[from] SectionMemInfoListError),
+    #[error("Failed when writing section JitMemory")]
+    SectionJitMemoryError(#[from] SectionJitMemoryError),
+    #[error("Failed when writing section ModuleImages")]
+    SectionModuleImagesError(#[from] SectionModuleImagesError),
+    #[error("Failed when writing section GoBuildInfo")]
+    SectionGoBuildInfoError(#[from] SectionGoBuildInfoError),
+    #[error("Failed when writing section CrashpadInfo")]
+    SectionCrashpadInfoError(#[from] SectionCrashpadInfoError),
+    #[error("Failed when writing section ReplacedModules")]
+    SectionReplacedModulesError(#[from] SectionReplacedModulesError),
+    #[error("Failed when writing section SoftErrors")]
+    SectionSoftErrorsError(#[from] SectionSoftErrorsError),
+    #[error("Failed when writing section SecondaryExceptions")]
+    SectionSecondaryExceptionsError(#[from] SectionSecondaryExceptionsError),
+    #[error("Failed when writing section ThreadWaitStates")]
+    SectionThreadWaitStatesError(#[from] SectionThreadWaitStatesError),
+    #[error("Failed when writing section Emulation")]
+    SectionEmulationError(#[from] SectionEmulationError),
+    #[error("Failed when writing section HeapSummary")]
+    SectionHeapSummaryError(#[from] SectionHeapSummaryError),
+    #[error("Failed when writing section JitSymbols")]
+    SectionJitSymbolsError(#[from] SectionJitSymbolsError),
+    #[error("Failed when writing section RegisteredBlocks")]
+    SectionRegisteredBlocksError(#[from] SectionRegisteredBlocksError),
+    #[error("Failed when writing section Exploitability")]
+    SectionExploitabilityError(#[from] SectionExploitabilityError),
+    #[error("Failed when writing section MiscInfo")]
+    SectionMiscInfoError(#[from] SectionMiscInfoError),
+    #[error("Failed when writing section AssertionInfo")]
+    SectionAssertionInfoError(#[from] SectionAssertionInfoError),
+    #[error("Failed when writing section Timestamps")]
+    SectionTimestampsError(#[from] SectionTimestampsError),
     #[error("Failed when writing section ThreadList")]
     SectionThreadListError(#[from] SectionThreadListError),
     #[error("Failed when writing section ThreadNameList")]
     SectionThreadNamesError(#[from] SectionThreadNamesError),
+    #[error("Failed when writing section ThreadInfoList")]
+    SectionThreadInfoListError(#[from] SectionThreadInfoListError),
+    #[error("Failed when writing section ThreadSignals")]
+    SectionThreadSignalsError(#[from] SectionThreadSignalsError),
+    #[error("Failed when writing section Backtraces")]
+    SectionBacktracesError(#[from] SectionBacktracesError),
+    #[error("Failed when writing section ThreadStacks")]
+    SectionThreadStacksError(#[from] SectionThreadStacksError),
+    #[error("Failed when writing section XState")]
+    SectionXStateError(#[from] SectionXStateError),
     #[error("Failed when writing section DsoDebug")]
     SectionDsoDebugError(#[from] SectionDsoDebugError),
     #[error("Failed to write to memory")]
@@ -250,6 +790,48 @@ pub enum WriterError {
     FileWriterError(#[from] FileWriterError),
     #[error("Failed to get current timestamp when writing header of minidump")]
     SystemTimeError(#[from] std::time::SystemTimeError),
+    #[error(transparent)]
+    Cancelled(#[from] CancelledError),
+    #[error(transparent)]
+    RateLimited(#[from] RateLimitError),
+}
+
+impl WriterError {
+    /// The stable code of the error, see [`crate::error_code`].
+    pub fn code(&self) -> ErrorCode {
+        let code = match self {
+            Self::InitError(e) => return e.code(),
+            Self::DumperError(e) => return e.code(),
+            Self::MemoryWriterError(_) => ErrorCode::Format,
+            Self::FileWriterError(_) => ErrorCode::Output,
+            Self::SystemTimeError(_) => ErrorCode::Other,
+            Self::Cancelled(_) => ErrorCode::Cancelled,
+            Self::RateLimited(_) => ErrorCode::RateLimited,
+            // The sections fail either because of the dumper or because of the
+            // buffer they are written to
+            _ => std::iter::successors(Some(self as &(dyn std::error::Error + 'static)), |e| {
+                e.source()
+            })
+            .find_map(|e| {
+                if let Some(e) = e.downcast_ref::<DumperError>() {
+                    Some(e.code())
+                } else if e.is::<MemoryWriterError>() {
+                    Some(ErrorCode::Format)
+                } else if e.is::<CancelledError>() {
+                    Some(ErrorCode::Cancelled)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ErrorCode::ProcessInfo),
+        };
+        refine_with_os_error(code, self.os_error())
+    }
+
+    /// The OS error number which caused the error, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        os_error_in_chain(self)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -301,6 +883,18 @@ pub enum ModuleReaderError {
     NoSoNameEntry,
     #[error("no dynamic linking information section")]
     NoDynamicSection,
+    #[error("no Go build id note")]
+    NoGoBuildIdNote,
+    #[error("no .go.buildinfo section")]
+    NoGoBuildInfoSection,
+    #[error("the Go build information predates Go 1.18 and is not supported")]
+    UnsupportedGoBuildInfo,
+    #[error("no Crashpad info note in program headers")]
+    NoCrashpadInfoNote,
+    #[error("no package metadata note in program headers")]
+    NoPackageNote,
+    #[error("the package metadata note does not hold a JSON object of strings")]
+    InvalidPackageMetadata,
     #[error(
         "failed to retrieve soname\n\
     ... from program headers: {program_headers}\n\
@@ -310,4 +904,6 @@ pub enum ModuleReaderError {
         program_headers: Box<Self>,
         section: Box<Self>,
     },
+    #[error(transparent)]
+    CaptureLimitExceeded(#[from] LimitExceeded),
 }
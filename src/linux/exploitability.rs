@@ -0,0 +1,340 @@
+//! A coarse assessment of how likely a crash is to be exploitable, made while
+//! the minidump is written so that security teams can prioritize crashes
+//! without processing them, see [`MinidumpWriter::assess_exploitability`].
+//!
+//! Like Breakpad's processor, this only looks at the state of the crashing
+//! thread: how it faulted, where its instruction and stack pointers point,
+//! and whether its registers hold values which look like they were
+//! controlled by an attacker. It is no substitute for an actual analysis.
+//!
+//! [`MinidumpWriter::assess_exploitability`]: crate::minidump_writer::MinidumpWriter::assess_exploitability
+
+use crate::linux::maps_reader::MappingInfo;
+use std::{fmt, ops::Range};
+
+/// Faults below this address are taken as null pointer dereferences
+const NULL_PAGE_END: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    /// Not a crash, or nothing hints that it's exploitable
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Rating {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What the rating is based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    /// The instruction pointer isn't in any mapping
+    PcUnmapped,
+    /// The instruction pointer is in a mapping which isn't executable, e.g.
+    /// the stack or the heap
+    PcNotExecutable,
+    /// The stack pointer isn't in any thread's stack, it may have been
+    /// pivoted
+    StackPointerNotInStack,
+    /// The faulting access was a write
+    WriteFault,
+    /// The faulting access was a read, or it's unknown whether it was a write
+    ReadFault,
+    /// The faulting address is in the first pages, a null pointer dereference
+    NearNullFault,
+    /// The faulting address is in a stack
+    StackTarget,
+    /// The faulting address is in the heap, or in anonymous writable memory
+    HeapTarget,
+    /// The faulting address or a register holds a repeated byte, like
+    /// `0x41414141`, as found in attacker controlled input
+    ControlledRegister,
+    /// The process executed an illegal instruction
+    IllegalInstruction,
+    /// The process aborted itself, e.g. after detecting heap corruption
+    Abort,
+}
+
+impl Indicator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PcUnmapped => "pc_unmapped",
+            Self::PcNotExecutable => "pc_not_executable",
+            Self::StackPointerNotInStack => "stack_pointer_not_in_stack",
+            Self::WriteFault => "write_fault",
+            Self::ReadFault => "read_fault",
+            Self::NearNullFault => "near_null_fault",
+            Self::StackTarget => "stack_target",
+            Self::HeapTarget => "heap_target",
+            Self::ControlledRegister => "controlled_register",
+            Self::IllegalInstruction => "illegal_instruction",
+            Self::Abort => "abort",
+        }
+    }
+
+    fn rating(self) -> Rating {
+        match self {
+            Self::PcUnmapped
+            | Self::PcNotExecutable
+            | Self::StackPointerNotInStack
+            | Self::WriteFault => Rating::High,
+            Self::ReadFault | Self::ControlledRegister | Self::IllegalInstruction => Rating::Medium,
+            Self::NearNullFault | Self::Abort => Rating::Low,
+            Self::StackTarget | Self::HeapTarget => Rating::None,
+        }
+    }
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The state of the crashing thread the assessment is made from
+pub struct CrashState<'a> {
+    pub signal: u32,
+    pub fault_address: u64,
+    /// Whether the faulting access was a write, if the architecture tells
+    pub write_fault: Option<bool>,
+    pub instruction_pointer: usize,
+    pub stack_pointer: usize,
+    /// The general purpose registers of the crashing thread
+    pub registers: Vec<u64>,
+    pub mappings: &'a [MappingInfo],
+    /// The stack mappings of all the threads
+    pub stacks: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assessment {
+    /// The highest rating of the indicators
+    pub rating: Rating,
+    pub indicators: Vec<Indicator>,
+}
+
+impl Assessment {
+    /// The assessment of a dump which wasn't caused by a crash
+    pub fn not_a_crash() -> Self {
+        Self {
+            rating: Rating::None,
+            indicators: Vec::new(),
+        }
+    }
+
+    pub fn assess(crash: &CrashState<'_>) -> Self {
+        let mut indicators = Vec::new();
+        let find_mapping = |address: usize| {
+            crash
+                .mappings
+                .iter()
+                .find(|mapping| mapping.contains_address(address))
+        };
+
+        match find_mapping(crash.instruction_pointer) {
+            None => indicators.push(Indicator::PcUnmapped),
+            Some(mapping) if !mapping.is_executable() => {
+                indicators.push(Indicator::PcNotExecutable)
+            }
+            Some(_) => {}
+        }
+        if !crash.stacks.is_empty()
+            && !crash
+                .stacks
+                .iter()
+                .any(|stack| stack.contains(&crash.stack_pointer))
+        {
+            indicators.push(Indicator::StackPointerNotInStack);
+        }
+
+        match crash.signal as i32 {
+            libc::SIGSEGV | libc::SIGBUS => {
+                let fault_address = crash.fault_address as usize;
+                if crash.fault_address < NULL_PAGE_END {
+                    indicators.push(Indicator::NearNullFault);
+                } else if crash.write_fault == Some(true) {
+                    indicators.push(Indicator::WriteFault);
+                } else {
+                    indicators.push(Indicator::ReadFault);
+                }
+
+                if crash
+                    .stacks
+                    .iter()
+                    .any(|stack| stack.contains(&fault_address))
+                {
+                    indicators.push(Indicator::StackTarget);
+                } else if find_mapping(fault_address).is_some_and(is_heap) {
+                    indicators.push(Indicator::HeapTarget);
+                }
+                if is_controlled(crash.fault_address) {
+                    indicators.push(Indicator::ControlledRegister);
+                }
+            }
+            libc::SIGILL => indicators.push(Indicator::IllegalInstruction),
+            libc::SIGABRT => indicators.push(Indicator::Abort),
+            _ => {}
+        }
+        if !indicators.contains(&Indicator::ControlledRegister)
+            && crash.registers.iter().any(|&value| is_controlled(value))
+        {
+            indicators.push(Indicator::ControlledRegister);
+        }
+
+        Self {
+            rating: indicators
+                .iter()
+                .map(|indicator| indicator.rating())
+                .max()
+                .unwrap_or(Rating::None),
+            indicators,
+        }
+    }
+}
+
+fn is_heap(mapping: &MappingInfo) -> bool {
+    match &mapping.name {
+        Some(name) => name == "[heap]",
+        None => mapping
+            .permissions
+            .contains(procfs_core::process::MMPermissions::WRITE),
+    }
+}
+
+/// Whether `value` is made of a repeated byte, other than the usual zero and
+/// all ones, either in full or in its low 32 bits.
+fn is_controlled(value: u64) -> bool {
+    let bytes = value.to_le_bytes();
+    let (low, high) = bytes.split_at(4);
+    let byte = low[0];
+    byte != 0
+        && byte != 0xff
+        && low.iter().all(|&b| b == byte)
+        && (high.iter().all(|&b| b == 0) || high.iter().all(|&b| b == byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::maps_reader::SystemMappingInfo;
+    use procfs_core::process::MMPermissions;
+
+    fn mapping(start_address: usize, permissions: MMPermissions, name: &str) -> MappingInfo {
+        MappingInfo {
+            start_address,
+            size: 0x1000,
+            system_mapping_info: SystemMappingInfo {
+                start_address,
+                end_address: start_address + 0x1000,
+            },
+            offset: 0,
+            permissions,
+            name: (!name.is_empty()).then(|| name.into()),
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn test_assess() {
+        let mappings = [
+            mapping(
+                0x10000,
+                MMPermissions::READ | MMPermissions::EXECUTE,
+                "/bin/app",
+            ),
+            mapping(
+                0x20000,
+                MMPermissions::READ | MMPermissions::WRITE,
+                "[heap]",
+            ),
+            mapping(
+                0x30000,
+                MMPermissions::READ | MMPermissions::WRITE,
+                "[stack]",
+            ),
+        ];
+        let crash = CrashState {
+            signal: libc::SIGSEGV as u32,
+            fault_address: 0x8,
+            write_fault: Some(false),
+            instruction_pointer: 0x10100,
+            stack_pointer: 0x30800,
+            registers: vec![0, 1, 0x20010],
+            mappings: &mappings,
+            stacks: vec![0x30000..0x31000, 0x40000..0x41000],
+        };
+        assert_eq!(
+            Assessment::assess(&crash),
+            Assessment {
+                rating: Rating::Low,
+                indicators: vec![Indicator::NearNullFault],
+            }
+        );
+
+        let crash = CrashState {
+            fault_address: 0x20010,
+            write_fault: Some(true),
+            ..crash
+        };
+        assert_eq!(
+            Assessment::assess(&crash),
+            Assessment {
+                rating: Rating::High,
+                indicators: vec![Indicator::WriteFault, Indicator::HeapTarget],
+            }
+        );
+
+        // Jumping to an attacker controlled address
+        let crash = CrashState {
+            fault_address: 0x4141_4141,
+            write_fault: Some(false),
+            instruction_pointer: 0x4141_4141,
+            ..crash
+        };
+        assert_eq!(
+            Assessment::assess(&crash),
+            Assessment {
+                rating: Rating::High,
+                indicators: vec![
+                    Indicator::PcUnmapped,
+                    Indicator::ReadFault,
+                    Indicator::ControlledRegister
+                ],
+            }
+        );
+
+        let crash = CrashState {
+            signal: libc::SIGABRT as u32,
+            fault_address: 0,
+            instruction_pointer: 0x10100,
+            ..crash
+        };
+        assert_eq!(Assessment::assess(&crash).rating, Rating::Low);
+    }
+
+    #[test]
+    fn test_is_controlled() {
+        assert!(is_controlled(0x4141_4141));
+        assert!(is_controlled(0x4242_4242_4242_4242));
+        assert!(!is_controlled(0));
+        assert!(!is_controlled(u64::MAX));
+        assert!(!is_controlled(0x7fff_4141_4141));
+        assert!(!is_controlled(0x1234_5678));
+    }
+}
@@ -0,0 +1,189 @@
+//! Dumping a process which stopped responding, e.g. whose main thread hasn't
+//! processed its events for a while, to build "application not responding"
+//! reports on top of the writer.
+//!
+//! The embedder detects the hang, e.g. with a watchdog thread, and calls
+//! [`MinidumpWriter::dump_hang`]. The process is then dumped like a live
+//! process, without an exception, annotated with the reason of the hang, and
+//! with the state each thread was waiting in in the kernel.
+
+use crate::{
+    builder::DumpResult,
+    linux::{errors::WriterError, minidump_writer::MinidumpWriter, Pid},
+};
+use std::{
+    fmt,
+    io::{Seek, Write},
+    time::Duration,
+};
+
+/// The annotation holding the reason of the hang
+pub const HANG_ANNOTATION: &str = "hang";
+/// The annotation holding how long the process was unresponsive for, in
+/// milliseconds
+pub const HANG_DURATION_ANNOTATION: &str = "hang_duration_ms";
+
+/// A hang reported by the embedder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hang {
+    /// What stopped responding, e.g. "main thread event loop"
+    pub reason: String,
+    /// How long it had been unresponsive for when the hang was reported
+    pub unresponsive_for: Duration,
+}
+
+/// What a thread was doing in the kernel, as reported by
+/// `/proc/<pid>/task/<tid>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadWaitState {
+    pub tid: Pid,
+    /// The scheduling state, e.g. `R` when running, `S` when sleeping and `D`
+    /// when waiting on I/O
+    pub state: char,
+    /// The kernel function the thread is blocked in, if any
+    pub wchan: Option<String>,
+    /// The number of the system call the thread is blocked in, if any.
+    /// Reading it requires the same access as ptrace.
+    pub syscall: Option<i64>,
+}
+
+impl fmt::Display for ThreadWaitState {
+    /// The thread id, state, kernel function and system call number,
+    /// separated by spaces, with `-` for the unknown ones.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.tid,
+            self.state,
+            self.wchan.as_deref().unwrap_or("-")
+        )?;
+        match self.syscall {
+            Some(syscall) => write!(f, " {syscall}"),
+            None => write!(f, " -"),
+        }
+    }
+}
+
+impl MinidumpWriter {
+    /// Write a minidump of the process for a `hang` reported by the embedder
+    /// to `destination`, see [`crate::hang_dump`].
+    ///
+    /// The kernel wait state of each thread is read before the process is
+    /// suspended, as suspending it changes them, and written to the
+    /// `LinuxThreadWaitStates` stream. The reason of the hang and its
+    /// duration are recorded in the [`HANG_ANNOTATION`] and
+    /// [`HANG_DURATION_ANNOTATION`] annotations. The crash context and the
+    /// registers of the blamed thread, if any were set, are left out of the
+    /// minidump, which holds no exception. All of them are restored once the
+    /// process has been dumped.
+    pub fn dump_hang(
+        &mut self,
+        hang: &Hang,
+        destination: &mut (impl Write + Seek),
+    ) -> Result<DumpResult, WriterError> {
+        let crash_context = self.crash_context.take();
+        let blamed_thread_context = self.blamed_thread_context.take();
+        let annotations = self.annotations.clone();
+        self.set_annotation(HANG_ANNOTATION, hang.reason.clone())
+            .set_annotation(
+                HANG_DURATION_ANNOTATION,
+                hang.unresponsive_for.as_millis().to_string(),
+            );
+        self.thread_wait_states = thread_wait_states(self.process_id);
+
+        let result = self.dump(destination);
+
+        self.crash_context = crash_context;
+        self.blamed_thread_context = blamed_thread_context;
+        self.annotations = annotations;
+        self.thread_wait_states.clear();
+        result
+    }
+}
+
+/// The kernel wait state of every thread of process `pid`, leaving out the
+/// threads which exited while they were listed.
+pub fn thread_wait_states(pid: Pid) -> Vec<ThreadWaitState> {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{pid}/task")) else {
+        return Vec::new();
+    };
+    let mut states: Vec<_> = entries
+        .filter_map(|entry| {
+            let tid: Pid = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let task = format!("/proc/{pid}/task/{tid}");
+            let stat = std::fs::read_to_string(format!("{task}/stat")).ok()?;
+            let wchan = std::fs::read_to_string(format!("{task}/wchan")).ok();
+            let syscall = std::fs::read_to_string(format!("{task}/syscall")).ok();
+            Some(ThreadWaitState {
+                tid,
+                state: parse_state(&stat)?,
+                wchan: wchan.and_then(|wchan| parse_wchan(&wchan)),
+                syscall: syscall.and_then(|syscall| parse_syscall(&syscall)),
+            })
+        })
+        .collect();
+    states.sort_unstable_by_key(|state| state.tid);
+    states
+}
+
+/// The state in the contents of `/proc/<pid>/task/<tid>/stat`. It follows the
+/// thread's name, in parentheses, which may contain anything.
+fn parse_state(stat: &str) -> Option<char> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_ascii_whitespace().next()?.chars().next()
+}
+
+/// The kernel function in the contents of `/proc/<pid>/task/<tid>/wchan`,
+/// which is `0` when the thread isn't blocked.
+fn parse_wchan(wchan: &str) -> Option<String> {
+    let wchan = wchan.trim();
+    (!wchan.is_empty() && wchan != "0").then(|| wchan.to_owned())
+}
+
+/// The system call number in the contents of
+/// `/proc/<pid>/task/<tid>/syscall`, which starts with it when the thread is
+/// blocked in a system call, and is `running` otherwise. It is `-1` when the
+/// thread is blocked outside of a system call.
+fn parse_syscall(syscall: &str) -> Option<i64> {
+    syscall
+        .split_ascii_whitespace()
+        .next()?
+        .parse()
+        .ok()
+        .filter(|&syscall| syscall >= 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wait_state() {
+        assert_eq!(
+            parse_state("1234 (a (weird) name) S 1 1234 1234 0 -1"),
+            Some('S')
+        );
+        assert_eq!(
+            parse_wchan("do_epoll_wait"),
+            Some("do_epoll_wait".to_owned())
+        );
+        assert_eq!(parse_wchan("0"), None);
+        assert_eq!(
+            parse_syscall("232 0x4 0x7ffd 0x20 0xffffffff 0x0 0x0 0x7ffd 0x7f12"),
+            Some(232)
+        );
+        assert_eq!(parse_syscall("running"), None);
+        assert_eq!(parse_syscall("-1 0x7ffd 0x7f12"), None);
+    }
+
+    #[test]
+    fn test_own_wait_states() {
+        let states = thread_wait_states(std::process::id() as Pid);
+        // The other threads of the test harness may be anywhere, but the one
+        // listing them is running, in the system call reading its own state
+        let tid = nix::unistd::gettid().as_raw();
+        let current = states.iter().find(|state| state.tid == tid).unwrap();
+        assert_eq!((current.state, current.wchan.as_deref()), ('R', None));
+    }
+}
@@ -0,0 +1,291 @@
+//! The symbols of JIT-compiled code, as the runtimes publish them for
+//! profilers, so that the frames of managed code resolve to method names
+//! rather than to addresses in anonymous mappings.
+//!
+//! Two formats are read, see `tools/perf/Documentation` in the kernel tree:
+//!
+//! - perf maps, `/tmp/perf-<pid>.map`, text files listing a symbol per line
+//!   as `<start> <size> <name>`, both numbers in hexadecimal. They're written
+//!   by V8 with `--perf-basic-prof`, the JVM with perf-map-agent, .NET with
+//!   `DOTNET_PerfMapEnabled` and many others.
+//! - jitdump files, `jit-<pid>.dump`, binary files logging the code the
+//!   runtime loads and moves. The runtime maps them into its own memory so
+//!   that perf finds them, which is how they're found here as well.
+//!
+//! The pid in those names is the one the process sees, which differs from
+//! ours if it lives in another PID namespace.
+
+use crate::linux::ptrace_dumper::PtraceDumper;
+use procfs_core::{process::Status, FromRead};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The largest symbol file read, the rest of a larger one is ignored
+pub const MAX_SYMBOL_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+const JITDUMP_HEADER_SIZE: usize = 40;
+const RECORD_HEADER_SIZE: usize = 16;
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_MOVE: u32 = 1;
+const JIT_CODE_CLOSE: u32 = 3;
+
+/// A function of JIT-compiled code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitSymbol {
+    pub address: usize,
+    pub size: usize,
+    pub name: String,
+}
+
+/// The symbols of a perf map. Later lines replace the earlier ones starting
+/// at the same address, as the code was compiled again, and the malformed
+/// lines are skipped.
+pub fn parse_perf_map(contents: &str) -> Vec<JitSymbol> {
+    let symbols: BTreeMap<_, _> = contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let address = parse_hex(fields.next()?)?;
+            let size = parse_hex(fields.next()?)?;
+            let name = fields.next()?.trim();
+            Some((address, (size, name.to_owned())))
+        })
+        .collect();
+    symbols
+        .into_iter()
+        .map(|(address, (size, name))| JitSymbol {
+            address,
+            size,
+            name,
+        })
+        .collect()
+}
+
+fn parse_hex(field: &str) -> Option<usize> {
+    usize::from_str_radix(field.trim_start_matches("0x"), 16).ok()
+}
+
+/// The symbols of a jitdump file, where the code is currently. A truncated
+/// file yields the symbols of its complete records, and one which isn't a
+/// jitdump file or wasn't written in our byte order yields `None`.
+pub fn parse_jitdump(bytes: &[u8]) -> Option<Vec<JitSymbol>> {
+    if read_u32(bytes, 0)? != JITDUMP_MAGIC {
+        return None;
+    }
+    let header_size = read_u32(bytes, 8)? as usize;
+    let mut offset = header_size.max(JITDUMP_HEADER_SIZE);
+
+    // The code is moved by its index, which identifies it across records
+    let mut symbols = BTreeMap::new();
+    let mut addresses = HashMap::new();
+    while let (Some(id), Some(size)) = (read_u32(bytes, offset), read_u32(bytes, offset + 4)) {
+        let size = size as usize;
+        let Some(record) = bytes.get(offset..offset.saturating_add(size)) else {
+            break;
+        };
+        if size < RECORD_HEADER_SIZE {
+            break;
+        }
+        let body = &record[RECORD_HEADER_SIZE..];
+        match id {
+            // pid, tid, vma, code_addr, code_size, code_index, name
+            JIT_CODE_LOAD => {
+                let (Some(address), Some(code_size), Some(index)) =
+                    (read_u64(body, 16), read_u64(body, 24), read_u64(body, 32))
+                else {
+                    break;
+                };
+                let name = body.get(40..).unwrap_or_default();
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                let name = String::from_utf8_lossy(name).into_owned();
+                if let (Ok(address), Ok(code_size)) = (address.try_into(), code_size.try_into()) {
+                    symbols.insert(address, (code_size, name));
+                    addresses.insert(index, address);
+                }
+            }
+            // pid, tid, vma, old_code_addr, new_code_addr, code_size, code_index
+            JIT_CODE_MOVE => {
+                let (Some(new_address), Some(index)) = (read_u64(body, 24), read_u64(body, 40))
+                else {
+                    break;
+                };
+                if let Ok(new_address) = usize::try_from(new_address) {
+                    if let Some(address) = addresses.insert(index, new_address) {
+                        if let Some(symbol) = symbols.remove(&address) {
+                            symbols.insert(new_address, symbol);
+                        }
+                    }
+                }
+            }
+            JIT_CODE_CLOSE => break,
+            // The debug and unwinding information isn't needed
+            _ => {}
+        }
+        offset += size;
+    }
+
+    Some(
+        symbols
+            .into_iter()
+            .map(|(address, (size, name))| JitSymbol {
+                address,
+                size,
+                name,
+            })
+            .collect(),
+    )
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// The symbol files of the process and their symbols. The perf map comes
+/// first, followed by the jitdump files the process mapped.
+pub(crate) fn read_symbols(dumper: &PtraceDumper) -> Vec<(PathBuf, Vec<JitSymbol>)> {
+    let pid = namespaced_pid(dumper.pid);
+    let mut sources = Vec::new();
+
+    let perf_map = PathBuf::from(format!("/tmp/perf-{pid}.map"));
+    if let Some(contents) = read_file(&dumper.resolve_path(&perf_map)) {
+        let symbols = parse_perf_map(&String::from_utf8_lossy(&contents));
+        sources.push((perf_map, symbols));
+    }
+
+    let jitdump_name = format!("jit-{pid}.dump");
+    let mut jitdumps: Vec<_> = dumper
+        .mappings
+        .iter()
+        .filter(|mapping| mapping.name_is_path())
+        .filter_map(|mapping| mapping.name.as_ref().map(PathBuf::from))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name == jitdump_name.as_str())
+        })
+        .collect();
+    jitdumps.dedup();
+    for jitdump in jitdumps {
+        let symbols =
+            read_file(&dumper.resolve_path(&jitdump)).and_then(|contents| parse_jitdump(&contents));
+        match symbols {
+            Some(symbols) => sources.push((jitdump, symbols)),
+            None => log::warn!("failed to read the jitdump file {}", jitdump.display()),
+        }
+    }
+    sources
+}
+
+/// The id of the process `pid` in its own PID namespace
+fn namespaced_pid(pid: crate::Pid) -> crate::Pid {
+    std::fs::File::open(format!("/proc/{pid}/status"))
+        .ok()
+        .and_then(|file| Status::from_read(file).ok())
+        .and_then(|status| status.nspid)
+        // The last entry is the id in the innermost namespace
+        .and_then(|nspid| nspid.last().copied())
+        .unwrap_or(pid)
+}
+
+fn read_file(path: &Path) -> Option<Vec<u8>> {
+    let mut contents = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(MAX_SYMBOL_FILE_SIZE)
+        .read_to_end(&mut contents)
+        .ok()?;
+    Some(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_perf_map() {
+        let perf_map = "\
+7f3a10001000 80 LazyCompile:~main app.js:1
+0x7f3a10002000 0x40 Builtin: ArgumentsAdaptorTrampoline
+garbage
+7f3a10001000 c0 LazyCompile:*main app.js:1
+";
+        assert_eq!(
+            parse_perf_map(perf_map),
+            vec![
+                JitSymbol {
+                    address: 0x7f3a10001000,
+                    size: 0xc0,
+                    name: "LazyCompile:*main app.js:1".to_owned(),
+                },
+                JitSymbol {
+                    address: 0x7f3a10002000,
+                    size: 0x40,
+                    name: "Builtin: ArgumentsAdaptorTrampoline".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_jitdump() {
+        fn record(id: u32, body: &[u8]) -> Vec<u8> {
+            let mut record = Vec::new();
+            record.extend_from_slice(&id.to_ne_bytes());
+            record.extend_from_slice(&((RECORD_HEADER_SIZE + body.len()) as u32).to_ne_bytes());
+            record.extend_from_slice(&0u64.to_ne_bytes());
+            record.extend_from_slice(body);
+            record
+        }
+        let words = |words: &[u64]| -> Vec<u8> {
+            // The pid and tid of the records are u32s, passed together
+            words.iter().flat_map(|word| word.to_ne_bytes()).collect()
+        };
+
+        let mut jitdump = Vec::new();
+        jitdump.extend_from_slice(&JITDUMP_MAGIC.to_ne_bytes());
+        jitdump.extend_from_slice(&1u32.to_ne_bytes());
+        jitdump.extend_from_slice(&(JITDUMP_HEADER_SIZE as u32).to_ne_bytes());
+        jitdump.resize(JITDUMP_HEADER_SIZE, 0);
+        let mut load = words(&[0, 0x1000, 0x1000, 0x20, 7]);
+        load.extend_from_slice(b"hot_loop\0code");
+        jitdump.extend(record(JIT_CODE_LOAD, &load));
+        let mut load = words(&[0, 0x2000, 0x2000, 0x10, 8]);
+        load.extend_from_slice(b"cold\0");
+        jitdump.extend(record(JIT_CODE_LOAD, &load));
+        // The first function moved
+        jitdump.extend(record(
+            JIT_CODE_MOVE,
+            &words(&[0, 0x1000, 0x1000, 0x3000, 0x20, 7]),
+        ));
+        // A truncated record is ignored
+        jitdump.extend(&record(JIT_CODE_LOAD, &load)[..20]);
+
+        assert_eq!(
+            parse_jitdump(&jitdump),
+            Some(vec![
+                JitSymbol {
+                    address: 0x2000,
+                    size: 0x10,
+                    name: "cold".to_owned(),
+                },
+                JitSymbol {
+                    address: 0x3000,
+                    size: 0x20,
+                    name: "hot_loop".to_owned(),
+                },
+            ])
+        );
+        assert_eq!(parse_jitdump(b"not a jitdump file"), None);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::auxv::AuxvType;
 use crate::errors::MapsReaderError;
+use crate::memory_map::{MemoryMapping, Protection};
+use crate::Pid;
 use byteorder::{NativeEndian, ReadBytesExt};
 use goblin::elf;
 use memmap2::{Mmap, MmapOptions};
@@ -38,6 +40,9 @@ pub struct MappingInfo {
     pub offset: usize,              // offset into the backed file.
     pub permissions: MMPermissions, // read, write and execute permissions.
     pub name: Option<OsString>,
+    // Whether the backing file was deleted (or replaced) after being mapped,
+    // in which case the file at `name` can't be trusted to match the mapping.
+    pub deleted: bool,
     // pub elf_obj: Option<elf::Elf>,
 }
 
@@ -47,6 +52,35 @@ pub struct MappingEntry {
     pub identifier: Vec<u8>,
 }
 
+impl MappingEntry {
+    /// A module loaded at `start_address`, spanning `size` bytes, whose
+    /// identifier, e.g. its build id, is `identifier`, for modules collected
+    /// by the application itself, see
+    /// [`crate::minidump_writer::MinidumpWriter::set_user_mapping_list`].
+    pub fn new(
+        path: impl Into<OsString>,
+        start_address: usize,
+        size: usize,
+        identifier: Vec<u8>,
+    ) -> Self {
+        Self {
+            mapping: MappingInfo {
+                start_address,
+                size,
+                system_mapping_info: SystemMappingInfo {
+                    start_address,
+                    end_address: start_address + size,
+                },
+                offset: 0,
+                permissions: MMPermissions::READ | MMPermissions::EXECUTE | MMPermissions::PRIVATE,
+                name: Some(path.into()),
+                deleted: false,
+            },
+            identifier,
+        }
+    }
+}
+
 // A list of <MappingInfo, GUID>
 pub type MappingList = Vec<MappingEntry>;
 
@@ -56,6 +90,15 @@ pub enum MappingInfoParsingResult {
     Success(MappingInfo),
 }
 
+/// Identifies the file backing a mapping, so that only segments of the same
+/// file get merged into a single module, even if another file with the same
+/// name (e.g. a library that was replaced on disk) is mapped right after it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FileId {
+    dev: (i32, i32),
+    inode: u64,
+}
+
 fn is_mapping_a_path(pathname: Option<&OsStr>) -> bool {
     match pathname {
         Some(x) => x.as_bytes().contains(&b'/'),
@@ -90,25 +133,21 @@ impl MappingInfo {
 
     pub fn aggregate(memory_maps: MemoryMaps, linux_gate_loc: AuxvType) -> Result<Vec<Self>> {
         let mut infos = Vec::<Self>::new();
+        // For each entry in `infos`, the file backing it and the file offset of
+        // the last segment that was merged into it
+        let mut segments = Vec::<(FileId, usize)>::new();
 
         for mm in memory_maps {
+            let file_id = FileId {
+                dev: mm.dev,
+                inode: mm.inode,
+            };
             let start_address: usize = mm.address.0.try_into()?;
             let end_address: usize = mm.address.1.try_into()?;
             let mut offset: usize = mm.offset.try_into()?;
 
-            let mut pathname: Option<OsString> = match mm.pathname {
-                MMapPath::Path(p) => Some(sanitize_path(p.into())),
-                MMapPath::Heap => Some("[heap]".into()),
-                MMapPath::Stack => Some("[stack]".into()),
-                MMapPath::TStack(i) => Some(format!("[stack:{i}]").into()),
-                MMapPath::Vdso => Some("[vdso]".into()),
-                MMapPath::Vvar => Some("[vvar]".into()),
-                MMapPath::Vsyscall => Some("[vsyscall]".into()),
-                MMapPath::Rollup => Some("[rollup]".into()),
-                MMapPath::Vsys(i) => Some(format!("/SYSV{i:x}").into()),
-                MMapPath::Other(n) => Some(format!("[{n}]").into()),
-                MMapPath::Anonymous => None,
-            };
+            let deleted = matches!(&mm.pathname, MMapPath::Path(p) if p.as_os_str().as_bytes().ends_with(DELETED_SUFFIX));
+            let mut pathname = mapping_name(mm.pathname);
 
             let is_path = is_mapping_a_path(pathname.as_deref());
 
@@ -117,18 +156,24 @@ impl MappingInfo {
                 offset = 0;
             }
 
-            if let Some(prev_module) = infos.last_mut() {
+            if let (Some(prev_module), Some(prev_segment)) = (infos.last_mut(), segments.last_mut())
+            {
                 if (start_address == prev_module.end_address())
                     && pathname.is_some()
                     && (pathname == prev_module.name)
+                    && (file_id == prev_segment.0)
+                    && (offset >= prev_segment.1)
                 {
                     // Merge adjacent mappings into one module, assuming they're a single
-                    // library mapped by the dynamic linker.
+                    // library mapped by the dynamic linker. The segments of an ELF file
+                    // are mapped in order, so the offsets never decrease.
+                    prev_segment.1 = offset;
                     prev_module.system_mapping_info.end_address = end_address;
                     prev_module.size = end_address - prev_module.start_address;
                     prev_module.permissions |= mm.perms;
                     continue;
                 } else if (start_address == prev_module.end_address())
+                    && pathname.is_none()
                     && prev_module.is_executable()
                     && prev_module.name_is_path()
                     && ((offset == 0) || (offset == prev_module.end_address()))
@@ -138,6 +183,8 @@ impl MappingInfo {
                     // linker reserved but which a loaded library did not use. These
                     // appear as an anonymous private mapping with no access flags set
                     // and which directly follow an executable mapping.
+                    // Mappings named via PR_SET_VMA (e.g. `[anon:scudo:primary]`) are
+                    // never merged this way, as their label is what identifies them.
                     prev_module.size = end_address - prev_module.start_address;
                     continue;
                 }
@@ -159,12 +206,16 @@ impl MappingInfo {
 
                 if empty_page {
                     let prev_prev_module = previous_modules.first_mut().unwrap();
+                    let prev_prev_idx = segments.len() - 2;
+                    let prev_prev_segment = &mut segments[prev_prev_idx];
 
-                    if pathname == prev_prev_module.name {
+                    if pathname == prev_prev_module.name && file_id == prev_prev_segment.0 {
+                        prev_prev_segment.1 = offset;
                         prev_prev_module.system_mapping_info.end_address = end_address;
                         prev_prev_module.size = end_address - prev_prev_module.start_address;
                         prev_prev_module.permissions |= mm.perms;
                         infos.pop();
+                        segments.pop();
                         continue;
                     }
                 }
@@ -180,7 +231,9 @@ impl MappingInfo {
                 offset,
                 permissions: mm.perms,
                 name: pathname,
+                deleted,
             });
+            segments.push((file_id, offset));
         }
         Ok(infos)
     }
@@ -281,8 +334,13 @@ impl MappingInfo {
         // DT_SONAME as the module name, if one exists, and will fall back to the
         // filesystem name of the module.
 
-        // Just use the filesystem name if no SONAME is present.
-        let Some(file_name) = soname.or_else(|| self.so_name().ok()) else {
+        // Just use the filesystem name if no SONAME is present. The file of a
+        // deleted mapping may have been replaced, so don't read it.
+        let Some(file_name) = soname.or_else(|| {
+            (!self.deleted && !self.is_kernel_page())
+                .then(|| self.so_name().ok())
+                .flatten()
+        }) else {
             //   file_path := /path/to/libname.so
             //   file_name := libname.so
             let file_name = file_path
@@ -333,6 +391,40 @@ impl MappingInfo {
         self.size >= 4096
     }
 
+    /// Whether this is one of the pages the kernel maps into every process to
+    /// provide code, other than the vDSO: the legacy x86_64 `[vsyscall]` page
+    /// and the ARM `[vectors]` and `[sigpage]` pages. These aren't ELF images,
+    /// and the vsyscall page usually can't even be read.
+    pub fn is_kernel_page(&self) -> bool {
+        matches!(
+            self.name.as_deref().and_then(OsStr::to_str),
+            Some("[vsyscall]" | "[vectors]" | "[sigpage]")
+        )
+    }
+
+    /// Whether this mapping is not backed by a file, this includes mappings
+    /// named via `PR_SET_VMA` (e.g. `[anon:v8]`).
+    pub fn is_anonymous(&self) -> bool {
+        match &self.name {
+            None => true,
+            Some(name) => name.as_bytes().starts_with(b"[anon:"),
+        }
+    }
+
+    /// Whether this looks like the shadow memory of a sanitizer (ASan, MSan,
+    /// TSan...). Shadow regions are anonymous, mostly unbacked mappings that
+    /// span terabytes of address space, so they must never be captured.
+    pub fn is_sanitizer_shadow(&self) -> bool {
+        // ASan's low shadow on x86_64 is "only" 256 MiB, but it always starts
+        // at the same address.
+        const ASAN_X86_64_LOW_SHADOW: usize = 0x7fff_8000;
+        const SHADOW_MIN_SIZE: u64 = 1 << 40;
+
+        self.name.is_none()
+            && ((self.size as u64 >= SHADOW_MIN_SIZE)
+                || (cfg!(target_arch = "x86_64") && self.start_address == ASAN_X86_64_LOW_SHADOW))
+    }
+
     pub fn contains_address(&self, address: usize) -> bool {
         self.system_mapping_info.start_address <= address
             && address < self.system_mapping_info.end_address
@@ -435,6 +527,90 @@ impl SoVersion {
     }
 }
 
+/// The name `/proc/<pid>/maps` gives a mapping, its path for those backed by
+/// a file and a pseudo-path like `[stack]` for the others, if it has one
+fn mapping_name(pathname: MMapPath) -> Option<OsString> {
+    match pathname {
+        MMapPath::Path(p) => Some(sanitize_path(p.into())),
+        MMapPath::Heap => Some("[heap]".into()),
+        MMapPath::Stack => Some("[stack]".into()),
+        MMapPath::TStack(i) => Some(format!("[stack:{i}]").into()),
+        MMapPath::Vdso => Some("[vdso]".into()),
+        MMapPath::Vvar => Some("[vvar]".into()),
+        MMapPath::Vsyscall => Some("[vsyscall]".into()),
+        MMapPath::Rollup => Some("[rollup]".into()),
+        MMapPath::Vsys(i) => Some(format!("/SYSV{i:x}").into()),
+        MMapPath::Other(n) => Some(format!("[{n}]").into()),
+        MMapPath::Anonymous => None,
+    }
+}
+
+/// The accesses `permissions` allow
+fn protection(permissions: MMPermissions) -> Protection {
+    Protection {
+        read: permissions.contains(MMPermissions::READ),
+        write: permissions.contains(MMPermissions::WRITE),
+        execute: permissions.contains(MMPermissions::EXECUTE),
+    }
+}
+
+/// Read the mappings of the process `pid` as `/proc/<pid>/maps` lists them,
+/// rather than merged into modules as [`MappingInfo::aggregate`] does.
+pub fn read_memory_map(pid: Pid) -> Result<Vec<MemoryMapping>> {
+    use procfs_core::FromRead;
+
+    let maps = MemoryMaps::from_file(PathBuf::from(format!("/proc/{pid}/maps")))?;
+    Ok(memory_map(maps))
+}
+
+/// The mappings of `memory_maps`, as they are listed
+pub fn memory_map(memory_maps: MemoryMaps) -> Vec<MemoryMapping> {
+    memory_maps
+        .into_iter()
+        .map(|mm| {
+            let is_file = matches!(mm.pathname, MMapPath::Path(_));
+            let name = mapping_name(mm.pathname);
+            let (backing_file, name) = if is_file {
+                (name.map(PathBuf::from), None)
+            } else {
+                (None, name.map(|name| name.to_string_lossy().into_owned()))
+            };
+            MemoryMapping {
+                range: mm.address.0..mm.address.1,
+                protection: protection(mm.perms),
+                shared: !mm.perms.contains(MMPermissions::PRIVATE),
+                offset: mm.offset,
+                backing_file,
+                name,
+            }
+        })
+        .collect()
+}
+
+impl From<&MappingInfo> for MemoryMapping {
+    fn from(mapping: &MappingInfo) -> Self {
+        let (backing_file, name) = if mapping.name_is_path() {
+            (mapping.name.clone().map(PathBuf::from), None)
+        } else {
+            (
+                None,
+                mapping
+                    .name
+                    .as_ref()
+                    .map(|name| name.to_string_lossy().into_owned()),
+            )
+        };
+        Self {
+            range: mapping.start_address as u64..mapping.end_address() as u64,
+            protection: protection(mapping.permissions),
+            shared: !mapping.permissions.contains(MMPermissions::PRIVATE),
+            offset: mapping.offset as u64,
+            backing_file,
+            name,
+        }
+    }
+}
+
 #[cfg(test)]
 impl PartialEq<(u32, u32, u32, u32)> for SoVersion {
     fn eq(&self, o: &(u32, u32, u32, u32)) -> bool {
@@ -529,6 +705,7 @@ ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsysca
                 | MMPermissions::EXECUTE
                 | MMPermissions::PRIVATE,
             name: Some("/usr/bin/cat".into()),
+            deleted: false,
         };
 
         assert_eq!(mappings[0], cat_map);
@@ -543,6 +720,7 @@ ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsysca
             offset: 0,
             permissions: MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
             name: Some("[heap]".into()),
+            deleted: false,
         };
 
         assert_eq!(mappings[1], heap_map);
@@ -557,6 +735,7 @@ ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsysca
             offset: 0,
             permissions: MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
             name: None,
+            deleted: false,
         };
 
         assert_eq!(mappings[2], empty_map);
@@ -576,6 +755,7 @@ ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsysca
             offset: 0,
             permissions: MMPermissions::READ | MMPermissions::EXECUTE | MMPermissions::PRIVATE,
             name: Some("linux-gate.so".into()),
+            deleted: false,
         };
 
         assert_eq!(mappings[21], gate_map);
@@ -639,6 +819,7 @@ ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsysca
                 | MMPermissions::EXECUTE
                 | MMPermissions::PRIVATE,
             name: Some("/lib64/libc-2.32.so".into()),
+            deleted: false,
         };
 
         assert_eq!(mappings[6], gate_map);
@@ -671,11 +852,87 @@ a4840000-a4873000 rw-p 09021000 08:12 393449     /data/app/org.mozilla.firefox-1
                 | MMPermissions::EXECUTE
                 | MMPermissions::PRIVATE,
             name: Some("/data/app/org.mozilla.firefox-1/lib/x86/libxul.so".into()),
+            deleted: false,
         };
 
         assert_eq!(mappings[0], gate_map);
     }
 
+    #[test]
+    fn test_merged_only_same_file() {
+        let mappings = get_mappings_for(
+            "\
+7efd96bc4000-7efd96bea000 r--p 00000000 00:31 4996104                    /lib64/libfoo.so
+7efd96bea000-7efd96d39000 r-xp 00026000 00:31 4996104                    /lib64/libfoo.so
+7efd96d39000-7efd96d5f000 r--p 00000000 00:31 4996105                    /lib64/libfoo.so
+7efd96d5f000-7efd96eae000 r-xp 00026000 00:31 4996105                    /lib64/libfoo.so
+7efd96eae000-7efd96ed4000 r--p 00000000 00:31 4996105                    /lib64/libfoo.so",
+            0,
+        );
+
+        // The second file has the same name as the first one, but a different
+        // inode, and it's mapped twice in a row
+        assert_eq!(
+            mappings
+                .iter()
+                .map(|m| (m.start_address, m.size))
+                .collect::<Vec<_>>(),
+            vec![
+                (0x7efd96bc4000, 0x175000),
+                (0x7efd96d39000, 0x175000),
+                (0x7efd96eae000, 0x26000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anonymous_names_are_preserved() {
+        let mappings = get_mappings_for(
+            "\
+7efd96bc4000-7efd96bea000 r-xp 00000000 00:31 4996104                    /lib64/libc-2.32.so
+7efd96bea000-7efd96bf0000 ---p 00000000 00:00 0                          [anon:scudo:primary_reserve]
+7efd96bf0000-7efd96bf8000 rw-p 00000000 00:00 0                          [anon:scudo:primary]
+7efd96bf8000-7efd96c00000 rw-p 00000000 00:00 0                          [anon:scudo:primary]
+7efd96c00000-7efd96c10000 rw-p 00000000 00:00 0                          [anon:libc_malloc]",
+            0,
+        );
+
+        assert_eq!(
+            mappings.iter().map(|m| m.name.clone()).collect::<Vec<_>>(),
+            vec![
+                Some("/lib64/libc-2.32.so".into()),
+                Some("[anon:scudo:primary_reserve]".into()),
+                Some("[anon:scudo:primary]".into()),
+                Some("[anon:libc_malloc]".into()),
+            ]
+        );
+        // The libc mapping must not swallow the reserved-but-named region
+        assert_eq!(mappings[0].size, 0x26000);
+        // Adjacent mappings with the same label are still merged
+        assert_eq!(mappings[2].start_address, 0x7efd96bf0000);
+        assert_eq!(mappings[2].size, 0x10000);
+    }
+
+    #[test]
+    fn test_sanitizer_shadow() {
+        let mappings = get_mappings_for(
+            "\
+00007fff8000-00008fff7000 rw-p 00000000 00:00 0 
+00008fff7000-02008fff7000 ---p 00000000 00:00 0 
+02008fff7000-10007fff8000 rw-p 00000000 00:00 0 
+559749b0e000-559749b2f000 rw-p 00000000 00:00 0                          [heap]
+7efd968d3000-7efd968f5000 rw-p 00000000 00:00 0 ",
+            0,
+        );
+
+        assert_eq!(mappings.len(), 5);
+        assert!(mappings[0].is_sanitizer_shadow() == cfg!(target_arch = "x86_64"));
+        assert!(mappings[1].is_sanitizer_shadow());
+        assert!(mappings[2].is_sanitizer_shadow());
+        assert!(!mappings[3].is_sanitizer_shadow());
+        assert!(!mappings[4].is_sanitizer_shadow());
+    }
+
     #[test]
     fn test_get_mapping_effective_name() {
         let mappings = get_mappings_for(
@@ -740,4 +997,124 @@ a4840000-a4873000 rw-p 09021000 08:12 393449     /data/app/org.mozilla.firefox-1
         );
         assert_eq!(mappings[2].name, None);
     }
+
+    #[test]
+    fn test_kernel_pages() {
+        let mappings = get_mappings_for(
+            "\
+7ffc6e0f7000-7ffc6e0f9000 r-xp 00000000 00:00 0                          [vdso]
+ffff0000-ffff1000 r-xp 00000000 00:00 0                                  [vectors]
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]",
+            0x7ffc6e0f7000,
+        );
+
+        assert_eq!(mappings.len(), 3);
+        assert!(!mappings[0].is_kernel_page());
+        assert!(mappings[1].is_kernel_page());
+        assert!(mappings[2].is_kernel_page());
+
+        let (path, name, version) = mappings[2]
+            .get_mapping_effective_path_name_and_version(None)
+            .unwrap();
+        assert_eq!(path, PathBuf::from("[vsyscall]"));
+        assert_eq!(name, "[vsyscall]");
+        assert!(version.is_none());
+    }
+
+    #[test]
+    fn test_deleted_mappings() {
+        let mappings = get_mappings_for(
+            "\
+7efd96bc4000-7efd96bea000 r--p 00000000 00:31 4996104                    /lib64/libfoo.so (deleted)
+7efd96bea000-7efd96d39000 r-xp 00026000 00:31 4996104                    /lib64/libfoo.so (deleted)
+7efd96d39000-7efd96d5f000 r--p 00000000 00:31 4996105                    /lib64/libbar.so",
+            0,
+        );
+
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].name, Some("/lib64/libfoo.so".into()));
+        assert_eq!(mappings[0].size, 0x175000);
+        assert!(mappings[0].deleted);
+        assert_eq!(mappings[1].name, Some("/lib64/libbar.so".into()));
+        assert!(!mappings[1].deleted);
+
+        // The soname can't be read from a file which might have been replaced
+        let (path, name, _) = mappings[0]
+            .get_mapping_effective_path_name_and_version(None)
+            .unwrap();
+        assert_eq!(path, PathBuf::from("/lib64/libfoo.so"));
+        assert_eq!(name, "libfoo.so");
+    }
+
+    #[test]
+    fn test_user_mapping_entry() {
+        let mappings = get_mappings_for(
+            "\
+7efd96bc4000-7efd96bea000 r--p 00000000 00:31 4996104                    /lib64/libfoo.so
+7efd96bea000-7efd96d39000 r-xp 00026000 00:31 4996104                    /lib64/libfoo.so
+7efd96d39000-7efd96d5f000 r--p 00000000 00:31 4996105                    /lib64/libbar.so",
+            0,
+        );
+        let user = vec![MappingEntry::new(
+            "/lib64/libfoo.so",
+            0x7efd96bc4000,
+            0x175000,
+            vec![0xaa; 20],
+        )];
+
+        let entry = &user[0].mapping;
+        assert_eq!(entry.system_mapping_info.end_address, 0x7efd96d39000);
+        assert!(entry.is_executable());
+        assert_eq!(entry.name, Some("/lib64/libfoo.so".into()));
+        // The provided entry replaces the mapping read from the process
+        assert!(mappings[0].is_contained_in(&user));
+        assert!(!mappings[1].is_contained_in(&user));
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let maps = MemoryMaps::from_read(
+            "\
+5597483fc000-5597483fe000 r--p 00000000 00:31 4750073                    /usr/bin/cat
+5597483fe000-559748402000 r-xp 00002000 00:31 4750073                    /usr/bin/cat
+5597494cb000-5597494ec000 rw-p 00000000 00:00 0                          [heap]
+7f23e5e00000-7f23e5e10000 rw-s 00000000 00:01 1024                       /memfd:shm (deleted)
+7f23e5e10000-7f23e5e11000 rw-p 00000000 00:00 0 "
+                .as_bytes(),
+        )
+        .unwrap();
+
+        // The mappings of a module aren't merged, unlike with aggregate
+        let mappings = memory_map(maps);
+        assert_eq!(mappings.len(), 5);
+        assert_eq!(mappings[1].range, 0x5597483fe000..0x559748402000);
+        assert_eq!(mappings[1].offset, 0x2000);
+        assert_eq!(
+            mappings[1].protection,
+            Protection {
+                read: true,
+                write: false,
+                execute: true
+            }
+        );
+        assert_eq!(
+            mappings[1].backing_file.as_deref(),
+            Some(std::path::Path::new("/usr/bin/cat"))
+        );
+        assert_eq!(mappings[2].name.as_deref(), Some("[heap]"));
+        assert_eq!(mappings[2].backing_file, None);
+        assert!(mappings[3].shared && !mappings[2].shared);
+        assert_eq!(
+            mappings[3].backing_file.as_deref(),
+            Some(std::path::Path::new("/memfd:shm"))
+        );
+        assert_eq!(
+            (&mappings[4].name, &mappings[4].backing_file),
+            (&None, &None)
+        );
+        assert_eq!(
+            mappings[3].memory_info()._type,
+            minidump_common::format::MemoryType::MEM_MAPPED.bits()
+        );
+    }
 }
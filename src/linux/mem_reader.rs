@@ -1,6 +1,9 @@
 //! Functionality for reading a remote process's memory
 
-use crate::{errors::CopyFromProcessError, ptrace_dumper::PtraceDumper, Pid};
+use crate::{
+    errors::CopyFromProcessError, process_memory::ProcessMemoryReader, ptrace_dumper::PtraceDumper,
+    scratch::ScratchBuffer, Pid,
+};
 
 enum Style {
     /// Uses [`process_vm_readv`](https://linux.die.net/man/2/process_vm_readv)
@@ -197,28 +200,61 @@ impl MemReader {
         })
     }
 
+    /// Read into `dst` with `read`, which reads into the slice it's given
+    /// from the address it's given, until `dst` is full. Interrupted reads are
+    /// retried, and short reads continued from where they stopped, until
+    /// nothing more can be read, e.g. past the end of a mapping. Returns the
+    /// number of bytes read, which is only an error if nothing could be read.
+    fn read_fully(
+        src: usize,
+        dst: &mut [u8],
+        mut read: impl FnMut(usize, &mut [u8]) -> Result<usize, nix::Error>,
+    ) -> Result<usize, (nix::Error, usize)> {
+        let mut offset = 0;
+        while offset < dst.len() {
+            match read(src + offset, &mut dst[offset..]) {
+                Ok(0) => break,
+                Ok(len) => offset += len,
+                Err(nix::Error::EINTR) => continue,
+                Err(_) if offset > 0 => break,
+                Err(err) => return Err((err, offset)),
+            }
+        }
+        Ok(offset)
+    }
+
     #[inline]
     fn vmem(pid: nix::unistd::Pid, src: usize, dst: &mut [u8]) -> Result<usize, nix::Error> {
-        let remote = &[nix::sys::uio::RemoteIoVec {
-            base: src,
-            len: dst.len(),
-        }];
-        nix::sys::uio::process_vm_readv(pid, &mut [std::io::IoSliceMut::new(dst)], remote)
+        Self::read_fully(src, dst, |src, dst| {
+            let remote = &[nix::sys::uio::RemoteIoVec {
+                base: src,
+                len: dst.len(),
+            }];
+            nix::sys::uio::process_vm_readv(pid, &mut [std::io::IoSliceMut::new(dst)], remote)
+        })
+        .map_err(|(err, _)| err)
     }
 
     #[inline]
     fn file(file: &mut std::fs::File, src: usize, dst: &mut [u8]) -> Result<usize, nix::Error> {
         use std::os::unix::fs::FileExt;
 
-        file.read_exact_at(dst, src as u64).map_err(|err| {
-            if let Some(os) = err.raw_os_error() {
-                nix::Error::from_raw(os)
-            } else {
-                nix::Error::E2BIG /* EOF */
-            }
-        })?;
+        let read = Self::read_fully(src, dst, |src, dst| {
+            file.read_at(dst, src as u64).map_err(|err| {
+                if let Some(os) = err.raw_os_error() {
+                    nix::Error::from_raw(os)
+                } else {
+                    nix::Error::EIO
+                }
+            })
+        })
+        .map_err(|(err, _)| err)?;
 
-        Ok(dst.len())
+        if read == 0 {
+            // EOF
+            return Err(nix::Error::E2BIG);
+        }
+        Ok(read)
     }
 
     #[inline]
@@ -227,25 +263,21 @@ impl MemReader {
         src: usize,
         dst: &mut [u8],
     ) -> Result<usize, (nix::Error, usize)> {
-        let mut offset = 0;
-        let mut chunks = dst.chunks_exact_mut(std::mem::size_of::<usize>());
-
-        for chunk in chunks.by_ref() {
-            let word = nix::sys::ptrace::read(pid, (src + offset) as *mut std::ffi::c_void)
-                .map_err(|err| (err, offset))?;
-            chunk.copy_from_slice(&word.to_ne_bytes());
-            offset += std::mem::size_of::<usize>();
-        }
+        // A word is read at a time, of which only what fits in dst is kept
+        Self::read_fully(src, dst, |src, dst| {
+            let word = nix::sys::ptrace::read(pid, src as *mut std::ffi::c_void)?.to_ne_bytes();
+            let len = dst.len().min(word.len());
+            dst[..len].copy_from_slice(&word[..len]);
+            Ok(len)
+        })
+    }
+}
 
-        // I don't think there would ever be a case where we would not read on word boundaries, but just in case...
-        let last = chunks.into_remainder();
-        if !last.is_empty() {
-            let word = nix::sys::ptrace::read(pid, (src + offset) as *mut std::ffi::c_void)
-                .map_err(|err| (err, offset))?;
-            last.copy_from_slice(&word.to_ne_bytes()[..last.len()]);
-        }
+impl ProcessMemoryReader for MemReader {
+    type Error = CopyFromProcessError;
 
-        Ok(dst.len())
+    fn read_into(&mut self, address: usize, dst: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(address, dst)
     }
 }
 
@@ -271,7 +303,95 @@ impl PtraceDumper {
             })
         })?;
 
+        event!(
+            TRACE,
+            pid,
+            address = src,
+            length = length.get(),
+            "reading memory"
+        );
         let mut mem = MemReader::new(pid);
         Ok(mem.read_to_vec(src, length)?)
     }
+
+    /// Like [`Self::copy_from_process`], reading from thread `tid` of the
+    /// dumped process into a buffer reused across reads, for the memory
+    /// which is copied to the minidump right away.
+    pub(crate) fn read_scratch(
+        &self,
+        tid: Pid,
+        src: usize,
+        length: usize,
+    ) -> Result<ScratchBuffer<'_>, crate::errors::DumperError> {
+        if length == 0 {
+            return Err(crate::errors::DumperError::CopyFromProcessError(
+                CopyFromProcessError {
+                    src,
+                    child: tid,
+                    offset: 0,
+                    length,
+                    source: nix::errno::Errno::EINVAL,
+                },
+            ));
+        }
+
+        event!(TRACE, pid = tid, address = src, length, "reading memory");
+        let mut bytes = self.scratch.take();
+        bytes.resize(length, 0);
+        let read = MemReader::new(tid).read(src, &mut bytes)?;
+        bytes.truncate(read);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_read() {
+        // SAFETY: no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+
+        // A mapping twice as large as the file backing it, so that its second
+        // page can't be read
+        // SAFETY: FFI calls with valid arguments, the mapping is only accessed
+        // within its first page, the one backed by the file
+        let (mapping, fd) = unsafe {
+            let fd = libc::memfd_create(c"mem_reader".as_ptr(), 0);
+            assert!(fd >= 0);
+            assert_eq!(libc::ftruncate(fd, page_size as libc::off_t), 0);
+            let mapping = libc::mmap(
+                std::ptr::null_mut(),
+                page_size * 2,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            assert_ne!(mapping, libc::MAP_FAILED);
+            std::ptr::write_bytes(mapping.cast::<u8>(), 0xaa, page_size);
+            (mapping as usize, fd)
+        };
+
+        let pid = std::process::id() as i32;
+        for mut reader in [
+            MemReader::for_virtual_mem(pid),
+            MemReader::for_file(pid).unwrap(),
+        ] {
+            // The read stops short at the page which can't be read
+            let mut dst = vec![0u8; page_size * 2];
+            assert_eq!(reader.read(mapping, &mut dst).unwrap(), page_size);
+            assert!(dst[..page_size].iter().all(|&byte| byte == 0xaa));
+
+            // And fails if nothing could be read
+            assert!(reader.read(mapping + page_size, &mut dst).is_err());
+        }
+
+        // SAFETY: the mapping and file were created above and aren't used anymore
+        unsafe {
+            libc::munmap(mapping as *mut libc::c_void, page_size * 2);
+            libc::close(fd);
+        }
+    }
 }
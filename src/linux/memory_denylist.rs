@@ -0,0 +1,215 @@
+//! Memory which must never be captured in a minidump, such as the regions
+//! holding cryptographic keys or the user's documents, see
+//! [`MinidumpWriter::set_memory_denylist`].
+//!
+//! Memory is denied by address range, or by the name of the mappings holding
+//! it, matched against a pattern where `*` stands for any characters:
+//!
+//! ```
+//! use minidump_writer::memory_denylist::MemoryDenylist;
+//!
+//! let mut denylist = MemoryDenylist::default();
+//! denylist
+//!     .deny_range(0x7f00_0000_0000..0x7f00_0001_0000)
+//!     .deny_mapping("/home/*/Documents/*")
+//!     .deny_mapping("*/libkeystore.so*");
+//! ```
+//!
+//! Wherever memory is captured, from the stacks, around the addresses found
+//! in them, or in full with [`MinidumpWriter::full_memory`], the denied bytes
+//! are zeroed before they are written. The regions captured in full leave out
+//! the denied memory altogether.
+//!
+//! [`MinidumpWriter::set_memory_denylist`]: crate::minidump_writer::MinidumpWriter::set_memory_denylist
+//! [`MinidumpWriter::full_memory`]: crate::minidump_writer::MinidumpWriter::full_memory
+
+use crate::linux::maps_reader::MappingInfo;
+use std::{ffi::OsStr, ops::Range, os::unix::ffi::OsStrExt};
+
+/// The address ranges and mapping names which must never be captured
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct MemoryDenylist {
+    ranges: Vec<Range<usize>>,
+    mapping_patterns: Vec<String>,
+}
+
+impl MemoryDenylist {
+    /// Never capture the memory in `range`
+    pub fn deny_range(&mut self, range: Range<usize>) -> &mut Self {
+        if !range.is_empty() {
+            self.ranges.push(range);
+        }
+        self
+    }
+
+    /// Never capture the mappings whose name, e.g. the path of the file they
+    /// map, matches `pattern`, where `*` stands for any characters
+    pub fn deny_mapping(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.mapping_patterns.push(pattern.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.mapping_patterns.is_empty()
+    }
+
+    /// The denied address ranges of a process with these `mappings`, sorted
+    /// and merged
+    pub(crate) fn resolve(&self, mappings: &[MappingInfo]) -> Vec<Range<usize>> {
+        let mut denied = self.ranges.clone();
+        if !self.mapping_patterns.is_empty() {
+            denied.extend(
+                mappings
+                    .iter()
+                    .filter(|mapping| {
+                        mapping
+                            .name
+                            .as_deref()
+                            .is_some_and(|name| self.denies_mapping(name))
+                    })
+                    .map(|mapping| {
+                        let range = &mapping.system_mapping_info;
+                        range.start_address..range.end_address
+                    }),
+            );
+        }
+
+        denied.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(denied.len());
+        for range in denied {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    fn denies_mapping(&self, name: &OsStr) -> bool {
+        self.mapping_patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern.as_bytes(), name.as_bytes()))
+    }
+}
+
+/// Zero the bytes of `bytes`, read from the process at `start`, which fall
+/// in the `denied` ranges, as returned by [`MemoryDenylist::resolve`]
+pub(crate) fn blank(denied: &[Range<usize>], start: usize, bytes: &mut [u8]) {
+    let end = start.saturating_add(bytes.len());
+    for range in overlapping(denied, start..end) {
+        let from = range.start.max(start) - start;
+        let to = range.end.min(end) - start;
+        bytes[from..to].fill(0);
+    }
+}
+
+/// The parts of `range` outside of the `denied` ranges, as returned by
+/// [`MemoryDenylist::resolve`]
+pub(crate) fn allowed(denied: &[Range<usize>], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut allowed = Vec::new();
+    let mut start = range.start;
+    for denied in overlapping(denied, range.clone()) {
+        if denied.start > start {
+            allowed.push(start..denied.start);
+        }
+        start = start.max(denied.end);
+    }
+    if start < range.end {
+        allowed.push(start..range.end);
+    }
+    allowed
+}
+
+fn overlapping(
+    denied: &[Range<usize>],
+    range: Range<usize>,
+) -> impl Iterator<Item = &Range<usize>> {
+    // The ranges are sorted and don't overlap, so their ends are sorted too
+    let first = denied.partition_point(|denied| denied.end <= range.start);
+    denied[first..]
+        .iter()
+        .take_while(move |denied| denied.start < range.end)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any bytes
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume from if matching after the last `*` fails
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern(
+            b"/usr/lib/libkeys.so",
+            b"/usr/lib/libkeys.so"
+        ));
+        assert!(matches_pattern(b"*/libkeys.so*", b"/usr/lib/libkeys.so.1"));
+        assert!(matches_pattern(
+            b"/home/*/Documents/*",
+            b"/home/alice/Documents/report.odt"
+        ));
+        assert!(matches_pattern(b"*", b""));
+        assert!(!matches_pattern(
+            b"/home/*/Documents/*",
+            b"/home/alice/notes"
+        ));
+        assert!(!matches_pattern(b"libkeys.so", b"/usr/lib/libkeys.so"));
+        assert!(!matches_pattern(b"*.so", b"/usr/lib/libkeys.so.1"));
+    }
+
+    #[test]
+    fn test_blank_and_allowed() {
+        let mut denylist = MemoryDenylist::default();
+        denylist
+            .deny_range(0x1010..0x1020)
+            .deny_range(0x1018..0x1030)
+            .deny_range(0x2000..0x2010);
+        let denied = denylist.resolve(&[]);
+        assert_eq!(denied, [0x1010..0x1030, 0x2000..0x2010]);
+
+        let mut bytes = [0xff; 0x40];
+        blank(&denied, 0x1000, &mut bytes);
+        assert!(bytes[..0x10].iter().all(|&b| b == 0xff));
+        assert!(bytes[0x10..0x30].iter().all(|&b| b == 0));
+        assert!(bytes[0x30..].iter().all(|&b| b == 0xff));
+
+        assert_eq!(
+            allowed(&denied, 0x1000..0x3000),
+            [0x1000..0x1010, 0x1030..0x2000, 0x2010..0x3000]
+        );
+        assert_eq!(allowed(&denied, 0x1010..0x1020), []);
+        let outside = 0x3000..0x4000;
+        assert_eq!(allowed(&denied, outside.clone()), [outside]);
+    }
+}
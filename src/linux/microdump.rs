@@ -0,0 +1,252 @@
+//! Breakpad microdumps, a text rendition of the bare minimum needed to walk
+//! the stack of the crashing thread, meant to be written to a log such as
+//! logcat or syslog when writing a file isn't possible.
+//!
+//! A microdump is a sequence of lines between the
+//! `-----BEGIN BREAKPAD MICRODUMP-----` and `-----END BREAKPAD MICRODUMP-----`
+//! markers, each starting with a letter telling what it holds:
+//!
+//! - `V <product>:<version>`
+//! - `O <os> <arch> <cpu count> <hardware> <os version>`
+//! - `S 0 <stack pointer> <stack start> <stack size>`, followed by
+//!   `S <address> <hex bytes>` lines with the contents of the stack
+//! - `C <hex bytes>`, the CPU context of the crashing thread, as in minidumps
+//! - `M <start> <offset> <size> <debug id> <name>`, one line per module
+
+use crate::{
+    linux::{
+        errors::WriterError, minidump_writer::MinidumpWriter, ptrace_dumper::PtraceDumper,
+        sections::mappings::build_id,
+    },
+    mem_writer::{Buffer, MemoryWriter},
+    minidump_cpu::RawContextCPU,
+};
+use std::{fmt::Write as _, io::Write};
+
+/// The first line of a microdump
+pub const BEGIN_MARKER: &str = "-----BEGIN BREAKPAD MICRODUMP-----";
+/// The last line of a microdump
+pub const END_MARKER: &str = "-----END BREAKPAD MICRODUMP-----";
+
+/// The number of bytes of the stack which are encoded on each line, so that
+/// lines fit in the limits of logging systems
+const STACK_BYTES_PER_LINE: usize = 384;
+/// At most this much of the stack is included, starting from the stack
+/// pointer
+const MAX_STACK_LEN: usize = 32 * 1024;
+
+/// The product written in the `V` line of a microdump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProductInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl Default for ProductInfo {
+    fn default() -> Self {
+        Self {
+            name: "UNKNOWN".to_owned(),
+            version: "0.0.0.0".to_owned(),
+        }
+    }
+}
+
+const ARCH: &str = if cfg!(target_arch = "x86_64") {
+    "x86_64"
+} else if cfg!(target_arch = "x86") {
+    "x86"
+} else if cfg!(target_arch = "aarch64") {
+    "arm64"
+} else if cfg!(target_arch = "arm") {
+    "arm"
+} else if cfg!(target_arch = "mips64") {
+    "mips64"
+} else if cfg!(target_arch = "mips") {
+    "mips"
+} else if cfg!(target_arch = "loongarch64") {
+    "loongarch64"
+} else {
+    "unknown"
+};
+
+/// Write the lines of the microdump of the process `dumper` is attached to.
+pub(crate) fn write(
+    config: &MinidumpWriter,
+    dumper: &mut PtraceDumper,
+    product: &ProductInfo,
+) -> Result<Vec<String>, WriterError> {
+    let mut lines = vec![BEGIN_MARKER.to_owned()];
+    lines.push(format!("V {}:{}", product.name, product.version));
+    lines.push(os_line());
+
+    let blamed_idx = dumper
+        .threads
+        .iter()
+        .position(|thread| thread.tid == config.blamed_thread);
+    let mut cpu = RawContextCPU::default();
+    let stack_pointer = match config.supplied_blamed_thread_context() {
+        Some(supplied) => {
+            cpu = supplied;
+            Some(crate::linux::crash_context::context_pointers(&cpu).1)
+        }
+        None => blamed_idx
+            .and_then(|idx| dumper.captured_thread_info(idx))
+            .map(|info| {
+                info.fill_cpu_context(&mut cpu);
+                info.stack_pointer
+            }),
+    };
+
+    if let Some(stack_pointer) = stack_pointer {
+        write_stack(config, dumper, stack_pointer, &mut lines);
+    }
+
+    if stack_pointer.is_some() {
+        let mut buffer = Buffer::with_capacity(0);
+        MemoryWriter::alloc_with_val(&mut buffer, cpu)?;
+        lines.push(format!("C {}", hex(&buffer)));
+    }
+
+    for map_idx in 0..dumper.mappings.len() {
+        if !dumper.mappings[map_idx].is_interesting() {
+            continue;
+        }
+        let identifier = build_id(dumper, map_idx).0;
+        if identifier.is_empty() || identifier.iter().all(|&x| x == 0) {
+            continue;
+        }
+
+        let mapping = &dumper.mappings[map_idx];
+        let name = mapping
+            .name
+            .as_deref()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        lines.push(format!(
+            "M {:016X} {:016X} {:016X} {} {name}",
+            mapping.start_address,
+            mapping.offset,
+            mapping.size,
+            debug_id(&identifier),
+        ));
+    }
+
+    lines.push(END_MARKER.to_owned());
+    Ok(lines)
+}
+
+fn os_line() -> String {
+    let os = if cfg!(target_os = "android") {
+        'A'
+    } else {
+        'L'
+    };
+    // SAFETY: no preconditions
+    let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.clamp(1, 0xff);
+    let (machine, version) = match nix::sys::utsname::uname() {
+        Ok(info) => (
+            info.machine().to_string_lossy().into_owned(),
+            format!(
+                "{} {}",
+                info.release().to_string_lossy(),
+                info.version().to_string_lossy()
+            ),
+        ),
+        Err(_) => ("<unknown>".to_owned(), "<unknown>".to_owned()),
+    };
+    format!("O {os} {ARCH} {cpus:02X} {machine} {version}")
+}
+
+/// Append the `S` lines holding the stack containing `stack_pointer`, which
+/// are left out if it can't be read.
+fn write_stack(
+    config: &MinidumpWriter,
+    dumper: &PtraceDumper,
+    stack_pointer: usize,
+    lines: &mut Vec<String>,
+) {
+    let Ok((stack_start, stack_len)) = dumper.get_stack_info(stack_pointer) else {
+        return;
+    };
+    let stack_len = stack_len.min(MAX_STACK_LEN);
+    let Ok(mut stack) =
+        PtraceDumper::copy_from_process(config.blamed_thread, stack_start, stack_len)
+    else {
+        return;
+    };
+    if config.sanitize_stack {
+        let stack_pointer_offset = stack_pointer.saturating_sub(stack_start);
+        let _ = dumper.sanitize_stack_copy(&mut stack, stack_pointer, stack_pointer_offset);
+    }
+    config.redact_memory(stack_start, &mut stack);
+
+    lines.push(format!(
+        "S 0 {stack_pointer:X} {stack_start:X} {:X}",
+        stack.len()
+    ));
+    for (idx, chunk) in stack.chunks(STACK_BYTES_PER_LINE).enumerate() {
+        let address = stack_start + idx * STACK_BYTES_PER_LINE;
+        lines.push(format!("S {address:X} {}", hex(chunk)));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a String is infallible
+        let _ = write!(hex, "{byte:02X}");
+    }
+    hex
+}
+
+/// Format a build id as a Breakpad debug id: the first 16 bytes as a GUID,
+/// followed by a zero age.
+fn debug_id(identifier: &[u8]) -> String {
+    let mut guid = [0u8; 16];
+    let len = identifier.len().min(16);
+    guid[..len].copy_from_slice(&identifier[..len]);
+
+    let data1 = u32::from_le_bytes(guid[0..4].try_into().unwrap());
+    let data2 = u16::from_le_bytes(guid[4..6].try_into().unwrap());
+    let data3 = u16::from_le_bytes(guid[6..8].try_into().unwrap());
+    format!("{data1:08X}{data2:04X}{data3:04X}{}0", hex(&guid[8..]))
+}
+
+impl MinidumpWriter {
+    /// Write a Breakpad microdump of the process to `destination`, see
+    /// [`crate::microdump`]. Only the crash context, the blamed thread and
+    /// [`Self::sanitize_stack`] are taken into account.
+    pub fn dump_microdump(
+        &mut self,
+        product: &ProductInfo,
+        destination: &mut impl Write,
+    ) -> Result<(), WriterError> {
+        let mut dumper = self.attach()?;
+        let lines = write(self, &mut dumper, product)?;
+        for line in lines {
+            writeln!(destination, "{line}").map_err(crate::dir_section::FileWriterError::from)?;
+        }
+        destination
+            .flush()
+            .map_err(crate::dir_section::FileWriterError::from)?;
+
+        dumper.resume_threads()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_id() {
+        let identifier: Vec<u8> = (0..20).collect();
+        assert_eq!(debug_id(&identifier), "030201000504070608090A0B0C0D0E0F0");
+        // Short build ids are padded with zeroes
+        assert_eq!(
+            debug_id(&[0xab, 0xcd]),
+            format!("0000CDAB{}", "0".repeat(25))
+        );
+    }
+}
@@ -1,23 +1,52 @@
 pub use crate::linux::auxv::{AuxvType, DirectAuxvDumpInfo};
 use crate::{
     auxv::AuxvDumpInfo,
-    dir_section::{DirSection, DumpBuf},
+    builder::DumpResult,
+    capture_limits::{self, CaptureLimits},
+    crash_reason,
+    dir_section::{DirSection, DiscardSink, DumpBuf, FileWriterError},
+    error_code::ErrorCode,
+    gpu_info::{self, GpuInfo},
+    host_ids::{self, HostIds},
     linux::{
-        app_memory::AppMemoryList,
-        crash_context::CrashContext,
+        app_memory::{AppMemory, AppMemoryList},
+        crash_context::{self, CrashContext},
+        crash_summary::CrashSummary,
         dso_debug,
-        errors::{InitError, WriterError},
+        emulation::Emulation,
+        errors::{
+            CancelledError, DumperError, InitError, SecondaryFault, SectionDsoDebugError,
+            SoftError, WriterError,
+        },
+        exploitability::Assessment,
+        hang_dump::ThreadWaitState,
         maps_reader::{MappingInfo, MappingList},
+        memory_denylist::{self, MemoryDenylist},
+        pii_scrubber,
         ptrace_dumper::PtraceDumper,
+        rate_limit::RateLimiter,
         sections::*,
+        stream_selection::StreamSelection,
     },
+    log_ring_buffer::{self, LogRingBuffer},
     mem_writer::{Buffer, MemoryArrayWriter, MemoryWriter, MemoryWriterError},
+    minidump_cpu::RawContextCPU,
     minidump_format::*,
+    module_filter::{ModuleAction, ModuleFilter},
+    signal_dispositions::SignalDisposition,
+    unloaded_modules::{self, UnloadedModuleList},
     Pid,
 };
 use std::{
+    collections::BTreeMap,
     io::{Seek, Write},
-    time::Duration,
+    ops::Range,
+    os::fd::OwnedFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 pub enum CrashingThreadContext {
@@ -26,24 +55,297 @@ pub enum CrashingThreadContext {
     CrashContextPlusAddress((MDLocationDescriptor, usize)),
 }
 
+/// What to do with the destination once the minidump has been written to it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum FlushMode {
+    /// Leave it alone, the caller will take care of it
+    None,
+    /// Flush any data buffered by the destination, see [`Write::flush`]
+    #[default]
+    Flush,
+    /// Flush, then wait for the data to reach the storage device, see
+    /// [`std::fs::File::sync_data`]. This requires the destination to be a
+    /// file, see [`MinidumpWriter::dump_to_file`], [`MinidumpWriter::dump`]
+    /// only flushes it.
+    Sync,
+}
+
+/// A step of writing a minidump, see [`MinidumpWriter::on_progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpPhase {
+    /// The process was stopped, and its threads and mappings were read
+    Attached,
+    /// A stream was written. Streams which were left out have an empty
+    /// directory entry, whose stream type is 0.
+    StreamWritten { stream_type: u32 },
+    /// The minidump was written and the process was resumed
+    Finished,
+}
+
+/// How far along writing a minidump is, see [`MinidumpWriter::on_progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpProgress {
+    pub phase: DumpPhase,
+    /// The number of threads which were captured
+    pub threads: usize,
+    /// The number of directory entries written so far, out of `stream_count`
+    pub streams_written: u32,
+    pub stream_count: u32,
+    /// The size of the minidump so far
+    pub bytes_written: u64,
+}
+
+pub type ProgressCallback = Box<dyn FnMut(&DumpProgress) + Send + Sync>;
+
+/// Edits the memory captured from the process before it's written, given the
+/// range it was read from, see [`MinidumpWriter::set_memory_redactor`]
+pub type MemoryRedactor = Box<dyn Fn(Range<usize>, &mut [u8]) + Send + Sync>;
+
+/// Computes memory regions to capture while the minidump is written, given
+/// the id of the process being dumped and the crash context, see
+/// [`MinidumpWriter::add_app_memory_callback`]
+pub type AppMemoryCallback =
+    Box<dyn FnMut(Pid, Option<&CrashContext>) -> AppMemoryList + Send + Sync>;
+
+/// What to capture of a thread, see [`MinidumpWriter::set_thread_filter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThreadPolicy {
+    /// Its registers and its whole stack, subject to the other limits
+    #[default]
+    FullStack,
+    /// Only its registers, without any stack memory
+    RegistersOnly,
+    /// Leave it out of the minidump, it isn't even suspended
+    Skip,
+}
+
+/// Decides what to capture of a thread given its id and name, see
+/// [`MinidumpWriter::set_thread_filter`]
+pub type ThreadFilter = Box<dyn FnMut(Pid, Option<&str>) -> ThreadPolicy + Send + Sync>;
+
+/// Computes the checksum of a module given its first 4 KiB, see
+/// [`ModuleChecksum::Custom`]
+pub type ModuleChecksumFn = Box<dyn Fn(&[u8]) -> u32 + Send + Sync>;
+
+/// How the checksums of the modules are computed from their first 4 KiB, see
+/// [`MinidumpWriter::set_module_checksum`]
+pub enum ModuleChecksum {
+    /// The CRC-32 used by zlib and PNG
+    Crc32,
+    Custom(ModuleChecksumFn),
+}
+
+/// Customizes the streams of a minidump as they are written, see
+/// [`MinidumpWriter::add_stream_hook`]. Streams which were left out, whose
+/// directory entry is empty, aren't passed to the hooks.
+pub trait StreamHook: Send + Sync {
+    /// Called once the contents of a stream have been added to `buffer`,
+    /// before they and its directory entry are written to the destination.
+    ///
+    /// The contents can be replaced by appending new ones to `buffer`, e.g.
+    /// with [`MemoryArrayWriter::write_bytes`], and pointing `dirent` to
+    /// them. Returning `false` leaves the stream out, its directory entry is
+    /// emptied. In both cases the previous contents stay in the minidump,
+    /// unreferenced, as other streams may point into them.
+    fn before_write(
+        &mut self,
+        dirent: &mut MDRawDirectory,
+        buffer: &mut DumpBuf,
+        dumper: &PtraceDumper,
+    ) -> bool {
+        let _ = (dirent, buffer, dumper);
+        true
+    }
+
+    /// Called once a stream has been written to the destination.
+    fn after_write(&mut self, dirent: &MDRawDirectory, buffer: &DumpBuf, dumper: &PtraceDumper) {
+        let _ = (dirent, buffer, dumper);
+    }
+}
+
+/// Produces the contents of a user stream, given the id of the process being
+/// dumped, see [`MinidumpWriter::add_deferred_user_stream`]
+pub type UserStreamCallback = Box<dyn FnMut(Pid) -> Vec<u8> + Send + Sync>;
+
+/// The contents of a stream added by the application
+pub enum UserStreamData {
+    Bytes(Vec<u8>),
+    Deferred(UserStreamCallback),
+}
+
+/// A stream added by the application, see [`MinidumpWriter::add_user_stream`]
+pub struct UserStream {
+    pub stream_type: u32,
+    pub data: UserStreamData,
+}
+
+/// A failed assertion, e.g. from a custom `assert!` macro, which is written to
+/// the assertion information stream so that minidump viewers show it, see
+/// [`MinidumpWriter::set_assertion`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssertionInfo {
+    /// The condition which failed, or the message of the assertion
+    pub expression: String,
+    /// The function the assertion is in, if known
+    pub function: String,
+    pub file: String,
+    pub line: u32,
+}
+
+/// A flag stopping a dump in progress once set, see
+/// [`MinidumpWriter::set_cancellation_token`]. Clones share the same flag, so
+/// that a supervisor can keep one to cancel the dump from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the dumps using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 /// The default timeout after a `SIGSTOP` after which minidump writing proceeds
 /// regardless of the process state
 pub const STOP_TIMEOUT: Duration = Duration::from_millis(100);
 
+/// The bytes below the stack pointer of each thread captured by default, the
+/// size of the red zone of the x86-64 ABI, see
+/// [`MinidumpWriter::set_stack_red_zone`]
+pub const DEFAULT_STACK_RED_ZONE: usize = 128;
+
+/// The CrashpadInfo annotation marking the minidumps which were finished early
+/// because of their deadline, see [`MinidumpWriter::set_deadline`]. Its value
+/// is the reason why.
+pub const TRUNCATED_ANNOTATION: &str = "minidump.truncated";
+
+/// The number of streams in the directory of the minidumps we write, not
+/// counting the user streams
+const NUM_STREAMS: u32 = 52;
+
+/// The room kept for the streams which are never dropped to respect
+/// [`MinidumpWriter::set_max_dump_size`]
+const DUMP_SIZE_RESERVE: u64 = 128 * 1024;
+
+/// The most space allocated upfront for a minidump, see
+/// [`MinidumpWriter::estimate_dump_size`]
+const MAX_SIZE_ESTIMATE: usize = 32 * 1024 * 1024;
+
 pub struct MinidumpWriter {
     pub process_id: Pid,
     pub blamed_thread: Pid,
     pub minidump_size_limit: Option<u64>,
+    pub max_dump_size: Option<u64>,
+    pub max_threads: Option<usize>,
+    pub max_full_stacks: Option<usize>,
+    pub max_thread_stacks: Option<usize>,
+    pub max_crashing_stack_bytes: Option<usize>,
+    pub max_stack_bytes: Option<usize>,
+    pub stack_red_zone: usize,
+    pub thread_filter: Option<ThreadFilter>,
+    pub registers_only_threads: Vec<Pid>,
+    pub handler_threads: Vec<Pid>,
+    pub handler_thread_policy: ThreadPolicy,
+    pub max_modules: Option<usize>,
+    pub module_filter: Option<ModuleFilter>,
+    pub module_checksum: Option<ModuleChecksum>,
+    pub worker_threads: usize,
+    pub unloaded_modules: UnloadedModuleList,
+    pub signal_dispositions: Vec<SignalDisposition>,
+    pub gpu_info: Vec<GpuInfo>,
+    pub log_ring_buffers: Vec<LogRingBuffer>,
+    pub crash_reason: Option<String>,
+    pub host_ids: HostIds,
+    pub max_memory_regions: Option<usize>,
+    pub capture_limits: CaptureLimits,
     pub skip_stacks_if_mapping_unreferenced: bool,
     pub principal_mapping_address: Option<usize>,
     pub user_mapping_list: MappingList,
+    pub jit_memory_window: Option<usize>,
+    pub thread_ip_memory_window: Option<usize>,
+    pub jit_mapping_list: MappingList,
+    pub backtrace_depth: Option<usize>,
+    pub fault_address_memory_window: Option<usize>,
+    pub referenced_memory_budget: Option<usize>,
+    pub register_memory_budget: Option<usize>,
+    pub capture_thread_local_storage: bool,
+    pub capture_jit_symbols: bool,
     pub app_memory: AppMemoryList,
+    pub app_memory64: AppMemoryList,
+    /// The application memory regions 32-bit RVAs couldn't reach, which are
+    /// written to the Memory64List instead
+    pub spilled_app_memory: AppMemoryList,
+    pub app_memory_callbacks: Vec<AppMemoryCallback>,
     pub memory_blocks: Vec<MDMemoryDescriptor>,
+    pub listed_modules: Vec<u64>,
+    pub unmapped_stack_regions: Vec<std::ops::Range<usize>>,
+    pub thread_stacks: MDRawThreadStackList,
+    pub shadow_stacks: MDRawThreadStackList,
+    pub xstate_header: Option<MDRawXStateHeader>,
+    pub thread_xstates: Vec<MDRawThreadXState>,
+    pub soft_errors: Vec<SoftError>,
+    /// The optional parts of the minidump which were left out because
+    /// writing them failed, see [`SoftError::SectionOmitted`]
+    pub omitted_sections: Vec<&'static str>,
+    pub secondary_faults: Vec<SecondaryFault>,
+    pub phase_durations: Vec<(&'static str, Duration)>,
+    pub annotations: BTreeMap<String, String>,
+    pub assertion: Option<AssertionInfo>,
+    pub user_streams: Vec<UserStream>,
+    pub crash_summary: Option<CrashSummary>,
+    pub assess_exploitability: bool,
+    pub exploitability: Option<Assessment>,
+    /// The usermode emulator the process runs under, if any, see
+    /// [`crate::emulation`]
+    pub emulation: Option<Emulation>,
+    pub thread_wait_states: Vec<ThreadWaitState>,
     pub principal_mapping: Option<MappingInfo>,
     pub sanitize_stack: bool,
+    pub memory_redactor: Option<MemoryRedactor>,
+    pub scrub_pii: bool,
+    pub memory_denylist: MemoryDenylist,
+    pub stream_selection: StreamSelection,
+    /// The address ranges of [`Self::memory_denylist`] in the process being
+    /// dumped, sorted and merged
+    pub denied_ranges: Vec<Range<usize>>,
+    pub full_memory: bool,
+    pub module_images_budget: Option<usize>,
+    /// The executable segments of the modules to capture, see
+    /// [`Self::capture_module_images`]
+    pub module_image_regions: Vec<Range<usize>>,
+    pub minimal: bool,
+    pub include_sanitizer_shadow: bool,
+    pub page_align_memory: bool,
+    pub deterministic_clock: Option<Box<dyn Fn() -> SystemTime + Send + Sync>>,
+    pub progress_callback: Option<ProgressCallback>,
+    pub stream_hooks: Vec<Box<dyn StreamHook>>,
+    pub cancellation_token: Option<CancellationToken>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub deadline: Option<Instant>,
+    pub timeout: Option<Duration>,
+    pub dump_start: Option<Instant>,
     pub crash_context: Option<CrashContext>,
+    pub blamed_thread_context: Option<RawContextCPU>,
     pub crashing_thread_context: CrashingThreadContext,
+    /// The general purpose registers of the crashing thread, as written to
+    /// the thread list
+    pub crashing_thread_registers: Vec<u64>,
     pub stop_timeout: Duration,
+    pub flush_mode: FlushMode,
+    pub use_cgroup_freezer: bool,
     pub direct_auxv_dump_info: Option<DirectAuxvDumpInfo>,
 }
 
@@ -59,117 +361,1355 @@ pub struct MinidumpWriter {
 type Result<T> = std::result::Result<T, WriterError>;
 
 impl MinidumpWriter {
+    /// Returns a builder offering the same API on every platform, see
+    /// [`crate::builder`].
+    pub fn builder() -> crate::builder::MinidumpWriterBuilder {
+        crate::builder::MinidumpWriterBuilder::new()
+    }
+
     pub fn new(process: Pid, blamed_thread: Pid) -> Self {
         Self {
             process_id: process,
             blamed_thread,
             minidump_size_limit: None,
+            max_dump_size: None,
+            max_threads: None,
+            max_full_stacks: None,
+            max_thread_stacks: None,
+            max_crashing_stack_bytes: None,
+            max_stack_bytes: None,
+            stack_red_zone: DEFAULT_STACK_RED_ZONE,
+            thread_filter: None,
+            registers_only_threads: Vec::new(),
+            handler_threads: Vec::new(),
+            handler_thread_policy: ThreadPolicy::Skip,
+            max_modules: None,
+            module_filter: None,
+            module_checksum: None,
+            worker_threads: 1,
+            unloaded_modules: UnloadedModuleList::new(),
+            signal_dispositions: Vec::new(),
+            gpu_info: Vec::new(),
+            log_ring_buffers: Vec::new(),
+            crash_reason: None,
+            host_ids: HostIds::default(),
+            max_memory_regions: None,
+            capture_limits: CaptureLimits::default(),
             skip_stacks_if_mapping_unreferenced: false,
             principal_mapping_address: None,
             user_mapping_list: MappingList::new(),
+            jit_memory_window: None,
+            thread_ip_memory_window: None,
+            jit_mapping_list: MappingList::new(),
+            backtrace_depth: None,
+            fault_address_memory_window: None,
+            referenced_memory_budget: None,
+            register_memory_budget: None,
+            capture_thread_local_storage: false,
+            capture_jit_symbols: false,
             app_memory: AppMemoryList::new(),
+            app_memory64: AppMemoryList::new(),
+            spilled_app_memory: AppMemoryList::new(),
+            app_memory_callbacks: Vec::new(),
             memory_blocks: Vec::new(),
+            listed_modules: Vec::new(),
+            unmapped_stack_regions: Vec::new(),
+            thread_stacks: Vec::new(),
+            shadow_stacks: Vec::new(),
+            xstate_header: None,
+            thread_xstates: Vec::new(),
+            soft_errors: Vec::new(),
+            omitted_sections: Vec::new(),
+            secondary_faults: Vec::new(),
+            phase_durations: Vec::new(),
+            annotations: BTreeMap::new(),
+            assertion: None,
+            user_streams: Vec::new(),
+            crash_summary: None,
+            assess_exploitability: false,
+            exploitability: None,
+            emulation: None,
+            thread_wait_states: Vec::new(),
             principal_mapping: None,
             sanitize_stack: false,
+            memory_redactor: None,
+            scrub_pii: false,
+            memory_denylist: MemoryDenylist::default(),
+            stream_selection: StreamSelection::default(),
+            denied_ranges: Vec::new(),
+            full_memory: false,
+            module_images_budget: None,
+            module_image_regions: Vec::new(),
+            minimal: false,
+            include_sanitizer_shadow: false,
+            page_align_memory: false,
+            deterministic_clock: None,
+            progress_callback: None,
+            stream_hooks: Vec::new(),
+            cancellation_token: None,
+            rate_limiter: None,
+            deadline: None,
+            timeout: None,
+            dump_start: None,
             crash_context: None,
+            blamed_thread_context: None,
             crashing_thread_context: CrashingThreadContext::None,
+            crashing_thread_registers: Vec::new(),
             stop_timeout: STOP_TIMEOUT,
+            flush_mode: FlushMode::default(),
+            use_cgroup_freezer: false,
             direct_auxv_dump_info: None,
         }
     }
 
-    pub fn set_minidump_size_limit(&mut self, limit: u64) -> &mut Self {
-        self.minidump_size_limit = Some(limit);
-        self
+    /// Prepare the writer to dump another process, so that long-lived
+    /// monitors don't have to set it up again for every dump. The options
+    /// are kept, while what describes the previous process is cleared: the
+    /// crash context, the blamed thread context and the handler threads, the
+    /// application memory and its callbacks, the annotations and user
+    /// streams, the mappings, unloaded modules, GPUs, log ring buffers and
+    /// auxv information, the crash reason, and the deadline. The buffers holding the state of
+    /// the previous dump are emptied but keep their allocations.
+    pub fn reset(&mut self, process: Pid, blamed_thread: Pid) -> &mut Self {
+        self.process_id = process;
+        self.blamed_thread = blamed_thread;
+        self.crash_context = None;
+        self.blamed_thread_context = None;
+        self.handler_threads.clear();
+        self.principal_mapping_address = None;
+        self.user_mapping_list.clear();
+        // The application may still be feeding the previous list
+        self.unloaded_modules = UnloadedModuleList::new();
+        self.signal_dispositions.clear();
+        self.gpu_info.clear();
+        self.log_ring_buffers.clear();
+        self.crash_reason = None;
+        self.app_memory.clear();
+        self.app_memory64.clear();
+        self.app_memory_callbacks.clear();
+        self.annotations.clear();
+        self.assertion = None;
+        self.user_streams.clear();
+        self.direct_auxv_dump_info = None;
+        self.deadline = None;
+        self.crash_summary = None;
+        self.clear_dump_state();
+        self
+    }
+
+    /// Clear what was gathered while writing the previous dump, if any.
+    pub(crate) fn clear_dump_state(&mut self) {
+        self.jit_mapping_list.clear();
+        self.memory_blocks.clear();
+        self.spilled_app_memory.clear();
+        self.module_image_regions.clear();
+        self.listed_modules.clear();
+        self.registers_only_threads.clear();
+        self.unmapped_stack_regions.clear();
+        self.thread_stacks.clear();
+        self.shadow_stacks.clear();
+        self.xstate_header = None;
+        self.thread_xstates.clear();
+        self.soft_errors.clear();
+        self.omitted_sections.clear();
+        self.secondary_faults.clear();
+        self.phase_durations.clear();
+        self.principal_mapping = None;
+        self.crashing_thread_context = CrashingThreadContext::None;
+        self.crashing_thread_registers.clear();
+        self.exploitability = None;
+        self.emulation = None;
+        self.denied_ranges.clear();
+    }
+
+    pub fn set_minidump_size_limit(&mut self, limit: u64) -> &mut Self {
+        self.minidump_size_limit = Some(limit);
+        self
+    }
+
+    /// Try to keep the minidump within `max_dump_size` bytes. When the limit
+    /// gets close, the application memory regions and JIT memory are left out
+    /// first, then the stacks of the threads beyond the first 20, except the
+    /// blamed thread's. The other streams are always written, so the limit
+    /// can still be exceeded. What was left out is recorded as soft errors.
+    pub fn set_max_dump_size(&mut self, max_dump_size: u64) -> &mut Self {
+        self.max_dump_size = Some(max_dump_size);
+        self
+    }
+
+    /// Only capture up to `max_threads` threads, picking the most relevant
+    /// ones: the blamed thread, the main thread, then the busiest ones. This
+    /// bounds the time and memory needed to dump processes with huge numbers
+    /// of threads. The omitted threads are recorded as a soft error.
+    pub fn set_max_threads(&mut self, max_threads: usize) -> &mut Self {
+        self.max_threads = Some(max_threads);
+        self
+    }
+
+    /// Only write the whole stack of the blamed thread and of the
+    /// `max_full_stacks` most relevant other threads. The threads executing
+    /// the module the crash happened in, or whose stack refers to it, come
+    /// first, then the others in the order of the thread list. The stacks of
+    /// the others are truncated to their top 2KiB, which is usually enough to
+    /// tell what they were doing. The truncation is recorded as a soft error.
+    pub fn set_max_full_stacks(&mut self, max_full_stacks: usize) -> &mut Self {
+        self.max_full_stacks = Some(max_full_stacks);
+        self
+    }
+
+    /// Only write the stack of the blamed thread and of the
+    /// `max_thread_stacks` most relevant other threads, ranked as with
+    /// [`Self::set_max_full_stacks`], which then applies among them. Only
+    /// the registers of the others are written, as with
+    /// [`ThreadPolicy::RegistersOnly`]. The stacks left out are recorded as
+    /// a soft error.
+    pub fn set_max_thread_stacks(&mut self, max_thread_stacks: usize) -> &mut Self {
+        self.max_thread_stacks = Some(max_thread_stacks);
+        self
+    }
+
+    /// Only capture up to `crashing_thread` bytes of the blamed thread's stack
+    /// and `other_threads` bytes of the other threads' stacks, starting at
+    /// their stack pointer, to trade the size of the dump against how deep
+    /// their stacks can be walked. The memory below the stack pointer, see
+    /// [`Self::set_stack_red_zone`], doesn't count towards those.
+    pub fn set_max_stack_bytes_per_thread(
+        &mut self,
+        crashing_thread: usize,
+        other_threads: usize,
+    ) -> &mut Self {
+        self.max_crashing_stack_bytes = Some(crashing_thread);
+        self.max_stack_bytes = Some(other_threads);
+        self
+    }
+
+    /// Capture at least `red_zone` bytes below the stack pointer of each
+    /// thread along with its stack, [`DEFAULT_STACK_RED_ZONE`] by default.
+    /// They hold the locals of leaf functions, which may not move the stack
+    /// pointer, and the frames which were just popped. The stacks are
+    /// captured from the start of the page the red zone starts in, and never
+    /// past the start of the stack mapping.
+    pub fn set_stack_red_zone(&mut self, red_zone: usize) -> &mut Self {
+        self.stack_red_zone = red_zone;
+        self
+    }
+
+    /// Decide for each thread, given its id and name, whether to capture its
+    /// stack, only its registers, or to leave it out entirely, e.g. to only
+    /// capture the stacks of the interesting subset of huge thread pools. The
+    /// blamed thread is always captured in full and isn't passed to `filter`.
+    /// The threads are filtered before [`Self::set_max_threads`] applies.
+    pub fn set_thread_filter(
+        &mut self,
+        filter: impl FnMut(Pid, Option<&str>) -> ThreadPolicy + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.thread_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Mark the threads of the crash handler, e.g. the one which requested
+    /// the dump from an in-process signal handler, or the helper threads of
+    /// the writer, so that they don't crowd out the threads of the
+    /// application. They're captured according to `policy`, typically
+    /// [`ThreadPolicy::Skip`] or [`ThreadPolicy::RegistersOnly`], rather than
+    /// passed to [`Self::set_thread_filter`]. The blamed thread is always
+    /// captured in full.
+    pub fn set_handler_threads(&mut self, threads: Vec<Pid>, policy: ThreadPolicy) -> &mut Self {
+        self.handler_threads = threads;
+        self.handler_thread_policy = policy;
+        self
+    }
+
+    /// Only list up to `max_modules` modules, picking the ones the threads
+    /// are executing and the ones the crashing thread's stack refers to first,
+    /// then those provided with [`Self::set_user_mapping_list`] and the JIT
+    /// code, then the others by address. This keeps processes with thousands
+    /// of libraries from producing huge module lists. The omitted modules are
+    /// recorded as a soft error.
+    pub fn set_max_modules(&mut self, max_modules: usize) -> &mut Self {
+        self.max_modules = Some(max_modules);
+        self
+    }
+
+    /// Identify the modules on up to `worker_threads` threads, 1 by default.
+    /// Reading the build ids and names of the modules of a process with
+    /// hundreds of libraries dominates the time taken by the dump, and the
+    /// reads don't depend on each other since the process is stopped, so
+    /// crash collectors running on hosts with many cores can spread them.
+    pub fn set_worker_threads(&mut self, worker_threads: usize) -> &mut Self {
+        self.worker_threads = worker_threads.max(1);
+        self
+    }
+
+    /// Decide for each module, given the path which would be recorded for
+    /// it, whether to list it, to list it with another path, e.g. to strip
+    /// the user's home directory, or to leave it out, see
+    /// [`crate::module_filter`]. The modules are filtered before
+    /// [`Self::set_max_modules`] applies.
+    pub fn set_module_filter(
+        &mut self,
+        filter: impl FnMut(&str) -> ModuleAction + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.module_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Compute a checksum of the first 4 KiB of each module mapped by the
+    /// process, which hold its headers, and record it in the module list, so
+    /// that processors can cheaply tell when the copy in their symbol store
+    /// doesn't match. The checksum is 0 when they couldn't be read.
+    pub fn set_module_checksum(&mut self, checksum: ModuleChecksum) -> &mut Self {
+        self.module_checksum = Some(checksum);
+        self
+    }
+
+    /// Write the modules reported to `unloaded_modules` as they were unloaded
+    /// to the UnloadedModuleListStream, see [`crate::unloaded_modules`]. The
+    /// list is shared, so the application can keep feeding it until the
+    /// process is dumped.
+    pub fn set_unloaded_module_list(&mut self, unloaded_modules: UnloadedModuleList) -> &mut Self {
+        self.unloaded_modules = unloaded_modules;
+        self
+    }
+
+    /// Write `dispositions` to the SignalDispositions stream instead of the
+    /// ones read from `/proc/<pid>/status`, see
+    /// [`crate::signal_dispositions`]. Unlike the kernel, the application can
+    /// tell which function handles each signal.
+    pub fn set_signal_dispositions(&mut self, dispositions: Vec<SignalDisposition>) -> &mut Self {
+        self.signal_dispositions = dispositions;
+        self
+    }
+
+    /// Record the machine id and the boot id of the host in the HostIds
+    /// stream as `host_ids` says, see [`crate::host_ids`]. They are recorded
+    /// as they are by default.
+    pub fn set_host_ids(&mut self, host_ids: HostIds) -> &mut Self {
+        self.host_ids = host_ids;
+        self
+    }
+
+    /// Describe the GPUs used by the process in the GpuInfo stream, see
+    /// [`crate::gpu_info`]. The stream is left out if there are none.
+    pub fn set_gpu_info(&mut self, gpu_info: Vec<GpuInfo>) -> &mut Self {
+        self.gpu_info = gpu_info;
+        self
+    }
+
+    /// Capture the lines logged to the circular buffer `ring` in the LogLines
+    /// stream, reading it from the process when it's dumped, see
+    /// [`crate::log_ring_buffer`]. The memory denied by
+    /// [`Self::set_memory_denylist`] is zeroed, as are the bytes
+    /// [`Self::set_memory_redactor`] blanks.
+    pub fn register_log_ring_buffer(&mut self, ring: LogRingBuffer) -> &mut Self {
+        self.log_ring_buffers.push(ring);
+        self
+    }
+
+    /// Give the reason of the crash, e.g. the message of an abort, written to
+    /// the CrashReason stream and to the [`Self::crash_summary`], see
+    /// [`crate::crash_reason`]. It's truncated to
+    /// [`crate::crash_reason::MAX_CRASH_REASON_LEN`] bytes.
+    pub fn set_crash_reason(&mut self, reason: impl Into<String>) -> &mut Self {
+        self.crash_reason = Some(crash_reason::truncate(reason.into()));
+        self
+    }
+
+    /// Only capture up to `max_memory_regions` regions in the memory list, and
+    /// as many in the Memory64List. The application memory and JIT code are
+    /// left out first, the stacks and the memory around the crashing
+    /// instruction are always captured, so the limit can still be exceeded.
+    /// In the Memory64List the application's regions come first. The omitted
+    /// regions are recorded as a soft error.
+    pub fn set_max_memory_regions(&mut self, max_memory_regions: usize) -> &mut Self {
+        self.max_memory_regions = Some(max_memory_regions);
+        self
+    }
+
+    /// Replace the limits on the counts and sizes read from the process, see
+    /// [`crate::capture_limits`]. More threads than allowed are left out as
+    /// with [`Self::set_max_threads`], the modules whose headers go over the
+    /// limits are read from their files instead, and the memory regions
+    /// larger than allowed are truncated.
+    pub fn set_capture_limits(&mut self, limits: CaptureLimits) -> &mut Self {
+        self.capture_limits = limits;
+        self
+    }
+
+    /// List the given modules, e.g. collected by the application when it was
+    /// loading them, with the identifiers provided. They replace the
+    /// mappings read from the process which they contain. If the process'
+    /// mappings can't be read at all, as can happen with sandboxed
+    /// processes, these are trusted instead of failing the dump, see
+    /// [`crate::maps_reader::MappingEntry::new`].
+    pub fn set_user_mapping_list(&mut self, user_mapping_list: MappingList) -> &mut Self {
+        self.user_mapping_list = user_mapping_list;
+        self
+    }
+
+    pub fn set_principal_mapping_address(&mut self, principal_mapping_address: usize) -> &mut Self {
+        self.principal_mapping_address = Some(principal_mapping_address);
+        self
+    }
+
+    /// Write the functions of the JIT-compiled code of the process, read from
+    /// the perf map or jitdump files its runtime publishes for profilers, see
+    /// [`crate::jit_symbols`], so that the frames of managed code resolve to
+    /// method names. Ignored in [`Self::minimal`] dumps.
+    pub fn capture_jit_symbols(&mut self) -> &mut Self {
+        self.capture_jit_symbols = true; // Off by default
+        self
+    }
+
+    /// Capture up to `window` bytes of memory around the instruction pointer of
+    /// every thread executing code in an anonymous executable mapping, as is
+    /// the case for JIT-compiled code (V8, LuaJIT, wasm runtimes...). A module
+    /// is added to the module list for each such mapping.
+    pub fn capture_jit_memory(&mut self, window: usize) -> &mut Self {
+        self.jit_memory_window = Some(window);
+        self
+    }
+
+    /// Capture up to `window` bytes of memory around the instruction pointer of
+    /// every thread, as is always done for the crashing one, so that what the
+    /// other threads were executing can be disassembled without the original
+    /// binaries, e.g. to see what a busy thread was doing in a hang. Ignored
+    /// in [`Self::minimal`] dumps and for the threads whose registers only
+    /// are wanted, see [`Self::set_thread_filter`].
+    pub fn capture_thread_ip_memory(&mut self, window: usize) -> &mut Self {
+        self.thread_ip_memory_window = Some(window);
+        self
+    }
+
+    /// Record the backtrace of every thread, up to `max_frames` deep, found by
+    /// following its frame pointers, in a stream of its own. This needs no
+    /// symbols nor unwind information, so it gives an idea of where each
+    /// thread was even to tools which can't unwind stacks, but it stops at
+    /// the first function built without frame pointers.
+    pub fn capture_backtraces(&mut self, max_frames: usize) -> &mut Self {
+        self.backtrace_depth = Some(max_frames.max(1));
+        self
+    }
+
+    /// Capture up to `window` bytes of memory around the address the crashing
+    /// thread faulted on, as reported by `SIGSEGV` and `SIGBUS`, to see the
+    /// contents of the object which was being accessed. Only readable memory
+    /// is captured. Ignored in [`Self::minimal`] dumps.
+    pub fn capture_fault_address_memory(&mut self, window: usize) -> &mut Self {
+        self.fault_address_memory_window = Some(window);
+        self
+    }
+
+    /// Scan the captured stacks for values pointing to data, i.e. into a
+    /// readable mapping which isn't executable, and capture the memory around
+    /// each, up to `budget` bytes in total. This is the equivalent of
+    /// `MiniDumpWithIndirectlyReferencedMemory` on Windows, and often shows
+    /// the objects the crashing code was working on. The crashing thread's
+    /// stack is scanned first. Ignored in [`Self::minimal`] dumps.
+    pub fn capture_referenced_memory(&mut self, budget: usize) -> &mut Self {
+        self.referenced_memory_budget = Some(budget);
+        self
+    }
+
+    /// Capture the memory around the addresses held by the general purpose
+    /// registers of the crashing thread which point to data, up to `budget`
+    /// bytes in total. After the stack, this is usually where the values the
+    /// crashing code was working on are. Ignored in [`Self::minimal`] dumps.
+    pub fn capture_register_memory(&mut self, budget: usize) -> &mut Self {
+        self.register_memory_budget = Some(budget);
+        self
+    }
+
+    /// Capture the thread-local storage of every thread: the memory around
+    /// its thread pointer, holding the thread control block and the TLS of
+    /// the modules loaded at startup, and with glibc the TLS blocks of the
+    /// modules loaded later. This is where `errno`, the caches of
+    /// allocators and the state of many runtimes live. Ignored in
+    /// [`Self::minimal`] dumps.
+    pub fn capture_thread_local_storage(&mut self) -> &mut Self {
+        self.capture_thread_local_storage = true; // Off by default
+        self
+    }
+
+    /// Capture the given regions of the process' memory in the memory list,
+    /// e.g. a ring buffer of recent log lines or the state of the
+    /// application, replacing those registered so far.
+    pub fn set_app_memory(&mut self, app_memory: AppMemoryList) -> &mut Self {
+        self.app_memory = app_memory;
+        self
+    }
+
+    /// Capture the `length` bytes at `ptr` in the process' memory in the
+    /// memory list, see [`Self::set_app_memory`]. Registering the same
+    /// address again changes the length of its region.
+    pub fn register_app_memory(&mut self, ptr: usize, length: usize) -> &mut Self {
+        match self.app_memory.iter_mut().find(|region| region.ptr == ptr) {
+            Some(region) => region.length = length,
+            None => self.app_memory.push(AppMemory { ptr, length }),
+        }
+        self
+    }
+
+    /// Stop capturing the region registered at `ptr` with
+    /// [`Self::register_app_memory`].
+    pub fn unregister_app_memory(&mut self, ptr: usize) -> &mut Self {
+        self.app_memory.retain(|region| region.ptr != ptr);
+        self
+    }
+
+    /// Like [`Self::set_app_memory`], with regions returned by `callback`
+    /// while the minidump is written. It runs with the process suspended,
+    /// and is passed its id and the crash context, if any, so it can
+    /// compute the regions from the state of the process, e.g. the object
+    /// the faulting address points into.
+    pub fn add_app_memory_callback(
+        &mut self,
+        callback: impl FnMut(Pid, Option<&CrashContext>) -> AppMemoryList + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.app_memory_callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Like [`Self::set_app_memory`], but the regions are written to the
+    /// Memory64List stream, whose 64-bit sizes and offsets can describe
+    /// regions and minidumps larger than 4GiB.
+    pub fn set_app_memory64(&mut self, app_memory: AppMemoryList) -> &mut Self {
+        self.app_memory64 = app_memory;
+        self
+    }
+
+    pub fn set_crash_context(&mut self, crash_context: CrashContext) -> &mut Self {
+        self.crash_context = Some(crash_context);
+        self
+    }
+
+    /// Designate the thread reported as the crashing one, replacing the
+    /// thread passed to [`Self::new`]. This is needed when the handler
+    /// requesting the dump doesn't run on the thread which faulted.
+    pub fn set_blamed_thread(&mut self, tid: Pid) -> &mut Self {
+        self.blamed_thread = tid;
+        self
+    }
+
+    /// Supply the registers of the blamed thread, e.g. captured earlier by a
+    /// watchdog, to be written instead of those of the crash context or of
+    /// the thread's current state. Its stack is walked from the stack pointer
+    /// of `context`.
+    pub fn set_blamed_thread_context(&mut self, context: RawContextCPU) -> &mut Self {
+        self.blamed_thread_context = Some(context);
+        self
+    }
+
+    /// Record the assertion which failed when the application aborts, so that
+    /// the minidump shows its expression and location.
+    pub fn set_assertion(&mut self, assertion: AssertionInfo) -> &mut Self {
+        self.assertion = Some(assertion);
+        self
+    }
+
+    /// Add a key/value pair describing the application, e.g. its version or
+    /// release channel, to the [`Self::crash_summary`], and to the
+    /// CrashpadInfo stream so that servers consuming Crashpad's annotations
+    /// find it.
+    pub fn set_annotation(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> &mut Self {
+        self.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a stream holding `bytes` to the minidump, e.g. the state of the
+    /// application or a blob from the GPU driver. `stream_type` should be
+    /// above [`MDStreamType::LastReservedStream`], and distinct from the
+    /// other streams, so that readers can find it.
+    pub fn add_user_stream(&mut self, stream_type: u32, bytes: &[u8]) -> &mut Self {
+        self.user_streams.push(UserStream {
+            stream_type,
+            data: UserStreamData::Bytes(bytes.to_vec()),
+        });
+        self
+    }
+
+    /// Like [`Self::add_user_stream`], with contents produced by `callback`
+    /// while the minidump is written. It runs with the process suspended,
+    /// and is passed its id, so it can gather data from the process as it
+    /// was when it crashed.
+    pub fn add_deferred_user_stream(
+        &mut self,
+        stream_type: u32,
+        callback: impl FnMut(Pid) -> Vec<u8> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.user_streams.push(UserStream {
+            stream_type,
+            data: UserStreamData::Deferred(Box::new(callback)),
+        });
+        self
+    }
+
+    pub fn skip_stacks_if_mapping_unreferenced(&mut self) -> &mut Self {
+        self.skip_stacks_if_mapping_unreferenced = true; // Off by default
+        self
+    }
+
+    pub fn sanitize_stack(&mut self) -> &mut Self {
+        self.sanitize_stack = true; // Off by default
+        self
+    }
+
+    /// Call `redactor` on every block of memory captured from the process,
+    /// stacks included, with the range it was read from, before it's written.
+    /// It can overwrite sensitive data, e.g. session tokens or key material,
+    /// while keeping the surrounding bytes for debugging. Large blocks, as
+    /// captured with [`Self::full_memory`], may be passed in several pieces.
+    pub fn set_memory_redactor(
+        &mut self,
+        redactor: impl Fn(Range<usize>, &mut [u8]) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.memory_redactor = Some(Box::new(redactor));
+        self
+    }
+
+    /// Replace what looks like personal data in the captured memory, stacks
+    /// included: email addresses, credit card numbers and long strings, for
+    /// deployments with strict data-handling rules. The size of the memory
+    /// and the pointers in it are kept, see [`crate::pii_scrubber`].
+    pub fn scrub_pii(&mut self) -> &mut Self {
+        self.scrub_pii = true; // Off by default
+        self
+    }
+
+    /// Never capture the memory denied by `denylist`, e.g. the regions holding
+    /// cryptographic keys or the user's documents, whether it's on a stack,
+    /// referenced from one or captured with [`Self::full_memory`]. The denied
+    /// bytes are zeroed, after [`Self::set_memory_redactor`] has run, see
+    /// [`crate::memory_denylist`].
+    pub fn set_memory_denylist(&mut self, denylist: MemoryDenylist) -> &mut Self {
+        self.memory_denylist = denylist;
+        self
+    }
+
+    /// Only write the streams selected by `selection`, see
+    /// [`crate::stream_selection`]. The others aren't gathered at all, their
+    /// directory entries are left empty.
+    pub fn set_stream_selection(&mut self, selection: StreamSelection) -> &mut Self {
+        self.stream_selection = selection;
+        self
+    }
+
+    /// Rate how likely the crash is to be exploitable, from how the crashing
+    /// thread faulted, where its instruction and stack pointers point and what
+    /// its registers hold, so that security teams can prioritize crashes
+    /// without processing them. The rating and what it's based on are written
+    /// to a stream, and the rating to the [`Self::crash_summary`], see
+    /// [`crate::exploitability`]. Ignored in [`Self::minimal`] dumps.
+    pub fn assess_exploitability(&mut self) -> &mut Self {
+        self.assess_exploitability = true; // Off by default
+        self
+    }
+
+    /// Capture the contents of every readable mapping of the process, to debug
+    /// heap corruption and the like. They are written to the Memory64List
+    /// stream, after everything else, and can be bounded with
+    /// [`Self::set_max_dump_size`]. Sanitizer shadow memory is still excluded
+    /// unless [`Self::include_sanitizer_shadow`] is set too.
+    pub fn full_memory(&mut self) -> &mut Self {
+        self.full_memory = true; // Off by default
+        self
+    }
+
+    /// Copy the executable segments of the modules to the Memory64List, up to
+    /// `budget` bytes, so that the dump can be disassembled and symbolicated
+    /// even when the binaries can't be found, as is often the case for code
+    /// injected by third parties. The segment of the crashing code comes
+    /// first, a segment mapped several times is copied once, and those which
+    /// don't fit in what's left of the budget are skipped. Ignored in
+    /// [`Self::minimal`] dumps and dumps of core files.
+    pub fn capture_module_images(&mut self, budget: usize) -> &mut Self {
+        self.module_images_budget = Some(budget);
+        self
+    }
+
+    /// Write a minimal dump, of a few tens of KiB, holding only what's needed
+    /// to walk the stack of the crashing thread: the system information, the
+    /// exception and assertion, the thread contexts, the crashing thread's stack and the
+    /// modules its instruction pointers and stack refer to. The other threads'
+    /// stacks and all the other streams are left out, and the options adding
+    /// memory are ignored. The build ids and names of the other modules
+    /// aren't read either, which makes minimal dumps of processes with
+    /// hundreds of modules much faster to write, from core dumps too.
+    pub fn minimal(&mut self) -> &mut Self {
+        self.minimal = true; // Off by default
+        self
+    }
+
+    /// Capture memory from sanitizer (ASan, MSan, TSan...) shadow mappings.
+    /// These are normally excluded as they can be terabytes in size, which is
+    /// recorded in the dump with a [`SoftError::SanitizerShadowOmitted`] for
+    /// each mapping which would have been captured.
+    pub fn include_sanitizer_shadow(&mut self) -> &mut Self {
+        self.include_sanitizer_shadow = true; // Off by default
+        self
+    }
+
+    /// Round the memory captured around instruction pointers and the
+    /// application's memory regions out to whole pages, as far as the
+    /// mappings containing them are readable. This compresses better, is what
+    /// some analysis tools expect, and guarantees that the whole page of the
+    /// crashing instruction is present.
+    pub fn page_align_memory(&mut self) -> &mut Self {
+        self.page_align_memory = true; // Off by default
+        self
+    }
+
+    /// Make the output depend only on the state of the process, so that dumping
+    /// the same process twice produces identical minidumps, e.g. for golden
+    /// file tests or deduplicating dumps by their hash. The time of the dump
+    /// is taken from `clock`, the monotonic and boot clocks and the CPU time
+    /// of the process are left out, and the threads are written ordered by
+    /// id. Unused bytes are always zeroed, so the rest of the minidump only
+    /// changes when the process itself does.
+    pub fn deterministic(
+        &mut self,
+        clock: impl Fn() -> SystemTime + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.deterministic_clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Call `callback` once the process is attached, after each stream is
+    /// written, and once it's resumed, so that crash reporters can show the
+    /// progress of the dump, and watchdogs can tell a slow dump from a stuck
+    /// one. The callback runs while the process is stopped, so it should
+    /// return quickly.
+    pub fn on_progress(
+        &mut self,
+        callback: impl FnMut(&DumpProgress) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.progress_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Pass every stream to `hook` as it's written, so that it can omit it,
+    /// replace its contents or observe it, see [`StreamHook`]. Hooks are
+    /// called in the order they were added, and run while the process is
+    /// stopped.
+    pub fn add_stream_hook(&mut self, hook: impl StreamHook + 'static) -> &mut Self {
+        self.stream_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Stop the dump as soon as `token` is cancelled. It is checked after
+    /// attaching to the process, between streams, and between the chunks of
+    /// memory copied with [`Self::full_memory`], so that even dumps of huge
+    /// processes can be interrupted. The dump then fails with
+    /// [`CancelledError::Cancelled`] and the process is resumed, leaving
+    /// whatever was written so far in the destination.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Refuse to dump processes which were dumped too recently according to
+    /// `limiter`, see [`crate::rate_limit`]. The dumps then fail with
+    /// [`crate::errors::RateLimitError`] before attaching to the process.
+    /// The limiter can be shared by the writers of a monitor.
+    pub fn set_rate_limiter(&mut self, limiter: Arc<RateLimiter>) -> &mut Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Stop the dump if it's still being written at `deadline`, like with
+    /// [`Self::set_cancellation_token`]. Rather than failing, the minidump is
+    /// then finished with the streams written so far, plus the exception and
+    /// system information streams, so that it still identifies the crash.
+    /// It's marked as truncated by the [`TRUNCATED_ANNOTATION`] annotation
+    /// and a [`SoftError::Truncated`]. The dump only fails with
+    /// [`CancelledError::DeadlineExceeded`] if the deadline passed while
+    /// attaching to the process, before anything was written.
+    pub fn set_deadline(&mut self, deadline: Instant) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop every dump which takes longer than `timeout`, counted from when
+    /// it starts attaching to the process, so that a wedged kernel interface
+    /// or a huge process can't stall the crash handler indefinitely. Besides
+    /// the checks made for [`Self::set_cancellation_token`], it's checked
+    /// for every thread, module and application memory region. The minidump
+    /// is then finished early like with [`Self::set_deadline`], but unlike
+    /// the deadline the timeout is kept by [`Self::reset`].
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout after `SIGSTOP` is sent to the process, if the process
+    /// has not stopped by the time the timeout has reached, we proceed with
+    /// minidump generation
+    pub fn stop_timeout(&mut self, duration: Duration) -> &mut Self {
+        self.stop_timeout = duration;
+        self
+    }
+
+    /// Sets what to do with the destination once the minidump has been
+    /// written, by default it is flushed
+    pub fn flush_mode(&mut self, flush_mode: FlushMode) -> &mut Self {
+        self.flush_mode = flush_mode;
+        self
+    }
+
+    /// Stop the process by freezing its cgroup (cgroup v2 `cgroup.freeze`)
+    /// instead of sending it `SIGSTOP`, which is faster and avoids races with
+    /// thread creation for processes with many threads. The process must be
+    /// alone in its cgroup, otherwise this falls back to `SIGSTOP`.
+    ///
+    /// The `stop_timeout` also applies to waiting for the cgroup to freeze.
+    pub fn use_cgroup_freezer(&mut self) -> &mut Self {
+        self.use_cgroup_freezer = true; // Off by default
+        self
+    }
+
+    /// Directly set important Auxv info determined by the crashing process
+    ///
+    /// Since `/proc/{pid}/auxv` can sometimes be inaccessible, the calling process should prefer to transfer this
+    /// information directly using the Linux `getauxval()` call (if possible).
+    ///
+    /// Any field that is set to `0` will be considered unset. In that case, minidump-writer might try other techniques
+    /// to obtain it (like reading `/proc/{pid}/auxv`).
+    pub fn set_direct_auxv_dump_info(
+        &mut self,
+        direct_auxv_dump_info: DirectAuxvDumpInfo,
+    ) -> &mut Self {
+        self.direct_auxv_dump_info = Some(direct_auxv_dump_info);
+        self
+    }
+
+    /// Generates a minidump and writes to the destination provided. Returns a
+    /// [`DumpResult`] describing what was written. A summary of the crash is
+    /// left in [`Self::crash_summary`], to be sent along with the minidump.
+    ///
+    /// Only the threads, the memory lists, the exception and the system
+    /// information are needed for the dump to succeed. When another stream
+    /// fails it is left out, see [`Self::omitted_sections`].
+    pub fn dump(&mut self, destination: &mut (impl Write + Seek)) -> Result<DumpResult> {
+        let (result, _) = self.dump_reserving(destination, |_, _| {})?;
+        Ok(result)
+    }
+
+    /// Generates a minidump of `process`, which didn't crash, e.g. to
+    /// investigate a hang or the growth of its memory, or because its user
+    /// asked for it. Its exception stream holds a synthetic `DUMP_REQUESTED`
+    /// exception blaming the main thread, and the process is only stopped
+    /// while the minidump is written. Use [`Self::new`] to customize the
+    /// minidump, which is the same as long as no crash context is set.
+    pub fn dump_without_crashing(
+        process: Pid,
+        destination: &mut (impl Write + Seek),
+    ) -> Result<DumpResult> {
+        Self::new(process, process).dump(destination)
+    }
+
+    /// Like [`Self::dump`], calling `reserve` with the destination and the
+    /// estimated size of the minidump before writing it, so that file
+    /// destinations can allocate the space upfront. Returns the in-memory
+    /// version of the minidump as well.
+    pub(crate) fn dump_reserving<W: Write + Seek>(
+        &mut self,
+        destination: &mut W,
+        reserve: impl FnOnce(&mut W, usize),
+    ) -> Result<(DumpResult, Vec<u8>)> {
+        let _span = span!(
+            "dump",
+            pid = self.process_id,
+            blamed_thread = self.blamed_thread
+        );
+        let start = Instant::now();
+        let mut dumper = self.attach()?;
+        self.phase_durations.push(("attach", start.elapsed()));
+        self.check_cancelled()?;
+        self.report_progress(DumpProgress {
+            phase: DumpPhase::Attached,
+            threads: dumper.threads.len(),
+            streams_written: 0,
+            stream_count: self.stream_count(),
+            bytes_written: 0,
+        });
+
+        if self.skip_stacks_if_mapping_unreferenced {
+            if let Some(address) = self.principal_mapping_address {
+                self.principal_mapping = dumper.find_mapping_no_bias(address).cloned();
+            }
+
+            if !self.crash_thread_references_principal_mapping(&dumper) {
+                return Err(InitError::PrincipalMappingNotReferenced.into());
+            }
+        }
+
+        let estimated_size = self.estimate_dump_size(&dumper);
+        reserve(destination, estimated_size);
+        let mut buffer = Buffer::with_capacity(estimated_size);
+        let dir_entries = {
+            let _span = span!("write_streams", threads = dumper.threads.len());
+            let start = Instant::now();
+            let dir_entries = self.generate_dump(&mut buffer, &mut dumper, destination)?;
+            self.phase_durations
+                .push(("write_streams", start.elapsed()));
+            dir_entries
+        };
+        event!(
+            INFO,
+            bytes = buffer.len(),
+            memory_regions = self.memory_blocks.len(),
+            "minidump written"
+        );
+
+        if self.flush_mode != FlushMode::None {
+            destination.flush().map_err(FileWriterError::from)?;
+        }
+
+        self.crash_summary = Some(CrashSummary::collect(self, &dumper));
+
+        // dumper would resume threads in drop() automatically,
+        // but in case there is an error, we want to catch it
+        let start = Instant::now();
+        dumper.resume_threads()?;
+        self.phase_durations.push(("resume", start.elapsed()));
+        self.report_progress(DumpProgress {
+            phase: DumpPhase::Finished,
+            threads: dumper.threads.len(),
+            streams_written: self.stream_count(),
+            stream_count: self.stream_count(),
+            bytes_written: buffer.position(),
+        });
+
+        let dump: Vec<u8> = buffer.into();
+        let result = DumpResult {
+            phases: self.phase_durations.clone(),
+            soft_errors: self.soft_errors.iter().map(ToString::to_string).collect(),
+            omitted_sections: self.omitted_sections.clone(),
+            ..DumpResult::from_dir_entries(&dump, &dir_entries)
+        };
+        Ok((result, dump))
+    }
+
+    /// Attach to the process and suspend its threads, gathering what's needed
+    /// to dump it.
+    pub(crate) fn attach(&mut self) -> Result<PtraceDumper> {
+        let _span = span!("attach");
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.check(self.process_id)?;
+        }
+        self.dump_start = Some(Instant::now());
+        let auxv = self
+            .direct_auxv_dump_info
+            .clone()
+            .map(AuxvDumpInfo::from)
+            .unwrap_or_default();
+        let mut dumper = if self.use_cgroup_freezer {
+            PtraceDumper::new_frozen(self.process_id, self.stop_timeout, auxv)?
+        } else {
+            PtraceDumper::new(self.process_id, self.stop_timeout, auxv)?
+        };
+        dumper.capture_limits = self.capture_limits;
+
+        // The blamed thread may have been provided by a crash handler living in
+        // the PID namespace of the process, translate it to our own.
+        if !dumper.threads.iter().any(|t| t.tid == self.blamed_thread) {
+            if let Some(tid) = dumper.translate_ns_tid(self.blamed_thread) {
+                log::debug!(
+                    "blamed thread {} is {tid} in our namespace",
+                    self.blamed_thread
+                );
+                self.blamed_thread = tid;
+            }
+        }
+
+        self.clear_dump_state();
+        if let Some(error) = dumper.mappings_error.take() {
+            if self.user_mapping_list.is_empty() {
+                return Err(error.into());
+            }
+            // Sandboxes may deny access to the process' mappings, trust those
+            // the application collected instead
+            dumper.mappings = self
+                .user_mapping_list
+                .iter()
+                .map(|entry| entry.mapping.clone())
+                .collect();
+            self.soft_errors.push(SoftError::MappingsUnavailable(error));
+        }
+        self.denied_ranges = self.memory_denylist.resolve(&dumper.mappings);
+        self.filter_threads(&mut dumper);
+        let max_threads = self.capture_limits.max_threads;
+        if let Err(error) =
+            capture_limits::check("thread count", dumper.threads.len() as u64, max_threads)
+        {
+            log::warn!("{error}");
+            dumper.select_threads(max_threads, self.blamed_thread);
+            self.soft_errors
+                .push(SoftError::CaptureLimitExceeded(error));
+        }
+        if let Some(max_threads) = self.max_threads {
+            let omitted = dumper.select_threads(max_threads, self.blamed_thread);
+            if omitted > 0 {
+                log::warn!("thread limit reached, leaving out {omitted} threads");
+                self.soft_errors.push(SoftError::ThreadsOmitted(omitted));
+            }
+        }
+
+        if self.deterministic_clock.is_some() {
+            dumper.threads.sort_by_key(|thread| thread.tid);
+        }
+
+        dumper.suspend_threads()?;
+        dumper.late_init()?;
+        dumper.capture_thread_infos();
+
+        Ok(dumper)
+    }
+
+    /// Apply the [`Self::set_handler_threads`] and [`Self::set_thread_filter`]
+    /// policies to the threads, before they are suspended.
+    fn filter_threads(&mut self, dumper: &mut PtraceDumper) {
+        if self.thread_filter.is_none() && self.handler_threads.is_empty() {
+            return;
+        }
+
+        // Like the blamed thread, the handler threads may have been provided
+        // by a handler living in the PID namespace of the process
+        let handler_threads: Vec<Pid> = self
+            .handler_threads
+            .iter()
+            .map(|&tid| {
+                if dumper.threads.iter().any(|thread| thread.tid == tid) {
+                    tid
+                } else {
+                    dumper.translate_ns_tid(tid).unwrap_or(tid)
+                }
+            })
+            .collect();
+        let handler_thread_policy = self.handler_thread_policy;
+        let blamed_thread = self.blamed_thread;
+        let filter = &mut self.thread_filter;
+        let registers_only = &mut self.registers_only_threads;
+        let threads_count = dumper.threads.len();
+        dumper.threads.retain(|thread| {
+            if thread.tid == blamed_thread {
+                return true;
+            }
+            let policy = if handler_threads.contains(&thread.tid) {
+                handler_thread_policy
+            } else if let Some(filter) = filter {
+                filter(thread.tid, thread.name.as_deref())
+            } else {
+                ThreadPolicy::FullStack
+            };
+            match policy {
+                ThreadPolicy::FullStack => true,
+                ThreadPolicy::RegistersOnly => {
+                    registers_only.push(thread.tid);
+                    true
+                }
+                ThreadPolicy::Skip => false,
+            }
+        });
+        let skipped = threads_count - dumper.threads.len();
+        if skipped > 0 {
+            log::debug!("the thread filters left out {skipped} threads");
+        }
+    }
+
+    /// Generates a minidump and writes it to `file`, syncing it to the storage
+    /// device afterwards if requested with [`FlushMode::Sync`].
+    pub fn dump_to_file(&mut self, file: &mut std::fs::File) -> Result<DumpResult> {
+        let (result, _) = self.dump_reserving(file, reserve_file_space)?;
+        if self.flush_mode == FlushMode::Sync {
+            file.sync_data().map_err(FileWriterError::from)?;
+        }
+        Ok(result)
     }
 
-    pub fn set_user_mapping_list(&mut self, user_mapping_list: MappingList) -> &mut Self {
-        self.user_mapping_list = user_mapping_list;
-        self
+    /// Generates a minidump in memory only, returning its contents without
+    /// writing them anywhere.
+    pub fn dump_to_vec(&mut self) -> Result<Vec<u8>> {
+        let (_, dump) = self.dump_reserving(&mut DiscardSink::default(), |_, _| {})?;
+        Ok(dump)
     }
 
-    pub fn set_principal_mapping_address(&mut self, principal_mapping_address: usize) -> &mut Self {
-        self.principal_mapping_address = Some(principal_mapping_address);
-        self
+    /// Generates a minidump and writes it to `destination` with a single
+    /// forward-only write, for destinations which can't seek, such as pipes
+    /// or sockets.
+    ///
+    /// The whole minidump is staged in memory first, so unlike with
+    /// [`Self::dump`] nothing is written if dumping fails midway.
+    pub fn dump_to_writer(&mut self, destination: &mut impl Write) -> Result<DumpResult> {
+        let (result, dump) = self.dump_reserving(&mut DiscardSink::default(), |_, _| {})?;
+        destination
+            .write_all(&dump)
+            .map_err(FileWriterError::from)?;
+        if self.flush_mode != FlushMode::None {
+            destination.flush().map_err(FileWriterError::from)?;
+        }
+        Ok(result)
     }
 
-    pub fn set_app_memory(&mut self, app_memory: AppMemoryList) -> &mut Self {
-        self.app_memory = app_memory;
-        self
+    /// Generates a minidump and writes it to `destination` compressed with the
+    /// given zstd `level`, see [`crate::compression`]. The size in the
+    /// returned [`DumpResult`] is the uncompressed one.
+    #[cfg(feature = "zstd")]
+    pub fn dump_compressed(
+        &mut self,
+        destination: &mut impl Write,
+        level: i32,
+    ) -> Result<DumpResult> {
+        let (result, dump) = self.dump_reserving(&mut DiscardSink::default(), |_, _| {})?;
+        crate::compression::write_compressed(&dump, destination, level)
+            .map_err(FileWriterError::from)?;
+        Ok(result)
     }
 
-    pub fn set_crash_context(&mut self, crash_context: CrashContext) -> &mut Self {
-        self.crash_context = Some(crash_context);
-        self
+    /// Generates a minidump and writes it to `destination` like
+    /// [`Self::dump_to_writer`], leaving its SHA-256 digest in
+    /// [`DumpResult::sha256`]. The digest is computed from the bytes as they
+    /// are written to `destination`, see
+    /// [`HashingWriter`](crate::dir_section::HashingWriter), so callers don't
+    /// have to read it back to verify or deduplicate the minidump.
+    #[cfg(feature = "sha2")]
+    pub fn dump_with_digest(&mut self, destination: &mut impl Write) -> Result<DumpResult> {
+        let mut destination = crate::dir_section::HashingWriter::new(destination);
+        let result = self.dump_to_writer(&mut destination)?;
+        Ok(DumpResult {
+            sha256: Some(destination.digest()),
+            ..result
+        })
     }
 
-    pub fn skip_stacks_if_mapping_unreferenced(&mut self) -> &mut Self {
-        self.skip_stacks_if_mapping_unreferenced = true; // Off by default
-        self
+    /// Generates a minidump into an anonymous file which isn't linked to any
+    /// filesystem, returning its file descriptor positioned at the start of
+    /// the minidump. This can be passed to another process, e.g. over a Unix
+    /// socket, by crash handlers which can't write to the filesystem.
+    ///
+    /// The file is created with `memfd_create`, falling back to an unlinked
+    /// temporary file where that isn't available.
+    pub fn dump_to_memfd(&mut self) -> Result<OwnedFd> {
+        use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+
+        let mut file = memfd_create(c"minidump", MemFdCreateFlag::MFD_CLOEXEC)
+            .map(std::fs::File::from)
+            .or_else(|_| tempfile::tempfile())
+            .map_err(FileWriterError::from)?;
+        self.dump_reserving(&mut file, reserve_file_space)?;
+        file.rewind().map_err(FileWriterError::from)?;
+        Ok(file.into())
     }
 
-    pub fn sanitize_stack(&mut self) -> &mut Self {
-        self.sanitize_stack = true; // Off by default
-        self
+    /// The current time, according to the clock passed to
+    /// [`Self::deterministic`] if any.
+    pub(crate) fn now(&self) -> SystemTime {
+        self.deterministic_clock
+            .as_ref()
+            .map_or_else(SystemTime::now, |clock| clock())
     }
 
-    /// Sets the timeout after `SIGSTOP` is sent to the process, if the process
-    /// has not stopped by the time the timeout has reached, we proceed with
-    /// minidump generation
-    pub fn stop_timeout(&mut self, duration: Duration) -> &mut Self {
-        self.stop_timeout = duration;
-        self
+    /// A rough estimate of the size of the minidump, from the number of threads
+    /// and modules, the size of the stacks and the memory captured besides, used to
+    /// allocate the space for it upfront. The contents of
+    /// [`Self::full_memory`] aren't accounted for, as they could be huge, and
+    /// the estimate is capped to [`MAX_SIZE_ESTIMATE`], as overestimating it
+    /// would waste memory rather than save reallocations.
+    fn estimate_dump_size(&self, dumper: &PtraceDumper) -> usize {
+        // The streams whose size doesn't depend on the process much, such as
+        // the system information or the contents of /proc files
+        const BASE_SIZE: usize = 64 * 1024;
+        // A thread, its context, its name and its entry in the other thread
+        // lists
+        const PER_THREAD_SIZE: usize =
+            std::mem::size_of::<MDRawThread>() + std::mem::size_of::<RawContextCPU>() + 128;
+        // A module, its name and its CodeView record
+        const PER_MODULE_SIZE: usize = std::mem::size_of::<MDRawModule>() + 256;
+        // The memory around the thread pointer, see
+        // Self::capture_thread_local_storage
+        const PER_THREAD_TLS_SIZE: usize = 8 * 1024;
+
+        // The stacks are bounded like when they are captured, see
+        // thread_list_stream::fill_thread_stack
+        let stacks = dumper
+            .threads
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, thread)| Some((thread.tid, dumper.captured_thread_info(idx)?)))
+            .filter(|(_, info)| {
+                self.include_sanitizer_shadow
+                    || !dumper
+                        .find_stack_mapping(info.stack_pointer)
+                        .is_some_and(MappingInfo::is_sanitizer_shadow)
+            })
+            .filter_map(|(tid, info)| {
+                let (valid_stack_ptr, stack_len) = dumper
+                    .get_stack_info_with_red_zone(info.stack_pointer, self.stack_red_zone)
+                    .ok()?;
+                let stack_len = match self.max_stack_bytes(tid) {
+                    Some(max_bytes) => stack_len.min(
+                        info.stack_pointer
+                            .saturating_sub(valid_stack_ptr)
+                            .saturating_add(max_bytes),
+                    ),
+                    None => stack_len,
+                };
+                Some(stack_len.min(self.capture_limits.max_region_size))
+            })
+            .fold(0, usize::saturating_add);
+        let app_memory = self
+            .app_memory
+            .iter()
+            .chain(&self.app_memory64)
+            .map(|app_memory| app_memory.length)
+            .chain(self.referenced_memory_budget)
+            .chain(self.register_memory_budget)
+            .chain(self.module_images_budget)
+            .fold(0, usize::saturating_add);
+        let user_streams = self
+            .user_streams
+            .iter()
+            .map(|stream| match &stream.data {
+                UserStreamData::Bytes(bytes) => bytes.len(),
+                UserStreamData::Deferred(_) => 0,
+            })
+            .fold(0, usize::saturating_add);
+
+        let threads = dumper.threads.len();
+        let tls = if self.capture_thread_local_storage {
+            threads.saturating_mul(PER_THREAD_TLS_SIZE)
+        } else {
+            0
+        };
+        let ip_memory = threads.saturating_mul(self.thread_ip_memory_window.unwrap_or(0));
+
+        let estimate = [
+            BASE_SIZE,
+            threads.saturating_mul(PER_THREAD_SIZE),
+            tls,
+            ip_memory,
+            stacks,
+            dumper.mappings.len().saturating_mul(PER_MODULE_SIZE),
+            app_memory,
+            user_streams,
+        ]
+        .into_iter()
+        .fold(0, usize::saturating_add);
+        let estimate = self
+            .max_dump_size
+            .map_or(estimate, |max| estimate.min(max as usize));
+        estimate.min(MAX_SIZE_ESTIMATE)
     }
 
-    /// Directly set important Auxv info determined by the crashing process
-    ///
-    /// Since `/proc/{pid}/auxv` can sometimes be inaccessible, the calling process should prefer to transfer this
-    /// information directly using the Linux `getauxval()` call (if possible).
-    ///
-    /// Any field that is set to `0` will be considered unset. In that case, minidump-writer might try other techniques
-    /// to obtain it (like reading `/proc/{pid}/auxv`).
-    pub fn set_direct_auxv_dump_info(
-        &mut self,
-        direct_auxv_dump_info: DirectAuxvDumpInfo,
-    ) -> &mut Self {
-        self.direct_auxv_dump_info = Some(direct_auxv_dump_info);
-        self
+    /// How many bytes of the stack of `tid` to capture past its stack pointer
+    /// at most, see [`Self::set_max_stack_bytes_per_thread`].
+    pub(crate) fn max_stack_bytes(&self, tid: Pid) -> Option<usize> {
+        if tid == self.blamed_thread {
+            self.max_crashing_stack_bytes
+        } else {
+            self.max_stack_bytes
+        }
     }
 
-    /// Generates a minidump and writes to the destination provided. Returns the in-memory
-    /// version of the minidump as well.
-    pub fn dump(&mut self, destination: &mut (impl Write + Seek)) -> Result<Vec<u8>> {
-        let auxv = self
-            .direct_auxv_dump_info
-            .clone()
-            .map(AuxvDumpInfo::from)
-            .unwrap_or_default();
-        let mut dumper = PtraceDumper::new(self.process_id, self.stop_timeout, auxv)?;
-        dumper.suspend_threads()?;
-        dumper.late_init()?;
+    /// Edit `bytes`, read from the process at `start`, before they are
+    /// written: scrub personal data if [`Self::scrub_pii`] is set, call the
+    /// [`Self::set_memory_redactor`] callback, then zero the memory denied by
+    /// [`Self::set_memory_denylist`].
+    pub(crate) fn redact_memory(&self, start: usize, bytes: &mut [u8]) {
+        if self.scrub_pii {
+            pii_scrubber::scrub(start, bytes);
+        }
+        if let Some(redactor) = &self.memory_redactor {
+            redactor(start..start + bytes.len(), bytes);
+        }
+        memory_denylist::blank(&self.denied_ranges, start, bytes);
+    }
 
-        if self.skip_stacks_if_mapping_unreferenced {
-            if let Some(address) = self.principal_mapping_address {
-                self.principal_mapping = dumper.find_mapping_no_bias(address).cloned();
-            }
+    /// Returns whether one more region can be added to the memory list, see
+    /// [`Self::set_max_memory_regions`].
+    pub(crate) fn fits_in_max_memory_regions(&self) -> bool {
+        self.max_memory_regions
+            .is_none_or(|max| self.memory_blocks.len() < max)
+    }
 
-            if !self.crash_thread_references_principal_mapping(&dumper) {
-                return Err(InitError::PrincipalMappingNotReferenced.into());
-            }
+    /// Record that the sanitizer shadow memory of `mapping` was left out, once
+    /// per mapping, see [`Self::include_sanitizer_shadow`].
+    pub(crate) fn note_sanitizer_shadow(&mut self, mapping: &MappingInfo) {
+        let start = mapping.start_address;
+        let noted = self.soft_errors.iter().any(
+            |error| matches!(error, SoftError::SanitizerShadowOmitted(noted, _) if *noted == start),
+        );
+        if !noted {
+            self.soft_errors
+                .push(SoftError::SanitizerShadowOmitted(start, mapping.size));
         }
+    }
 
-        let mut buffer = Buffer::with_capacity(0);
-        self.generate_dump(&mut buffer, &mut dumper, destination)?;
+    /// Returns whether `len` more bytes can be written to `buffer` while
+    /// leaving room for the streams which are never dropped, see
+    /// [`Self::set_max_dump_size`].
+    pub(crate) fn fits_in_max_dump_size(&self, buffer: &DumpBuf, len: usize) -> bool {
+        self.max_dump_size
+            .is_none_or(|max| buffer.position() + len as u64 + DUMP_SIZE_RESERVE <= max)
+    }
 
-        // dumper would resume threads in drop() automatically,
-        // but in case there is an error, we want to catch it
-        dumper.resume_threads()?;
+    /// Record a failed copy of the memory of the process as a secondary fault,
+    /// see [`SecondaryFault`], so that dumping can go on. The error is
+    /// returned instead if the process is gone, or if it isn't a copy error.
+    pub(crate) fn record_secondary_fault(
+        &mut self,
+        error: DumperError,
+    ) -> std::result::Result<(), DumperError> {
+        match error {
+            DumperError::CopyFromProcessError(e) if e.source != nix::Error::ESRCH => {
+                log::warn!("{e}, recording it as a secondary fault");
+                self.secondary_faults.push(SecondaryFault {
+                    address: e.src + e.offset,
+                    length: e.length,
+                    error: e.source,
+                });
+                Ok(())
+            }
+            error => Err(error),
+        }
+    }
 
-        Ok(buffer.into())
+    /// The registers of the blamed thread which were given to the writer,
+    /// either explicitly or through the crash context, rather than read from
+    /// the thread.
+    pub(crate) fn supplied_blamed_thread_context(&self) -> Option<RawContextCPU> {
+        if let Some(context) = &self.blamed_thread_context {
+            return Some(context.clone());
+        }
+        let crash_context = self.crash_context.as_ref()?;
+        let mut cpu = RawContextCPU::default();
+        crash_context.fill_cpu_context(&mut cpu);
+        Some(cpu)
+    }
+
+    /// The instruction and stack pointers of
+    /// [`Self::supplied_blamed_thread_context`].
+    pub(crate) fn supplied_blamed_thread_pointers(&self) -> Option<(usize, usize)> {
+        match &self.blamed_thread_context {
+            Some(context) => Some(crash_context::context_pointers(context)),
+            None => self.crash_context.as_ref().map(|crash_context| {
+                (
+                    crash_context.get_instruction_pointer(),
+                    crash_context.get_stack_pointer(),
+                )
+            }),
+        }
     }
 
     fn crash_thread_references_principal_mapping(&self, dumper: &PtraceDumper) -> bool {
-        if self.crash_context.is_none() || self.principal_mapping.is_none() {
+        let Some((pc, stack_pointer)) = self.supplied_blamed_thread_pointers() else {
+            return false;
+        };
+        if self.principal_mapping.is_none() {
             return false;
         }
 
@@ -186,17 +1726,16 @@ impl MinidumpWriter {
             .system_mapping_info
             .end_address;
 
-        let pc = self
-            .crash_context
-            .as_ref()
-            .unwrap()
-            .get_instruction_pointer();
-        let stack_pointer = self.crash_context.as_ref().unwrap().get_stack_pointer();
-
         if pc >= low_addr && pc < high_addr {
             return true;
         }
 
+        if let Some(mapping) = dumper.find_stack_mapping(stack_pointer) {
+            if mapping.is_sanitizer_shadow() && !self.include_sanitizer_shadow {
+                return false;
+            }
+        }
+
         let (valid_stack_pointer, stack_len) = match dumper.get_stack_info(stack_pointer) {
             Ok(x) => x,
             Err(_) => {
@@ -227,26 +1766,20 @@ impl MinidumpWriter {
         buffer: &mut DumpBuf,
         dumper: &mut PtraceDumper,
         destination: &mut (impl Write + Seek),
-    ) -> Result<()> {
-        // A minidump file contains a number of tagged streams. This is the number
-        // of streams which we write.
-        let num_writers = 17u32;
-
+    ) -> Result<Vec<MDRawDirectory>> {
         let mut header_section = MemoryWriter::<MDRawHeader>::alloc(buffer)?;
 
-        let mut dir_section = DirSection::new(buffer, num_writers, destination)?;
+        let mut dir_section = DirSection::new(buffer, self.stream_count(), destination)?;
 
         let header = MDRawHeader {
             signature: MD_HEADER_SIGNATURE,
             version: MD_HEADER_VERSION,
-            stream_count: num_writers,
+            stream_count: self.stream_count(),
             //   header.get()->stream_directory_rva = dir.position();
             stream_directory_rva: dir_section.position(),
             checksum: 0, /* Can be 0.  In fact, that's all that's
                           * been found in minidump files. */
-            time_date_stamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)?
-                .as_secs() as u32, // TODO: This is not Y2038 safe, but thats how its currently defined as
+            time_date_stamp: self.now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as u32, // TODO: This is not Y2038 safe, but thats how its currently defined as
             flags: 0,
         };
         header_section.set_value(buffer, header)?;
@@ -255,122 +1788,659 @@ impl MinidumpWriter {
         // we should have a mostly-intact dump
         dir_section.write_to_file(buffer, None)?;
 
+        match self.write_streams(buffer, dumper, &mut dir_section) {
+            Err(error) if error.code() == ErrorCode::Cancelled => match self.check_cancelled() {
+                Err(reason @ CancelledError::DeadlineExceeded) => {
+                    self.finish_truncated(buffer, &mut dir_section, reason)?
+                }
+                // Report the cancellation rather than the section it stopped
+                Err(reason) => return Err(reason.into()),
+                Ok(()) => return Err(error),
+            },
+            result => result?,
+        }
+        Ok(dir_section.dir_entries(buffer))
+    }
+
+    fn write_streams(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dumper: &mut PtraceDumper,
+        dir_section: &mut DirSection<'_, impl Write + Seek>,
+    ) -> Result<()> {
+        let start = Instant::now();
         let dirent = thread_list_stream::write(self, buffer, dumper)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // This must happen before the module list is written, as it adds
+        // synthetic modules for the JIT code it finds
+        self.write_optional("JitMemory", |config| {
+            jit_memory::write(config, buffer, dumper)
+        })?;
+        dir_section.write_to_file(buffer, None)?;
+
+        // The modules of an emulated process which the thread contexts don't
+        // match are left out of the module list
+        self.emulation = Emulation::detect(dumper);
+        let dirent = self.write_selected(
+            MDStreamType::ModuleListStream as u32,
+            "ModuleList",
+            |config| mappings::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxJitSymbols as u32,
+            "JitSymbols",
+            |config| jit_symbols_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxEmulation as u32,
+            "Emulation",
+            |config| emulation_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        let dirent = mappings::write(self, buffer, dumper)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        // Minimal dumps only hold what's needed to walk the crashing stack,
+        // see Self::minimal
+        let dirent = if self.minimal {
+            Default::default()
+        } else {
+            self.write_selected(
+                MDExtraStreamType::LinuxGoBuildInfo as u32,
+                "GoBuildInfo",
+                |_| go_build_info_stream::write(buffer, dumper),
+            )?
+        };
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = if self.minimal {
+            Default::default()
+        } else {
+            self.write_selected(
+                MDExtraStreamType::LinuxReplacedModules as u32,
+                "ReplacedModules",
+                |_| replaced_modules_stream::write(buffer, dumper),
+            )?
+        };
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = if self.minimal {
+            Default::default()
+        } else {
+            self.write_selected(
+                MDStreamType::UnloadedModuleListStream as u32,
+                "UnloadedModuleList",
+                |config| unloaded_modules::write(buffer, &config.unloaded_modules),
+            )?
+        };
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = if self.minimal {
+            Default::default()
+        } else {
+            self.write_selected(
+                MDStreamType::CrashpadInfoStream as u32,
+                "CrashpadInfo",
+                |config| crashpad_info_stream::write(config, buffer, dumper),
+            )?
+        };
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        app_memory::write(self, buffer)?;
+        self.write_optional("AlternateSignalStack", |config| {
+            alternate_signal_stack::write(config, buffer, dumper)
+        })?;
+        if !self.minimal {
+            self.write_optional("FaultAddressMemory", |config| {
+                fault_address_memory::write(config, buffer, dumper)
+            })?;
+            self.write_optional("RegisterMemory", |config| {
+                register_memory::write(config, buffer, dumper)
+            })?;
+            self.write_optional("AppMemory", |config| {
+                app_memory::write(config, buffer, dumper)
+            })?;
+            self.write_optional("ThreadLocalStorage", |config| {
+                thread_local_storage::write(config, buffer, dumper)
+            })?;
+            self.write_optional("ReferencedMemory", |config| {
+                referenced_memory::write(config, buffer, dumper)
+            })?;
+        }
         dir_section.write_to_file(buffer, None)?;
 
         let dirent = memory_list_stream::write(self, buffer)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
         let dirent = exception_stream::write(self, buffer)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDStreamType::AssertionInfoStream as u32,
+            "AssertionInfo",
+            |config| assertion_info_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
         let dirent = systeminfo_stream::write(buffer)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        let dirent = memory_info_list_stream::write(self, buffer)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        // The remaining directory entries are left empty in minimal dumps
+        if self.minimal {
+            let dirent = self.write_soft_errors(buffer)?;
+            self.write_stream(dir_section, buffer, dumper, dirent)?;
+            return Ok(());
+        }
 
-        let dirent = match self.write_file(buffer, "/proc/cpuinfo") {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxCpuInfo as u32,
-                location,
-            },
-            Err(_) => Default::default(),
-        };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        let dirent =
+            self.write_selected(MDStreamType::MiscInfoStream as u32, "MiscInfo", |config| {
+                misc_info_stream::write(config, buffer)
+            })?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/status", self.blamed_thread))
-        {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxProcStatus as u32,
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxDumpTimestamps as u32,
+            "Timestamps",
+            |config| timestamps_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDStreamType::MemoryInfoListStream as u32,
+            "MemoryInfoList",
+            |config| memory_info_list_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxHeapSummary as u32,
+            "HeapSummary",
+            |config| heap_summary_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxCpuInfo as u32,
+            &["/proc/cpuinfo"],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // The blamed thread may have exited, or may not belong to the process
+        // at all, fall back to the status of the process itself
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxProcStatus as u32,
+            &[
+                &format!("/proc/{}/status", self.blamed_thread),
+                &format!("/proc/{}/status", self.process_id),
+            ],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // System-wide memory pressure at the time of the crash, which helps
+        // telling apart allocation failures from genuine bugs
+        let dirent = self.write_file_stream(
+            buffer,
+            MDExtraStreamType::LinuxMemInfo as u32,
+            &["/proc/meminfo"],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxLsbRelease as u32,
+            &["/etc/lsb-release", "/etc/os-release"],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxCmdLine as u32,
+            &[&format!("/proc/{}/cmdline", self.blamed_thread)],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxEnviron as u32,
+            &[&format!("/proc/{}/environ", self.blamed_thread)],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::LinuxAuxv as u32,
+            &[&format!("/proc/{}/auxv", self.blamed_thread)],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = match self.write_maps(buffer) {
+            Ok(Some(location)) => MDRawDirectory {
+                stream_type: MDStreamType::LinuxMaps as u32,
                 location,
             },
-            Err(_) => Default::default(),
+            _ => Default::default(),
         };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        let dirent = match self
-            .write_file(buffer, "/etc/lsb-release")
-            .or_else(|_| self.write_file(buffer, "/etc/os-release"))
+        let dirent = if self
+            .stream_selection
+            .selects(MDStreamType::LinuxDsoDebug as u32)
         {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxLsbRelease as u32,
-                location,
-            },
-            Err(_) => Default::default(),
+            dso_debug::write_dso_debug_stream(
+                buffer,
+                self.process_id,
+                &dumper.auxv,
+                &self.capture_limits,
+            )
+            .unwrap_or_else(|error| {
+                if let SectionDsoDebugError::CaptureLimitExceeded(error) = error {
+                    log::warn!("{error}");
+                    self.soft_errors
+                        .push(SoftError::CaptureLimitExceeded(error));
+                }
+                Default::default()
+            })
+        } else {
+            Default::default()
         };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/cmdline", self.blamed_thread))
-        {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxCmdLine as u32,
-                location,
-            },
-            Err(_) => Default::default(),
+        let dirent = self.write_file_stream(
+            buffer,
+            MDStreamType::MozLinuxLimits as u32,
+            &[&format!("/proc/{}/limits", self.blamed_thread)],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // IO accounting may be disabled in the kernel, or restricted to the
+        // process owner, in which case we just leave the entry empty
+        let dirent = self.write_file_stream(
+            buffer,
+            MDExtraStreamType::LinuxProcIo as u32,
+            &[&format!("/proc/{}/io", self.process_id)],
+        );
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxSecurityContext as u32,
+            "SecurityContext",
+            |config| security_context_stream::write(buffer, config.process_id),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxNamespaces as u32,
+            "Namespaces",
+            |config| namespaces_stream::write(buffer, config.process_id),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent =
+            self.write_selected(MDExtraStreamType::HostIds as u32, "HostIds", |config| {
+                host_ids::write(buffer, &config.host_ids)
+            })?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDStreamType::ThreadNamesStream as u32,
+            "ThreadNames",
+            |_| thread_names_stream::write(buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDStreamType::ThreadInfoListStream as u32,
+            "ThreadInfoList",
+            |config| thread_info_list_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadCpus as u32,
+            "ThreadCpus",
+            |config| thread_info_list_stream::write_cpus(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadStops as u32,
+            "ThreadStops",
+            |_| thread_info_list_stream::write_stops(buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadTlsRegisters as u32,
+            "ThreadTlsRegisters",
+            |_| thread_info_list_stream::write_tls_registers(buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxBacktraces as u32,
+            "Backtraces",
+            |config| backtraces_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadSignals as u32,
+            "ThreadSignals",
+            |config| thread_signals_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::SignalDispositions as u32,
+            "SignalDispositions",
+            |config| signal_dispositions_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent =
+            self.write_selected(MDExtraStreamType::GpuInfo as u32, "GpuInfo", |config| {
+                gpu_info::write(buffer, &config.gpu_info)
+            })?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::CrashReason as u32,
+            "CrashReason",
+            |config| crash_reason::write(buffer, config.crash_reason.as_deref()),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent =
+            self.write_selected(MDExtraStreamType::LogLines as u32, "LogLines", |config| {
+                log_ring_buffer::write(buffer, dumper, &config.log_ring_buffers, |start, bytes| {
+                    config.redact_memory(start, bytes)
+                })
+            })?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxRegisteredBlocks as u32,
+            "RegisteredBlocks",
+            |config| registry_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadWaitStates as u32,
+            "ThreadWaitStates",
+            |config| thread_wait_states_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxThreadStacks as u32,
+            "ThreadStacks",
+            |config| thread_stacks_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxShadowStacks as u32,
+            "ShadowStacks",
+            |config| thread_stacks_stream::write_shadow_stacks(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent =
+            self.write_selected(MDExtraStreamType::LinuxXState as u32, "XState", |config| {
+                xstate_stream::write(config, buffer)
+            })?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxExploitability as u32,
+            "Exploitability",
+            |config| exploitability_stream::write(config, buffer, dumper),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        let dirent = self.write_selected(
+            MDStreamType::HandleDataStream as u32,
+            "HandleData",
+            |config| handle_data_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        for idx in 0..self.user_streams.len() {
+            let stream_type = self.user_streams[idx].stream_type;
+            let dirent = self.write_selected(stream_type, "UserStream", |config| {
+                user_streams::write(config, buffer, idx)
+            })?;
+            self.write_stream(dir_section, buffer, dumper, dirent)?;
+        }
+
+        let streams_duration = start.elapsed();
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxWriterInfo as u32,
+            "WriterInfo",
+            |config| writer_info_stream::write(config, buffer, streams_duration),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        self.write_optional("ModuleImages", module_images::collect)?;
+        // The memory of this stream is appended once everything else has
+        // been written, see memory64_list_stream::Memory64List
+        let memory64_list = memory64_list_stream::Memory64List::alloc(self, buffer, dumper)?;
+        dir_section.write_to_file(buffer, None)?;
+
+        let dirent = self.write_selected(
+            MDExtraStreamType::LinuxSecondaryExceptions as u32,
+            "SecondaryExceptions",
+            |config| secondary_exceptions_stream::write(config, buffer),
+        )?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // This must come last so that it includes the soft errors of all the
+        // other streams
+        let dirent = self.write_soft_errors(buffer)?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // The header and descriptors were written to the file empty
+        let dirent = memory64_list.write(self, buffer, dumper)?;
+        dir_section.rewrite(buffer, dirent.location)?;
+        self.write_stream(dir_section, buffer, dumper, dirent)?;
+
+        // If you add more directory entries, don't forget to update NUM_STREAMS.
+        Ok(())
+    }
+
+    /// Finish the minidump once its deadline has passed, with the streams
+    /// written so far, so that it still identifies the crash. The exception
+    /// and system information streams are added if they are missing, and the
+    /// dump is marked as truncated with a [`SoftError::Truncated`] and the
+    /// [`TRUNCATED_ANNOTATION`] annotation.
+    fn finish_truncated(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dir_section: &mut DirSection<'_, impl Write + Seek>,
+        reason: CancelledError,
+    ) -> Result<()> {
+        log::warn!("finishing the minidump early: {reason}");
+        self.soft_errors.push(SoftError::Truncated(reason));
+
+        let missing = |dir_section: &DirSection<'_, _>, buffer: &DumpBuf, stream_type| {
+            dir_section
+                .find_dir_entry(buffer, stream_type as u32)
+                .is_none()
         };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        if missing(dir_section, buffer, MDStreamType::ExceptionStream) {
+            let dirent = exception_stream::write(self, buffer)?;
+            self.finish_stream(buffer, dir_section, dirent)?;
+        }
+        if missing(dir_section, buffer, MDStreamType::SystemInfoStream) {
+            let dirent = systeminfo_stream::write(buffer)?;
+            self.finish_stream(buffer, dir_section, dirent)?;
+        }
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/environ", self.blamed_thread))
+        if self
+            .stream_selection
+            .selects(MDStreamType::CrashpadInfoStream as u32)
         {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxEnviron as u32,
-                location,
-            },
-            Err(_) => Default::default(),
-        };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+            let previous = dir_section
+                .find_dir_entry(buffer, MDStreamType::CrashpadInfoStream as u32)
+                .map(|(_, dirent)| dirent);
+            let dirent =
+                crashpad_info_stream::write_truncated(self, buffer, previous, &reason.to_string())?;
+            self.finish_stream(buffer, dir_section, dirent)?;
+        }
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/auxv", self.blamed_thread)) {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxAuxv as u32,
-                location,
-            },
-            Err(_) => Default::default(),
-        };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+        let dirent = self.write_soft_errors(buffer)?;
+        if dirent.stream_type != 0 {
+            self.finish_stream(buffer, dir_section, dirent)?;
+        }
+        Ok(())
+    }
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/maps", self.blamed_thread)) {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::LinuxMaps as u32,
-                location,
-            },
-            Err(_) => Default::default(),
-        };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+    /// Write `dirent` in place of the stream of the same type if one was
+    /// written already, or to the next free directory entry otherwise. Unlike
+    /// [`Self::write_stream`] this doesn't stop once the dump is cancelled.
+    fn finish_stream(
+        &self,
+        buffer: &mut DumpBuf,
+        dir_section: &mut DirSection<'_, impl Write + Seek>,
+        dirent: MDRawDirectory,
+    ) -> Result<()> {
+        match dir_section.find_dir_entry(buffer, dirent.stream_type) {
+            Some((idx, _)) => dir_section.replace_dir_entry(buffer, idx, dirent)?,
+            None if dir_section.entries_written() < self.stream_count() => {
+                dir_section.dump_dir_entry(buffer, dirent)?;
+            }
+            None => log::warn!("no room left for stream {}", dirent.stream_type),
+        }
+        dir_section.write_to_file(buffer, None)?;
+        Ok(())
+    }
 
-        let dirent = dso_debug::write_dso_debug_stream(buffer, self.process_id, &dumper.auxv)
-            .unwrap_or_default();
-        dir_section.write_to_file(buffer, Some(dirent))?;
+    /// Write a part of the minidump which it can do without with `write`. If
+    /// it fails it is left out, which is recorded as a soft error, rather
+    /// than failing the whole dump, unless the dump was cancelled.
+    pub(crate) fn write_optional<T: Default, E: Into<WriterError>>(
+        &mut self,
+        section: &'static str,
+        write: impl FnOnce(&mut Self) -> std::result::Result<T, E>,
+    ) -> Result<T> {
+        match write(self).map_err(Into::into) {
+            Ok(written) => Ok(written),
+            Err(error) if error.code() == ErrorCode::Cancelled => Err(error),
+            Err(error) => {
+                log::warn!("leaving out {section}: {error}");
+                self.omitted_sections.push(section);
+                self.soft_errors
+                    .push(SoftError::SectionOmitted(section, Box::new(error)));
+                Ok(T::default())
+            }
+        }
+    }
+
+    /// Write the stream of type `stream_type` with `write`, like
+    /// [`Self::write_optional`]. Nothing is written if the stream isn't
+    /// selected, see [`Self::set_stream_selection`].
+    fn write_selected<E: Into<WriterError>>(
+        &mut self,
+        stream_type: u32,
+        section: &'static str,
+        write: impl FnOnce(&mut Self) -> std::result::Result<MDRawDirectory, E>,
+    ) -> Result<MDRawDirectory> {
+        if self.stream_selection.selects(stream_type) {
+            self.write_optional(section, write)
+        } else {
+            Ok(Default::default())
+        }
+    }
 
-        let dirent = match self.write_file(buffer, &format!("/proc/{}/limits", self.blamed_thread))
+    /// Write the soft errors, unless their stream isn't selected
+    fn write_soft_errors(&mut self, buffer: &mut DumpBuf) -> Result<MDRawDirectory> {
+        if self
+            .stream_selection
+            .selects(MDExtraStreamType::LinuxSoftErrors as u32)
         {
-            Ok(location) => MDRawDirectory {
-                stream_type: MDStreamType::MozLinuxLimits as u32,
-                location,
-            },
-            Err(_) => Default::default(),
-        };
-        dir_section.write_to_file(buffer, Some(dirent))?;
+            Ok(soft_errors_stream::write(self, buffer)?)
+        } else {
+            Ok(Default::default())
+        }
+    }
 
-        let dirent = thread_names_stream::write(buffer, dumper)?;
-        dir_section.write_to_file(buffer, Some(dirent))?;
+    /// The number of streams in the directory, ours and the application's.
+    fn stream_count(&self) -> u32 {
+        NUM_STREAMS + self.user_streams.len() as u32
+    }
 
-        // This section is optional, so we ignore errors when writing it
-        if let Ok(dirent) = handle_data_stream::write(self, buffer) {
-            let _ = dir_section.write_to_file(buffer, Some(dirent));
+    /// Write `dirent` to the directory, along with everything added to the
+    /// buffer since the previous stream, once the stream hooks have seen it,
+    /// and report it to the progress callback.
+    fn write_stream(
+        &mut self,
+        dir_section: &mut DirSection<'_, impl Write + Seek>,
+        buffer: &mut DumpBuf,
+        dumper: &PtraceDumper,
+        mut dirent: MDRawDirectory,
+    ) -> Result<()> {
+        if dirent.stream_type != 0 {
+            for hook in &mut self.stream_hooks {
+                if !hook.before_write(&mut dirent, buffer, dumper) {
+                    dirent = MDRawDirectory::default();
+                    break;
+                }
+            }
+        }
+        dir_section.write_to_file(buffer, Some(dirent.clone()))?;
+        if dirent.stream_type != 0 {
+            for hook in &mut self.stream_hooks {
+                hook.after_write(&dirent, buffer, dumper);
+            }
         }
+        self.check_cancelled()?;
+        self.report_progress(DumpProgress {
+            phase: DumpPhase::StreamWritten {
+                stream_type: dirent.stream_type,
+            },
+            threads: dumper.threads.len(),
+            streams_written: dir_section.entries_written(),
+            stream_count: self.stream_count(),
+            bytes_written: buffer.position(),
+        });
+        Ok(())
+    }
 
-        // If you add more directory entries, don't forget to update num_writers, above.
+    /// Whether the dump should stop, see [`Self::set_cancellation_token`],
+    /// [`Self::set_deadline`] and [`Self::set_timeout`].
+    pub(crate) fn check_cancelled(&self) -> std::result::Result<(), CancelledError> {
+        if self
+            .cancellation_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(CancelledError::Cancelled);
+        }
+        let timed_out = self
+            .timeout
+            .zip(self.dump_start)
+            .is_some_and(|(timeout, start)| start.elapsed() >= timeout);
+        if timed_out
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(CancelledError::DeadlineExceeded);
+        }
         Ok(())
     }
 
+    fn report_progress(&mut self, progress: DumpProgress) {
+        if let Some(callback) = &mut self.progress_callback {
+            callback(&progress);
+        }
+    }
+
     #[allow(clippy::unused_self)]
     fn write_file(
         &self,
@@ -379,7 +2449,74 @@ impl MinidumpWriter {
     ) -> std::result::Result<MDLocationDescriptor, MemoryWriterError> {
         let content = std::fs::read(filename)?;
 
-        let section = MemoryArrayWriter::write_bytes(buffer, &content);
+        let section = MemoryArrayWriter::write_bytes(buffer, &content)?;
         Ok(section.location())
     }
+
+    /// Write the contents of the first of `paths` which can be read, as the
+    /// stream of type `stream_type`. Its directory entry is left empty if
+    /// none can be read, or if the stream isn't selected, see
+    /// [`Self::set_stream_selection`].
+    fn write_file_stream(
+        &self,
+        buffer: &mut DumpBuf,
+        stream_type: u32,
+        paths: &[&str],
+    ) -> MDRawDirectory {
+        if !self.stream_selection.selects(stream_type) {
+            return Default::default();
+        }
+        paths
+            .iter()
+            .find_map(|path| self.write_file(buffer, path).ok())
+            .map_or_else(Default::default, |location| MDRawDirectory {
+                stream_type,
+                location,
+            })
+    }
+
+    /// Write `/proc/<pid>/maps`, with the stack mappings of the threads
+    /// labeled, see [`thread_stacks_stream::label_maps`]. Nothing is written
+    /// if the stream isn't selected.
+    fn write_maps(
+        &self,
+        buffer: &mut DumpBuf,
+    ) -> std::result::Result<Option<MDLocationDescriptor>, MemoryWriterError> {
+        if !self
+            .stream_selection
+            .selects(MDStreamType::LinuxMaps as u32)
+        {
+            return Ok(None);
+        }
+        let maps = std::fs::read(format!("/proc/{}/maps", self.blamed_thread))?;
+        let maps = thread_stacks_stream::label_maps(&maps, &self.thread_stacks, self.process_id);
+
+        let section = MemoryArrayWriter::write_bytes(buffer, &maps)?;
+        Ok(Some(section.location()))
+    }
+}
+
+/// Allocate `len` bytes after the current position of `file`, without changing
+/// its size, so that writing the minidump doesn't have to extend it as it
+/// goes. Failing to do so isn't a problem, the file is extended as usual.
+fn reserve_file_space(file: &mut std::fs::File, len: usize) {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::fcntl::{fallocate, FallocateFlags};
+        use std::os::fd::AsRawFd;
+
+        let Ok(offset) = file.stream_position() else {
+            return;
+        };
+        if let Err(e) = fallocate(
+            file.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        ) {
+            log::debug!("failed to reserve {len} bytes for the minidump: {e}");
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, len);
 }
@@ -0,0 +1,225 @@
+//! A destination writing to a file through a memory map, for large minidumps.
+//!
+//! Writing a minidump with [`MinidumpWriter::full_memory`] can mean copying
+//! gigabytes to the destination. Through a memory map the copy goes straight
+//! to the page cache, without a system call per write, and the file is grown
+//! in large steps rather than block by block.
+
+use crate::{
+    builder::DumpResult,
+    dir_section::FileWriterError,
+    linux::{
+        errors::WriterError,
+        minidump_writer::{FlushMode, MinidumpWriter},
+    },
+};
+use memmap2::{MmapMut, MmapOptions};
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Seek, SeekFrom, Write},
+};
+
+/// The file is grown by at least this much at once
+const MIN_GROWTH: u64 = 1024 * 1024;
+
+/// A [`Write`] + [`Seek`] destination backed by a memory map of a file, which
+/// must be opened for both reading and writing.
+///
+/// Writing starts at the current position of the file. The file is extended
+/// as needed, possibly past what was written, and truncated back to the end of
+/// the written data by [`MmapWriter::finish`], or when the writer is dropped.
+pub struct MmapWriter<'a> {
+    file: &'a File,
+    map: Option<MmapMut>,
+    /// The size of the file, and of the map
+    capacity: u64,
+    position: u64,
+    /// The end of what was written, or of the original contents of the file
+    len: u64,
+}
+
+impl<'a> MmapWriter<'a> {
+    pub fn new(file: &'a mut File) -> std::io::Result<Self> {
+        let position = file.stream_position()?;
+        let len = file.metadata()?.len();
+        let mut writer = Self {
+            file,
+            map: None,
+            capacity: len,
+            position,
+            len,
+        };
+        writer.remap()?;
+        Ok(writer)
+    }
+
+    /// Make room for `additional` bytes past the current position upfront.
+    pub fn reserve(&mut self, additional: usize) -> std::io::Result<()> {
+        self.grow_to(self.position.saturating_add(additional as u64), false)
+    }
+
+    /// Truncate the file to the end of the data written, leaving the position
+    /// of the file where the writer's was, and write the changes to the
+    /// storage device if `sync` is set.
+    pub fn finish(mut self, sync: bool) -> std::io::Result<()> {
+        if sync {
+            if let Some(map) = &self.map {
+                map.flush()?;
+            }
+        }
+        self.truncate()?;
+        let mut file = self.file;
+        file.seek(SeekFrom::Start(self.position))?;
+        if sync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn grow_to(&mut self, needed: u64, amortize: bool) -> std::io::Result<()> {
+        if needed <= self.capacity {
+            return Ok(());
+        }
+
+        let capacity = if amortize {
+            needed.max(self.capacity.saturating_mul(2)).max(MIN_GROWTH)
+        } else {
+            needed
+        };
+        self.map = None;
+        self.file.set_len(capacity)?;
+        self.capacity = capacity;
+        self.remap()
+    }
+
+    fn remap(&mut self) -> std::io::Result<()> {
+        let len = usize::try_from(self.capacity)
+            .map_err(|_| Error::new(ErrorKind::OutOfMemory, "file too large to be mapped"))?;
+        // Empty files can't be mapped
+        if len == 0 {
+            self.map = None;
+            return Ok(());
+        }
+
+        // SAFETY: the file is borrowed mutably for the lifetime of the writer,
+        // so nothing else in this process can change it. Other processes
+        // could, like with any other shared mapping, but the map is only
+        // written to, never read from.
+        self.map = Some(unsafe { MmapOptions::new().len(len).map_mut(self.file)? });
+        Ok(())
+    }
+
+    fn truncate(&mut self) -> std::io::Result<()> {
+        self.map = None;
+        if self.capacity != self.len {
+            self.file.set_len(self.len)?;
+            self.capacity = self.len;
+        }
+        Ok(())
+    }
+}
+
+impl Write for MmapWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let end = self.position + buf.len() as u64;
+        self.grow_to(end, true)?;
+        let map = self.map.as_mut().expect("non-empty files are mapped");
+        map[self.position as usize..end as usize].copy_from_slice(buf);
+        self.position = end;
+        self.len = self.len.max(end);
+        Ok(buf.len())
+    }
+
+    /// The data is visible to readers of the file as soon as it's written, so
+    /// there's nothing to flush, see [`MmapWriter::finish`] to sync it.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MmapWriter<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for MmapWriter<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.truncate() {
+            log::warn!("failed to truncate the minidump to {} bytes: {e}", self.len);
+        }
+    }
+}
+
+impl MinidumpWriter {
+    /// Generates a minidump and writes it to `file` through a memory map, see
+    /// [`crate::mmap_writer`]. This is faster than [`Self::dump_to_file`] for
+    /// large minidumps, typically with [`Self::full_memory`], but `file` must
+    /// be opened for both reading and writing.
+    pub fn dump_to_file_mapped(&mut self, file: &mut File) -> Result<DumpResult, WriterError> {
+        let mut writer = MmapWriter::new(file).map_err(FileWriterError::from)?;
+        let (result, _) = self.dump_reserving(&mut writer, |writer, len| {
+            if let Err(e) = writer.reserve(len) {
+                log::debug!("failed to reserve {len} bytes for the minidump: {e}");
+            }
+        })?;
+        writer
+            .finish(self.flush_mode == FlushMode::Sync)
+            .map_err(FileWriterError::from)?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_mmap_writer() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"head").unwrap();
+
+        let mut writer = MmapWriter::new(&mut file).unwrap();
+        writer.reserve(16).unwrap();
+        writer.write_all(&[1; 8]).unwrap();
+        writer.seek(SeekFrom::Start(6)).unwrap();
+        writer.write_all(&[2; 2]).unwrap();
+        assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), 12);
+        writer.write_all(&[3; 4]).unwrap();
+        assert!(writer.seek(SeekFrom::Current(-20)).is_err());
+        writer.finish(false).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 16);
+
+        let mut contents = Vec::new();
+        file.rewind().unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(
+            contents,
+            b"head\x01\x01\x02\x02\x01\x01\x01\x01\x03\x03\x03\x03"
+        );
+
+        // Growing past the reserved space, and truncating on drop
+        let mut file = tempfile::tempfile().unwrap();
+        {
+            let mut writer = MmapWriter::new(&mut file).unwrap();
+            writer.write_all(&vec![4; MIN_GROWTH as usize + 1]).unwrap();
+        }
+        assert_eq!(file.metadata().unwrap().len(), MIN_GROWTH + 1);
+    }
+}
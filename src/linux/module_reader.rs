@@ -1,3 +1,4 @@
+use crate::capture_limits::{self, CaptureLimits};
 use crate::errors::ModuleReaderError as Error;
 use crate::mem_reader::MemReader;
 use crate::minidump_format::GUID;
@@ -10,17 +11,34 @@ use std::{borrow::Cow, ffi::CStr};
 type Buf<'buf> = Cow<'buf, [u8]>;
 
 const NOTE_SECTION_NAME: &[u8] = b".note.gnu.build-id\0";
+const GO_NOTE_SECTION_NAME: &[u8] = b".note.go.buildid\0";
+const GO_BUILD_INFO_SECTION_NAME: &[u8] = b".go.buildinfo\0";
+/// The type of the note holding the Go build id, see `cmd/link/internal/ld/elf.go`
+const NT_GO_BUILD_ID: u32 = 4;
+const GO_BUILD_INFO_MAGIC: &[u8] = b"\xff Go buildinf:";
+/// The name and type of the note Crashpad adds to the modules it's linked in,
+/// see `client/crashpad_info_note.S`
+const CRASHPAD_NOTE_NAME: &str = "Crashpad";
+const NT_CRASHPAD_INFO: u32 = 0x4f464e49;
+/// The name and type of the `.note.package` note describing the distribution
+/// package a module comes from, see <https://systemd.io/ELF_PACKAGE_METADATA/>
+const PACKAGE_NOTE_NAME: &str = "FDO";
+const NT_FDO_PACKAGING_METADATA: u32 = 0xcafe1a7e;
 
 pub struct ProcessReader {
     inner: MemReader,
     start_address: u64,
+    /// The sizes read from the module's headers can't be trusted, reads
+    /// larger than this fail rather than allocating whatever they say
+    max_read: usize,
 }
 
 impl ProcessReader {
-    pub fn new(pid: i32, start_address: usize) -> Self {
+    pub fn new(pid: i32, start_address: usize, limits: &CaptureLimits) -> Self {
         Self {
             inner: MemReader::new(pid),
             start_address: start_address as u64,
+            max_read: limits.max_segment_size,
         }
     }
 }
@@ -55,6 +73,7 @@ impl<'buf> ProcessMemory<'buf> {
         match self {
             Self::Process(pr) => {
                 let error = |e| error(Some(pr.start_address), e);
+                capture_limits::check("module segment size", length, pr.max_read)?;
                 let len = std::num::NonZeroUsize::new(length as usize)
                     .ok_or_else(|| error(nix::Error::EINVAL))?;
                 let proc_offset = pr
@@ -224,6 +243,224 @@ impl<'a> Iterator for DynIter<'a> {
     }
 }
 
+/// The build id the Go toolchain embeds in Go binaries.
+///
+/// This is not a replacement for [`BuildId`], Go binaries usually lack a GNU
+/// build id note so their module identifier is generated from the text
+/// section like for any other such module.
+pub struct GoBuildId(pub String);
+
+impl ReadFromModule for GoBuildId {
+    fn read_from_module(module_memory: ProcessMemory<'_>) -> Result<Self, Error> {
+        let mut reader = ModuleReader::new(module_memory)?;
+        reader
+            .go_build_id_from_program_headers()
+            .or_else(|_| reader.go_build_id_from_section())
+            .map(GoBuildId)
+    }
+}
+
+/// The build information the Go toolchain embeds in Go binaries.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct GoBuildInfo {
+    /// The version of the Go toolchain, e.g. `go1.22.1`
+    pub go_version: String,
+    /// The path of the main module, e.g. `github.com/foo/bar`
+    pub module_path: Option<String>,
+    /// The version of the main module, `(devel)` for local builds
+    pub module_version: Option<String>,
+}
+
+impl GoBuildInfo {
+    /// Parse the contents of the `.go.buildinfo` section. Only the format used
+    /// since Go 1.18, where strings are stored inline, is supported.
+    fn parse(data: &[u8]) -> Option<Self> {
+        const HEADER_SIZE: usize = 32;
+        const FLAGS_OFFSET: usize = 15;
+        const FLAG_INLINE_STRINGS: u8 = 0x2;
+        // The module information is wrapped between two 16 byte sentinels
+        const MOD_INFO_SENTINEL_SIZE: usize = 16;
+
+        if !data.starts_with(GO_BUILD_INFO_MAGIC)
+            || data.len() < HEADER_SIZE
+            || data[FLAGS_OFFSET] & FLAG_INLINE_STRINGS == 0
+        {
+            return None;
+        }
+
+        let mut rest = &data[HEADER_SIZE..];
+        let go_version = String::from_utf8_lossy(read_go_string(&mut rest)?).into_owned();
+        let mod_info = read_go_string(&mut rest)
+            .and_then(|s| {
+                s.get(MOD_INFO_SENTINEL_SIZE..s.len().checked_sub(MOD_INFO_SENTINEL_SIZE)?)
+            })
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+
+        // The main module is described as `mod<TAB>path<TAB>version<TAB>sum`
+        let (module_path, module_version) = mod_info
+            .lines()
+            .find_map(|line| {
+                let mut fields = line.strip_prefix("mod\t")?.split('\t');
+                Some((fields.next()?.to_owned(), fields.next()?.to_owned()))
+            })
+            .unzip();
+
+        Some(Self {
+            go_version,
+            module_path,
+            module_version,
+        })
+    }
+}
+
+impl ReadFromModule for GoBuildInfo {
+    fn read_from_module(module_memory: ProcessMemory<'_>) -> Result<Self, Error> {
+        ModuleReader::new(module_memory)?.go_build_info()
+    }
+}
+
+/// Read a string prefixed by its length encoded as an unsigned LEB128 varint.
+fn read_go_string<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let mut len = 0u64;
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        len |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift >= 64 {
+            return None;
+        }
+    }
+
+    let len = usize::try_from(len).ok()?;
+    if len > data.len() {
+        return None;
+    }
+    let (s, rest) = data.split_at(len);
+    *data = rest;
+    Some(s)
+}
+
+/// The address of the `CrashpadInfo` structure of a module linked with the
+/// Crashpad client, relative to the start of the module.
+pub struct CrashpadInfoAddress(pub u64);
+
+impl ReadFromModule for CrashpadInfoAddress {
+    fn read_from_module(module_memory: ProcessMemory<'_>) -> Result<Self, Error> {
+        ModuleReader::new(module_memory)?
+            .crashpad_info_address()
+            .map(CrashpadInfoAddress)
+    }
+}
+
+/// The architecture the module was built for, the `e_machine` field of its
+/// ELF header.
+pub struct Machine(pub u16);
+
+impl ReadFromModule for Machine {
+    fn read_from_module(module_memory: ProcessMemory<'_>) -> Result<Self, Error> {
+        Ok(Machine(ModuleReader::new(module_memory)?.header.e_machine))
+    }
+}
+
+/// The metadata of the distribution package a module comes from, read from
+/// its `.note.package` note, e.g. its `type`, `name`, `version` and
+/// `architecture`, in the order of the note.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct PackageMetadata(pub Vec<(String, String)>);
+
+impl PackageMetadata {
+    /// Parse the JSON object held by the note, whose values are all strings.
+    fn parse(json: &str) -> Option<Self> {
+        let mut chars = json.chars().peekable();
+        let mut fields = Vec::new();
+        if next_json_token(&mut chars)? != '{' {
+            return None;
+        }
+        let mut token = next_json_token(&mut chars)?;
+        if token != '}' {
+            loop {
+                if token != '"' {
+                    return None;
+                }
+                let key = read_json_string(&mut chars)?;
+                if next_json_token(&mut chars)? != ':' || next_json_token(&mut chars)? != '"' {
+                    return None;
+                }
+                fields.push((key, read_json_string(&mut chars)?));
+                match next_json_token(&mut chars)? {
+                    ',' => token = next_json_token(&mut chars)?,
+                    '}' => break,
+                    _ => return None,
+                }
+            }
+        }
+
+        next_json_token(&mut chars)
+            .is_none()
+            .then_some(Self(fields))
+    }
+}
+
+impl ReadFromModule for PackageMetadata {
+    fn read_from_module(module_memory: ProcessMemory<'_>) -> Result<Self, Error> {
+        ModuleReader::new(module_memory)?.package_metadata()
+    }
+}
+
+type JsonChars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn next_json_token(chars: &mut JsonChars<'_>) -> Option<char> {
+    chars.find(|c| !c.is_ascii_whitespace())
+}
+
+/// Read the rest of a JSON string whose opening quote was consumed.
+fn read_json_string(chars: &mut JsonChars<'_>) -> Option<String> {
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => s.push(match chars.next()? {
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let unit = read_json_hex(chars)?;
+                    // Characters outside of the BMP are escaped as surrogate
+                    // pairs
+                    let code = if (0xd800..0xdc00).contains(&unit) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = read_json_hex(chars)?
+                            .checked_sub(0xdc00)
+                            .filter(|&low| low < 0x400)?;
+                        0x10000 + ((unit - 0xd800) << 10) + low
+                    } else {
+                        unit
+                    };
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+                c @ ('"' | '\\' | '/') => c,
+                _ => return None,
+            }),
+            c if c < ' ' => return None,
+            c => s.push(c),
+        }
+    }
+}
+
+fn read_json_hex(chars: &mut JsonChars<'_>) -> Option<u32> {
+    (0..4).try_fold(0, |code, _| Some(code << 4 | chars.next()?.to_digit(16)?))
+}
+
 /// The module SONAME.
 #[derive(Default, Clone, Debug)]
 pub struct SoName(pub String);
@@ -391,6 +628,129 @@ impl<'buf> ModuleReader<'buf> {
         }
     }
 
+    /// Read the Go build id from a program header note.
+    pub fn go_build_id_from_program_headers(&mut self) -> Result<String, Error> {
+        let program_headers = self.read_program_headers()?;
+        for header in program_headers {
+            if header.p_type != elf::program_header::PT_NOTE {
+                continue;
+            }
+            if let Ok(Some(result)) = self.find_note(
+                header.p_offset,
+                header.p_filesz,
+                header.p_align,
+                "Go",
+                NT_GO_BUILD_ID,
+            ) {
+                return Ok(String::from_utf8_lossy(&result).into_owned());
+            }
+        }
+        Err(Error::NoGoBuildIdNote)
+    }
+
+    /// Read the package metadata from the program header note holding it. The
+    /// JSON text of the note is terminated and padded with NUL bytes.
+    pub fn package_metadata(&mut self) -> Result<PackageMetadata, Error> {
+        let program_headers = self.read_program_headers()?;
+        for header in program_headers {
+            if header.p_type != elf::program_header::PT_NOTE {
+                continue;
+            }
+            if let Ok(Some(desc)) = self.find_note(
+                header.p_offset,
+                header.p_filesz,
+                header.p_align,
+                PACKAGE_NOTE_NAME,
+                NT_FDO_PACKAGING_METADATA,
+            ) {
+                let len = desc.iter().position(|&b| b == 0).unwrap_or(desc.len());
+                return std::str::from_utf8(&desc[..len])
+                    .ok()
+                    .and_then(PackageMetadata::parse)
+                    .ok_or(Error::InvalidPackageMetadata);
+            }
+        }
+        Err(Error::NoPackageNote)
+    }
+
+    /// Read the address of the `CrashpadInfo` structure from the program
+    /// header note pointing to it, which holds its offset from the note's
+    /// descriptor.
+    pub fn crashpad_info_address(&mut self) -> Result<u64, Error> {
+        let program_headers = self.read_program_headers()?;
+        for header in program_headers {
+            if header.p_type != elf::program_header::PT_NOTE {
+                continue;
+            }
+            let Ok(Some((desc_offset, desc))) = self.find_note_with_offset(
+                header.p_offset,
+                header.p_filesz,
+                header.p_align,
+                CRASHPAD_NOTE_NAME,
+                NT_CRASHPAD_INFO,
+            ) else {
+                continue;
+            };
+
+            let offset = if self.context.is_big() {
+                desc.get(..8)
+                    .map(|d| i64::from_ne_bytes(d.try_into().unwrap()))
+            } else {
+                desc.get(..4)
+                    .map(|d| i32::from_ne_bytes(d.try_into().unwrap()).into())
+            };
+            if let Some(address) = offset
+                .and_then(|offset| (header.p_vaddr + desc_offset as u64).checked_add_signed(offset))
+            {
+                return Ok(address);
+            }
+        }
+        Err(Error::NoCrashpadInfoNote)
+    }
+
+    /// Read the Go build id from the `.note.go.buildid` section.
+    pub fn go_build_id_from_section(&mut self) -> Result<String, Error> {
+        let section_headers = self.read_section_headers()?;
+
+        let header = section_header_with_name(
+            &section_headers,
+            self.header.e_shstrndx as usize,
+            GO_NOTE_SECTION_NAME,
+            &mut self.module_memory,
+        )?
+        .ok_or(Error::NoGoBuildIdNote)?;
+
+        match self.find_note(
+            header.sh_offset,
+            header.sh_size,
+            header.sh_addralign,
+            "Go",
+            NT_GO_BUILD_ID,
+        ) {
+            Ok(Some(v)) => Ok(String::from_utf8_lossy(&v).into_owned()),
+            Ok(None) => Err(Error::NoGoBuildIdNote),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read the Go build information from the `.go.buildinfo` section.
+    pub fn go_build_info(&mut self) -> Result<GoBuildInfo, Error> {
+        let section_headers = self.read_section_headers()?;
+
+        let header = section_header_with_name(
+            &section_headers,
+            self.header.e_shstrndx as usize,
+            GO_BUILD_INFO_SECTION_NAME,
+            &mut self.module_memory,
+        )?
+        .ok_or(Error::NoGoBuildInfoSection)?;
+
+        let data = self
+            .module_memory
+            .read(self.section_offset(header), header.sh_size)?;
+        GoBuildInfo::parse(&data).ok_or(Error::UnsupportedGoBuildInfo)
+    }
+
     /// Generate a build id by hashing the first page of the text section.
     pub fn build_id_generate_from_text(&mut self) -> Result<Vec<u8>, Error> {
         let Some(text_header) = self
@@ -482,6 +842,32 @@ impl<'buf> ModuleReader<'buf> {
         size: u64,
         alignment: u64,
     ) -> Result<Option<Vec<u8>>, Error> {
+        self.find_note(offset, size, alignment, "GNU", elf::note::NT_GNU_BUILD_ID)
+    }
+
+    fn find_note(
+        &mut self,
+        offset: u64,
+        size: u64,
+        alignment: u64,
+        name: &str,
+        n_type: u32,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .find_note_with_offset(offset, size, alignment, name, n_type)?
+            .map(|(_, desc)| desc))
+    }
+
+    /// Like [`Self::find_note`], also returning the offset of the note's
+    /// descriptor from `offset`.
+    fn find_note_with_offset(
+        &mut self,
+        offset: u64,
+        size: u64,
+        alignment: u64,
+        name: &str,
+        n_type: u32,
+    ) -> Result<Option<(usize, Vec<u8>)>, Error> {
         let notes = self.module_memory.read(offset, size)?;
         for note in (elf::note::NoteDataIterator {
             data: &notes,
@@ -492,8 +878,9 @@ impl<'buf> ModuleReader<'buf> {
             ctx: (alignment as usize, self.context),
         }) {
             let Ok(note) = note else { break };
-            if note.name == "GNU" && note.n_type == elf::note::NT_GNU_BUILD_ID {
-                return Ok(Some(note.desc.to_owned()));
+            if note.name == name && note.n_type == n_type {
+                let desc_offset = note.desc.as_ptr() as usize - notes.as_ptr() as usize;
+                return Ok(Some((desc_offset, note.desc.to_owned())));
             }
         }
         Ok(None)
@@ -613,10 +1000,88 @@ mod test {
         assert_eq!(soname, "libfoo.so.1");
     }
 
+    #[test]
+    fn go_build_info() {
+        let mod_info = "path\tgithub.com/foo/bar\n\
+                        mod\tgithub.com/foo/bar\tv1.2.3\th1:abcdef=\n\
+                        dep\tgolang.org/x/sys\tv0.1.0\th1:012345=\n";
+        let mut data = GO_BUILD_INFO_MAGIC.to_vec();
+        data.extend_from_slice(&[8, 2]);
+        data.resize(32, 0);
+        data.push(8);
+        data.extend_from_slice(b"go1.22.1");
+        // The length is a varint, this one takes two bytes
+        let len = mod_info.len() + 32;
+        assert!((128..16384).contains(&len));
+        data.extend_from_slice(&[(len & 0x7f) as u8 | 0x80, (len >> 7) as u8]);
+        data.extend_from_slice(&[0xff; 16]);
+        data.extend_from_slice(mod_info.as_bytes());
+        data.extend_from_slice(&[0xfe; 16]);
+
+        let info = GoBuildInfo::parse(&data).unwrap();
+        assert_eq!(info.go_version, "go1.22.1");
+        assert_eq!(info.module_path.as_deref(), Some("github.com/foo/bar"));
+        assert_eq!(info.module_version.as_deref(), Some("v1.2.3"));
+
+        // Pre Go 1.18 binaries store pointers rather than inline strings
+        data[15] = 0;
+        assert!(GoBuildInfo::parse(&data).is_none());
+    }
+
+    #[test]
+    fn package_metadata() {
+        let json = r#"{ "type":"deb", "name":"fsverity-utils",
+            "version":"1.3-1\u00e9\ud83e\udd80", "architecture":"amd64" }"#;
+        let metadata = PackageMetadata::parse(json).unwrap();
+        assert_eq!(
+            metadata.0,
+            [
+                ("type", "deb"),
+                ("name", "fsverity-utils"),
+                ("version", "1.3-1é🦀"),
+                ("architecture", "amd64"),
+            ]
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        );
+        assert_eq!(
+            PackageMetadata::parse(" {} "),
+            Some(PackageMetadata::default())
+        );
+
+        for invalid in [
+            r#"{"name":"foo",}"#,
+            r#"{"name":1}"#,
+            r#"{"name":"foo"} trailing"#,
+            r#"{"name":"\ud83e"}"#,
+            r#"["foo"]"#,
+        ] {
+            assert_eq!(PackageMetadata::parse(invalid), None, "{invalid}");
+        }
+    }
+
     #[test]
     fn soname_section() {
         let mut reader = ModuleReader::new(TINY_ELF.into()).unwrap();
         let soname = reader.soname_from_sections().unwrap();
         assert_eq!(soname, "libfoo.so.1");
     }
+
+    #[test]
+    fn read_over_capture_limit() {
+        let limits = CaptureLimits {
+            max_segment_size: 16,
+            ..Default::default()
+        };
+        let mut memory: ProcessMemory<'_> = ProcessReader::new(
+            std::process::id() as i32,
+            TINY_ELF.as_ptr() as usize,
+            &limits,
+        )
+        .into();
+        assert_eq!(&*memory.read(0, 16).unwrap(), &TINY_ELF[..16]);
+        assert!(matches!(
+            memory.read(0, 17),
+            Err(Error::CaptureLimitExceeded(error)) if error.value == 17 && error.limit == 16
+        ));
+    }
 }
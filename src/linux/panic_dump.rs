@@ -0,0 +1,215 @@
+//! Writing a minidump when the process panics, available with the
+//! `panic-dump` feature, so that panics are reported like crashes, even with
+//! `panic = "abort"`.
+//!
+//! [`install`] sets a panic hook which dumps the process before calling the
+//! hook it replaces. The panicking thread is blamed, with its registers in
+//! the hook, and the exception is tagged with [`PANIC_EXCEPTION_CODE`]
+//! instead of a signal. The message of the panic is the crash reason, see
+//! [`crate::crash_reason`], and it's written along with the location of the
+//! panic to the assertion stream, and to the [`PANIC_ANNOTATION`] and
+//! [`PANIC_LOCATION_ANNOTATION`] annotations.
+//!
+//! ```no_run
+//! minidump_writer::panic_dump::install("/var/crash/myapp", |writer| {
+//!     writer.set_annotation("version", env!("CARGO_PKG_VERSION"));
+//! });
+//! ```
+//!
+//! A process can't attach to itself with ptrace, so the process is forked
+//! and the child dumps its parent. The child is allowed to attach with
+//! `PR_SET_PTRACER`, which replaces the process allowed before, if any, and
+//! the process must be dumpable. The child only runs the writer, but it
+//! deadlocks if the writer needs a lock, e.g. of the logger, which another
+//! thread held when the process was forked.
+
+use crate::linux::{
+    crash_context::CrashContext,
+    errors::PanicDumpError,
+    minidump_writer::{AssertionInfo, MinidumpWriter},
+};
+use nix::{
+    errno::Errno,
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{fork, ForkResult},
+};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    panic::{AssertUnwindSafe, PanicHookInfo},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The exception code of the minidumps of panics, `RUST` in ASCII, which
+/// can't be mistaken for a signal number
+pub const PANIC_EXCEPTION_CODE: u32 = 0x5255_5354;
+/// The annotation holding the message of the panic
+pub const PANIC_ANNOTATION: &str = "panic";
+/// The annotation holding the location of the panic, as `file:line:column`
+pub const PANIC_LOCATION_ANNOTATION: &str = "panic_location";
+
+/// Set while a panic is being dumped, so that panics of the writer, or of
+/// other threads meanwhile, don't fork again
+static DUMPING: AtomicBool = AtomicBool::new(false);
+
+/// Set a panic hook writing a minidump of the process to `directory`, named
+/// `panic-<pid>-<tid>-<timestamp>.dmp`, before calling the previous hook.
+/// `configure` customizes the writer, e.g. with annotations. Failures are
+/// logged, and leave no minidump behind.
+pub fn install<F>(directory: impl Into<PathBuf>, configure: F)
+where
+    F: Fn(&mut MinidumpWriter) + Send + Sync + 'static,
+{
+    let directory = directory.into();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = directory.join(format!(
+            "panic-{}-{}-{timestamp}.dmp",
+            std::process::id(),
+            nix::unistd::gettid()
+        ));
+        let result = File::create(&path)
+            .map_err(PanicDumpError::from)
+            .and_then(|mut file| write_dump(info, &configure, &mut file));
+        if let Err(error) = result {
+            log::warn!("failed to write a minidump of the panic: {error}");
+            let _ = std::fs::remove_file(&path);
+        }
+        previous(info);
+    }));
+}
+
+/// Write a minidump of the current process for the panic described by
+/// `info` to `file`, for panic hooks other than the one set by [`install`].
+/// `configure` customizes the writer, e.g. with annotations.
+pub fn write_dump(
+    info: &PanicHookInfo<'_>,
+    configure: &dyn Fn(&mut MinidumpWriter),
+    file: &mut File,
+) -> Result<(), PanicDumpError> {
+    if DUMPING.swap(true, Ordering::SeqCst) {
+        return Err(PanicDumpError::DumpInProgress);
+    }
+
+    let result = dump_panic(info, configure, file);
+    DUMPING.store(false, Ordering::SeqCst);
+    result
+}
+
+fn dump_panic(
+    info: &PanicHookInfo<'_>,
+    configure: &dyn Fn(&mut MinidumpWriter),
+    file: &mut File,
+) -> Result<(), PanicDumpError> {
+    // The registers are captured here, as the stack of this function is kept
+    // until the child has dumped the process
+    // SAFETY: all zeroes is a valid context, which getcontext fills
+    let mut context: crash_context::ucontext_t = unsafe { std::mem::zeroed() };
+    // SAFETY: as above
+    unsafe { crash_context::crash_context_getcontext(&mut context) };
+    let crash_context = panic_crash_context(&context);
+    let mut writer = MinidumpWriter::new(crash_context.inner.pid, crash_context.inner.tid);
+    writer.set_crash_context(crash_context);
+
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("Box<dyn Any>", String::as_str),
+    };
+    if let Some(location) = info.location() {
+        writer
+            .set_assertion(AssertionInfo {
+                expression: message.to_owned(),
+                function: String::new(),
+                file: location.file().to_owned(),
+                line: location.line(),
+            })
+            .set_annotation(PANIC_LOCATION_ANNOTATION, location.to_string());
+    }
+    writer
+        .set_annotation(PANIC_ANNOTATION, message)
+        .set_crash_reason(message);
+    configure(&mut writer);
+
+    let (socket, child_socket) = UnixStream::pair()?;
+    // SAFETY: the child only writes the minidump, then exits without
+    // returning
+    match unsafe { fork() }.map_err(PanicDumpError::ForkFailed)? {
+        ForkResult::Child => {
+            drop(socket);
+            // Wait until the parent allowed us to attach
+            let mut allowed = [0u8; 1];
+            let dumped = (&child_socket).read_exact(&mut allowed).is_ok()
+                && std::panic::catch_unwind(AssertUnwindSafe(|| writer.dump(file)))
+                    .is_ok_and(|result| result.is_ok());
+            // SAFETY: exiting without running the parent's destructors
+            unsafe { libc::_exit(if dumped { 0 } else { 1 }) }
+        }
+        ForkResult::Parent { child } => {
+            drop(child_socket);
+            // SAFETY: no pointers are involved. This fails when Yama isn't
+            // enabled, and attaching is allowed anyway.
+            unsafe { libc::prctl(libc::PR_SET_PTRACER, child.as_raw() as libc::c_ulong) };
+            // The child exits if it doesn't get this
+            let _ = (&socket).write_all(&[1]);
+
+            let status = loop {
+                match waitpid(child, None) {
+                    Err(Errno::EINTR) => continue,
+                    status => break status,
+                }
+            };
+            // SAFETY: as above
+            unsafe { libc::prctl(libc::PR_SET_PTRACER, 0 as libc::c_ulong) };
+            match status.map_err(PanicDumpError::WaitFailed)? {
+                WaitStatus::Exited(_, 0) => Ok(()),
+                status => Err(PanicDumpError::DumpFailed(status)),
+            }
+        }
+    }
+}
+
+/// A crash context holding `context`, as filled by getcontext, tagged with
+/// [`PANIC_EXCEPTION_CODE`] at the instruction it was captured at.
+fn panic_crash_context(context: &crash_context::ucontext_t) -> CrashContext {
+    // SAFETY: all zeroes is valid signal information, and getcontext points
+    // the floating point registers to the context itself
+    let inner = unsafe {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
+                let float_state = if context.uc_mcontext.fpregs.is_null() {
+                    std::mem::zeroed()
+                } else {
+                    (*context.uc_mcontext.fpregs).clone()
+                };
+            } else if #[cfg(not(target_arch = "arm"))] {
+                let float_state = std::mem::zeroed();
+            }
+        }
+
+        let mut siginfo: libc::signalfd_siginfo = std::mem::zeroed();
+        siginfo.ssi_signo = PANIC_EXCEPTION_CODE;
+
+        crash_context::CrashContext {
+            context: context.clone(),
+            #[cfg(not(target_arch = "arm"))]
+            float_state,
+            siginfo,
+            pid: libc::getpid(),
+            tid: libc::gettid(),
+        }
+    };
+
+    let mut crash_context = CrashContext { inner };
+    crash_context.inner.siginfo.ssi_addr = crash_context.get_instruction_pointer() as u64;
+    crash_context
+}
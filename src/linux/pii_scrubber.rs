@@ -0,0 +1,251 @@
+//! Heuristic scrubbing of personal data from the memory captured in a
+//! minidump, see [`MinidumpWriter::scrub_pii`].
+//!
+//! Only text is looked at: runs of printable ASCII characters. Those which
+//! are long enough to hold names, addresses or access tokens are replaced as a
+//! whole, while in the shorter ones only email addresses and credit card
+//! numbers are. Everything is replaced in place with [`PLACEHOLDER`], so the
+//! size of the memory and the offsets in it are unchanged.
+//!
+//! This can't be exhaustive, so it's no substitute for redacting the data an
+//! application knows to be sensitive with [`MinidumpWriter::set_memory_redactor`].
+//!
+//! [`MinidumpWriter::scrub_pii`]: crate::minidump_writer::MinidumpWriter::scrub_pii
+//! [`MinidumpWriter::set_memory_redactor`]: crate::minidump_writer::MinidumpWriter::set_memory_redactor
+
+use std::ops::Range;
+
+/// What scrubbed characters are replaced with
+pub const PLACEHOLDER: u8 = b'*';
+/// Runs of printable characters at least this long are replaced as a whole
+pub const LONG_STRING_LEN: usize = 32;
+
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+
+/// Replace the likely personal data in `bytes`, read from the process at
+/// `start`.
+///
+/// A pointer's low bytes may be printable and follow a string, so aligned
+/// words which look like pointers are left alone. This is only possible on
+/// 64-bit targets, where the top bytes of pointers are zero: any 4 bytes can
+/// be a 32-bit pointer.
+pub fn scrub(start: usize, bytes: &mut [u8]) {
+    let mut scrubbed = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let len = bytes[offset..]
+            .iter()
+            .take_while(|&&b| is_printable(b))
+            .count();
+        if len == 0 {
+            offset += 1;
+            continue;
+        }
+
+        let run = offset..offset + len;
+        if len >= LONG_STRING_LEN {
+            scrubbed.push(run);
+        } else {
+            let text = &bytes[run.clone()];
+            scrubbed.extend(
+                email_addresses(text)
+                    .into_iter()
+                    .chain(card_numbers(text))
+                    .map(|r| r.start + offset..r.end + offset),
+            );
+        }
+        offset += len;
+    }
+    if scrubbed.is_empty() {
+        return;
+    }
+
+    let pointers = if cfg!(target_pointer_width = "64") {
+        pointer_like_words(start, bytes)
+    } else {
+        Vec::new()
+    };
+    for range in scrubbed {
+        for idx in range {
+            if !pointers.iter().any(|word| word.contains(&idx)) {
+                bytes[idx] = PLACEHOLDER;
+            }
+        }
+    }
+}
+
+fn is_printable(b: u8) -> bool {
+    b.is_ascii_graphic() || b == b' '
+}
+
+/// The aligned words in `bytes` holding a value in the range of user space
+/// addresses, above the first 4 GiB which 64-bit processes don't map.
+fn pointer_like_words(start: usize, bytes: &[u8]) -> Vec<Range<usize>> {
+    const USER_ADDRESSES: Range<usize> = 1 << 32..1 << 47;
+    let first = start.next_multiple_of(POINTER_SIZE) - start;
+    let Some(words) = bytes.get(first..) else {
+        return Vec::new();
+    };
+    words
+        .chunks_exact(POINTER_SIZE)
+        .enumerate()
+        .filter(|(_, word)| {
+            USER_ADDRESSES.contains(&usize::from_ne_bytes((*word).try_into().unwrap()))
+        })
+        .map(|(idx, _)| {
+            let offset = first + idx * POINTER_SIZE;
+            offset..offset + POINTER_SIZE
+        })
+        .collect()
+}
+
+/// The email addresses in `text`, a run of printable characters
+fn email_addresses(text: &[u8]) -> Vec<Range<usize>> {
+    let is_local = |b: u8| b.is_ascii_alphanumeric() || b"._%+-".contains(&b);
+    let is_domain = |b: u8| b.is_ascii_alphanumeric() || b".-".contains(&b);
+
+    let mut addresses: Vec<Range<usize>> = Vec::new();
+    for at in text
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, &b)| (b == b'@').then_some(idx))
+    {
+        if addresses.last().is_some_and(|last| at < last.end) {
+            continue;
+        }
+        let start = text[..at]
+            .iter()
+            .rposition(|&b| !is_local(b))
+            .map_or(0, |idx| idx + 1);
+        let domain_len = text[at + 1..].iter().take_while(|&&b| is_domain(b)).count();
+        let domain = &text[at + 1..at + 1 + domain_len];
+        let domain = match domain.iter().rposition(|&b| b.is_ascii_alphanumeric()) {
+            Some(last) => &domain[..=last],
+            None => continue,
+        };
+        // The top level domain has at least two letters
+        let tld_len = domain
+            .iter()
+            .rev()
+            .take_while(|b| b.is_ascii_alphabetic())
+            .count();
+        if start == at || tld_len < 2 || tld_len == domain.len() {
+            continue;
+        }
+        if domain[domain.len() - tld_len - 1] != b'.' {
+            continue;
+        }
+        addresses.push(start..at + 1 + domain.len());
+    }
+    addresses
+}
+
+/// The credit card numbers in `text`, a run of printable characters: 13 to 19
+/// digits, possibly in groups separated by spaces or dashes, with a valid
+/// Luhn checksum.
+fn card_numbers(text: &[u8]) -> Vec<Range<usize>> {
+    let mut numbers = Vec::new();
+    let mut idx = 0;
+    while idx < text.len() {
+        if !text[idx].is_ascii_digit() {
+            idx += 1;
+            continue;
+        }
+
+        let start = idx;
+        let mut digits = Vec::new();
+        let mut end = idx;
+        while end < text.len() {
+            if text[end].is_ascii_digit() {
+                digits.push(text[end] - b'0');
+                end += 1;
+            } else if b" -".contains(&text[end])
+                && text.get(end + 1).is_some_and(u8::is_ascii_digit)
+            {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        if (13..=19).contains(&digits.len()) && luhn_valid(&digits) {
+            numbers.push(start..end);
+        }
+        idx = end;
+    }
+    numbers
+}
+
+fn luhn_valid(digits: &[u8]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, &digit)| {
+            let digit = u32::from(digit);
+            if idx % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrubbed(start: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        scrub(start, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_scrub_short_strings() {
+        assert_eq!(
+            scrubbed(0, b"mail jane.doe@example.com\0"),
+            b"mail ********************\0"
+        );
+        assert_eq!(
+            scrubbed(0, b"cc 4111 1111 1111 1111\0"),
+            b"cc *******************\0"
+        );
+        // Not a valid checksum, nor an email address
+        assert_eq!(
+            scrubbed(0, b"id 4111111111111112 a@b\0"),
+            b"id 4111111111111112 a@b\0"
+        );
+    }
+
+    #[test]
+    fn test_scrub_long_strings() {
+        let mut bytes = b"Authorization: Bearer abcdefghijklmnop\0".to_vec();
+        let len = bytes.len() - 1;
+        scrub(0, &mut bytes);
+        assert!(bytes[..len].iter().all(|&b| b == PLACEHOLDER));
+        assert_eq!(bytes[len], 0);
+
+        // Shorter strings, non-printable bytes and their length are left alone
+        let bytes = b"\x01\x02short\0\xff".to_vec();
+        assert_eq!(scrubbed(0, &bytes), bytes);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_scrub_keeps_pointers() {
+        // A string immediately followed by a pointer whose low bytes are
+        // printable
+        let mut bytes = vec![b'a'; 40];
+        bytes.extend_from_slice(&0x5555_4142_4344usize.to_ne_bytes());
+        let scrubbed = scrubbed(0x1000, &bytes);
+        assert!(scrubbed[..40].iter().all(|&b| b == PLACEHOLDER));
+        assert_eq!(scrubbed[40..], bytes[40..]);
+    }
+}
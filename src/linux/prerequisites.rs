@@ -0,0 +1,82 @@
+//! Checks of whether a process can be dumped, without affecting it.
+//!
+//! Crash reporters can use these when starting up to validate their
+//! configuration (capabilities, Yama settings, `/proc` mount options...),
+//! rather than discovering a problem when a crash needs to be dumped.
+
+use crate::linux::{errors::PtraceAccessError, ptrace_access, Pid};
+use std::path::PathBuf;
+
+/// The `/proc/<pid>` entries which are needed to write a dump
+const REQUIRED_PROC_ENTRIES: &[&str] = &["maps", "task", "auxv", "status"];
+
+/// The result of checking whether a process can be dumped, see [`can_dump`].
+#[derive(Debug)]
+pub struct DumpPrerequisites {
+    /// Whether we are allowed to attach to the process with ptrace
+    pub ptrace_access: Result<(), PtraceAccessError>,
+    /// The `/proc/<pid>` entries needed to write a dump which we can't read
+    pub unreadable_proc_entries: Vec<(PathBuf, std::io::Error)>,
+}
+
+impl DumpPrerequisites {
+    /// Check whether process `pid` can be dumped.
+    pub fn check(pid: Pid) -> Self {
+        let unreadable_proc_entries = REQUIRED_PROC_ENTRIES
+            .iter()
+            .map(|entry| PathBuf::from(format!("/proc/{pid}/{entry}")))
+            .filter_map(|path| {
+                let result = if path.ends_with("task") {
+                    std::fs::read_dir(&path).map(drop)
+                } else {
+                    std::fs::File::open(&path).map(drop)
+                };
+                result.err().map(|e| (path, e))
+            })
+            .collect();
+
+        Self {
+            ptrace_access: ptrace_access::check(pid),
+            unreadable_proc_entries,
+        }
+    }
+
+    /// Return whether all the prerequisites for writing a dump are met.
+    pub fn are_met(&self) -> bool {
+        self.ptrace_access.is_ok() && self.unreadable_proc_entries.is_empty()
+    }
+}
+
+/// Return whether process `pid` can be dumped, use [`DumpPrerequisites`] to
+/// find out why it can't.
+pub fn can_dump(pid: Pid) -> bool {
+    DumpPrerequisites::check(pid).are_met()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_dump_child() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as Pid;
+
+        let prerequisites = DumpPrerequisites::check(pid);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(prerequisites.are_met(), "{prerequisites:?}");
+
+        // The process is gone now
+        let prerequisites = DumpPrerequisites::check(pid);
+        assert!(!prerequisites.are_met());
+        assert_eq!(
+            prerequisites.unreadable_proc_entries.len(),
+            REQUIRED_PROC_ENTRIES.len()
+        );
+    }
+}
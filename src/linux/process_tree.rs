@@ -0,0 +1,178 @@
+//! Dumping a process along with all of its descendants, for applications
+//! made of several processes where the cause of a crash often lives in
+//! another process than the one which crashed, e.g. a browser's renderers and
+//! its GPU process.
+//!
+//! Each process is written to its own minidump, and the minidumps are
+//! correlated by an incident id, recorded in their annotations along with the
+//! id of the root process, see [`MinidumpWriter::dump_process_tree`].
+
+use crate::{
+    dir_section::FileWriterError,
+    linux::{
+        errors::{InitError, WriterError},
+        minidump_writer::MinidumpWriter,
+    },
+    Pid,
+};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The annotation holding the id shared by the minidumps of a process tree
+pub const INCIDENT_ID_ANNOTATION: &str = "incident_id";
+/// The annotation holding the id of the root of the process tree
+pub const INCIDENT_ROOT_PID_ANNOTATION: &str = "incident_root_pid";
+
+/// The minidumps written by [`MinidumpWriter::dump_process_tree`]
+#[derive(Debug)]
+pub struct ProcessTreeDump {
+    pub incident_id: String,
+    /// The path of the minidump of each process, the root first, or why it
+    /// couldn't be written, e.g. because the process exited in the meantime
+    pub dumps: Vec<(Pid, Result<PathBuf, WriterError>)>,
+}
+
+impl MinidumpWriter {
+    /// Write a minidump of the process being dumped and one of each of its
+    /// descendants to `directory`, named `<incident id>-<pid>.dmp`.
+    ///
+    /// The incident id is taken from the [`INCIDENT_ID_ANNOTATION`]
+    /// annotation if it was set, and generated otherwise. The root process is
+    /// dumped as configured, then the writer is [`Self::reset`] for each of
+    /// its descendants, keeping the annotations, which are dumped without a
+    /// crash context. The writer is left set up for the last of them.
+    ///
+    /// The descendants are found when the root process has been dumped, and
+    /// are dumped one after the other, so processes which are started or
+    /// exit in the meantime may be missing.
+    pub fn dump_process_tree(&mut self, directory: &Path) -> Result<ProcessTreeDump, WriterError> {
+        let root = self.process_id;
+        let incident_id = self
+            .annotations
+            .get(INCIDENT_ID_ANNOTATION)
+            .cloned()
+            .unwrap_or_else(|| generate_incident_id(root));
+        self.set_annotation(INCIDENT_ID_ANNOTATION, incident_id.clone())
+            .set_annotation(INCIDENT_ROOT_PID_ANNOTATION, root.to_string());
+        let annotations = self.annotations.clone();
+
+        let mut dumps = vec![(root, self.dump_to_directory(directory, &incident_id))];
+        for pid in descendants(root)? {
+            self.reset(pid, pid);
+            self.annotations.clone_from(&annotations);
+            dumps.push((pid, self.dump_to_directory(directory, &incident_id)));
+        }
+
+        Ok(ProcessTreeDump { incident_id, dumps })
+    }
+
+    fn dump_to_directory(
+        &mut self,
+        directory: &Path,
+        incident_id: &str,
+    ) -> Result<PathBuf, WriterError> {
+        let path = directory.join(format!("{incident_id}-{}.dmp", self.process_id));
+        let mut file = std::fs::File::create(&path).map_err(FileWriterError::from)?;
+        self.dump(&mut file)?;
+        Ok(path)
+    }
+}
+
+/// The ids of the descendants of `root`, parents before their children.
+pub fn descendants(root: Pid) -> Result<Vec<Pid>, InitError> {
+    let parents = parent_pids()?;
+    let mut descendants = Vec::new();
+    let mut idx = 0;
+    let mut parent = root;
+    loop {
+        descendants.extend(
+            parents
+                .iter()
+                .filter(|&&(_, ppid)| ppid == parent)
+                .map(|&(pid, _)| pid),
+        );
+        let Some(&next) = descendants.get(idx) else {
+            break;
+        };
+        parent = next;
+        idx += 1;
+    }
+    Ok(descendants)
+}
+
+/// The id of every process along with the id of its parent, sorted.
+fn parent_pids() -> Result<Vec<(Pid, Pid)>, InitError> {
+    let entries =
+        std::fs::read_dir("/proc").map_err(|e| InitError::IOError("/proc".to_owned(), e))?;
+    let mut parents: Vec<_> = entries
+        .filter_map(|entry| {
+            let pid: Pid = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            // Processes may exit while they are listed
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            Some((pid, parse_parent_pid(&stat)?))
+        })
+        .collect();
+    parents.sort_unstable();
+    Ok(parents)
+}
+
+/// The parent process id in the contents of `/proc/<pid>/stat`. It follows
+/// the process' name, in parentheses, which may contain anything, and its
+/// state.
+fn parse_parent_pid(stat: &str) -> Option<Pid> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_ascii_whitespace().nth(1)?.parse().ok()
+}
+
+/// A random 128-bit hexadecimal id, from the randomly seeded hashers of the
+/// standard library.
+fn generate_incident_id(root: Pid) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(now);
+        hasher.write_i32(root);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", random(), random())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_parent_pid() {
+        assert_eq!(
+            parse_parent_pid("1234 (a (weird) name) S 42 1234 1234 0 -1 4194560"),
+            Some(42)
+        );
+        assert_eq!(parse_parent_pid("1234 (truncated"), None);
+    }
+
+    #[test]
+    fn test_descendants() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let descendants = descendants(std::process::id() as Pid).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(descendants.contains(&(child.id() as Pid)));
+    }
+
+    #[test]
+    fn test_generate_incident_id() {
+        let id = generate_incident_id(1);
+        assert_eq!(id.len(), 32);
+        assert_ne!(id, generate_incident_id(1));
+    }
+}
@@ -0,0 +1,164 @@
+//! Checks of whether we're allowed to attach to a process with ptrace.
+//!
+//! When attaching fails the kernel only returns `EPERM`, which doesn't say
+//! whether the Yama LSM, the credentials of the process or its dumpable flag
+//! are to blame. The checks here look at the same information the kernel does
+//! so that a meaningful error can be reported instead.
+
+use crate::linux::{errors::PtraceAccessError, Pid};
+use procfs_core::{
+    process::{Stat, Status},
+    FromRead,
+};
+use std::os::unix::fs::MetadataExt;
+
+const YAMA_PTRACE_SCOPE: &str = "/proc/sys/kernel/yama/ptrace_scope";
+const CAP_SYS_PTRACE: u64 = 1 << 19;
+
+/// The values of `kernel.yama.ptrace_scope`, see the kernel's
+/// `Documentation/admin-guide/LSM/Yama.rst`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceScope {
+    /// Any process running as the same user may be attached to
+    Classic,
+    /// Only descendants, or processes which allowed us via `PR_SET_PTRACER`,
+    /// may be attached to
+    Restricted,
+    /// Only processes with `CAP_SYS_PTRACE` may attach
+    AdminOnly,
+    /// Attaching is not allowed at all
+    NoAttach,
+}
+
+impl PtraceScope {
+    /// Read the current scope, returns `None` if Yama is not enabled.
+    pub fn read() -> Option<Self> {
+        let scope = std::fs::read_to_string(YAMA_PTRACE_SCOPE).ok()?;
+        Self::parse(&scope)
+    }
+
+    fn parse(scope: &str) -> Option<Self> {
+        match scope.trim() {
+            "0" => Some(Self::Classic),
+            "1" => Some(Self::Restricted),
+            "2" => Some(Self::AdminOnly),
+            "3" => Some(Self::NoAttach),
+            _ => None,
+        }
+    }
+}
+
+fn read_status(pid: Pid) -> Result<Status, PtraceAccessError> {
+    let path = format!("/proc/{pid}/status");
+    let file =
+        std::fs::File::open(&path).map_err(|e| PtraceAccessError::IOError(path.clone(), e))?;
+    Status::from_read(file).map_err(|e| {
+        PtraceAccessError::IOError(
+            path,
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        )
+    })
+}
+
+/// Return whether `pid` is a descendant of the current process.
+fn is_descendant(pid: Pid) -> bool {
+    let ours = std::process::id() as Pid;
+    let mut current = pid;
+    while current > 1 {
+        let Some(stat) = std::fs::File::open(format!("/proc/{current}/stat"))
+            .ok()
+            .and_then(|file| Stat::from_read(file).ok())
+        else {
+            return false;
+        };
+        if stat.ppid == ours {
+            return true;
+        }
+        current = stat.ppid;
+    }
+    false
+}
+
+/// Check that nothing will prevent us from attaching to process `pid`.
+///
+/// Only the conditions which are certain to make attaching fail are reported.
+/// In particular, when Yama only allows attaching to descendants there is no
+/// way to tell whether the process called `PR_SET_PTRACER` to allow us, see
+/// [`explain_denial`] for that case. This has no side effects on the process.
+pub fn check(pid: Pid) -> Result<(), PtraceAccessError> {
+    let ours = read_status(std::process::id() as Pid)?;
+    if ours.capeff & CAP_SYS_PTRACE != 0 {
+        // Only the system-wide switch can stop us now
+        return match PtraceScope::read() {
+            Some(PtraceScope::NoAttach) => Err(PtraceAccessError::AttachDisabled),
+            _ => Ok(()),
+        };
+    }
+
+    match PtraceScope::read() {
+        Some(PtraceScope::NoAttach) => return Err(PtraceAccessError::AttachDisabled),
+        Some(PtraceScope::AdminOnly) => return Err(PtraceAccessError::CapSysPtraceRequired),
+        _ => {}
+    }
+
+    // Our real credentials must match all of the process' ones
+    let theirs = read_status(pid)?;
+    let uids = [theirs.ruid, theirs.euid, theirs.suid];
+    let gids = [theirs.rgid, theirs.egid, theirs.sgid];
+    if uids.iter().any(|&uid| uid != ours.ruid) || gids.iter().any(|&gid| gid != ours.rgid) {
+        return Err(PtraceAccessError::DifferentUser(pid));
+    }
+
+    // The kernel makes the /proc entries of a process which is not dumpable
+    // owned by root, regardless of the user it runs as
+    let path = format!("/proc/{pid}");
+    let owner = std::fs::metadata(&path)
+        .map_err(|e| PtraceAccessError::IOError(path, e))?
+        .uid();
+    if owner != theirs.euid {
+        return Err(PtraceAccessError::NotDumpable(pid));
+    }
+
+    Ok(())
+}
+
+/// Explain why attaching to process `pid` failed with `EPERM`.
+pub fn explain_denial(pid: Pid) -> PtraceAccessError {
+    match check(pid) {
+        Err(e) => e,
+        Ok(()) => {
+            let restricted = PtraceScope::read() == Some(PtraceScope::Restricted);
+            if restricted && !is_descendant(pid) {
+                PtraceAccessError::NotDescendant(pid)
+            } else {
+                PtraceAccessError::Denied(pid)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scope() {
+        assert_eq!(PtraceScope::parse("0\n"), Some(PtraceScope::Classic));
+        assert_eq!(PtraceScope::parse("1\n"), Some(PtraceScope::Restricted));
+        assert_eq!(PtraceScope::parse("2\n"), Some(PtraceScope::AdminOnly));
+        assert_eq!(PtraceScope::parse("3\n"), Some(PtraceScope::NoAttach));
+        assert_eq!(PtraceScope::parse("4\n"), None);
+    }
+
+    #[test]
+    fn test_is_descendant() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        assert!(is_descendant(child.id() as Pid));
+        assert!(!is_descendant(1));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -2,23 +2,30 @@
 use crate::linux::android::late_process_mappings;
 use crate::linux::{
     auxv::AuxvDumpInfo,
-    errors::{DumperError, InitError, ThreadInfoError},
-    maps_reader::MappingInfo,
-    module_reader,
+    cgroup_freezer::CgroupFreezer,
+    errors::{DumperError, InitError, PtraceAccessError, ThreadInfoError},
+    maps_reader::{self, MappingInfo},
+    module_reader, ptrace_access,
     thread_info::ThreadInfo,
     Pid,
 };
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use crate::thread_info;
+use crate::{
+    capture_limits::CaptureLimits, memory_map::MemoryMapping, process_dumper::ProcessDumper,
+    scratch::ScratchPool,
+};
 use nix::{
     errno::Errno,
     sys::{ptrace, signal, wait},
 };
 use procfs_core::{
-    process::{MMPermissions, ProcState, Stat},
+    process::{MMPermissions, ProcState, Stat, Status},
     FromRead, ProcError,
 };
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     path,
     result::Result,
     time::{Duration, Instant},
@@ -30,14 +37,96 @@ pub struct Thread {
     pub name: Option<String>,
 }
 
+/// Why a thread was stopped when it was suspended, see
+/// [`PtraceDumper::thread_stop`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The thread was in a group-stop, the whole process having been stopped
+    GroupStop,
+    /// A signal was being delivered to the thread, as read with
+    /// `PTRACE_GETSIGINFO`
+    Signal {
+        signo: i32,
+        code: i32,
+        /// The process which sent the signal, for the signals sent by
+        /// `kill`, `tgkill` or `sigqueue` rather than raised by the kernel
+        sender: Option<Pid>,
+    },
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GroupStop => write!(f, "group_stop"),
+            Self::Signal {
+                signo,
+                code,
+                sender,
+            } => {
+                match signal::Signal::try_from(*signo) {
+                    Ok(signal) => write!(f, "signal={signal}")?,
+                    Err(_) => write!(f, "signal={signo}")?,
+                }
+                write!(f, " code={code}")?;
+                if let Some(sender) = sender {
+                    write!(f, " sender={sender}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// How a thread was stopped when it was suspended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadStop {
+    /// Why it stopped, if that could be read
+    pub reason: Option<StopReason>,
+    /// The signals the thread stopped for before the `SIGSTOP` of the attach,
+    /// which were delivered to it again
+    pub reinjected: Vec<StopReason>,
+}
+
 #[derive(Debug)]
 pub struct PtraceDumper {
     pub pid: Pid,
     threads_suspended: bool,
     pub threads: Vec<Thread>,
+    thread_infos: ThreadInfos,
+    thread_stops: HashMap<Pid, ThreadStop>,
     pub auxv: AuxvDumpInfo,
     pub mappings: Vec<MappingInfo>,
     pub page_size: usize,
+    /// The root directory of the process, if it lives in a different mount
+    /// namespace than ours (e.g. in a container). The files it maps must be
+    /// looked up relative to it, see [`Self::resolve_path`].
+    pub root: Option<path::PathBuf>,
+    /// Why the mappings of the process couldn't be read, e.g. because a
+    /// sandbox blocks access to its `/proc` entries. [`Self::mappings`] is
+    /// left empty then, see [`MinidumpWriter::set_user_mapping_list`].
+    ///
+    /// [`MinidumpWriter::set_user_mapping_list`]: crate::minidump_writer::MinidumpWriter::set_user_mapping_list
+    pub mappings_error: Option<InitError>,
+    /// The limits on the sizes read from the modules' headers, see
+    /// [`crate::capture_limits`]
+    pub capture_limits: CaptureLimits,
+    freezer: Option<CgroupFreezer>,
+    /// The buffers reused by [`Self::read_scratch`], and by the reads whose
+    /// bytes are copied to the minidump right away
+    pub(crate) scratch: ScratchPool,
+}
+
+/// The information of each thread, see [`PtraceDumper::capture_thread_infos`].
+/// `ThreadInfo` doesn't implement `Debug` on every architecture.
+#[derive(Default)]
+struct ThreadInfos(Vec<Option<ThreadInfo>>);
+
+impl std::fmt::Debug for ThreadInfos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadInfos")
+            .field("len", &self.0.len())
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(target_pointer_width = "32")]
@@ -50,7 +139,11 @@ impl Drop for PtraceDumper {
         // Always try to resume all threads (e.g. in case of error)
         let _ = self.resume_threads();
         // Always allow the process to continue.
-        let _ = self.continue_process();
+        if let Some(freezer) = self.freezer.take() {
+            let _ = freezer.thaw();
+        } else {
+            let _ = self.continue_process();
+        }
     }
 }
 
@@ -86,6 +179,22 @@ fn ptrace_detach(child: Pid) -> Result<(), DumperError> {
     })
 }
 
+/// Why the traced thread `pid` is stopped. `PTRACE_GETSIGINFO` fails with
+/// `EINVAL` in a group-stop, as no signal is being delivered then.
+fn stop_reason(pid: nix::unistd::Pid) -> Option<StopReason> {
+    match ptrace::getsiginfo(pid) {
+        Ok(siginfo) => Some(StopReason::Signal {
+            signo: siginfo.si_signo,
+            code: siginfo.si_code,
+            // SAFETY: the sender is set for the signals sent from user space,
+            // whose code is SI_USER (0) or below
+            sender: (siginfo.si_code <= 0).then(|| unsafe { siginfo.si_pid() }),
+        }),
+        Err(Errno::EINVAL) => Some(StopReason::GroupStop),
+        Err(_) => None,
+    }
+}
+
 impl PtraceDumper {
     /// Constructs a dumper for extracting information from the specified process id
     pub fn new(pid: Pid, stop_timeout: Duration, auxv: AuxvDumpInfo) -> Result<Self, InitError> {
@@ -97,9 +206,51 @@ impl PtraceDumper {
             pid,
             threads_suspended: false,
             threads: Vec::new(),
+            thread_infos: ThreadInfos::default(),
+            thread_stops: HashMap::new(),
+            auxv,
+            mappings: Vec::new(),
+            page_size: 0,
+            root: None,
+            mappings_error: None,
+            capture_limits: CaptureLimits::default(),
+            freezer: None,
+            scratch: ScratchPool::default(),
+        };
+        dumper.init(stop_timeout)?;
+        Ok(dumper)
+    }
+
+    /// Constructs a dumper which stops the process by freezing its cgroup
+    /// rather than sending it `SIGSTOP`, see [`CgroupFreezer`]. If the cgroup
+    /// can't be frozen this falls back to the behavior of [`Self::new`].
+    pub fn new_frozen(
+        pid: Pid,
+        stop_timeout: Duration,
+        auxv: AuxvDumpInfo,
+    ) -> Result<Self, InitError> {
+        if pid == std::process::id() as _ {
+            return Err(InitError::CannotPtraceSameProcess);
+        }
+
+        let freezer = CgroupFreezer::freeze(pid, stop_timeout)
+            .map_err(|e| log::warn!("failed to freeze process {pid}: {e}"))
+            .ok();
+
+        let mut dumper = Self {
+            pid,
+            threads_suspended: false,
+            threads: Vec::new(),
+            thread_infos: ThreadInfos::default(),
+            thread_stops: HashMap::new(),
             auxv,
             mappings: Vec::new(),
             page_size: 0,
+            root: None,
+            mappings_error: None,
+            capture_limits: CaptureLimits::default(),
+            freezer,
+            scratch: ScratchPool::default(),
         };
         dumper.init(stop_timeout)?;
         Ok(dumper)
@@ -107,17 +258,33 @@ impl PtraceDumper {
 
     // TODO: late_init for chromeos and android
     pub fn init(&mut self, stop_timeout: Duration) -> Result<(), InitError> {
-        // Stopping the process is best-effort.
-        if let Err(e) = self.stop_process(stop_timeout) {
-            log::warn!("failed to stop process {}: {e}", self.pid);
+        // Bail out early with a meaningful error rather than stopping a process
+        // we won't be able to attach to. Failing to read the information needed
+        // for the check doesn't mean attaching will fail though.
+        match ptrace_access::check(self.pid) {
+            Err(PtraceAccessError::IOError(path, e)) => {
+                log::warn!("failed to check ptrace access using {path}: {e}");
+            }
+            result => result?,
+        }
+
+        // Stopping the process is best-effort, and not needed if it's frozen.
+        if self.freezer.is_none() {
+            if let Err(e) = self.stop_process(stop_timeout) {
+                log::warn!("failed to stop process {}: {e}", self.pid);
+            }
         }
 
         if let Err(e) = self.auxv.try_filling_missing_info(self.pid) {
             log::warn!("failed trying to fill in missing auxv info: {e}");
         }
 
+        self.root = Self::foreign_root(self.pid);
         self.enumerate_threads()?;
-        self.enumerate_mappings()?;
+        if let Err(e) = self.enumerate_mappings() {
+            log::warn!("failed to read the mappings of process {}: {e}", self.pid);
+            self.mappings_error = Some(e);
+        }
         self.page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE)?
             .expect("page size apparently unlimited: doesn't make sense.")
             as usize;
@@ -135,12 +302,13 @@ impl PtraceDumper {
     }
 
     /// Suspends a thread by attaching to it.
-    pub fn suspend_thread(child: Pid) -> Result<(), DumperError> {
+    pub fn suspend_thread(child: Pid) -> Result<ThreadStop, DumperError> {
         use DumperError::PtraceAttachError as AttachErr;
 
         let pid = nix::unistd::Pid::from_raw(child);
         // This may fail if the thread has just died or debugged.
         ptrace::attach(pid).map_err(|e| AttachErr(child, e))?;
+        let mut reinjected = Vec::new();
         loop {
             match wait::waitpid(pid, Some(wait::WaitPidFlag::__WALL)) {
                 Ok(status) => {
@@ -160,6 +328,11 @@ impl PtraceDumper {
 
                     // Signals other than SIGSTOP that are received need to be reinjected,
                     // or they will otherwise get lost.
+                    reinjected.push(stop_reason(pid).unwrap_or(StopReason::Signal {
+                        signo: status as i32,
+                        code: 0,
+                        sender: None,
+                    }));
                     if let Err(err) = ptrace::cont(pid, status) {
                         return Err(DumperError::WaitPidError(child, err));
                     }
@@ -199,7 +372,10 @@ impl PtraceDumper {
                 return Err(DumperError::DetachSkippedThread(child));
             }
         }
-        Ok(())
+        Ok(ThreadStop {
+            reason: stop_reason(pid),
+            reinjected,
+        })
     }
 
     /// Resumes a thread by detaching from it.
@@ -213,9 +389,25 @@ impl PtraceDumper {
         // If the thread either disappeared before we could attach to it, or if
         // it was part of the seccomp sandbox's trusted code, it is OK to
         // silently drop it from the minidump.
-        self.threads.retain(|x| Self::suspend_thread(x.tid).is_ok());
+        let mut denied = false;
+        self.thread_stops.clear();
+        let thread_stops = &mut self.thread_stops;
+        self.threads.retain(|x| match Self::suspend_thread(x.tid) {
+            Ok(stop) => {
+                thread_stops.insert(x.tid, stop);
+                true
+            }
+            Err(DumperError::PtraceAttachError(_, Errno::EPERM)) => {
+                denied = true;
+                false
+            }
+            Err(_) => false,
+        });
 
         if self.threads.is_empty() {
+            if denied {
+                return Err(ptrace_access::explain_denial(self.pid).into());
+            }
             Err(DumperError::SuspendNoThreadsLeft(threads_count))
         } else {
             self.threads_suspended = true;
@@ -274,6 +466,8 @@ impl PtraceDumper {
     /// Parse /proc/$pid/task to list all the threads of the process identified by
     /// pid.
     fn enumerate_threads(&mut self) -> Result<(), InitError> {
+        // Threads may have come and gone since a previous call
+        self.threads.clear();
         let pid = self.pid;
         let filename = format!("/proc/{}/task", pid);
         let task_path = path::PathBuf::from(&filename);
@@ -300,6 +494,75 @@ impl PtraceDumper {
         Ok(())
     }
 
+    /// Keep at most `max_threads` threads, ordered by relevance: the blamed
+    /// thread first, then the main thread, then the remaining ones by
+    /// decreasing CPU time, as the process is stopped by now and busy threads
+    /// are the likeliest to be involved in a problem. Returns the number of
+    /// threads which were dropped. This must be called before suspending the
+    /// threads.
+    pub fn select_threads(&mut self, max_threads: usize, blamed_thread: Pid) -> usize {
+        if self.threads.len() <= max_threads {
+            return 0;
+        }
+
+        let pid = self.pid;
+        let relevance = |tid: Pid| {
+            if tid == blamed_thread {
+                (0, std::cmp::Reverse(0))
+            } else if tid == pid {
+                (1, std::cmp::Reverse(0))
+            } else {
+                let cpu_time = std::fs::File::open(format!("/proc/{pid}/task/{tid}/stat"))
+                    .ok()
+                    .and_then(|file| Stat::from_read(file).ok())
+                    .map_or(0, |stat| stat.utime + stat.stime);
+                (2, std::cmp::Reverse(cpu_time))
+            }
+        };
+
+        // Stable sorting preserves the enumeration order for equal relevance
+        self.threads
+            .sort_by_cached_key(|thread| relevance(thread.tid));
+        let omitted = self.threads.len() - max_threads;
+        self.threads.truncate(max_threads);
+        omitted
+    }
+
+    /// Return the path to the root directory of the process identified by pid
+    /// if its mount namespace differs from ours.
+    fn foreign_root(pid: Pid) -> Option<path::PathBuf> {
+        let ours = std::fs::read_link("/proc/self/ns/mnt").ok();
+        let theirs = std::fs::read_link(format!("/proc/{pid}/ns/mnt")).ok()?;
+        (ours.as_ref() != Some(&theirs)).then(|| format!("/proc/{pid}/root").into())
+    }
+
+    /// Translate a path as seen by the process into one we can open, going
+    /// through the root of the process if it lives in another mount namespace.
+    pub fn resolve_path(&self, path: &path::Path) -> path::PathBuf {
+        match &self.root {
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_owned(),
+        }
+    }
+
+    /// Find the thread whose id, as seen from within the PID namespace of the
+    /// process, is `ns_tid`, and return its id in our own PID namespace.
+    ///
+    /// Crash handlers running in a container report the thread ids they see,
+    /// which don't match the ones we use when the process lives in a nested
+    /// PID namespace.
+    pub fn translate_ns_tid(&self, ns_tid: Pid) -> Option<Pid> {
+        self.threads.iter().map(|thread| thread.tid).find(|&tid| {
+            std::fs::File::open(format!("/proc/{}/task/{tid}/status", self.pid))
+                .ok()
+                .and_then(|file| Status::from_read(file).ok())
+                .and_then(|status| status.nspid)
+                // The last entry is the id in the innermost namespace
+                .and_then(|nspid| nspid.last().copied())
+                == Some(ns_tid)
+        })
+    }
+
     fn enumerate_mappings(&mut self) -> Result<(), InitError> {
         // linux_gate_loc is the beginning of the kernel's mapping of
         // linux-gate.so in the process.  It doesn't actually show up in the
@@ -346,6 +609,30 @@ impl PtraceDumper {
         Ok(())
     }
 
+    /// Read the registers of every thread in a single pass, so that the
+    /// streams which need them don't have to read them again, which would
+    /// lengthen the time the process stays stopped. This must be called after
+    /// suspending the threads.
+    pub fn capture_thread_infos(&mut self) {
+        self.thread_infos = ThreadInfos(
+            self.threads
+                .iter()
+                .map(|thread| ThreadInfo::create(self.pid, thread.tid).ok())
+                .collect(),
+        );
+    }
+
+    /// How the thread `tid` was stopped by [`Self::suspend_threads`].
+    pub fn thread_stop(&self, tid: Pid) -> Option<&ThreadStop> {
+        self.thread_stops.get(&tid)
+    }
+
+    /// Return the information of the thread at `index` read by
+    /// [`Self::capture_thread_infos`], if it could be read.
+    pub fn captured_thread_info(&self, index: usize) -> Option<&ThreadInfo> {
+        self.thread_infos.0.get(index)?.as_ref()
+    }
+
     /// Read thread info from /proc/$pid/status.
     /// Fill out the |tgid|, |ppid| and |pid| members of |info|. If unavailable,
     /// these members are set to -1. Returns true if all three members are
@@ -363,8 +650,38 @@ impl PtraceDumper {
     // not in case of stack overflows, hence the returned pointer might be
     // different from the one that was passed in.
     pub fn get_stack_info(&self, int_stack_pointer: usize) -> Result<(usize, usize), DumperError> {
+        self.get_stack_info_with_red_zone(int_stack_pointer, 0)
+    }
+
+    /// Like [`Self::get_stack_info`], with the stack starting at least
+    /// `red_zone` bytes below the stack pointer, but not before the start of
+    /// its mapping.
+    pub fn get_stack_info_with_red_zone(
+        &self,
+        int_stack_pointer: usize,
+        red_zone: usize,
+    ) -> Result<(usize, usize), DumperError> {
         // Round the stack pointer to the nearest page, this will cause us to
         // capture data below the stack pointer which might still be relevant.
+        let stack_pointer = int_stack_pointer.saturating_sub(red_zone) & !(self.page_size - 1);
+
+        self.find_stack_mapping(int_stack_pointer)
+            .map(|mapping| {
+                let valid_stack_pointer = if mapping.contains_address(stack_pointer) {
+                    stack_pointer
+                } else {
+                    mapping.start_address
+                };
+
+                let stack_len = mapping.size - (valid_stack_pointer - mapping.start_address);
+                (valid_stack_pointer, stack_len)
+            })
+            .ok_or(DumperError::NoStackPointerMapping)
+    }
+
+    /// Find the mapping holding the stack the given stack pointer points into,
+    /// skipping over the guard page if the stack pointer landed on it.
+    pub fn find_stack_mapping(&self, int_stack_pointer: usize) -> Option<&MappingInfo> {
         let mut stack_pointer = int_stack_pointer & !(self.page_size - 1);
         let mut mapping = self.find_mapping(stack_pointer);
 
@@ -384,17 +701,76 @@ impl PtraceDumper {
         }
 
         mapping
-            .map(|mapping| {
-                let valid_stack_pointer = if mapping.contains_address(stack_pointer) {
-                    stack_pointer
-                } else {
-                    mapping.start_address
-                };
+    }
 
-                let stack_len = mapping.size - (valid_stack_pointer - mapping.start_address);
-                (valid_stack_pointer, stack_len)
+    /// Find the stack mapping of thread `tid`. The kernel labels the main
+    /// thread's one `[stack]`, and before Linux 4.5 those of the other threads
+    /// `[stack:<tid>]`, which is relied upon when present as the stack pointer
+    /// may point elsewhere, e.g. into an alternate signal stack. Otherwise
+    /// this is the mapping `stack_pointer` points into, see
+    /// [`Self::find_stack_mapping`].
+    pub fn find_thread_stack_mapping(
+        &self,
+        tid: Pid,
+        stack_pointer: usize,
+    ) -> Option<&MappingInfo> {
+        let label = if tid == self.pid {
+            "[stack]".to_owned()
+        } else {
+            format!("[stack:{tid}]")
+        };
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.name.as_deref() == Some(std::ffi::OsStr::new(&label)))
+            .or_else(|| self.find_stack_mapping(stack_pointer))
+    }
+
+    /// Return the hole in the address space containing `address`, if it's not
+    /// mapped.
+    pub fn find_unmapped_region(&self, address: usize) -> Option<std::ops::Range<usize>> {
+        if self.find_mapping(address).is_some() {
+            return None;
+        }
+
+        let start = self
+            .mappings
+            .iter()
+            .map(|map| map.start_address + map.size)
+            .filter(|&end| end <= address)
+            .max()
+            .unwrap_or(0);
+        let end = self
+            .mappings
+            .iter()
+            .map(|map| map.start_address)
+            .filter(|&start| start > address)
+            .min()
+            .unwrap_or(usize::MAX);
+        Some(start..end)
+    }
+
+    /// Find the readable memory closest to `address`, above or below it, and
+    /// return the start and length of the (up to) `max_len` bytes of it which
+    /// are nearest to the address.
+    pub fn find_nearest_readable(&self, address: usize, max_len: usize) -> Option<(usize, usize)> {
+        self.mappings
+            .iter()
+            .filter(|map| map.permissions.contains(MMPermissions::READ))
+            .map(|map| {
+                let end = map.start_address + map.size;
+                let len = map.size.min(max_len);
+                if map.start_address > address {
+                    (map.start_address - address, (map.start_address, len))
+                } else if end <= address {
+                    (address - end, (end - len, len))
+                } else {
+                    // The address is within the mapping
+                    let start = address.min(end - len);
+                    (0, (start, len))
+                }
             })
-            .ok_or(DumperError::NoStackPointerMapping)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, region)| region)
     }
 
     fn may_be_stack(mapping: Option<&MappingInfo>) -> bool {
@@ -534,20 +910,48 @@ impl PtraceDumper {
     }
 
     pub fn from_process_memory_for_index<T: module_reader::ReadFromModule>(
-        &mut self,
+        &self,
         idx: usize,
     ) -> Result<T, DumperError> {
         assert!(idx < self.mappings.len());
 
-        Self::from_process_memory_for_mapping(&self.mappings[idx], self.pid)
+        Self::from_process_memory_for_mapping(&self.mappings[idx], self.pid, &self.capture_limits)
     }
 
     pub fn from_process_memory_for_mapping<T: module_reader::ReadFromModule>(
         mapping: &MappingInfo,
         pid: Pid,
+        limits: &CaptureLimits,
     ) -> Result<T, DumperError> {
         Ok(T::read_from_module(
-            module_reader::ProcessReader::new(pid, mapping.start_address).into(),
+            module_reader::ProcessReader::new(pid, mapping.start_address, limits).into(),
         )?)
     }
 }
+
+impl ProcessDumper for PtraceDumper {
+    type Thread = Thread;
+    type Mapping = MappingInfo;
+    type ThreadState = ThreadInfo;
+    type Error = DumperError;
+
+    fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>, DumperError> {
+        Self::copy_from_process(self.pid, address, length)
+    }
+
+    fn threads(&self) -> Result<Cow<'_, [Thread]>, DumperError> {
+        Ok(Cow::Borrowed(&self.threads))
+    }
+
+    fn mappings(&self) -> Result<Cow<'_, [MappingInfo]>, DumperError> {
+        Ok(Cow::Borrowed(&self.mappings))
+    }
+
+    fn memory_map(&self) -> Result<Vec<MemoryMapping>, DumperError> {
+        Ok(maps_reader::read_memory_map(self.pid)?)
+    }
+
+    fn thread_state(&self, thread: &Thread) -> Result<ThreadInfo, DumperError> {
+        Ok(ThreadInfo::create(self.pid, thread.tid)?)
+    }
+}
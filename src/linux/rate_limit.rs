@@ -0,0 +1,289 @@
+//! Limiting how often minidumps are written, so that a process crashing in a
+//! loop, e.g. because its supervisor keeps restarting it, doesn't fill the
+//! disk or flood the crash ingestion endpoint, see
+//! `MinidumpWriter::set_rate_limiter`.
+//!
+//! A [`RateLimiter`] refuses to dump a process, or any process running the
+//! same executable, if it was dumped less than a minimum interval ago, or if
+//! it was already dumped a given number of times over a period. Refused dumps
+//! fail with [`RateLimitError`] before the process is attached to.
+//!
+//! ```
+//! use minidump_writer::rate_limit::{RateLimitKey, RateLimiter};
+//! use std::time::Duration;
+//!
+//! let mut limiter = RateLimiter::new(RateLimitKey::Executable);
+//! limiter
+//!     .set_min_interval(Duration::from_secs(10))
+//!     .set_max_dumps(5, Duration::from_secs(3600));
+//! ```
+//!
+//! Long-lived monitors keep the times of the previous dumps in memory, while
+//! handlers started for every crash, such as a `core_pattern` handler, keep
+//! them in a marker file per process or executable, see
+//! [`RateLimiter::persisted`]. The marker files are locked while they are
+//! updated, so that concurrent handlers don't both get to dump.
+
+use crate::linux::{errors::RateLimitError, Pid};
+use nix::fcntl::{Flock, FlockArg};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{Read, Seek, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// What the dumps are counted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// Each process separately, by pid
+    Process,
+    /// All the processes running the same executable, e.g. the successive
+    /// instances of a service restarted after each crash. Processes whose
+    /// executable can't be read are counted separately.
+    Executable,
+}
+
+/// Where the times of the previous dumps are kept
+#[derive(Debug)]
+enum History {
+    Memory(Mutex<HashMap<String, Vec<u64>>>),
+    Directory(PathBuf),
+}
+
+/// Refuses dumps which come too soon after the previous ones, see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct RateLimiter {
+    key: RateLimitKey,
+    min_interval: Duration,
+    max_dumps: Option<(usize, Duration)>,
+    history: History,
+}
+
+impl RateLimiter {
+    /// A limiter keeping the times of the dumps in memory, which doesn't
+    /// limit anything until limits are set.
+    pub fn new(key: RateLimitKey) -> Self {
+        Self {
+            key,
+            min_interval: Duration::ZERO,
+            max_dumps: None,
+            history: History::Memory(Mutex::default()),
+        }
+    }
+
+    /// Like [`Self::new`], keeping the times of the dumps in marker files in
+    /// `directory`, which must exist, so that they outlive the process.
+    pub fn persisted(directory: impl Into<PathBuf>, key: RateLimitKey) -> Self {
+        Self {
+            history: History::Directory(directory.into()),
+            ..Self::new(key)
+        }
+    }
+
+    /// Refuse dumps less than `min_interval` after the previous one.
+    pub fn set_min_interval(&mut self, min_interval: Duration) -> &mut Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Refuse dumps once `max_dumps` were written over the last `period`.
+    pub fn set_max_dumps(&mut self, max_dumps: usize, period: Duration) -> &mut Self {
+        self.max_dumps = Some((max_dumps, period));
+        self
+    }
+
+    /// Check whether process `pid` can be dumped now, and count the dump if
+    /// so. Failing to keep track of the dumps doesn't prevent them, so that
+    /// crashes aren't lost to a full disk or a read-only directory.
+    pub fn check(&self, pid: Pid) -> Result<(), RateLimitError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.check_at(&self.key_of(pid), now)
+    }
+
+    fn check_at(&self, key: &str, now: u64) -> Result<(), RateLimitError> {
+        match &self.history {
+            History::Memory(dumps) => {
+                let mut dumps = dumps.lock().unwrap_or_else(|e| e.into_inner());
+                let result = self.admit(key, dumps.entry(key.to_owned()).or_default(), now);
+                // Forget the processes which weren't dumped recently
+                let window = self.window();
+                dumps.retain(|_, times| {
+                    times.iter().any(|&time| time <= now && now - time < window)
+                });
+                result
+            }
+            History::Directory(directory) => {
+                // The characters which can't be in file names are escaped
+                let name = key.replace('%', "%25").replace('/', "%2F");
+                let path = directory.join(format!("{name}.dumps"));
+                let result = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&path)
+                    .and_then(|file| {
+                        Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e.into())
+                    });
+                let mut file = match result {
+                    Ok(file) => file,
+                    Err(e) => {
+                        log::warn!("failed to open {}: {e}", path.display());
+                        return Ok(());
+                    }
+                };
+
+                let mut contents = String::new();
+                if let Err(e) = file.read_to_string(&mut contents) {
+                    log::warn!("failed to read {}: {e}", path.display());
+                }
+                let mut dumps = contents
+                    .lines()
+                    .filter_map(|line| line.parse().ok())
+                    .collect();
+                self.admit(key, &mut dumps, now)?;
+
+                let contents: String = dumps.iter().map(|time| format!("{time}\n")).collect();
+                let result = file
+                    .rewind()
+                    .and_then(|_| file.set_len(0))
+                    .and_then(|_| file.write_all(contents.as_bytes()));
+                if let Err(e) = result {
+                    log::warn!("failed to write {}: {e}", path.display());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Check whether a dump can be written at `now`, given the times of the
+    /// previous `dumps`, in milliseconds since the epoch, and add it to them
+    /// if so. The dumps which don't matter anymore are forgotten.
+    fn admit(&self, key: &str, dumps: &mut Vec<u64>, now: u64) -> Result<(), RateLimitError> {
+        let min_interval = self.min_interval.as_millis() as u64;
+        let (max_dumps, period) = self.max_dumps.map_or((usize::MAX, 0), |(max, period)| {
+            (max, period.as_millis() as u64)
+        });
+        // Dumps "from the future" are only left by a clock which went back
+        let window = self.window();
+        dumps.retain(|&time| time <= now && now - time < window);
+        dumps.sort_unstable();
+
+        if let Some(elapsed) = dumps.last().map(|&last| now - last) {
+            if elapsed < min_interval {
+                return Err(RateLimitError::TooSoon {
+                    key: key.to_owned(),
+                    retry_after: Duration::from_millis(min_interval - elapsed),
+                });
+            }
+        }
+        let recent: Vec<_> = dumps.iter().filter(|&&time| now - time < period).collect();
+        if recent.len() >= max_dumps {
+            // Once enough of the recent dumps are old enough, never if no
+            // dumps are allowed at all
+            let elapsed = recent
+                .get(recent.len() - max_dumps)
+                .map_or(0, |&&time| now - time);
+            return Err(RateLimitError::TooManyDumps {
+                key: key.to_owned(),
+                retry_after: Duration::from_millis(period - elapsed),
+            });
+        }
+
+        dumps.push(now);
+        Ok(())
+    }
+
+    /// How long the dumps matter for, in milliseconds.
+    fn window(&self) -> u64 {
+        let period = self.max_dumps.map_or(Duration::ZERO, |(_, period)| period);
+        self.min_interval.max(period).as_millis() as u64
+    }
+
+    fn key_of(&self, pid: Pid) -> String {
+        let executable = match self.key {
+            RateLimitKey::Process => None,
+            RateLimitKey::Executable => std::fs::read_link(format!("/proc/{pid}/exe")).ok(),
+        };
+        match executable {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => format!("pid-{pid}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1000;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(RateLimitKey::Process);
+        limiter
+            .set_min_interval(Duration::from_secs(10))
+            .set_max_dumps(2, Duration::from_secs(60));
+        limiter.check_at("a", 100 * SECOND).unwrap();
+        assert_eq!(
+            limiter.check_at("a", 105 * SECOND),
+            Err(RateLimitError::TooSoon {
+                key: "a".to_owned(),
+                retry_after: Duration::from_secs(5)
+            })
+        );
+        // The keys are independent
+        limiter.check_at("b", 105 * SECOND).unwrap();
+        limiter.check_at("a", 120 * SECOND).unwrap();
+        assert_eq!(
+            limiter.check_at("a", 140 * SECOND),
+            Err(RateLimitError::TooManyDumps {
+                key: "a".to_owned(),
+                retry_after: Duration::from_secs(20)
+            })
+        );
+        limiter.check_at("a", 160 * SECOND).unwrap();
+    }
+
+    #[test]
+    fn test_persisted_rate_limiter() {
+        let directory = tempfile::tempdir().unwrap();
+        let limiter = || {
+            let mut limiter = RateLimiter::persisted(directory.path(), RateLimitKey::Executable);
+            limiter.set_min_interval(Duration::from_secs(10));
+            limiter
+        };
+        limiter().check_at("/usr/bin/crash", 100 * SECOND).unwrap();
+        // Another handler knows about the previous dump
+        assert!(limiter().check_at("/usr/bin/crash", 105 * SECOND).is_err());
+        limiter().check_at("/usr/bin/crash", 110 * SECOND).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("%2Fusr%2Fbin%2Fcrash.dumps")).unwrap(),
+            "110000\n"
+        );
+
+        // The dumps aren't prevented if they can't be counted
+        let limiter =
+            RateLimiter::persisted(directory.path().join("missing"), RateLimitKey::Process);
+        limiter.check_at("pid-1", 100 * SECOND).unwrap();
+    }
+
+    #[test]
+    fn test_key() {
+        let pid = std::process::id() as Pid;
+        let limiter = RateLimiter::new(RateLimitKey::Executable);
+        assert_eq!(
+            limiter.key_of(pid),
+            std::env::current_exe().unwrap().to_string_lossy()
+        );
+        let limiter = RateLimiter::new(RateLimitKey::Process);
+        assert_eq!(limiter.key_of(pid), format!("pid-{pid}"));
+    }
+}
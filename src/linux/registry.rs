@@ -0,0 +1,279 @@
+//! Data blocks the process registers for the writer to capture, e.g. its
+//! version, the features it enabled or a buffer of breadcrumbs, written to
+//! the RegisteredBlocks stream of its dumps.
+//!
+//! The process keeps a [`Registry`], a table in its own memory pointing to
+//! the blocks, which the writer finds and reads when it dumps it, so nothing
+//! has to be done at crash time and the writer needs no configuration:
+//!
+//! ```
+//! use minidump_writer::registry::{BlockKind, Registry};
+//!
+//! static VERSION: &[u8] = b"1.2.3";
+//!
+//! let mut registry = Registry::new(16).unwrap();
+//! registry.register("version", BlockKind::Text, VERSION).unwrap();
+//! ```
+//!
+//! A process may have several registries, e.g. one per library, their
+//! blocks are all captured. The registry must be kept alive as long as the
+//! blocks should be captured.
+//!
+//! # Layout
+//!
+//! The table is mapped from a memfd named [`REGISTRY_NAME`], which is how
+//! the writer finds it in `/proc/<pid>/maps`. Its fields are in the native
+//! byte order, and it starts with a header:
+//!
+//! | Offset | Size | Field                                              |
+//! |--------|------|----------------------------------------------------|
+//! | 0      | 8    | [`REGISTRY_MAGIC`]                                 |
+//! | 8      | 4    | [`REGISTRY_VERSION`]                               |
+//! | 12     | 4    | The capacity, how many entries there's room for    |
+//! | 16     | 4    | The number of entries in use                       |
+//! | 20     | 12   | Reserved, zero                                     |
+//!
+//! followed by `capacity` entries:
+//!
+//! | Offset | Size | Field                                              |
+//! |--------|------|----------------------------------------------------|
+//! | 0      | 40   | The name of the block, UTF-8 padded with zeroes    |
+//! | 40     | 4    | The [`BlockKind`] of the block                     |
+//! | 44     | 4    | Reserved, zero                                     |
+//! | 48     | 8    | The address of the block                           |
+//! | 56     | 8    | The size of the block in bytes                     |
+//!
+//! An entry is filled before the count of entries is updated, so the writer
+//! never reads one which is half written, and the entries are never removed.
+//! The blocks are read when the dump is written, they may change until then.
+
+use crate::linux::{maps_reader::MappingInfo, sections::LinuxDumper};
+use memmap2::{MmapMut, MmapOptions};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use std::{
+    ffi::CStr,
+    fs::File,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// The name of the memfd the registry is mapped from, which is listed as
+/// `/memfd:minidump-writer-registry (deleted)` in `/proc/<pid>/maps`
+pub const REGISTRY_NAME: &CStr = c"minidump-writer-registry";
+pub const REGISTRY_MAGIC: [u8; 8] = *b"MDWRREG\0";
+pub const REGISTRY_VERSION: u32 = 1;
+/// The longest name of a block, in bytes
+pub const MAX_NAME_LEN: usize = 40;
+/// The most entries a registry can have
+pub const MAX_CAPACITY: u32 = 4096;
+
+const HEADER_SIZE: usize = 32;
+const ENTRY_SIZE: usize = 64;
+const COUNT_OFFSET: usize = 16;
+
+/// How the contents of a block should be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum BlockKind {
+    Bytes = 0,
+    /// UTF-8 text
+    Text = 1,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("the capacity {0} is over the limit of {MAX_CAPACITY}")]
+    CapacityTooLarge(u32),
+    #[error("the block name {0:?} is empty, longer than {MAX_NAME_LEN} bytes or holds a NUL")]
+    InvalidName(String),
+    #[error("the registry is full")]
+    Full,
+    #[error("failed to map the registry")]
+    Map(#[from] std::io::Error),
+    #[error("failed to create the memfd of the registry")]
+    MemfdCreate(#[from] nix::Error),
+}
+
+/// A table of the data blocks of the process to capture in its dumps, see
+/// [`crate::registry`].
+pub struct Registry {
+    map: MmapMut,
+}
+
+impl Registry {
+    /// Map a registry with room for `capacity` blocks.
+    pub fn new(capacity: u32) -> Result<Self, RegistryError> {
+        if capacity > MAX_CAPACITY {
+            return Err(RegistryError::CapacityTooLarge(capacity));
+        }
+        let file = File::from(memfd_create(REGISTRY_NAME, MemFdCreateFlag::MFD_CLOEXEC)?);
+        file.set_len(registry_size(capacity) as u64)?;
+        // A private mapping, so that the processes forked later on get a copy
+        // of the registry rather than sharing it
+        // SAFETY: nothing else has access to the memfd
+        let mut map = unsafe { MmapOptions::new().map_copy(&file)? };
+
+        map[..8].copy_from_slice(&REGISTRY_MAGIC);
+        map[8..12].copy_from_slice(&REGISTRY_VERSION.to_ne_bytes());
+        map[12..16].copy_from_slice(&capacity.to_ne_bytes());
+        Ok(Self { map })
+    }
+
+    /// The address of the registry in the memory of the process
+    pub fn address(&self) -> usize {
+        self.map.as_ptr() as usize
+    }
+
+    /// Capture `data` in the dumps, under `name`.
+    pub fn register(
+        &mut self,
+        name: &str,
+        kind: BlockKind,
+        data: &'static [u8],
+    ) -> Result<(), RegistryError> {
+        // SAFETY: static data lives as long as the process
+        unsafe { self.register_raw(name, kind, data.as_ptr() as usize, data.len()) }
+    }
+
+    /// Capture the `size` bytes at `address` in the dumps, under `name`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay allocated as long as the registry does, as it's
+    /// read whenever the process is dumped. The writer reads it from another
+    /// process so it's not undefined behavior if it doesn't, but the dump
+    /// would then hold whatever the memory was reused for.
+    pub unsafe fn register_raw(
+        &mut self,
+        name: &str,
+        kind: BlockKind,
+        address: usize,
+        size: usize,
+    ) -> Result<(), RegistryError> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN || name.contains('\0') {
+            return Err(RegistryError::InvalidName(name.to_owned()));
+        }
+        let count = self.count();
+        if count >= self.capacity() {
+            return Err(RegistryError::Full);
+        }
+
+        let offset = HEADER_SIZE + count as usize * ENTRY_SIZE;
+        let entry = &mut self.map[offset..offset + ENTRY_SIZE];
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        entry[40..44].copy_from_slice(&(kind as u32).to_ne_bytes());
+        entry[48..56].copy_from_slice(&(address as u64).to_ne_bytes());
+        entry[56..64].copy_from_slice(&(size as u64).to_ne_bytes());
+        self.count_field().store(count + 1, Ordering::Release);
+        Ok(())
+    }
+
+    fn capacity(&self) -> u32 {
+        u32::from_ne_bytes(self.map[12..16].try_into().unwrap())
+    }
+
+    fn count(&self) -> u32 {
+        self.count_field().load(Ordering::Relaxed)
+    }
+
+    fn count_field(&self) -> &AtomicU32 {
+        // SAFETY: the map is page aligned, so the count is aligned, and it's
+        // only ever accessed atomically
+        unsafe { AtomicU32::from_ptr(self.map.as_ptr().add(COUNT_OFFSET) as *mut u32) }
+    }
+}
+
+/// A block read from a registry of the process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredBlock {
+    pub name: String,
+    /// The [`BlockKind`] of the block, which may be one this version doesn't
+    /// know
+    pub kind: u32,
+    pub address: usize,
+    pub size: usize,
+}
+
+/// The size of a registry with room for `capacity` blocks
+fn registry_size(capacity: u32) -> usize {
+    HEADER_SIZE + capacity as usize * ENTRY_SIZE
+}
+
+/// Whether `mapping` holds a registry
+fn is_registry(mapping: &MappingInfo) -> bool {
+    let name = REGISTRY_NAME.to_bytes();
+    mapping.name.as_ref().is_some_and(|mapping_name| {
+        mapping_name
+            .as_encoded_bytes()
+            .strip_prefix(b"/memfd:")
+            .is_some_and(|mapping_name| mapping_name == name)
+    })
+}
+
+/// Read the blocks listed in the registries of the process, leaving out the
+/// registries which can't be read or are malformed, and the entries whose
+/// address or size don't fit in a pointer.
+pub(crate) fn read_blocks(dumper: &impl LinuxDumper) -> Vec<RegisteredBlock> {
+    let Ok(mappings) = dumper.mappings() else {
+        return Vec::new();
+    };
+    mappings
+        .iter()
+        .filter(|mapping| is_registry(mapping))
+        .flat_map(|mapping| {
+            read_registry(dumper, mapping).unwrap_or_else(|| {
+                log::warn!(
+                    "failed to read the registry at {:#x}",
+                    mapping.start_address
+                );
+                Vec::new()
+            })
+        })
+        .collect()
+}
+
+fn read_registry(dumper: &impl LinuxDumper, mapping: &MappingInfo) -> Option<Vec<RegisteredBlock>> {
+    let header = dumper
+        .read_memory(mapping.start_address, HEADER_SIZE)
+        .ok()?;
+    if header.get(..8)? != REGISTRY_MAGIC || read_u32(&header, 8)? != REGISTRY_VERSION {
+        return None;
+    }
+    let capacity = read_u32(&header, 12)?;
+    let count = read_u32(&header, COUNT_OFFSET)?.min(capacity);
+    if capacity > MAX_CAPACITY || registry_size(capacity) > mapping.size {
+        return None;
+    }
+
+    let entries = dumper
+        .read_memory(
+            mapping.start_address + HEADER_SIZE,
+            count as usize * ENTRY_SIZE,
+        )
+        .ok()?;
+    let blocks = entries
+        .chunks_exact(ENTRY_SIZE)
+        .filter_map(|entry| {
+            let name = &entry[..MAX_NAME_LEN];
+            let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            Some(RegisteredBlock {
+                name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                kind: read_u32(entry, 40)?,
+                address: read_u64(entry, 48)?.try_into().ok()?,
+                size: read_u64(entry, 56)?.try_into().ok()?,
+            })
+        })
+        .collect();
+    Some(blocks)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
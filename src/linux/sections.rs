@@ -1,12 +1,44 @@
+pub mod alternate_signal_stack;
 pub mod app_memory;
+pub mod assertion_info_stream;
+pub mod backtraces_stream;
+pub mod crashpad_info_stream;
+pub mod emulation_stream;
 pub mod exception_stream;
+pub mod exploitability_stream;
+pub mod fault_address_memory;
+pub mod go_build_info_stream;
 pub mod handle_data_stream;
+pub mod heap_summary_stream;
+pub mod jit_memory;
+pub mod jit_symbols_stream;
 pub mod mappings;
+pub mod memory64_list_stream;
 pub mod memory_info_list_stream;
 pub mod memory_list_stream;
+pub mod misc_info_stream;
+pub mod module_images;
+pub mod namespaces_stream;
+pub mod referenced_memory;
+pub mod register_memory;
+pub mod registry_stream;
+pub mod replaced_modules_stream;
+pub mod secondary_exceptions_stream;
+pub mod security_context_stream;
+pub mod signal_dispositions_stream;
+pub mod soft_errors_stream;
 pub mod systeminfo_stream;
+pub mod thread_info_list_stream;
 pub mod thread_list_stream;
+pub mod thread_local_storage;
 pub mod thread_names_stream;
+pub mod thread_signals_stream;
+pub mod thread_stacks_stream;
+pub mod thread_wait_states_stream;
+pub mod timestamps_stream;
+pub mod user_streams;
+pub mod writer_info_stream;
+pub mod xstate_stream;
 
 use crate::{
     dir_section::DumpBuf,
@@ -14,7 +46,164 @@ use crate::{
     linux::{
         minidump_writer::{self, MinidumpWriter},
         ptrace_dumper::PtraceDumper,
+        Pid,
     },
     mem_writer::*,
     minidump_format::*,
 };
+
+/// The dumper the generic streams are written against, see
+/// [`crate::process_dumper`]. This is implemented by [`PtraceDumper`], and by
+/// mocks in the tests.
+pub trait LinuxDumper:
+    crate::process_dumper::ProcessDumper<
+    Thread = crate::linux::ptrace_dumper::Thread,
+    Mapping = crate::linux::maps_reader::MappingInfo,
+    ThreadState = crate::linux::thread_info::ThreadInfo,
+    Error = errors::DumperError,
+>
+{
+}
+
+impl<D> LinuxDumper for D where
+    D: crate::process_dumper::ProcessDumper<
+        Thread = crate::linux::ptrace_dumper::Thread,
+        Mapping = crate::linux::maps_reader::MappingInfo,
+        ThreadState = crate::linux::thread_info::ThreadInfo,
+        Error = errors::DumperError,
+    >
+{
+}
+
+/// A process which only exists in memory, for testing the streams written
+/// against [`LinuxDumper`].
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use crate::linux::{
+        errors::{CopyFromProcessError, DumperError, ThreadInfoError},
+        maps_reader::{MappingInfo, SystemMappingInfo},
+        ptrace_dumper::Thread,
+        thread_info::ThreadInfo,
+    };
+    use crate::{memory_map::MemoryMapping, process_dumper::ProcessDumper};
+    use procfs_core::process::MMPermissions;
+    use std::borrow::Cow;
+
+    pub const MOCK_PID: Pid = 1000;
+
+    #[derive(Default)]
+    pub struct MockDumper {
+        pub threads: Vec<Thread>,
+        pub mappings: Vec<MappingInfo>,
+        /// The readable memory, as (address, contents) pairs
+        pub memory: Vec<(usize, Vec<u8>)>,
+    }
+
+    impl ProcessDumper for MockDumper {
+        type Thread = Thread;
+        type Mapping = MappingInfo;
+        type ThreadState = ThreadInfo;
+        type Error = DumperError;
+
+        fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>, DumperError> {
+            self.memory
+                .iter()
+                .find_map(|(start, contents)| {
+                    let offset = address.checked_sub(*start)?;
+                    Some(contents.get(offset..offset.checked_add(length)?)?.to_vec())
+                })
+                .ok_or(DumperError::CopyFromProcessError(CopyFromProcessError {
+                    child: MOCK_PID,
+                    src: address,
+                    offset: 0,
+                    length,
+                    source: nix::Error::EFAULT,
+                }))
+        }
+
+        fn threads(&self) -> Result<Cow<'_, [Thread]>, DumperError> {
+            Ok(Cow::Borrowed(&self.threads))
+        }
+
+        fn mappings(&self) -> Result<Cow<'_, [MappingInfo]>, DumperError> {
+            Ok(Cow::Borrowed(&self.mappings))
+        }
+
+        fn memory_map(&self) -> Result<Vec<MemoryMapping>, DumperError> {
+            Ok(self.mappings.iter().map(MemoryMapping::from).collect())
+        }
+
+        fn thread_state(&self, thread: &Thread) -> Result<ThreadInfo, DumperError> {
+            Err(ThreadInfoError::IndexOutOfBounds(thread.tid as usize, self.threads.len()).into())
+        }
+    }
+
+    /// A mapping of `size` bytes at `start_address`, at offset 0 of `name`
+    pub fn mapping(
+        start_address: usize,
+        size: usize,
+        permissions: MMPermissions,
+        name: Option<&str>,
+    ) -> MappingInfo {
+        MappingInfo {
+            start_address,
+            size,
+            system_mapping_info: SystemMappingInfo {
+                start_address,
+                end_address: start_address + size,
+            },
+            offset: 0,
+            permissions,
+            name: name.map(Into::into),
+            deleted: false,
+        }
+    }
+
+    pub fn read_u32(buffer: &DumpBuf, offset: usize) -> u32 {
+        u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Reads the `MDString` at `rva`
+    pub fn read_string(buffer: &DumpBuf, rva: usize) -> String {
+        let len = read_u32(buffer, rva) as usize;
+        let letters: Vec<u16> = buffer[rva + 4..rva + 4 + len]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16(&letters).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mock::*, *};
+
+    #[test]
+    fn test_optional_section_failure() {
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+
+        let dirent: MDRawDirectory = config
+            .write_optional("ModuleList", |_| {
+                Err(errors::SectionMappingsError::MemoryWriterError(
+                    MemoryWriterError::TryFromIntError(u8::try_from(256u32).unwrap_err()),
+                ))
+            })
+            .unwrap();
+        assert_eq!((dirent.stream_type, dirent.location.data_size), (0, 0));
+        assert_eq!(config.omitted_sections, ["ModuleList"]);
+        assert!(matches!(
+            config.soft_errors.as_slice(),
+            [errors::SoftError::SectionOmitted("ModuleList", _)]
+        ));
+
+        // Cancelling still stops the dump
+        let result: Result<(), _> = config.write_optional("ReferencedMemory", |_| {
+            Err(errors::SectionReferencedMemoryError::Cancelled(
+                errors::CancelledError::DeadlineExceeded,
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(config.omitted_sections, ["ModuleList"]);
+    }
+}
@@ -0,0 +1,70 @@
+use super::*;
+
+/// Write the alternate signal stack of the crashing thread to the memory list,
+/// when the crash is being handled on it.
+///
+/// This is what happens with stack overflows, the main reason alternate
+/// stacks exist: the thread's own stack has no room left to run the signal
+/// handler. The thread list captures the stack of the crash, at the stack
+/// pointer of the crash context, while the thread's current stack pointer
+/// lies in the alternate stack, which would be lost otherwise.
+///
+/// The alternate stack is found in the crash context, so nothing is written
+/// without one. It's truncated to
+/// [`crate::capture_limits::CaptureLimits::max_region_size`], and left out
+/// if it doesn't fit in [`MinidumpWriter::set_max_dump_size`] or
+/// [`MinidumpWriter::set_max_memory_regions`].
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<(), errors::SectionAlternateSignalStackError> {
+    let Some(altstack) = config
+        .crash_context
+        .as_ref()
+        .and_then(|crash_context| crash_context.alternate_signal_stack())
+    else {
+        return Ok(());
+    };
+    let stack_pointer = dumper
+        .threads
+        .iter()
+        .position(|thread| thread.tid == config.blamed_thread)
+        .and_then(|idx| dumper.captured_thread_info(idx))
+        .map(|info| info.stack_pointer);
+    if !stack_pointer.is_some_and(|stack_pointer| altstack.contains(&stack_pointer)) {
+        return Ok(());
+    }
+
+    let requested = memory_list_stream::capped(config, altstack);
+    for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, requested) {
+        if !config.fits_in_max_dump_size(buffer, range.len()) {
+            log::warn!("dump size limit reached, leaving out the alternate signal stack");
+            config
+                .soft_errors
+                .push(errors::SoftError::MemoryRegionsOmitted(1));
+            continue;
+        }
+        if !config.fits_in_max_memory_regions() {
+            log::warn!("memory region limit reached, leaving out the alternate signal stack");
+            config
+                .soft_errors
+                .push(errors::SoftError::MemoryRegionLimitReached(1));
+            continue;
+        }
+
+        config.check_cancelled()?;
+        let parts = memory_list_stream::read_readable_parts(dumper, range.clone())?;
+        let read = parts.iter().map(|(_, bytes)| bytes.len()).sum();
+        memory_list_stream::note_trimmed(config, range, read);
+        for (start, mut data_copy) in parts {
+            config.redact_memory(start, &mut data_copy);
+            let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+            config.memory_blocks.push(MDMemoryDescriptor {
+                start_of_memory_range: start as u64,
+                memory: section.location(),
+            });
+        }
+    }
+    Ok(())
+}
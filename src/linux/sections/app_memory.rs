@@ -1,23 +1,363 @@
 use super::*;
+use crate::linux::app_memory::AppMemory;
 
-/// Write application-provided memory regions.
+/// Write application-provided memory regions, those registered and those
+/// returned by [`MinidumpWriter::add_app_memory_callback`], leaving out those
+/// which don't fit in [`MinidumpWriter::set_max_dump_size`] or
+/// [`MinidumpWriter::set_max_memory_regions`].
+///
+/// Overlapping regions are merged, and the parts which were already captured,
+/// e.g. as part of a stack, are left out, so that the memory list is made of
+/// disjoint regions. With [`MinidumpWriter::page_align_memory`] the regions
+/// are rounded out to whole pages first, and truncated to
+/// [`crate::capture_limits::CaptureLimits::max_region_size`]. The pages of a region which can't be
+/// read, e.g. guard pages, are left out of it. The regions which 32-bit RVAs
+/// can't reach, past the first 4GiB of the minidump, are moved to the
+/// Memory64List instead, see [`MinidumpWriter::spilled_app_memory`].
 pub fn write(
     config: &mut MinidumpWriter,
     buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
 ) -> Result<(), errors::SectionAppMemoryError> {
-    for app_memory in &config.app_memory {
-        let data_copy = PtraceDumper::copy_from_process(
-            config.blamed_thread,
-            app_memory.ptr,
-            app_memory.length,
-        )?;
+    let mappings = dumper.mappings()?;
+    let mut callbacks = std::mem::take(&mut config.app_memory_callbacks);
+    let computed: Vec<_> = callbacks
+        .iter_mut()
+        .flat_map(|callback| callback(config.process_id, config.crash_context.as_ref()))
+        .collect();
+    config.app_memory_callbacks = callbacks;
 
-        let section = MemoryArrayWriter::write_bytes(buffer, &data_copy);
-        let desc = MDMemoryDescriptor {
-            start_of_memory_range: app_memory.ptr as u64,
-            memory: section.location(),
-        };
-        config.memory_blocks.push(desc);
+    let requested = memory_list_stream::merge_ranges(
+        config
+            .app_memory
+            .iter()
+            .chain(&computed)
+            .map(|app_memory| {
+                memory_list_stream::page_aligned(
+                    config,
+                    &mappings,
+                    app_memory.ptr..app_memory.ptr + app_memory.length,
+                )
+            })
+            .collect(),
+    );
+
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    for requested in requested {
+        let requested = memory_list_stream::capped(config, requested);
+        for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, requested) {
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                over_limit += 1;
+                continue;
+            }
+
+            config.check_cancelled()?;
+            let parts = memory_list_stream::read_readable_parts(dumper, range.clone())?;
+            let read = parts.iter().map(|(_, bytes)| bytes.len()).sum();
+            memory_list_stream::note_trimmed(config, range, read);
+
+            for (start, mut data_copy) in parts {
+                config.redact_memory(start, &mut data_copy);
+                let section = match MemoryArrayWriter::write_bytes(buffer, &data_copy) {
+                    Ok(section) => section,
+                    Err(MemoryWriterError::RvaOverflow { .. }) => {
+                        config.spilled_app_memory.push(AppMemory {
+                            ptr: start,
+                            length: data_copy.len(),
+                        });
+                        continue;
+                    }
+                    Err(error) => return Err(error.into()),
+                };
+                let desc = MDMemoryDescriptor {
+                    start_of_memory_range: start as u64,
+                    memory: section.location(),
+                };
+                config.memory_blocks.push(desc);
+            }
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} application memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!(
+            "memory region limit reached, leaving out {over_limit} application memory regions"
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_app_memory() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, (0..=255).collect())],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.app_memory.push(AppMemory {
+            ptr: 0x1010,
+            length: 4,
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let block = &config.memory_blocks[0];
+        assert_eq!(block.start_of_memory_range, 0x1010);
+        let rva = block.memory.rva as usize;
+        assert_eq!(&buffer[rva..rva + 4], &[0x10, 0x11, 0x12, 0x13]);
+
+        // Memory which can't be read is left out
+        config.app_memory.push(AppMemory {
+            ptr: 0x10f0,
+            length: 0x20,
+        });
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(config.memory_blocks.len(), 1);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::MemoryRegionTrimmed(0x10f0, 0x20)]
+        ));
+    }
+
+    #[test]
+    fn test_app_memory_region_size_limit() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, vec![0xab; 0x100])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.set_capture_limits(crate::capture_limits::CaptureLimits {
+            max_region_size: 0x10,
+            ..Default::default()
+        });
+        // A corrupted length only gets the start of the region captured
+        config.app_memory.push(AppMemory {
+            ptr: 0x1000,
+            length: usize::MAX / 2,
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| (block.start_of_memory_range, block.memory.data_size))
+            .collect();
+        assert_eq!(regions, [(0x1000, 0x10)]);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::MemoryRegionTruncated(0x1000, length)] if length == usize::MAX / 2
+        ));
+    }
+
+    #[test]
+    fn test_registered_app_memory() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, (0..=255).collect())],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config
+            .register_app_memory(0x1010, 2)
+            .register_app_memory(0x1020, 4)
+            .register_app_memory(0x1010, 4)
+            .register_app_memory(0x1030, 4)
+            .unregister_app_memory(0x1020);
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| (block.start_of_memory_range, block.memory.data_size))
+            .collect();
+        assert_eq!(regions, [(0x1010, 4), (0x1030, 4)]);
+    }
+
+    #[test]
+    fn test_app_memory_max_dump_size() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, vec![0; 0x1000])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.set_max_dump_size(0);
+        config.app_memory.push(AppMemory {
+            ptr: 0x1000,
+            length: 0x1000,
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert!(config.memory_blocks.is_empty());
+        assert_eq!(buffer.position(), 0);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::MemoryRegionsOmitted(1)]
+        ));
+    }
+
+    #[test]
+    fn test_app_memory_max_memory_regions() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, vec![0; 0x1000])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.set_max_memory_regions(2);
+        for ptr in [0x1000, 0x1100, 0x1200] {
+            config.app_memory.push(AppMemory { ptr, length: 0x10 });
+        }
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let starts: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| block.start_of_memory_range)
+            .collect();
+        assert_eq!(starts, [0x1000, 0x1100]);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::MemoryRegionLimitReached(1)]
+        ));
+    }
+
+    #[test]
+    fn test_app_memory_page_aligned() {
+        // SAFETY: no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let readable = 16 * page_size;
+        let unreadable = 32 * page_size;
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(
+                    readable,
+                    2 * page_size,
+                    MMPermissions::READ | MMPermissions::PRIVATE,
+                    None,
+                ),
+                mapping(unreadable, 2 * page_size, MMPermissions::PRIVATE, None),
+            ],
+            memory: vec![
+                (readable, vec![1; 2 * page_size]),
+                (unreadable, vec![2; 2 * page_size]),
+            ],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.page_align_memory();
+        config.app_memory.push(AppMemory {
+            ptr: readable + page_size - 8,
+            length: 16,
+        });
+        config.app_memory.push(AppMemory {
+            ptr: unreadable + 0x10,
+            length: 4,
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| (block.start_of_memory_range as usize, block.memory.data_size))
+            .collect();
+        // Regions in unreadable mappings are left as they are
+        assert_eq!(
+            regions,
+            [(readable, 2 * page_size as u32), (unreadable + 0x10, 4)]
+        );
+    }
+
+    #[test]
+    fn test_memory_redactor() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, vec![0xaa; 0x100])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        // Zero a secret at 0x1040..0x1048, wherever it shows up
+        config.set_memory_redactor(|range, bytes| {
+            let secret = 0x1040..0x1048;
+            let start = secret.start.max(range.start);
+            let end = secret.end.min(range.end);
+            if start < end {
+                bytes[start - range.start..end - range.start].fill(0);
+            }
+        });
+        config.app_memory.push(AppMemory {
+            ptr: 0x1030,
+            length: 0x20,
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let block = &config.memory_blocks[0];
+        let rva = block.memory.rva as usize;
+        let mut expected = [0xaa; 0x20];
+        expected[0x10..0x18].fill(0);
+        assert_eq!(buffer[rva..rva + 0x20], expected);
+    }
+
+    #[test]
+    fn test_app_memory_callback() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, (0..=255).collect())],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config
+            .register_app_memory(0x1010, 4)
+            .add_app_memory_callback(|pid, crash_context| {
+                assert_eq!(pid, MOCK_PID);
+                assert!(crash_context.is_none());
+                vec![AppMemory {
+                    ptr: 0x1020,
+                    length: 4,
+                }]
+            });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| {
+                let rva = block.memory.rva as usize;
+                (
+                    block.start_of_memory_range,
+                    buffer[rva..rva + block.memory.data_size as usize].to_vec(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                (0x1010, vec![0x10, 0x11, 0x12, 0x13]),
+                (0x1020, vec![0x20, 0x21, 0x22, 0x23])
+            ]
+        );
+        // The callback is kept for the next dumps
+        assert_eq!(config.app_memory_callbacks.len(), 1);
+    }
+}
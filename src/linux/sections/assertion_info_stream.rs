@@ -0,0 +1,84 @@
+use super::*;
+
+/// Write the assertion set with [`MinidumpWriter::set_assertion`], nothing is
+/// written if there is none.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionAssertionInfoError> {
+    let Some(assertion) = &config.assertion else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    let info = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawAssertionInfo {
+            expression: utf16_field(&assertion.expression),
+            function: utf16_field(&assertion.function),
+            file: utf16_field(&assertion.file),
+            line: assertion.line,
+            ty: MDRawAssertionInfo::TYPE_UNKNOWN,
+        },
+    )?;
+
+    Ok(MDRawDirectory {
+        stream_type: MDStreamType::AssertionInfoStream as u32,
+        location: info.location(),
+    })
+}
+
+/// `s` as a nul-terminated UTF-16 string, truncated to fit. A surrogate pair
+/// which doesn't fit as a whole is left out.
+fn utf16_field(s: &str) -> [u16; 128] {
+    let mut field = [0; 128];
+    let mut len = 0;
+    for c in s.chars() {
+        let mut units = [0; 2];
+        let units = c.encode_utf16(&mut units);
+        if len + units.len() >= field.len() {
+            break;
+        }
+        field[len..len + units.len()].copy_from_slice(units);
+        len += units.len();
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_assertion_info_stream() {
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&config, &mut buffer).unwrap();
+        assert_eq!(dirent.stream_type, 0);
+
+        config.set_assertion(minidump_writer::AssertionInfo {
+            expression: "len <= capacity".to_owned(),
+            function: "Buffer::push".to_owned(),
+            file: "x".repeat(200),
+            line: 42,
+        });
+        let dirent = write(&config, &mut buffer).unwrap();
+        assert_eq!(dirent.stream_type, MDStreamType::AssertionInfoStream as u32);
+        assert_eq!(dirent.location.data_size, 3 * 256 + 8);
+
+        let rva = dirent.location.rva as usize;
+        let string_at = |offset: usize| {
+            let units: Vec<u16> = buffer[rva + offset..rva + offset + 256]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&unit| unit != 0)
+                .collect();
+            String::from_utf16(&units).unwrap()
+        };
+        assert_eq!(string_at(0), "len <= capacity");
+        assert_eq!(string_at(256), "Buffer::push");
+        // Truncated to leave room for the terminator
+        assert_eq!(string_at(512), "x".repeat(127));
+        assert_eq!(read_u32(&buffer, rva + 768), 42);
+    }
+}
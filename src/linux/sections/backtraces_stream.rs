@@ -0,0 +1,172 @@
+use super::*;
+use crate::linux::crash_context;
+use std::ops::Range;
+
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+
+/// Write the backtrace of each thread found by following its frame pointers,
+/// up to [`MinidumpWriter::capture_backtraces`] frames, so that tools which
+/// can't unwind the stacks themselves get an idea of where each thread was.
+/// On the architectures whose frame records we don't know, only the
+/// instruction pointer of each thread is recorded.
+///
+/// This does nothing unless [`MinidumpWriter::capture_backtraces`] was set.
+/// Threads whose registers couldn't be read are left out.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionBacktracesError> {
+    let Some(max_frames) = config.backtrace_depth else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    let mut backtraces = Vec::new();
+    for (idx, thread) in dumper.threads.iter().enumerate() {
+        config.check_cancelled()?;
+        // As in the thread list, the registers of the blamed thread are
+        // those of the crash rather than those of the signal handler
+        let supplied = (thread.tid == config.blamed_thread)
+            .then(|| config.supplied_blamed_thread_context())
+            .flatten();
+        let cpu = match supplied {
+            Some(cpu) => cpu,
+            None => {
+                let Some(info) = dumper.captured_thread_info(idx) else {
+                    continue;
+                };
+                let mut cpu = Default::default();
+                info.fill_cpu_context(&mut cpu);
+                cpu
+            }
+        };
+
+        let (instruction_ptr, stack_pointer) = crash_context::context_pointers(&cpu);
+        let frames = match (
+            crash_context::context_frame_pointer(&cpu),
+            dumper.get_stack_info(stack_pointer),
+        ) {
+            (Some(frame_pointer), Ok((stack_start, stack_len))) => walk(
+                instruction_ptr,
+                frame_pointer,
+                stack_start..stack_start.saturating_add(stack_len),
+                max_frames,
+                |address| {
+                    PtraceDumper::copy_from_process(dumper.pid, address, 2 * POINTER_SIZE).ok()
+                },
+            ),
+            _ => vec![instruction_ptr as u64],
+        };
+        backtraces.push((thread.tid.try_into()?, frames));
+    }
+
+    // Memory looks like this:
+    // <num_threads><backtrace_1><backtrace_2>...<frames_1><frames_2>...
+    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, backtraces.len() as u32)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxBacktraces as u32,
+        location: list_header.location(),
+    };
+
+    let mut backtrace_list =
+        MemoryArrayWriter::<MDRawThreadBacktrace>::alloc_array(buffer, backtraces.len())?;
+    dirent.location.data_size += backtrace_list.location().data_size;
+
+    for (idx, (thread_id, frames)) in backtraces.into_iter().enumerate() {
+        let frame_list = MemoryArrayWriter::alloc_from_array(buffer, &frames)?;
+        let backtrace = MDRawThreadBacktrace {
+            thread_id,
+            frame_count: frames.len() as u32,
+            frames: frame_list.location(),
+        };
+        backtrace_list.set_value_at(buffer, backtrace, idx)?;
+    }
+
+    Ok(dirent)
+}
+
+/// Follow the chain of frame records starting at `frame_pointer`, each being
+/// the caller's frame pointer followed by the return address, and return
+/// `instruction_ptr` followed by the return addresses, at most `max_frames`
+/// in all. `read_record` reads the two pointers of the record at an address.
+///
+/// The walk stops at the first record outside of `stack`, misaligned, or not
+/// further up the stack than the previous one, as the frame pointer register
+/// is used for other things by code built without frame pointers.
+pub(crate) fn walk(
+    instruction_ptr: usize,
+    frame_pointer: usize,
+    stack: Range<usize>,
+    max_frames: usize,
+    mut read_record: impl FnMut(usize) -> Option<Vec<u8>>,
+) -> Vec<u64> {
+    let mut frames = vec![instruction_ptr as u64];
+    let mut frame_pointer = frame_pointer;
+    while frames.len() < max_frames {
+        if !frame_pointer.is_multiple_of(POINTER_SIZE)
+            || frame_pointer < stack.start
+            || frame_pointer.saturating_add(2 * POINTER_SIZE) > stack.end
+        {
+            break;
+        }
+        let Some(record) = read_record(frame_pointer) else {
+            break;
+        };
+        let Some((caller_frame_pointer, return_address)) = record
+            .get(..2 * POINTER_SIZE)
+            .map(|record| record.split_at(POINTER_SIZE))
+        else {
+            break;
+        };
+        let caller_frame_pointer = usize::from_ne_bytes(caller_frame_pointer.try_into().unwrap());
+        let return_address = usize::from_ne_bytes(return_address.try_into().unwrap());
+        if return_address == 0 {
+            break;
+        }
+
+        frames.push(return_address as u64);
+        if caller_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = caller_frame_pointer;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backtrace_walk() {
+        const PTR: usize = std::mem::size_of::<usize>();
+        let base = 0x1000;
+        let word = |idx: usize| base + idx * PTR;
+        // Three frame records, the last one pointing back down the stack
+        let mut stack = [0usize; 16];
+        stack[2..4].copy_from_slice(&[word(6), 0xaaa]);
+        stack[6..8].copy_from_slice(&[word(10), 0xbbb]);
+        stack[10..12].copy_from_slice(&[word(4), 0xccc]);
+        let read_record = |address: usize| {
+            let idx = (address - base) / PTR;
+            Some(
+                stack[idx..idx + 2]
+                    .iter()
+                    .flat_map(|value| value.to_ne_bytes())
+                    .collect(),
+            )
+        };
+        let range = base..word(stack.len());
+
+        let walk = |frame_pointer, max_frames| {
+            walk(0x999, frame_pointer, range.clone(), max_frames, read_record)
+        };
+        assert_eq!(walk(word(2), 16), [0x999, 0xaaa, 0xbbb, 0xccc]);
+        assert_eq!(walk(word(2), 3), [0x999, 0xaaa, 0xbbb]);
+        // Misaligned or outside of the stack
+        assert_eq!(walk(word(2) + 1, 16), [0x999]);
+        assert_eq!(walk(word(16), 16), [0x999]);
+        assert_eq!(walk(0, 16), [0x999]);
+    }
+}
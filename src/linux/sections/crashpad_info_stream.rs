@@ -0,0 +1,439 @@
+use super::*;
+use crate::linux::module_reader::{CrashpadInfoAddress, PackageMetadata};
+use scroll::{
+    ctx::{SizeWith, TryIntoCtx},
+    Pread,
+};
+
+/// The signature of Crashpad's `CrashpadInfo` structure, `'CPad'`
+const CRASHPAD_INFO_SIGNATURE: u32 = u32::from_be_bytes(*b"CPad");
+const CRASHPAD_INFO_VERSION: u32 = 1;
+/// The offset of the pointers following the fixed-size fields of `CrashpadInfo`
+const CRASHPAD_INFO_POINTERS_OFFSET: usize = 24;
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+/// The layout of Crashpad's `SimpleStringDictionary`, an array of fixed-size
+/// entries whose key is empty when unused
+const DICTIONARY_ENTRIES: usize = 64;
+const DICTIONARY_KEY_LEN: usize = 256;
+const DICTIONARY_VALUE_LEN: usize = 256;
+/// The limits Crashpad puts on annotations
+const MAX_ANNOTATIONS: usize = 200;
+const ANNOTATION_NAME_MAX_LEN: usize = 256;
+const ANNOTATION_VALUE_MAX_LEN: usize = 5 * 4096;
+const ANNOTATION_TYPE_INVALID: u16 = 0;
+/// Strings are read in chunks which never cross a page boundary, as the next
+/// page may not be mapped
+const MIN_PAGE_SIZE: usize = 4096;
+/// The prefix of the annotations holding the fields of the `.note.package`
+/// note of a module, e.g. `package.name` and `package.version`
+pub const PACKAGE_ANNOTATION_PREFIX: &str = "package.";
+
+/// The annotations of a module linked with the Crashpad client.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ModuleAnnotations {
+    pub simple_annotations: Vec<(String, String)>,
+    pub annotation_objects: Vec<Annotation>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub name: String,
+    pub ty: u16,
+    pub value: Vec<u8>,
+}
+
+/// Write the CrashpadInfo stream, holding the annotations set with
+/// [`MinidumpWriter::set_annotation`] and those of the modules linked with
+/// the Crashpad client, which are read from the process. The distribution
+/// package of each module, if described by its `.note.package` note, is
+/// added to its annotations. Nothing is written if there are none.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &mut PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionCrashpadInfoError> {
+    let mut module_links = Vec::new();
+
+    for map_idx in 0..dumper.mappings.len() {
+        let mapping = &dumper.mappings[map_idx];
+        if !mapping.is_interesting() || mapping.offset != 0 || mapping.is_kernel_page() {
+            continue;
+        }
+        let start_address = mapping.start_address;
+        // Crashpad identifies modules by their index in the module list
+        let Some(module_idx) = config
+            .listed_modules
+            .iter()
+            .position(|&base| base == start_address as u64)
+        else {
+            continue;
+        };
+
+        let mut annotations = dumper
+            .from_process_memory_for_index(map_idx)
+            .ok()
+            .and_then(|CrashpadInfoAddress(address)| start_address.checked_add(address as usize))
+            .and_then(|address| read_module_annotations(dumper, address))
+            .unwrap_or_default();
+        if let Ok(PackageMetadata(fields)) = dumper.from_process_memory_for_index(map_idx) {
+            annotations.simple_annotations.extend(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (format!("{PACKAGE_ANNOTATION_PREFIX}{key}"), value)),
+            );
+        }
+        if annotations.simple_annotations.is_empty() && annotations.annotation_objects.is_empty() {
+            continue;
+        }
+
+        module_links.push(MDRawModuleCrashpadInfoLink {
+            minidump_module_list_index: module_idx as u32,
+            location: write_module_annotations(buffer, &annotations)?,
+        });
+    }
+
+    if module_links.is_empty() && config.annotations.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let simple_annotations = write_dictionary(buffer, config.annotations.iter())?;
+    let module_list = write_list(buffer, &module_links)?;
+    let info = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawCrashpadInfo {
+            version: MDRawCrashpadInfo::VERSION,
+            report_id: [0; 16],
+            client_id: [0; 16],
+            simple_annotations,
+            module_list,
+        },
+    )?;
+
+    Ok(MDRawDirectory {
+        stream_type: MDStreamType::CrashpadInfoStream as u32,
+        location: info.location(),
+    })
+}
+
+/// Write a CrashpadInfo stream marking the dump as truncated, with the
+/// [`minidump_writer::TRUNCATED_ANNOTATION`] set to `reason` besides the
+/// annotations set with [`MinidumpWriter::set_annotation`]. The module
+/// annotations of `previous`, the stream written before the dump was
+/// interrupted if any, are kept.
+pub fn write_truncated(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    previous: Option<MDRawDirectory>,
+    reason: &str,
+) -> Result<MDRawDirectory, errors::SectionCrashpadInfoError> {
+    let module_list = match previous {
+        Some(dirent) => {
+            buffer
+                .pread_with::<MDRawCrashpadInfo>(dirent.location.rva as usize, scroll::LE)
+                .map_err(MemoryWriterError::from)?
+                .module_list
+        }
+        None => write_list::<MDRawModuleCrashpadInfoLink>(buffer, &[])?,
+    };
+
+    let truncated = (
+        minidump_writer::TRUNCATED_ANNOTATION.to_owned(),
+        reason.to_owned(),
+    );
+    let simple_annotations = write_dictionary(
+        buffer,
+        config
+            .annotations
+            .iter()
+            .chain(std::iter::once((&truncated.0, &truncated.1))),
+    )?;
+    let info = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawCrashpadInfo {
+            version: MDRawCrashpadInfo::VERSION,
+            report_id: [0; 16],
+            client_id: [0; 16],
+            simple_annotations,
+            module_list,
+        },
+    )?;
+
+    Ok(MDRawDirectory {
+        stream_type: MDStreamType::CrashpadInfoStream as u32,
+        location: info.location(),
+    })
+}
+
+/// Read the annotations of a module from its `CrashpadInfo` structure at
+/// `address`, see `client/crashpad_info.h` in Crashpad.
+pub fn read_module_annotations(
+    dumper: &impl LinuxDumper,
+    address: usize,
+) -> Option<ModuleAnnotations> {
+    let info = dumper
+        .read_memory(address, CRASHPAD_INFO_POINTERS_OFFSET + 4 * POINTER_SIZE)
+        .ok()?;
+    let u32_at = |offset: usize| u32::from_ne_bytes(info[offset..offset + 4].try_into().unwrap());
+    if u32_at(0) != CRASHPAD_INFO_SIGNATURE || u32_at(8) != CRASHPAD_INFO_VERSION {
+        return None;
+    }
+
+    // The structure of older clients ends before the latest pointers
+    let size = u32_at(4) as usize;
+    let pointer = |idx: usize| {
+        let offset = CRASHPAD_INFO_POINTERS_OFFSET + idx * POINTER_SIZE;
+        (offset + POINTER_SIZE <= size)
+            .then(|| read_pointer(&info, offset))
+            .filter(|&pointer| pointer != 0)
+    };
+
+    Some(ModuleAnnotations {
+        simple_annotations: pointer(1)
+            .map(|address| read_dictionary(dumper, address))
+            .unwrap_or_default(),
+        annotation_objects: pointer(3)
+            .map(|address| read_annotation_list(dumper, address))
+            .unwrap_or_default(),
+    })
+}
+
+fn read_pointer(bytes: &[u8], offset: usize) -> usize {
+    usize::from_ne_bytes(bytes[offset..offset + POINTER_SIZE].try_into().unwrap())
+}
+
+fn read_dictionary(dumper: &impl LinuxDumper, address: usize) -> Vec<(String, String)> {
+    const ENTRY_LEN: usize = DICTIONARY_KEY_LEN + DICTIONARY_VALUE_LEN;
+    let Ok(entries) = dumper.read_memory(address, DICTIONARY_ENTRIES * ENTRY_LEN) else {
+        return Vec::new();
+    };
+
+    entries
+        .chunks_exact(ENTRY_LEN)
+        .filter_map(|entry| {
+            let (key, value) = entry.split_at(DICTIONARY_KEY_LEN);
+            let key = c_string(key);
+            (!key.is_empty()).then(|| (key, c_string(value)))
+        })
+        .collect()
+}
+
+/// Read the annotations of an `AnnotationList`, which is a pointer to its
+/// tail followed by its head and tail nodes. Each `Annotation` starts with a
+/// pointer to the next one, its name and its value, followed by the size of
+/// the value as a `u32` and its type as a `u16`.
+fn read_annotation_list(dumper: &impl LinuxDumper, address: usize) -> Vec<Annotation> {
+    const NODE_LEN: usize = 3 * POINTER_SIZE + 6;
+    let mut annotations = Vec::new();
+    let Ok(head) = dumper.read_memory(address + POINTER_SIZE, POINTER_SIZE) else {
+        return annotations;
+    };
+
+    let mut node = read_pointer(&head, 0);
+    for _ in 0..MAX_ANNOTATIONS {
+        let Ok(bytes) = dumper.read_memory(node, NODE_LEN) else {
+            break;
+        };
+        let next = read_pointer(&bytes, 0);
+        // The tail is the only node without a successor
+        if next == 0 {
+            break;
+        }
+
+        let name = read_pointer(&bytes, POINTER_SIZE);
+        let value = read_pointer(&bytes, 2 * POINTER_SIZE);
+        let offset = 3 * POINTER_SIZE;
+        let size = u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let ty = u16::from_ne_bytes(bytes[offset + 4..offset + 6].try_into().unwrap());
+        if ty != ANNOTATION_TYPE_INVALID && size > 0 {
+            let name = read_c_string(dumper, name, ANNOTATION_NAME_MAX_LEN);
+            let value = dumper.read_memory(value, size.min(ANNOTATION_VALUE_MAX_LEN));
+            if let (Some(name), Ok(value)) = (name, value) {
+                annotations.push(Annotation { name, ty, value });
+            }
+        }
+        node = next;
+    }
+    annotations
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+fn read_c_string(dumper: &impl LinuxDumper, mut address: usize, max_len: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < max_len {
+        let len = (MIN_PAGE_SIZE - address % MIN_PAGE_SIZE).min(max_len - bytes.len());
+        let chunk = dumper.read_memory(address, len).ok()?;
+        if let Some(end) = chunk.iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..end]);
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+        address += len;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write_module_annotations(
+    buffer: &mut DumpBuf,
+    annotations: &ModuleAnnotations,
+) -> Result<MDLocationDescriptor, MemoryWriterError> {
+    let simple_annotations = write_dictionary(
+        buffer,
+        annotations
+            .simple_annotations
+            .iter()
+            .map(|(key, value)| (key, value)),
+    )?;
+
+    let mut objects = Vec::with_capacity(annotations.annotation_objects.len());
+    for annotation in &annotations.annotation_objects {
+        objects.push(MDRawAnnotation {
+            name: write_utf8_string(buffer, &annotation.name)?,
+            ty: annotation.ty,
+            _reserved: 0,
+            value: write_byte_array(buffer, &annotation.value)?,
+        });
+    }
+    let annotation_objects = write_list(buffer, &objects)?;
+
+    let info = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawModuleCrashpadInfo {
+            version: MDRawModuleCrashpadInfo::VERSION,
+            list_annotations: MDLocationDescriptor::default(),
+            simple_annotations,
+            annotation_objects,
+        },
+    )?;
+    Ok(info.location())
+}
+
+fn write_dictionary<'a>(
+    buffer: &mut DumpBuf,
+    entries: impl Iterator<Item = (&'a String, &'a String)>,
+) -> Result<MDLocationDescriptor, MemoryWriterError> {
+    let mut raw_entries = Vec::new();
+    for (key, value) in entries {
+        raw_entries.push(MDRawSimpleStringDictionaryEntry {
+            key: write_utf8_string(buffer, key)?,
+            value: write_utf8_string(buffer, value)?,
+        });
+    }
+    write_list(buffer, &raw_entries)
+}
+
+/// Write a `u32` count followed by `items`.
+fn write_list<T>(
+    buffer: &mut DumpBuf,
+    items: &[T],
+) -> Result<MDLocationDescriptor, MemoryWriterError>
+where
+    T: TryIntoCtx<scroll::Endian, Error = scroll::Error> + SizeWith<scroll::Endian> + Copy,
+{
+    let mut location = MemoryWriter::alloc_with_val(buffer, items.len() as u32)?.location();
+    if !items.is_empty() {
+        location.data_size += MemoryArrayWriter::alloc_from_array(buffer, items)?
+            .location()
+            .data_size;
+    }
+    Ok(location)
+}
+
+fn write_utf8_string(buffer: &mut DumpBuf, s: &str) -> Result<MDRVA, MemoryWriterError> {
+    let rva = write_byte_array(buffer, s.as_bytes())?;
+    MemoryArrayWriter::write_bytes(buffer, &[0])?;
+    Ok(rva)
+}
+
+fn write_byte_array(buffer: &mut DumpBuf, bytes: &[u8]) -> Result<MDRVA, MemoryWriterError> {
+    let length = MemoryWriter::alloc_with_val(buffer, bytes.len() as u32)?;
+    MemoryArrayWriter::write_bytes(buffer, bytes)?;
+    Ok(length.location().rva)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_crashpad_module_annotations() {
+        const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+        const BASE: usize = 0x10000;
+        fn put(memory: &mut [u8], address: usize, bytes: &[u8]) {
+            memory[address - BASE..address - BASE + bytes.len()].copy_from_slice(bytes);
+        }
+
+        let info = BASE;
+        let dictionary = BASE + 0x1000;
+        let list = BASE + 0x9000;
+        let node = BASE + 0x9100;
+        let tail = BASE + 0x9200;
+        let name = BASE + 0x9ffe;
+        let value = BASE + 0xa100;
+
+        let mut memory = vec![0; 0x10000];
+        put(
+            &mut memory,
+            info,
+            &u32::from_be_bytes(*b"CPad").to_ne_bytes(),
+        );
+        put(
+            &mut memory,
+            info + 4,
+            &((24 + 4 * POINTER_SIZE) as u32).to_ne_bytes(),
+        );
+        put(&mut memory, info + 8, &1u32.to_ne_bytes());
+        put(
+            &mut memory,
+            info + 24 + POINTER_SIZE,
+            &dictionary.to_ne_bytes(),
+        );
+        put(
+            &mut memory,
+            info + 24 + 3 * POINTER_SIZE,
+            &list.to_ne_bytes(),
+        );
+
+        // The first entry of the dictionary is unused
+        put(&mut memory, dictionary + 512, b"channel");
+        put(&mut memory, dictionary + 512 + 256, b"beta");
+
+        put(&mut memory, list + POINTER_SIZE, &node.to_ne_bytes());
+        put(&mut memory, node, &tail.to_ne_bytes());
+        put(&mut memory, node + POINTER_SIZE, &name.to_ne_bytes());
+        put(&mut memory, node + 2 * POINTER_SIZE, &value.to_ne_bytes());
+        put(&mut memory, node + 3 * POINTER_SIZE, &5u32.to_ne_bytes());
+        put(
+            &mut memory,
+            node + 3 * POINTER_SIZE + 4,
+            &1u16.to_ne_bytes(),
+        );
+        // The name crosses a page boundary
+        put(&mut memory, name, b"gpu\0");
+        put(&mut memory, value, b"intel");
+
+        let dumper = MockDumper {
+            memory: vec![(BASE, memory)],
+            ..Default::default()
+        };
+        let annotations = read_module_annotations(&dumper, info).unwrap();
+        assert_eq!(
+            annotations,
+            ModuleAnnotations {
+                simple_annotations: vec![("channel".to_owned(), "beta".to_owned())],
+                annotation_objects: vec![Annotation {
+                    name: "gpu".to_owned(),
+                    ty: 1,
+                    value: b"intel".to_vec(),
+                }],
+            }
+        );
+
+        // Anything else than a CrashpadInfo structure is ignored
+        assert!(read_module_annotations(&dumper, dictionary).is_none());
+    }
+}
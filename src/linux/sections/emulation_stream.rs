@@ -0,0 +1,63 @@
+use super::*;
+use crate::linux::emulation::{self, Emulation};
+use std::fmt::Write as _;
+
+/// Write the LinuxEmulation stream if the process runs under a usermode
+/// emulator, see [`crate::emulation`]. It lists the host and emulated
+/// architectures, the emulator, and the emulated modules left out of the
+/// module list, one per line, e.g.:
+///
+/// ```text
+/// host_machine=X86_64
+/// emulated_machine=AARCH64
+/// interpreter=/usr/bin/qemu-aarch64-static
+/// emulated_module=0x5500000000 /usr/bin/true
+/// ```
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionEmulationError> {
+    let Some(emulation) = &config.emulation else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    let contents = describe(emulation, dumper);
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxEmulation as u32,
+        location: section.location(),
+    })
+}
+
+fn describe(emulation: &Emulation, dumper: &PtraceDumper) -> String {
+    // Writing to a String is infallible
+    let mut contents = String::new();
+    let _ = writeln!(
+        contents,
+        "host_machine={}",
+        emulation::machine_name(emulation::HOST_MACHINE)
+    );
+    let _ = writeln!(
+        contents,
+        "emulated_machine={}",
+        emulation::machine_name(emulation.emulated_machine)
+    );
+    if let Some(interpreter) = &emulation.interpreter {
+        let _ = writeln!(contents, "interpreter={interpreter}");
+    }
+    for mapping in dumper
+        .mappings
+        .iter()
+        .filter(|mapping| emulation.is_emulated_module(mapping.start_address))
+    {
+        let name = mapping.name.as_deref().unwrap_or_default();
+        let _ = writeln!(
+            contents,
+            "emulated_module={:#x} {}",
+            mapping.start_address,
+            name.to_string_lossy()
+        );
+    }
+    contents
+}
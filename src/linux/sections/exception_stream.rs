@@ -8,7 +8,7 @@ pub fn write(
 ) -> Result<MDRawDirectory, errors::SectionExceptionStreamError> {
     let exception = if let Some(context) = &config.crash_context {
         MDException {
-            exception_code: context.inner.siginfo.ssi_signo,
+            exception_code: exception_code(config),
             exception_flags: context.inner.siginfo.ssi_code as u32,
             exception_address: context.inner.siginfo.ssi_addr,
             ..Default::default()
@@ -19,7 +19,7 @@ pub fn write(
             _ => 0,
         };
         MDException {
-            exception_code: ExceptionCodeLinux::DUMP_REQUESTED as u32,
+            exception_code: exception_code(config),
             exception_address: addr as u64,
             ..Default::default()
         }
@@ -48,3 +48,12 @@ pub fn write(
 
     Ok(dirent)
 }
+
+/// The code of the exception: the signal which was raised, or
+/// `DUMP_REQUESTED` without a crash context.
+pub fn exception_code(config: &MinidumpWriter) -> u32 {
+    match &config.crash_context {
+        Some(context) => context.inner.siginfo.ssi_signo,
+        None => ExceptionCodeLinux::DUMP_REQUESTED as u32,
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use crate::linux::exploitability::{Assessment, CrashState};
+use std::fmt::Write as _;
+
+/// Assess how exploitable the crash is, see
+/// [`MinidumpWriter::assess_exploitability`], and write a text stream holding
+/// the rating on its first line followed by its indicators, one per line.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionExploitabilityError> {
+    if !config.assess_exploitability {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let assessment = assess(config, dumper);
+    let mut contents = format!("{}\n", assessment.rating);
+    for indicator in &assessment.indicators {
+        // Writing to a String is infallible
+        let _ = writeln!(contents, "{indicator}");
+    }
+    config.exploitability = Some(assessment);
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxExploitability as u32,
+        location: section.location(),
+    })
+}
+
+fn assess(config: &MinidumpWriter, dumper: &PtraceDumper) -> Assessment {
+    let (Some(crash_context), Some(cpu)) = (
+        &config.crash_context,
+        config.supplied_blamed_thread_context(),
+    ) else {
+        return Assessment::not_a_crash();
+    };
+
+    let (instruction_pointer, stack_pointer) = crate::linux::crash_context::context_pointers(&cpu);
+    let siginfo = &crash_context.inner.siginfo;
+    Assessment::assess(&CrashState {
+        signal: siginfo.ssi_signo,
+        fault_address: siginfo.ssi_addr,
+        write_fault: crash_context.is_write_fault(),
+        instruction_pointer,
+        stack_pointer,
+        registers: crate::linux::crash_context::general_registers(&cpu),
+        mappings: &dumper.mappings,
+        stacks: config
+            .thread_stacks
+            .iter()
+            .map(|stack| {
+                let start = stack.start_of_mapping as usize;
+                start..start + stack.size_of_mapping as usize
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,127 @@
+use super::*;
+
+/// Write the memory around the address the crashing thread faulted on, see
+/// [`MinidumpWriter::capture_fault_address_memory`].
+///
+/// Only `SIGSEGV` and `SIGBUS` report the address of a data access, the
+/// other signals report the faulting instruction, whose memory is captured
+/// with the thread list. Only the parts of the window lying in readable
+/// mappings are captured, so nothing is for null pointer dereferences.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<(), errors::SectionFaultAddressMemoryError> {
+    let (Some(window), Some(crash_context)) =
+        (config.fault_address_memory_window, &config.crash_context)
+    else {
+        return Ok(());
+    };
+    let siginfo = &crash_context.inner.siginfo;
+    if !matches!(siginfo.ssi_signo as i32, libc::SIGSEGV | libc::SIGBUS) {
+        return Ok(());
+    }
+
+    let fault_address = siginfo.ssi_addr as usize;
+    let window = fault_address.saturating_sub(window / 2)..fault_address.saturating_add(window / 2);
+    let mappings = dumper.mappings()?;
+    let window = memory_list_stream::page_aligned(config, &mappings, window);
+    let readable: Vec<_> = mappings
+        .iter()
+        .filter(|mapping| mapping.is_readable())
+        .map(|mapping| {
+            window.start.max(mapping.start_address)..window.end.min(mapping.end_address())
+        })
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    for requested in memory_list_stream::merge_ranges(readable) {
+        for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, requested) {
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                over_limit += 1;
+                continue;
+            }
+
+            config.check_cancelled()?;
+            for (start, mut data_copy) in memory_list_stream::read_readable_parts(dumper, range)? {
+                config.redact_memory(start, &mut data_copy);
+                let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+                config.memory_blocks.push(MDMemoryDescriptor {
+                    start_of_memory_range: start as u64,
+                    memory: section.location(),
+                });
+            }
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} fault address memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!(
+            "memory region limit reached, leaving out {over_limit} fault address memory regions"
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_fault_address_memory() {
+        let dumper = MockDumper {
+            mappings: vec![mapping(
+                0x1000,
+                0x1000,
+                MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
+                None,
+            )],
+            memory: vec![(0x1000, vec![0xab; 0x1000])],
+            ..Default::default()
+        };
+        // SAFETY: the crash context is made of plain C structures
+        let mut crash_context: crash_context::CrashContext = unsafe { std::mem::zeroed() };
+        crash_context.siginfo.ssi_signo = libc::SIGSEGV as u32;
+        crash_context.siginfo.ssi_addr = 0x1010;
+
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config
+            .set_crash_context(crate::linux::crash_context::CrashContext {
+                inner: crash_context,
+            })
+            .capture_fault_address_memory(0x40);
+        let mut buffer = DumpBuf::with_capacity(0);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        // The window is clipped to the mapping
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| (block.start_of_memory_range, block.memory.data_size))
+            .collect();
+        assert_eq!(regions, [(0x1000, 0x30)]);
+
+        // Nothing is mapped around null pointers
+        config.memory_blocks.clear();
+        if let Some(context) = &mut config.crash_context {
+            context.inner.siginfo.ssi_addr = 0x8;
+        }
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert!(config.memory_blocks.is_empty());
+    }
+}
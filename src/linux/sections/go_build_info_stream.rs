@@ -0,0 +1,67 @@
+use super::*;
+use crate::linux::module_reader::{GoBuildId, GoBuildInfo, ReadFromModule};
+use std::fmt::Write as _;
+
+/// Write a text stream describing the Go binaries mapped in the process, one
+/// per line with the following tab-separated fields:
+///
+/// `<base address> <Go build id> <Go version> <main module path> <main module version>`
+///
+/// Fields which could not be retrieved are left empty. Nothing is written if
+/// the process has no Go modules.
+pub fn write(
+    buffer: &mut DumpBuf,
+    dumper: &mut PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionGoBuildInfoError> {
+    let mut contents = String::new();
+
+    for map_idx in 0..dumper.mappings.len() {
+        let mapping = &dumper.mappings[map_idx];
+        if !mapping.is_interesting() || mapping.offset != 0 || mapping.is_kernel_page() {
+            continue;
+        }
+
+        // A deleted file may have been replaced by a different binary
+        let path = mapping
+            .name
+            .as_ref()
+            .filter(|_| !mapping.deleted)
+            .map(|name| dumper.resolve_path(std::path::Path::new(name)))
+            .filter(|path| path.exists());
+
+        // The build id note is in a loaded segment, while the build info has
+        // to be located through the section headers which usually aren't
+        let Ok(GoBuildId(build_id)) = dumper
+            .from_process_memory_for_index(map_idx)
+            .or_else(|_| GoBuildId::read_from_file(path.as_deref().ok_or(())?).map_err(|_| ()))
+        else {
+            continue;
+        };
+
+        let info = path
+            .as_deref()
+            .and_then(|path| GoBuildInfo::read_from_file(path).ok())
+            .or_else(|| dumper.from_process_memory_for_index(map_idx).ok())
+            .unwrap_or_default();
+
+        // Writing to a String is infallible
+        let _ = writeln!(
+            contents,
+            "{:#x}\t{build_id}\t{}\t{}\t{}",
+            dumper.mappings[map_idx].start_address,
+            info.go_version,
+            info.module_path.unwrap_or_default(),
+            info.module_version.unwrap_or_default(),
+        );
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxGoBuildInfo as u32,
+        location: section.location(),
+    })
+}
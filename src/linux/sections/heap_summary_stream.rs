@@ -0,0 +1,213 @@
+use super::*;
+use crate::memory_map::MemoryMapping;
+use procfs_core::{
+    process::{SmapsRollup, Stat, Status},
+    FromRead,
+};
+use std::fmt::Write as _;
+
+/// The names of the mappings the allocators get their memory from: the brk
+/// heap, and the anonymous mappings glibc, Bionic and the common allocators
+/// label on Android and recent kernels
+const HEAP_NAMES: &[&str] = &[
+    "[heap]",
+    "[anon:libc_malloc",
+    "[anon:scudo:",
+    "[anon:jemalloc",
+    "[anon:tcmalloc",
+    "[anon:mimalloc",
+];
+
+/// The fields of `/proc/<pid>/smaps_rollup` written to the stream, and the
+/// keys they're written under
+const ROLLUP_FIELDS: &[(&str, &str)] = &[
+    ("Rss", "rss"),
+    ("Pss", "pss"),
+    ("Shared_Clean", "shared_clean"),
+    ("Shared_Dirty", "shared_dirty"),
+    ("Private_Clean", "private_clean"),
+    ("Private_Dirty", "private_dirty"),
+    ("Swap", "swap"),
+    ("SwapPss", "swap_pss"),
+];
+
+/// Write the LinuxHeapSummary stream, a few figures to tell whether the
+/// process was running out of memory without capturing all of it, one per
+/// line, e.g.:
+///
+/// ```text
+/// brk_start=0x55d4c6a1b000
+/// brk_end=0x55d4c6a3c000
+/// heap_mappings=3
+/// heap_size=8650752
+/// rss_anon=10240000
+/// rss=25165824
+/// pss=19922944
+/// ...
+/// ```
+///
+/// `brk_start` and `brk_end` are the range of the brk heap, `heap_mappings`
+/// and `heap_size` the number and total size in bytes of the brk heap and
+/// of the mappings labeled by the allocators, and `rss_anon` the anonymous
+/// memory of the process which is resident, in bytes.
+///
+/// They're followed by the memory footprint of the process, as totalled in
+/// `/proc/<pid>/smaps_rollup`, in bytes: the resident memory and its
+/// proportional share, which divides the pages shared with other processes
+/// among them, the shared and private pages, clean or dirty, and the swapped
+/// out memory. Unlike the figures of `/proc/<pid>/stat`, these don't count
+/// the shared memory in full in every process mapping it.
+///
+/// The figures which can't be read are left out.
+///
+/// The statistics of the allocator itself, e.g. glibc's `mallinfo2`, can
+/// only be read from within the process, which can register them to be
+/// captured, see [`crate::registry`].
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionHeapSummaryError> {
+    let stat_path = format!("/proc/{}/stat", config.process_id);
+    let start_brk = match std::fs::File::open(stat_path).map(Stat::from_read) {
+        Ok(Ok(stat)) => stat.start_brk,
+        _ => None,
+    };
+    let status_path = format!("/proc/{}/status", config.process_id);
+    let rss_anon = match std::fs::File::open(status_path).map(Status::from_read) {
+        Ok(Ok(status)) => status.rssanon.map(|kb| kb * 1024),
+        _ => None,
+    };
+
+    let rollup_path = format!("/proc/{}/smaps_rollup", config.process_id);
+    let rollup = match SmapsRollup::from_file(rollup_path) {
+        Ok(rollup) => rollup_figures(&rollup),
+        Err(_) => Vec::new(),
+    };
+
+    let contents = describe(&dumper.memory_map()?, start_brk, rss_anon, &rollup);
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxHeapSummary as u32,
+        location: section.location(),
+    })
+}
+
+/// The figures of `rollup` listed in [`ROLLUP_FIELDS`], with their keys
+fn rollup_figures(rollup: &SmapsRollup) -> Vec<(&'static str, u64)> {
+    let Some(totals) = rollup.memory_map_rollup.iter().next() else {
+        return Vec::new();
+    };
+    ROLLUP_FIELDS
+        .iter()
+        .filter_map(|&(field, key)| Some((key, *totals.extension.map.get(field)?)))
+        .collect()
+}
+
+fn describe(
+    mappings: &[MemoryMapping],
+    start_brk: Option<u64>,
+    rss_anon: Option<u64>,
+    rollup: &[(&str, u64)],
+) -> String {
+    let heaps: Vec<_> = mappings
+        .iter()
+        .filter(|mapping| {
+            mapping.name.as_deref().is_some_and(|name| {
+                HEAP_NAMES
+                    .iter()
+                    .any(|heap_name| name.starts_with(heap_name))
+            })
+        })
+        .collect();
+
+    // Writing to a String is infallible
+    let mut contents = String::new();
+    if let Some(start_brk) = start_brk {
+        // The brk heap isn't mapped until the process first grows it
+        let brk_end = heaps
+            .iter()
+            .find(|mapping| mapping.range.start == start_brk)
+            .map_or(start_brk, |mapping| mapping.range.end);
+        let _ = writeln!(contents, "brk_start={start_brk:#x}");
+        let _ = writeln!(contents, "brk_end={brk_end:#x}");
+    }
+    let _ = writeln!(contents, "heap_mappings={}", heaps.len());
+    let heap_size: u64 = heaps
+        .iter()
+        .map(|mapping| mapping.range.end - mapping.range.start)
+        .sum();
+    let _ = writeln!(contents, "heap_size={heap_size}");
+    if let Some(rss_anon) = rss_anon {
+        let _ = writeln!(contents, "rss_anon={rss_anon}");
+    }
+    for (key, value) in rollup {
+        let _ = writeln!(contents, "{key}={value}");
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+    use procfs_core::FromBufRead;
+
+    #[test]
+    fn test_rollup_figures() {
+        let rollup = "\
+55d4c6a1b000-7ffd5a3fe000 ---p 00000000 00:00 0                          [rollup]
+Rss:               24576 kB
+Pss:               19456 kB
+Pss_Anon:           9000 kB
+Shared_Clean:       6144 kB
+Shared_Dirty:          0 kB
+Private_Clean:      2048 kB
+Private_Dirty:     16384 kB
+Swap:                  0 kB
+SwapPss:               0 kB
+";
+        let rollup = SmapsRollup::from_buf_read(rollup.as_bytes()).unwrap();
+        assert_eq!(
+            rollup_figures(&rollup),
+            vec![
+                ("rss", 24576 * 1024),
+                ("pss", 19456 * 1024),
+                ("shared_clean", 6144 * 1024),
+                ("shared_dirty", 0),
+                ("private_clean", 2048 * 1024),
+                ("private_dirty", 16384 * 1024),
+                ("swap", 0),
+                ("swap_pss", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heap_summary_stream() {
+        let rw = MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE;
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(0x1000, 0x1000, rw, Some("[heap]")),
+                mapping(0x3000, 0x1000, rw, Some("[anon:scudo:primary]")),
+                mapping(0x5000, 0x1000, rw, None),
+                mapping(0x7000, 0x1000, rw, Some("[stack]")),
+            ],
+            ..Default::default()
+        };
+
+        let config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&config, &mut buffer, &dumper).unwrap();
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::LinuxHeapSummary as u32
+        );
+        let location = dirent.location;
+        let contents = &buffer[location.rva as usize..][..location.data_size as usize];
+        let contents = std::str::from_utf8(contents).unwrap();
+        assert!(contents.contains("heap_mappings=2\n"));
+        assert!(contents.contains("heap_size=8192\n"));
+    }
+}
@@ -0,0 +1,177 @@
+use super::*;
+use crate::linux::maps_reader::{MappingEntry, MappingInfo};
+
+/// Capture the memory around the instruction pointers of threads executing
+/// code in anonymous executable mappings, which is usually JIT-compiled code,
+/// and record a synthetic module for each such mapping so that the code can
+/// be attributed to something.
+///
+/// This does nothing unless [`MinidumpWriter::capture_jit_memory`] was set,
+/// or in [`MinidumpWriter::minimal`] dumps.
+/// Regions which don't fit in [`MinidumpWriter::set_max_dump_size`] or
+/// [`MinidumpWriter::set_max_memory_regions`] are left out.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<(), errors::SectionJitMemoryError> {
+    config.jit_mapping_list.clear();
+    let Some(window) = config.jit_memory_window.filter(|_| !config.minimal) else {
+        return Ok(());
+    };
+
+    let mut regions = Vec::new();
+    for (idx, thread) in dumper.threads.iter().enumerate() {
+        let supplied = config.supplied_blamed_thread_pointers();
+        let instruction_ptr = match supplied {
+            Some((instruction_ptr, _)) if thread.tid == config.blamed_thread => instruction_ptr,
+            _ => match dumper.captured_thread_info(idx) {
+                Some(info) => info.get_instruction_pointer(),
+                None => continue,
+            },
+        };
+
+        let Some(mapping) = dumper.find_mapping(instruction_ptr) else {
+            continue;
+        };
+        if !mapping.is_executable() || !mapping.is_anonymous() {
+            continue;
+        }
+
+        let start = instruction_ptr
+            .saturating_sub(window / 2)
+            .max(mapping.start_address);
+        let end = instruction_ptr
+            .saturating_add(window / 2)
+            .min(mapping.end_address());
+        let range = memory_list_stream::page_aligned(config, &dumper.mappings, start..end);
+        regions.push((mapping, range.start, range.end));
+    }
+
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    for (mapping, start, end) in merge_regions(regions) {
+        // The memory around the crashing instruction pointer was captured
+        // with the thread list already
+        let ranges = memory_list_stream::uncaptured_ranges(&config.memory_blocks, start..end);
+        let omitted_before = omitted + over_limit;
+        for range in &ranges {
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                over_limit += 1;
+                continue;
+            }
+
+            let mut data_copy = match PtraceDumper::copy_from_process(
+                config.blamed_thread,
+                range.start,
+                range.len(),
+            ) {
+                Ok(data_copy) => data_copy,
+                Err(e) => {
+                    config.record_secondary_fault(e)?;
+                    continue;
+                }
+            };
+            config.redact_memory(range.start, &mut data_copy);
+
+            let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+            config.memory_blocks.push(MDMemoryDescriptor {
+                start_of_memory_range: range.start as u64,
+                memory: section.location(),
+            });
+        }
+        if !ranges.is_empty() && omitted + over_limit - omitted_before == ranges.len() {
+            continue;
+        }
+
+        if !config
+            .jit_mapping_list
+            .iter()
+            .any(|entry| entry.mapping == *mapping)
+        {
+            let mut mapping = mapping.clone();
+            mapping.name.get_or_insert_with(|| "[jit]".into());
+            config.jit_mapping_list.push(MappingEntry {
+                mapping,
+                identifier: Vec::new(),
+            });
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} JIT memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!("memory region limit reached, leaving out {over_limit} JIT memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+    }
+
+    Ok(())
+}
+
+/// Sort the regions and merge the overlapping ones, several threads may well
+/// be running the same code.
+fn merge_regions(
+    mut regions: Vec<(&MappingInfo, usize, usize)>,
+) -> Vec<(&MappingInfo, usize, usize)> {
+    regions.sort_by_key(|&(_, start, _)| start);
+
+    let mut merged: Vec<(&MappingInfo, usize, usize)> = Vec::with_capacity(regions.len());
+    for region in regions {
+        match merged.last_mut() {
+            Some(last) if std::ptr::eq(last.0, region.0) && region.1 <= last.2 => {
+                last.2 = last.2.max(region.2);
+            }
+            _ => merged.push(region),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock;
+    use procfs_core::process::MMPermissions;
+
+    fn mapping(start_address: usize, size: usize) -> MappingInfo {
+        let rx = MMPermissions::READ | MMPermissions::EXECUTE | MMPermissions::PRIVATE;
+        mock::mapping(start_address, size, rx, None)
+    }
+
+    #[test]
+    fn test_merge_regions() {
+        let first = mapping(0x1000, 0x3000);
+        let second = mapping(0x4000, 0x1000);
+
+        let merged = merge_regions(vec![
+            (&second, 0x4000, 0x4800),
+            (&first, 0x2000, 0x3000),
+            (&first, 0x1000, 0x2800),
+            (&first, 0x3800, 0x4000),
+        ]);
+
+        let merged: Vec<_> = merged
+            .into_iter()
+            .map(|(m, start, end)| (m.start_address, start, end))
+            .collect();
+        // Adjacent regions of different mappings are kept apart
+        assert_eq!(
+            merged,
+            vec![
+                (0x1000, 0x1000, 0x3000),
+                (0x1000, 0x3800, 0x4000),
+                (0x4000, 0x4000, 0x4800)
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+use crate::linux::jit_symbols;
+use std::{collections::BTreeMap, fmt::Write as _};
+
+/// The most functions written, the following ones are left out and recorded
+/// as a soft error
+const MAX_JIT_SYMBOLS: usize = 1 << 16;
+
+/// Write the LinuxJitSymbols stream, listing the functions of JIT-compiled
+/// code the runtime of the process published, see [`crate::jit_symbols`],
+/// when [`MinidumpWriter::capture_jit_symbols`] was set. The symbol files
+/// read come first, then each mapping holding JIT code as a synthetic module,
+/// followed by its functions, e.g.:
+///
+/// ```text
+/// SOURCE /tmp/perf-1234.map
+/// MODULE 0x7f3a10000000 0x200000
+/// FUNC 0x7f3a10001000 0xc0 LazyCompile:*main app.js:1
+/// FUNC 0x7f3a10002000 0x40 Builtin: ArgumentsAdaptorTrampoline
+/// ```
+///
+/// The functions outside of the mappings of the process, stale entries of
+/// code which was freed since, are left out. When several files have a
+/// function at the same address, the first one is kept.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionJitSymbolsError> {
+    if !config.capture_jit_symbols || config.minimal {
+        return Ok(MDRawDirectory::default());
+    }
+    let sources = jit_symbols::read_symbols(dumper);
+    if sources.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    // The functions by mapping, in address order
+    let mut modules = BTreeMap::<usize, BTreeMap<usize, &jit_symbols::JitSymbol>>::new();
+    for symbol in sources.iter().flat_map(|(_, symbols)| symbols) {
+        let Some(mapping) = dumper.find_mapping(symbol.address) else {
+            continue;
+        };
+        modules
+            .entry(mapping.start_address)
+            .or_default()
+            .entry(symbol.address)
+            .or_insert(symbol);
+    }
+
+    // Writing to a String is infallible
+    let mut contents = String::new();
+    for (path, _) in &sources {
+        let _ = writeln!(contents, "SOURCE {}", path.display());
+    }
+    let mut written = 0;
+    let mut omitted = 0;
+    for (start_address, functions) in &modules {
+        if written >= MAX_JIT_SYMBOLS {
+            omitted += functions.len();
+            continue;
+        }
+        let size = dumper
+            .find_mapping(*start_address)
+            .map_or(0, |mapping| mapping.size);
+        let _ = writeln!(contents, "MODULE {start_address:#x} {size:#x}");
+        for symbol in functions.values() {
+            if written >= MAX_JIT_SYMBOLS {
+                omitted += 1;
+                continue;
+            }
+            let _ = writeln!(
+                contents,
+                "FUNC {:#x} {:#x} {}",
+                symbol.address, symbol.size, symbol.name
+            );
+            written += 1;
+        }
+    }
+    if omitted > 0 {
+        log::warn!("JIT symbol limit reached, leaving out {omitted} functions");
+        config
+            .soft_errors
+            .push(errors::SoftError::JitSymbolsOmitted(omitted));
+    }
+
+    if !config.fits_in_max_dump_size(buffer, contents.len()) {
+        log::warn!("dump size limit reached, leaving out the JIT symbols");
+        config
+            .soft_errors
+            .push(errors::SoftError::JitSymbolsOmitted(written));
+        return Ok(MDRawDirectory::default());
+    }
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxJitSymbols as u32,
+        location: section.location(),
+    })
+}
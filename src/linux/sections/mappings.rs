@@ -1,6 +1,13 @@
 use super::*;
 use crate::linux::maps_reader::MappingInfo;
+use crate::linux::minidump_writer::ModuleChecksum;
 use crate::linux::module_reader::{BuildId, ReadFromModule, SoName};
+use crate::linux::thread_info::ThreadInfo;
+use crate::module_filter::ModuleAction;
+
+/// The bytes of a module its checksum covers, a fixed amount rather than the
+/// page size so that it can be computed from a copy of the module anywhere
+const MODULE_CHECKSUM_LEN: usize = 4096;
 
 /// Write information about the mappings in effect. Because we are using the
 /// minidump format, the information about the mappings is pretty limited.
@@ -12,9 +19,13 @@ pub fn write(
     dumper: &mut PtraceDumper,
 ) -> Result<MDRawDirectory, errors::SectionMappingsError> {
     let mut modules = Vec::new();
+    let referenced = (config.minimal || config.max_modules.is_some())
+        .then(|| ReferencedCode::new(config, buffer, dumper));
 
     // First write all the mappings from the dumper
+    let mut candidates = Vec::new();
     for map_idx in 0..dumper.mappings.len() {
+        config.check_cancelled()?;
         // If the mapping is uninteresting, or if
         // there is caller-provided information about this mapping
         // in the user_mapping_list list, skip it
@@ -24,76 +35,406 @@ pub fn write(
         {
             continue;
         }
-        log::debug!("retrieving build id for {:?}", &dumper.mappings[map_idx]);
-        let BuildId(identifier) = dumper
-            .from_process_memory_for_index(map_idx)
-            .or_else(|e| {
-                // If the mapping has an associated name that is a file, try to read the build id
-                // from the file. If there is no note segment with the build id in
-                // the program headers, we can't get to the note section if the section header
-                // table isn't loaded.
-                if let Some(path) = &dumper.mappings[map_idx].name {
-                    let path = std::path::Path::new(&path);
-                    if path.exists() {
-                        log::debug!("failed to get build id from process memory ({e}), attempting to retrieve from {}", path.display());
-                        return BuildId::read_from_file(path)
-                            .map_err(errors::DumperError::ModuleReaderError);
-                    }
-                    log::debug!(
-                        "not attempting to get build id from {}: path does not exist",
-                        path.display()
-                    );
-                }
-                Err(e)
-            })
-            .unwrap_or_else(|e| {
-                log::warn!("failed to get build id for mapping: {e}");
-                BuildId(Vec::new())
-            });
-
-        // If the identifier is all 0, its an uninteresting mapping (bmc#1676109)
-        if identifier.is_empty() || identifier.iter().all(|&x| x == 0) {
+        // The modules of another architecture than the thread contexts', see
+        // crate::emulation
+        if config.emulation.as_ref().is_some_and(|emulation| {
+            emulation.is_emulated_module(dumper.mappings[map_idx].start_address)
+        }) {
             continue;
         }
 
-        // SONAME should always be accessible through program headers alone, so we don't really
-        // need to fall back to trying to read from the mapping file.
-        let soname = dumper
-            .from_process_memory_for_index(map_idx)
-            .ok()
-            .map(|SoName(n)| n);
+        let is_referenced = referenced
+            .as_ref()
+            .is_some_and(|referenced| referenced.is_referenced(&dumper.mappings[map_idx]));
+        // Minimal dumps only list the modules needed to symbolicate the
+        // crashing stack
+        if config.minimal && !is_referenced {
+            continue;
+        }
+        let relevance = if is_referenced {
+            Relevance::Referenced
+        } else {
+            Relevance::Other
+        };
+        candidates.push((relevance, map_idx));
+    }
 
-        let module = fill_raw_module(buffer, &dumper.mappings[map_idx], &identifier, soname)?;
-        modules.push(module);
+    // Reading the identity of the modules is what takes time, and can be
+    // spread over several threads, see MinidumpWriter::set_worker_threads
+    let identities = parallel_map(&candidates, config.worker_threads, |&(_, map_idx)| {
+        identify_module(dumper, map_idx)
+    });
+    config.check_cancelled()?;
+    for ((relevance, map_idx), identity) in candidates.into_iter().zip(identities) {
+        let Some((identifier, soname)) = identity else {
+            continue;
+        };
+        modules.push((
+            relevance,
+            Module::Mapped {
+                map_idx,
+                identifier,
+                soname,
+            },
+        ));
     }
 
     // Next write all the mappings provided by the caller
-    for user in &config.user_mapping_list {
-        // GUID was provided by caller.
-        let module = fill_raw_module(buffer, &user.mapping, &user.identifier, None)?;
-        modules.push(module);
+    modules.extend(
+        (0..config.user_mapping_list.len()).map(|idx| (Relevance::Provided, Module::User(idx))),
+    );
+
+    // And finally the JIT code regions we captured, if any
+    modules.extend(
+        (0..config.jit_mapping_list.len()).map(|idx| (Relevance::Provided, Module::Jit(idx))),
+    );
+
+    // Let the caller leave out or rename modules, before the most relevant
+    // ones are picked
+    let user_mapping_list = &config.user_mapping_list;
+    let jit_mapping_list = &config.jit_mapping_list;
+    let mut filter = config.module_filter.as_mut();
+    let mut modules: Vec<_> = modules
+        .into_iter()
+        .filter_map(|(relevance, module)| {
+            let Some(filter) = filter.as_mut() else {
+                return Some((relevance, (module, None)));
+            };
+            let (mapping, soname) = match &module {
+                Module::Mapped {
+                    map_idx, soname, ..
+                } => (&dumper.mappings[*map_idx], soname.clone()),
+                Module::User(idx) => (&user_mapping_list[*idx].mapping, None),
+                Module::Jit(idx) => (&jit_mapping_list[*idx].mapping, None),
+            };
+            let path = mapping
+                .get_mapping_effective_path_name_and_version(soname)
+                .map(|(path, _, _)| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            match filter(&path) {
+                ModuleAction::Keep => Some((relevance, (module, None))),
+                ModuleAction::Rename(name) => Some((relevance, (module, Some(name)))),
+                ModuleAction::Omit => None,
+            }
+        })
+        .collect();
+
+    if let Some(max_modules) = config.max_modules {
+        let omitted = select_modules(&mut modules, max_modules);
+        if omitted > 0 {
+            log::warn!("module limit reached, leaving out {omitted} modules");
+            config
+                .soft_errors
+                .push(errors::SoftError::ModulesOmitted(omitted));
+        }
+    }
+
+    let mut raw_modules = Vec::with_capacity(modules.len());
+    for (_, (module, name)) in modules {
+        let name = name.as_deref();
+        let raw_module = match module {
+            Module::Mapped {
+                map_idx,
+                identifier,
+                soname,
+            } => {
+                let mapping = &dumper.mappings[map_idx];
+                let mut raw_module = fill_raw_module(buffer, mapping, &identifier, soname, name)?;
+                raw_module.time_date_stamp = module_timestamp(dumper, mapping);
+                if let Some(checksum) = &config.module_checksum {
+                    raw_module.checksum = module_checksum(checksum, dumper.pid, mapping);
+                }
+                raw_module
+            }
+            // GUID was provided by caller.
+            Module::User(idx) => {
+                let user = &config.user_mapping_list[idx];
+                fill_raw_module(buffer, &user.mapping, &user.identifier, None, name)?
+            }
+            Module::Jit(idx) => {
+                let jit = &config.jit_mapping_list[idx];
+                fill_raw_module(buffer, &jit.mapping, &jit.identifier, None, name)?
+            }
+        };
+        raw_modules.push(raw_module);
     }
 
-    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, modules.len() as u32)?;
+    config.listed_modules = raw_modules
+        .iter()
+        .map(|module| module.base_of_image)
+        .collect();
+    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, raw_modules.len() as u32)?;
 
     let mut dirent = MDRawDirectory {
         stream_type: MDStreamType::ModuleListStream as u32,
         location: list_header.location(),
     };
 
-    if !modules.is_empty() {
-        let mapping_list = MemoryArrayWriter::<MDRawModule>::alloc_from_iter(buffer, modules)?;
+    if !raw_modules.is_empty() {
+        let mapping_list = MemoryArrayWriter::<MDRawModule>::alloc_from_iter(buffer, raw_modules)?;
         dirent.location.data_size += mapping_list.location().data_size;
     }
 
     Ok(dirent)
 }
 
-fn fill_raw_module(
+/// A module to be written to the module list
+enum Module {
+    /// A mapping of the process, by its index in the dumper
+    Mapped {
+        map_idx: usize,
+        identifier: Vec<u8>,
+        soname: Option<String>,
+    },
+    /// An index in [`MinidumpWriter::user_mapping_list`]
+    User(usize),
+    /// An index in [`MinidumpWriter::jit_mapping_list`]
+    Jit(usize),
+}
+
+/// Which modules are kept first with [`MinidumpWriter::set_max_modules`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Relevance {
+    /// Executed by a thread, or referenced by the crashing stack
+    Referenced,
+    /// Provided by the caller, or JIT code
+    Provided,
+    Other,
+}
+
+/// Keep the `max_modules` most relevant modules, in their original order,
+/// returning how many were left out.
+fn select_modules<T>(modules: &mut Vec<(Relevance, T)>, max_modules: usize) -> usize {
+    if modules.len() <= max_modules {
+        return 0;
+    }
+
+    // Stable sorting keeps the modules of equal relevance in address order
+    let mut by_relevance: Vec<usize> = (0..modules.len()).collect();
+    by_relevance.sort_by_key(|&idx| modules[idx].0);
+    let mut keep = vec![false; modules.len()];
+    for &idx in &by_relevance[..max_modules] {
+        keep[idx] = true;
+    }
+
+    let omitted = modules.len() - max_modules;
+    let mut keep = keep.into_iter();
+    modules.retain(|_| keep.next().unwrap_or_default());
+    omitted
+}
+
+/// The code referenced by the threads of the process, see
+/// [`MinidumpWriter::minimal`].
+pub(crate) struct ReferencedCode {
+    /// The instruction pointers of all the threads
+    instruction_pointers: Vec<usize>,
+    /// The stack of the crashing thread, and the offset of its stack pointer,
+    /// which may hold return addresses
+    stack: Option<(Vec<u8>, usize)>,
+}
+
+impl ReferencedCode {
+    fn new(config: &MinidumpWriter, buffer: &DumpBuf, dumper: &PtraceDumper) -> Self {
+        let thread_infos = dumper
+            .threads
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, thread)| Some((thread.tid, dumper.captured_thread_info(idx)?)));
+        Self::from_threads(config, buffer, thread_infos)
+    }
+
+    /// The code referenced by the threads whose state is `thread_infos`,
+    /// once their stacks were written to `buffer` by the thread list.
+    pub(crate) fn from_threads<'a>(
+        config: &MinidumpWriter,
+        buffer: &DumpBuf,
+        thread_infos: impl Iterator<Item = (Pid, &'a ThreadInfo)>,
+    ) -> Self {
+        let mut instruction_pointers = Vec::new();
+        let mut stack_pointer = None;
+        if let Some((instruction_ptr, sp)) = config.supplied_blamed_thread_pointers() {
+            instruction_pointers.push(instruction_ptr);
+            stack_pointer = Some(sp);
+        }
+        for (tid, info) in thread_infos {
+            instruction_pointers.push(info.get_instruction_pointer());
+            if tid == config.blamed_thread {
+                stack_pointer.get_or_insert(info.stack_pointer);
+            }
+        }
+
+        // The stack was already written to the buffer by the thread list
+        let stack = stack_pointer.and_then(|stack_pointer| {
+            let block = config.memory_blocks.iter().find(|block| {
+                let start = block.start_of_memory_range as usize;
+                stack_pointer >= start && stack_pointer - start < block.memory.data_size as usize
+            })?;
+            let rva = block.memory.rva as usize;
+            let bytes = buffer.get(rva..rva + block.memory.data_size as usize)?;
+            Some((
+                bytes.to_vec(),
+                stack_pointer - block.start_of_memory_range as usize,
+            ))
+        });
+
+        Self {
+            instruction_pointers,
+            stack,
+        }
+    }
+
+    pub(crate) fn is_referenced(&self, mapping: &MappingInfo) -> bool {
+        self.instruction_pointers
+            .iter()
+            .any(|&ip| mapping.contains_address(ip))
+            || self.stack.as_ref().is_some_and(|(stack, sp_offset)| {
+                mapping.stack_has_pointer_to_mapping(stack, *sp_offset)
+            })
+    }
+}
+
+/// The build id and SONAME of the mapping at `map_idx`, or `None` if it has
+/// no build id.
+fn identify_module(dumper: &PtraceDumper, map_idx: usize) -> Option<(Vec<u8>, Option<String>)> {
+    // The kernel's code pages have no identity to read, but listing them
+    // as modules tells consumers the code executing there is legitimate
+    if dumper.mappings[map_idx].is_kernel_page() {
+        return Some((Vec::new(), None));
+    }
+
+    let BuildId(identifier) = build_id(dumper, map_idx);
+
+    // If the identifier is all 0, its an uninteresting mapping (bmc#1676109)
+    if identifier.is_empty() || identifier.iter().all(|&x| x == 0) {
+        return None;
+    }
+
+    // SONAME should always be accessible through program headers alone, so we don't really
+    // need to fall back to trying to read from the mapping file.
+    let soname = dumper
+        .from_process_memory_for_index(map_idx)
+        .ok()
+        .map(|SoName(n)| n);
+    Some((identifier, soname))
+}
+
+/// Apply `f` to each of `items` on up to `workers` threads, returning the
+/// results in the order of the items.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    workers: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if workers <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(workers);
+    std::thread::scope(|scope| {
+        let f = &f;
+        let workers: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect()
+    })
+}
+
+/// Get the build id of the mapping at `map_idx`, from the process memory or
+/// failing that from its file. It is empty if it couldn't be found.
+pub fn build_id(dumper: &PtraceDumper, map_idx: usize) -> BuildId {
+    log::debug!("retrieving build id for {:?}", &dumper.mappings[map_idx]);
+    dumper
+        .from_process_memory_for_index(map_idx)
+        .or_else(|e| {
+            // If the mapping has an associated name that is a file, try to read the build id
+            // from the file. If there is no note segment with the build id in
+            // the program headers, we can't get to the note section if the section header
+            // table isn't loaded.
+            // A deleted file might have been replaced by a different one
+            // with the same name, whose build id would be wrong.
+            if dumper.mappings[map_idx].deleted {
+                log::debug!("not attempting to get build id from a deleted file");
+                return Err(e);
+            }
+            if let Some(path) = &dumper.mappings[map_idx].name {
+                let path = dumper.resolve_path(std::path::Path::new(&path));
+                if path.exists() {
+                    log::debug!("failed to get build id from process memory ({e}), attempting to retrieve from {}", path.display());
+                    return BuildId::read_from_file(&path)
+                        .map_err(errors::DumperError::ModuleReaderError);
+                }
+                log::debug!(
+                    "not attempting to get build id from {}: path does not exist",
+                    path.display()
+                );
+            }
+            Err(e)
+        })
+        .unwrap_or_else(|e| {
+            log::warn!("failed to get build id for mapping: {e}");
+            BuildId(Vec::new())
+        })
+}
+
+/// The modification time of the file backing `mapping`, as seen by the
+/// process, 0 if it's unknown. Consumers use it as the timestamp of the
+/// module, e.g. to key their caches. The file of a deleted mapping may have
+/// been replaced, so its time isn't recorded.
+fn module_timestamp(dumper: &PtraceDumper, mapping: &MappingInfo) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(name) = mapping.name.as_ref().filter(|_| !mapping.deleted) else {
+        return 0;
+    };
+    let path = dumper.resolve_path(std::path::Path::new(name));
+    std::fs::metadata(path)
+        .ok()
+        .and_then(|metadata| u32::try_from(metadata.mtime()).ok())
+        .unwrap_or_default()
+}
+
+/// The checksum of the first [`MODULE_CHECKSUM_LEN`] bytes of `mapping` in
+/// process `pid`, 0 if they can't be read.
+fn module_checksum(checksum: &ModuleChecksum, pid: Pid, mapping: &MappingInfo) -> u32 {
+    let len = mapping.size.min(MODULE_CHECKSUM_LEN);
+    match PtraceDumper::copy_from_process(pid, mapping.start_address, len) {
+        Ok(bytes) => match checksum {
+            ModuleChecksum::Crc32 => crc32(&bytes),
+            ModuleChecksum::Custom(checksum) => checksum(&bytes),
+        },
+        Err(e) => {
+            log::debug!("failed to read the headers of a module for its checksum: {e}");
+            0
+        }
+    }
+}
+
+/// The CRC-32 of `bytes`, with the reflected 0xEDB88320 polynomial
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+pub fn fill_raw_module(
     buffer: &mut DumpBuf,
     mapping: &MappingInfo,
     identifier: &[u8],
     soname: Option<String>,
+    name: Option<&str>,
 ) -> Result<MDRawModule, errors::SectionMappingsError> {
     let cv_record = if identifier.is_empty() {
         // Just zeroes
@@ -117,7 +458,10 @@ fn fill_raw_module(
     let (file_path, _, so_version) = mapping
         .get_mapping_effective_path_name_and_version(soname)
         .map_err(|e| errors::SectionMappingsError::GetEffectivePathError(mapping.clone(), e))?;
-    let name_header = write_string_to_location(buffer, file_path.to_string_lossy().as_ref())?;
+    let name_header = match name {
+        Some(name) => write_string_to_location(buffer, name)?,
+        None => write_string_to_location(buffer, file_path.to_string_lossy().as_ref())?,
+    };
 
     let version_info = so_version.map_or(Default::default(), |sov| format::VS_FIXEDFILEINFO {
         signature: format::VS_FFI_SIGNATURE,
@@ -140,3 +484,44 @@ fn fill_raw_module(
 
     Ok(raw_module)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map() {
+        let items: Vec<usize> = (0..10).collect();
+        let squares: Vec<usize> = items.iter().map(|x| x * x).collect();
+        for workers in [0, 1, 3, 10, 20] {
+            assert_eq!(parallel_map(&items, workers, |x| x * x), squares);
+        }
+        assert!(parallel_map(&[] as &[usize], 4, |x| x * x).is_empty());
+    }
+
+    #[test]
+    fn test_select_modules() {
+        let mut modules = vec![
+            (Relevance::Other, 0),
+            (Relevance::Referenced, 1),
+            (Relevance::Other, 2),
+            (Relevance::Provided, 3),
+            (Relevance::Referenced, 4),
+        ];
+        assert_eq!(select_modules(&mut modules, 5), 0);
+        assert_eq!(modules.len(), 5);
+
+        assert_eq!(select_modules(&mut modules, 3), 2);
+        let kept: Vec<_> = modules.iter().map(|(_, idx)| *idx).collect();
+        assert_eq!(kept, [1, 3, 4]);
+
+        assert_eq!(select_modules(&mut modules, 0), 3);
+        assert!(modules.is_empty());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
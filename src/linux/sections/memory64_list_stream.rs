@@ -0,0 +1,381 @@
+use super::*;
+use crate::linux::{maps_reader::MappingInfo, memory_denylist};
+
+/// The memory is copied in chunks of this size, so that huge regions don't
+/// have to be held twice in memory
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The Memory64List stream, which describes memory with 64-bit sizes and
+/// offsets, unlike the regular memory list.
+///
+/// The contents of all its regions are laid out contiguously at the end of the
+/// minidump, as they can push it past 4GiB where the 32-bit RVAs of the other
+/// streams can't reach. The stream is therefore written in two steps: it is
+/// allocated with [`Memory64List::alloc`] before the last regular stream, and
+/// the memory is appended with [`Memory64List::write`] once everything else
+/// has been written.
+pub struct Memory64List {
+    regions: Vec<(usize, usize)>,
+    slots: Option<(
+        MemoryWriter<MDRawMemory64List>,
+        MemoryArrayWriter<MDMemoryDescriptor64>,
+    )>,
+}
+
+impl Memory64List {
+    /// Reserve room for the stream header and a descriptor for each region:
+    /// the application's ones, those of the regular memory list which 32-bit
+    /// RVAs couldn't reach, the module images of
+    /// [`MinidumpWriter::capture_module_images`], then all the mappings with
+    /// [`MinidumpWriter::full_memory`], less the memory denied by
+    /// [`MinidumpWriter::set_memory_denylist`], each truncated to
+    /// [`crate::capture_limits::CaptureLimits::max_region_size`]. The regions
+    /// which don't fit in [`MinidumpWriter::set_max_dump_size`] or
+    /// [`MinidumpWriter::set_max_memory_regions`] are left out, and those of the
+    /// application are rounded out to whole pages with
+    /// [`MinidumpWriter::page_align_memory`].
+    pub fn alloc(
+        config: &mut MinidumpWriter,
+        buffer: &mut DumpBuf,
+        dumper: &impl LinuxDumper,
+    ) -> Result<Self, errors::SectionMemory64ListError> {
+        let mappings = dumper.mappings()?;
+        let mut regions: Vec<_> = config
+            .app_memory64
+            .iter()
+            .chain(&config.spilled_app_memory)
+            .map(|app_memory| {
+                let range = memory_list_stream::page_aligned(
+                    config,
+                    &mappings,
+                    app_memory.ptr..app_memory.ptr + app_memory.length,
+                );
+                (range.start, range.len())
+            })
+            .collect();
+        regions.extend(
+            config
+                .module_image_regions
+                .iter()
+                .map(|range| (range.start, range.len())),
+        );
+        if config.full_memory {
+            if !config.include_sanitizer_shadow {
+                for mapping in mappings
+                    .iter()
+                    .filter(|mapping| mapping.is_readable() && mapping.is_sanitizer_shadow())
+                {
+                    config.note_sanitizer_shadow(mapping);
+                }
+            }
+            regions.extend(
+                mappings
+                    .iter()
+                    .filter(|mapping| is_captured_in_full(config, mapping))
+                    .map(|mapping| {
+                        let range = &mapping.system_mapping_info;
+                        (range.start_address, range.end_address - range.start_address)
+                    }),
+            );
+        }
+        // The denied memory is left out rather than zeroed, as it may span
+        // whole mappings
+        let regions: Vec<_> = regions
+            .into_iter()
+            .flat_map(|(start, length)| {
+                memory_denylist::allowed(&config.denied_ranges, start..start + length)
+            })
+            .collect();
+        let mut regions: Vec<_> = regions
+            .into_iter()
+            .map(|range| {
+                let range = memory_list_stream::capped(config, range);
+                (range.start, range.len())
+            })
+            .collect();
+        regions.retain(|&(_, length)| length > 0);
+
+        if let Some(max) = config.max_memory_regions {
+            if regions.len() > max {
+                let over_limit = regions.len() - max;
+                log::warn!(
+                    "memory region limit reached, leaving out {over_limit} Memory64List regions"
+                );
+                regions.truncate(max);
+                config
+                    .soft_errors
+                    .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+            }
+        }
+
+        let mut total = std::mem::size_of::<MDRawMemory64List>();
+        let mut omitted = 0;
+        regions.retain(|&(_, length)| {
+            let size = std::mem::size_of::<MDMemoryDescriptor64>() + length;
+            if config.fits_in_max_dump_size(buffer, total + size) {
+                total += size;
+                true
+            } else {
+                omitted += 1;
+                false
+            }
+        });
+        if omitted > 0 {
+            log::warn!("dump size limit reached, leaving out {omitted} Memory64List regions");
+            config
+                .soft_errors
+                .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+        }
+
+        if regions.is_empty() {
+            return Ok(Self {
+                regions,
+                slots: None,
+            });
+        }
+
+        let header = MemoryWriter::<MDRawMemory64List>::alloc(buffer)?;
+        let descriptors =
+            MemoryArrayWriter::<MDMemoryDescriptor64>::alloc_array(buffer, regions.len())?;
+        Ok(Self {
+            regions,
+            slots: Some((header, descriptors)),
+        })
+    }
+
+    /// Append the contents of the regions to `buffer`, which must not be
+    /// written to afterwards, and fill in the stream.
+    ///
+    /// Regions which can't be read are left out, and those which can only be
+    /// partly read are truncated. Cancellation is checked between chunks, as
+    /// this can take a long time for processes using a lot of memory.
+    pub fn write(
+        self,
+        config: &MinidumpWriter,
+        buffer: &mut DumpBuf,
+        dumper: &impl LinuxDumper,
+    ) -> Result<MDRawDirectory, errors::SectionMemory64ListError> {
+        let Some((mut header, mut descriptors)) = self.slots else {
+            return Ok(MDRawDirectory::default());
+        };
+
+        let base_rva = buffer.position();
+        let mut num_written = 0;
+        for (start, length) in self.regions {
+            let mut copied = 0;
+            while copied < length {
+                config.check_cancelled()?;
+                let chunk = (length - copied).min(CHUNK_SIZE);
+                match dumper.read_memory(start + copied, chunk) {
+                    Ok(mut bytes) => {
+                        config.redact_memory(start + copied, &mut bytes);
+                        buffer.write_all(&bytes);
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "failed to copy memory at {:#x}, truncating region {start:#x}+{length:#x}: {e}",
+                            start + copied
+                        );
+                        break;
+                    }
+                }
+                copied += chunk;
+            }
+            if copied == 0 {
+                continue;
+            }
+
+            let descriptor = MDMemoryDescriptor64 {
+                start_of_memory_range: start as u64,
+                data_size: copied as u64,
+            };
+            descriptors.set_value_at(buffer, descriptor, num_written)?;
+            num_written += 1;
+        }
+
+        header.set_value(
+            buffer,
+            MDRawMemory64List {
+                number_of_memory_ranges: num_written as u64,
+                base_rva,
+            },
+        )?;
+
+        let mut location = header.location();
+        location.data_size += descriptors.location_of_index(0).data_size * num_written as u32;
+        Ok(MDRawDirectory {
+            stream_type: MDStreamType::Memory64ListStream as u32,
+            location,
+        })
+    }
+}
+
+/// Whether the whole contents of `mapping` are captured in full-memory mode.
+/// Besides unreadable mappings this leaves out sanitizer shadow memory, the
+/// pages the kernel maps into every process, and device mappings, which may
+/// have side effects when read.
+pub fn is_captured_in_full(config: &MinidumpWriter, mapping: &MappingInfo) -> bool {
+    if !mapping.is_readable() || mapping.is_kernel_page() {
+        return false;
+    }
+    if mapping.is_sanitizer_shadow() && !config.include_sanitizer_shadow {
+        return false;
+    }
+
+    match mapping
+        .name
+        .as_deref()
+        .map(std::os::unix::ffi::OsStrExt::as_bytes)
+    {
+        Some(name) if name.starts_with(b"[vvar") => false,
+        Some(name) if name.starts_with(b"/dev/") => name.starts_with(b"/dev/shm/"),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::app_memory::AppMemory;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_memory64_list() {
+        let dumper = MockDumper {
+            memory: vec![(0x1000, (0..=255).collect())],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.set_app_memory64(vec![
+            AppMemory {
+                ptr: 0x1010,
+                length: 4,
+            },
+            // Unreadable regions are left out
+            AppMemory {
+                ptr: 0x2000,
+                length: 4,
+            },
+            AppMemory {
+                ptr: 0x10fe,
+                length: 2,
+            },
+        ]);
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+        let dirent = memory64_list.write(&config, &mut buffer, &dumper).unwrap();
+        assert_eq!(dirent.stream_type, MDStreamType::Memory64ListStream as u32);
+        assert_eq!(dirent.location.rva, 0);
+        assert_eq!(dirent.location.data_size, 16 + 2 * 16);
+
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+        // The header, then the descriptors of the regions which were read
+        assert_eq!(u64_at(0), 2);
+        let base_rva = u64_at(8) as usize;
+        assert_eq!(base_rva, 16 + 3 * 16);
+        assert_eq!((u64_at(16), u64_at(24)), (0x1010, 4));
+        assert_eq!((u64_at(32), u64_at(40)), (0x10fe, 2));
+        assert_eq!(&buffer[base_rva..], &[0x10, 0x11, 0x12, 0x13, 0xfe, 0xff]);
+
+        // Nothing is written without regions
+        config.app_memory64.clear();
+        let mut buffer = DumpBuf::with_capacity(0);
+        let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+        let dirent = memory64_list.write(&config, &mut buffer, &dumper).unwrap();
+        assert_eq!(dirent.stream_type, 0);
+        assert_eq!(buffer.position(), 0);
+
+        // Copying the memory stops once the dump is cancelled
+        let token = minidump_writer::CancellationToken::new();
+        config.set_app_memory64(vec![AppMemory {
+            ptr: 0x1000,
+            length: 0x10,
+        }]);
+        config.set_cancellation_token(token.clone());
+        token.cancel();
+        let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+        assert!(matches!(
+            memory64_list.write(&config, &mut buffer, &dumper),
+            Err(errors::SectionMemory64ListError::Cancelled(
+                errors::CancelledError::Cancelled
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_memory64_list_full_memory() {
+        let rw = MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE;
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(0x1000, 0x10, rw, None),
+                mapping(0x2000, 0x10, MMPermissions::PRIVATE, None),
+                mapping(0x3000, 0x10, rw, Some("/dev/dri/card0")),
+                mapping(0x4000, 0x10, rw, Some("[vvar]")),
+                mapping(0x5000, 0x10, rw, Some("/dev/shm/foo")),
+            ],
+            memory: vec![(0x1000, vec![1; 0x10]), (0x5000, vec![5; 0x10])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.full_memory();
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+        memory64_list.write(&config, &mut buffer, &dumper).unwrap();
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+        assert_eq!(u64_at(0), 2);
+        assert_eq!((u64_at(16), u64_at(24)), (0x1000, 0x10));
+        assert_eq!((u64_at(32), u64_at(40)), (0x5000, 0x10));
+
+        // The regions which don't fit are left out
+        config.set_max_dump_size(0);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+        let dirent = memory64_list.write(&config, &mut buffer, &dumper).unwrap();
+        assert_eq!(dirent.stream_type, 0);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::MemoryRegionsOmitted(2)]
+        ));
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_memory64_list_sanitizer_shadow() {
+        const SHADOW_START: usize = 0x100_0000_0000;
+        const SHADOW_SIZE: usize = 1 << 40;
+        let rw = MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE;
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(0x1000, 0x10, rw, None),
+                mapping(SHADOW_START, SHADOW_SIZE, rw, None),
+            ],
+            memory: vec![(0x1000, vec![1; 0x10])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.full_memory();
+
+        // The shadow memory is left out, which is recorded once
+        let mut buffer = DumpBuf::with_capacity(0);
+        for _ in 0..2 {
+            let memory64_list = Memory64List::alloc(&mut config, &mut buffer, &dumper).unwrap();
+            memory64_list.write(&config, &mut buffer, &dumper).unwrap();
+        }
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+        assert_eq!(u64_at(0), 1);
+        assert_eq!((u64_at(16), u64_at(24)), (0x1000, 0x10));
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::SanitizerShadowOmitted(
+                SHADOW_START,
+                SHADOW_SIZE
+            )]
+        ));
+    }
+}
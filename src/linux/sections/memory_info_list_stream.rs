@@ -1,23 +1,39 @@
 use super::*;
-use minidump_common::format::{MemoryProtection, MemoryState, MemoryType};
-use procfs_core::{process::MMPermissions, FromRead};
+use crate::{linux::maps_reader, memory_map::MemoryMapping};
+use minidump_common::format::{MemoryProtection, MemoryState};
 
-/// Write a MemoryInfoListStream using information from procfs.
+/// Write a MemoryInfoListStream using information from procfs, along with the
+/// unmapped regions the stack pointers of the threads point into.
 pub fn write(
     config: &mut MinidumpWriter,
     buffer: &mut DumpBuf,
 ) -> Result<MDRawDirectory, errors::SectionMemInfoListError> {
-    let maps = procfs_core::process::MemoryMaps::from_file(std::path::PathBuf::from(format!(
-        "/proc/{}/maps",
-        config.blamed_thread
-    )))?;
+    let mut entries: Vec<_> = maps_reader::read_memory_map(config.blamed_thread)?
+        .iter()
+        .map(MemoryMapping::memory_info)
+        .collect();
+
+    for region in &config.unmapped_stack_regions {
+        entries.push(MDMemoryInfo {
+            base_address: region.start as u64,
+            allocation_base: 0,
+            allocation_protection: 0,
+            __alignment1: 0,
+            region_size: (region.end - region.start) as u64,
+            state: MemoryState::MEM_FREE.bits(),
+            protection: MemoryProtection::PAGE_NOACCESS.bits(),
+            _type: 0,
+            __alignment2: 0,
+        });
+    }
+    entries.sort_by_key(|entry| entry.base_address);
 
     let list_header = MemoryWriter::alloc_with_val(
         buffer,
         MDMemoryInfoList {
             size_of_header: std::mem::size_of::<MDMemoryInfoList>() as u32,
             size_of_entry: std::mem::size_of::<MDMemoryInfo>() as u32,
-            number_of_entries: maps.len() as u64,
+            number_of_entries: entries.len() as u64,
         },
     )?;
 
@@ -26,43 +42,9 @@ pub fn write(
         location: list_header.location(),
     };
 
-    let block_list = MemoryArrayWriter::<MDMemoryInfo>::alloc_from_iter(
-        buffer,
-        maps.iter().map(|mm| MDMemoryInfo {
-            base_address: mm.address.0,
-            allocation_base: mm.address.0,
-            allocation_protection: get_memory_protection(mm.perms).bits(),
-            __alignment1: 0,
-            region_size: mm.address.1 - mm.address.0,
-            state: MemoryState::MEM_COMMIT.bits(),
-            protection: get_memory_protection(mm.perms).bits(),
-            _type: if mm.perms.contains(MMPermissions::PRIVATE) {
-                MemoryType::MEM_PRIVATE
-            } else {
-                MemoryType::MEM_MAPPED
-            }
-            .bits(),
-            __alignment2: 0,
-        }),
-    )?;
+    let block_list = MemoryArrayWriter::<MDMemoryInfo>::alloc_from_iter(buffer, entries)?;
 
     dirent.location.data_size += block_list.location().data_size;
 
     Ok(dirent)
 }
-
-fn get_memory_protection(permissions: MMPermissions) -> MemoryProtection {
-    let read = permissions.contains(MMPermissions::READ);
-    let write = permissions.contains(MMPermissions::WRITE);
-    let exec = permissions.contains(MMPermissions::EXECUTE);
-    match (read, write, exec) {
-        (false, false, false) => MemoryProtection::PAGE_NOACCESS,
-        (false, false, true) => MemoryProtection::PAGE_EXECUTE,
-        (true, false, false) => MemoryProtection::PAGE_READONLY,
-        (true, false, true) => MemoryProtection::PAGE_EXECUTE_READ,
-        // No support for write-only
-        (true | false, true, false) => MemoryProtection::PAGE_READWRITE,
-        // No support for execute+write-only
-        (true | false, true, true) => MemoryProtection::PAGE_EXECUTE_READWRITE,
-    }
-}
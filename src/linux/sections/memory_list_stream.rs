@@ -1,4 +1,6 @@
 use super::*;
+use crate::linux::{errors::DumperError, maps_reader::MappingInfo};
+use std::ops::Range;
 
 pub fn write(
     config: &mut MinidumpWriter,
@@ -19,3 +21,240 @@ pub fn write(
 
     Ok(dirent)
 }
+
+/// The parts of `range` which aren't covered by the memory blocks captured so
+/// far, in order. Capturing only those keeps the same bytes from being written
+/// twice, and the descriptors of the memory list from overlapping, which some
+/// consumers don't handle.
+pub fn uncaptured_ranges(blocks: &[MDMemoryDescriptor], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut captured: Vec<_> = blocks
+        .iter()
+        .map(|block| {
+            let start = block.start_of_memory_range as usize;
+            start..start + block.memory.data_size as usize
+        })
+        .filter(|block| block.start < range.end && block.end > range.start)
+        .collect();
+    captured.sort_unstable_by_key(|block| block.start);
+
+    let mut ranges = Vec::new();
+    let mut current = range.start;
+    for block in captured {
+        if block.start > current {
+            ranges.push(current..block.start);
+        }
+        current = current.max(block.end);
+    }
+    if current < range.end {
+        ranges.push(current..range.end);
+    }
+    ranges
+}
+
+/// Sort `ranges` and merge those which overlap or are adjacent.
+pub fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_unstable_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Read `range` from the process, leaving out the pages which can't be read,
+/// such as the guard pages at the edges of stacks and heap regions, rather
+/// than failing altogether. Returns the parts which were read, in order.
+/// Only the errors meaning the process is gone are returned.
+pub fn read_readable_parts(
+    dumper: &impl LinuxDumper,
+    range: Range<usize>,
+) -> Result<Vec<(usize, Vec<u8>)>, DumperError> {
+    // SAFETY: no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+
+    let mut parts: Vec<(usize, Vec<u8>)> = Vec::new();
+    let mut address = range.start;
+    while address < range.end {
+        // Reads usually stop short at the first page which can't be read, but
+        // fail altogether if that's the first one, in which case the page is
+        // read on its own to tell whether it's the culprit
+        let page_end = (address + 1).next_multiple_of(page_size).min(range.end);
+        let read = match dumper.read_memory(address, range.end - address) {
+            Ok(bytes) if !bytes.is_empty() => Ok(bytes),
+            _ => dumper.read_memory(address, page_end - address),
+        };
+        match read {
+            Ok(bytes) if !bytes.is_empty() => {
+                let len = bytes.len();
+                match parts.last_mut() {
+                    Some((start, last)) if *start + last.len() == address => {
+                        last.extend_from_slice(&bytes)
+                    }
+                    _ => parts.push((address, bytes)),
+                }
+                address += len;
+            }
+            Err(DumperError::CopyFromProcessError(e)) if e.source == nix::Error::ESRCH => {
+                return Err(DumperError::CopyFromProcessError(e));
+            }
+            _ => address = page_end,
+        }
+    }
+    Ok(parts)
+}
+
+/// Record a soft error if only `read` bytes of `range` could be read, see
+/// [`read_readable_parts`].
+pub fn note_trimmed(config: &mut MinidumpWriter, range: Range<usize>, read: usize) {
+    if read < range.len() {
+        let trimmed = range.len() - read;
+        log::warn!(
+            "left out {trimmed} unreadable bytes of the memory region at {:#x}",
+            range.start
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionTrimmed(range.start, trimmed));
+    }
+}
+
+/// `range` truncated to [`CaptureLimits::max_region_size`], so that a corrupted
+/// length read from the process can't make us read and write huge amounts of
+/// memory. The truncation is recorded as a soft error, the descriptor of the
+/// region only covering what was captured.
+///
+/// [`CaptureLimits::max_region_size`]: crate::capture_limits::CaptureLimits::max_region_size
+pub fn capped(config: &mut MinidumpWriter, range: Range<usize>) -> Range<usize> {
+    let max_region_size = config.capture_limits.max_region_size;
+    if range.len() <= max_region_size {
+        return range;
+    }
+    log::warn!(
+        "truncating the memory region at {:#x} from {} to {max_region_size} bytes",
+        range.start,
+        range.len()
+    );
+    config
+        .soft_errors
+        .push(errors::SoftError::MemoryRegionTruncated(
+            range.start,
+            range.len(),
+        ));
+    range.start..range.start + max_region_size
+}
+
+/// `range` rounded out to page boundaries with
+/// [`MinidumpWriter::page_align_memory`]. Each end is only moved within the
+/// mapping containing it, and only if that mapping is readable.
+pub fn page_aligned(
+    config: &MinidumpWriter,
+    mappings: &[MappingInfo],
+    range: Range<usize>,
+) -> Range<usize> {
+    if !config.page_align_memory || range.is_empty() {
+        return range;
+    }
+
+    // SAFETY: no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+    let readable_mapping = |address: usize| {
+        mappings
+            .iter()
+            .find(|mapping| mapping.contains_address(address))
+            .filter(|mapping| mapping.is_readable())
+    };
+
+    let start = match readable_mapping(range.start) {
+        Some(mapping) => {
+            (range.start & !(page_size - 1)).max(mapping.system_mapping_info.start_address)
+        }
+        None => range.start,
+    };
+    let end = match readable_mapping(range.end - 1) {
+        Some(mapping) => (range.end.saturating_add(page_size - 1) & !(page_size - 1))
+            .min(mapping.system_mapping_info.end_address),
+        None => range.end,
+    };
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    fn block(start: u64, size: u32) -> MDMemoryDescriptor {
+        MDMemoryDescriptor {
+            start_of_memory_range: start,
+            memory: MDLocationDescriptor {
+                data_size: size,
+                rva: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_uncaptured_ranges() {
+        let blocks = [
+            block(0x1100, 0x100),
+            block(0x1000, 0x80),
+            block(0x2000, 0x10),
+        ];
+        assert_eq!(
+            uncaptured_ranges(&blocks, 0x1040..0x1300),
+            [0x1080..0x1100, 0x1200..0x1300]
+        );
+        assert!(uncaptured_ranges(&blocks, 0x1100..0x1180).is_empty());
+        assert_eq!(
+            uncaptured_ranges(&blocks, 0x3000..0x3010),
+            vec![0x3000..0x3010]
+        );
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        assert_eq!(
+            merge_ranges(vec![
+                0x2000..0x2010,
+                0x1000..0x1100,
+                0x1080..0x1200,
+                0x1200..0x1210,
+                0x3000..0x3000
+            ]),
+            [0x1000..0x1210, 0x2000..0x2010]
+        );
+    }
+
+    #[test]
+    fn test_read_readable_parts() {
+        // SAFETY: no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        // The pages in between and around are unreadable, like guard pages
+        let dumper = MockDumper {
+            memory: vec![
+                (page_size, vec![1; page_size]),
+                (3 * page_size, vec![3; page_size]),
+            ],
+            ..Default::default()
+        };
+
+        let parts = read_readable_parts(&dumper, page_size + 8..4 * page_size - 8).unwrap();
+        assert_eq!(
+            parts,
+            [
+                (page_size + 8, vec![1; page_size - 8]),
+                (3 * page_size, vec![3; page_size - 8])
+            ]
+        );
+        let parts = read_readable_parts(&dumper, 0..2 * page_size).unwrap();
+        assert_eq!(parts, [(page_size, vec![1; page_size])]);
+        assert!(read_readable_parts(&dumper, 4 * page_size..5 * page_size)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+use format::{
+    MiscInfoFlags, MINIDUMP_MISC_INFO as MDRawMiscInfo, MINIDUMP_MISC_INFO_5 as MDRawMiscInfo5,
+    TIME_ZONE_INFORMATION, XSTATE_CONFIG_FEATURE_MSC_INFO, XSTATE_FEATURE,
+};
+use procfs_core::{process::Stat, FromRead};
+use scroll::ctx::SizeWith;
+
+/// Write a MiscInfoStream with the process id and, if available, the start
+/// time of the process and the time it spent in user and kernel mode, all at
+/// second granularity. The CPU times are left out of deterministic dumps, see
+/// [`MinidumpWriter::deterministic`].
+///
+/// If the extended register state of the threads was captured, see
+/// [`MinidumpWriter::xstate_header`], a `MINIDUMP_MISC_INFO_5` is written
+/// instead, whose XSTATE configuration describes the layout of their XSAVE
+/// areas, held in the LinuxXState stream.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionMiscInfoError> {
+    let mut misc_info = MDRawMiscInfo {
+        size_of_info: std::mem::size_of::<MDRawMiscInfo>() as u32,
+        flags1: MiscInfoFlags::MINIDUMP_MISC1_PROCESS_ID.bits(),
+        process_id: config.process_id as u32,
+        process_create_time: 0,
+        process_user_time: 0,
+        process_kernel_time: 0,
+    };
+
+    if let Some((create_time, user_time, kernel_time)) = process_times(config.process_id) {
+        misc_info.flags1 |= MiscInfoFlags::MINIDUMP_MISC1_PROCESS_TIMES.bits();
+        misc_info.process_create_time = create_time;
+        if config.deterministic_clock.is_none() {
+            misc_info.process_user_time = user_time;
+            misc_info.process_kernel_time = kernel_time;
+        }
+    }
+
+    let location = match &config.xstate_header {
+        Some(header) => {
+            let misc_info = misc_info_5(misc_info, header);
+            MemoryWriter::<MDRawMiscInfo5>::alloc_with_val(buffer, misc_info)?.location()
+        }
+        None => MemoryWriter::<MDRawMiscInfo>::alloc_with_val(buffer, misc_info)?.location(),
+    };
+
+    Ok(MDRawDirectory {
+        stream_type: MDStreamType::MiscInfoStream as u32,
+        location,
+    })
+}
+
+/// Extend `misc_info` with the XSTATE configuration matching `header`, the
+/// fields of the versions in between are left empty as their flags aren't
+/// set.
+fn misc_info_5(misc_info: MDRawMiscInfo, header: &MDRawXStateHeader) -> MDRawMiscInfo5 {
+    let mut features = [XSTATE_FEATURE::default(); 64];
+    for (feature, raw) in features.iter_mut().zip(&header.features) {
+        feature.offset = raw.offset;
+        feature.size = raw.size;
+    }
+
+    MDRawMiscInfo5 {
+        size_of_info: MDRawMiscInfo5::size_with(&scroll::Endian::Little) as u32,
+        flags1: misc_info.flags1,
+        process_id: misc_info.process_id,
+        process_create_time: misc_info.process_create_time,
+        process_user_time: misc_info.process_user_time,
+        process_kernel_time: misc_info.process_kernel_time,
+        processor_max_mhz: 0,
+        processor_current_mhz: 0,
+        processor_mhz_limit: 0,
+        processor_max_idle_state: 0,
+        processor_current_idle_state: 0,
+        process_integrity_level: 0,
+        process_execute_flags: 0,
+        protected_process: 0,
+        time_zone_id: 0,
+        time_zone: TIME_ZONE_INFORMATION::default(),
+        build_string: [0; 260],
+        dbg_bld_str: [0; 40],
+        xstate_data: XSTATE_CONFIG_FEATURE_MSC_INFO {
+            size_of_info: XSTATE_CONFIG_FEATURE_MSC_INFO::size_with(&scroll::Endian::Little) as u32,
+            context_size: header.xsave_size,
+            enabled_features: header.enabled_features,
+            features,
+        },
+        process_cookie: 0,
+    }
+}
+
+fn process_times(pid: Pid) -> Option<(u32, u32, u32)> {
+    let ticks_per_second = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK).ok()?? as u64;
+    let stat = Stat::from_file(format!("/proc/{pid}/stat")).ok()?;
+    // The start time of the process is relative to the system boot
+    let create_time = timestamps_stream::boot_time()? + stat.starttime / ticks_per_second;
+
+    Some((
+        create_time as u32,
+        (stat.utime / ticks_per_second) as u32,
+        (stat.stime / ticks_per_second) as u32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_misc_info_xstate() {
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut config, &mut buffer).unwrap();
+        assert_eq!(dirent.location.data_size, 24);
+
+        let mut features = [MDRawXStateFeature::default(); 64];
+        features[2] = MDRawXStateFeature {
+            offset: 576,
+            size: 256,
+        };
+        config.xstate_header = Some(MDRawXStateHeader {
+            enabled_features: 0b111,
+            xsave_size: 832,
+            thread_count: 1,
+            features,
+        });
+        let dirent = write(&mut config, &mut buffer).unwrap();
+        // MINIDUMP_MISC_INFO_5, with the XSTATE configuration following the
+        // 832 bytes of MINIDUMP_MISC_INFO_4
+        assert_eq!(dirent.location.data_size, 1364);
+        let misc_info = dirent.location.rva as usize;
+        assert_eq!(read_u32(&buffer, misc_info), 1364);
+        let xstate = misc_info + 832;
+        assert_eq!(read_u32(&buffer, xstate), 528);
+        assert_eq!(read_u32(&buffer, xstate + 4), 832);
+        assert_eq!(read_u32(&buffer, xstate + 8), 0b111);
+        assert_eq!(read_u32(&buffer, xstate + 16 + 2 * 8), 576);
+        assert_eq!(read_u32(&buffer, xstate + 16 + 2 * 8 + 4), 256);
+    }
+}
@@ -0,0 +1,107 @@
+use super::*;
+use procfs_core::{
+    process::{MMPermissions, MMapPath, MemoryMaps},
+    FromRead,
+};
+use std::{collections::HashSet, ops::Range};
+
+/// A segment of a file, as mapped by the process: the device and inode of
+/// the file, and the offset of the segment in it
+type FileSegment = ((i32, i32), u64, u64);
+
+/// Pick the executable segments of the modules to copy to the Memory64List,
+/// see [`MinidumpWriter::capture_module_images`], reading them from
+/// `/proc/<pid>/maps` as the mappings of the dumper merge the segments of
+/// each module.
+///
+/// This does nothing unless [`MinidumpWriter::capture_module_images`] was set,
+/// or in [`MinidumpWriter::minimal`] dumps and with
+/// [`MinidumpWriter::full_memory`], which captures them already.
+pub fn collect(config: &mut MinidumpWriter) -> Result<(), errors::SectionModuleImagesError> {
+    config.module_image_regions.clear();
+    let Some(budget) = config
+        .module_images_budget
+        .filter(|_| !config.minimal && !config.full_memory)
+    else {
+        return Ok(());
+    };
+
+    let maps = MemoryMaps::from_file(std::path::PathBuf::from(format!(
+        "/proc/{}/maps",
+        config.process_id
+    )))?;
+    let segments = maps.into_iter().filter_map(|mm| {
+        let MMapPath::Path(_) = mm.pathname else {
+            return None;
+        };
+        if !mm
+            .perms
+            .contains(MMPermissions::READ | MMPermissions::EXECUTE)
+        {
+            return None;
+        }
+        let range = mm.address.0 as usize..mm.address.1 as usize;
+        Some(((mm.dev, mm.inode, mm.offset), range))
+    });
+    let instruction_ptr = config
+        .supplied_blamed_thread_pointers()
+        .map(|(instruction_ptr, _)| instruction_ptr);
+    config.module_image_regions = select(segments, budget, instruction_ptr);
+    Ok(())
+}
+
+/// The ranges of the `segments` to capture within `budget` bytes, the one
+/// holding `instruction_ptr` first, as the crashing code matters most.
+/// A segment mapped more than once is captured once, and the segments which
+/// don't fit in what's left of the budget are skipped, as part of a segment
+/// wouldn't be of much use.
+fn select(
+    segments: impl IntoIterator<Item = (FileSegment, Range<usize>)>,
+    budget: usize,
+    instruction_ptr: Option<usize>,
+) -> Vec<Range<usize>> {
+    let mut segments: Vec<_> = segments.into_iter().collect();
+    if let Some(instruction_ptr) = instruction_ptr {
+        segments.sort_by_key(|(_, range)| !range.contains(&instruction_ptr));
+    }
+
+    let mut seen = HashSet::new();
+    let mut remaining = budget;
+    let mut regions = Vec::new();
+    for (segment, range) in segments {
+        if range.len() > remaining || !seen.insert(segment) {
+            continue;
+        }
+        remaining -= range.len();
+        regions.push(range);
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let libc = ((8, 1), 100, 0x1000);
+        let segments = [
+            (((8, 1), 200, 0x1000), 0x1000..0x5000),
+            (libc, 0x10000..0x12000),
+            (((8, 1), 300, 0x2000), 0x20000..0x21000),
+            // The same segment loaded again, e.g. in another namespace
+            (libc, 0x30000..0x32000),
+        ];
+
+        assert_eq!(
+            select(segments.clone(), usize::MAX, None),
+            [0x1000..0x5000, 0x10000..0x12000, 0x20000..0x21000]
+        );
+        // The crashing code comes first, and what doesn't fit is skipped
+        assert_eq!(
+            select(segments.clone(), 0x3000, Some(0x20010)),
+            [0x20000..0x21000, 0x10000..0x12000]
+        );
+        assert_eq!(select(segments, 0, None), []);
+    }
+}
@@ -0,0 +1,55 @@
+use super::*;
+use std::fmt::Write as _;
+
+/// The namespaces recorded, in the order they are written
+const NAMESPACES: [&str; 8] = ["pid", "mnt", "net", "user", "uts", "ipc", "cgroup", "time"];
+
+/// Write a text stream listing the inode numbers identifying the namespaces
+/// of the process, one `<type>: <inode>` pair per line, e.g. `net:
+/// 4026531840`.
+///
+/// They are what container runtimes and `lsns` report, so dumps of
+/// containerized processes can be matched to the container they ran in.
+/// The namespaces the kernel doesn't support, or which can't be read, are
+/// left out, and nothing is written if none can.
+pub fn write(buffer: &mut DumpBuf, pid: Pid) -> Result<MDRawDirectory, MemoryWriterError> {
+    let mut contents = String::new();
+    for namespace in NAMESPACES {
+        let Some(inode) = std::fs::read_link(format!("/proc/{pid}/ns/{namespace}"))
+            .ok()
+            .and_then(|link| parse_link(&link.to_string_lossy()))
+        else {
+            continue;
+        };
+        // Writing to a String is infallible
+        let _ = writeln!(contents, "{namespace}: {inode}");
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxNamespaces as u32,
+        location: section.location(),
+    })
+}
+
+/// The inode number of a namespace link, which reads `<type>:[<inode>]`
+fn parse_link(link: &str) -> Option<u64> {
+    let (_, inode) = link.split_once(":[")?;
+    inode.strip_suffix(']')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_link() {
+        assert_eq!(parse_link("net:[4026531840]"), Some(4026531840));
+        assert_eq!(parse_link("net:[]"), None);
+        assert_eq!(parse_link("4026531840"), None);
+    }
+}
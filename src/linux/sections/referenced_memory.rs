@@ -0,0 +1,210 @@
+use super::*;
+use crate::linux::maps_reader::MappingInfo;
+use std::ops::Range;
+
+/// How much memory is captured before and after each address found on the
+/// stacks, enough for the object it points to in most cases
+const BYTES_BEFORE: usize = 128;
+const BYTES_AFTER: usize = 384;
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+
+/// Write the memory referenced by the values on the captured stacks, see
+/// [`MinidumpWriter::capture_referenced_memory`].
+///
+/// The stacks are scanned from their stack pointer upwards, the crashing
+/// thread's first, and every pointer-sized value which points into a
+/// readable, non-executable mapping is taken as a pointer to data. The memory
+/// around it is captured until the budget is spent, leaving out what was
+/// already captured, e.g. the stacks themselves, which doesn't count towards
+/// the budget.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<(), errors::SectionReferencedMemoryError> {
+    let Some(budget) = config.referenced_memory_budget else {
+        return Ok(());
+    };
+    let mappings = dumper.mappings()?;
+
+    let mut stacks: Vec<_> = config
+        .thread_stacks
+        .iter()
+        .map(|stack| (stack.thread_id, stack.stack_pointer as usize))
+        .collect();
+    // Stable, so the other threads stay in the order they were written in
+    stacks.sort_by_key(|&(tid, _)| tid != config.blamed_thread as u32);
+
+    let mut remaining = budget;
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    'stacks: for (_, stack_pointer) in stacks {
+        // Copied, as the captured memory is appended to the buffer
+        let Some(stack) = stack_contents(config, buffer, stack_pointer).map(<[u8]>::to_vec) else {
+            continue;
+        };
+        for word in stack.chunks_exact(POINTER_SIZE) {
+            let address = usize::from_ne_bytes(word.try_into().unwrap());
+            let Some(referenced) = referenced_range(config, &mappings, address) else {
+                continue;
+            };
+
+            // Windows overlapping with what was captured before only add the
+            // rest, so the same object referenced twice costs nothing
+            for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, referenced) {
+                if remaining == 0 {
+                    break 'stacks;
+                }
+                let range = range.start..range.end.min(range.start.saturating_add(remaining));
+                if !config.fits_in_max_dump_size(buffer, range.len()) {
+                    omitted += 1;
+                    continue;
+                }
+                if !config.fits_in_max_memory_regions() {
+                    over_limit += 1;
+                    continue;
+                }
+
+                config.check_cancelled()?;
+                // Values are only read where the mappings say there is memory,
+                // but the mappings may have changed since
+                let Ok(mut data_copy) = dumper.read_memory(range.start, range.len()) else {
+                    continue;
+                };
+                config.redact_memory(range.start, &mut data_copy);
+                remaining -= range.len();
+
+                let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+                config.memory_blocks.push(MDMemoryDescriptor {
+                    start_of_memory_range: range.start as u64,
+                    memory: section.location(),
+                });
+            }
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} referenced memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!(
+            "memory region limit reached, leaving out {over_limit} referenced memory regions"
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+    }
+    Ok(())
+}
+
+/// The captured contents of the stack at `stack_pointer`, from the first
+/// pointer-aligned address at or above it.
+fn stack_contents<'a>(
+    config: &MinidumpWriter,
+    buffer: &'a DumpBuf,
+    stack_pointer: usize,
+) -> Option<&'a [u8]> {
+    let block = config.memory_blocks.iter().find(|block| {
+        let start = block.start_of_memory_range as usize;
+        start <= stack_pointer && stack_pointer < start + block.memory.data_size as usize
+    })?;
+    let start = block.start_of_memory_range as usize;
+    let offset = stack_pointer.next_multiple_of(POINTER_SIZE) - start;
+    let rva = block.memory.rva as usize;
+    buffer.get(
+        rva + offset.min(block.memory.data_size as usize)..rva + block.memory.data_size as usize,
+    )
+}
+
+/// The memory to capture for a value found on a stack, if it points to data.
+fn referenced_range(
+    config: &mut MinidumpWriter,
+    mappings: &[MappingInfo],
+    address: usize,
+) -> Option<Range<usize>> {
+    let mapping = mappings
+        .iter()
+        .find(|mapping| mapping.contains_address(address))?;
+    if mapping.is_executable() || !memory64_list_stream::is_captured_in_full(config, mapping) {
+        if mapping.is_sanitizer_shadow() && !config.include_sanitizer_shadow {
+            config.note_sanitizer_shadow(mapping);
+        }
+        return None;
+    }
+
+    let start = address
+        .saturating_sub(BYTES_BEFORE)
+        .max(mapping.system_mapping_info.start_address);
+    let end = address
+        .saturating_add(BYTES_AFTER)
+        .min(mapping.system_mapping_info.end_address);
+    Some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_referenced_memory() {
+        let rw = MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE;
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(0x10000, 0x1000, rw, None),
+                mapping(0x20000, 0x1000, rw, None),
+                mapping(
+                    0x30000,
+                    0x1000,
+                    MMPermissions::READ | MMPermissions::EXECUTE,
+                    None,
+                ),
+            ],
+            memory: vec![(0x20000, vec![0; 0x1000])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+
+        // A pointer to data, one to code, an unmapped value, and a pointer to
+        // the same object, below the stack pointer first
+        let stack: Vec<u8> = [0x20100, 0x20800, 0x30010, 0x1234, 0x20810]
+            .into_iter()
+            .flat_map(usize::to_ne_bytes)
+            .collect();
+        let section = MemoryArrayWriter::write_bytes(&mut buffer, &stack).unwrap();
+        config.memory_blocks.push(MDMemoryDescriptor {
+            start_of_memory_range: 0x10f00,
+            memory: section.location(),
+        });
+        config.thread_stacks.push(MDRawThreadStack {
+            thread_id: MOCK_PID as u32,
+            stack_pointer: 0x10f00 + std::mem::size_of::<usize>() as u64,
+            ..Default::default()
+        });
+
+        // Nothing is captured unless asked to
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(config.memory_blocks.len(), 1);
+
+        let regions = |config: &MinidumpWriter| -> Vec<_> {
+            config.memory_blocks[1..]
+                .iter()
+                .map(|block| (block.start_of_memory_range, block.memory.data_size))
+                .collect()
+        };
+        config.capture_referenced_memory(0x1000);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(regions(&config), [(0x20780, 0x200), (0x20980, 0x10)]);
+
+        // The budget bounds the memory captured
+        config.memory_blocks.truncate(1);
+        config.capture_referenced_memory(0x100);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(regions(&config), [(0x20780, 0x100)]);
+    }
+}
@@ -0,0 +1,161 @@
+use super::*;
+use crate::linux::maps_reader::MappingInfo;
+use std::ops::Range;
+
+/// How much memory is captured before and after each address held by a
+/// register, enough for the start of the object it points to
+const BYTES_BEFORE: usize = 64;
+const BYTES_AFTER: usize = 192;
+
+/// Write the memory pointed to by the general purpose registers of the
+/// crashing thread, see [`MinidumpWriter::capture_register_memory`].
+///
+/// The registers are the ones written to the thread list, so this has to come
+/// after it. Every register which points into a readable, non-executable
+/// mapping is taken as a pointer to data, and the memory around it is
+/// captured until the budget is spent, leaving out what was already captured,
+/// which doesn't count towards the budget. The instruction and stack pointers
+/// are left out, as the memory around them already is.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<(), errors::SectionRegisterMemoryError> {
+    let Some(budget) = config.register_memory_budget else {
+        return Ok(());
+    };
+    let mappings = dumper.mappings()?;
+
+    let mut remaining = budget;
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    let registers = config.crashing_thread_registers.clone();
+    'registers: for register in registers {
+        let Some(referenced) = referenced_range(config, &mappings, register as usize) else {
+            continue;
+        };
+
+        // Registers pointing to the same object only add what the others
+        // didn't capture
+        for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, referenced) {
+            if remaining == 0 {
+                break 'registers;
+            }
+            let range = range.start..range.end.min(range.start.saturating_add(remaining));
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                over_limit += 1;
+                continue;
+            }
+
+            config.check_cancelled()?;
+            // The mappings may have changed since they were read
+            let Ok(mut data_copy) = dumper.read_memory(range.start, range.len()) else {
+                continue;
+            };
+            config.redact_memory(range.start, &mut data_copy);
+            remaining -= range.len();
+
+            let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+            config.memory_blocks.push(MDMemoryDescriptor {
+                start_of_memory_range: range.start as u64,
+                memory: section.location(),
+            });
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} register memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!("memory region limit reached, leaving out {over_limit} register memory regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+    }
+    Ok(())
+}
+
+/// The memory to capture for the value of a register, if it points to data.
+fn referenced_range(
+    config: &MinidumpWriter,
+    mappings: &[MappingInfo],
+    address: usize,
+) -> Option<Range<usize>> {
+    let mapping = mappings
+        .iter()
+        .find(|mapping| mapping.contains_address(address))?;
+    if mapping.is_executable() || !memory64_list_stream::is_captured_in_full(config, mapping) {
+        return None;
+    }
+
+    let start = address
+        .saturating_sub(BYTES_BEFORE)
+        .max(mapping.system_mapping_info.start_address);
+    let end = address
+        .saturating_add(BYTES_AFTER)
+        .min(mapping.system_mapping_info.end_address);
+    Some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_register_memory() {
+        let dumper = MockDumper {
+            mappings: vec![
+                mapping(
+                    0x10000,
+                    0x1000,
+                    MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
+                    None,
+                ),
+                mapping(
+                    0x30000,
+                    0x1000,
+                    MMPermissions::READ | MMPermissions::EXECUTE,
+                    None,
+                ),
+            ],
+            memory: vec![(0x10000, vec![0; 0x1000])],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+
+        // A pointer to data, one to code, an unmapped value, and a pointer
+        // into the same object
+        config.crashing_thread_registers = vec![0x10100, 0x30010, 0x1234, 0x10180];
+
+        // Nothing is captured unless asked to
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert!(config.memory_blocks.is_empty());
+
+        let regions = |config: &MinidumpWriter| -> Vec<_> {
+            config
+                .memory_blocks
+                .iter()
+                .map(|block| (block.start_of_memory_range, block.memory.data_size))
+                .collect()
+        };
+        config.capture_register_memory(0x1000);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(regions(&config), [(0x100c0, 0x100), (0x101c0, 0x80)]);
+
+        // The budget bounds the memory captured
+        config.memory_blocks.clear();
+        config.capture_register_memory(0x80);
+        write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(regions(&config), [(0x100c0, 0x80)]);
+    }
+}
@@ -0,0 +1,152 @@
+use super::*;
+use crate::{capture_limits, linux::registry};
+
+/// Write the data blocks listed in the registries of the process, see
+/// [`crate::registry`]. The memory denied by
+/// [`MinidumpWriter::set_memory_denylist`] is zeroed, as are the bytes
+/// [`MinidumpWriter::set_memory_redactor`] blanks.
+///
+/// The blocks larger than the segment size limit of
+/// [`MinidumpWriter::set_capture_limits`], or which don't fit in
+/// [`MinidumpWriter::set_max_dump_size`], are left out and recorded as soft
+/// errors. Those which can't be read are left out. Nothing is written if the
+/// process has no registered blocks.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionRegisteredBlocksError> {
+    let mut blocks = Vec::new();
+    let mut omitted = 0;
+    for block in registry::read_blocks(dumper) {
+        config.check_cancelled()?;
+        if let Err(error) = capture_limits::check(
+            "registered block size",
+            block.size as u64,
+            config.capture_limits.max_segment_size,
+        ) {
+            log::warn!("leaving out the registered block {}: {error}", block.name);
+            config
+                .soft_errors
+                .push(errors::SoftError::CaptureLimitExceeded(error));
+            continue;
+        }
+        if !config.fits_in_max_dump_size(buffer, block.size) {
+            omitted += 1;
+            continue;
+        }
+        let mut data = match dumper.read_memory(block.address, block.size) {
+            Ok(data) => data,
+            Err(error) => {
+                log::warn!(
+                    "failed to read the registered block {}: {error}",
+                    block.name
+                );
+                continue;
+            }
+        };
+        config.redact_memory(block.address, &mut data);
+
+        let name = MemoryArrayWriter::write_bytes(buffer, block.name.as_bytes())?;
+        let data = MemoryArrayWriter::write_bytes(buffer, &data)?;
+        blocks.push(MDRawRegisteredBlock {
+            kind: block.kind,
+            address: block.address as u64,
+            name: name.location(),
+            data: data.location(),
+            ..Default::default()
+        });
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} registered data blocks");
+        config
+            .soft_errors
+            .push(errors::SoftError::RegisteredBlocksOmitted(omitted));
+    }
+    if blocks.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, blocks.len() as u32)?;
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxRegisteredBlocks as u32,
+        location: list_header.location(),
+    };
+    let block_list = MemoryArrayWriter::alloc_from_array(buffer, &blocks)?;
+    dirent.location.data_size += block_list.location().data_size;
+    Ok(dirent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::maps_reader::MappingInfo;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_registered_blocks_stream() {
+        use crate::linux::registry::{BlockKind, Registry};
+
+        static VERSION: &[u8] = b"1.2.3";
+        static BREADCRUMBS: [u8; 0x100] = [0xab; 0x100];
+        let mut registry = Registry::new(4).unwrap();
+        registry
+            .register("version", BlockKind::Text, VERSION)
+            .unwrap();
+        registry
+            .register("breadcrumbs", BlockKind::Bytes, &BREADCRUMBS)
+            .unwrap();
+
+        // The registry and the blocks are copied from this process
+        let address = registry.address();
+        // SAFETY: the registry maps a page at least
+        let table = unsafe { std::slice::from_raw_parts(address as *const u8, 0x1000) };
+        let dumper = MockDumper {
+            mappings: vec![MappingInfo {
+                deleted: true,
+                ..mapping(
+                    address,
+                    0x1000,
+                    MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
+                    Some("/memfd:minidump-writer-registry"),
+                )
+            }],
+            memory: vec![
+                (address, table.to_vec()),
+                (VERSION.as_ptr() as usize, VERSION.to_vec()),
+                (BREADCRUMBS.as_ptr() as usize, BREADCRUMBS.to_vec()),
+            ],
+            ..Default::default()
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.set_capture_limits(crate::capture_limits::CaptureLimits {
+            max_segment_size: 0x80,
+            ..Default::default()
+        });
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut config, &mut buffer, &dumper).unwrap();
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::LinuxRegisteredBlocks as u32
+        );
+        // The breadcrumbs are over the size limit
+        let rva = dirent.location.rva as usize;
+        assert_eq!(read_u32(&buffer, rva), 1);
+        let entry = rva + 4;
+        assert_eq!(read_u32(&buffer, entry), BlockKind::Text as u32);
+        let location = |offset: usize| {
+            let size = read_u32(&buffer, entry + offset) as usize;
+            let rva = read_u32(&buffer, entry + offset + 4) as usize;
+            &buffer[rva..rva + size]
+        };
+        assert_eq!(location(16), b"version");
+        assert_eq!(location(24), VERSION);
+        assert!(matches!(
+            config.soft_errors[..],
+            [errors::SoftError::CaptureLimitExceeded(error)] if error.value == 0x100
+        ));
+    }
+}
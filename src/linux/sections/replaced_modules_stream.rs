@@ -0,0 +1,87 @@
+use super::*;
+use std::fmt::Write as _;
+
+/// Write a text stream listing the modules whose backing file was deleted or
+/// replaced on disk after they were mapped, one per line with the following
+/// tab-separated fields:
+///
+/// `<base address> <size> <path>`
+///
+/// The identifiers of these modules are only read from process memory, but
+/// the files found at their paths may not match them anymore, so processors
+/// should be careful when looking up their symbols. Nothing is written if no
+/// such module exists.
+pub fn write(
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionReplacedModulesError> {
+    let mut contents = String::new();
+
+    for mapping in dumper.mappings()?.iter() {
+        if !mapping.deleted || !mapping.is_interesting() {
+            continue;
+        }
+
+        // Writing to a String is infallible
+        let _ = writeln!(
+            contents,
+            "{:#x}\t{:#x}\t{}",
+            mapping.start_address,
+            mapping.size,
+            mapping
+                .name
+                .as_deref()
+                .unwrap_or_default()
+                .to_string_lossy(),
+        );
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxReplacedModules as u32,
+        location: section.location(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::maps_reader::MappingInfo;
+    use crate::linux::sections::mock::*;
+    use procfs_core::process::MMPermissions;
+
+    #[test]
+    fn test_replaced_modules_stream() {
+        let mapping = |start_address, name, deleted| MappingInfo {
+            deleted,
+            ..mapping(
+                start_address,
+                0x1000,
+                MMPermissions::READ | MMPermissions::EXECUTE | MMPermissions::PRIVATE,
+                Some(name),
+            )
+        };
+        let mut dumper = MockDumper {
+            mappings: vec![
+                mapping(0x1000, "/usr/lib/libfoo.so", false),
+                mapping(0x3000, "/usr/lib/libbar.so", true),
+            ],
+            ..Default::default()
+        };
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut buffer, &dumper).unwrap();
+        let location = dirent.location;
+        let contents = &buffer[location.rva as usize..][..location.data_size as usize];
+        assert_eq!(contents, b"0x3000\t0x1000\t/usr/lib/libbar.so\n");
+
+        // Nothing is written if no module was replaced
+        dumper.mappings.truncate(1);
+        let dirent = write(&mut buffer, &dumper).unwrap();
+        assert_eq!(dirent.stream_type, 0);
+    }
+}
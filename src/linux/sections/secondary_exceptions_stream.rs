@@ -0,0 +1,116 @@
+use super::*;
+
+/// Write the faults hit while copying the memory of the process, see
+/// [`errors::SecondaryFault`], as exceptions nested in the one of the
+/// ExceptionStream. Nothing is written if there were none.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionSecondaryExceptionsError> {
+    if config.secondary_faults.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let primary_exception_code = exception_stream::exception_code(config);
+    let exceptions: Vec<_> = config
+        .secondary_faults
+        .iter()
+        .map(|fault| {
+            let signal = if fault.error == nix::Error::EFAULT {
+                libc::SIGSEGV
+            } else {
+                libc::SIGBUS
+            };
+            let mut exception_information = [0; 15];
+            exception_information[0] = fault.length as u64;
+            MDRawSecondaryException {
+                primary_thread_id: config.blamed_thread as u32,
+                primary_exception_code,
+                exception_record: MDException {
+                    exception_code: signal as u32,
+                    exception_flags: fault.error as u32,
+                    exception_address: fault.address as u64,
+                    number_parameters: 1,
+                    exception_information,
+                    ..Default::default()
+                },
+            }
+        })
+        .collect();
+
+    // Memory looks like this:
+    // <num_exceptions><exception_1><exception_2>...
+    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, exceptions.len() as u32)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxSecondaryExceptions as u32,
+        location: list_header.location(),
+    };
+
+    let mut exception_list =
+        MemoryArrayWriter::<MDRawSecondaryException>::alloc_array(buffer, exceptions.len())?;
+    dirent.location.data_size += exception_list.location().data_size;
+    for (index, exception) in exceptions.into_iter().enumerate() {
+        exception_list.set_value_at(buffer, exception, index)?;
+    }
+
+    Ok(dirent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_secondary_exceptions_stream() {
+        let copy_error = |source| {
+            errors::DumperError::CopyFromProcessError(errors::CopyFromProcessError {
+                child: MOCK_PID,
+                src: 0x1000,
+                offset: 0x10,
+                length: 0x20,
+                source,
+            })
+        };
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+
+        // Nothing is written without faults
+        let dirent = write(&config, &mut buffer).unwrap();
+        assert_eq!(dirent.location.data_size, 0);
+
+        // The process being gone isn't a fault
+        assert!(config
+            .record_secondary_fault(copy_error(nix::Error::ESRCH))
+            .is_err());
+        config
+            .record_secondary_fault(copy_error(nix::Error::EFAULT))
+            .unwrap();
+        let dirent = write(&config, &mut buffer).unwrap();
+
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::LinuxSecondaryExceptions as u32
+        );
+        let rva = dirent.location.rva as usize;
+        // A count followed by the 160 byte entries
+        assert_eq!(read_u32(&buffer, rva), 1);
+        assert_eq!(dirent.location.data_size, 4 + 160);
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+
+        let entry = rva + 4;
+        assert_eq!(read_u32(&buffer, entry), MOCK_PID as u32);
+        assert_eq!(
+            read_u32(&buffer, entry + 4),
+            minidump_common::errors::ExceptionCodeLinux::DUMP_REQUESTED as u32
+        );
+        let record = entry + 8;
+        assert_eq!(read_u32(&buffer, record), libc::SIGSEGV as u32);
+        assert_eq!(read_u32(&buffer, record + 4), libc::EFAULT as u32);
+        assert_eq!(read_u64(record + 16), 0x1010);
+        assert_eq!(read_u32(&buffer, record + 24), 1);
+        assert_eq!(read_u64(record + 32), 0x20);
+    }
+}
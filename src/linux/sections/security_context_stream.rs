@@ -0,0 +1,148 @@
+use super::*;
+use std::fmt::Write as _;
+
+/// The security module confining a process, and how
+#[derive(Debug, PartialEq, Eq)]
+struct SecurityContext {
+    lsm: &'static str,
+    /// The SELinux context or AppArmor profile of the process
+    context: String,
+    /// Whether denials are enforced, or only logged
+    mode: String,
+}
+
+/// Write a text stream describing the Linux security module (LSM) confining
+/// the process, SELinux or AppArmor, one `key: value` pair per line:
+///
+/// ```text
+/// lsm: selinux
+/// context: system_u:system_r:httpd_t:s0
+/// mode: enforcing
+/// ```
+///
+/// Denials of these modules make system calls fail with `EPERM` or `EACCES`,
+/// or kill the process, which is hard to tell from a bug without knowing
+/// the process was confined. Nothing is written if neither is enabled.
+pub fn write(buffer: &mut DumpBuf, pid: Pid) -> Result<MDRawDirectory, MemoryWriterError> {
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+    let Some(security_context) = read_security_context(pid, read) else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    let mut contents = String::new();
+    // Writing to a String is infallible
+    let _ = writeln!(contents, "lsm: {}", security_context.lsm);
+    let _ = writeln!(contents, "context: {}", security_context.context);
+    let _ = writeln!(contents, "mode: {}", security_context.mode);
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxSecurityContext as u32,
+        location: section.location(),
+    })
+}
+
+/// Find which security module confines process `pid`, reading files with
+/// `read`. The per-module attribute files are only there when several
+/// modules are stacked, `/proc/<pid>/attr/current` belongs to the first one
+/// otherwise.
+fn read_security_context(
+    pid: Pid,
+    read: impl Fn(&str) -> Option<String>,
+) -> Option<SecurityContext> {
+    // The attributes end with a newline or a nul byte, depending on the module
+    let attribute = |name: &str| {
+        read(&format!("/proc/{pid}/attr/{name}"))
+            .map(|value| value.trim_end_matches(['\n', '\0']).to_owned())
+    };
+
+    if let Some(enforce) = read("/sys/fs/selinux/enforce") {
+        let context = attribute("selinux/current").or_else(|| attribute("current"))?;
+        let mode = if enforce.trim() == "1" {
+            "enforcing"
+        } else {
+            "permissive"
+        };
+        return Some(SecurityContext {
+            lsm: "selinux",
+            context,
+            mode: mode.to_owned(),
+        });
+    }
+
+    if read("/sys/module/apparmor/parameters/enabled").is_some_and(|enabled| enabled.trim() == "Y")
+    {
+        // `<profile> (<mode>)`, or just `unconfined`
+        let label = attribute("apparmor/current").or_else(|| attribute("current"))?;
+        let (context, mode) = match label.rsplit_once(" (") {
+            Some((profile, mode)) => (profile.to_owned(), mode.trim_end_matches(')').to_owned()),
+            None => (label.clone(), label),
+        };
+        return Some(SecurityContext {
+            lsm: "apparmor",
+            context,
+            mode,
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader<'a>(files: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |path| {
+            files
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, contents)| contents.to_string())
+        }
+    }
+
+    #[test]
+    fn test_read_security_context() {
+        assert_eq!(read_security_context(1, reader(&[])), None);
+
+        let selinux = [
+            ("/sys/fs/selinux/enforce", "0"),
+            ("/proc/1/attr/current", "system_u:system_r:httpd_t:s0\0"),
+        ];
+        assert_eq!(
+            read_security_context(1, reader(&selinux)),
+            Some(SecurityContext {
+                lsm: "selinux",
+                context: "system_u:system_r:httpd_t:s0".to_owned(),
+                mode: "permissive".to_owned(),
+            })
+        );
+
+        let apparmor = [
+            ("/sys/module/apparmor/parameters/enabled", "Y\n"),
+            ("/proc/1/attr/apparmor/current", "/usr/bin/man (complain)\n"),
+            ("/proc/1/attr/current", "something else\n"),
+        ];
+        assert_eq!(
+            read_security_context(1, reader(&apparmor)),
+            Some(SecurityContext {
+                lsm: "apparmor",
+                context: "/usr/bin/man".to_owned(),
+                mode: "complain".to_owned(),
+            })
+        );
+
+        let unconfined = [
+            ("/sys/module/apparmor/parameters/enabled", "Y\n"),
+            ("/proc/1/attr/current", "unconfined\n"),
+        ];
+        assert_eq!(
+            read_security_context(1, reader(&unconfined)),
+            Some(SecurityContext {
+                lsm: "apparmor",
+                context: "unconfined".to_owned(),
+                mode: "unconfined".to_owned(),
+            })
+        );
+    }
+}
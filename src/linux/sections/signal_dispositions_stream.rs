@@ -0,0 +1,24 @@
+use super::*;
+use crate::signal_dispositions::{self, SignalDisposition};
+use procfs_core::{process::Status, FromRead};
+
+/// Write the dispositions of the signals, see [`crate::signal_dispositions`].
+/// The ones supplied with [`MinidumpWriter::set_signal_dispositions`] are
+/// written if any, and otherwise the ones read from `/proc/<pid>/status`,
+/// which have no handler addresses.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    if !config.signal_dispositions.is_empty() {
+        return signal_dispositions::write(buffer, &config.signal_dispositions);
+    }
+
+    let status_path = format!("/proc/{}/status", config.process_id);
+    let dispositions: Vec<SignalDisposition> =
+        match std::fs::File::open(status_path).map(Status::from_read) {
+            Ok(Ok(status)) => signal_dispositions::from_signal_sets(status.sigign, status.sigcgt),
+            _ => return Ok(MDRawDirectory::default()),
+        };
+    signal_dispositions::write(buffer, &dispositions)
+}
@@ -0,0 +1,25 @@
+use super::*;
+use std::fmt::Write as _;
+
+/// Write a text stream listing the [`errors::SoftError`]s encountered while
+/// writing the minidump, one per line. Nothing is written if there were none.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionSoftErrorsError> {
+    if config.soft_errors.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let mut contents = String::new();
+    for error in &config.soft_errors {
+        // Writing to a String is infallible
+        let _ = writeln!(contents, "{error}");
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxSoftErrors as u32,
+        location: section.location(),
+    })
+}
@@ -0,0 +1,343 @@
+use super::*;
+use procfs_core::{
+    process::{Stat, Status},
+    FromRead,
+};
+use std::fmt::Write as _;
+
+/// The FILETIME of the Unix epoch: FILETIMEs count 100ns intervals since 1601
+const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+const FILETIME_TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Write the ThreadInfoListStream, holding the time each thread was started
+/// and the CPU time it spent in user and kernel mode, as read from
+/// `/proc/<pid>/task/<tid>/stat`, to find the threads which were busy before
+/// the process was dumped. As on Windows, the start time is a FILETIME and
+/// the CPU times are in units of 100ns, with the granularity of the clock
+/// ticks of the kernel.
+///
+/// Threads which exited in the meantime are flagged with
+/// [`MD_THREAD_INFO_INVALID_INFO`]. The CPU times are left out of
+/// deterministic dumps, see [`MinidumpWriter::deterministic`].
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionThreadInfoListError> {
+    let threads = dumper.threads()?;
+    let ticks_per_second = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK)
+        .ok()
+        .flatten()
+        .filter(|&ticks| ticks > 0)
+        .unwrap_or(100) as u64;
+    let boot_time = timestamps_stream::boot_time();
+
+    let list_header = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawThreadInfoList {
+            size_of_header: std::mem::size_of::<MDRawThreadInfoList>() as u32,
+            size_of_entry: std::mem::size_of::<MDRawThreadInfo>() as u32,
+            number_of_entries: threads.len() as u32,
+        },
+    )?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDStreamType::ThreadInfoListStream as u32,
+        location: list_header.location(),
+    };
+
+    let mut info_list = MemoryArrayWriter::<MDRawThreadInfo>::alloc_array(buffer, threads.len())?;
+    dirent.location.data_size += info_list.location().data_size;
+
+    for (idx, thread) in threads.iter().enumerate() {
+        let mut info = MDRawThreadInfo {
+            thread_id: thread.tid.try_into()?,
+            dump_flags: 0,
+            dump_error: 0,
+            exit_status: 0,
+            create_time: 0,
+            exit_time: 0,
+            kernel_time: 0,
+            user_time: 0,
+            start_address: 0,
+            affinity: affinity(thread.tid),
+        };
+
+        let stat_path = format!("/proc/{}/task/{}/stat", config.process_id, thread.tid);
+        match std::fs::File::open(stat_path).map(Stat::from_read) {
+            Ok(Ok(stat)) => {
+                let to_filetime_ticks =
+                    |ticks: u64| ticks * FILETIME_TICKS_PER_SECOND / ticks_per_second;
+                // The start time of the thread is relative to the system boot
+                if let Some(boot_time) = boot_time {
+                    info.create_time = UNIX_EPOCH_FILETIME
+                        + boot_time * FILETIME_TICKS_PER_SECOND
+                        + to_filetime_ticks(stat.starttime);
+                }
+                if config.deterministic_clock.is_none() {
+                    info.user_time = to_filetime_ticks(stat.utime);
+                    info.kernel_time = to_filetime_ticks(stat.stime);
+                }
+            }
+            _ => info.dump_flags |= MD_THREAD_INFO_INVALID_INFO,
+        }
+
+        info_list.set_value_at(buffer, info, idx)?;
+    }
+
+    Ok(dirent)
+}
+
+/// Write a text stream listing the CPU each thread last ran on and the CPUs
+/// it may run on, as read from `/proc/<pid>/task/<tid>/stat` and `status`,
+/// which tell apart the crashes happening on specific cores or NUMA nodes.
+/// Unlike the affinity of the ThreadInfoListStream, which only covers the
+/// first 64 CPUs, all of them are listed, as ranges, e.g.:
+///
+/// ```text
+/// 1234 last_cpu=3 cpus_allowed=0-7,16-23
+/// ```
+///
+/// Threads which exited in the meantime are left out.
+pub fn write_cpus(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionThreadInfoListError> {
+    let mut contents = String::new();
+    for thread in dumper.threads()?.iter() {
+        let task_path = format!("/proc/{}/task/{}", config.process_id, thread.tid);
+        let stat = std::fs::File::open(format!("{task_path}/stat")).map(Stat::from_read);
+        let status = std::fs::File::open(format!("{task_path}/status")).map(Status::from_read);
+        let (Ok(Ok(stat)), Ok(Ok(status))) = (stat, status) else {
+            continue;
+        };
+
+        // Writing to a String is infallible
+        let _ = write!(contents, "{}", thread.tid);
+        if let Some(cpu) = stat.processor {
+            let _ = write!(contents, " last_cpu={cpu}");
+        }
+        if let Some(cpus) = status.cpus_allowed_list {
+            let cpus: Vec<_> = cpus
+                .iter()
+                .map(|&(first, last)| {
+                    if first == last {
+                        first.to_string()
+                    } else {
+                        format!("{first}-{last}")
+                    }
+                })
+                .collect();
+            let _ = write!(contents, " cpus_allowed={}", cpus.join(","));
+        }
+        contents.push('\n');
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadCpus as u32,
+        location: section.location(),
+    })
+}
+
+/// Write the LinuxThreadStops stream, listing why each thread was stopped
+/// when the dumper suspended it, and the signals it was stopping for before
+/// that, which were delivered to it again, e.g.:
+///
+/// ```text
+/// 1234 stop group_stop
+/// 1235 stop signal=SIGSTOP code=0 sender=4321
+/// 1235 reinjected signal=SIGSEGV code=1
+/// ```
+///
+/// This tells apart the threads which were crashing or being signaled at
+/// the same time as the one the dump was requested for.
+pub fn write_stops(
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionThreadInfoListError> {
+    let mut contents = String::new();
+    for thread in &dumper.threads {
+        let Some(stop) = dumper.thread_stop(thread.tid) else {
+            continue;
+        };
+        // Writing to a String is infallible
+        if let Some(reason) = &stop.reason {
+            let _ = writeln!(contents, "{} stop {reason}", thread.tid);
+        }
+        for reason in &stop.reinjected {
+            let _ = writeln!(contents, "{} reinjected {reason}", thread.tid);
+        }
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadStops as u32,
+        location: section.location(),
+    })
+}
+
+/// Write the LinuxThreadTlsRegisters stream, listing the registers each
+/// thread finds its thread-local data through, which neither the thread
+/// contexts nor the ThreadInfoListStream have room for, e.g.:
+///
+/// ```text
+/// 1234 fs_base=0x7f3a2c5ff740 gs_base=0x0
+/// ```
+///
+/// This locates the structures held in TLS, such as `errno` or the caches of
+/// the allocator, for every thread rather than only through the `teb` of the
+/// thread list, which holds the thread pointer. The threads whose registers
+/// couldn't be read are left out.
+pub fn write_tls_registers(
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<MDRawDirectory, errors::SectionThreadInfoListError> {
+    let mut contents = String::new();
+    for (idx, thread) in dumper.threads.iter().enumerate() {
+        let Some(info) = dumper.captured_thread_info(idx) else {
+            continue;
+        };
+        let registers = info.get_tls_registers();
+        if registers.is_empty() {
+            continue;
+        }
+        // Writing to a String is infallible
+        let _ = write!(contents, "{}", thread.tid);
+        for (name, value) in registers {
+            let _ = write!(contents, " {name}={value:#x}");
+        }
+        contents.push('\n');
+    }
+
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadTlsRegisters as u32,
+        location: section.location(),
+    })
+}
+
+/// The CPUs the thread may run on, as a mask of the first 64 of them
+fn affinity(tid: Pid) -> u64 {
+    // SAFETY: cpu_set_t is a plain bit array
+    let mut cpus: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: syscall, cpus is as large as the size we pass
+    if unsafe { libc::sched_getaffinity(tid, std::mem::size_of_val(&cpus), &mut cpus) } != 0 {
+        return 0;
+    }
+    (0..u64::BITS as usize)
+        // SAFETY: the index is within the set
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &cpus) })
+        .fold(0, |mask, cpu| mask | 1 << cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::ptrace_dumper::Thread;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_thread_info_list_stream() {
+        let pid = std::process::id() as Pid;
+        // SAFETY: syscall
+        let tid = unsafe { libc::gettid() };
+        let dumper = MockDumper {
+            threads: vec![
+                Thread { tid, name: None },
+                // A thread which exited
+                Thread {
+                    tid: i32::MAX,
+                    name: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let config = MinidumpWriter::new(pid, tid);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&config, &mut buffer, &dumper).unwrap();
+
+        assert_eq!(
+            dirent.stream_type,
+            MDStreamType::ThreadInfoListStream as u32
+        );
+        let rva = dirent.location.rva as usize;
+        // A 12 byte header holding the number of entries last, followed by the
+        // 64 byte entries
+        assert_eq!(read_u32(&buffer, rva + 8), 2);
+        assert_eq!(dirent.location.data_size, 12 + 2 * 64);
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+
+        let entry = rva + 12;
+        assert_eq!(read_u32(&buffer, entry), tid as u32);
+        assert_eq!(read_u32(&buffer, entry + 4), 0);
+        // Started after 2020, the creation time being a FILETIME
+        assert!(read_u64(entry + 16) > 132_223_104_000_000_000);
+        // The affinity
+        assert_ne!(read_u64(entry + 56), 0);
+
+        let entry = rva + 12 + 64;
+        assert_eq!(read_u32(&buffer, entry + 4), MD_THREAD_INFO_INVALID_INFO);
+        assert_eq!(read_u64(entry + 16), 0);
+    }
+
+    #[test]
+    fn test_thread_cpus_stream() {
+        let pid = std::process::id() as Pid;
+        // SAFETY: syscall
+        let tid = unsafe { libc::gettid() };
+        let dumper = MockDumper {
+            threads: vec![
+                Thread { tid, name: None },
+                // A thread which exited
+                Thread {
+                    tid: i32::MAX,
+                    name: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let config = MinidumpWriter::new(pid, tid);
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write_cpus(&config, &mut buffer, &dumper).unwrap();
+
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::LinuxThreadCpus as u32
+        );
+        let start = dirent.location.rva as usize;
+        let text = std::str::from_utf8(&buffer[start..start + dirent.location.data_size as usize])
+            .unwrap();
+        // The exited thread is left out
+        let line = text.strip_suffix('\n').unwrap();
+        assert!(!line.contains('\n'));
+        let mut fields = line.split(' ');
+        assert_eq!(fields.next(), Some(tid.to_string().as_str()));
+        let last_cpu: u32 = fields
+            .next()
+            .and_then(|field| field.strip_prefix("last_cpu="))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let cpus_allowed = fields
+            .next()
+            .and_then(|field| field.strip_prefix("cpus_allowed="))
+            .unwrap();
+        // This thread is running, so it may run where it last did
+        assert!(cpus_allowed.split(',').any(|cpus| {
+            let (first, last) = cpus.split_once('-').unwrap_or((cpus, cpus));
+            (first.parse().unwrap()..=last.parse().unwrap()).contains(&last_cpu)
+        }));
+    }
+}
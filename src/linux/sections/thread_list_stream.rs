@@ -1,7 +1,9 @@
 use std::cmp::min;
 
 use super::*;
-use crate::{minidump_cpu::RawContextCPU, minidump_writer::CrashingThreadContext};
+use crate::{
+    errors::DumperError, minidump_cpu::RawContextCPU, minidump_writer::CrashingThreadContext,
+};
 
 // The following kLimit* constants are for when minidump_size_limit_ is set
 // and the minidump size might exceed it.
@@ -18,6 +20,16 @@ const LIMIT_MAX_EXTRA_THREAD_STACK_LEN: usize = 2 * 1024;
 // Make sure this number of additional bytes can fit in the minidump
 // (exclude the stack data).
 const LIMIT_MINIDUMP_FUDGE_FACTOR: u64 = 64 * 1024;
+// Maximum amount of memory to dump next to a stack pointer pointing into
+// unmapped memory (in bytes).
+const UNMAPPED_STACK_POINTER_FALLBACK_LEN: usize = 32 * 1024;
+
+// Amount of memory to dump around the crashing instruction pointer (in bytes).
+const CRASHING_IP_MEMORY_SIZE: usize = 256;
+
+// Amount of stack scanned for pointers into the crashing module when ranking
+// the threads (in bytes).
+const RANKING_STACK_SCAN_LEN: usize = 16 * 1024;
 
 #[derive(Debug, Clone, Copy)]
 enum MaxStackLen {
@@ -31,10 +43,12 @@ pub fn write(
     dumper: &PtraceDumper,
 ) -> Result<MDRawDirectory, errors::SectionThreadListError> {
     let num_threads = dumper.threads.len();
+    // The stacks are associated with the threads as they are enumerated here
+    config.thread_stacks.clear();
     // Memory looks like this:
     // <num_threads><thread_1><thread_2>...
 
-    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, num_threads as u32)?;
+    let mut list_header = MemoryWriter::<u32>::alloc_with_val(buffer, num_threads as u32)?;
 
     let mut dirent = MDRawDirectory {
         stream_type: MDStreamType::ThreadListStream as u32,
@@ -59,13 +73,34 @@ pub fn write(
         }
     }
 
-    for (idx, item) in dumper.threads.clone().iter().enumerate() {
+    // Threads may exit after we enumerated them, in which case they are left
+    // out of the list
+    let mut num_written = 0;
+    let mut stacks_omitted = 0;
+    // The threads whose stack was truncated, see MinidumpWriter::set_max_full_stacks,
+    // and those whose stack was left out, see MinidumpWriter::set_max_thread_stacks
+    let mut stacks_truncated = 0;
+    let mut stacks_limited = 0;
+    let ranking = if !config.minimal
+        && (config.max_full_stacks.is_some() || config.max_thread_stacks.is_some())
+    {
+        rank_threads(config, dumper)
+    } else {
+        Vec::new()
+    };
+    let mut ip_memory_omitted = IpMemoryOmitted::default();
+    for (idx, item) in dumper.threads.iter().enumerate() {
+        config.check_cancelled()?;
         let mut thread = MDRawThread {
             thread_id: item.tid.try_into()?,
             suspend_count: 0,
             priority_class: 0,
             priority: 0,
-            teb: 0,
+            // As Crashpad does, in lieu of the Windows thread environment block
+            teb: dumper
+                .captured_thread_info(idx)
+                .and_then(|info| info.get_thread_pointer())
+                .unwrap_or_default() as u64,
             stack: MDMemoryDescriptor::default(),
             thread_context: MDLocationDescriptor::default(),
         };
@@ -74,10 +109,12 @@ pub fn write(
         // we used the actual state of the thread we would find it running in the
         // signal handler with the alternative stack, which would be deeply
         // unhelpful.
-        if config.crash_context.is_some() && thread.thread_id == config.blamed_thread as u32 {
-            let crash_context = config.crash_context.as_ref().unwrap();
-            let instruction_ptr = crash_context.get_instruction_pointer();
-            let stack_pointer = crash_context.get_stack_pointer();
+        let supplied_context = (thread.thread_id == config.blamed_thread as u32)
+            .then(|| config.supplied_blamed_thread_context())
+            .flatten();
+        if let Some(cpu) = supplied_context {
+            let (instruction_ptr, stack_pointer) =
+                crate::linux::crash_context::context_pointers(&cpu);
             fill_thread_stack(
                 config,
                 buffer,
@@ -86,77 +123,146 @@ pub fn write(
                 instruction_ptr,
                 stack_pointer,
                 MaxStackLen::None,
+                None,
             )?;
-            // Copy 256 bytes around crashing instruction pointer to minidump.
-            let ip_memory_size: usize = 256;
-            // Bound it to the upper and lower bounds of the memory map
-            // it's contained within. If it's not in mapped memory,
-            // don't bother trying to write it.
-            for mapping in &dumper.mappings {
-                if instruction_ptr < mapping.start_address
-                    || instruction_ptr >= mapping.start_address + mapping.size
-                {
-                    continue;
-                }
-                // Try to get 128 bytes before and after the IP, but
-                // settle for whatever's available.
-                let mut ip_memory_d = MDMemoryDescriptor {
-                    start_of_memory_range: std::cmp::max(
-                        mapping.start_address,
-                        instruction_ptr - ip_memory_size / 2,
-                    ) as u64,
-                    ..Default::default()
-                };
-
-                let end_of_range = std::cmp::min(
-                    mapping.start_address + mapping.size,
-                    instruction_ptr + ip_memory_size / 2,
-                ) as u64;
-                ip_memory_d.memory.data_size =
-                    (end_of_range - ip_memory_d.start_of_memory_range) as u32;
-
-                let memory_copy = PtraceDumper::copy_from_process(
-                    thread.thread_id as i32,
-                    ip_memory_d.start_of_memory_range as _,
-                    ip_memory_d.memory.data_size as usize,
-                )?;
-
-                let mem_section = MemoryArrayWriter::alloc_from_array(buffer, &memory_copy)?;
-                ip_memory_d.memory = mem_section.location();
-                config.memory_blocks.push(ip_memory_d);
-
-                break;
-            }
-            // let cpu = MemoryWriter::alloc(buffer, &memory_copy)?;
-            let mut cpu: RawContextCPU = Default::default();
-            let crash_context = config.crash_context.as_ref().unwrap();
-            crash_context.fill_cpu_context(&mut cpu);
+            // Copy the memory around the crashing instruction pointer to the minidump.
+            fill_ip_memory(
+                config,
+                buffer,
+                dumper,
+                item.tid,
+                instruction_ptr,
+                CRASHING_IP_MEMORY_SIZE,
+                None,
+            )?;
+            config.crashing_thread_registers = crate::linux::crash_context::general_registers(&cpu);
             let cpu_section = MemoryWriter::alloc_with_val(buffer, cpu)?;
             thread.thread_context = cpu_section.location();
 
-            config.crashing_thread_context =
-                CrashingThreadContext::CrashContext(cpu_section.location());
+            config.crashing_thread_context = if config.crash_context.is_some() {
+                CrashingThreadContext::CrashContext(cpu_section.location())
+            } else {
+                CrashingThreadContext::CrashContextPlusAddress((
+                    cpu_section.location(),
+                    instruction_ptr,
+                ))
+            };
+
+            // Minimal dumps only have the thread contexts
+            #[cfg(target_arch = "x86_64")]
+            if !config.minimal {
+                let ssp = match dumper.captured_thread_info(idx) {
+                    Some(info) => info.shadow_stack_pointer,
+                    None => crate::thread_info::ThreadInfo::get_shadow_stack_pointer(item.tid),
+                };
+                fill_shadow_stack(config, buffer, dumper, &thread, ssp, MaxStackLen::None)?;
+                // The extended state of the crash context doesn't go along
+                // with explicitly supplied registers
+                let xsave = match &config.crash_context {
+                    Some(crash_context) if config.blamed_thread_context.is_none() => {
+                        crash_context_xstate(crash_context, item.tid)
+                    }
+                    _ => None,
+                };
+                fill_xstate(config, buffer, &thread, xsave.as_deref())?;
+            }
         } else {
-            let info = dumper.get_thread_info_by_index(idx)?;
+            // Only read the registers again if they couldn't be captured
+            // along with the other threads' ones
+            let live_info;
+            let info = match dumper.captured_thread_info(idx) {
+                Some(info) => info,
+                None => match dumper.get_thread_info_by_index(idx) {
+                    Ok(info) => {
+                        live_info = info;
+                        &live_info
+                    }
+                    Err(e) if e.is_thread_gone() => {
+                        skip_exited_thread(config, item.tid);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+            };
+            // The rank of the thread among those whose stack may be
+            // captured, the blamed thread isn't ranked as its stack always is
+            let rank = ranking.iter().position(|&tid| tid == item.tid);
+            let over_stack_limit = rank
+                .zip(config.max_thread_stacks)
+                .is_some_and(|(rank, max_thread_stacks)| rank >= max_thread_stacks);
+            if over_stack_limit {
+                stacks_limited += 1;
+            }
+            // Threads whose stack isn't wanted, see MinidumpWriter::set_thread_filter
+            let registers_only = config.minimal
+                || config.registers_only_threads.contains(&item.tid)
+                || over_stack_limit;
+            let over_full_stack_limit = !registers_only
+                && rank
+                    .zip(config.max_full_stacks)
+                    .is_some_and(|(rank, max_full_stacks)| rank >= max_full_stacks);
             let max_stack_len =
                 if config.minidump_size_limit.is_some() && idx >= LIMIT_BASE_THREAD_COUNT {
                     extra_thread_stack_len
+                } else if over_full_stack_limit {
+                    stacks_truncated += 1;
+                    MaxStackLen::Len(LIMIT_MAX_EXTRA_THREAD_STACK_LEN)
                 } else {
                     MaxStackLen::None // default to no maximum for this thread
                 };
+            // The stacks of the extra threads are the first thing left out
+            // after the additional memory regions to respect the dump size
+            let omitted_before = stacks_omitted;
+            let droppable = idx >= LIMIT_BASE_THREAD_COUNT && item.tid != config.blamed_thread;
+            let omitted_counter = droppable.then_some(&mut stacks_omitted);
             let instruction_ptr = info.get_instruction_pointer();
-            fill_thread_stack(
-                config,
-                buffer,
-                dumper,
-                &mut thread,
-                instruction_ptr,
-                info.stack_pointer,
-                max_stack_len,
-            )?;
+            let filled = if registers_only && item.tid != config.blamed_thread {
+                // Minimal dumps only have the crashing thread's stack, and so do
+                // the threads the filter only wants the registers of
+                Ok(())
+            } else {
+                fill_thread_stack(
+                    config,
+                    buffer,
+                    dumper,
+                    &mut thread,
+                    instruction_ptr,
+                    info.stack_pointer,
+                    max_stack_len,
+                    omitted_counter,
+                )
+            };
+            if let Err(e) = filled {
+                if !e.is_thread_gone() {
+                    return Err(e);
+                }
+                config
+                    .thread_stacks
+                    .retain(|stack| stack.thread_id != thread.thread_id);
+                skip_exited_thread(config, item.tid);
+                continue;
+            }
+
+            // The memory around the other threads' instruction pointers is
+            // dropped like their stacks to respect the dump size
+            if let Some(window) = config.thread_ip_memory_window.filter(|_| !registers_only) {
+                fill_ip_memory(
+                    config,
+                    buffer,
+                    dumper,
+                    item.tid,
+                    instruction_ptr,
+                    window,
+                    Some(&mut ip_memory_omitted),
+                )?;
+            }
 
             let mut cpu = RawContextCPU::default();
             info.fill_cpu_context(&mut cpu);
+            if item.tid == config.blamed_thread {
+                config.crashing_thread_registers =
+                    crate::linux::crash_context::general_registers(&cpu);
+            }
             let cpu_section = MemoryWriter::<RawContextCPU>::alloc_with_val(buffer, cpu)?;
             thread.thread_context = cpu_section.location();
             if item.tid == config.blamed_thread {
@@ -168,12 +274,224 @@ pub fn write(
                     instruction_ptr,
                 ));
             }
+
+            #[cfg(target_arch = "x86_64")]
+            if !config.minimal {
+                if !registers_only {
+                    fill_shadow_stack(
+                        config,
+                        buffer,
+                        dumper,
+                        &thread,
+                        info.shadow_stack_pointer,
+                        max_stack_len,
+                    )?;
+                }
+                // The extended state is large, skip it for the extra threads
+                // like their stacks when trying to respect the size limit
+                if matches!(max_stack_len, MaxStackLen::None) && stacks_omitted == omitted_before {
+                    fill_xstate(config, buffer, &thread, info.xstate.as_deref())?;
+                }
+            }
         }
-        thread_list.set_value_at(buffer, thread, idx)?;
+        thread_list.set_value_at(buffer, thread, num_written)?;
+        num_written += 1;
+    }
+
+    if stacks_omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {stacks_omitted} thread stacks");
+        config
+            .soft_errors
+            .push(errors::SoftError::StacksOmitted(stacks_omitted));
+    }
+    if stacks_truncated > 0 {
+        log::warn!("full stack limit reached, truncating {stacks_truncated} thread stacks");
+        config
+            .soft_errors
+            .push(errors::SoftError::StacksTruncated(stacks_truncated));
+    }
+    if stacks_limited > 0 {
+        log::warn!("thread stack limit reached, leaving out {stacks_limited} thread stacks");
+        config
+            .soft_errors
+            .push(errors::SoftError::ThreadStacksLimitReached(stacks_limited));
+    }
+
+    if ip_memory_omitted.size > 0 {
+        log::warn!(
+            "dump size limit reached, leaving out {} instruction memory regions",
+            ip_memory_omitted.size
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(
+                ip_memory_omitted.size,
+            ));
+    }
+    if ip_memory_omitted.count > 0 {
+        log::warn!(
+            "memory region limit reached, leaving out {} instruction memory regions",
+            ip_memory_omitted.count
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(
+                ip_memory_omitted.count,
+            ));
+    }
+
+    if num_written < num_threads {
+        list_header.set_value(buffer, num_written as u32)?;
+        dirent.location.data_size = list_header.location().data_size
+            + thread_list.location_of_index(0).data_size * num_written as u32;
     }
     Ok(dirent)
 }
 
+/// The threads whose stack may be captured, other than the blamed one, most
+/// relevant first, see [`MinidumpWriter::set_max_full_stacks`]: those
+/// executing the module the crash happened in or whose stack refers to it,
+/// then the others, in the order of the thread list.
+fn rank_threads(config: &MinidumpWriter, dumper: &PtraceDumper) -> Vec<Pid> {
+    let crashing_module = config
+        .supplied_blamed_thread_pointers()
+        .map(|(instruction_ptr, _)| instruction_ptr)
+        .or_else(|| {
+            let idx = dumper
+                .threads
+                .iter()
+                .position(|thread| thread.tid == config.blamed_thread)?;
+            Some(dumper.captured_thread_info(idx)?.get_instruction_pointer())
+        })
+        .and_then(|instruction_ptr| dumper.find_mapping(instruction_ptr))
+        .map(|mapping| mapping.start_address..mapping.end_address());
+
+    let mut ranking: Vec<_> = dumper
+        .threads
+        .iter()
+        .enumerate()
+        .filter(|(_, thread)| {
+            thread.tid != config.blamed_thread
+                && !config.registers_only_threads.contains(&thread.tid)
+        })
+        .map(|(idx, thread)| {
+            let refers_to_crash = crashing_module.as_ref().is_some_and(|module| {
+                dumper
+                    .captured_thread_info(idx)
+                    .is_some_and(|info| refers_to(dumper, thread.tid, info, module))
+            });
+            (!refers_to_crash, thread.tid)
+        })
+        .collect();
+    // Stable sorting keeps the order of the thread list for equal relevance
+    ranking.sort_by_key(|&(unrelated, _)| unrelated);
+    ranking.into_iter().map(|(_, tid)| tid).collect()
+}
+
+/// Whether the thread `tid` is executing code of `module`, or the top of its
+/// stack holds a pointer into it, such as a return address.
+fn refers_to(
+    dumper: &PtraceDumper,
+    tid: Pid,
+    info: &crate::linux::thread_info::ThreadInfo,
+    module: &std::ops::Range<usize>,
+) -> bool {
+    if module.contains(&info.get_instruction_pointer()) {
+        return true;
+    }
+    let Ok((stack_start, stack_len)) = dumper.get_stack_info(info.stack_pointer) else {
+        return false;
+    };
+    let scan_start = info.stack_pointer.max(stack_start);
+    let scan_len = (stack_start + stack_len)
+        .saturating_sub(scan_start)
+        .min(RANKING_STACK_SCAN_LEN);
+    let Ok(stack) = PtraceDumper::copy_from_process(tid, scan_start, scan_len) else {
+        return false;
+    };
+    stack
+        .chunks_exact(std::mem::size_of::<usize>())
+        .map(|word| usize::from_ne_bytes(word.try_into().unwrap()))
+        .any(|word| module.contains(&word))
+}
+
+/// The regions around the instruction pointers of the threads other than the
+/// crashing one which were left out, because of the dump size and because of
+/// the number of memory regions
+#[derive(Debug, Default)]
+struct IpMemoryOmitted {
+    size: usize,
+    count: usize,
+}
+
+/// Copy up to `ip_memory_size` bytes around `instruction_ptr` to the minidump,
+/// bounded by the mapping it's in, leaving out what was already captured.
+/// Nothing is copied if it isn't in mapped memory. The regions which may be
+/// dropped to respect the dump limits are counted in `omitted`.
+#[allow(clippy::too_many_arguments)]
+fn fill_ip_memory(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+    tid: Pid,
+    instruction_ptr: usize,
+    ip_memory_size: usize,
+    mut omitted: Option<&mut IpMemoryOmitted>,
+) -> Result<(), errors::SectionThreadListError> {
+    let Some(mapping) = dumper.find_mapping(instruction_ptr) else {
+        return Ok(());
+    };
+    // Try to get half of the memory before and after the IP, but settle for
+    // whatever's available.
+    let start_of_range = mapping
+        .start_address
+        .max(instruction_ptr.saturating_sub(ip_memory_size / 2));
+    let end_of_range = mapping
+        .end_address()
+        .min(instruction_ptr.saturating_add(ip_memory_size / 2));
+    let ip_range =
+        memory_list_stream::page_aligned(config, &dumper.mappings, start_of_range..end_of_range);
+
+    // Leave out what the stack already covers, if the code happens to live
+    // there, and what other threads running the same code captured
+    for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, ip_range) {
+        if let Some(omitted) = omitted.as_deref_mut() {
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted.size += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                omitted.count += 1;
+                continue;
+            }
+        }
+
+        let mut memory_copy = match PtraceDumper::copy_from_process(tid, range.start, range.len()) {
+            Ok(memory_copy) => memory_copy,
+            Err(e) => {
+                config.record_secondary_fault(e)?;
+                continue;
+            }
+        };
+        config.redact_memory(range.start, &mut memory_copy);
+
+        let mem_section = MemoryArrayWriter::alloc_from_array(buffer, &memory_copy)?;
+        config.memory_blocks.push(MDMemoryDescriptor {
+            start_of_memory_range: range.start as u64,
+            memory: mem_section.location(),
+        });
+    }
+    Ok(())
+}
+
+fn skip_exited_thread(config: &mut MinidumpWriter, tid: Pid) {
+    log::warn!("thread {tid} exited before it could be captured, skipping it");
+    config
+        .soft_errors
+        .push(errors::SoftError::ThreadExited(tid));
+}
+
+#[allow(clippy::too_many_arguments)]
 fn fill_thread_stack(
     config: &mut MinidumpWriter,
     buffer: &mut DumpBuf,
@@ -182,24 +500,97 @@ fn fill_thread_stack(
     instruction_ptr: usize,
     stack_ptr: usize,
     max_stack_len: MaxStackLen,
+    stacks_omitted: Option<&mut usize>,
 ) -> Result<(), errors::SectionThreadListError> {
     thread.stack.start_of_memory_range = stack_ptr.try_into()?;
     thread.stack.memory.data_size = 0;
-    thread.stack.memory.rva = buffer.position() as u32;
+    thread.stack.memory.rva = buffer.next_location(0)?.rva;
 
-    if let Ok((valid_stack_ptr, stack_len)) = dumper.get_stack_info(stack_ptr) {
+    if let Some(mapping) = dumper.find_thread_stack_mapping(thread.thread_id as Pid, stack_ptr) {
+        config.thread_stacks.push(MDRawThreadStack {
+            thread_id: thread.thread_id,
+            stack_pointer: stack_ptr as u64,
+            start_of_mapping: mapping.start_address as u64,
+            size_of_mapping: mapping.size as u64,
+            ..Default::default()
+        });
+    }
+
+    if let Some(mapping) = dumper.find_stack_mapping(stack_ptr) {
+        // A garbage stack pointer might point into sanitizer shadow memory,
+        // copying it would mean reading (or failing to allocate) terabytes.
+        // The stack is left empty, with its start marking the stack pointer.
+        if mapping.is_sanitizer_shadow() && !config.include_sanitizer_shadow {
+            config.note_sanitizer_shadow(mapping);
+            log::warn!(
+                "stack pointer {stack_ptr:#x} of thread {} points into sanitizer shadow memory, skipping its stack",
+                thread.thread_id
+            );
+            return Ok(());
+        }
+    }
+
+    let stack_info = match dumper.get_stack_info_with_red_zone(stack_ptr, config.stack_red_zone) {
+        Ok(stack_info) => Some(stack_info),
+        Err(_) => unmapped_stack_pointer_fallback(config, dumper, stack_ptr, max_stack_len),
+    };
+    if let Some((valid_stack_ptr, stack_len)) = stack_info {
         let stack_len = if let MaxStackLen::Len(max_stack_len) = max_stack_len {
             min(stack_len, max_stack_len)
         } else {
             stack_len
         };
+        let stack_len = match config.max_stack_bytes(thread.thread_id as Pid) {
+            Some(max_bytes) => min(
+                stack_len,
+                stack_ptr
+                    .saturating_sub(valid_stack_ptr)
+                    .saturating_add(max_bytes),
+            ),
+            None => stack_len,
+        };
+        let stack_len =
+            memory_list_stream::capped(config, valid_stack_ptr..valid_stack_ptr + stack_len).len();
+
+        // Stacks which may be dropped are counted in `stacks_omitted`
+        if let Some(stacks_omitted) = stacks_omitted {
+            if !config.fits_in_max_dump_size(buffer, stack_len) {
+                *stacks_omitted += 1;
+                return Ok(());
+            }
+        }
 
-        let mut stack_bytes = PtraceDumper::copy_from_process(
-            thread.thread_id.try_into()?,
-            valid_stack_ptr,
-            stack_len,
-        )?;
-        let stack_pointer_offset = stack_ptr.saturating_sub(valid_stack_ptr);
+        let stack_range = valid_stack_ptr..valid_stack_ptr + stack_len;
+        let tid = thread.thread_id.try_into()?;
+        let read = dumper.read_scratch(tid, valid_stack_ptr, stack_len);
+        let (valid_stack_ptr, mut stack_bytes) = match read {
+            Ok(stack_bytes) => (valid_stack_ptr, stack_bytes),
+            Err(DumperError::CopyFromProcessError(e)) if e.source == nix::Error::ESRCH => {
+                return Err(DumperError::CopyFromProcessError(e).into());
+            }
+            // Leave out the pages which can't be read, e.g. a guard page the
+            // stack pointer landed next to, keeping the part of the stack
+            // the stack pointer is in, or the one above it
+            Err(_) => {
+                let parts = memory_list_stream::read_readable_parts(dumper, stack_range.clone())?;
+                let part = parts
+                    .iter()
+                    .position(|(start, bytes)| stack_ptr < start + bytes.len())
+                    .or(parts.len().checked_sub(1));
+                let (start, bytes) = match part {
+                    Some(part) => parts.into_iter().nth(part).unwrap(),
+                    None => (valid_stack_ptr, Vec::new()),
+                };
+                (start, dumper.scratch.adopt(bytes))
+            }
+        };
+        memory_list_stream::note_trimmed(config, stack_range, stack_bytes.len());
+        if stack_bytes.is_empty() {
+            return Ok(());
+        }
+        let stack_pointer_offset = stack_ptr
+            .saturating_sub(valid_stack_ptr)
+            .min(stack_bytes.len());
         if config.skip_stacks_if_mapping_unreferenced {
             if let Some(principal_mapping) = &config.principal_mapping {
                 let low_addr = principal_mapping.system_mapping_info.start_address;
@@ -218,15 +609,186 @@ fn fill_thread_stack(
         if config.sanitize_stack {
             dumper.sanitize_stack_copy(&mut stack_bytes, stack_ptr, stack_pointer_offset)?;
         }
+        config.redact_memory(valid_stack_ptr, &mut stack_bytes);
 
-        let stack_location = MDLocationDescriptor {
-            data_size: stack_bytes.len() as u32,
-            rva: buffer.position() as u32,
-        };
-        buffer.write_all(&stack_bytes);
+        let stack_location = MemoryArrayWriter::write_bytes(buffer, &stack_bytes)?.location();
         thread.stack.start_of_memory_range = valid_stack_ptr as u64;
         thread.stack.memory = stack_location;
         config.memory_blocks.push(thread.stack);
     }
     Ok(())
 }
+
+/// The stack pointer doesn't point into a stack, as happens with stack
+/// overflows or corrupted registers. Rather than leaving the stack empty, pick
+/// the readable memory closest to the stack pointer, it may hold the frames
+/// preceding the crash. The hole the stack pointer points into, if any, is
+/// recorded so it shows up in the memory info list.
+fn unmapped_stack_pointer_fallback(
+    config: &mut MinidumpWriter,
+    dumper: &PtraceDumper,
+    stack_ptr: usize,
+    max_stack_len: MaxStackLen,
+) -> Option<(usize, usize)> {
+    if let Some(region) = dumper.find_unmapped_region(stack_ptr) {
+        if !config.unmapped_stack_regions.contains(&region) {
+            config.unmapped_stack_regions.push(region);
+        }
+    }
+
+    let max_len = match max_stack_len {
+        MaxStackLen::Len(max_stack_len) => min(max_stack_len, UNMAPPED_STACK_POINTER_FALLBACK_LEN),
+        MaxStackLen::None => UNMAPPED_STACK_POINTER_FALLBACK_LEN,
+    };
+    let nearest = dumper.find_nearest_readable(stack_ptr, max_len);
+    if let Some((start, len)) = nearest {
+        log::warn!(
+            "stack pointer {stack_ptr:#x} points into unmapped memory, dumping {len:#x} bytes at {start:#x} instead"
+        );
+    }
+    nearest
+}
+
+/// Capture the Intel CET shadow stack of the thread, if it has one. It holds
+/// nothing but the return addresses of the active frames, so it's a reliable
+/// record of the call chain even if the regular stack was corrupted.
+///
+/// This is a best-effort extra: it's capped like the regular stacks, and left
+/// out if it can't be read rather than failing the dump.
+#[cfg(target_arch = "x86_64")]
+fn fill_shadow_stack(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+    thread: &MDRawThread,
+    ssp: Option<usize>,
+    max_stack_len: MaxStackLen,
+) -> Result<(), errors::SectionThreadListError> {
+    let tid: Pid = thread.thread_id.try_into()?;
+    let Some(ssp) = ssp else {
+        return Ok(());
+    };
+    let Some(mapping) = dumper.find_mapping(ssp) else {
+        log::warn!("shadow stack pointer {ssp:#x} of thread {tid} is not mapped");
+        return Ok(());
+    };
+
+    config.shadow_stacks.push(MDRawThreadStack {
+        thread_id: thread.thread_id,
+        stack_pointer: ssp as u64,
+        start_of_mapping: mapping.start_address as u64,
+        size_of_mapping: mapping.size as u64,
+        ..Default::default()
+    });
+
+    // Like regular stacks, shadow stacks grow down so only the part above the
+    // shadow stack pointer is in use, the innermost frames first
+    let mut len = mapping.end_address() - ssp;
+    if let MaxStackLen::Len(max_stack_len) = max_stack_len {
+        len = min(len, max_stack_len);
+    }
+    if let Some(max_bytes) = config.max_stack_bytes(tid) {
+        len = min(len, max_bytes);
+    }
+    let len = memory_list_stream::capped(config, ssp..ssp + len).len();
+    if !config.fits_in_max_dump_size(buffer, len) {
+        log::warn!("dump size limit reached, leaving out the shadow stack of thread {tid}");
+        return Ok(());
+    }
+
+    let mut bytes = match PtraceDumper::copy_from_process(tid, ssp, len) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::warn!("failed to copy the shadow stack of thread {tid}, leaving it out: {e}");
+            return Ok(());
+        }
+    };
+    config.redact_memory(ssp, &mut bytes);
+    let section = MemoryArrayWriter::alloc_from_array(buffer, &bytes)?;
+    config.memory_blocks.push(MDMemoryDescriptor {
+        start_of_memory_range: ssp as u64,
+        memory: section.location(),
+    });
+
+    Ok(())
+}
+
+/// The value of `_fpx_sw_bytes.magic1` when the legacy FXSAVE region is followed
+/// by the rest of an XSAVE area
+#[cfg(target_arch = "x86_64")]
+const FP_XSTATE_MAGIC1: u32 = 0x46505853;
+/// The offset of `_fpx_sw_bytes` in the legacy FXSAVE region
+#[cfg(target_arch = "x86_64")]
+const FPX_SW_BYTES_OFFSET: usize = 464;
+
+/// Read the XSAVE area that the kernel saved in the signal frame when it
+/// delivered the crash signal.
+#[cfg(target_arch = "x86_64")]
+fn crash_context_xstate(
+    crash_context: &crate::crash_context::CrashContext,
+    tid: Pid,
+) -> Option<Vec<u8>> {
+    let fpregs = crash_context.inner.context.uc_mcontext.fpregs as usize;
+    if fpregs == 0 {
+        return None;
+    }
+
+    // The software reserved bytes of the legacy region tell whether the rest
+    // of the XSAVE area follows, and how large it is
+    let legacy = PtraceDumper::copy_from_process(tid, fpregs, 512).ok()?;
+    let sw_bytes = &legacy[FPX_SW_BYTES_OFFSET..];
+    let magic1 = u32::from_ne_bytes(sw_bytes[0..4].try_into().ok()?);
+    if magic1 != FP_XSTATE_MAGIC1 {
+        return None;
+    }
+    let xstate_size = u32::from_ne_bytes(sw_bytes[16..20].try_into().ok()?);
+    PtraceDumper::copy_from_process(tid, fpregs, xstate_size as usize).ok()
+}
+
+/// Record the XSAVE area of the thread, which holds its full register state
+/// including the AVX and AVX-512 registers.
+#[cfg(target_arch = "x86_64")]
+fn fill_xstate(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    thread: &MDRawThread,
+    xsave: Option<&[u8]>,
+) -> Result<(), errors::SectionThreadListError> {
+    let Some(xsave) = xsave else {
+        return Ok(());
+    };
+    // Both the kernel's ptrace and signal frame XSAVE areas report the
+    // enabled features in the software reserved bytes of the legacy region:
+    // signal frames in `_fpx_sw_bytes.xfeatures`, after its magic number,
+    // and ptrace as XCR0 in their first 8 bytes
+    let read_u64 = |offset: usize| {
+        xsave
+            .get(offset..offset + 8)
+            .map(|bytes| u64::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+    let enabled_features = match read_u64(FPX_SW_BYTES_OFFSET) {
+        Some(sw_bytes) if sw_bytes as u32 == FP_XSTATE_MAGIC1 => read_u64(FPX_SW_BYTES_OFFSET + 8),
+        sw_bytes => sw_bytes,
+    };
+    let Some(enabled_features) = enabled_features else {
+        return Ok(());
+    };
+
+    let header = config
+        .xstate_header
+        .get_or_insert_with(|| MDRawXStateHeader {
+            enabled_features,
+            xsave_size: 0,
+            thread_count: 0,
+            features: crate::thread_info::ThreadInfo::xstate_features(enabled_features),
+        });
+    header.xsave_size = header.xsave_size.max(xsave.len() as u32);
+    header.thread_count += 1;
+
+    let section = MemoryArrayWriter::alloc_from_array(buffer, xsave)?;
+    config.thread_xstates.push(MDRawThreadXState {
+        thread_id: thread.thread_id,
+        xsave: section.location(),
+    });
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use super::*;
+use std::ops::Range;
+
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+/// How much of the thread control block, glibc's `struct pthread`, and of the
+/// static TLS blocks is captured around the thread pointer
+const TCB_BYTES: usize = 2048;
+const STATIC_TLS_BYTES: usize = 4096;
+/// How much is captured at the start of each TLS block the DTV points to
+const DTV_BLOCK_BYTES: usize = 1024;
+/// The DTV has an entry per module with TLS, this bounds what a corrupted
+/// one can make us read
+const MAX_DTV_ENTRIES: usize = 64;
+
+/// Write the thread-local storage of every thread, see
+/// [`MinidumpWriter::capture_thread_local_storage`].
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &PtraceDumper,
+) -> Result<(), errors::SectionThreadLocalStorageError> {
+    if !config.capture_thread_local_storage {
+        return Ok(());
+    }
+
+    let thread_pointers: Vec<_> = (0..dumper.threads.len())
+        .filter_map(|idx| dumper.captured_thread_info(idx)?.get_thread_pointer())
+        .collect();
+    capture(config, buffer, dumper, &thread_pointers)
+}
+
+/// Capture the memory around each of `thread_pointers`, which holds the thread
+/// control block and the TLS blocks of the modules loaded at startup. With
+/// glibc the TLS blocks of the modules loaded later are found through the
+/// dynamic thread vector (DTV), whose start is captured as well.
+///
+/// The memory around the thread pointer may not be mapped in full, the pages
+/// which can't be read are left out.
+pub fn capture(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+    thread_pointers: &[usize],
+) -> Result<(), errors::SectionThreadLocalStorageError> {
+    let mut requested = Vec::new();
+    for &thread_pointer in thread_pointers.iter().filter(|&&tp| tp != 0) {
+        // x86 puts the static TLS blocks below the thread pointer (TLS variant
+        // II), the other architectures above it, after the TCB (variant I)
+        requested.push(if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            thread_pointer.saturating_sub(STATIC_TLS_BYTES)
+                ..thread_pointer.saturating_add(TCB_BYTES)
+        } else {
+            thread_pointer.saturating_sub(TCB_BYTES)
+                ..thread_pointer.saturating_add(STATIC_TLS_BYTES)
+        });
+        if cfg!(target_env = "gnu") {
+            requested.extend(dtv_ranges(dumper, thread_pointer));
+        }
+    }
+
+    let mut omitted = 0;
+    let mut over_limit = 0;
+    for requested in memory_list_stream::merge_ranges(requested) {
+        for range in memory_list_stream::uncaptured_ranges(&config.memory_blocks, requested) {
+            if !config.fits_in_max_dump_size(buffer, range.len()) {
+                omitted += 1;
+                continue;
+            }
+            if !config.fits_in_max_memory_regions() {
+                over_limit += 1;
+                continue;
+            }
+
+            config.check_cancelled()?;
+            for (start, mut data_copy) in memory_list_stream::read_readable_parts(dumper, range)? {
+                config.redact_memory(start, &mut data_copy);
+                let section = MemoryArrayWriter::write_bytes(buffer, &data_copy)?;
+                config.memory_blocks.push(MDMemoryDescriptor {
+                    start_of_memory_range: start as u64,
+                    memory: section.location(),
+                });
+            }
+        }
+    }
+
+    if omitted > 0 {
+        log::warn!("dump size limit reached, leaving out {omitted} thread-local storage regions");
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionsOmitted(omitted));
+    }
+    if over_limit > 0 {
+        log::warn!(
+            "memory region limit reached, leaving out {over_limit} thread-local storage regions"
+        );
+        config
+            .soft_errors
+            .push(errors::SoftError::MemoryRegionLimitReached(over_limit));
+    }
+    Ok(())
+}
+
+/// The DTV of the thread at `thread_pointer` and the start of the TLS blocks
+/// it points to, following glibc's layout: the TCB holds a pointer to the
+/// second entry of the DTV, the first one holding the number of entries. Each
+/// entry is a pointer to a TLS block followed by a flag, the second one
+/// being the generation of the DTV rather than a block.
+fn dtv_ranges(dumper: &impl LinuxDumper, thread_pointer: usize) -> Vec<Range<usize>> {
+    const ENTRY_SIZE: usize = 2 * POINTER_SIZE;
+    let read_pointer = |address: usize| {
+        let bytes = dumper.read_memory(address, POINTER_SIZE).ok()?;
+        Some(usize::from_ne_bytes(bytes.try_into().ok()?))
+    };
+
+    // The DTV pointer follows the TCB's pointer to itself in variant II
+    let dtv_slot = if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        thread_pointer + POINTER_SIZE
+    } else {
+        thread_pointer
+    };
+    let Some(dtv) = read_pointer(dtv_slot).filter(|&dtv| dtv >= ENTRY_SIZE) else {
+        return Vec::new();
+    };
+    let Some(len) = read_pointer(dtv - ENTRY_SIZE) else {
+        return Vec::new();
+    };
+    let len = len.min(MAX_DTV_ENTRIES);
+    let Ok(entries) = dumper.read_memory(dtv, (len + 1) * ENTRY_SIZE) else {
+        return Vec::new();
+    };
+
+    let blocks = entries
+        .chunks_exact(ENTRY_SIZE)
+        .skip(1)
+        .map(|entry| usize::from_ne_bytes(entry[..POINTER_SIZE].try_into().unwrap()))
+        // Blocks which haven't been allocated yet are marked with -1
+        .filter(|&block| block != 0 && block != usize::MAX)
+        .map(|block| block..block.saturating_add(DTV_BLOCK_BYTES));
+    std::iter::once(dtv - ENTRY_SIZE..dtv + entries.len())
+        .chain(blocks)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[cfg(all(target_arch = "x86_64", target_env = "gnu"))]
+    #[test]
+    fn test_thread_local_storage() {
+        let base = 0x10000;
+        let thread_pointer = 0x14800;
+        let dtv = 0x18010;
+        let mut memory = vec![0; 0x10000];
+        let mut write_word = |address: usize, value: usize| {
+            let offset = address - base;
+            memory[offset..offset + 8].copy_from_slice(&value.to_ne_bytes());
+        };
+        // The TCB points to the second entry of the DTV, after its length.
+        // The first module's block can't be read, the second one's isn't
+        // allocated.
+        write_word(thread_pointer, thread_pointer);
+        write_word(thread_pointer + 8, dtv);
+        write_word(dtv - 16, 2);
+        write_word(dtv + 16, 0x30000);
+        write_word(dtv + 32, usize::MAX);
+        let dumper = MockDumper {
+            memory: vec![(base, memory)],
+            ..Default::default()
+        };
+
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        let mut buffer = DumpBuf::with_capacity(0);
+        capture(&mut config, &mut buffer, &dumper, &[thread_pointer, 0]).unwrap();
+        let regions: Vec<_> = config
+            .memory_blocks
+            .iter()
+            .map(|block| (block.start_of_memory_range, block.memory.data_size))
+            .collect();
+        // The static TLS below the thread pointer and the TCB above it, then
+        // the DTV
+        assert_eq!(regions, [(0x13800, 0x1800), (0x18000, 0x40)]);
+    }
+}
@@ -2,10 +2,11 @@ use super::*;
 
 pub fn write(
     buffer: &mut DumpBuf,
-    dumper: &PtraceDumper,
+    dumper: &impl LinuxDumper,
 ) -> Result<MDRawDirectory, errors::SectionThreadNamesError> {
+    let threads = dumper.threads()?;
     // Only count threads that have a name
-    let num_threads = dumper.threads.iter().filter(|t| t.name.is_some()).count();
+    let num_threads = threads.iter().filter(|t| t.name.is_some()).count();
     // Memory looks like this:
     // <num_threads><thread_1><thread_2>...
 
@@ -19,15 +20,65 @@ pub fn write(
     let mut thread_list = MemoryArrayWriter::<MDRawThreadName>::alloc_array(buffer, num_threads)?;
     dirent.location.data_size += thread_list.location().data_size;
 
-    for (idx, item) in dumper.threads.iter().enumerate() {
-        if let Some(name) = &item.name {
-            let pos = write_string_to_location(buffer, name)?;
-            let thread = MDRawThreadName {
-                thread_id: item.tid.try_into()?,
-                thread_name_rva: pos.rva.into(),
-            };
-            thread_list.set_value_at(buffer, thread, idx)?;
-        }
+    let named_threads = threads
+        .iter()
+        .filter_map(|t| Some((t.tid, t.name.as_ref()?)));
+    for (idx, (tid, name)) in named_threads.enumerate() {
+        let pos = write_string_to_location(buffer, name)?;
+        let thread = MDRawThreadName {
+            thread_id: tid.try_into()?,
+            thread_name_rva: pos.rva.into(),
+        };
+        thread_list.set_value_at(buffer, thread, idx)?;
     }
     Ok(dirent)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::ptrace_dumper::Thread;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_thread_names_stream() {
+        let dumper = MockDumper {
+            threads: vec![
+                Thread {
+                    tid: MOCK_PID,
+                    name: Some("main".into()),
+                },
+                Thread {
+                    tid: MOCK_PID + 1,
+                    name: None,
+                },
+                Thread {
+                    tid: MOCK_PID + 2,
+                    name: Some("worker".into()),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut buffer, &dumper).unwrap();
+
+        assert_eq!(dirent.stream_type, MDStreamType::ThreadNamesStream as u32);
+        let rva = dirent.location.rva as usize;
+        assert_eq!(read_u32(&buffer, rva), 2);
+        // Each entry is a u32 thread id followed by a u64 RVA of the name
+        let names: Vec<_> = (0..2)
+            .map(|i| {
+                let entry = rva + 4 + i * 12;
+                let name_rva = read_u32(&buffer, entry + 4) as usize;
+                (read_u32(&buffer, entry), read_string(&buffer, name_rva))
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (MOCK_PID as u32, "main".to_owned()),
+                (MOCK_PID as u32 + 2, "worker".to_owned())
+            ]
+        );
+    }
+}
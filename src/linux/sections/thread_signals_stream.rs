@@ -0,0 +1,113 @@
+use super::*;
+use procfs_core::{process::Status, FromRead};
+
+/// Write the signals pending for and blocked by each thread, as read from
+/// `/proc/<pid>/task/<tid>/status`. A signal which is pending but blocked is
+/// often what's behind a process which doesn't respond to being terminated.
+///
+/// Threads which exited in the meantime are left out.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    dumper: &impl LinuxDumper,
+) -> Result<MDRawDirectory, errors::SectionThreadSignalsError> {
+    let mut signals = Vec::new();
+    for thread in dumper.threads()?.iter() {
+        let status_path = format!("/proc/{}/task/{}/status", config.process_id, thread.tid);
+        let Ok(Ok(status)) = std::fs::File::open(status_path).map(Status::from_read) else {
+            continue;
+        };
+        signals.push(MDRawThreadSignals {
+            thread_id: thread.tid.try_into()?,
+            __padding: 0,
+            pending: status.sigpnd,
+            shared_pending: status.shdpnd,
+            blocked: status.sigblk,
+            ignored: status.sigign,
+            caught: status.sigcgt,
+        });
+    }
+
+    // Memory looks like this:
+    // <num_threads><signals_1><signals_2>...
+    let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, signals.len() as u32)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadSignals as u32,
+        location: list_header.location(),
+    };
+
+    let signal_list = MemoryArrayWriter::alloc_from_array(buffer, &signals)?;
+    dirent.location.data_size += signal_list.location().data_size;
+
+    Ok(dirent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::ptrace_dumper::Thread;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_thread_signals_stream() {
+        let pid = std::process::id() as Pid;
+        // SAFETY: syscall
+        let tid = unsafe { libc::gettid() };
+        let dumper = MockDumper {
+            threads: vec![
+                Thread { tid, name: None },
+                // A thread which exited
+                Thread {
+                    tid: i32::MAX,
+                    name: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let config = MinidumpWriter::new(pid, tid);
+
+        // Leave SIGUSR2 pending but blocked in this thread
+        let sigusr2 = nix::sys::signal::Signal::SIGUSR2;
+        let mut blocked = nix::sys::signal::SigSet::empty();
+        blocked.add(sigusr2);
+        let mut previous = nix::sys::signal::SigSet::empty();
+        nix::sys::signal::pthread_sigmask(
+            nix::sys::signal::SigmaskHow::SIG_BLOCK,
+            Some(&blocked),
+            Some(&mut previous),
+        )
+        .unwrap();
+        // Sent to the calling thread
+        nix::sys::signal::raise(sigusr2).unwrap();
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&config, &mut buffer, &dumper).unwrap();
+
+        // Take the signal before unblocking it
+        assert_eq!(blocked.wait().unwrap(), sigusr2);
+        nix::sys::signal::pthread_sigmask(
+            nix::sys::signal::SigmaskHow::SIG_SETMASK,
+            Some(&previous),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::LinuxThreadSignals as u32
+        );
+        let rva = dirent.location.rva as usize;
+        // The exited thread is left out
+        assert_eq!(read_u32(&buffer, rva), 1);
+        assert_eq!(dirent.location.data_size, 4 + 48);
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+
+        let entry = rva + 4;
+        let sigusr2_bit = 1 << (libc::SIGUSR2 - 1);
+        assert_eq!(read_u32(&buffer, entry), tid as u32);
+        assert_eq!(read_u64(entry + 8) & sigusr2_bit, sigusr2_bit);
+        assert_eq!(read_u64(entry + 24) & sigusr2_bit, sigusr2_bit);
+    }
+}
@@ -0,0 +1,124 @@
+use super::*;
+
+/// Write the stack mapping associated with each thread, as recorded while
+/// writing the thread list stream.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionThreadStacksError> {
+    // Memory looks like this:
+    // <num_stacks><stack_1><stack_2>...
+    let list_header =
+        MemoryWriter::<u32>::alloc_with_val(buffer, config.thread_stacks.len() as u32)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadStacks as u32,
+        location: list_header.location(),
+    };
+
+    let stack_list = MemoryArrayWriter::alloc_from_array(buffer, &config.thread_stacks)?;
+    dirent.location.data_size += stack_list.location().data_size;
+
+    Ok(dirent)
+}
+
+/// Write the CET shadow stack associated with each thread, as recorded while
+/// writing the thread list stream. Nothing is written if no thread uses one.
+pub fn write_shadow_stacks(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionThreadStacksError> {
+    if config.shadow_stacks.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let list_header =
+        MemoryWriter::<u32>::alloc_with_val(buffer, config.shadow_stacks.len() as u32)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxShadowStacks as u32,
+        location: list_header.location(),
+    };
+
+    let stack_list = MemoryArrayWriter::alloc_from_array(buffer, &config.shadow_stacks)?;
+    dirent.location.data_size += stack_list.location().data_size;
+
+    Ok(dirent)
+}
+
+/// Label the stack mappings of the threads in `maps`, the contents of
+/// `/proc/<pid>/maps`, with the `[stack:<tid>]` pseudo-path kernels older than
+/// 4.5 gave them, using the associations recorded while writing the thread
+/// list stream. Only anonymous mappings are labeled, and the main thread's,
+/// `pid`, is already labeled `[stack]` by the kernel.
+pub fn label_maps(maps: &[u8], thread_stacks: &[MDRawThreadStack], pid: Pid) -> Vec<u8> {
+    let mut labeled = Vec::with_capacity(maps.len());
+    for line in maps.split_inclusive(|&c| c == b'\n') {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let tid = std::str::from_utf8(content).ok().and_then(|content| {
+            // address perms offset dev inode [pathname]
+            if content.split_ascii_whitespace().count() != 5 {
+                return None;
+            }
+            let (start, _) = content.split_once('-')?;
+            let start = u64::from_str_radix(start, 16).ok()?;
+            thread_stacks
+                .iter()
+                .find(|stack| stack.start_of_mapping == start && stack.thread_id != pid as u32)
+                .map(|stack| stack.thread_id)
+        });
+
+        match tid {
+            Some(tid) => {
+                // The kernel pads the pathnames to the same column
+                let content = std::str::from_utf8(content).unwrap().trim_end();
+                labeled.extend_from_slice(format!("{content:<72} [stack:{tid}]").as_bytes());
+                if line.ends_with(b"\n") {
+                    labeled.push(b'\n');
+                }
+            }
+            None => labeled.extend_from_slice(line),
+        }
+    }
+    labeled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_label_maps() {
+        let maps = b"\
+55d3c0a00000-55d3c0a21000 rw-p 00000000 00:00 0                          [heap]
+7f1c00000000-7f1c00800000 rw-p 00000000 00:00 0
+7f1c00800000-7f1c01000000 rw-p 00000000 00:00 0
+7ffc12300000-7ffc12321000 rw-p 00000000 00:00 0                          [stack]
+";
+        let stack = |thread_id: u32, start: u64| MDRawThreadStack {
+            thread_id,
+            stack_pointer: start + 0x1000,
+            start_of_mapping: start,
+            size_of_mapping: 0x800000,
+            ..Default::default()
+        };
+        let thread_stacks = [
+            stack(MOCK_PID as u32, 0x7ffc12300000),
+            stack(MOCK_PID as u32 + 1, 0x7f1c00800000),
+        ];
+
+        let labeled = label_maps(maps, &thread_stacks, MOCK_PID);
+        let labeled = String::from_utf8(labeled).unwrap();
+        let expected = format!(
+            "\
+55d3c0a00000-55d3c0a21000 rw-p 00000000 00:00 0                          [heap]
+7f1c00000000-7f1c00800000 rw-p 00000000 00:00 0
+7f1c00800000-7f1c01000000 rw-p 00000000 00:00 0                          [stack:{}]
+7ffc12300000-7ffc12321000 rw-p 00000000 00:00 0                          [stack]
+",
+            MOCK_PID + 1
+        );
+        assert_eq!(labeled, expected);
+    }
+}
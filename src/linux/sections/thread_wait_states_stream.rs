@@ -0,0 +1,25 @@
+use super::*;
+use std::fmt::Write as _;
+
+/// Write a text stream listing the kernel wait state of each thread, one per
+/// line, see [`crate::hang_dump`]. Nothing is written outside of hang dumps.
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionThreadWaitStatesError> {
+    if config.thread_wait_states.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let mut contents = String::new();
+    for state in &config.thread_wait_states {
+        // Writing to a String is infallible
+        let _ = writeln!(contents, "{state}");
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxThreadWaitStates as u32,
+        location: section.location(),
+    })
+}
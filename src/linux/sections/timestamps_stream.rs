@@ -0,0 +1,104 @@
+use super::*;
+use nix::time::{clock_gettime, ClockId};
+
+/// Write the time of the dump according to several clocks, so that it can be
+/// correlated with other logs even if the wall clock was adjusted.
+///
+/// The clocks are read in our own time namespace, the offsets of the time
+/// namespace of the process are recorded separately as containers may shift
+/// its monotonic and boot clocks.
+///
+/// Deterministic dumps only record the time given by their clock, see
+/// [`MinidumpWriter::deterministic`].
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionTimestampsError> {
+    let ours = TimeNamespaceOffsets::read("self");
+    let theirs = TimeNamespaceOffsets::read(&config.process_id.to_string());
+
+    let (realtime_ns, monotonic_ns, boottime_ns) = if config.deterministic_clock.is_some() {
+        let realtime = config
+            .now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        (realtime.as_nanos() as u64, 0, 0)
+    } else {
+        (
+            clock_ns(ClockId::CLOCK_REALTIME),
+            clock_ns(ClockId::CLOCK_MONOTONIC),
+            clock_ns(ClockId::CLOCK_BOOTTIME),
+        )
+    };
+
+    let timestamps = MDRawDumpTimestamps {
+        realtime_ns,
+        monotonic_ns,
+        boottime_ns,
+        boot_time: boot_time().unwrap_or_default(),
+        process_monotonic_offset_ns: theirs.monotonic_ns - ours.monotonic_ns,
+        process_boottime_offset_ns: theirs.boottime_ns - ours.boottime_ns,
+    };
+
+    let section = MemoryWriter::<MDRawDumpTimestamps>::alloc_with_val(buffer, timestamps)?;
+
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxDumpTimestamps as u32,
+        location: section.location(),
+    })
+}
+
+/// The current value of `clock` in nanoseconds, or 0 if it's not available
+fn clock_ns(clock: ClockId) -> u64 {
+    clock_gettime(clock)
+        .map(|ts| ts.tv_sec() as u64 * 1_000_000_000 + ts.tv_nsec() as u64)
+        .unwrap_or_default()
+}
+
+/// The offsets of the clocks of a time namespace relative to the initial one
+#[derive(Debug, Default)]
+struct TimeNamespaceOffsets {
+    monotonic_ns: i64,
+    boottime_ns: i64,
+}
+
+impl TimeNamespaceOffsets {
+    /// Read the offsets of the time namespace of `/proc/<pid>`, they are all 0
+    /// if the kernel doesn't support time namespaces.
+    fn read(pid: &str) -> Self {
+        let mut offsets = Self::default();
+        let Ok(contents) = std::fs::read_to_string(format!("/proc/{pid}/timens_offsets")) else {
+            return offsets;
+        };
+
+        // Each line looks like `monotonic <seconds> <nanoseconds>`
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(clock), Some(Ok(secs)), Some(Ok(nsecs))) = (
+                fields.next(),
+                fields.next().map(str::parse::<i64>),
+                fields.next().map(str::parse::<i64>),
+            ) else {
+                continue;
+            };
+            let offset = secs * 1_000_000_000 + nsecs;
+            match clock {
+                "monotonic" => offsets.monotonic_ns = offset,
+                "boottime" => offsets.boottime_ns = offset,
+                _ => {}
+            }
+        }
+        offsets
+    }
+}
+
+/// The time the system booted at, in seconds since the Unix epoch
+pub(crate) fn boot_time() -> Option<u64> {
+    std::fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()
+}
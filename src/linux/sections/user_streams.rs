@@ -0,0 +1,55 @@
+use super::*;
+use crate::linux::minidump_writer::UserStreamData;
+
+/// Write the user stream at `idx` in [`MinidumpWriter::user_streams`],
+/// producing its contents first if they are deferred.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+    idx: usize,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    let process_id = config.process_id;
+    let stream = &mut config.user_streams[idx];
+    let location = match &mut stream.data {
+        UserStreamData::Bytes(bytes) => MemoryArrayWriter::write_bytes(buffer, bytes)?.location(),
+        UserStreamData::Deferred(callback) => {
+            let bytes = callback(process_id);
+            MemoryArrayWriter::write_bytes(buffer, &bytes)?.location()
+        }
+    };
+
+    Ok(MDRawDirectory {
+        stream_type: stream.stream_type,
+        location,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::sections::mock::*;
+
+    #[test]
+    fn test_user_streams() {
+        let mut config = MinidumpWriter::new(MOCK_PID, MOCK_PID);
+        config.add_user_stream(0x1234_0000, b"state");
+        config.add_deferred_user_stream(0x1234_0001, |pid| pid.to_string().into_bytes());
+
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(&mut config, &mut buffer, 0).unwrap();
+        assert_eq!(dirent.stream_type, 0x1234_0000);
+        let rva = dirent.location.rva as usize;
+        assert_eq!(
+            &buffer[rva..rva + dirent.location.data_size as usize],
+            b"state"
+        );
+
+        let dirent = write(&mut config, &mut buffer, 1).unwrap();
+        assert_eq!(dirent.stream_type, 0x1234_0001);
+        let rva = dirent.location.rva as usize;
+        assert_eq!(
+            &buffer[rva..rva + dirent.location.data_size as usize],
+            MOCK_PID.to_string().as_bytes()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+use std::{fmt::Write as _, time::Duration};
+
+/// Write a text stream describing how the minidump was produced, one
+/// `name: value` line each: the version of the crate, how the process was
+/// stopped, the options of the writer as a [`DumpConfig`] and the time each
+/// phase took, the writing of the streams being timed up to this one, which
+/// took `streams_duration`.
+///
+/// Deterministic dumps leave out the timings, see
+/// [`MinidumpWriter::deterministic`].
+///
+/// [`DumpConfig`]: crate::dump_config::DumpConfig
+pub fn write(
+    config: &MinidumpWriter,
+    buffer: &mut DumpBuf,
+    streams_duration: Duration,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    let mut contents = String::new();
+    // Writing to a String is infallible
+    let _ = writeln!(contents, "version: {}", env!("CARGO_PKG_VERSION"));
+    let backend = if config.use_cgroup_freezer {
+        "ptrace (cgroup freezer)"
+    } else {
+        "ptrace"
+    };
+    let _ = writeln!(contents, "backend: {backend}");
+    let _ = writeln!(contents, "config: {:?}", config.dump_config());
+
+    if config.deterministic_clock.is_none() {
+        let phases = config
+            .phase_durations
+            .iter()
+            .copied()
+            .chain([("write_streams", streams_duration)]);
+        for (phase, duration) in phases {
+            let _ = writeln!(contents, "phase {phase}: {duration:?}");
+        }
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxWriterInfo as u32,
+        location: section.location(),
+    })
+}
@@ -0,0 +1,27 @@
+use super::*;
+
+/// Write the extended register state of the threads, as recorded while writing
+/// the thread list stream, along with the layout of the XSAVE areas holding
+/// it. Nothing is written if the state could not be retrieved for any thread.
+pub fn write(
+    config: &mut MinidumpWriter,
+    buffer: &mut DumpBuf,
+) -> Result<MDRawDirectory, errors::SectionXStateError> {
+    let Some(header) = config.xstate_header else {
+        return Ok(MDRawDirectory::default());
+    };
+
+    // Memory looks like this:
+    // <header><thread_1><thread_2>...
+    let header_section = MemoryWriter::alloc_with_val(buffer, header)?;
+
+    let mut dirent = MDRawDirectory {
+        stream_type: MDExtraStreamType::LinuxXState as u32,
+        location: header_section.location(),
+    };
+
+    let thread_list = MemoryArrayWriter::alloc_from_array(buffer, &config.thread_xstates)?;
+    dirent.location.data_size += thread_list.location().data_size;
+
+    Ok(dirent)
+}
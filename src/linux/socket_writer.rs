@@ -0,0 +1,216 @@
+//! A destination sending the minidump to a connected socket, e.g. of a crash
+//! collector uploading it, so that it's never written to the local disk.
+//!
+//! The stream directory at the start of a minidump is only complete once all
+//! the streams have been written, so the minidump is laid out in memory, as
+//! always, before it's sent. It's then handed to the socket in chunks of at
+//! most [`SocketWriter::set_chunk_size`] bytes, as fast as the peer accepts
+//! them: nothing else is buffered, and sending gives up as soon as the peer
+//! closes its end, or when it stops reading for longer than
+//! [`SocketWriter::set_timeout`].
+
+use crate::{
+    builder::DumpResult,
+    dir_section::FileWriterError,
+    linux::{errors::WriterError, minidump_writer::MinidumpWriter},
+};
+use nix::{
+    errno::Errno,
+    sys::socket::{send, MsgFlags},
+};
+use std::{
+    io::{Error, ErrorKind, Write},
+    os::fd::{AsRawFd, BorrowedFd},
+    time::{Duration, Instant},
+};
+
+/// The default size of the chunks handed to the socket
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// The events telling that the peer closed its end of the socket, or shut
+/// down writing to it, which is what closing a TCP socket looks like
+const CLOSED_EVENTS: libc::c_short = libc::POLLHUP | libc::POLLRDHUP | libc::POLLERR;
+
+/// A [`Write`] destination sending to a connected stream socket, TCP or UNIX,
+/// in bounded chunks. The socket can be blocking or not.
+pub struct SocketWriter<'a> {
+    socket: BorrowedFd<'a>,
+    chunk_size: usize,
+    timeout: Option<Duration>,
+}
+
+impl<'a> SocketWriter<'a> {
+    pub fn new(socket: BorrowedFd<'a>) -> Self {
+        Self {
+            socket,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            timeout: None,
+        }
+    }
+
+    /// Hand at most `chunk_size` bytes to the socket at once, see
+    /// [`DEFAULT_CHUNK_SIZE`].
+    pub fn set_chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Give up if the peer doesn't accept more data for `timeout`. By default
+    /// sending waits as long as the peer keeps its end open.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns an error if the peer closed its end of the socket, or if the
+    /// socket is in error, without waiting.
+    pub fn check_connected(&self) -> std::io::Result<()> {
+        if self.poll(0)? & CLOSED_EVENTS != 0 {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "the peer closed the socket",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wait until the socket can take more data, or until the peer closes
+    /// its end.
+    fn wait_writable(&self) -> std::io::Result<()> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    // Round up, not to spin for the last millisecond
+                    (remaining + Duration::from_nanos(999_999))
+                        .as_millis()
+                        .try_into()
+                        .unwrap_or(libc::c_int::MAX)
+                }
+                None => -1,
+            };
+            let revents = self.poll(timeout)?;
+            // Only a peer which is gone, it might still read what it's sent
+            // after shutting down writing
+            if revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                return Err(Error::new(
+                    ErrorKind::BrokenPipe,
+                    "the peer closed the socket",
+                ));
+            }
+            if revents & libc::POLLOUT != 0 {
+                return Ok(());
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "the peer stopped reading the minidump",
+                ));
+            }
+        }
+    }
+
+    /// Wait up to `timeout` milliseconds, -1 meaning forever, for the socket
+    /// to be writable or closed, returning the events which happened.
+    fn poll(&self, timeout: libc::c_int) -> std::io::Result<libc::c_short> {
+        // nix's poll can't be used, as it doesn't know about POLLRDHUP
+        let mut fd = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLOUT | libc::POLLRDHUP,
+            revents: 0,
+        };
+        // SAFETY: a single valid pollfd is passed
+        match Errno::result(unsafe { libc::poll(&mut fd, 1, timeout) }) {
+            Ok(_) => Ok(fd.revents),
+            Err(Errno::EINTR) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Write for SocketWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let chunk = &buf[..buf.len().min(self.chunk_size)];
+        loop {
+            self.wait_writable()?;
+            // Without MSG_NOSIGNAL, sending to a closed socket raises SIGPIPE,
+            // which kills the process by default
+            match send(
+                self.socket.as_raw_fd(),
+                chunk,
+                MsgFlags::MSG_NOSIGNAL | MsgFlags::MSG_DONTWAIT,
+            ) {
+                Ok(sent) => return Ok(sent),
+                Err(Errno::EAGAIN | Errno::EINTR) => continue,
+                Err(Errno::EPIPE | Errno::ECONNRESET) => {
+                    return Err(Error::new(
+                        ErrorKind::BrokenPipe,
+                        "the peer closed the socket",
+                    ))
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Everything written was handed to the socket, there's nothing to flush.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MinidumpWriter {
+    /// Generates a minidump and sends it through `writer`, see
+    /// [`crate::socket_writer`]. Nothing is dumped if the peer already closed
+    /// its end of the socket, and the process is resumed before the minidump
+    /// is sent, so a slow peer doesn't keep it stopped.
+    pub fn dump_to_socket(
+        &mut self,
+        writer: &mut SocketWriter<'_>,
+    ) -> Result<DumpResult, WriterError> {
+        // Don't stop the process for nothing
+        writer.check_connected().map_err(FileWriterError::from)?;
+        self.dump_to_writer(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Read,
+        os::{fd::AsFd, unix::net::UnixStream},
+    };
+
+    #[test]
+    fn test_socket_writer() {
+        let (socket, mut peer) = UnixStream::pair().unwrap();
+        let mut writer = SocketWriter::new(socket.as_fd());
+        writer.set_chunk_size(3);
+        writer.check_connected().unwrap();
+        assert_eq!(writer.write(b"minidump").unwrap(), 3);
+        writer.write_all(b"idump").unwrap();
+        let mut received = [0; 8];
+        peer.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"minidump");
+
+        // The peer doesn't read
+        let mut writer = SocketWriter::new(socket.as_fd());
+        writer.set_timeout(Duration::from_millis(10));
+        let error = writer.write_all(&vec![0; 16 * 1024 * 1024]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+
+        // The peer is gone
+        drop(peer);
+        assert_eq!(
+            writer.check_connected().unwrap_err().kind(),
+            ErrorKind::BrokenPipe
+        );
+        let error = writer.write_all(b"minidump").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,105 @@
+//! Which streams to write to minidumps, see
+//! [`MinidumpWriter::set_stream_selection`].
+//!
+//! Streams are selected by type, e.g. to leave out the environment of the
+//! process, which often holds secrets, or to only keep what a crash server
+//! processes:
+//!
+//! ```
+//! use minidump_writer::{minidump_format::MDStreamType, stream_selection::StreamSelection};
+//!
+//! let mut selection = StreamSelection::default();
+//! selection
+//!     .deny(MDStreamType::LinuxEnviron as u32)
+//!     .deny(MDStreamType::LinuxCmdLine as u32);
+//! ```
+//!
+//! The streams the minidump can't do without, listed in [`REQUIRED_STREAMS`],
+//! are always written. The streams which aren't selected aren't gathered at
+//! all, their directory entries are left empty.
+//!
+//! [`MinidumpWriter::set_stream_selection`]: crate::minidump_writer::MinidumpWriter::set_stream_selection
+
+use crate::minidump_format::MDStreamType;
+use std::collections::BTreeSet;
+
+/// The streams written regardless of the selection: the threads, the memory
+/// lists, the exception and the system information
+pub const REQUIRED_STREAMS: [u32; 5] = [
+    MDStreamType::ThreadListStream as u32,
+    MDStreamType::MemoryListStream as u32,
+    MDStreamType::Memory64ListStream as u32,
+    MDStreamType::ExceptionStream as u32,
+    MDStreamType::SystemInfoStream as u32,
+];
+
+/// The types of the streams to write, by default all of them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct StreamSelection {
+    /// Only write these streams, besides the [`REQUIRED_STREAMS`]. All of
+    /// them are written if this isn't set.
+    pub allow: Option<BTreeSet<u32>>,
+    /// Never write these streams, unless they are among the
+    /// [`REQUIRED_STREAMS`]
+    pub deny: BTreeSet<u32>,
+}
+
+impl StreamSelection {
+    /// Only write the stream of type `stream_type` and the others allowed so
+    /// far, besides the [`REQUIRED_STREAMS`]
+    pub fn allow(&mut self, stream_type: u32) -> &mut Self {
+        self.allow
+            .get_or_insert_with(BTreeSet::new)
+            .insert(stream_type);
+        self
+    }
+
+    /// Never write the stream of type `stream_type`
+    pub fn deny(&mut self, stream_type: u32) -> &mut Self {
+        self.deny.insert(stream_type);
+        self
+    }
+
+    /// Whether the stream of type `stream_type` is written
+    pub fn selects(&self, stream_type: u32) -> bool {
+        REQUIRED_STREAMS.contains(&stream_type)
+            || (!self.deny.contains(&stream_type)
+                && self
+                    .allow
+                    .as_ref()
+                    .is_none_or(|allow| allow.contains(&stream_type)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minidump_format::MDStreamType::*;
+
+    #[test]
+    fn test_selects() {
+        let mut selection = StreamSelection::default();
+        assert!(selection.selects(LinuxEnviron as u32));
+
+        selection
+            .deny(LinuxEnviron as u32)
+            .deny(ThreadListStream as u32);
+        assert!(!selection.selects(LinuxEnviron as u32));
+        assert!(selection.selects(LinuxCmdLine as u32));
+        assert!(selection.selects(ThreadListStream as u32));
+
+        selection
+            .allow(ModuleListStream as u32)
+            .allow(LinuxEnviron as u32);
+        assert!(selection.selects(ModuleListStream as u32));
+        assert!(!selection.selects(LinuxCmdLine as u32));
+        // Denying wins over allowing
+        assert!(!selection.selects(LinuxEnviron as u32));
+        assert!(selection.selects(ExceptionStream as u32));
+    }
+}
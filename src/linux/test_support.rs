@@ -0,0 +1,358 @@
+//! Processes to test crash reporters with, available with the `test-support`
+//! feature.
+//!
+//! A [`Fixture`] is a child process with a given number of threads and
+//! anonymous mappings, which waits until it's told to crash. When it crashes
+//! its signal handler sends the crash context back, as a real crash handler
+//! would, and the process is kept alive so that it can be dumped:
+//!
+//! ```no_run
+//! use minidump_writer::{minidump_writer::MinidumpWriter, test_support::Fixture};
+//! use nix::sys::{mman::ProtFlags, signal::Signal};
+//!
+//! let mut fixture = Fixture::builder()
+//!     .threads(4)
+//!     .mapping(4096, ProtFlags::PROT_READ)
+//!     .spawn()
+//!     .unwrap();
+//! let crash_context = fixture.crash(Signal::SIGSEGV).unwrap();
+//!
+//! let mut file = std::fs::File::create("fixture.dmp").unwrap();
+//! MinidumpWriter::builder()
+//!     .crash_context(crash_context)
+//!     .write(&mut file)
+//!     .unwrap();
+//! ```
+//!
+//! The fixture is forked from the calling process, without executing a new
+//! program, so it has the same modules mapped.
+
+use crate::linux::Pid;
+use nix::{
+    sys::{
+        mman::{mmap_anonymous, MapFlags, ProtFlags},
+        signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal},
+        wait::waitpid,
+    },
+    unistd::{fork, ForkResult},
+};
+use std::{
+    convert::Infallible,
+    io::{Error, ErrorKind, Read, Result, Write},
+    num::NonZeroUsize,
+    os::unix::{io::AsRawFd, net::UnixStream},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// The signals the fixture can be crashed with
+const CRASH_SIGNALS: &[Signal] = &[
+    Signal::SIGSEGV,
+    Signal::SIGBUS,
+    Signal::SIGILL,
+    Signal::SIGFPE,
+    Signal::SIGABRT,
+    Signal::SIGTRAP,
+];
+
+/// The socket the fixture's signal handler sends the crash context on
+static CRASH_SOCKET: AtomicI32 = AtomicI32::new(-1);
+
+/// The description of a [`Fixture`], see [`Fixture::builder`].
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    threads: usize,
+    mappings: Vec<(NonZeroUsize, ProtFlags)>,
+}
+
+impl FixtureBuilder {
+    /// The number of threads of the fixture, including the main one.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Add an anonymous mapping of `size` bytes with the given protection.
+    /// Readable mappings are filled with a repeating byte pattern.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is zero.
+    pub fn mapping(mut self, size: usize, protection: ProtFlags) -> Self {
+        let size = NonZeroUsize::new(size).expect("mappings can't be empty");
+        self.mappings.push((size, protection));
+        self
+    }
+
+    /// Start the fixture, returning once all its threads and mappings exist.
+    pub fn spawn(self) -> Result<Fixture> {
+        let (socket, child_socket) = UnixStream::pair()?;
+
+        // SAFETY: the child only sets up the fixture, then either waits on
+        // the socket or exits without returning
+        match unsafe { fork() }? {
+            ForkResult::Child => {
+                drop(socket);
+                let _ = self.run(child_socket);
+                // SAFETY: exiting without running the parent's destructors
+                unsafe { libc::_exit(1) }
+            }
+            ForkResult::Parent { child } => {
+                drop(child_socket);
+                let mut fixture = Fixture {
+                    pid: child.as_raw(),
+                    threads: Vec::new(),
+                    mappings: Vec::new(),
+                    socket,
+                };
+
+                let mut read_u64 = || -> Result<u64> {
+                    let mut value = [0u8; 8];
+                    fixture.socket.read_exact(&mut value)?;
+                    Ok(u64::from_ne_bytes(value))
+                };
+                let threads = (0..self.threads)
+                    .map(|_| Ok(read_u64()? as Pid))
+                    .collect::<Result<_>>()?;
+                let mappings = (0..self.mappings.len())
+                    .map(|_| Ok(read_u64()? as usize))
+                    .collect::<Result<_>>()?;
+                fixture.threads = threads;
+                fixture.mappings = mappings;
+                Ok(fixture)
+            }
+        }
+    }
+
+    /// The body of the fixture process.
+    fn run(self, mut socket: UnixStream) -> Result<Infallible> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&(nix::unistd::gettid().as_raw() as u64).to_ne_bytes());
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for id in 1..self.threads {
+            let sender = sender.clone();
+            std::thread::Builder::new()
+                .name(format!("fixture_{id}"))
+                .spawn(move || {
+                    let _ = sender.send(nix::unistd::gettid().as_raw());
+                    loop {
+                        std::thread::park();
+                    }
+                })?;
+        }
+        for _ in 1..self.threads {
+            let tid = receiver.recv().map_err(Error::other)?;
+            message.extend_from_slice(&(tid as u64).to_ne_bytes());
+        }
+
+        for (size, protection) in self.mappings {
+            // SAFETY: a new mapping can't alias anything
+            let mapping = unsafe {
+                mmap_anonymous(
+                    None,
+                    size,
+                    protection | ProtFlags::PROT_WRITE,
+                    MapFlags::MAP_PRIVATE,
+                )?
+            };
+            let address = mapping.as_ptr() as usize;
+            // SAFETY: the mapping is writable and `size` bytes long
+            unsafe {
+                let bytes =
+                    std::slice::from_raw_parts_mut(mapping.as_ptr().cast::<u8>(), size.get());
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = i as u8;
+                }
+                nix::sys::mman::mprotect(mapping, size.get(), protection)?;
+            }
+            message.extend_from_slice(&(address as u64).to_ne_bytes());
+        }
+
+        CRASH_SOCKET.store(socket.as_raw_fd(), Ordering::SeqCst);
+        let action = SigAction::new(
+            SigHandler::SigAction(handle_crash),
+            SaFlags::SA_SIGINFO,
+            SigSet::empty(),
+        );
+        for &crash_signal in CRASH_SIGNALS {
+            // SAFETY: the handler never returns
+            unsafe { signal::sigaction(crash_signal, &action) }?;
+        }
+
+        socket.write_all(&message)?;
+
+        // Wait for the parent to tell us which signal to crash with, this
+        // fails once the parent closes the socket
+        loop {
+            let mut signal = [0u8; 4];
+            socket.read_exact(&mut signal)?;
+            signal::raise(Signal::try_from(i32::from_ne_bytes(signal))?)?;
+        }
+    }
+}
+
+/// Send the crash context to the parent, then wait to be dumped.
+extern "C" fn handle_crash(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    // SAFETY: the kernel passes valid pointers to SA_SIGINFO handlers
+    let crash_context = unsafe {
+        let context = &*context.cast::<crash_context::ucontext_t>();
+        let mut siginfo: libc::signalfd_siginfo = std::mem::zeroed();
+        siginfo.ssi_signo = signal as u32;
+        siginfo.ssi_code = (*info).si_code;
+        siginfo.ssi_addr = (*info).si_addr() as u64;
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
+                let float_state = if context.uc_mcontext.fpregs.is_null() {
+                    std::mem::zeroed()
+                } else {
+                    (*context.uc_mcontext.fpregs).clone()
+                };
+            } else if #[cfg(not(target_arch = "arm"))] {
+                let float_state = std::mem::zeroed();
+            }
+        }
+
+        crash_context::CrashContext {
+            context: context.clone(),
+            #[cfg(not(target_arch = "arm"))]
+            float_state,
+            siginfo,
+            pid: libc::getpid(),
+            tid: libc::gettid(),
+        }
+    };
+
+    let bytes = crash_context.as_bytes();
+    let socket = CRASH_SOCKET.load(Ordering::SeqCst);
+    let mut written = 0;
+    while written < bytes.len() {
+        // SAFETY: writing the remainder of `bytes`
+        let ret = unsafe {
+            libc::write(
+                socket,
+                bytes[written..].as_ptr().cast(),
+                bytes.len() - written,
+            )
+        };
+        if ret <= 0 {
+            break;
+        }
+        written += ret as usize;
+    }
+
+    loop {
+        // SAFETY: no preconditions
+        unsafe { libc::pause() };
+    }
+}
+
+/// A child process to test dumping, see the [module documentation](self).
+/// It is killed when dropped.
+#[derive(Debug)]
+pub struct Fixture {
+    pid: Pid,
+    threads: Vec<Pid>,
+    mappings: Vec<usize>,
+    socket: UnixStream,
+}
+
+impl Fixture {
+    /// A fixture with a single thread and no additional mappings.
+    pub fn builder() -> FixtureBuilder {
+        FixtureBuilder {
+            threads: 1,
+            mappings: Vec::new(),
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// The ids of the threads of the fixture, starting with the main one.
+    pub fn threads(&self) -> &[Pid] {
+        &self.threads
+    }
+
+    /// The addresses of the mappings, in the order they were added to the
+    /// builder.
+    pub fn mappings(&self) -> &[usize] {
+        &self.mappings
+    }
+
+    /// Make the main thread of the fixture crash with `signal`, returning the
+    /// crash context its signal handler captured.
+    ///
+    /// # Errors
+    ///
+    /// If `signal` isn't a crash signal (`SIGSEGV`, `SIGBUS`, `SIGILL`,
+    /// `SIGFPE`, `SIGABRT` or `SIGTRAP`), or if the fixture died.
+    pub fn crash(&mut self, signal: Signal) -> Result<crash_context::CrashContext> {
+        if !CRASH_SIGNALS.contains(&signal) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{signal} is not a crash signal"),
+            ));
+        }
+
+        self.socket.write_all(&(signal as i32).to_ne_bytes())?;
+        let mut bytes = vec![0u8; std::mem::size_of::<crash_context::CrashContext>()];
+        self.socket.read_exact(&mut bytes)?;
+        crash_context::CrashContext::from_bytes(&bytes)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid crash context"))
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let pid = nix::unistd::Pid::from_raw(self.pid);
+        let _ = signal::kill(pid, Signal::SIGKILL);
+        let _ = waitpid(pid, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::{
+        crash_context::CrashContext, minidump_writer::MinidumpWriter, ptrace_dumper::PtraceDumper,
+    };
+
+    #[test]
+    fn test_fixture() {
+        let mut fixture = Fixture::builder()
+            .threads(3)
+            .mapping(4096, ProtFlags::PROT_READ)
+            .spawn()
+            .unwrap();
+        assert_eq!(fixture.threads().len(), 3);
+        assert_eq!(fixture.threads()[0], fixture.pid());
+
+        let tasks = std::fs::read_dir(format!("/proc/{}/task", fixture.pid()))
+            .unwrap()
+            .count();
+        assert_eq!(tasks, 3);
+
+        assert!(fixture.crash(Signal::SIGUSR1).is_err());
+        let crash_context = fixture.crash(Signal::SIGSEGV).unwrap();
+        assert_eq!(crash_context.pid, fixture.pid());
+        assert_eq!(crash_context.tid, fixture.pid());
+        assert_eq!(crash_context.siginfo.ssi_signo, libc::SIGSEGV as u32);
+
+        let memory = PtraceDumper::copy_from_process(fixture.pid(), fixture.mappings()[0], 4);
+        assert_eq!(memory.unwrap(), [0, 1, 2, 3]);
+
+        let dump = MinidumpWriter::new(fixture.pid(), fixture.pid())
+            .set_crash_context(CrashContext {
+                inner: crash_context,
+            })
+            .dump_to_vec()
+            .unwrap();
+        assert_eq!(&dump[..4], b"MDMP");
+    }
+}
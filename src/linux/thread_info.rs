@@ -17,6 +17,9 @@ cfg_if::cfg_if! {
     } else if #[cfg(target_arch = "aarch64")] {
         mod aarch64;
         pub type ThreadInfo = aarch64::ThreadInfoAarch64;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        mod loongarch64;
+        pub type ThreadInfo = loongarch64::ThreadInfoLoongArch64;
     } else if #[cfg(target_arch = "mips")] {
         mod mips;
         pub type ThreadInfo = mips::ThreadInfoMips;
@@ -32,7 +35,18 @@ enum NT_Elf {
     //NT_PRPSINFO = 3,
     //NT_TASKSTRUCT = 4,
     //NT_AUXV = 6,
-    NT_ARM_VFP = 0x400, // ARM VFP/NEON registers
+    NT_X86_XSTATE = 0x202, // x86 extended state using XSAVE
+    NT_X86_SHSTK = 0x204,  // x86 CET shadow stack pointer
+    NT_ARM_VFP = 0x400,    // ARM VFP/NEON registers
+    NT_ARM_TLS = 0x401,    // ARM TLS register
+}
+
+/// A register set of a thread, laid out as `PTRACE_GETREGSET` returns it for
+/// `note_type`, e.g. read from the notes of an ELF core dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegSet {
+    pub note_type: u32,
+    pub contents: Vec<u8>,
 }
 
 #[inline]
@@ -50,6 +64,21 @@ pub fn copy_u32_registers(dst: &mut [u128], src: &[u32]) {
 }
 
 trait CommonThreadInfo {
+    /// The register set of type `note_type` among `regsets`, if it's there
+    /// and large enough.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn find_regset<T>(regsets: &[RegSet], note_type: NT_Elf) -> Option<T> {
+        let note_type = note_type as u32;
+        let regset = regsets
+            .iter()
+            .find(|regset| regset.note_type == note_type)?;
+        (regset.contents.len() >= std::mem::size_of::<T>()).then(|| {
+            // SAFETY: the register sets are plain structures, valid whatever
+            // their contents
+            unsafe { std::ptr::read_unaligned(regset.contents.as_ptr().cast()) }
+        })
+    }
+
     fn get_ppid_and_tgid(tid: Pid) -> Result<(Pid, Pid)> {
         let mut ppid = -1;
         let mut tgid = -1;
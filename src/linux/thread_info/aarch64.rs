@@ -1,4 +1,4 @@
-use super::{CommonThreadInfo, NT_Elf};
+use super::{CommonThreadInfo, NT_Elf, RegSet};
 use crate::{
     errors::ThreadInfoError,
     minidump_cpu::{RawContextCPU, FP_REG_COUNT, GP_REG_COUNT},
@@ -25,6 +25,8 @@ pub struct ThreadInfoAarch64 {
     pub ppid: Pid, // parent process
     pub regs: libc::user_regs_struct,
     pub fpregs: user_fpsimd_struct,
+    /// The `TPIDR_EL0` register, see [`Self::get_thread_pointer`]
+    pub thread_pointer: Option<usize>,
 }
 
 impl CommonThreadInfo for ThreadInfoAarch64 {}
@@ -70,6 +72,31 @@ impl ThreadInfoAarch64 {
         )
     }
 
+    // nix currently doesn't support PTRACE_GETREGSET, so we have to do it ourselves
+    fn gettls(pid: Pid) -> Result<u64> {
+        Self::ptrace_get_data_via_io(
+            0x4204 as ptrace::RequestType, // PTRACE_GETREGSET
+            Some(NT_Elf::NT_ARM_TLS),
+            nix::unistd::Pid::from_raw(pid),
+        )
+    }
+
+    /// The thread pointer, which the C library points at the thread control
+    /// block, followed by the static TLS blocks.
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        self.thread_pointer
+    }
+
+    /// The registers the C library and the runtimes find their thread-local
+    /// data through. `TPIDRRO_EL0` is left out, Linux zeroes it for 64-bit
+    /// threads and ptrace doesn't expose it.
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        self.thread_pointer
+            .map(|tp| ("tpidr_el0", tp as u64))
+            .into_iter()
+            .collect()
+    }
+
     pub fn fill_cpu_context(&self, out: &mut RawContextCPU) {
         out.context_flags =
             minidump_common::format::ContextFlagsArm64Old::CONTEXT_ARM64_OLD_FULL.bits() as u64;
@@ -87,6 +114,29 @@ impl ThreadInfoAarch64 {
         out.float_regs[..FP_REG_COUNT].copy_from_slice(&self.fpregs.vregs[..FP_REG_COUNT]);
     }
 
+    /// Build the state of a thread of process `tgid` from its register sets,
+    /// e.g. those of an ELF core dump, rather than by reading them with
+    /// ptrace.
+    pub fn from_regsets(tgid: Pid, ppid: Pid, regsets: &[RegSet]) -> Option<Self> {
+        let regs: libc::user_regs_struct = Self::find_regset(regsets, NT_Elf::NT_PRSTATUS)?;
+        let fpregs =
+            Self::find_regset(regsets, NT_Elf::NT_PRFPREGSET).unwrap_or(user_fpsimd_struct {
+                vregs: [0; 32],
+                fpsr: 0,
+                fpcr: 0,
+            });
+
+        Some(Self {
+            stack_pointer: regs.sp as usize,
+            tgid,
+            ppid,
+            regs,
+            fpregs,
+            thread_pointer: Self::find_regset::<u64>(regsets, NT_Elf::NT_ARM_TLS)
+                .map(|tp| tp as usize),
+        })
+    }
+
     pub fn create_impl(_pid: Pid, tid: Pid) -> Result<Self> {
         let (ppid, tgid) = Self::get_ppid_and_tgid(tid)?;
         let regs = Self::getregset(tid).or_else(|_| Self::getregs(tid))?;
@@ -100,6 +150,7 @@ impl ThreadInfoAarch64 {
             ppid,
             regs,
             fpregs,
+            thread_pointer: Self::gettls(tid).ok().map(|tp| tp as usize),
         })
     }
 }
@@ -26,6 +26,8 @@ pub struct ThreadInfoArm {
     pub ppid: Pid, // parent process
     pub regs: user_regs_struct,
     pub fpregs: user_fpregs_struct,
+    /// The TLS register, see [`Self::get_thread_pointer`]
+    pub thread_pointer: Option<usize>,
 }
 
 impl CommonThreadInfo for ThreadInfoArm {}
@@ -63,6 +65,30 @@ impl ThreadInfoArm {
         out.float_save.regs = self.fpregs.fpregs;
     }
 
+    // nix currently doesn't support PTRACE_GET_THREAD_AREA, so we have to do it ourselves
+    fn gettls(pid: Pid) -> Result<libc::c_ulong> {
+        Self::ptrace_get_data(
+            22 as ptrace::RequestType, // PTRACE_GET_THREAD_AREA
+            None,
+            nix::unistd::Pid::from_raw(pid),
+        )
+    }
+
+    /// The thread pointer, which the C library points at the thread control
+    /// block, followed by the static TLS blocks.
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        self.thread_pointer
+    }
+
+    /// The registers the C library and the runtimes find their thread-local
+    /// data through.
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        self.thread_pointer
+            .map(|tp| ("tpidruro", tp as u64))
+            .into_iter()
+            .collect()
+    }
+
     pub fn create_impl(_pid: Pid, tid: Pid) -> Result<Self> {
         let (ppid, tgid) = Self::get_ppid_and_tgid(tid)?;
         let regs = Self::getregs(tid)?;
@@ -76,6 +102,7 @@ impl ThreadInfoArm {
             ppid,
             regs,
             fpregs,
+            thread_pointer: Self::gettls(tid).ok().map(|tp| tp as usize),
         })
     }
 }
@@ -0,0 +1,106 @@
+use super::{CommonThreadInfo, NT_Elf};
+use crate::{
+    errors::ThreadInfoError,
+    minidump_cpu::{RawContextCPU, FP_REG_COUNT, GP_REG_COUNT},
+    Pid,
+};
+use nix::sys::ptrace;
+
+/// `struct user_pt_regs` from the kernel's `arch/loongarch/include/uapi/asm/ptrace.h`
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub struct user_regs_struct {
+    pub regs: [u64; 32],
+    pub orig_a0: u64,
+    pub csr_era: u64,
+    pub csr_badv: u64,
+    pub reserved: [u64; 10],
+}
+
+/// `struct user_fp_state` from the kernel's `arch/loongarch/include/uapi/asm/ptrace.h`
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub struct user_fp_struct {
+    pub fpr: [u64; 32],
+    pub fcc: u64,
+    pub fcsr: u32,
+}
+
+/// The index of the stack pointer in the general purpose registers
+const REG_SP: usize = 3;
+
+type Result<T> = std::result::Result<T, ThreadInfoError>;
+
+#[derive(Debug)]
+pub struct ThreadInfoLoongArch64 {
+    pub stack_pointer: usize,
+    pub tgid: Pid, // thread group id
+    pub ppid: Pid, // parent process
+    pub regs: user_regs_struct,
+    pub fpregs: user_fp_struct,
+}
+
+impl CommonThreadInfo for ThreadInfoLoongArch64 {}
+
+impl ThreadInfoLoongArch64 {
+    pub fn get_instruction_pointer(&self) -> usize {
+        self.regs.csr_era as usize
+    }
+
+    /// The thread pointer, `$tp`, which the C library points at the thread
+    /// control block, followed by the static TLS blocks.
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        Some(self.regs.regs[2] as usize)
+    }
+
+    /// The registers the C library and the runtimes find their thread-local
+    /// data through.
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        vec![("tp", self.regs.regs[2])]
+    }
+
+    // nix currently doesn't support PTRACE_GETREGSET, so we have to do it ourselves
+    fn getregset(pid: Pid) -> Result<user_regs_struct> {
+        Self::ptrace_get_data_via_io(
+            0x4204 as ptrace::RequestType, // PTRACE_GETREGSET
+            Some(NT_Elf::NT_PRSTATUS),
+            nix::unistd::Pid::from_raw(pid),
+        )
+    }
+
+    // nix currently doesn't support PTRACE_GETREGSET, so we have to do it ourselves
+    fn getfpregset(pid: Pid) -> Result<user_fp_struct> {
+        Self::ptrace_get_data_via_io(
+            0x4204 as ptrace::RequestType, // PTRACE_GETREGSET
+            Some(NT_Elf::NT_PRFPREGSET),
+            nix::unistd::Pid::from_raw(pid),
+        )
+    }
+
+    pub fn fill_cpu_context(&self, out: &mut RawContextCPU) {
+        out.iregs[..GP_REG_COUNT].copy_from_slice(&self.regs.regs[..GP_REG_COUNT]);
+        out.pc = self.regs.csr_era;
+
+        out.float_regs[..FP_REG_COUNT].copy_from_slice(&self.fpregs.fpr[..FP_REG_COUNT]);
+        out.fcc = self.fpregs.fcc;
+        out.fcsr = self.fpregs.fcsr;
+    }
+
+    pub fn create_impl(_pid: Pid, tid: Pid) -> Result<Self> {
+        let (ppid, tgid) = Self::get_ppid_and_tgid(tid)?;
+        // Unlike older architectures, LoongArch only supports the regset
+        // requests, not PTRACE_GETREGS and PTRACE_GETFPREGS
+        let regs = Self::getregset(tid)?;
+        let fpregs = Self::getfpregset(tid)?;
+
+        let stack_pointer = regs.regs[REG_SP] as usize;
+
+        Ok(Self {
+            stack_pointer,
+            tgid,
+            ppid,
+            regs,
+            fpregs,
+        })
+    }
+}
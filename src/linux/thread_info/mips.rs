@@ -17,6 +17,17 @@ impl ThreadInfoMips {
         self.mcontext.pc
     }
 
+    /// The thread pointer isn't part of the registers on MIPS, it's only
+    /// available through `PTRACE_GET_THREAD_AREA`, which isn't read.
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        None
+    }
+
+    /// The thread pointer isn't read, see [`Self::get_thread_pointer`].
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+
     pub fn fill_cpu_context(&self, out: &mut RawContextCPU) {
         // #if _MIPS_SIM == _ABI64
         //   out->context_flags = MD_CONTEXT_MIPS64_FULL;
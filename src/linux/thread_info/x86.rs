@@ -1,4 +1,8 @@
+#[cfg(target_arch = "x86_64")]
+use super::RegSet;
 use super::{CommonThreadInfo, NT_Elf};
+#[cfg(target_arch = "x86_64")]
+use crate::minidump_format::MDRawXStateFeature;
 use crate::{errors::ThreadInfoError, minidump_cpu::RawContextCPU, minidump_format::format, Pid};
 use core::mem::size_of_val;
 #[cfg(all(not(target_os = "android"), target_arch = "x86"))]
@@ -104,6 +108,12 @@ pub struct ThreadInfoX86 {
     pub dregs: [libc::c_int; NUM_DEBUG_REGISTERS],
     #[cfg(target_arch = "x86")]
     pub fpxregs: user_fpxregs_struct,
+    /// The Intel CET shadow stack pointer, see [`Self::get_shadow_stack_pointer`]
+    #[cfg(target_arch = "x86_64")]
+    pub shadow_stack_pointer: Option<usize>,
+    /// The XSAVE area, see [`Self::get_xstate`]
+    #[cfg(target_arch = "x86_64")]
+    pub xstate: Option<Vec<u8>>,
 }
 
 impl CommonThreadInfo for ThreadInfoX86 {}
@@ -155,6 +165,118 @@ impl ThreadInfoX86 {
         )
     }
 
+    /// The thread pointer, which the C library points at the thread control
+    /// block and the static TLS blocks lie below.
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        Some(self.regs.fs_base as usize)
+    }
+
+    /// 32-bit threads find their thread control block through a segment
+    /// descriptor instead, which isn't read.
+    #[cfg(target_arch = "x86")]
+    pub fn get_thread_pointer(&self) -> Option<usize> {
+        None
+    }
+
+    /// The registers the C library and the runtimes find their thread-local
+    /// data through, see [`crate::minidump_format::MDExtraStreamType::LinuxThreadTlsRegisters`].
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("fs_base", self.regs.fs_base),
+            ("gs_base", self.regs.gs_base),
+        ]
+    }
+
+    /// The segment bases of 32-bit threads are held by descriptors, which
+    /// aren't read.
+    #[cfg(target_arch = "x86")]
+    pub fn get_tls_registers(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+
+    /// Read the shadow stack pointer of thread `tid`, if it has Intel CET
+    /// shadow stacks enabled.
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_shadow_stack_pointer(tid: Pid) -> Option<usize> {
+        // nix currently doesn't support PTRACE_GETREGSET, so we have to do it ourselves.
+        // This fails with ENODEV if shadow stacks aren't enabled for the thread.
+        let ssp: u64 = Self::ptrace_get_data_via_io(
+            0x4204 as ptrace::RequestType, // PTRACE_GETREGSET
+            Some(NT_Elf::NT_X86_SHSTK),
+            nix::unistd::Pid::from_raw(tid),
+        )
+        .ok()?;
+        (ssp != 0).then_some(ssp as usize)
+    }
+
+    /// Read the XSAVE area holding the full register state of thread `tid`,
+    /// including the AVX and AVX-512 registers.
+    #[cfg(target_arch = "x86_64")]
+    pub fn get_xstate(tid: Pid) -> Option<Vec<u8>> {
+        // The maximum size of the XSAVE area for the features this CPU supports.
+        // CPUID is slow under virtualization, so only query it once.
+        static MAX_SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+        let max_size = *MAX_SIZE.get_or_init(|| {
+            #[allow(unused_unsafe)]
+            // SAFETY: CPUID is always available on x86_64
+            let leaf = unsafe { std::arch::x86_64::__cpuid_count(0xd, 0) };
+            leaf.ecx as usize
+        });
+        if max_size == 0 {
+            return None;
+        }
+
+        let mut data = vec![0u8; max_size];
+        let mut io = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        // nix currently doesn't support PTRACE_GETREGSET, so we have to do it ourselves
+        // SAFETY: the kernel writes at most `iov_len` bytes into `data`
+        let res = unsafe {
+            libc::ptrace(
+                0x4204 as ptrace::RequestType, // PTRACE_GETREGSET
+                libc::pid_t::from(tid),
+                NT_Elf::NT_X86_XSTATE,
+                &mut io as *mut libc::iovec,
+            )
+        };
+        nix::errno::Errno::result(res).ok()?;
+        // The kernel updates the length with the amount of data it wrote
+        data.truncate(io.iov_len);
+        Some(data)
+    }
+
+    /// Return the location of every XSAVE state component enabled in
+    /// `enabled_features`, as reported by CPUID leaf 0xD.
+    #[cfg(target_arch = "x86_64")]
+    pub fn xstate_features(enabled_features: u64) -> [MDRawXStateFeature; 64] {
+        let mut features = [MDRawXStateFeature::default(); 64];
+        // The x87 and SSE state live in the legacy region of the XSAVE area
+        features[0] = MDRawXStateFeature {
+            offset: 0,
+            size: 160,
+        };
+        features[1] = MDRawXStateFeature {
+            offset: 160,
+            size: 256,
+        };
+        for (idx, feature) in features.iter_mut().enumerate().skip(2) {
+            if enabled_features & (1 << idx) != 0 {
+                #[allow(unused_unsafe)]
+                // SAFETY: CPUID is always available on x86_64
+                let leaf = unsafe { std::arch::x86_64::__cpuid_count(0xd, idx as u32) };
+                *feature = MDRawXStateFeature {
+                    offset: leaf.ebx,
+                    size: leaf.eax,
+                };
+            }
+        }
+        features
+    }
+
     fn peek_user(pid: Pid, addr: ptrace::AddressType) -> nix::Result<libc::c_long> {
         Self::ptrace_peek(
             ptrace::Request::PTRACE_PEEKUSER as ptrace::RequestType,
@@ -215,6 +337,37 @@ impl ThreadInfoX86 {
             dregs,
             #[cfg(target_arch = "x86")]
             fpxregs,
+            #[cfg(target_arch = "x86_64")]
+            shadow_stack_pointer: Self::get_shadow_stack_pointer(tid),
+            #[cfg(target_arch = "x86_64")]
+            xstate: Self::get_xstate(tid),
+        })
+    }
+
+    /// Build the state of a thread of process `tgid` from its register sets,
+    /// e.g. those of an ELF core dump, rather than by reading them with
+    /// ptrace. The debug registers aren't part of them and are left zeroed.
+    #[cfg(target_arch = "x86_64")]
+    pub fn from_regsets(tgid: Pid, ppid: Pid, regsets: &[RegSet]) -> Option<Self> {
+        let regs: user_regs_struct = Self::find_regset(regsets, NT_Elf::NT_PRSTATUS)?;
+        let fpregs = Self::find_regset(regsets, NT_Elf::NT_PRFPREGSET)
+            // SAFETY: all zeroes is a valid floating point state
+            .unwrap_or_else(|| unsafe { std::mem::zeroed() });
+
+        Some(Self {
+            stack_pointer: regs.rsp as usize,
+            tgid,
+            ppid,
+            regs,
+            fpregs,
+            dregs: [0; NUM_DEBUG_REGISTERS],
+            shadow_stack_pointer: Self::find_regset::<u64>(regsets, NT_Elf::NT_X86_SHSTK)
+                .filter(|&ssp| ssp != 0)
+                .map(|ssp| ssp as usize),
+            xstate: regsets
+                .iter()
+                .find(|regset| regset.note_type == NT_Elf::NT_X86_XSTATE as u32)
+                .map(|regset| regset.contents.clone()),
         })
     }
 
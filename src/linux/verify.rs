@@ -0,0 +1,160 @@
+//! Checks of written minidumps, available with the `verify` feature.
+//!
+//! [`verify_dump`] parses a minidump with the `minidump` crate, as a processor
+//! would, and reports the problems it finds. Comparing the report with the
+//! minidump returned by [`MinidumpWriter::dump`](crate::minidump_writer::MinidumpWriter::dump),
+//! see [`DumpReport::compare_with_written`], also catches minidumps which
+//! didn't reach the disk intact.
+
+use crate::{errors::VerifyError, minidump_format::MDStreamType};
+use minidump::{Minidump, MinidumpModuleList, MinidumpThreadList};
+use std::{ops::Deref, path::Path};
+
+/// The streams every minidump written by this crate contains
+const REQUIRED_STREAMS: &[MDStreamType] = &[
+    MDStreamType::ThreadListStream,
+    MDStreamType::ModuleListStream,
+    MDStreamType::MemoryListStream,
+    MDStreamType::SystemInfoStream,
+    MDStreamType::MiscInfoStream,
+];
+
+/// A problem found in a minidump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A stream which is always written is missing
+    MissingStream(u32),
+    /// A stream type appears more than once in the directory
+    DuplicateStream(u32),
+    /// A stream extends past the end of the file
+    StreamOutOfBounds(u32),
+    /// A stream could not be parsed
+    UnreadableStream(u32, String),
+    /// The minidump differs from the one which was written
+    Mismatch {
+        what: &'static str,
+        written: String,
+        found: String,
+    },
+}
+
+/// The result of [`verify_dump`].
+#[derive(Debug, Clone, Default)]
+pub struct DumpReport {
+    /// The types of the streams in the directory, in order
+    pub stream_types: Vec<u32>,
+    /// The number of threads in the thread list
+    pub threads: usize,
+    /// The number of modules in the module list
+    pub modules: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl DumpReport {
+    /// Check the minidump in `bytes`, e.g. as returned by
+    /// [`MinidumpWriter::dump`](crate::minidump_writer::MinidumpWriter::dump).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VerifyError> {
+        let dump = Minidump::read(bytes)?;
+        Ok(Self::new(&dump, bytes.len() as u64))
+    }
+
+    fn new<'a, T>(dump: &'a Minidump<'a, T>, len: u64) -> Self
+    where
+        T: Deref<Target = [u8]> + 'a,
+    {
+        let mut report = Self::default();
+
+        for entry in dump.all_streams() {
+            let stream_type = entry.stream_type;
+            if report.stream_types.contains(&stream_type) {
+                report.problems.push(Problem::DuplicateStream(stream_type));
+            }
+            report.stream_types.push(stream_type);
+
+            let end = u64::from(entry.location.rva) + u64::from(entry.location.data_size);
+            if end > len {
+                report
+                    .problems
+                    .push(Problem::StreamOutOfBounds(stream_type));
+            }
+        }
+
+        for &required in REQUIRED_STREAMS {
+            if !report.stream_types.contains(&(required as u32)) {
+                report
+                    .problems
+                    .push(Problem::MissingStream(required as u32));
+            }
+        }
+
+        match dump.get_stream::<MinidumpThreadList<'_>>() {
+            Ok(thread_list) => report.threads = thread_list.threads.len(),
+            Err(e) => report.problems.push(Problem::UnreadableStream(
+                MDStreamType::ThreadListStream as u32,
+                e.to_string(),
+            )),
+        }
+
+        match dump.get_stream::<MinidumpModuleList>() {
+            Ok(module_list) => report.modules = module_list.iter().count(),
+            Err(e) => report.problems.push(Problem::UnreadableStream(
+                MDStreamType::ModuleListStream as u32,
+                e.to_string(),
+            )),
+        }
+
+        report
+    }
+
+    /// Return whether no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Record a [`Problem::Mismatch`] for every difference with `written`,
+    /// the minidump the writer returned, which is what it intended to write.
+    pub fn compare_with_written(&mut self, written: &[u8]) -> Result<(), VerifyError> {
+        let written = Self::from_bytes(written)?;
+
+        let mut compare = |what, written: String, found: String| {
+            if written != found {
+                self.problems.push(Problem::Mismatch {
+                    what,
+                    written,
+                    found,
+                });
+            }
+        };
+        compare(
+            "streams",
+            format!("{:x?}", written.stream_types),
+            format!("{:x?}", self.stream_types),
+        );
+        compare(
+            "threads",
+            written.threads.to_string(),
+            self.threads.to_string(),
+        );
+        compare(
+            "modules",
+            written.modules.to_string(),
+            self.modules.to_string(),
+        );
+        Ok(())
+    }
+}
+
+/// Parse the minidump at `path` and check it for problems.
+///
+/// # Errors
+///
+/// If the file can't be read or isn't a minidump at all, other problems are
+/// listed in the report.
+pub fn verify_dump(path: impl AsRef<Path>) -> Result<DumpReport, VerifyError> {
+    let path = path.as_ref();
+    let len = std::fs::metadata(path)
+        .map_err(|e| VerifyError::IOError(path.to_owned(), e))?
+        .len();
+    let dump = Minidump::read_path(path)?;
+    Ok(DumpReport::new(&dump, len))
+}
@@ -0,0 +1,213 @@
+//! Capturing the last lines an application logged, from the circular buffers
+//! it logs to in memory, so that they ship with every dump, see
+//! `MinidumpWriter::register_log_ring_buffer`.
+//!
+//! The application describes where each buffer is and where its offsets are
+//! kept, and the writer reads them from the process when it dumps it, so
+//! nothing has to be done at crash time:
+//!
+//! ```
+//! use minidump_writer::log_ring_buffer::{LogRingBuffer, OffsetWidth};
+//!
+//! use std::sync::atomic::AtomicU64;
+//!
+//! let log = vec![0u8; 64 * 1024];
+//! let head = AtomicU64::new(0);
+//! let ring = LogRingBuffer::new(
+//!     "main",
+//!     log.as_ptr() as usize,
+//!     log.len(),
+//!     head.as_ptr() as usize,
+//!     OffsetWidth::U64,
+//! );
+//! ```
+//!
+//! The contents of the buffers are written as UTF-8 text to a `LogLines`
+//! stream, each preceded by a `# <name>` line.
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{MemoryArrayWriter, MemoryWriterError},
+    minidump_format::{MDExtraStreamType, MDRawDirectory},
+    process_dumper::ProcessDumper,
+};
+
+/// The width of the offsets of a [`LogRingBuffer`], which are read as native
+/// endian unsigned integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetWidth {
+    U32,
+    U64,
+}
+
+impl OffsetWidth {
+    fn size(self) -> usize {
+        match self {
+            Self::U32 => 4,
+            Self::U64 => 8,
+        }
+    }
+}
+
+/// A circular buffer in the memory of the process which lines of text are
+/// logged to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRingBuffer {
+    /// The name the lines are listed under in the stream
+    pub name: String,
+    /// The address of the buffer
+    pub address: usize,
+    /// The size of the buffer in bytes
+    pub capacity: usize,
+    /// The address of the offset the next byte will be written at. It may
+    /// count the bytes written since the start rather than wrap around, only
+    /// its remainder by the capacity is used.
+    pub head_address: usize,
+    /// The address of the offset of the oldest byte still in the buffer, if
+    /// the application keeps one. Without it everything before the head is
+    /// taken as logged, less the zeroes the buffer started with.
+    pub tail_address: Option<usize>,
+    pub offset_width: OffsetWidth,
+}
+
+impl LogRingBuffer {
+    pub fn new(
+        name: impl Into<String>,
+        address: usize,
+        capacity: usize,
+        head_address: usize,
+        offset_width: OffsetWidth,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            address,
+            capacity,
+            head_address,
+            tail_address: None,
+            offset_width,
+        }
+    }
+
+    /// Take the oldest byte in the buffer from the offset at `tail_address`
+    pub fn with_tail(mut self, tail_address: usize) -> Self {
+        self.tail_address = Some(tail_address);
+        self
+    }
+
+    /// Read the lines in the buffer from the process, oldest first, once
+    /// `redact` edited the buffer, given its address. A line partly
+    /// overwritten by newer ones is left out.
+    fn read<D: ProcessDumper>(
+        &self,
+        dumper: &D,
+        redact: &mut impl FnMut(usize, &mut [u8]),
+    ) -> Result<Vec<u8>, D::Error> {
+        if self.capacity == 0 {
+            return Ok(Vec::new());
+        }
+        let head = self.read_offset(dumper, self.head_address)? % self.capacity;
+        let tail = match self.tail_address {
+            Some(tail_address) => Some(self.read_offset(dumper, tail_address)? % self.capacity),
+            None => None,
+        };
+        let mut contents = dumper.read_memory(self.address, self.capacity)?;
+        redact(self.address, &mut contents);
+        Ok(unwrap(&contents, head, tail))
+    }
+
+    fn read_offset<D: ProcessDumper>(&self, dumper: &D, address: usize) -> Result<usize, D::Error> {
+        let size = self.offset_width.size();
+        let bytes = dumper.read_memory(address, size)?;
+        // A short read leaves the offset at zero
+        let mut raw = [0u8; 8];
+        if bytes.len() >= size {
+            raw[..size].copy_from_slice(&bytes[..size]);
+        }
+        let offset = match self.offset_width {
+            OffsetWidth::U32 => u32::from_ne_bytes(raw[..4].try_into().unwrap()).into(),
+            OffsetWidth::U64 => u64::from_ne_bytes(raw),
+        };
+        Ok(offset as usize)
+    }
+}
+
+/// The bytes of the circular buffer `contents` from `tail` up to `head`, or
+/// from `head` all the way around to it without a tail, less the zeroes the
+/// buffer started with, or the oldest line if it was partly overwritten.
+fn unwrap(contents: &[u8], head: usize, tail: Option<usize>) -> Vec<u8> {
+    let head = head.min(contents.len());
+    let tail = tail.map(|tail| tail.min(contents.len()));
+    let (newer, older) = contents.split_at(head);
+    match tail {
+        Some(tail) if tail <= head => contents[tail..head].to_vec(),
+        Some(tail) => [&contents[tail..], newer].concat(),
+        None => {
+            let older_len = older.iter().position(|&b| b != 0).unwrap_or(older.len());
+            let mut lines = [&older[older_len..], newer].concat();
+            // The buffer wrapped around, the oldest line was cut
+            if older_len == 0 && !older.is_empty() {
+                let cut = lines.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+                lines.drain(..cut);
+            }
+            lines
+        }
+    }
+}
+
+/// Write the `LogLines` stream, redacting the contents of each buffer with
+/// `redact`, which is given the address they were read from. Nothing is
+/// written if there are no buffers, and those which can't be read are left
+/// out.
+pub(crate) fn write<D: ProcessDumper>(
+    buffer: &mut DumpBuf,
+    dumper: &D,
+    rings: &[LogRingBuffer],
+    mut redact: impl FnMut(usize, &mut [u8]),
+) -> Result<MDRawDirectory, MemoryWriterError>
+where
+    D::Error: std::fmt::Display,
+{
+    if rings.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let mut contents = Vec::new();
+    for ring in rings {
+        let lines = match ring.read(dumper, &mut redact) {
+            Ok(lines) => lines,
+            Err(e) => {
+                log::warn!("failed to read the log ring buffer {}: {e}", ring.name);
+                continue;
+            }
+        };
+
+        contents.extend_from_slice(format!("# {}\n", ring.name).as_bytes());
+        contents.extend_from_slice(&lines);
+        if !lines.is_empty() && !lines.ends_with(b"\n") {
+            contents.push(b'\n');
+        }
+    }
+
+    let text = String::from_utf8_lossy(&contents);
+    let section = MemoryArrayWriter::write_bytes(buffer, text.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::LogLines as u32,
+        location: section.location(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap() {
+        // Not wrapped around yet
+        assert_eq!(unwrap(b"one\ntwo\n\0\0\0\0", 8, None), b"one\ntwo\n");
+        // "one\ntwo\nthree\n" wrapped around, overwriting the start of "one"
+        assert_eq!(unwrap(b"e\ne\ntwo\nthre", 2, None), b"two\nthree\n");
+        assert_eq!(unwrap(b"four\nne\ntwo\n", 5, Some(8)), b"two\nfour\n");
+        assert_eq!(unwrap(b"one\ntwo\n\0\0", 8, Some(4)), b"two\n");
+        assert_eq!(unwrap(b"one\n", 4, Some(4)), b"");
+    }
+}
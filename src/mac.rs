@@ -9,5 +9,6 @@ pub use mach2;
 pub mod errors;
 pub mod mach;
 pub mod minidump_writer;
+pub mod prerequisites;
 mod streams;
 pub mod task_dumper;
@@ -1,9 +1,10 @@
+use crate::{error_code::ErrorCode, mac::task_dumper::TaskDumpError};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum WriterError {
     #[error(transparent)]
-    TaskDumpError(#[from] crate::mac::task_dumper::TaskDumpError),
+    TaskDumpError(#[from] TaskDumpError),
     #[error("Failed to write to memory")]
     MemoryWriterError(#[from] crate::mem_writer::MemoryWriterError),
     #[error("Failed to write to file")]
@@ -11,3 +12,72 @@ pub enum WriterError {
     #[error("Attempted to write an exception stream with no crash context")]
     NoCrashContext,
 }
+
+impl WriterError {
+    /// The stable code of the error, see [`crate::error_code`].
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::TaskDumpError(e) => e.code(),
+            Self::MemoryWriterError(_) => ErrorCode::Format,
+            Self::FileWriterError(_) => ErrorCode::Output,
+            Self::NoCrashContext => ErrorCode::InvalidInput,
+        }
+    }
+
+    /// The OS error number which caused the error, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        match self {
+            Self::TaskDumpError(e) => e.os_error(),
+            Self::FileWriterError(crate::dir_section::FileWriterError::IOError(e)) => {
+                e.raw_os_error()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A problem which left the minidump incomplete without preventing it from
+/// being written. These are recorded in the
+/// [`MacSoftErrors`](crate::minidump_format::MDExtraStreamType::MacSoftErrors)
+/// stream.
+#[derive(Debug, Error)]
+pub enum SoftError {
+    #[error("failed to read the state of thread {tid}: {error}")]
+    ThreadState { tid: u32, error: TaskDumpError },
+    #[error("failed to read the stack of thread {tid}: {error}")]
+    ThreadStack { tid: u32, error: TaskDumpError },
+    #[error("failed to read the thread-local storage of thread {tid}: {error}")]
+    ThreadLocalStorage { tid: u32, error: TaskDumpError },
+    #[error("failed to read the name of thread {tid}: {error}")]
+    ThreadName { tid: u32, error: WriterError },
+    #[error("failed to read the load commands of the image at {address:#x}: {error}")]
+    ImageLoadCommands { address: u64, error: TaskDumpError },
+    #[error("failed to read the loaded images: {0}")]
+    Images(WriterError),
+    #[error("failed to read task info {flavor}: {error}")]
+    TaskInfo {
+        flavor: &'static str,
+        error: TaskDumpError,
+    },
+    #[error("{section} was left out because writing it failed: {error}")]
+    SectionOmitted {
+        section: &'static str,
+        error: WriterError,
+    },
+}
+
+impl SoftError {
+    /// The kernel error which caused the problem, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        match self {
+            Self::ThreadState { error, .. }
+            | Self::ThreadStack { error, .. }
+            | Self::ThreadLocalStorage { error, .. }
+            | Self::ImageLoadCommands { error, .. }
+            | Self::TaskInfo { error, .. } => error.os_error(),
+            Self::ThreadName { error, .. }
+            | Self::Images(error)
+            | Self::SectionOmitted { error, .. } => error.os_error(),
+        }
+    }
+}
@@ -16,7 +16,7 @@ pub use mach2::{
 /// A Mach kernel error.
 ///
 /// See <usr/include/mach/kern_return.h>.
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KernelError {
     #[error("specified address is not currently valid")]
     InvalidAddress = 1,
@@ -274,6 +274,24 @@ impl ThreadState {
         }
     }
 
+    /// Gets the general purpose registers, other than the program counter
+    /// and the stack pointer
+    pub fn general_registers(&self) -> Vec<u64> {
+        let ts = self.arch_state();
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                vec![
+                    ts.__rax, ts.__rbx, ts.__rcx, ts.__rdx, ts.__rdi, ts.__rsi, ts.__rbp, ts.__r8,
+                    ts.__r9, ts.__r10, ts.__r11, ts.__r12, ts.__r13, ts.__r14, ts.__r15,
+                ]
+            } else if #[cfg(target_arch = "aarch64")] {
+                let mut registers = ts.x.to_vec();
+                registers.extend([ts.fp, ts.lr]);
+                registers
+            }
+        }
+    }
+
     /// Converts the raw binary blob into the architecture specific state
     #[inline]
     pub fn arch_state(&self) -> &ArchThreadState {
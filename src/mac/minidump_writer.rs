@@ -1,8 +1,20 @@
 use crate::{
-    dir_section::{DirSection, DumpBuf},
-    mac::{errors::WriterError, task_dumper::TaskDumper},
+    builder::DumpResult,
+    capture_limits::CaptureLimits,
+    crash_reason,
+    dir_section::{DirSection, DiscardSink, DumpBuf},
+    gpu_info::{self, GpuInfo},
+    host_ids::{self, HostIds},
+    log_ring_buffer::{self, LogRingBuffer},
+    mac::{
+        errors::{SoftError, WriterError},
+        task_dumper::TaskDumper,
+    },
     mem_writer::*,
     minidump_format::{self, MDMemoryDescriptor, MDRawDirectory, MDRawHeader},
+    module_filter::{ModuleAction, ModuleFilter},
+    signal_dispositions::{self, SignalDisposition},
+    unloaded_modules::{self, UnloadedModuleList},
 };
 use std::io::{Seek, Write};
 
@@ -10,19 +22,69 @@ pub use mach2::mach_types::{task_t, thread_t};
 
 type Result<T> = std::result::Result<T, WriterError>;
 
+/// The bytes below the stack pointer of each thread captured by default, the
+/// size of the red zone of the x86-64 and arm64 ABIs, see
+/// [`MinidumpWriter::set_stack_red_zone`]
+pub const DEFAULT_STACK_RED_ZONE: usize = 128;
+
 pub struct MinidumpWriter {
     /// The crash context as captured by an exception handler
     pub(crate) crash_context: Option<crash_context::CrashContext>,
     /// List of raw blocks of memory we've written into the stream. These are
     /// referenced by other streams (eg thread list)
     pub(crate) memory_blocks: Vec<MDMemoryDescriptor>,
+    /// The problems which left the minidump incomplete, they are written in
+    /// the last stream
+    pub(crate) soft_errors: Vec<SoftError>,
+    /// The streams which were left out because writing them failed, see
+    /// [`SoftError::SectionOmitted`]
+    pub(crate) omitted_sections: Vec<&'static str>,
     /// The task being dumped
     pub(crate) task: task_t,
     /// The handler thread, so it can be ignored/deprioritized
     pub(crate) handler_thread: thread_t,
+    /// Leaves out or renames modules, see [`Self::set_module_filter`]
+    pub(crate) module_filter: Option<ModuleFilter>,
+    /// The modules reported as unloaded, see [`Self::set_unloaded_module_list`]
+    pub(crate) unloaded_modules: UnloadedModuleList,
+    /// The dispositions of the signals, see [`Self::set_signal_dispositions`]
+    pub(crate) signal_dispositions: Vec<SignalDisposition>,
+    /// The GPUs used by the task, see [`Self::set_gpu_info`]
+    pub(crate) gpu_info: Vec<GpuInfo>,
+    /// The circular buffers the task logs to, see
+    /// [`Self::register_log_ring_buffer`]
+    pub(crate) log_ring_buffers: Vec<LogRingBuffer>,
+    /// The reason of the crash, see [`Self::set_crash_reason`]
+    pub(crate) crash_reason: Option<String>,
+    /// How the identifiers of the host are recorded, see
+    /// [`Self::set_host_ids`]
+    pub(crate) host_ids: HostIds,
+    /// Whether to capture the thread-local storage of every thread, see
+    /// [`Self::capture_thread_local_storage`]
+    pub(crate) capture_thread_local_storage: bool,
+    /// How much memory to capture around the faulting data address, see
+    /// [`Self::capture_fault_address_memory`]
+    pub(crate) fault_address_memory_window: Option<usize>,
+    /// How much memory to capture around the addresses held by the registers
+    /// of the crashing thread, see [`Self::capture_register_memory`]
+    pub(crate) register_memory_budget: Option<usize>,
+    /// How much memory to capture below the stack pointer of each thread,
+    /// see [`Self::set_stack_red_zone`]
+    pub(crate) stack_red_zone: usize,
+    /// The limits on the counts and sizes read from the task, see
+    /// [`Self::set_capture_limits`]
+    pub(crate) capture_limits: CaptureLimits,
+    /// The environment variables written, see [`Self::capture_environment`]
+    pub(crate) environment_allowlist: Option<Vec<String>>,
 }
 
 impl MinidumpWriter {
+    /// Returns a builder offering the same API on every platform, see
+    /// [`crate::builder`].
+    pub fn builder() -> crate::builder::MinidumpWriterBuilder {
+        crate::builder::MinidumpWriterBuilder::new()
+    }
+
     /// Creates a minidump writer for the specified mach task (process) and
     /// handler thread. If not specified, defaults to the current task and thread.
     ///
@@ -44,6 +106,8 @@ impl MinidumpWriter {
         Self {
             crash_context: None,
             memory_blocks: Vec::new(),
+            soft_errors: Vec::new(),
+            omitted_sections: Vec::new(),
             task: task.unwrap_or_else(|| {
                 // SAFETY: syscall
                 unsafe { mach2::traps::mach_task_self() }
@@ -52,26 +116,172 @@ impl MinidumpWriter {
                 // SAFETY: syscall
                 unsafe { mach2::mach_init::mach_thread_self() }
             }),
+            module_filter: None,
+            unloaded_modules: UnloadedModuleList::new(),
+            signal_dispositions: Vec::new(),
+            gpu_info: Vec::new(),
+            log_ring_buffers: Vec::new(),
+            crash_reason: None,
+            host_ids: HostIds::default(),
+            capture_thread_local_storage: false,
+            fault_address_memory_window: None,
+            register_memory_budget: None,
+            stack_red_zone: DEFAULT_STACK_RED_ZONE,
+            capture_limits: CaptureLimits::default(),
+            environment_allowlist: None,
         }
     }
 
     /// Creates a minidump writer with the specified crash context, presumably
     /// for another task
     pub fn with_crash_context(crash_context: crash_context::CrashContext) -> Self {
-        let task = crash_context.task;
-        let handler_thread = crash_context.handler_thread;
+        let mut writer = Self::new(Some(crash_context.task), Some(crash_context.handler_thread));
+        writer.set_crash_context(crash_context);
+        writer
+    }
 
-        Self {
-            crash_context: Some(crash_context),
-            memory_blocks: Vec::new(),
-            task,
-            handler_thread,
-        }
+    /// Dump the task described by `crash_context`, as captured by the
+    /// exception handler, like [`Self::with_crash_context`]. Its task and
+    /// handler thread replace those the writer was created with. The thread
+    /// it holds is reported as the crashing one, and its exception, if any, is
+    /// written to the exception stream.
+    pub fn set_crash_context(&mut self, crash_context: crash_context::CrashContext) -> &mut Self {
+        self.task = crash_context.task;
+        self.handler_thread = crash_context.handler_thread;
+        self.crash_context = Some(crash_context);
+        self
+    }
+
+    /// Decide for each module, given its path, whether to list it, to list it
+    /// with another path, e.g. to strip the user's home directory, or to
+    /// leave it out, see [`crate::module_filter`].
+    pub fn set_module_filter(
+        &mut self,
+        filter: impl FnMut(&str) -> ModuleAction + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.module_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Write the modules reported to `unloaded_modules` as they were unloaded
+    /// to the UnloadedModuleListStream, see [`crate::unloaded_modules`]. The
+    /// list is shared, so the application can keep feeding it until the
+    /// task is dumped.
+    pub fn set_unloaded_module_list(&mut self, unloaded_modules: UnloadedModuleList) -> &mut Self {
+        self.unloaded_modules = unloaded_modules;
+        self
+    }
+
+    /// Write the dispositions of the signals, as collected by the application,
+    /// e.g. with `sigaction`, to the SignalDispositions stream, see
+    /// [`crate::signal_dispositions`]. Nothing is written otherwise, as the
+    /// handlers of another task can't be read.
+    pub fn set_signal_dispositions(&mut self, dispositions: Vec<SignalDisposition>) -> &mut Self {
+        self.signal_dispositions = dispositions;
+        self
+    }
+
+    /// Describe the GPUs used by the task in the GpuInfo stream, see
+    /// [`crate::gpu_info`]. The stream is left out if there are none.
+    pub fn set_gpu_info(&mut self, gpu_info: Vec<GpuInfo>) -> &mut Self {
+        self.gpu_info = gpu_info;
+        self
+    }
+
+    /// Capture the lines logged to the circular buffer `ring` in the LogLines
+    /// stream, reading it from the task when it's dumped, see
+    /// [`crate::log_ring_buffer`].
+    pub fn register_log_ring_buffer(&mut self, ring: LogRingBuffer) -> &mut Self {
+        self.log_ring_buffers.push(ring);
+        self
+    }
+
+    /// Give the reason of the crash, e.g. the message of an abort, written to
+    /// the CrashReason stream, see [`crate::crash_reason`]. It's truncated to
+    /// [`crate::crash_reason::MAX_CRASH_REASON_LEN`] bytes.
+    pub fn set_crash_reason(&mut self, reason: impl Into<String>) -> &mut Self {
+        self.crash_reason = Some(crash_reason::truncate(reason.into()));
+        self
+    }
+
+    /// Record the hardware UUID of the machine and its boot session UUID in
+    /// the HostIds stream as `host_ids` says, see [`crate::host_ids`]. They
+    /// are recorded as they are by default.
+    pub fn set_host_ids(&mut self, host_ids: HostIds) -> &mut Self {
+        self.host_ids = host_ids;
+        self
+    }
+
+    /// Capture the start of each thread's `pthread` structure, which holds
+    /// its thread-specific data slots, into the memory list, and record its
+    /// address in the thread's `teb` field.
+    pub fn capture_thread_local_storage(&mut self) -> &mut Self {
+        self.capture_thread_local_storage = true; // Off by default
+        self
     }
 
-    /// Writes a minidump to the specified destination, returning the raw minidump
-    /// contents upon success
-    pub fn dump(&mut self, destination: &mut (impl Write + Seek)) -> Result<Vec<u8>> {
+    /// Capture up to `window` bytes of memory around the address the crashing
+    /// thread faulted on, as reported by the subcode of `EXC_BAD_ACCESS`
+    /// exceptions, to see the contents of the object which was being
+    /// accessed. Only memory within the region holding the address is
+    /// captured.
+    pub fn capture_fault_address_memory(&mut self, window: usize) -> &mut Self {
+        self.fault_address_memory_window = Some(window);
+        self
+    }
+
+    /// Capture the memory around the addresses held by the general purpose
+    /// registers of the crashing thread which point to data, up to `budget`
+    /// bytes in total. After the stack, this is usually where the values the
+    /// crashing code was working on are.
+    pub fn capture_register_memory(&mut self, budget: usize) -> &mut Self {
+        self.register_memory_budget = Some(budget);
+        self
+    }
+
+    /// Capture `red_zone` bytes below the stack pointer of each thread along
+    /// with its stack, [`DEFAULT_STACK_RED_ZONE`] by default. They hold the
+    /// locals of leaf functions, which may not move the stack pointer, and
+    /// the frames which were just popped. The stacks are never captured past
+    /// the start of the region holding the stack pointer.
+    pub fn set_stack_red_zone(&mut self, red_zone: usize) -> &mut Self {
+        self.stack_red_zone = red_zone;
+        self
+    }
+
+    /// Replace the limits on the counts and sizes read from the task, see
+    /// [`crate::capture_limits`]. When the images or the load commands of an
+    /// image go over them, they are left out and recorded as soft errors.
+    pub fn set_capture_limits(&mut self, limits: CaptureLimits) -> &mut Self {
+        self.capture_limits = limits;
+        self
+    }
+
+    /// Write the environment variables of the task named in `allowlist` to
+    /// the LinuxEnviron stream, the one Linux dumps use. An entry ending with `*` allows
+    /// all the variables starting with what precedes it, e.g. `MOZ_*`. The
+    /// environment often holds secrets, so it's left out by default, while
+    /// the arguments of the task are always written to the LinuxCmdLine
+    /// stream.
+    pub fn capture_environment(&mut self, allowlist: Vec<String>) -> &mut Self {
+        self.environment_allowlist = Some(allowlist);
+        self
+    }
+
+    /// Writes a minidump to the specified destination, returning a
+    /// [`DumpResult`] describing what was written upon success
+    pub fn dump(&mut self, destination: &mut (impl Write + Seek)) -> Result<DumpResult> {
+        let (result, _) = self.write_dump(destination)?;
+        Ok(result)
+    }
+
+    /// Like [`Self::dump`], returning the in-memory version of the minidump
+    /// as well
+    fn write_dump(
+        &mut self,
+        destination: &mut (impl Write + Seek),
+    ) -> Result<(DumpResult, Vec<u8>)> {
+        let start = std::time::Instant::now();
         let writers = {
             #[allow(clippy::type_complexity)]
             let mut writers: Vec<
@@ -80,15 +290,58 @@ impl MinidumpWriter {
                 Box::new(|mw, buffer, dumper| mw.write_thread_list(buffer, dumper)),
                 Box::new(|mw, buffer, dumper| mw.write_memory_list(buffer, dumper)),
                 Box::new(|mw, buffer, dumper| mw.write_system_info(buffer, dumper)),
-                Box::new(|mw, buffer, dumper| mw.write_module_list(buffer, dumper)),
-                Box::new(|mw, buffer, dumper| mw.write_misc_info(buffer, dumper)),
-                Box::new(|mw, buffer, dumper| mw.write_breakpad_info(buffer, dumper)),
-                Box::new(|mw, buffer, dumper| mw.write_thread_names(buffer, dumper)),
+                Self::optional("ModuleList", |mw, buffer, dumper| {
+                    mw.write_module_list(buffer, dumper)
+                }),
+                Self::optional("SharedCache", |mw, buffer, dumper| {
+                    mw.write_shared_cache(buffer, dumper)
+                }),
+                Self::optional("UnloadedModuleList", |mw, buffer, _dumper| {
+                    Ok(unloaded_modules::write(buffer, &mw.unloaded_modules)?)
+                }),
+                Self::optional("MiscInfo", |mw, buffer, dumper| {
+                    mw.write_misc_info(buffer, dumper)
+                }),
+                Self::optional("BreakpadInfo", |mw, buffer, dumper| {
+                    mw.write_breakpad_info(buffer, dumper)
+                }),
+                Self::optional("ThreadNames", |mw, buffer, dumper| {
+                    mw.write_thread_names(buffer, dumper)
+                }),
+                Self::optional("ThreadInfoList", |mw, buffer, dumper| {
+                    mw.write_thread_info_list(buffer, dumper)
+                }),
+                Self::optional("SignalDispositions", |mw, buffer, _dumper| {
+                    Ok(signal_dispositions::write(buffer, &mw.signal_dispositions)?)
+                }),
+                Self::optional("GpuInfo", |mw, buffer, _dumper| {
+                    Ok(gpu_info::write(buffer, &mw.gpu_info)?)
+                }),
+                Self::optional("CrashReason", |mw, buffer, _dumper| {
+                    Ok(crash_reason::write(buffer, mw.crash_reason.as_deref())?)
+                }),
+                Self::optional("LogLines", |mw, buffer, dumper| {
+                    Ok(log_ring_buffer::write(
+                        buffer,
+                        dumper,
+                        &mw.log_ring_buffers,
+                        |_, _| {},
+                    )?)
+                }),
+                Self::optional("CmdLine", |mw, buffer, dumper| {
+                    mw.write_cmdline(buffer, dumper)
+                }),
+                Self::optional("Environ", |mw, buffer, dumper| {
+                    mw.write_environ(buffer, dumper)
+                }),
+                Self::optional("HostIds", |mw, buffer, _dumper| {
+                    Ok(host_ids::write(buffer, &mw.host_ids)?)
+                }),
             ];
 
-            // Exception stream needs to be the last entry in this array as it may
-            // be omitted in the case where the minidump is written without an
-            // exception.
+            // Exception stream needs to be after the other regular streams as it
+            // may be omitted in the case where the minidump is written without
+            // an exception.
             if self
                 .crash_context
                 .as_ref()
@@ -100,9 +353,17 @@ impl MinidumpWriter {
                 }));
             }
 
+            // This must come last so that it includes the soft errors of all
+            // the other streams
+            writers.push(Box::new(|mw, buffer, dumper| {
+                mw.write_soft_errors(buffer, dumper)
+            }));
+
             writers
         };
 
+        self.soft_errors.clear();
+        self.omitted_sections.clear();
         let num_writers = writers.len() as u32;
         let mut buffer = Buffer::with_capacity(0);
 
@@ -128,48 +389,87 @@ impl MinidumpWriter {
         // we should have a mostly-intact dump
         dir_section.write_to_file(&mut buffer, None)?;
 
-        let dumper = super::task_dumper::TaskDumper::new(self.task);
+        let dumper =
+            super::task_dumper::TaskDumper::with_capture_limits(self.task, self.capture_limits);
 
         for mut writer in writers {
             let dirent = writer(self, &mut buffer, &dumper)?;
             dir_section.write_to_file(&mut buffer, Some(dirent))?;
         }
 
-        Ok(buffer.into())
+        let dir_entries = dir_section.dir_entries(&buffer);
+        let dump: Vec<u8> = buffer.into();
+        let result = DumpResult {
+            phases: vec![("write", start.elapsed())],
+            soft_errors: self.soft_errors.iter().map(ToString::to_string).collect(),
+            omitted_sections: self.omitted_sections.clone(),
+            ..DumpResult::from_dir_entries(&dump, &dir_entries)
+        };
+        Ok((result, dump))
+    }
+
+    /// Wraps the writer of a stream the minidump can do without, so that if it
+    /// fails the stream is left out, which is recorded as a soft error,
+    /// rather than failing the whole dump.
+    #[allow(clippy::type_complexity)]
+    fn optional(
+        stream: &'static str,
+        mut write: impl FnMut(&mut Self, &mut DumpBuf, &TaskDumper) -> Result<MDRawDirectory> + 'static,
+    ) -> Box<dyn FnMut(&mut Self, &mut DumpBuf, &TaskDumper) -> Result<MDRawDirectory>> {
+        Box::new(move |mw, buffer, dumper| match write(mw, buffer, dumper) {
+            Ok(dirent) => Ok(dirent),
+            Err(error) => {
+                log::warn!("leaving out {stream}: {error}");
+                mw.omitted_sections.push(stream);
+                mw.soft_errors.push(SoftError::SectionOmitted {
+                    section: stream,
+                    error,
+                });
+                Ok(MDRawDirectory::default())
+            }
+        })
+    }
+
+    /// Writes a minidump in memory only, returning its contents without
+    /// writing them anywhere
+    pub fn dump_to_vec(&mut self) -> Result<Vec<u8>> {
+        let (_, dump) = self.write_dump(&mut DiscardSink::default())?;
+        Ok(dump)
     }
 
     /// Retrieves the list of active threads in the target process, except
-    /// the handler thread if it is known, to simplify dump analysis
+    /// the handler thread if it is known, to simplify dump analysis. The
+    /// threads are listed once per dump, see [`TaskDumper::threads`].
     #[inline]
-    pub(crate) fn threads(&self, dumper: &TaskDumper) -> ActiveThreads {
+    pub(crate) fn threads<'a>(&self, dumper: &'a TaskDumper) -> ActiveThreads<'a> {
         ActiveThreads {
-            threads: dumper.read_threads().unwrap_or_default(),
+            threads: dumper
+                .threads()
+                .map(|threads| threads.as_slice())
+                .unwrap_or_default(),
             handler_thread: self.handler_thread,
             i: 0,
         }
     }
 }
 
-pub(crate) struct ActiveThreads {
-    threads: &'static [u32],
+pub(crate) struct ActiveThreads<'a> {
+    threads: &'a [u32],
     handler_thread: u32,
     i: usize,
 }
 
-impl ActiveThreads {
+impl ActiveThreads<'_> {
     #[inline]
     pub(crate) fn len(&self) -> usize {
-        let mut len = self.threads.len();
-
-        if self.handler_thread != mach2::port::MACH_PORT_NULL {
-            len -= 1;
-        }
-
-        len
+        self.threads
+            .iter()
+            .filter(|&&thread| thread != self.handler_thread)
+            .count()
     }
 }
 
-impl Iterator for ActiveThreads {
+impl Iterator for ActiveThreads<'_> {
     type Item = u32;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -0,0 +1,46 @@
+//! Checks of whether a process can be dumped, without affecting it.
+//!
+//! Crash reporters can use these when starting up to validate their
+//! configuration (code signing entitlements in particular), rather than
+//! discovering a problem when a crash needs to be dumped.
+
+use crate::mac::mach;
+
+/// The result of checking whether a process can be dumped, see [`can_dump`].
+#[derive(Debug)]
+pub struct DumpPrerequisites {
+    /// Whether we can retrieve the task port of the process, which requires
+    /// the `com.apple.security.cs.debugger` entitlement (or running as root)
+    /// unless the process is our own
+    pub task_for_pid: Result<(), mach::KernelError>,
+}
+
+impl DumpPrerequisites {
+    /// Check whether process `pid` can be dumped.
+    pub fn check(pid: i32) -> Self {
+        let mut task = mach2::port::MACH_PORT_NULL;
+        // SAFETY: syscalls
+        let task_for_pid = unsafe {
+            let kr = mach2::traps::task_for_pid(mach::mach_task_self(), pid, &mut task);
+            if kr == mach::KERN_SUCCESS {
+                mach2::mach_port::mach_port_deallocate(mach::mach_task_self(), task);
+                Ok(())
+            } else {
+                Err(kr.into())
+            }
+        };
+
+        Self { task_for_pid }
+    }
+
+    /// Return whether all the prerequisites for writing a dump are met.
+    pub fn are_met(&self) -> bool {
+        self.task_for_pid.is_ok()
+    }
+}
+
+/// Return whether process `pid` can be dumped, use [`DumpPrerequisites`] to
+/// find out why it can't.
+pub fn can_dump(pid: i32) -> bool {
+    DumpPrerequisites::check(pid).are_met()
+}
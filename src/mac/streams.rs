@@ -3,12 +3,16 @@ mod exception;
 mod memory_list;
 mod misc_info;
 mod module_list;
+mod process_args;
+mod shared_cache;
+mod soft_errors;
 mod system_info;
+mod thread_info_list;
 mod thread_list;
 mod thread_names;
 
 use super::{
-    errors::WriterError,
+    errors::{SoftError, WriterError},
     mach,
     minidump_writer::MinidumpWriter,
     task_dumper::{self, ImageInfo, TaskDumpError, TaskDumper},
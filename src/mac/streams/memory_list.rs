@@ -1,5 +1,12 @@
 use super::*;
 
+use mach2::{exception_types as et, vm_prot};
+
+/// How much memory is captured before and after each address held by a
+/// register, enough for the start of the object it points to
+const REGISTER_BYTES_BEFORE: u64 = 64;
+const REGISTER_BYTES_AFTER: u64 = 192;
+
 impl MinidumpWriter {
     /// Writes the [`MDStreamType::MemoryListStream`]. The memory blocks that are
     /// written into this stream are the raw thread contexts that were retrieved
@@ -41,11 +48,8 @@ impl MinidumpWriter {
                     let size = ip_range.end - ip_range.start;
                     let stack_buffer =
                         dumper.read_task_memory(ip_range.start as _, size as usize)?;
-                    let ip_location = MDLocationDescriptor {
-                        data_size: size as u32,
-                        rva: buffer.position() as u32,
-                    };
-                    buffer.write_all(&stack_buffer);
+                    let ip_location =
+                        MemoryArrayWriter::write_bytes(buffer, &stack_buffer)?.location();
 
                     self.memory_blocks.push(MDMemoryDescriptor {
                         start_of_memory_range: ip_range.start,
@@ -55,6 +59,26 @@ impl MinidumpWriter {
             }
         }
 
+        if let Some(fault_range) = self.fault_address_block(dumper) {
+            let size = fault_range.end - fault_range.start;
+            // The region may have gone away since, as with the IP block the
+            // memory is optional
+            match dumper.read_task_memory::<u8>(fault_range.start as _, size as usize) {
+                Ok(fault_buffer) => {
+                    let fault_location =
+                        MemoryArrayWriter::write_bytes(buffer, &fault_buffer)?.location();
+
+                    self.memory_blocks.push(MDMemoryDescriptor {
+                        start_of_memory_range: fault_range.start,
+                        memory: fault_location,
+                    });
+                }
+                Err(e) => log::warn!("failed to read the memory around the fault address: {e}"),
+            }
+        }
+
+        self.write_register_memory(buffer, dumper);
+
         let list_header =
             MemoryWriter::<u32>::alloc_with_val(buffer, self.memory_blocks.len() as u32)?;
 
@@ -69,4 +93,92 @@ impl MinidumpWriter {
         dirent.location.data_size += block_list.location().data_size;
         Ok(dirent)
     }
+
+    /// The memory around the address an `EXC_BAD_ACCESS` faulted on, see
+    /// [`Self::capture_fault_address_memory`], bounded to the region it lies
+    /// in and left out if it overlaps the memory already captured around the
+    /// instruction pointer.
+    fn fault_address_block(&self, dumper: &TaskDumper) -> Option<std::ops::Range<u64>> {
+        let window = self.fault_address_memory_window? as u64;
+        let exception = self.crash_context.as_ref()?.exception.as_ref()?;
+        if exception.kind != et::EXC_BAD_ACCESS {
+            return None;
+        }
+        let address = exception.subcode?;
+
+        let region = dumper.get_vm_region(address).ok()?;
+        if address < region.range.start || address >= region.range.end {
+            return None;
+        }
+
+        let start = std::cmp::max(region.range.start, address.saturating_sub(window / 2));
+        let end = std::cmp::min(address.saturating_add(window / 2), region.range.end);
+        let overlaps = self.memory_blocks.iter().any(|block| {
+            let block_start = block.start_of_memory_range;
+            block_start < end && start < block_start + block.memory.data_size as u64
+        });
+        (start < end && !overlaps).then_some(start..end)
+    }
+
+    /// Capture the memory pointed to by the registers of the crashing thread,
+    /// see [`Self::capture_register_memory`]. Registers pointing into
+    /// readable, non-executable regions are taken as pointers to data, and
+    /// the memory around each is captured until the budget is spent, leaving
+    /// out the blocks overlapping with what was already captured.
+    fn write_register_memory(&mut self, buffer: &mut DumpBuf, dumper: &TaskDumper) {
+        let (Some(mut remaining), Some(cc)) = (self.register_memory_budget, &self.crash_context)
+        else {
+            return;
+        };
+        let Ok(thread_state) = dumper.read_thread_state(cc.thread) else {
+            return;
+        };
+
+        for address in thread_state.general_registers() {
+            if remaining == 0 {
+                break;
+            }
+            let Ok(region) = dumper.get_vm_region(address) else {
+                continue;
+            };
+            let protection = region.info.protection;
+            if address < region.range.start
+                || address >= region.range.end
+                || protection & vm_prot::VM_PROT_READ == 0
+                || protection & vm_prot::VM_PROT_EXECUTE != 0
+            {
+                continue;
+            }
+
+            let start = std::cmp::max(
+                region.range.start,
+                address.saturating_sub(REGISTER_BYTES_BEFORE),
+            );
+            let end = std::cmp::min(
+                address.saturating_add(REGISTER_BYTES_AFTER),
+                region.range.end,
+            )
+            .min(start + remaining as u64);
+            let overlaps = self.memory_blocks.iter().any(|block| {
+                let block_start = block.start_of_memory_range;
+                block_start < end && start < block_start + block.memory.data_size as u64
+            });
+            if overlaps {
+                continue;
+            }
+
+            let Ok(contents) = dumper.read_task_bytes(start, (end - start) as usize) else {
+                continue;
+            };
+            // Past the 4GiB RVAs can reach the memory is left out
+            let Ok(section) = MemoryArrayWriter::write_bytes(buffer, &contents) else {
+                break;
+            };
+            remaining -= contents.len();
+            self.memory_blocks.push(MDMemoryDescriptor {
+                start_of_memory_range: start,
+                memory: section.location(),
+            });
+        }
+    }
 }
@@ -1,4 +1,8 @@
 use super::*;
+use crate::{
+    module_filter::ModuleAction,
+    module_list::{self, ModuleInfo},
+};
 
 struct ImageLoadInfo {
     /// The preferred load address of the TEXT segment
@@ -23,6 +27,25 @@ struct ImageDetails {
     version: Option<u32>,
 }
 
+fn module_info(image: ImageDetails) -> ModuleInfo {
+    ModuleInfo {
+        path: image.file_path.unwrap_or_default(),
+        base_address: (image.load_info.vm_addr as isize + image.load_info.slide) as u64,
+        size: image.load_info.vm_size,
+        code_id: module_list::hex(&image.uuid),
+        debug_id: module_list::debug_id(&image.uuid),
+        // The version is stored as xxxx.yy.zz
+        version: image.version.map(|version| {
+            format!(
+                "{}.{}.{}",
+                version >> 16,
+                (version >> 8) & 0xff,
+                version & 0xff
+            )
+        }),
+    }
+}
+
 impl MinidumpWriter {
     /// Writes the [`MDStreamType::ModuleListStream`] to the minidump, which is
     /// the last of all loaded modules (images) in the process.
@@ -39,9 +62,13 @@ impl MinidumpWriter {
         // The list of modules is pretty critical information, but there could
         // still be useful information in the minidump without them if we can't
         // retrieve them for some reason
-        let modules = self
-            .write_loaded_modules(buffer, dumper)
-            .unwrap_or_default();
+        let modules = match self.write_loaded_modules(buffer, dumper) {
+            Ok(modules) => modules,
+            Err(error) => {
+                self.soft_errors.push(SoftError::Images(error));
+                Vec::new()
+            }
+        };
 
         let list_header = MemoryWriter::<u32>::alloc_with_val(buffer, modules.len() as u32)?;
 
@@ -59,7 +86,7 @@ impl MinidumpWriter {
     }
 
     fn write_loaded_modules(
-        &self,
+        &mut self,
         buf: &mut DumpBuf,
         dumper: &TaskDumper,
     ) -> Result<Vec<MDRawModule>, WriterError> {
@@ -72,30 +99,41 @@ impl MinidumpWriter {
         images.dedup();
 
         let mut modules = Vec::with_capacity(images.len());
+        // The main executable may be left out by the module filter
+        let mut has_executable = false;
 
         for image in images {
-            if let Ok(image_details) = self.read_image(image, dumper) {
-                let is_main_executable = image_details.version.is_none();
-
-                if let Ok(module) = self.write_module(image_details, buf) {
-                    // We want to keep the modules sorted by their load address except
-                    // in the case of the main executable image which we want to put
-                    // first, as it is most likely the culprit, or at least generally
-                    // the most interesting module for human and machine inspectors
-                    if is_main_executable {
-                        modules.insert(0, module);
-                    } else {
-                        modules.push(module)
-                    };
+            let address = image.load_address;
+            let image_details = match self.read_image(image, dumper) {
+                Ok(image_details) => image_details,
+                Err(error) => {
+                    self.soft_errors
+                        .push(SoftError::ImageLoadCommands { address, error });
+                    continue;
                 }
+            };
+
+            let is_main_executable = image_details.version.is_none();
+            let Some(image_details) = self.filter_module(image_details) else {
+                has_executable |= is_main_executable;
+                continue;
+            };
+
+            if let Ok(module) = self.write_module(image_details, buf) {
+                // We want to keep the modules sorted by their load address except
+                // in the case of the main executable image which we want to put
+                // first, as it is most likely the culprit, or at least generally
+                // the most interesting module for human and machine inspectors
+                if is_main_executable {
+                    has_executable = true;
+                    modules.insert(0, module);
+                } else {
+                    modules.push(module)
+                };
             }
         }
 
-        if !modules
-            .get(0)
-            .map(|rm| rm.version_info.signature != format::VS_FFI_SIGNATURE)
-            .unwrap_or_default()
-        {
+        if !has_executable {
             Err(TaskDumpError::NoExecutableImage.into())
         } else {
             // Crashpad also has code for loading the dyld info from the all images
@@ -104,7 +142,11 @@ impl MinidumpWriter {
             // Apple, which considering their penchant for changings things often
             // and not actually documenting anything, is fair, but if that ever
             // happens we can just...change the code.
-            if let Ok(dyld_image) = self.read_dyld(&all_images_info, dumper) {
+            let dyld_image = self
+                .read_dyld(&all_images_info, dumper)
+                .ok()
+                .and_then(|dyld_image| self.filter_module(dyld_image));
+            if let Some(dyld_image) = dyld_image {
                 if let Ok(module) = self.write_module(dyld_image, buf) {
                     modules.push(module);
                 }
@@ -114,6 +156,50 @@ impl MinidumpWriter {
         }
     }
 
+    /// Lists the images of the task like [`Self::write_loaded_modules`] does,
+    /// for [`crate::module_list::list_modules`]. The images whose load
+    /// commands can't be read are left out.
+    pub(crate) fn list_modules(
+        &self,
+        dumper: &TaskDumper,
+    ) -> Result<Vec<ModuleInfo>, TaskDumpError> {
+        let (all_images_info, mut images) = dumper.read_images()?;
+        images.sort();
+        images.dedup();
+
+        let mut modules = Vec::with_capacity(images.len() + 1);
+        for image in images {
+            let Ok(image) = self.read_image(image, dumper) else {
+                continue;
+            };
+            // The main executable is the only image without a version
+            if image.version.is_none() {
+                modules.insert(0, module_info(image));
+            } else {
+                modules.push(module_info(image));
+            }
+        }
+        if let Ok(dyld_image) = self.read_dyld(&all_images_info, dumper) {
+            modules.push(module_info(dyld_image));
+        }
+        Ok(modules)
+    }
+
+    /// Apply the [`MinidumpWriter::set_module_filter`] filter to an image,
+    /// returning `None` if it must be left out.
+    fn filter_module(&mut self, mut image: ImageDetails) -> Option<ImageDetails> {
+        let Some(filter) = self.module_filter.as_mut() else {
+            return Some(image);
+        };
+
+        match filter(image.file_path.as_deref().unwrap_or_default()) {
+            ModuleAction::Keep => {}
+            ModuleAction::Rename(path) => image.file_path = Some(path),
+            ModuleAction::Omit => return None,
+        }
+        Some(image)
+    }
+
     /// Obtains important image metadata by traversing the image's load commands
     ///
     /// # Errors
@@ -0,0 +1,77 @@
+use super::*;
+
+impl MinidumpWriter {
+    /// Writes the [`MDStreamType::LinuxCmdLine`] stream, holding the
+    /// arguments of the task, each NUL-terminated like `/proc/<pid>/cmdline`
+    /// on Linux.
+    pub(crate) fn write_cmdline(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dumper: &TaskDumper,
+    ) -> Result<MDRawDirectory, WriterError> {
+        let args = dumper.read_process_args()?.args;
+        write_strings(buffer, MDStreamType::LinuxCmdLine, &args)
+    }
+
+    /// Writes the [`MDStreamType::LinuxEnviron`] stream, holding the
+    /// environment variables of the task [`Self::capture_environment`]
+    /// allows, each NUL-terminated like `/proc/<pid>/environ` on Linux.
+    /// Nothing is written unless it was set.
+    pub(crate) fn write_environ(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dumper: &TaskDumper,
+    ) -> Result<MDRawDirectory, WriterError> {
+        let Some(allowlist) = &self.environment_allowlist else {
+            return Ok(MDRawDirectory::default());
+        };
+        let mut env = dumper.read_process_args()?.env;
+        env.retain(|variable| is_allowed(allowlist, variable));
+        write_strings(buffer, MDStreamType::LinuxEnviron, &env)
+    }
+}
+
+fn write_strings(
+    buffer: &mut DumpBuf,
+    stream_type: MDStreamType,
+    strings: &[Vec<u8>],
+) -> Result<MDRawDirectory, WriterError> {
+    let contents: Vec<u8> = strings
+        .iter()
+        .flat_map(|string| string.iter().copied().chain(std::iter::once(0)))
+        .collect();
+    let section = MemoryArrayWriter::write_bytes(buffer, &contents)?;
+    Ok(MDRawDirectory {
+        stream_type: stream_type as u32,
+        location: section.location(),
+    })
+}
+
+/// Whether the environment variable `variable`, as `NAME=value`, is allowed
+/// by `allowlist`, which holds names, or prefixes of names ending with `*`
+fn is_allowed(allowlist: &[String], variable: &[u8]) -> bool {
+    let name = variable.split(|&b| b == b'=').next().unwrap_or_default();
+    allowlist
+        .iter()
+        .any(|allowed| match allowed.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix.as_bytes()),
+            None => name == allowed.as_bytes(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed() {
+        let allowlist = ["LANG".to_owned(), "MOZ_*".to_owned()];
+        assert!(is_allowed(&allowlist, b"LANG=en_US.UTF-8"));
+        assert!(is_allowed(&allowlist, b"MOZ_CRASHREPORTER=1"));
+        assert!(is_allowed(&allowlist, b"MOZ_="));
+        assert!(!is_allowed(&allowlist, b"LANGUAGE=en"));
+        assert!(!is_allowed(&allowlist, b"HOME=/Users/someone"));
+        assert!(!is_allowed(&allowlist, b"LAN"));
+        assert!(!is_allowed(&[], b"LANG=en_US.UTF-8"));
+    }
+}
@@ -0,0 +1,30 @@
+use super::*;
+
+impl MinidumpWriter {
+    /// Writes the [`MDExtraStreamType::MacSharedCache`] stream, describing the
+    /// dyld shared cache the process uses, as read from
+    /// `dyld_all_image_infos`. Nothing is written if it uses none, or dyld is
+    /// too old to tell.
+    pub(crate) fn write_shared_cache(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dumper: &TaskDumper,
+    ) -> Result<MDRawDirectory, WriterError> {
+        let Some(shared_cache) = dumper.read_all_images_info()?.shared_cache() else {
+            return Ok(MDRawDirectory::default());
+        };
+
+        let section = MemoryWriter::alloc_with_val(
+            buffer,
+            MDRawMacSharedCache {
+                uuid: shared_cache.uuid,
+                base_address: shared_cache.base_address,
+                slide: shared_cache.slide,
+            },
+        )?;
+        Ok(MDRawDirectory {
+            stream_type: MDExtraStreamType::MacSharedCache as u32,
+            location: section.location(),
+        })
+    }
+}
@@ -0,0 +1,33 @@
+use super::*;
+use std::fmt::Write as _;
+
+impl MinidumpWriter {
+    /// Writes the [`MDExtraStreamType::MacSoftErrors`] stream, a text stream
+    /// listing the [`SoftError`]s encountered while writing the minidump, one
+    /// per line. Nothing is written if there were none.
+    pub(crate) fn write_soft_errors(
+        &mut self,
+        buffer: &mut DumpBuf,
+        _dumper: &TaskDumper,
+    ) -> Result<MDRawDirectory, WriterError> {
+        if self.soft_errors.is_empty() {
+            return Ok(MDRawDirectory::default());
+        }
+
+        let mut contents = String::new();
+        for error in &self.soft_errors {
+            // Writing to a String is infallible
+            let _ = write!(contents, "{error}");
+            if let Some(kern_return) = error.os_error() {
+                let _ = write!(contents, " (kern_return {kern_return})");
+            }
+            contents.push('\n');
+        }
+
+        let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+        Ok(MDRawDirectory {
+            stream_type: MDExtraStreamType::MacSoftErrors as u32,
+            location: section.location(),
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use super::*;
+
+impl MinidumpWriter {
+    /// Writes the [`MDStreamType::ThreadInfoListStream`], holding the CPU time
+    /// each thread spent in user and kernel mode, in units of 100ns as on
+    /// Windows. The kernel doesn't report when threads were started, so their
+    /// creation time is left empty.
+    pub(crate) fn write_thread_info_list(
+        &mut self,
+        buffer: &mut DumpBuf,
+        dumper: &TaskDumper,
+    ) -> Result<MDRawDirectory, WriterError> {
+        let threads: Vec<_> = self.threads(dumper).collect();
+
+        let list_header = MemoryWriter::alloc_with_val(
+            buffer,
+            MDRawThreadInfoList {
+                size_of_header: std::mem::size_of::<MDRawThreadInfoList>() as u32,
+                size_of_entry: std::mem::size_of::<MDRawThreadInfo>() as u32,
+                number_of_entries: threads.len() as u32,
+            },
+        )?;
+
+        let mut dirent = MDRawDirectory {
+            stream_type: MDStreamType::ThreadInfoListStream as u32,
+            location: list_header.location(),
+        };
+
+        let mut info_list =
+            MemoryArrayWriter::<MDRawThreadInfo>::alloc_array(buffer, threads.len())?;
+        dirent.location.data_size += info_list.location().data_size;
+
+        for (i, tid) in threads.into_iter().enumerate() {
+            let mut info = MDRawThreadInfo {
+                thread_id: tid,
+                dump_flags: 0,
+                dump_error: 0,
+                exit_status: 0,
+                create_time: 0,
+                exit_time: 0,
+                kernel_time: 0,
+                user_time: 0,
+                start_address: 0,
+                affinity: 0,
+            };
+
+            // The CPU times are in nanoseconds, see `write_thread_name` for the
+            // flavor
+            match dumper.thread_info::<libc::proc_threadinfo>(tid) {
+                Ok(thread_info) => {
+                    info.user_time = thread_info.pth_user_time / 100;
+                    info.kernel_time = thread_info.pth_system_time / 100;
+                }
+                Err(error) => {
+                    log::warn!("failed to read the CPU times of thread {tid}: {error}");
+                    info.dump_flags |= MD_THREAD_INFO_INVALID_INFO;
+                }
+            }
+
+            info_list.set_value_at(buffer, info, i)?;
+        }
+
+        Ok(dirent)
+    }
+}
@@ -1,6 +1,10 @@
 use super::*;
 use crate::minidump_cpu::RawContextCPU;
 
+/// How much of each thread's `struct _pthread` is captured, enough for the
+/// thread-specific data slots which follow its header
+const PTHREAD_BYTES: u64 = 8 * 1024;
+
 impl MinidumpWriter {
     /// Writes the [`MDStreamType::ThreadListStream`] which is an array of
     /// [`miniduimp_common::format::MINIDUMP_THREAD`]
@@ -45,9 +49,21 @@ impl MinidumpWriter {
             thread_context: MDLocationDescriptor::default(),
         };
 
-        let thread_state = dumper.read_thread_state(tid)?;
+        let thread_state = match dumper.read_thread_state(tid) {
+            Ok(thread_state) => thread_state,
+            Err(error) => {
+                // The thread is still listed, without a stack or a context
+                self.soft_errors.push(SoftError::ThreadState { tid, error });
+                return Ok(thread);
+            }
+        };
 
-        self.write_stack_from_start_address(thread_state.sp(), &mut thread, buffer, dumper)?;
+        let stack_start = self.stack_start(thread_state.sp(), dumper);
+        self.write_stack_from_start_address(stack_start, &mut thread, buffer, dumper)?;
+
+        if self.capture_thread_local_storage {
+            self.write_thread_local_storage(&mut thread, buffer, dumper);
+        }
 
         let mut cpu: RawContextCPU = Default::default();
         Self::fill_cpu_context(&thread_state, &mut cpu);
@@ -65,7 +81,7 @@ impl MinidumpWriter {
     ) -> Result<(), WriterError> {
         thread.stack.start_of_memory_range = start;
         thread.stack.memory.data_size = 0;
-        thread.stack.memory.rva = buffer.position() as u32;
+        thread.stack.memory.rva = buffer.next_location(0)?.rva;
 
         let stack_size = self.calculate_stack_size(start, dumper);
 
@@ -77,38 +93,37 @@ impl MinidumpWriter {
         // stack eg. InvalidAddress in which case we use a different borked
         // value to indicate the different failure
         let stack_location = if stack_size != 0 {
-            dumper
-                .read_task_memory(start, stack_size)
-                .map(|stack_buffer| {
-                    let stack_location = MDLocationDescriptor {
-                        data_size: stack_buffer.len() as u32,
-                        rva: buffer.position() as u32,
-                    };
-                    buffer.write_all(&stack_buffer);
-                    stack_location
-                })
-                .ok()
+            match dumper.read_task_bytes(start, stack_size) {
+                Ok(stack_buffer) => {
+                    Some(MemoryArrayWriter::write_bytes(buffer, &stack_buffer)?.location())
+                }
+                Err(error) => {
+                    self.soft_errors.push(SoftError::ThreadStack {
+                        tid: thread.thread_id,
+                        error,
+                    });
+                    None
+                }
+            }
         } else {
             None
         };
 
-        thread.stack.memory = stack_location.unwrap_or_else(|| {
-            let borked = if stack_size == 0 {
-                0xdeadbeef
-            } else {
-                0xdeaddead
-            };
+        thread.stack.memory = match stack_location {
+            Some(stack_location) => stack_location,
+            None => {
+                let borked: u64 = if stack_size == 0 {
+                    0xdeadbeef
+                } else {
+                    0xdeaddead
+                };
 
-            thread.stack.start_of_memory_range = borked;
+                thread.stack.start_of_memory_range = borked;
 
-            let stack_location = MDLocationDescriptor {
-                data_size: 16,
-                rva: buffer.position() as u32,
-            };
-            buffer.write_all(&borked.to_ne_bytes());
-            buffer.write_all(&borked.to_ne_bytes());
-            stack_location
-        });
+                let borked = borked.to_ne_bytes();
+                MemoryArrayWriter::write_bytes(buffer, &[borked, borked].concat())?.location()
+            }
+        };
 
         // Add the stack memory as a raw block of memory, this is written to
         // the minidump as part of the memory list stream
@@ -116,6 +131,73 @@ impl MinidumpWriter {
         Ok(())
     }
 
+    /// Captures the thread's `pthread` structure, whose address the kernel
+    /// reports as the thread's handle, and records its address as the thread's
+    /// TEB, as Crashpad does.
+    fn write_thread_local_storage(
+        &mut self,
+        thread: &mut MDRawThread,
+        buffer: &mut DumpBuf,
+        dumper: &TaskDumper,
+    ) {
+        impl mach::ThreadInfo for libc::thread_identifier_info {
+            const FLAVOR: u32 = libc::THREAD_IDENTIFIER_INFO as u32;
+        }
+
+        let tid = thread.thread_id;
+        let thread_handle = match dumper.thread_info::<libc::thread_identifier_info>(tid) {
+            Ok(info) if info.thread_handle != 0 => info.thread_handle,
+            Ok(_) => return,
+            Err(error) => {
+                self.soft_errors
+                    .push(SoftError::ThreadLocalStorage { tid, error });
+                return;
+            }
+        };
+        thread.teb = thread_handle;
+
+        // The structure may be at the end of its region, don't read past it
+        let end = match dumper.get_vm_region(thread_handle) {
+            Ok(region) if region.range.start <= thread_handle => {
+                region.range.end.min(thread_handle + PTHREAD_BYTES)
+            }
+            Ok(_) => return,
+            Err(error) => {
+                self.soft_errors
+                    .push(SoftError::ThreadLocalStorage { tid, error });
+                return;
+            }
+        };
+
+        match dumper.read_task_bytes(thread_handle, (end - thread_handle) as usize) {
+            Ok(contents) => {
+                // Past the 4GiB RVAs can reach the memory is left out
+                if let Ok(section) = MemoryArrayWriter::write_bytes(buffer, &contents) {
+                    self.memory_blocks.push(MDMemoryDescriptor {
+                        start_of_memory_range: thread_handle,
+                        memory: section.location(),
+                    });
+                }
+            }
+            Err(error) => {
+                self.soft_errors
+                    .push(SoftError::ThreadLocalStorage { tid, error });
+            }
+        }
+    }
+
+    /// Where to start capturing the stack of a thread whose stack pointer is
+    /// `stack_pointer`, [`Self::set_stack_red_zone`] bytes below it but not
+    /// past the start of its region
+    fn stack_start(&self, stack_pointer: u64, dumper: &TaskDumper) -> u64 {
+        match dumper.get_vm_region(stack_pointer) {
+            Ok(region) if region.range.contains(&stack_pointer) => stack_pointer
+                .saturating_sub(self.stack_red_zone as u64)
+                .max(region.range.start),
+            _ => stack_pointer,
+        }
+    }
+
     fn calculate_stack_size(&self, start_address: u64, dumper: &TaskDumper) -> usize {
         if start_address == 0 {
             return 0;
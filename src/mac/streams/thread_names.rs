@@ -25,8 +25,9 @@ impl MinidumpWriter {
             // not a critical failure
             let name_loc = match Self::write_thread_name(buffer, dumper, tid) {
                 Ok(loc) => loc,
-                Err(err) => {
-                    log::warn!("failed to write thread name for thread {tid}: {err}");
+                Err(error) => {
+                    log::warn!("failed to write thread name for thread {tid}: {error}");
+                    self.soft_errors.push(SoftError::ThreadName { tid, error });
                     write_string_to_location(buffer, "")?
                 }
             };
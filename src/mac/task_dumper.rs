@@ -1,5 +1,14 @@
-use crate::mac::mach;
+use crate::{
+    capture_limits::{self, CaptureLimits, LimitExceeded},
+    error_code::ErrorCode,
+    mac::mach,
+    memory_map::{MemoryMapping, Protection},
+    process_dumper::ProcessDumper,
+    process_memory::ProcessMemoryReader,
+    scratch::{ScratchBuffer, ScratchPool},
+};
 use mach2::mach_types as mt;
+use std::borrow::Cow;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -20,6 +29,44 @@ pub enum TaskDumpError {
         name: &'static str,
         id: mach::LoadCommandKind,
     },
+    #[error(transparent)]
+    CaptureLimitExceeded(#[from] LimitExceeded),
+    #[error("sysctl {name} failed: {error}")]
+    Sysctl {
+        name: &'static str,
+        error: std::io::Error,
+    },
+    #[error("the arguments of the process are malformed")]
+    MalformedProcessArgs,
+}
+
+impl TaskDumpError {
+    /// The stable code of the error, see [`crate::error_code`].
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Kernel {
+                error: mach::KernelError::InvalidAddress | mach::KernelError::ProtectionFailure,
+                ..
+            } => ErrorCode::ReadMemory,
+            Self::Kernel { .. }
+            | Self::InvalidMachHeader
+            | Self::NonUtf8String(_)
+            | Self::NoExecutableImage
+            | Self::MissingLoadCommand { .. }
+            | Self::CaptureLimitExceeded(_)
+            | Self::Sysctl { .. }
+            | Self::MalformedProcessArgs => ErrorCode::ProcessInfo,
+        }
+    }
+
+    /// The kernel error which caused the error, if any.
+    pub fn os_error(&self) -> Option<i32> {
+        match self {
+            Self::Kernel { error, .. } => Some(*error as i32),
+            Self::Sysctl { error, .. } => error.raw_os_error(),
+            _ => None,
+        }
+    }
 }
 
 /// Wraps a mach call in a Result
@@ -45,8 +92,9 @@ macro_rules! mach_call {
 
 /// `dyld_all_image_infos` from <usr/include/mach-o/dyld_images.h>
 ///
-/// This struct is truncated as we only need a couple of fields at the beginning
-/// of the struct
+/// This struct is truncated as we only need the fields up to the base address
+/// of the shared cache. The fields past those of [`Self::version`] may be
+/// garbage, see [`Self::shared_cache`].
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct AllImagesInfo {
@@ -58,14 +106,64 @@ pub struct AllImagesInfo {
     info_array_addr: u64,
     /// A function pointer, unused
     _notification: u64,
-    /// Unused
-    _process_detached_from_shared_region: bool,
+    /// Whether the process stopped using the shared cache
+    process_detached_from_shared_region: bool,
     // VERSION 2
     lib_system_initialized: bool,
     // Note that crashpad adds a 32-bit int here to get proper alignment when
     // building on 32-bit targets...but we explicitly don't care about 32-bit
     // targets since Apple doesn't
     pub dyld_image_load_address: u64,
+    // VERSION 3 to 11, unused
+    _jit_info: u64,
+    _dyld_version: u64,
+    _error_message: u64,
+    _termination_flags: u64,
+    _core_symbolication_shm_page: u64,
+    _system_order_flag: u64,
+    _uuid_array_count: u64,
+    _uuid_array: u64,
+    _dyld_all_image_infos_address: u64,
+    _initial_image_count: u64,
+    _error_kind: u64,
+    _error_client_of_dylib_path: u64,
+    _error_target_dylib_path: u64,
+    _error_symbol: u64,
+    // VERSION 12
+    shared_cache_slide: u64,
+    // VERSION 13
+    shared_cache_uuid: [u8; 16],
+    // VERSION 15
+    shared_cache_base_address: u64,
+}
+
+impl AllImagesInfo {
+    /// The dyld shared cache the process uses, if it uses one and dyld is
+    /// recent enough to tell (macOS 10.13 and later)
+    pub fn shared_cache(&self) -> Option<SharedCacheInfo> {
+        if self.version < 15
+            || self.process_detached_from_shared_region
+            || self.shared_cache_base_address == 0
+        {
+            return None;
+        }
+        Some(SharedCacheInfo {
+            uuid: self.shared_cache_uuid,
+            base_address: self.shared_cache_base_address,
+            slide: self.shared_cache_slide,
+        })
+    }
+}
+
+/// The dyld shared cache a process uses, holding the system libraries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedCacheInfo {
+    /// The UUID of the cache file, which identifies its symbols
+    pub uuid: [u8; 16],
+    /// The address the cache is loaded at
+    pub base_address: u64,
+    /// How far the cache was moved from its preferred address
+    pub slide: u64,
 }
 
 /// `dyld_image_info` from <usr/include/mach-o/dyld_images.h>
@@ -100,26 +198,171 @@ impl PartialOrd for ImageInfo {
     }
 }
 
+/// The share modes of the regions whose writes are seen by the other tasks
+/// mapping them, from <usr/include/mach/vm_region.h>
+const SM_SHARED: u8 = 4;
+const SM_TRUESHARED: u8 = 5;
+const SM_SHARED_ALIASED: u8 = 7;
+
 /// Describes a region of virtual memory
 pub struct VMRegionInfo {
     pub info: mach::vm_region_submap_info_64,
     pub range: std::ops::Range<u64>,
 }
 
+impl VMRegionInfo {
+    /// The region as a [`MemoryMapping`] of the task `pid`, whose file is
+    /// looked up by pid. Regions don't have names on macOS.
+    pub fn memory_mapping(&self, pid: Option<i32>) -> MemoryMapping {
+        let protection = self.info.protection;
+        MemoryMapping {
+            range: self.range.clone(),
+            protection: Protection {
+                read: protection & mach2::vm_prot::VM_PROT_READ != 0,
+                write: protection & mach2::vm_prot::VM_PROT_WRITE != 0,
+                execute: protection & mach2::vm_prot::VM_PROT_EXECUTE != 0,
+            },
+            shared: matches!(
+                self.info.share_mode,
+                SM_SHARED | SM_TRUESHARED | SM_SHARED_ALIASED
+            ),
+            offset: self.info.offset,
+            backing_file: pid.and_then(|pid| region_file(pid, self.range.start)),
+            name: None,
+        }
+    }
+}
+
+/// The path of the file the region of the process `pid` at `address` is
+/// mapped from, if any
+fn region_file(pid: i32, address: u64) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut path = [0u8; libc::PATH_MAX as usize];
+    // SAFETY: syscall, the buffer is as large as we say it is
+    let len = unsafe {
+        libc::proc_regionfilename(pid, address, path.as_mut_ptr().cast(), path.len() as u32)
+    };
+    let path = path.get(..usize::try_from(len).ok().filter(|&len| len > 0)?)?;
+    Some(std::ffi::OsStr::from_bytes(path).into())
+}
+
+/// The arguments and environment a task was started with, see
+/// [`TaskDumper::read_process_args`]. The strings are kept as they are, as
+/// nothing guarantees they are UTF-8.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessArgs {
+    /// The path of the executable, as given to `execve`
+    pub executable_path: Vec<u8>,
+    /// The arguments, starting with `argv[0]`
+    pub args: Vec<Vec<u8>>,
+    /// The environment variables, as `NAME=value`
+    pub env: Vec<Vec<u8>>,
+}
+
+impl ProcessArgs {
+    /// Parses the buffer filled by `KERN_PROCARGS2`, which holds `argc` as
+    /// an `i32`, followed by the NUL-terminated executable path, the padding
+    /// NULs aligning what follows, then the argc arguments and the
+    /// environment variables, all NUL-terminated. The environment ends with
+    /// an empty string, and is followed by the strings the kernel passes to
+    /// dyld, which are left out.
+    ///
+    /// Returns `None` if the buffer is too short for argc or for its
+    /// arguments.
+    pub fn parse(buffer: &[u8]) -> Option<Self> {
+        let argc = i32::from_ne_bytes(buffer.get(..4)?.try_into().ok()?);
+        let argc = usize::try_from(argc).ok()?;
+
+        let mut strings = buffer[4..].split(|&b| b == 0);
+        let executable_path = strings.next()?.to_vec();
+        // The padding shows up as empty strings
+        let mut strings = strings.skip_while(|string| string.is_empty());
+
+        let args = strings
+            .by_ref()
+            .take(argc)
+            .map(<[u8]>::to_vec)
+            .collect::<Vec<_>>();
+        if args.len() != argc {
+            return None;
+        }
+        let env = strings
+            .take_while(|string| !string.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+
+        Some(Self {
+            executable_path,
+            args,
+            env,
+        })
+    }
+}
+
+/// The threads of a task, as listed by a single call to `task_threads`. The
+/// send rights to the threads which come with the list are released when it
+/// is dropped.
+#[derive(Debug)]
+pub struct ThreadList {
+    threads: Vec<mt::thread_act_t>,
+}
+
+impl ThreadList {
+    /// The ports of the threads
+    pub fn as_slice(&self) -> &[mt::thread_act_t] {
+        &self.threads
+    }
+}
+
+impl std::ops::Deref for ThreadList {
+    type Target = [mt::thread_act_t];
+
+    fn deref(&self) -> &Self::Target {
+        &self.threads
+    }
+}
+
+impl Drop for ThreadList {
+    fn drop(&mut self) {
+        for &thread in &self.threads {
+            // SAFETY: syscall, the right was given to us by task_threads
+            unsafe {
+                mach2::mach_port::mach_port_deallocate(mach::mach_task_self(), thread);
+            }
+        }
+    }
+}
+
 /// Similarly to PtraceDumper for Linux, this provides access to information
 /// for a task (MacOS process)
 pub struct TaskDumper {
     task: mt::task_t,
     page_size: i64,
+    capture_limits: CaptureLimits,
+    /// The threads of the task, listed the first time they're needed, see
+    /// [`Self::threads`]
+    threads: std::sync::OnceLock<ThreadList>,
+    /// The buffers reused by [`Self::read_task_bytes`]
+    scratch: ScratchPool,
 }
 
 impl TaskDumper {
     /// Constructs a [`TaskDumper`] for the specified task
     pub fn new(task: mt::task_t) -> Self {
+        Self::with_capture_limits(task, CaptureLimits::default())
+    }
+
+    /// Constructs a [`TaskDumper`] for the specified task, which doesn't
+    /// trust the counts and sizes read from it beyond `capture_limits`
+    pub fn with_capture_limits(task: mt::task_t, capture_limits: CaptureLimits) -> Self {
         Self {
             task,
             // SAFETY: syscall
             page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as i64,
+            capture_limits,
+            threads: std::sync::OnceLock::new(),
+            scratch: ScratchPool::default(),
         }
     }
 
@@ -132,7 +375,37 @@ impl TaskDumper {
     where
         T: Sized + Clone,
     {
-        let length = (count * std::mem::size_of::<T>()) as u64;
+        let length = count * std::mem::size_of::<T>();
+        self.read_task_memory_with(address, length, |bytes| {
+            // SAFETY: this is safe as long as the kernel has not lied to us
+            let task_buffer = unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast(), count) };
+            task_buffer.to_vec()
+        })
+    }
+
+    /// Like [`Self::read_task_memory`], into a buffer reused across reads,
+    /// for the memory which is copied to the minidump right away.
+    pub(crate) fn read_task_bytes(
+        &self,
+        address: u64,
+        length: usize,
+    ) -> Result<ScratchBuffer<'_>, TaskDumpError> {
+        self.read_task_memory_with(address, length, |bytes| {
+            let mut buffer = self.scratch.take();
+            buffer.extend_from_slice(bytes);
+            buffer
+        })
+    }
+
+    /// Reads `length` bytes of the task's memory at `address`, and passes
+    /// them to `read` while they are mapped in our address space.
+    fn read_task_memory_with<R>(
+        &self,
+        address: u64,
+        length: usize,
+        read: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, TaskDumpError> {
+        let length = length as u64;
 
         // use the negative of the page size for the mask to find the page address
         let page_address = address & (-self.page_size as u64);
@@ -151,18 +424,14 @@ impl TaskDumper {
             &mut local_length
         ))?;
 
-        let mut buffer = Vec::with_capacity(count);
-
         // SAFETY: this is safe as long as the kernel has not lied to us
         let task_buffer = unsafe {
             std::slice::from_raw_parts(
-                (local_start as *const u8)
-                    .offset((address - page_address) as isize)
-                    .cast(),
-                count,
+                (local_start as *const u8).offset((address - page_address) as isize),
+                length as usize,
             )
         };
-        buffer.extend_from_slice(task_buffer);
+        let result = read(task_buffer);
 
         // Don't worry about the return here, if something goes wrong there's probably
         // not much we can do about it, and we have what we want anyways
@@ -172,16 +441,17 @@ impl TaskDumper {
             local_length as u64, // vm_read and vm_deallocate use different sizes :-/
         ));
 
-        Ok(buffer)
+        Ok(result)
     }
 
     /// Reads a null terminated string starting at the specified address. This
     /// is a specialization of [`read_task_memory`] since strings can span VM
     /// regions.
     ///
-    /// If not specified, the string is capped at 8k which should never be close
-    /// to being hit in normal scenarios, at least for "system" strings, which is
-    /// all this interface is used to retrieve
+    /// If not specified, the string is capped at the
+    /// [`CaptureLimits::max_string_len`], 8k by default, which should never be
+    /// close to being hit in normal scenarios, at least for "system" strings,
+    /// which is all this interface is used to retrieve
     ///
     /// # Errors
     ///
@@ -216,17 +486,18 @@ impl TaskDumper {
         };
 
         if let Ok(size_to_end) = get_region_size() {
-            let mut bytes = self.read_task_memory(
+            let bytes = self.read_task_bytes(
                 addr,
-                std::cmp::min(size_to_end as usize, expected_size.unwrap_or(8 * 1024)),
+                std::cmp::min(
+                    size_to_end as usize,
+                    expected_size.unwrap_or(self.capture_limits.max_string_len),
+                ),
             )?;
 
             // Find the null terminator and truncate our string
-            if let Some(null_pos) = bytes.iter().position(|c| *c == 0) {
-                bytes.resize(null_pos, 0);
-            }
+            let len = bytes.iter().position(|c| *c == 0).unwrap_or(bytes.len());
 
-            Ok(String::from_utf8(bytes).map(Some)?)
+            Ok(String::from_utf8(bytes[..len].to_vec()).map(Some)?)
         } else {
             Ok(None)
         }
@@ -337,9 +608,33 @@ impl TaskDumper {
     ///
     /// # Errors
     ///
-    /// The syscall to retrieve the location of the loaded images fails, or
-    /// the syscall to read the loaded images from the process memory fails
+    /// The syscall to retrieve the location of the loaded images fails, the
+    /// syscall to read the loaded images from the process memory fails, or
+    /// there are more of them than [`CaptureLimits::max_modules`]
     pub fn read_images(&self) -> Result<(AllImagesInfo, Vec<ImageInfo>), TaskDumpError> {
+        let all_images_info = self.read_all_images_info()?;
+        capture_limits::check(
+            "image count",
+            all_images_info.info_array_count.into(),
+            self.capture_limits.max_modules,
+        )?;
+
+        let images = self.read_task_memory::<ImageInfo>(
+            all_images_info.info_array_addr,
+            all_images_info.info_array_count as usize,
+        )?;
+
+        Ok((all_images_info, images))
+    }
+
+    /// Retrieves dyld's description of the images loaded in the task, without
+    /// the images themselves.
+    ///
+    /// # Errors
+    ///
+    /// The syscall to retrieve its location fails, or the syscall to read it
+    /// from the process memory fails
+    pub fn read_all_images_info(&self) -> Result<AllImagesInfo, TaskDumpError> {
         impl mach::TaskInfo for mach::task_info::task_dyld_info {
             const FLAVOR: u32 = mach::task_info::TASK_DYLD_INFO;
         }
@@ -358,13 +653,7 @@ impl TaskDumper {
             self.read_task_memory::<u8>(all_images_addr, std::mem::size_of::<AllImagesInfo>())?;
         // SAFETY: this is fine as long as the kernel isn't lying to us
         let all_images_info: &AllImagesInfo = unsafe { &*(dyld_all_info_buf.as_ptr().cast()) };
-
-        let images = self.read_task_memory::<ImageInfo>(
-            all_images_info.info_array_addr,
-            all_images_info.info_array_count as usize,
-        )?;
-
-        Ok((*all_images_info, images))
+        Ok(*all_images_info)
     }
 
     /// Retrieves the main executable image for the task.
@@ -401,8 +690,9 @@ impl TaskDumper {
     /// # Errors
     ///
     /// We fail to read the image header for the specified image, the header we
-    /// read is determined to be invalid, or we fail to read the block of memory
-    /// containing the load commands themselves.
+    /// read is determined to be invalid, its load commands are larger than
+    /// [`CaptureLimits::max_segment_size`], or we fail to read the block of
+    /// memory containing the load commands themselves.
     pub fn read_load_commands(&self, img: &ImageInfo) -> Result<mach::LoadCommands, TaskDumpError> {
         let mach_header = self.read_task_memory::<mach::MachHeader>(img.load_address, 1)?;
 
@@ -416,6 +706,11 @@ impl TaskDumper {
         // the task memory. Note that load commands vary in size so we need to
         // retrieve the memory as a raw byte buffer that we can then iterate
         // through and step according to the size of each load command
+        capture_limits::check(
+            "load commands size",
+            header.size_commands.into(),
+            self.capture_limits.max_segment_size,
+        )?;
         let load_commands_buf = self.read_task_memory::<u8>(
             img.load_address + std::mem::size_of::<mach::MachHeader>() as u64,
             header.size_commands as usize,
@@ -427,12 +722,13 @@ impl TaskDumper {
         })
     }
 
-    /// Gets a list of all of the thread ids in the task
+    /// Gets a list of all of the thread ids in the task, as they are now.
+    /// Use [`Self::threads`] to see the same threads every time.
     ///
     /// # Errors
     ///
     /// The syscall to retrieve the list of threads fails
-    pub fn read_threads(&self) -> Result<&'static [u32], TaskDumpError> {
+    pub fn read_threads(&self) -> Result<ThreadList, TaskDumpError> {
         let mut threads = std::ptr::null_mut();
         let mut thread_count = 0;
 
@@ -442,10 +738,36 @@ impl TaskDumper {
             &mut thread_count
         ))?;
 
-        Ok(
-            // SAFETY: This should be valid if the call succeeded
-            unsafe { std::slice::from_raw_parts(threads, thread_count as usize) },
-        )
+        // SAFETY: This should be valid if the call succeeded
+        let ports = unsafe { std::slice::from_raw_parts(threads, thread_count as usize) };
+        let list = ThreadList {
+            threads: ports.to_vec(),
+        };
+
+        // The array was allocated in our address space by the kernel, the
+        // thread ports are now owned by the list
+        let _res = mach_call!(mach::mach_vm_deallocate(
+            mach::mach_task_self(),
+            threads as u64,
+            (thread_count as usize * std::mem::size_of::<mt::thread_act_t>()) as u64,
+        ));
+
+        Ok(list)
+    }
+
+    /// Gets the list of the threads in the task the first time it is called,
+    /// and the same list afterwards, so that all the streams of a minidump
+    /// agree on which threads there are.
+    ///
+    /// # Errors
+    ///
+    /// The syscall to retrieve the list of threads fails
+    pub fn threads(&self) -> Result<&ThreadList, TaskDumpError> {
+        if let Some(threads) = self.threads.get() {
+            return Ok(threads);
+        }
+        let threads = self.read_threads()?;
+        Ok(self.threads.get_or_init(|| threads))
     }
 
     /// Retrieves the PID for the task
@@ -459,4 +781,119 @@ impl TaskDumper {
         mach_call!(mach::pid_for_task(self.task, &mut pid))?;
         Ok(pid)
     }
+
+    /// Reads the arguments and the environment the task was started with,
+    /// as `sysctl(KERN_PROCARGS2)` reports them
+    ///
+    /// # Errors
+    ///
+    /// The task is gone, belongs to another user, or the kernel returned
+    /// arguments we can't make sense of.
+    pub fn read_process_args(&self) -> Result<ProcessArgs, TaskDumpError> {
+        let pid = self.pid_for_task()?;
+
+        let sysctl_error = |name| TaskDumpError::Sysctl {
+            name,
+            error: std::io::Error::last_os_error(),
+        };
+
+        // The arguments can't be larger than ARG_MAX, which the kernel reports
+        let mut mib = [libc::CTL_KERN, libc::KERN_ARGMAX];
+        let mut arg_max: libc::c_int = 0;
+        let mut len = std::mem::size_of_val(&arg_max);
+        // SAFETY: syscall, the buffer is as large as we say it is
+        if unsafe {
+            libc::sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as u32,
+                (&mut arg_max as *mut libc::c_int).cast(),
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        } != 0
+        {
+            return Err(sysctl_error("KERN_ARGMAX"));
+        }
+
+        let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid];
+        let mut buffer = vec![0u8; arg_max.max(0) as usize];
+        let mut len = buffer.len();
+        // SAFETY: syscall, the buffer is as large as we say it is
+        if unsafe {
+            libc::sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as u32,
+                buffer.as_mut_ptr().cast(),
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        } != 0
+        {
+            return Err(sysctl_error("KERN_PROCARGS2"));
+        }
+        buffer.truncate(len);
+
+        ProcessArgs::parse(&buffer).ok_or(TaskDumpError::MalformedProcessArgs)
+    }
+
+    /// Lists the top-level VM regions of the task, lowest first, see
+    /// [`crate::memory_map`]. The regions of submaps, like the dyld shared
+    /// cache, are listed as a single region.
+    pub fn memory_map(&self) -> Vec<MemoryMapping> {
+        let pid = self.pid_for_task().ok();
+        let mut mappings = Vec::new();
+        let mut address = 0;
+        // The lookup fails past the last region
+        while let Ok(region) = self.get_vm_region(address) {
+            if region.range.end <= address {
+                break;
+            }
+            address = region.range.end;
+            mappings.push(region.memory_mapping(pid));
+        }
+        mappings
+    }
+}
+
+impl ProcessMemoryReader for TaskDumper {
+    type Error = TaskDumpError;
+
+    fn read_into(&mut self, address: usize, dst: &mut [u8]) -> Result<usize, TaskDumpError> {
+        if dst.is_empty() {
+            return Ok(0);
+        }
+        let bytes = self.read_task_memory::<u8>(address as u64, dst.len())?;
+        dst.copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+}
+
+impl ProcessDumper for TaskDumper {
+    type Thread = u32;
+    type Mapping = ImageInfo;
+    type ThreadState = mach::ThreadState;
+    type Error = TaskDumpError;
+
+    fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>, TaskDumpError> {
+        self.read_task_memory(address as u64, length)
+    }
+
+    fn threads(&self) -> Result<Cow<'_, [u32]>, TaskDumpError> {
+        TaskDumper::threads(self).map(|threads| Cow::Borrowed(threads.as_slice()))
+    }
+
+    fn mappings(&self) -> Result<Cow<'_, [ImageInfo]>, TaskDumpError> {
+        let (_all_images, images) = self.read_images()?;
+        Ok(Cow::Owned(images))
+    }
+
+    fn memory_map(&self) -> Result<Vec<MemoryMapping>, TaskDumpError> {
+        Ok(TaskDumper::memory_map(self))
+    }
+
+    fn thread_state(&self, thread: &u32) -> Result<mach::ThreadState, TaskDumpError> {
+        self.read_thread_state(*thread)
+    }
 }
@@ -1,3 +1,45 @@
+//! The primitives the streams of a minidump are written with, which can be
+//! used to write custom streams the same way.
+//!
+//! A minidump is laid out in a [`Buffer`] ([`crate::dir_section::DumpBuf`]),
+//! which only grows, from offset 0 being the start of the minidump. Values
+//! are written to it in little endian, either right away or into slots which
+//! are reserved first and filled in later, e.g. a count which is only known
+//! once the items following it have been written.
+//!
+//! The writers hold the RVA of what they wrote, that is its offset in the
+//! buffer, and can describe it with an [`MDLocationDescriptor`] to be
+//! referenced from other structures or from the stream directory. RVAs are
+//! 32 bits, so allocating past 4GiB, or more than 4GiB at once, fails with
+//! [`MemoryWriterError::RvaOverflow`]. Memory which may not fit is written
+//! to the Memory64List stream instead, whose contents are appended last and
+//! are referenced with 64-bit offsets. Slots only refer to the buffer
+//! they were allocated in, and have to be filled in before the part of the
+//! buffer holding them is written out.
+//!
+//! ```
+//! use minidump_writer::{
+//!     dir_section::DumpBuf,
+//!     mem_writer::{write_string_to_location, MemoryArrayWriter, MemoryWriter},
+//! };
+//!
+//! let mut buffer = DumpBuf::with_capacity(0);
+//! // A count of names followed by their locations
+//! let mut count = MemoryWriter::<u32>::alloc(&mut buffer)?;
+//! let mut names = MemoryArrayWriter::alloc_array(&mut buffer, 2)?;
+//! for (idx, name) in ["foo", "bar"].into_iter().enumerate() {
+//!     let location = write_string_to_location(&mut buffer, name)?;
+//!     names.set_value_at(&mut buffer, location, idx)?;
+//! }
+//! count.set_value(&mut buffer, 2)?;
+//!
+//! let mut stream = count.location();
+//! stream.data_size += names.location().data_size;
+//! assert_eq!(stream.rva, 0);
+//! assert_eq!(stream.data_size, 4 + 2 * 8);
+//! # Ok::<(), minidump_writer::mem_writer::MemoryWriterError>(())
+//! ```
+
 use crate::minidump_format::{MDLocationDescriptor, MDRVA};
 use scroll::ctx::{SizeWith, TryIntoCtx};
 
@@ -9,6 +51,8 @@ pub enum MemoryWriterError {
     TryFromIntError(#[from] std::num::TryFromIntError),
     #[error("Failed to write to buffer")]
     Scroll(#[from] scroll::Error),
+    #[error("{size} bytes at offset {offset} are out of reach of 32-bit RVAs")]
+    RvaOverflow { offset: u64, size: u64 },
 }
 
 type WriteResult<T> = std::result::Result<T, MemoryWriterError>;
@@ -19,28 +63,50 @@ macro_rules! size {
     };
 }
 
+/// The in-memory contents of a minidump, see the [module](self) documentation.
 pub struct Buffer {
     inner: Vec<u8>,
+    /// The strings written with [`write_string_to_location`], so that
+    /// repeated ones are only written once
+    strings: std::collections::HashMap<String, MDLocationDescriptor>,
 }
 
 impl Buffer {
+    /// An empty buffer, with room for `cap` bytes.
     pub fn with_capacity(cap: usize) -> Self {
         Self {
             inner: Vec::with_capacity(cap),
+            strings: std::collections::HashMap::new(),
         }
     }
 
+    /// The current length of the buffer, which is where the next value will
+    /// be written.
     #[inline]
     pub fn position(&self) -> u64 {
         self.inner.len() as u64
     }
 
+    /// The location `size` bytes appended to the buffer would have. This
+    /// fails if they would start past 4GiB or are larger than that, as 32-bit
+    /// RVAs and sizes couldn't describe them.
+    pub fn next_location(&self, size: usize) -> WriteResult<MDLocationDescriptor> {
+        let offset = self.position();
+        match (MDRVA::try_from(offset), u32::try_from(size)) {
+            (Ok(rva), Ok(data_size)) => Ok(MDLocationDescriptor { data_size, rva }),
+            _ => Err(MemoryWriterError::RvaOverflow {
+                offset,
+                size: size as u64,
+            }),
+        }
+    }
+
+    /// Append `len` zeroed bytes, returning their RVA.
     #[inline]
-    #[must_use]
-    fn reserve(&mut self, len: usize) -> usize {
-        let mark = self.inner.len();
+    fn reserve(&mut self, len: usize) -> WriteResult<MDRVA> {
+        let rva = self.next_location(len)?.rva;
         self.inner.resize(self.inner.len() + len, 0);
-        mark
+        Ok(rva)
     }
 
     #[inline]
@@ -68,6 +134,7 @@ impl Buffer {
         val.try_into_ctx(dst, scroll::Endian::Little)
     }
 
+    /// Append raw bytes to the buffer.
     #[inline]
     pub fn write_all(&mut self, buffer: &[u8]) {
         self.inner.extend_from_slice(buffer);
@@ -88,9 +155,12 @@ impl std::ops::Deref for Buffer {
     }
 }
 
+/// A single `T` written to a [`Buffer`].
 #[derive(Debug)]
 pub struct MemoryWriter<T> {
+    /// The RVA of the value
     pub position: MDRVA,
+    /// The size of the value in the minidump
     pub size: usize,
     phantom: std::marker::PhantomData<T>,
 }
@@ -102,11 +172,11 @@ where
     /// Create a slot for a type T in the buffer, we can fill right now with real values.
     pub fn alloc_with_val(buffer: &mut Buffer, val: T) -> WriteResult<Self> {
         // Mark the position as we may overwrite later
-        let position = buffer.position();
+        let position = buffer.next_location(size!(T))?.rva;
         let size = buffer.write(val)?;
 
         Ok(Self {
-            position: position as u32,
+            position,
             size,
             phantom: std::marker::PhantomData,
         })
@@ -115,7 +185,7 @@ where
     /// Create a slot for a type T in the buffer, we can fill later with real values.
     pub fn alloc(buffer: &mut Buffer) -> WriteResult<Self> {
         let size = size!(T);
-        let position = buffer.reserve(size) as u32;
+        let position = buffer.reserve(size)?;
 
         Ok(Self {
             position,
@@ -130,6 +200,7 @@ where
         Ok(buffer.write_at(self.position as usize, val).map(|_sz| ())?)
     }
 
+    /// Where the value is in the minidump.
     #[inline]
     pub fn location(&self) -> MDLocationDescriptor {
         MDLocationDescriptor {
@@ -139,25 +210,27 @@ where
     }
 }
 
+/// Contiguous `T`s written to a [`Buffer`].
 #[derive(Debug)]
 pub struct MemoryArrayWriter<T> {
+    /// The RVA of the first element
     pub position: MDRVA,
     array_size: usize,
     phantom: std::marker::PhantomData<T>,
 }
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
 impl MemoryArrayWriter<u8> {
+    /// Append raw bytes to the buffer.
     #[inline]
-    pub fn write_bytes(buffer: &mut Buffer, slice: &[u8]) -> Self {
-        let position = buffer.position();
+    pub fn write_bytes(buffer: &mut Buffer, slice: &[u8]) -> WriteResult<Self> {
+        let position = buffer.next_location(slice.len())?.rva;
         buffer.write_all(slice);
 
-        Self {
-            position: position as u32,
+        Ok(Self {
+            position,
             array_size: slice.len(),
             phantom: std::marker::PhantomData,
-        }
+        })
     }
 }
 
@@ -165,16 +238,17 @@ impl<T> MemoryArrayWriter<T>
 where
     T: TryIntoCtx<scroll::Endian, Error = scroll::Error> + SizeWith<scroll::Endian> + Copy,
 {
+    /// Write a copy of `array` to the buffer.
     pub fn alloc_from_array(buffer: &mut Buffer, array: &[T]) -> WriteResult<Self> {
         let array_size = array.len();
-        let position = buffer.reserve(array_size * size!(T));
+        let position = buffer.reserve(array_size * size!(T))?;
 
         for (idx, val) in array.iter().enumerate() {
-            buffer.write_at(position + idx * size!(T), *val)?;
+            buffer.write_at(position as usize + idx * size!(T), *val)?;
         }
 
         Ok(Self {
-            position: position as u32,
+            position,
             array_size,
             phantom: std::marker::PhantomData,
         })
@@ -196,14 +270,14 @@ where
         let iter = iter.into_iter();
         let array_size = iter.len();
         let size = size!(T);
-        let position = buffer.reserve(array_size * size);
+        let position = buffer.reserve(array_size * size)?;
 
         for (idx, val) in iter.enumerate() {
-            buffer.write_at(position + idx * size, val)?;
+            buffer.write_at(position as usize + idx * size, val)?;
         }
 
         Ok(Self {
-            position: position as u32,
+            position,
             array_size,
             phantom: std::marker::PhantomData,
         })
@@ -213,10 +287,10 @@ where
     /// This function fills it with `Default::default()`, which is less performant than
     /// using uninitialized memory, but safe.
     pub fn alloc_array(buffer: &mut Buffer, array_size: usize) -> WriteResult<Self> {
-        let position = buffer.reserve(array_size * size!(T));
+        let position = buffer.reserve(array_size * size!(T))?;
 
         Ok(Self {
-            position: position as u32,
+            position,
             array_size,
             phantom: std::marker::PhantomData,
         })
@@ -230,6 +304,7 @@ where
             .map(|_sz| ())?)
     }
 
+    /// Where the whole array is in the minidump.
     #[inline]
     pub fn location(&self) -> MDLocationDescriptor {
         MDLocationDescriptor {
@@ -238,6 +313,7 @@ where
         }
     }
 
+    /// Where the element at `idx` is in the minidump.
     #[inline]
     pub fn location_of_index(&self, idx: usize) -> MDLocationDescriptor {
         MDLocationDescriptor {
@@ -247,10 +323,17 @@ where
     }
 }
 
+/// Write `text` as a `MINIDUMP_STRING`, returning its location. Strings
+/// which were already written to `buffer`, such as the paths of modules mapped
+/// several times, aren't written again and share the same location.
 pub fn write_string_to_location(
     buffer: &mut Buffer,
     text: &str,
 ) -> WriteResult<MDLocationDescriptor> {
+    if let Some(location) = buffer.strings.get(text) {
+        return Ok(*location);
+    }
+
     let letters: Vec<u16> = text.encode_utf16().collect();
 
     // First write size of the string (x letters in u16, times the size of u16)
@@ -268,5 +351,41 @@ pub fn write_string_to_location(
     let mut location = text_header.location();
     location.data_size += text_section.location().data_size;
 
+    buffer.strings.insert(text.to_owned(), location);
     Ok(location)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strings_are_deduplicated() {
+        let mut buffer = Buffer::with_capacity(0);
+        let first = write_string_to_location(&mut buffer, "/usr/lib/libc.so.6").unwrap();
+        let len = buffer.position();
+        let other = write_string_to_location(&mut buffer, "/usr/lib/libm.so.6").unwrap();
+        let again = write_string_to_location(&mut buffer, "/usr/lib/libc.so.6").unwrap();
+
+        assert_eq!(again.rva, first.rva);
+        assert_eq!(again.data_size, first.data_size);
+        assert_ne!(other.rva, first.rva);
+        assert_eq!(buffer.position(), len * 2);
+    }
+
+    #[test]
+    fn test_next_location() {
+        let mut buffer = Buffer::with_capacity(0);
+        buffer.write_all(&[0; 16]);
+        let location = buffer.next_location(8).unwrap();
+        assert_eq!((location.rva, location.data_size), (16, 8));
+        // The sizes of 32-bit locations can't describe more than 4GiB
+        assert!(matches!(
+            buffer.next_location(1 << 32),
+            Err(MemoryWriterError::RvaOverflow {
+                offset: 16,
+                size: 0x1_0000_0000
+            })
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+//! A description of the memory mappings of a process which is the same on
+//! every platform, see [`crate::process_dumper::ProcessDumper::memory_map`].
+//!
+//! On Linux the mappings are listed as `/proc/<pid>/maps` does, one per line,
+//! rather than merged into modules as the dumper's own mappings are. On macOS
+//! they are the top-level VM regions of the task.
+
+use crate::minidump_format::MDMemoryInfo;
+use minidump_common::format::{MemoryProtection, MemoryState, MemoryType};
+use std::{ops::Range, path::PathBuf};
+
+/// The accesses a mapping allows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Protection {
+    /// The closest Windows page protection, as used in the MemoryInfoList
+    pub fn memory_protection(self) -> MemoryProtection {
+        match (self.read, self.write, self.execute) {
+            (false, false, false) => MemoryProtection::PAGE_NOACCESS,
+            (false, false, true) => MemoryProtection::PAGE_EXECUTE,
+            (true, false, false) => MemoryProtection::PAGE_READONLY,
+            (true, false, true) => MemoryProtection::PAGE_EXECUTE_READ,
+            // No support for write-only
+            (true | false, true, false) => MemoryProtection::PAGE_READWRITE,
+            // No support for execute+write-only
+            (true | false, true, true) => MemoryProtection::PAGE_EXECUTE_READWRITE,
+        }
+    }
+}
+
+/// A range of the address space of a process, mapped with the same
+/// protection from the same file, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMapping {
+    pub range: Range<u64>,
+    pub protection: Protection,
+    /// Whether writes to the mapping are shared with the other processes
+    /// mapping the same memory, rather than copied on write
+    pub shared: bool,
+    /// The offset of the mapping in its backing file, if any
+    pub offset: u64,
+    /// The path of the file the mapping was mapped from
+    pub backing_file: Option<PathBuf>,
+    /// The name of a mapping which isn't backed by a file, if the system
+    /// gives one, e.g. `[stack]` or `[anon:scudo:primary]` on Linux
+    pub name: Option<String>,
+}
+
+impl MemoryMapping {
+    /// The MemoryInfoList entry describing the mapping
+    pub fn memory_info(&self) -> MDMemoryInfo {
+        let protection = self.protection.memory_protection().bits();
+        MDMemoryInfo {
+            base_address: self.range.start,
+            allocation_base: self.range.start,
+            allocation_protection: protection,
+            __alignment1: 0,
+            region_size: self.range.end - self.range.start,
+            state: MemoryState::MEM_COMMIT.bits(),
+            protection,
+            _type: if self.shared {
+                MemoryType::MEM_MAPPED
+            } else {
+                MemoryType::MEM_PRIVATE
+            }
+            .bits(),
+            __alignment2: 0,
+        }
+    }
+}
@@ -18,6 +18,32 @@ cfg_if::cfg_if! {
         pub(crate) const FP_REG_COUNT: usize = 32;
 
         pub type RawContextCPU = minidump_common::format::CONTEXT_ARM64_OLD;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        /// This is the number of general purpose registers, including the
+        /// zero register
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub(crate) const GP_REG_COUNT: usize = 32;
+        /// The number of floating point registers in the floating point save area
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub(crate) const FP_REG_COUNT: usize = 32;
+
+        /// minidump-common doesn't define a LoongArch64 context, so this one
+        /// follows the layout of the registers the kernel exposes via ptrace
+        #[derive(Debug, Default, Clone, scroll::Pwrite, scroll::SizeWith)]
+        #[allow(non_camel_case_types)]
+        pub struct CONTEXT_LOONGARCH64 {
+            /// No context flags are defined for LoongArch64, this is always 0
+            pub context_flags: u64,
+            /// `r0` to `r31`, `r3` being the stack pointer
+            pub iregs: [u64; 32],
+            pub pc: u64,
+            pub float_regs: [u64; 32],
+            pub fcc: u64,
+            pub fcsr: u32,
+            pub __padding: u32,
+        }
+
+        pub type RawContextCPU = CONTEXT_LOONGARCH64;
     } else if #[cfg(target_arch = "mips")] {
         compile_error!("flesh me out");
     } else {
@@ -5,11 +5,13 @@ pub use minidump_common::format::{
     MINIDUMP_HANDLE_DATA_STREAM as MDRawHandleDataStream,
     MINIDUMP_HANDLE_DESCRIPTOR as MDRawHandleDescriptor, MINIDUMP_HEADER as MDRawHeader,
     MINIDUMP_LOCATION_DESCRIPTOR as MDLocationDescriptor,
-    MINIDUMP_MEMORY_DESCRIPTOR as MDMemoryDescriptor, MINIDUMP_MEMORY_INFO as MDMemoryInfo,
+    MINIDUMP_MEMORY_DESCRIPTOR as MDMemoryDescriptor,
+    MINIDUMP_MEMORY_DESCRIPTOR64 as MDMemoryDescriptor64, MINIDUMP_MEMORY_INFO as MDMemoryInfo,
     MINIDUMP_MEMORY_INFO_LIST as MDMemoryInfoList, MINIDUMP_MODULE as MDRawModule,
     MINIDUMP_SIGNATURE as MD_HEADER_SIGNATURE, MINIDUMP_STREAM_TYPE as MDStreamType,
     MINIDUMP_SYSTEM_INFO as MDRawSystemInfo, MINIDUMP_THREAD as MDRawThread,
-    MINIDUMP_THREAD_NAME as MDRawThreadName, MINIDUMP_VERSION as MD_HEADER_VERSION,
+    MINIDUMP_THREAD_INFO as MDRawThreadInfo, MINIDUMP_THREAD_NAME as MDRawThreadName,
+    MINIDUMP_UNLOADED_MODULE as MDRawUnloadedModule, MINIDUMP_VERSION as MD_HEADER_VERSION,
     VS_FIXEDFILEINFO as MDVSFixedFileInfo,
 };
 
@@ -19,12 +21,402 @@ pub type MDRVA = u32;
 
 pub type MDRawThreadList = Vec<MDRawThread>;
 
+/// The header of the `Memory64ListStream`, followed by
+/// `number_of_memory_ranges` [`MDMemoryDescriptor64`]s. The memory of the
+/// ranges is stored contiguously from `base_rva`, in the order of the
+/// descriptors. minidump-common doesn't define it as it's variable-length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawMemory64List {
+    pub number_of_memory_ranges: u64,
+    pub base_rva: u64,
+}
+
+/// Stream types written by this crate which don't have a counterpart in
+/// [`MDStreamType`]. They are allocated from a range of its own, `0x4d570000`
+/// ("MW"), as the vendor ranges of Breakpad (`0x47670000`), Crashpad
+/// (`0x43500000`) and Mozilla (`0x4d7a0000`) are allocated by minidump-common,
+/// which may add streams there at any time.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MDExtraStreamType {
+    /// The raw contents of `/proc/<pid>/io`, the IO counters of the process.
+    ///
+    /// They aren't part of [`MDStreamType::MiscInfoStream`], as the layout of
+    /// `MINIDUMP_MISC_INFO` is fixed by Microsoft and has no room for them,
+    /// but are kept as text like the other `/proc` files, e.g.
+    /// [`MDStreamType::LinuxProcStatus`].
+    LinuxProcIo = 0x4d57_0001,
+    /// A [`MDRawThreadStackList`] associating each thread with the mapping
+    /// holding its stack
+    LinuxThreadStacks = 0x4d57_0002,
+    /// The raw contents of `/proc/meminfo`
+    LinuxMemInfo = 0x4d57_0003,
+    /// A [`MDRawDumpTimestamps`] structure
+    LinuxDumpTimestamps = 0x4d57_0004,
+    /// Text describing the Go build id and build information of the Go
+    /// binaries mapped in the process
+    LinuxGoBuildInfo = 0x4d57_0005,
+    /// Text listing the modules whose backing file was deleted or replaced
+    /// on disk after being mapped
+    LinuxReplacedModules = 0x4d57_0006,
+    /// A [`MDRawThreadStackList`] associating each thread using an x86 CET
+    /// shadow stack with its shadow stack pointer and the mapping holding it
+    LinuxShadowStacks = 0x4d57_0007,
+    /// A [`MDRawXStateHeader`] followed by the extended register state of
+    /// each thread
+    LinuxXState = 0x4d57_0008,
+    /// Text listing the problems which left the minidump incomplete, one per
+    /// line
+    LinuxSoftErrors = 0x4d57_0009,
+    /// Text listing the problems which left the minidump incomplete on macOS,
+    /// one per line, followed by the `kern_return_t` which caused them if any
+    MacSoftErrors = 0x4d57_000a,
+    /// Text holding a coarse rating of how exploitable the crash is, followed
+    /// by the indicators it's based on, one per line
+    LinuxExploitability = 0x4d57_000b,
+    /// Text listing the state each thread was waiting in in the kernel in
+    /// hang dumps, one per line, see [`crate::hang_dump`]
+    LinuxThreadWaitStates = 0x4d57_000c,
+    /// A list of [`MDRawThreadSignals`] holding the signals pending for and
+    /// blocked by each thread
+    LinuxThreadSignals = 0x4d57_000d,
+    /// Text listing the signals the process handles or ignores, one per line,
+    /// see [`crate::signal_dispositions`]
+    SignalDispositions = 0x4d57_000e,
+    /// A list of [`MDRawSecondaryException`]s recording the faults hit while
+    /// dumping the process
+    LinuxSecondaryExceptions = 0x4d57_000f,
+    /// A list of [`MDRawGpuInfo`] describing the GPUs used by the process, as
+    /// supplied by the application, see [`crate::gpu_info`]
+    GpuInfo = 0x4d57_0010,
+    /// UTF-8 text giving the reason of the crash, as supplied by the
+    /// application, see [`crate::crash_reason`]
+    CrashReason = 0x4d57_0011,
+    /// Text describing the writer which produced the minidump, its version,
+    /// options and the time each phase took, one per line
+    LinuxWriterInfo = 0x4d57_0012,
+    /// Text describing the Linux security module confining the process, its
+    /// name, the context of the process and the enforcement mode, one per
+    /// line
+    LinuxSecurityContext = 0x4d57_0013,
+    /// Text listing the inode numbers of the namespaces of the process, one
+    /// per line
+    LinuxNamespaces = 0x4d57_0014,
+    /// Text holding the identifiers of the machine and of its boot, one per
+    /// line, see [`crate::host_ids`]
+    HostIds = 0x4d57_0015,
+    /// Text listing the CPU each thread last ran on and the CPUs it may run
+    /// on, one thread per line
+    LinuxThreadCpus = 0x4d57_0016,
+    /// A list of [`MDRawThreadBacktrace`] holding the return addresses found
+    /// by following the frame pointers of each thread
+    LinuxBacktraces = 0x4d57_0017,
+    /// An [`MDRawMacSharedCache`] describing the dyld shared cache the process
+    /// uses
+    MacSharedCache = 0x4d57_0018,
+    /// Text holding the lines the application logged last, read from its
+    /// log ring buffers, see [`crate::log_ring_buffer`]
+    LogLines = 0x4d57_0019,
+    /// Text listing why each thread was stopped when it was suspended, and
+    /// the signals it was stopping for before that, one per line
+    LinuxThreadStops = 0x4d57_001a,
+    /// Text describing the usermode emulator the process runs under, the
+    /// host and emulated architectures and the emulated modules, one per
+    /// line, see [`crate::emulation`]
+    LinuxEmulation = 0x4d57_001b,
+    /// A list of [`MDRawRegisteredBlock`] holding the data blocks the process
+    /// registered, see [`crate::registry`]
+    LinuxRegisteredBlocks = 0x4d57_001c,
+    /// Text listing the thread-local storage registers of each thread, e.g.
+    /// the fs and gs bases on x86-64, one thread per line
+    LinuxThreadTlsRegisters = 0x4d57_001d,
+    /// Text summarizing the heap of the process, the brk range and the size
+    /// of the heap mappings, and its memory footprint, as totalled in
+    /// `/proc/<pid>/smaps_rollup`, one figure per line
+    LinuxHeapSummary = 0x4d57_001e,
+    /// Text listing the functions of the JIT-compiled code of the process,
+    /// grouped by the mapping holding them, see [`crate::jit_symbols`]
+    LinuxJitSymbols = 0x4d57_001f,
+}
+
+/// Associates a thread with the memory mapping its stack pointer falls in.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread names stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawThreadStack {
+    pub thread_id: u32,
+    pub __padding: u32,
+    /// The stack pointer of the thread at the time of the dump
+    pub stack_pointer: u64,
+    /// The start (lowest address) of the stack mapping
+    pub start_of_mapping: u64,
+    /// The size of the stack mapping, the distance between `stack_pointer` and
+    /// `start_of_mapping` is the stack space the thread had left
+    pub size_of_mapping: u64,
+}
+
+pub type MDRawThreadStackList = Vec<MDRawThreadStack>;
+
+/// A fault hit while reading the memory of the process during the dump, see
+/// `SecondaryFault`. The exception record holds `SIGSEGV` if the memory was
+/// gone, `SIGBUS` if it couldn't be read for another reason, the `errno` of
+/// the read as its flags, its address, and its length as its only parameter.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread stacks stream.
+#[derive(Debug, Clone, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawSecondaryException {
+    /// The thread of the exception in the ExceptionStream, which was being
+    /// dumped when the fault happened
+    pub primary_thread_id: u32,
+    /// The code of the exception in the ExceptionStream
+    pub primary_exception_code: u32,
+    pub exception_record: MDException,
+}
+
+/// The instruction pointer of a thread and the return addresses found by
+/// following its frame pointers, innermost first. This is a hint for tools
+/// which can't unwind the stack themselves, it's wrong wherever the code was
+/// built without frame pointers.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread stacks stream.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawThreadBacktrace {
+    pub thread_id: u32,
+    pub frame_count: u32,
+    /// The `frame_count` addresses, as `u64`s
+    pub frames: MDLocationDescriptor,
+}
+
+/// A data block the process registered for its dumps, whose name and
+/// contents are written separately, the name as UTF-8 without a terminator.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread stacks stream.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawRegisteredBlock {
+    /// How the contents should be read, a `BlockKind`
+    pub kind: u32,
+    pub __padding: u32,
+    /// The address the block was read from
+    pub address: u64,
+    pub name: MDLocationDescriptor,
+    pub data: MDLocationDescriptor,
+}
+
+/// The dyld shared cache a macOS process uses, which holds the system
+/// libraries. Symbolicating the frames which land in it takes the UUID of the
+/// cache, and its slide to map their addresses back to those of the cache
+/// file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawMacSharedCache {
+    pub uuid: [u8; 16],
+    /// The address the cache is loaded at
+    pub base_address: u64,
+    /// How far the cache was moved from its preferred address
+    pub slide: u64,
+}
+
+/// A GPU used by the process, see `crate::gpu_info`.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread stacks stream.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawGpuInfo {
+    /// The PCI vendor id, e.g. `0x10de` for NVIDIA
+    pub vendor_id: u32,
+    /// The PCI device id
+    pub device_id: u32,
+    /// The version of the driver, as an `MDString`
+    pub driver_version_rva: MDRVA,
+    /// The crash data provided by the driver, if any
+    pub crash_data: MDLocationDescriptor,
+}
+
+/// The signal sets of a thread, as read from `/proc/<pid>/task/<tid>/status`.
+/// Signal `n` is bit `n - 1` of each set.
+///
+/// The stream is laid out as a `u32` count followed by that many entries, like
+/// the thread stacks stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawThreadSignals {
+    pub thread_id: u32,
+    pub __padding: u32,
+    /// The signals sent to the thread which weren't delivered yet
+    pub pending: u64,
+    /// The signals sent to the whole process which weren't delivered yet, the
+    /// same for every thread
+    pub shared_pending: u64,
+    /// The signals the thread blocks
+    pub blocked: u64,
+    /// The signals the process ignores
+    pub ignored: u64,
+    /// The signals the process has a handler for
+    pub caught: u64,
+}
+
+/// The location of an XSAVE state component within an XSAVE area.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawXStateFeature {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Describes the layout of the x86 XSAVE areas holding the extended register
+/// state (AVX, AVX-512...) of the threads, it is followed by `thread_count`
+/// [`MDRawThreadXState`] entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawXStateHeader {
+    /// The state components enabled by the kernel, as in the XCR0 register
+    pub enabled_features: u64,
+    /// The size of the largest XSAVE area
+    pub xsave_size: u32,
+    pub thread_count: u32,
+    /// The location of each state component, as reported by CPUID leaf 0xD.
+    /// The entries of disabled components are zeroed.
+    pub features: [MDRawXStateFeature; 64],
+}
+
+/// The header of the `ThreadInfoListStream`, followed by `number_of_entries`
+/// [`MDRawThreadInfo`]s. minidump-common doesn't define it as it's
+/// variable-length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawThreadInfoList {
+    pub size_of_header: u32,
+    pub size_of_entry: u32,
+    pub number_of_entries: u32,
+}
+
+/// The information of the thread isn't available, e.g. because it exited
+pub const MD_THREAD_INFO_INVALID_INFO: u32 = 0x8;
+
+/// The header of the `UnloadedModuleListStream`, followed by
+/// `number_of_entries` [`MDRawUnloadedModule`]s. minidump-common doesn't
+/// define it as it's variable-length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawUnloadedModuleList {
+    pub size_of_header: u32,
+    pub size_of_entry: u32,
+    pub number_of_entries: u32,
+}
+
+/// The raw XSAVE area of a thread, in the standard (non-compacted) format.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawThreadXState {
+    pub thread_id: u32,
+    pub xsave: MDLocationDescriptor,
+}
+
+/// The time at which a dump was written, according to different clocks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawDumpTimestamps {
+    /// `CLOCK_REALTIME`, in nanoseconds since the Unix epoch
+    pub realtime_ns: u64,
+    /// `CLOCK_MONOTONIC`, in nanoseconds
+    pub monotonic_ns: u64,
+    /// `CLOCK_BOOTTIME`, in nanoseconds. Unlike `CLOCK_MONOTONIC` this includes
+    /// the time the system spent suspended
+    pub boottime_ns: u64,
+    /// The time the system booted at, in seconds since the Unix epoch
+    pub boot_time: u64,
+    /// The offset of `CLOCK_MONOTONIC` in the time namespace of the process
+    /// relative to ours, in nanoseconds. Adding it to `monotonic_ns` gives
+    /// the time the process would have read.
+    pub process_monotonic_offset_ns: i64,
+    /// The offset of `CLOCK_BOOTTIME` in the time namespace of the process
+    /// relative to ours, in nanoseconds
+    pub process_boottime_offset_ns: i64,
+}
+
+/// The Crashpad information stream, as written by Crashpad, see
+/// `minidump/minidump_extensions.h` there. Its strings are UTF-8, stored as a
+/// `u32` length followed by the nul-terminated bytes.
+#[derive(Debug, Default, Clone, Copy, scroll::Pread, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawCrashpadInfo {
+    /// [`MDRawCrashpadInfo::VERSION`]
+    pub version: u32,
+    /// The UUIDs Crashpad assigns to the report and to the client, which are
+    /// left zeroed
+    pub report_id: [u8; 16],
+    pub client_id: [u8; 16],
+    /// A `u32` count followed by as many
+    /// [`MDRawSimpleStringDictionaryEntry`], describing the process
+    pub simple_annotations: MDLocationDescriptor,
+    /// A `u32` count followed by as many [`MDRawModuleCrashpadInfoLink`]
+    pub module_list: MDLocationDescriptor,
+}
+
+impl MDRawCrashpadInfo {
+    pub const VERSION: u32 = 1;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawSimpleStringDictionaryEntry {
+    pub key: MDRVA,
+    pub value: MDRVA,
+}
+
+/// Associates a module of the module list with its Crashpad annotations.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawModuleCrashpadInfoLink {
+    pub minidump_module_list_index: u32,
+    /// A [`MDRawModuleCrashpadInfo`]
+    pub location: MDLocationDescriptor,
+}
+
+/// The annotations of a module linked with the Crashpad client.
+#[derive(Debug, Default, Clone, Copy, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawModuleCrashpadInfo {
+    /// [`MDRawModuleCrashpadInfo::VERSION`]
+    pub version: u32,
+    /// A `u32` count followed by as many RVAs of strings, from the older
+    /// crash reporter annotations, which are never written
+    pub list_annotations: MDLocationDescriptor,
+    /// A `u32` count followed by as many [`MDRawSimpleStringDictionaryEntry`]
+    pub simple_annotations: MDLocationDescriptor,
+    /// A `u32` count followed by as many [`MDRawAnnotation`]
+    pub annotation_objects: MDLocationDescriptor,
+}
+
+impl MDRawModuleCrashpadInfo {
+    pub const VERSION: u32 = 1;
+}
+
+/// A typed annotation, whose value is a `u32` length followed by the bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawAnnotation {
+    pub name: MDRVA,
+    /// 1 for strings, from 0x8001 for the application's own types
+    pub ty: u16,
+    pub _reserved: u16,
+    pub value: MDRVA,
+}
+
+/// Describes a failed assertion, as Breakpad writes it for invalid parameter
+/// and pure virtual call handlers on Windows. The strings are UTF-16 and
+/// nul-terminated, truncated to fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, scroll::Pwrite, scroll::SizeWith)]
+pub struct MDRawAssertionInfo {
+    pub expression: [u16; 128],
+    pub function: [u16; 128],
+    pub file: [u16; 128],
+    pub line: u32,
+    /// [`MDRawAssertionInfo::TYPE_UNKNOWN`] for assertions of the application
+    pub ty: u32,
+}
+
+impl MDRawAssertionInfo {
+    pub const TYPE_UNKNOWN: u32 = 0;
+}
+
 cfg_if::cfg_if! {
     if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
         pub use format::X86CpuInfo as MDCPUInformation;
     } else if #[cfg(any(target_arch = "arm", target_arch = "aarch64"))] {
         pub use format::ARMCpuInfo as MDCPUInformation;
-    } else if #[cfg(target_arch = "mips")] {
+    } else if #[cfg(any(target_arch = "mips", target_arch = "loongarch64"))] {
         pub struct MDCPUInformation {
             pub cpuid: [u64; 2],
             _padding: [u32; 2],
@@ -0,0 +1,38 @@
+//! Filtering and renaming the modules listed in minidumps, which works the
+//! same on Linux and macOS, see `MinidumpWriter::set_module_filter`.
+//!
+//! ```
+//! use minidump_writer::module_filter::ModuleAction;
+//!
+//! // Hide the user's home directory, and leave out a proprietary plugin
+//! fn filter(path: &str) -> ModuleAction {
+//!     if path.ends_with("/libsecret.so") {
+//!         ModuleAction::Omit
+//!     } else if let Some(rest) = path.strip_prefix("/home/") {
+//!         let rest = rest.split_once('/').map_or("", |(_, rest)| rest);
+//!         ModuleAction::Rename(format!("~/{rest}"))
+//!     } else {
+//!         ModuleAction::Keep
+//!     }
+//! }
+//!
+//! assert_eq!(
+//!     filter("/home/alice/bin/app"),
+//!     ModuleAction::Rename("~/bin/app".to_owned())
+//! );
+//! ```
+
+/// What to write to the module list for a module, given its path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleAction {
+    /// List it with its path
+    Keep,
+    /// List it with another path. Only the recorded path changes, the module
+    /// is still identified by its build id.
+    Rename(String),
+    /// Leave it out of the module list
+    Omit,
+}
+
+/// Decides what to write for each module, see [`ModuleAction`]
+pub type ModuleFilter = Box<dyn FnMut(&str) -> ModuleAction + Send + Sync>;
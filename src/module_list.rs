@@ -0,0 +1,151 @@
+//! Listing the modules loaded by a process, without writing a minidump.
+//!
+//! The modules are identified the same way they are in the module list of
+//! the minidumps this crate writes, so tools such as symbol pre-fetchers or
+//! inventory agents get the identifiers symbol servers expect:
+//!
+//! ```no_run
+//! # #[cfg(any(target_os = "linux", target_os = "android"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! for module in minidump_writer::module_list::list_modules(1234)? {
+//!     println!("{} {} {}", module.debug_id, module.code_id, module.path);
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(target_os = "macos")]
+//! # fn main() {}
+//! ```
+//!
+//! On Linux the process is stopped while its modules are read, like it is
+//! while it is dumped, and must be traceable by the caller.
+
+/// A module loaded by a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleInfo {
+    /// The path of the module, as the process sees it. On Linux it's named
+    /// after its `DT_SONAME` if it has one, as Breakpad symbol files are.
+    pub path: String,
+    /// The address the module is loaded at
+    pub base_address: u64,
+    /// The size of the module in memory
+    pub size: u64,
+    /// The identifier of the module file, the ELF build id or the Mach-O UUID
+    /// in lowercase hex
+    pub code_id: String,
+    /// The Breakpad debug identifier of the module, the GUID derived from
+    /// its code id followed by an age of 0, in uppercase hex
+    pub debug_id: String,
+    /// The version of the module, from its file name on Linux, e.g.
+    /// `6.0.30.0` for `libstdc++.so.6.0.30`, or from its load commands on macOS
+    pub version: Option<String>,
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        use crate::linux::{
+            auxv::AuxvDumpInfo,
+            errors::InitError,
+            minidump_writer::STOP_TIMEOUT,
+            module_reader::{BuildId, SoName},
+            ptrace_dumper::PtraceDumper,
+            sections::mappings,
+            Pid,
+        };
+
+        /// List the modules of process `pid`, the main executable first. The
+        /// mappings without a build id are left out, as they are from dumps.
+        pub fn list_modules(pid: Pid) -> Result<Vec<ModuleInfo>, InitError> {
+            let dumper = PtraceDumper::new(pid, STOP_TIMEOUT, AuxvDumpInfo::default())?;
+
+            let mut modules = Vec::new();
+            for map_idx in 0..dumper.mappings.len() {
+                let mapping = &dumper.mappings[map_idx];
+                if !mapping.is_interesting() || mapping.is_kernel_page() {
+                    continue;
+                }
+
+                let BuildId(identifier) = mappings::build_id(&dumper, map_idx);
+                if identifier.is_empty() || identifier.iter().all(|&x| x == 0) {
+                    continue;
+                }
+
+                let soname = dumper
+                    .from_process_memory_for_index(map_idx)
+                    .ok()
+                    .map(|SoName(n)| n);
+                let mapping = &dumper.mappings[map_idx];
+                let Ok((path, _, so_version)) =
+                    mapping.get_mapping_effective_path_name_and_version(soname)
+                else {
+                    continue;
+                };
+
+                modules.push(ModuleInfo {
+                    path: path.to_string_lossy().into_owned(),
+                    base_address: mapping.start_address as u64,
+                    size: mapping.size as u64,
+                    code_id: hex(&identifier),
+                    debug_id: elf_debug_id(&identifier),
+                    version: so_version.map(|sov| {
+                        format!("{}.{}.{}.{}", sov.major, sov.minor, sov.patch, sov.prerelease)
+                    }),
+                });
+            }
+            Ok(modules)
+        }
+
+        /// The debug identifier of an ELF module, which is its build id
+        /// truncated or padded to 16 bytes, read as a little-endian GUID.
+        fn elf_debug_id(build_id: &[u8]) -> String {
+            let mut guid = [0u8; 16];
+            let len = build_id.len().min(guid.len());
+            guid[..len].copy_from_slice(&build_id[..len]);
+            guid[..4].reverse();
+            guid[4..6].reverse();
+            guid[6..8].reverse();
+            debug_id(&guid)
+        }
+    } else if #[cfg(target_os = "macos")] {
+        use crate::mac::{
+            minidump_writer::MinidumpWriter,
+            task_dumper::{TaskDumpError, TaskDumper},
+        };
+        use mach2::mach_types::task_t;
+
+        /// List the modules of `task`, the main executable first.
+        pub fn list_modules(task: task_t) -> Result<Vec<ModuleInfo>, TaskDumpError> {
+            let dumper = TaskDumper::new(task);
+            MinidumpWriter::new(Some(task), None).list_modules(&dumper)
+        }
+    }
+}
+
+/// The lowercase hex representation of `bytes`
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The Breakpad debug identifier of the module with this GUID, and an age
+/// of 0. The GUID of a Mach-O module is its UUID.
+pub(crate) fn debug_id(guid: &[u8; 16]) -> String {
+    let mut debug_id = hex(guid).to_uppercase();
+    debug_id.push('0');
+    debug_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_elf_debug_id() {
+        let build_id: Vec<u8> = (0..20).collect();
+        assert_eq!(elf_debug_id(&build_id), "030201000504070608090A0B0C0D0E0F0");
+        // Short build ids are padded
+        assert_eq!(
+            elf_debug_id(&[0xab, 0xcd, 0xef, 0x01, 0x23]),
+            "01EFCDAB0023000000000000000000000"
+        );
+    }
+}
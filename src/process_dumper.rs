@@ -0,0 +1,38 @@
+//! An abstraction over the platform specific process dumpers.
+//!
+//! The streams which only need to read memory, list the threads and mappings
+//! of a process, or read the state of its threads can be written against
+//! [`ProcessDumper`] instead of a concrete dumper, so that they can be tested
+//! with a mock process rather than by spawning a real one.
+
+use crate::memory_map::MemoryMapping;
+use std::borrow::Cow;
+
+/// The operations every platform's dumper supports, see
+/// [`crate::ptrace_dumper::PtraceDumper`] on Linux and
+/// [`crate::task_dumper::TaskDumper`] on macOS.
+pub trait ProcessDumper {
+    /// A thread of the process
+    type Thread: Clone;
+    /// A mapping (or image on macOS) of the process
+    type Mapping: Clone;
+    /// The register state of a thread
+    type ThreadState;
+    type Error;
+
+    /// Read `length` bytes of the process' memory at `address`.
+    fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>, Self::Error>;
+
+    /// The threads of the process.
+    fn threads(&self) -> Result<Cow<'_, [Self::Thread]>, Self::Error>;
+
+    /// The mappings of the process.
+    fn mappings(&self) -> Result<Cow<'_, [Self::Mapping]>, Self::Error>;
+
+    /// The whole address space of the process, as the system lists it, see
+    /// [`crate::memory_map`].
+    fn memory_map(&self) -> Result<Vec<MemoryMapping>, Self::Error>;
+
+    /// Read the register state of `thread`.
+    fn thread_state(&self, thread: &Self::Thread) -> Result<Self::ThreadState, Self::Error>;
+}
@@ -0,0 +1,99 @@
+//! Reading the memory of another process, for embedders which need to look
+//! at it the way the writer does, e.g. to experiment with unwinding or to
+//! inspect heaps.
+//!
+//! [`ProcessMemoryReader`] is implemented by
+//! [`crate::mem_reader::MemReader`] on Linux, which reads with
+//! `process_vm_readv`, `/proc/<pid>/mem` or ptrace, whichever works first,
+//! and by [`crate::task_dumper::TaskDumper`] on macOS, which reads with
+//! `mach_vm_read`.
+//!
+//! ```no_run
+//! # #[cfg(any(target_os = "linux", target_os = "android"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use minidump_writer::{
+//!     capture_limits::CaptureLimits, mem_reader::MemReader,
+//!     process_memory::ProcessMemoryReader,
+//! };
+//!
+//! let mut reader = MemReader::new(1234);
+//! let bytes = reader.read_vec(0x7f00_0000_0000, 4096, &CaptureLimits::default())?;
+//! # Ok(())
+//! # }
+//! # #[cfg(target_os = "macos")]
+//! # fn main() {}
+//! ```
+
+use crate::capture_limits::{self, CaptureLimits, LimitExceeded};
+
+/// Why [`ProcessMemoryReader::read_vec`] failed
+#[derive(Debug, thiserror::Error)]
+pub enum ReadMemoryError<E> {
+    #[error(transparent)]
+    Read(E),
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitExceeded),
+}
+
+/// Reads the memory of a process
+pub trait ProcessMemoryReader {
+    type Error: std::error::Error;
+
+    /// Read the process' memory at `address` into `dst`, returning how many
+    /// bytes were read, which may be fewer than asked for if the end of the
+    /// readable memory was reached.
+    fn read_into(&mut self, address: usize, dst: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Read `length` bytes of the process' memory at `address`, or fewer if
+    /// the end of the readable memory was reached. Reads longer than
+    /// [`CaptureLimits::max_segment_size`] are refused, as the length is
+    /// usually read from the process, which may be corrupted.
+    fn read_vec(
+        &mut self,
+        address: usize,
+        length: usize,
+        limits: &CaptureLimits,
+    ) -> Result<Vec<u8>, ReadMemoryError<Self::Error>> {
+        capture_limits::check("read length", length as u64, limits.max_segment_size)?;
+        let mut bytes = vec![0; length];
+        if length > 0 {
+            let read = self
+                .read_into(address, &mut bytes)
+                .map_err(ReadMemoryError::Read)?;
+            bytes.truncate(read);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use crate::mem_reader::MemReader;
+
+    #[test]
+    fn test_read_vec() {
+        let data = b"some bytes of the process".to_vec();
+        let mut reader = MemReader::for_virtual_mem(std::process::id() as i32);
+        let limits = CaptureLimits::default();
+        assert_eq!(
+            reader
+                .read_vec(data.as_ptr() as usize, data.len(), &limits)
+                .unwrap(),
+            data
+        );
+        assert!(reader
+            .read_vec(data.as_ptr() as usize, 0, &limits)
+            .unwrap()
+            .is_empty());
+
+        let limits = CaptureLimits {
+            max_segment_size: 4,
+            ..limits
+        };
+        assert!(matches!(
+            reader.read_vec(data.as_ptr() as usize, data.len(), &limits),
+            Err(ReadMemoryError::LimitExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+//! Reusing the buffers the memory of the process is read into.
+//!
+//! Dumping reads the memory of the process many times, e.g. each thread's
+//! stack and each module's strings, and most of those reads are copied to
+//! the minidump right away. Allocating a new buffer for each of them makes
+//! allocations dominate the time taken by processes with many threads or
+//! modules, so the dumpers keep a [`ScratchPool`] of buffers instead.
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+
+/// The most buffers kept for reuse, reads are rarely nested deeper
+const MAX_POOLED: usize = 4;
+/// The largest buffer kept for reuse, so that a huge read doesn't keep its
+/// memory for the rest of the dump
+const MAX_POOLED_CAPACITY: usize = 1024 * 1024;
+
+/// The buffers available for reuse
+#[derive(Debug, Default)]
+pub(crate) struct ScratchPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl ScratchPool {
+    /// An empty buffer, which goes back to the pool when it's dropped
+    pub(crate) fn take(&self) -> ScratchBuffer<'_> {
+        let buffer = self
+            .buffers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop()
+            .unwrap_or_default();
+        ScratchBuffer { pool: self, buffer }
+    }
+
+    /// Put `buffer` in the pool once it's dropped, for reads which produce
+    /// a `Vec` of their own
+    pub(crate) fn adopt(&self, buffer: Vec<u8>) -> ScratchBuffer<'_> {
+        ScratchBuffer { pool: self, buffer }
+    }
+}
+
+/// A buffer taken from a [`ScratchPool`]
+#[derive(Debug)]
+pub(crate) struct ScratchBuffer<'pool> {
+    pool: &'pool ScratchPool,
+    buffer: Vec<u8>,
+}
+
+impl Deref for ScratchBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for ScratchBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for ScratchBuffer<'_> {
+    fn drop(&mut self) {
+        if self.buffer.capacity() == 0 || self.buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        let mut buffers = self.pool.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        if buffers.len() < MAX_POOLED {
+            let mut buffer = std::mem::take(&mut self.buffer);
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_pool() {
+        let pool = ScratchPool::default();
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[1; 100]);
+        let allocation = buffer.as_ptr();
+        drop(buffer);
+
+        // The buffer is reused, emptied
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), allocation);
+        // Buffers taken while it's in use are new
+        let other = pool.take();
+        assert_eq!(other.capacity(), 0);
+        drop(buffer);
+        drop(other);
+        assert_eq!(pool.buffers.lock().unwrap().len(), 1);
+
+        // Huge buffers aren't kept
+        drop(pool.adopt(vec![0; MAX_POOLED_CAPACITY + 1]));
+        assert_eq!(pool.buffers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+//! Recording how the process handles each signal, so that crash pipelines
+//! can tell when the crash handler was silently replaced, e.g. by a library
+//! installing its own `SIGSEGV` handler, or when crashes were ignored.
+//!
+//! On Linux the signals which are handled and ignored are read from
+//! `/proc/<pid>/status` when the process is dumped. The kernel doesn't expose
+//! the handlers themselves to other processes, so the application may
+//! provide its own table, e.g. collected with `sigaction` by its crash
+//! handler, which then replaces it. On macOS only the table provided by the
+//! application is written, see `MinidumpWriter::set_signal_dispositions`.
+//!
+//! The dispositions are written to a `SignalDispositions` text stream, one
+//! signal per line, see [`SignalDisposition`]. Signals with the default
+//! disposition are left out.
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{MemoryArrayWriter, MemoryWriterError},
+    minidump_format::{MDExtraStreamType, MDRawDirectory},
+};
+use std::fmt::{self, Write as _};
+
+/// What happens when a signal is delivered to the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// The default action, e.g. terminating the process
+    Default,
+    Ignored,
+    /// A handler installed by the process is called
+    Handled,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Ignored => "ignored",
+            Self::Handled => "handled",
+        }
+    }
+}
+
+/// How the process handles a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalDisposition {
+    pub signal: u32,
+    pub disposition: Disposition,
+    /// The address of the handler, when known
+    pub handler: Option<u64>,
+}
+
+impl SignalDisposition {
+    pub fn new(signal: u32, disposition: Disposition) -> Self {
+        Self {
+            signal,
+            disposition,
+            handler: None,
+        }
+    }
+
+    /// A signal handled by the function at `handler`
+    pub fn handled_by(signal: u32, handler: u64) -> Self {
+        Self {
+            signal,
+            disposition: Disposition::Handled,
+            handler: Some(handler),
+        }
+    }
+}
+
+impl fmt::Display for SignalDisposition {
+    /// The signal number and its disposition, followed by the address of the
+    /// handler in hexadecimal if it's known, separated by spaces.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.signal, self.disposition.as_str())?;
+        match self.handler {
+            Some(handler) => write!(f, " {handler:#x}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The dispositions of the signals in the `ignored` and `caught` sets of
+/// `/proc/<pid>/status`, signal `n` being bit `n - 1`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn from_signal_sets(ignored: u64, caught: u64) -> Vec<SignalDisposition> {
+    (1..=u64::BITS)
+        .filter_map(|signal| {
+            let bit = 1 << (signal - 1);
+            if caught & bit != 0 {
+                Some(SignalDisposition::new(signal, Disposition::Handled))
+            } else if ignored & bit != 0 {
+                Some(SignalDisposition::new(signal, Disposition::Ignored))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Write the `SignalDispositions` stream, leaving out the signals with the
+/// default disposition. Nothing is written if there are none left.
+pub(crate) fn write(
+    buffer: &mut DumpBuf,
+    dispositions: &[SignalDisposition],
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    let mut contents = String::new();
+    for disposition in dispositions {
+        if disposition.disposition != Disposition::Default {
+            // Writing to a String is infallible
+            let _ = writeln!(contents, "{disposition}");
+        }
+    }
+    if contents.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let section = MemoryArrayWriter::write_bytes(buffer, contents.as_bytes())?;
+    Ok(MDRawDirectory {
+        stream_type: MDExtraStreamType::SignalDispositions as u32,
+        location: section.location(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_dispositions() {
+        let mut buffer = DumpBuf::with_capacity(0);
+        let dirent = write(
+            &mut buffer,
+            &[
+                SignalDisposition::new(1, Disposition::Default),
+                SignalDisposition::new(13, Disposition::Ignored),
+                SignalDisposition::handled_by(11, 0x7f00_1234),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            dirent.stream_type,
+            MDExtraStreamType::SignalDispositions as u32
+        );
+        let rva = dirent.location.rva as usize;
+        assert_eq!(
+            &buffer[rva..rva + dirent.location.data_size as usize],
+            b"13 ignored\n11 handled 0x7f001234\n"
+        );
+
+        // Nothing is written if every signal has the default disposition
+        let dirent = write(&mut buffer, &[]).unwrap();
+        assert_eq!(dirent.location.data_size, 0);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_from_signal_sets() {
+        // SIGPIPE ignored, SIGSEGV and the last real-time signal handled
+        let dispositions = from_signal_sets(1 << 12, 1 << 10 | 1 << 63);
+        assert_eq!(
+            dispositions,
+            [
+                SignalDisposition::new(11, Disposition::Handled),
+                SignalDisposition::new(13, Disposition::Ignored),
+                SignalDisposition::new(64, Disposition::Handled),
+            ]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Instrumentation of the dump pipeline with `tracing`, which is compiled out
+//! unless the `tracing` feature is enabled.
+
+/// Enter an `INFO` span, see [`tracing::info_span!`]. The span is exited when
+/// the returned guard is dropped.
+macro_rules! span {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::info_span!($($arg)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Record an event at the given level, e.g. `event!(DEBUG, bytes = 10, "read")`,
+/// see [`tracing::event!`].
+macro_rules! event {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($arg)*);
+    }};
+}
+
+/// The guard returned by [`span!`] when tracing is disabled
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+// Like the real guard, so that it can be dropped explicitly to exit the span
+#[cfg(not(feature = "tracing"))]
+impl Drop for NoSpan {
+    fn drop(&mut self) {}
+}
@@ -0,0 +1,179 @@
+//! Recording the modules which were unloaded from the process, so that crashes
+//! in a plugin which was just unloaded, e.g. through a dangling callback,
+//! aren't left pointing at unmapped memory. Neither Linux nor macOS keep
+//! track of them, so the application reports each unload to an
+//! [`UnloadedModuleList`] as it happens, and the writer emits the list as an
+//! `UnloadedModuleListStream`, like Windows does, see
+//! `MinidumpWriter::set_unloaded_module_list` and
+//! [`MinidumpWriterBuilder::unloaded_module_list`].
+//!
+//! [`MinidumpWriterBuilder::unloaded_module_list`]: crate::builder::MinidumpWriterBuilder::unloaded_module_list
+//!
+//! ```
+//! use minidump_writer::unloaded_modules::{UnloadedModule, UnloadedModuleList};
+//!
+//! let unloaded_modules = UnloadedModuleList::new();
+//! // Kept by the application, e.g. in its `dlclose` wrapper
+//! let feed = unloaded_modules.clone();
+//! feed.module_unloaded(UnloadedModule::new("/usr/lib/libplugin.so", 0x7f00_0000_0000, 0x4000));
+//! assert_eq!(unloaded_modules.len(), 1);
+//! ```
+
+use crate::{
+    dir_section::DumpBuf,
+    mem_writer::{write_string_to_location, MemoryArrayWriter, MemoryWriter, MemoryWriterError},
+    minidump_format::{MDRawDirectory, MDRawUnloadedModule, MDRawUnloadedModuleList, MDStreamType},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// The number of unloads kept, the oldest ones are forgotten first. This is
+/// what Windows keeps.
+pub const MAX_UNLOADED_MODULES: usize = 64;
+
+/// A module which was unloaded from the process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnloadedModule {
+    /// The path of the module
+    pub name: String,
+    /// The address it was loaded at
+    pub base_address: u64,
+    pub size: u32,
+    /// Identifies the build of the module along with its size, 0 if unknown.
+    /// This is the checksum of the PE header on Windows.
+    pub checksum: u32,
+    /// When the module was unloaded, in seconds since the Unix epoch. On
+    /// Windows this is the link time of the module instead, which other
+    /// platforms have no equivalent of.
+    pub time_date_stamp: u32,
+}
+
+impl UnloadedModule {
+    /// A module unloaded just now, without a checksum
+    pub fn new(name: impl Into<String>, base_address: u64, size: u32) -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            name: name.into(),
+            base_address,
+            size,
+            checksum: 0,
+            time_date_stamp: now.as_secs() as u32,
+        }
+    }
+}
+
+/// The modules unloaded from the process, oldest first, fed by the
+/// application as it unloads them. Clones share the same list, so that it can
+/// be fed from any thread while the writer holds it.
+#[derive(Debug, Clone, Default)]
+pub struct UnloadedModuleList(Arc<Mutex<VecDeque<UnloadedModule>>>);
+
+impl UnloadedModuleList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `module` was unloaded, forgetting the oldest unload if
+    /// there are more than [`MAX_UNLOADED_MODULES`].
+    pub fn module_unloaded(&self, module: UnloadedModule) {
+        let mut modules = self.lock();
+        if modules.len() == MAX_UNLOADED_MODULES {
+            modules.pop_front();
+        }
+        modules.push_back(module);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// The unloaded modules, oldest first
+    pub fn modules(&self) -> Vec<UnloadedModule> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<UnloadedModule>> {
+        // The list is always consistent, even if a thread panicked holding it
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Write the `UnloadedModuleListStream`: a header followed by an entry per
+/// module, most recently unloaded first as on Windows. Nothing is written if
+/// no module was unloaded.
+// Windows writes the list it keeps itself
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+pub(crate) fn write(
+    buffer: &mut DumpBuf,
+    unloaded_modules: &UnloadedModuleList,
+) -> Result<MDRawDirectory, MemoryWriterError> {
+    let modules = unloaded_modules.modules();
+    if modules.is_empty() {
+        return Ok(MDRawDirectory::default());
+    }
+
+    let header = MemoryWriter::alloc_with_val(
+        buffer,
+        MDRawUnloadedModuleList {
+            size_of_header: std::mem::size_of::<MDRawUnloadedModuleList>() as u32,
+            size_of_entry: std::mem::size_of::<MDRawUnloadedModule>() as u32,
+            number_of_entries: modules.len() as u32,
+        },
+    )?;
+    let mut entries = MemoryArrayWriter::<MDRawUnloadedModule>::alloc_array(buffer, modules.len())?;
+    let mut location = header.location();
+    location.data_size += entries.location().data_size;
+
+    for (idx, module) in modules.iter().rev().enumerate() {
+        let name = write_string_to_location(buffer, &module.name)?;
+        entries.set_value_at(
+            buffer,
+            MDRawUnloadedModule {
+                base_of_image: module.base_address,
+                size_of_image: module.size,
+                checksum: module.checksum,
+                time_date_stamp: module.time_date_stamp,
+                module_name_rva: name.rva,
+            },
+            idx,
+        )?;
+    }
+
+    Ok(MDRawDirectory {
+        stream_type: MDStreamType::UnloadedModuleListStream as u32,
+        location,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unloaded_module_list() {
+        let list = UnloadedModuleList::new();
+        let feed = list.clone();
+        for idx in 0..MAX_UNLOADED_MODULES as u64 + 2 {
+            feed.module_unloaded(UnloadedModule::new("libplugin.so", idx * 0x1000, 0x1000));
+        }
+        let modules = list.modules();
+        assert_eq!(modules.len(), MAX_UNLOADED_MODULES);
+        assert_eq!(modules[0].base_address, 0x2000);
+
+        feed.clear();
+        assert!(list.is_empty());
+    }
+}
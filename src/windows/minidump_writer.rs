@@ -30,6 +30,12 @@ pub struct MinidumpWriter {
 }
 
 impl MinidumpWriter {
+    /// Returns a builder offering the same API on every platform, see
+    /// [`crate::builder`].
+    pub fn builder() -> crate::builder::MinidumpWriterBuilder {
+        crate::builder::MinidumpWriterBuilder::new()
+    }
+
     /// Creates a minidump of the current process, optionally including an
     /// exception code and the CPU context of the specified thread. If no thread
     /// is specified the current thread CPU context is used.
@@ -7,10 +7,18 @@ use minidump_writer::{
     app_memory::AppMemory,
     crash_context::CrashContext,
     errors::*,
+    log_ring_buffer::{LogRingBuffer, OffsetWidth},
     maps_reader::{MappingEntry, MappingInfo, SystemMappingInfo},
-    minidump_writer::MinidumpWriter,
+    memory_denylist::MemoryDenylist,
+    minidump_format::MDExtraStreamType,
+    minidump_writer::{
+        CancellationToken, DumpPhase, DumpProgress, FlushMode, MinidumpWriter, ModuleChecksum,
+        ThreadPolicy, TRUNCATED_ANNOTATION,
+    },
+    module_filter::ModuleAction,
     module_reader::{BuildId, ReadFromModule},
     ptrace_dumper::PtraceDumper,
+    unloaded_modules::{UnloadedModule, UnloadedModuleList},
     Pid,
 };
 use nix::{errno::Errno, sys::signal::Signal};
@@ -18,7 +26,7 @@ use procfs_core::process::MMPermissions;
 use std::collections::HashSet;
 
 use std::{
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
     os::unix::process::ExitStatusExt,
     process::{Command, Stdio},
 };
@@ -108,7 +116,7 @@ contextual_test! {
             .unwrap();
 
         let mut tmp = context.minidump_writer(pid);
-        let in_memory_buffer = tmp.dump(&mut tmpfile).expect("Could not write minidump");
+        let result = tmp.dump(&mut tmpfile).expect("Could not write minidump");
         child.kill().expect("Failed to kill process");
 
         // Reap child
@@ -121,9 +129,120 @@ contextual_test! {
         assert!(meta.len() > 0);
 
         let mem_slice = std::fs::read(tmpfile.path()).expect("Failed to minidump");
-        assert_eq!(mem_slice.len(), in_memory_buffer.len());
-        assert_eq!(mem_slice, in_memory_buffer);
+        assert_eq!(mem_slice.len() as u64, result.size);
+
+        // The result lists the streams of the directory
+        let dump = Minidump::read(mem_slice).expect("Failed to parse minidump");
+        let mut streams: Vec<_> = dump
+            .all_streams()
+            .filter(|stream| stream.stream_type != 0)
+            .map(|stream| (stream.stream_type, stream.location.rva, stream.location.data_size))
+            .collect();
+        let mut written: Vec<_> = result
+            .streams
+            .iter()
+            .map(|stream| (stream.stream_type, stream.rva, stream.size))
+            .collect();
+        streams.sort_unstable();
+        written.sort_unstable();
+        assert_eq!(streams, written);
+    }
+}
+
+contextual_test! {
+    fn write_dump_with_cgroup_freezer(context: Context) {
+        let num_of_threads = 3;
+        let mut child = start_child_and_wait_for_threads(num_of_threads);
+        let pid = child.id() as i32;
+
+        let mut tmpfile = tempfile::Builder::new()
+            .prefix("write_dump_with_cgroup_freezer")
+            .tempfile()
+            .unwrap();
+
+        // The child shares its cgroup with us, so this must fall back to
+        // SIGSTOP rather than freezing the test process too
+        let mut tmp = context.minidump_writer(pid);
+        tmp.use_cgroup_freezer();
+        tmp.dump(&mut tmpfile).expect("Could not write minidump");
+        child.kill().expect("Failed to kill process");
+
+        // Reap child
+        let waitres = child.wait().expect("Failed to wait for child");
+        let status = waitres.signal().expect("Child did not die due to signal");
+        assert_eq!(waitres.code(), None);
+        assert_eq!(status, Signal::SIGKILL as i32);
+
+        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+        let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+        assert_eq!(threads.threads.len(), num_of_threads);
+    }
+}
+
+/// Move `pid` to a new cgroup of its own below ours, so that it can be frozen
+/// without freezing the test too. Returns `None` if there's no cgroup v2
+/// hierarchy we can create cgroups in, as is the case on most CI runners and
+/// in containers, which don't delegate one to the tests.
+fn move_to_own_cgroup(pid: Pid) -> Option<std::path::PathBuf> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let ours = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let path = std::path::Path::new("/sys/fs/cgroup")
+        .join(ours.trim_start_matches('/'))
+        .join(format!("minidump-writer-test-{pid}"));
+    std::fs::create_dir(&path).ok()?;
+    if !path.join("cgroup.freeze").exists()
+        || std::fs::write(path.join("cgroup.procs"), pid.to_string()).is_err()
+    {
+        let _ = std::fs::remove_dir(&path);
+        return None;
     }
+    Some(path)
+}
+
+#[test]
+fn cgroup_freezer_freeze_and_thaw() {
+    use minidump_writer::cgroup_freezer::CgroupFreezer;
+
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let Some(cgroup) = move_to_own_cgroup(pid) else {
+        child.kill().expect("Failed to kill process");
+        child.wait().expect("Failed to wait for child");
+        eprintln!("no writable cgroup v2 hierarchy, skipping");
+        return;
+    };
+    let is_frozen = || {
+        std::fs::read_to_string(cgroup.join("cgroup.events"))
+            .expect("Failed to read cgroup.events")
+            .lines()
+            .any(|line| line == "frozen 1")
+    };
+
+    let freezer =
+        CgroupFreezer::freeze(pid, std::time::Duration::from_secs(1)).expect("Failed to freeze");
+    assert!(is_frozen());
+    freezer.thaw().expect("Failed to thaw");
+    assert!(!is_frozen());
+
+    // Dumping freezes the process rather than stopping it, and thaws it after
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("cgroup_freezer_freeze_and_thaw")
+        .tempfile()
+        .unwrap();
+    let mut tmp = MinidumpWriter::new(pid, pid);
+    tmp.use_cgroup_freezer();
+    tmp.dump(&mut tmpfile).expect("Could not write minidump");
+    assert!(!is_frozen());
+
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    std::fs::remove_dir(&cgroup).expect("Failed to remove the cgroup");
+
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    assert_eq!(threads.threads.len(), num_of_threads);
 }
 
 contextual_test! {
@@ -160,6 +279,7 @@ contextual_test! {
             offset: 0,
             permissions: MMPermissions::READ | MMPermissions::WRITE,
             name: Some("a fake mapping".into()),
+            deleted: false,
             system_mapping_info: SystemMappingInfo {
                 start_address: mmap_addr,
                 end_address: mmap_addr + memory_size,
@@ -231,6 +351,10 @@ contextual_test! {
         let _ = dump
             .get_raw_stream(MozLinuxLimits as u32)
             .expect("Couldn't find MozLinuxLimits");
+        let io = dump
+            .get_raw_stream(MDExtraStreamType::LinuxProcIo as u32)
+            .expect("Couldn't find LinuxProcIo");
+        assert!(std::str::from_utf8(io).unwrap().contains("rchar:"));
     }
 }
 
@@ -297,6 +421,51 @@ contextual_test! {
     }
 }
 
+#[test]
+fn write_with_additional_memory64() {
+    let mut child = start_child_and_return(&["spawn_alloc_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read address provided by child");
+    let mut output = buf.split_whitespace();
+    let memory_addr = usize::from_str_radix(output.next().unwrap().trim_start_matches("0x"), 16)
+        .expect("unable to parse mmap_addr");
+    let memory_size = output
+        .next()
+        .unwrap()
+        .parse()
+        .expect("unable to parse memory_size");
+
+    let app_memory = AppMemory {
+        ptr: memory_addr,
+        length: memory_size,
+    };
+
+    let in_memory = MinidumpWriter::new(pid, pid)
+        .set_app_memory64(vec![app_memory])
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(in_memory).expect("Failed to parse minidump");
+    let section: MinidumpMemory64List = dump
+        .get_stream()
+        .expect("Couldn't find MinidumpMemory64List");
+    let region = section
+        .memory_at_address(memory_addr as u64)
+        .expect("Couldn't find memory region");
+    assert_eq!(region.base_address, memory_addr as u64);
+    assert_eq!(region.size, memory_size as u64);
+
+    let values: Vec<u8> = (0..memory_size).map(|idx| (idx % 255) as u8).collect();
+    assert_eq!(region.bytes, values);
+}
+
 contextual_test! {
     fn skip_if_requested(context: Context) {
         let num_of_threads = 1;
@@ -391,12 +560,12 @@ contextual_test! {
 }
 
 contextual_test! {
-    fn write_early_abort(context: Context) {
+    fn write_unreadable_app_memory(context: Context) {
         let mut child = start_child_and_return(&["spawn_alloc_wait"]);
         let pid = child.id() as i32;
 
         let mut tmpfile = tempfile::Builder::new()
-            .prefix("additional_memory")
+            .prefix("unreadable_app_memory")
             .tempfile()
             .unwrap();
 
@@ -407,7 +576,7 @@ contextual_test! {
             .expect("Couldn't read address provided by child");
         let mut output = buf.split_whitespace();
         // We do not read the actual memory_address, but use NULL, which
-        // should create an error during dumping and lead to a truncated minidump.
+        // can't be read at all
         let _ = usize::from_str_radix(output.next().unwrap().trim_start_matches("0x"), 16)
             .expect("unable to parse mmap_addr");
         let memory_addr = 0;
@@ -424,11 +593,10 @@ contextual_test! {
 
         let mut tmp = context.minidump_writer(pid);
 
-        // This should fail, because during the dump an error is detected (try_from fails)
-        match tmp.set_app_memory(vec![app_memory]).dump(&mut tmpfile) {
-            Err(WriterError::SectionAppMemoryError(_)) => (),
-            _ => panic!("Wrong kind of error returned"),
-        }
+        // The region is left out rather than failing the dump
+        tmp.set_app_memory(vec![app_memory])
+            .dump(&mut tmpfile)
+            .expect("Could not write minidump");
 
         child.kill().expect("Failed to kill process");
         // Reap child
@@ -437,14 +605,22 @@ contextual_test! {
         assert_eq!(waitres.code(), None);
         assert_eq!(status, Signal::SIGKILL as i32);
 
-        // Read dump file and check its contents. There should be a truncated minidump available
         let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
-        // Should be there
         let _: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
-        let _: MinidumpModuleList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+        let _: MinidumpModuleList = dump.get_stream().expect("Couldn't find MinidumpModuleList");
 
-        // Should be missing:
-        assert!(dump.get_stream::<MinidumpMemoryList>().is_err());
+        let memory_list: MinidumpMemoryList = dump
+            .get_stream()
+            .expect("Couldn't find MinidumpMemoryList");
+        assert!(memory_list.memory_at_address(memory_addr as u64).is_none());
+
+        let soft_errors = dump
+            .get_raw_stream(MDExtraStreamType::LinuxSoftErrors as u32)
+            .expect("Couldn't find LinuxSoftErrors");
+        let soft_errors = std::str::from_utf8(soft_errors).expect("LinuxSoftErrors isn't UTF-8");
+        assert!(soft_errors.contains(&format!(
+            "{memory_size} unreadable bytes were left out of the memory region at 0x0"
+        )));
     }
 }
 
@@ -493,166 +669,2535 @@ contextual_test! {
     }
 }
 
-contextual_test! {
-    fn file_descriptors(context: Context) {
-        let num_of_files = 5;
-        let mut child = start_child_and_wait_for_create_files(num_of_files);
-        let pid = child.id() as i32;
-
-        let mut tmpfile = tempfile::Builder::new()
-            .prefix("testfiles")
-            .tempfile()
-            .unwrap();
+#[test]
+fn dump_to_file_and_sync() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
 
-        let mut tmp = context.minidump_writer(pid);
-        let _ = tmp.dump(&mut tmpfile).expect("Could not write minidump");
-        child.kill().expect("Failed to kill process");
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("dump_to_file_and_sync")
+        .tempfile()
+        .unwrap();
 
-        // Reap child
-        let waitres = child.wait().expect("Failed to wait for child");
-        let status = waitres.signal().expect("Child did not die due to signal");
-        assert_eq!(waitres.code(), None);
-        assert_eq!(status, Signal::SIGKILL as i32);
+    let result = MinidumpWriter::new(pid, pid)
+        .flush_mode(FlushMode::Sync)
+        .dump_to_file(tmpfile.as_file_mut())
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
 
-        // Read dump file and check its contents. There should be a truncated minidump available
-        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
-        let fds: MinidumpHandleDataStream = dump.get_stream().expect("Couldn't find MinidumpHandleDataStream");
-        // We check that we create num_of_files plus stdin, stdout and stderr
-        for i in 0..3 {
-            let descriptor = fds.handles.get(i).expect("Descriptor should be present");
-            let fd = *descriptor.raw.handle().expect("Handle should be populated");
-            assert_eq!(fd, i as u64);
-        }
+    let on_disk = std::fs::read(tmpfile.path()).expect("Failed to read minidump");
+    assert_eq!(result.size, on_disk.len() as u64);
+    Minidump::read(on_disk).expect("Failed to parse minidump");
+}
 
-        let non_std_files = &fds.handles[3..];
+#[test]
+fn dump_to_file_mapped() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
 
-        // We need to handle the android case where additional pipes might be opened and
-        // interspersed with the test_files (emulator? adb?) so that CI doesn't sporadically fail
-        for i in 0..num_of_files {
-            if !non_std_files.iter().any(|descriptor| {
-                let Some(name) = &descriptor.object_name else { return false; };
-                let Some(file_name) = name.rsplit_once('/').map(|(_, fname)| fname) else { return false; };
-                if !file_name.starts_with("test_file") {
-                    return false;
-                }
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("dump_to_file_mapped")
+        .tempfile()
+        .unwrap();
 
-                file_name.ends_with(&i.to_string())
-            }) {
-                panic!("unable to locate expected file `test_file{i}` in file handle stream");
-            }
-        }
-    }
+    let result = MinidumpWriter::new(pid, pid)
+        .full_memory()
+        .dump_to_file_mapped(tmpfile.as_file_mut())
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The file was truncated to the size of the minidump
+    let on_disk = std::fs::read(tmpfile.path()).expect("Failed to read minidump");
+    assert_eq!(result.size, on_disk.len() as u64);
+    let dump = Minidump::read(on_disk).expect("Failed to parse minidump");
+    let memory: MinidumpMemory64List = dump.get_stream().expect("Couldn't find memory64 list");
+    assert!(memory.iter().next().is_some());
 }
 
 #[test]
-fn minidump_size_limit() {
-    let num_of_threads = 40;
-    let mut child = start_child_and_wait_for_threads(num_of_threads);
+fn unbounded_budgets() {
+    let mut child = start_child_and_wait_for_threads(1);
     let pid = child.id() as i32;
 
-    let mut total_normal_stack_size = 0;
-    let normal_file_size;
-    // First, write a minidump with no size limit.
-    {
-        let mut tmpfile = tempfile::Builder::new()
-            .prefix("write_dump_unlimited")
-            .tempfile()
-            .unwrap();
-
-        MinidumpWriter::new(pid, pid)
-            .dump(&mut tmpfile)
-            .expect("Could not write minidump");
+    // The budgets only bound what is captured, they don't make the writer
+    // allocate that much upfront
+    let dump = MinidumpWriter::new(pid, pid)
+        .capture_referenced_memory(usize::MAX)
+        .capture_register_memory(usize::MAX)
+        .capture_module_images(usize::MAX)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
 
-        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
-        assert!(meta.len() > 0);
+    Minidump::read(dump).expect("Failed to parse minidump");
+}
 
-        normal_file_size = meta.len();
+#[test]
+fn minimal_dump() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
 
-        // Read dump file and check its contents
-        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
-        let thread_list: MinidumpThreadList =
-            dump.get_stream().expect("Couldn't find MinidumpThreadList");
-        for thread in thread_list.threads {
-            assert!(thread.raw.thread_id > 0);
-            assert!(thread.raw.stack.memory.data_size > 0);
-            total_normal_stack_size += thread.raw.stack.memory.data_size;
-        }
+    let full = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    let minimal = MinidumpWriter::new(pid, pid)
+        .minimal()
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    assert!(minimal.len() < full.len());
+
+    let full = Minidump::read(full).expect("Failed to parse minidump");
+    let dump = Minidump::read(minimal).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 3);
+    for thread in &threads.threads {
+        let has_stack = thread.raw.stack.memory.data_size > 0;
+        assert_eq!(has_stack, thread.raw.thread_id == pid as u32);
     }
 
-    // Second, write a minidump with a size limit big enough to not trigger
-    // anything.
-    {
-        // Set size limit arbitrarily 2MiB larger than the normal file size -- such
-        // that the limiting code will not kick in.
-        let minidump_size_limit = normal_file_size + 2 * 1024 * 1024;
+    let _: MinidumpSystemInfo = dump.get_stream().expect("Couldn't find system info");
+    let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+    let full_modules: MinidumpModuleList = full.get_stream().expect("Couldn't find module list");
+    assert!(modules.iter().count() > 0);
+    assert!(modules.iter().count() <= full_modules.iter().count());
+    assert!(dump.get_stream::<MinidumpMiscInfo>().is_err());
+    assert!(dump.get_raw_stream(LinuxMaps as u32).is_err());
+}
 
-        let mut tmpfile = tempfile::Builder::new()
-            .prefix("write_dump_pseudolimited")
-            .tempfile()
-            .unwrap();
+#[test]
+fn stream_selection() {
+    use minidump_writer::stream_selection::StreamSelection;
 
-        MinidumpWriter::new(pid, pid)
-            .set_minidump_size_limit(minidump_size_limit)
-            .dump(&mut tmpfile)
-            .expect("Could not write minidump");
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
 
-        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
+    let mut denied = StreamSelection::default();
+    denied
+        .deny(LinuxEnviron as u32)
+        .deny(MiscInfoStream as u32)
+        .deny(ThreadListStream as u32);
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_stream_selection(denied)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    let mut allowed = StreamSelection::default();
+    allowed.allow(ModuleListStream as u32);
+    let only_modules = MinidumpWriter::new(pid, pid)
+        .set_stream_selection(allowed)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    assert!(dump.get_raw_stream(LinuxEnviron as u32).is_err());
+    assert!(dump.get_stream::<MinidumpMiscInfo>().is_err());
+    assert!(dump.get_raw_stream(LinuxCmdLine as u32).is_ok());
+    // The minidump can't do without the threads
+    let _: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+
+    let dump = Minidump::read(only_modules).expect("Failed to parse minidump");
+    let mut streams: Vec<_> = dump
+        .all_streams()
+        .map(|stream| stream.stream_type)
+        .filter(|&stream_type| stream_type != 0)
+        .collect();
+    streams.sort_unstable();
+    let mut expected = vec![
+        ThreadListStream as u32,
+        ModuleListStream as u32,
+        MemoryListStream as u32,
+        ExceptionStream as u32,
+        SystemInfoStream as u32,
+    ];
+    expected.sort_unstable();
+    assert_eq!(streams, expected);
+}
 
-        // Make sure limiting wasn't actually triggered.  NOTE: If you fail this,
-        // first make sure that "minidump_size_limit" above is indeed set to a
-        // large enough value -- the limit-checking code in minidump_writer.rs
-        // does just a rough estimate.
-        // TODO: Fix this properly
-        //assert_eq!(meta.len(), normal_file_size);
-        let min = std::cmp::min(meta.len(), normal_file_size);
-        let max = std::cmp::max(meta.len(), normal_file_size);
+#[test]
+fn dump_without_crashing() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
 
-        // Setting a stack limit limits the size of non-main stacks even before
-        // the limit is reached. This will cause slight variations in size
-        // between a limited and an unlimited minidump.
-        assert!(max - min < 1024, "max = {max:} min = {min:}");
-    }
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("dump_without_crashing")
+        .tempfile()
+        .unwrap();
+    MinidumpWriter::dump_without_crashing(pid, &mut tmpfile).expect("Could not write minidump");
 
-    // Third, write a minidump with a size limit small enough to be triggered.
-    {
-        // Set size limit to some arbitrary amount, such that the limiting code
-        // will kick in.  The equation used to set this value was determined by
-        // simply reversing the size-limit logic a little bit in order to pick a
-        // size we know will trigger it.
+    // The process keeps running
+    assert!(child.try_wait().expect("Failed to check child").is_none());
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
 
-        // Copyied from sections/thread_list_stream.rs
-        const LIMIT_AVERAGE_THREAD_STACK_LENGTH: u64 = 8 * 1024;
-        let mut minidump_size_limit = LIMIT_AVERAGE_THREAD_STACK_LENGTH * 40;
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let exception: MinidumpException = dump.get_stream().expect("Couldn't find exception");
+    assert_eq!(exception.raw.thread_id, pid as u32);
+    assert_eq!(
+        exception.raw.exception_record.exception_code,
+        minidump_common::errors::ExceptionCodeLinux::DUMP_REQUESTED as u32
+    );
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 2);
+}
 
-        // If, in reality, each of the threads' stack is *smaller* than
-        // kLimitAverageThreadStackLength, the normal file size could very well be
-        // smaller than the arbitrary limit that was just set.  In that case,
-        // either of these numbers should trigger the size-limiting code, but we
-        // might as well pick the smallest.
-        if normal_file_size < minidump_size_limit {
-            minidump_size_limit = normal_file_size;
-        }
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn blamed_thread_context() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+    let tid = std::fs::read_dir(format!("/proc/{pid}/task"))
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .file_name()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap()
+        })
+        .find(|&tid: &Pid| tid != pid)
+        .expect("Couldn't find the second thread");
+
+    let context = minidump_writer::minidump_cpu::RawContextCPU {
+        rip: 0x1234_5678,
+        ..Default::default()
+    };
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_blamed_thread(tid)
+        .set_blamed_thread_context(context)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let exception: MinidumpException = dump.get_stream().expect("Couldn't find exception");
+    assert_eq!(exception.raw.thread_id, tid as u32);
+    assert_eq!(
+        exception.raw.exception_record.exception_address,
+        0x1234_5678
+    );
+
+    // The supplied registers are written for the blamed thread
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    let thread = threads.get_thread(tid as u32).unwrap();
+    assert_eq!(
+        thread.raw.thread_context.rva,
+        exception.raw.thread_context.rva
+    );
+    assert_eq!(
+        thread.raw.thread_context.data_size,
+        exception.raw.thread_context.data_size
+    );
+}
 
-        let mut tmpfile = tempfile::Builder::new()
-            .prefix("write_dump_limited")
-            .tempfile()
-            .unwrap();
+#[test]
+fn thread_filter() {
+    let mut child = start_child_and_wait_for_threads(4);
+    let pid = child.id() as i32;
+    let mut tids: Vec<Pid> = std::fs::read_dir(format!("/proc/{pid}/task"))
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .file_name()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap()
+        })
+        .filter(|&tid| tid != pid)
+        .collect();
+    tids.sort();
+    let (skipped, registers_only) = (tids[0], tids[1]);
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_thread_filter(move |tid, _name| {
+            assert_ne!(tid, pid);
+            if tid == skipped {
+                ThreadPolicy::Skip
+            } else if tid == registers_only {
+                ThreadPolicy::RegistersOnly
+            } else {
+                ThreadPolicy::FullStack
+            }
+        })
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 3);
+    assert!(threads.get_thread(skipped as u32).is_none());
+    let thread = threads.get_thread(registers_only as u32).unwrap();
+    assert_eq!(thread.raw.stack.memory.data_size, 0);
+    assert_ne!(thread.raw.thread_context.data_size, 0);
+    let thread = threads.get_thread(tids[2] as u32).unwrap();
+    assert_ne!(thread.raw.stack.memory.data_size, 0);
+}
 
-        MinidumpWriter::new(pid, pid)
-            .set_minidump_size_limit(minidump_size_limit)
-            .dump(&mut tmpfile)
+#[test]
+fn handler_threads() {
+    let mut child = start_child_and_wait_for_threads(4);
+    let pid = child.id() as i32;
+    let mut tids: Vec<Pid> = std::fs::read_dir(format!("/proc/{pid}/task"))
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .file_name()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap()
+        })
+        .filter(|&tid| tid != pid)
+        .collect();
+    tids.sort();
+    let handler = tids[0];
+
+    let write_dump = |policy| {
+        let dump = MinidumpWriter::new(pid, pid)
+            // The blamed thread can't be left out
+            .set_handler_threads(vec![handler, pid], policy)
+            .set_thread_filter(move |tid, _name| {
+                assert_ne!(tid, handler);
+                ThreadPolicy::FullStack
+            })
+            .dump_to_vec()
             .expect("Could not write minidump");
+        Minidump::read(dump).expect("Failed to parse minidump")
+    };
+    let skipped = write_dump(ThreadPolicy::Skip);
+    let registers_only = write_dump(ThreadPolicy::RegistersOnly);
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
 
-        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
-        assert!(meta.len() > 0);
-        // Make sure the file size is at least smaller than the original.  If this
-        // fails because it's the same size, then the size-limit logic didn't kick
-        // in like it was supposed to.
-        assert!(meta.len() < normal_file_size);
+    let threads: MinidumpThreadList = skipped.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 3);
+    assert!(threads.get_thread(handler as u32).is_none());
+    let thread = threads.get_thread(pid as u32).unwrap();
+    assert_ne!(thread.raw.stack.memory.data_size, 0);
 
-        let mut total_limit_stack_size = 0;
-        // Read dump file and check its contents
-        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = registers_only
+        .get_stream()
+        .expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 4);
+    let thread = threads.get_thread(handler as u32).unwrap();
+    assert_eq!(thread.raw.stack.memory.data_size, 0);
+    assert_ne!(thread.raw.thread_context.data_size, 0);
+}
+
+#[test]
+fn module_filter() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let exe = std::fs::read_link(format!("/proc/{pid}/exe")).unwrap();
+    let exe = exe.to_str().unwrap().to_owned();
+
+    let filtered_exe = exe.clone();
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_module_filter(move |path| {
+            if path.contains("libc.so") {
+                ModuleAction::Omit
+            } else if path == filtered_exe {
+                ModuleAction::Rename("/redacted/test".to_owned())
+            } else {
+                ModuleAction::Keep
+            }
+        })
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+    let names: Vec<_> = modules.iter().map(|module| module.code_file()).collect();
+    assert!(!names.iter().any(|name| name.contains("libc.so")));
+    assert!(!names.iter().any(|name| *name == exe));
+    assert!(names.iter().any(|name| name == "/redacted/test"));
+}
+
+#[test]
+fn module_checksums() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let exe = std::fs::read_link(format!("/proc/{pid}/exe")).unwrap();
+    let exe = exe.to_str().unwrap().to_owned();
+
+    let write_dump = |checksum| {
+        let dump = MinidumpWriter::new(pid, pid)
+            .set_module_checksum(checksum)
+            .dump_to_vec()
+            .expect("Could not write minidump");
+        Minidump::read(dump).expect("Failed to parse minidump")
+    };
+    let crc32 = write_dump(ModuleChecksum::Crc32);
+    // The checksum covers the headers, which start with the ELF magic
+    let magic = write_dump(ModuleChecksum::Custom(Box::new(|bytes| {
+        u32::from_le_bytes(bytes[..4].try_into().unwrap())
+    })));
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let checksum = |dump: &Minidump<'_, Vec<u8>>| {
+        let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+        let checksum = modules
+            .iter()
+            .find(|module| module.code_file() == exe)
+            .expect("Couldn't find the executable")
+            .raw
+            .checksum;
+        checksum
+    };
+    assert_ne!(checksum(&crc32), 0);
+    assert_eq!(checksum(&magic), u32::from_le_bytes(*b"\x7fELF"));
+}
+
+#[test]
+fn module_timestamps() {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let exe = std::fs::read_link(format!("/proc/{pid}/exe")).unwrap();
+    let mtime = std::fs::metadata(&exe).unwrap().mtime();
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+    let module = modules
+        .iter()
+        .find(|module| module.code_file() == exe.to_str().unwrap())
+        .expect("Couldn't find the executable");
+    assert_eq!(i64::from(module.raw.time_date_stamp), mtime);
+}
+
+#[test]
+fn worker_threads() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let write_dump = |worker_threads| {
+        let dump = MinidumpWriter::new(pid, pid)
+            .set_worker_threads(worker_threads)
+            .dump_to_vec()
+            .expect("Could not write minidump");
+        Minidump::read(dump).expect("Failed to parse minidump")
+    };
+    let sequential = write_dump(1);
+    let parallel = write_dump(4);
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The modules are the same, in the same order
+    let modules = |dump: &Minidump<'_, Vec<u8>>| {
+        let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+        modules
+            .iter()
+            .map(|module| (module.base_address(), module.debug_identifier()))
+            .collect::<Vec<_>>()
+    };
+    assert!(modules(&sequential).len() > 1);
+    assert_eq!(modules(&sequential), modules(&parallel));
+}
+
+#[test]
+fn unloaded_modules() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let unloaded_modules = UnloadedModuleList::new();
+    let mut writer = MinidumpWriter::new(pid, pid);
+    writer.set_unloaded_module_list(unloaded_modules.clone());
+    unloaded_modules.module_unloaded(UnloadedModule::new("/usr/lib/libfirst.so", 0x1000, 0x2000));
+    unloaded_modules.module_unloaded(UnloadedModule::new("/usr/lib/libsecond.so", 0x8000, 0x1000));
+    let dump = writer.dump_to_vec().expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let modules: MinidumpUnloadedModuleList = dump
+        .get_stream()
+        .expect("Couldn't find unloaded module list");
+    // The most recently unloaded module comes first
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|module| {
+            (
+                module.name.as_str(),
+                module.raw.base_of_image,
+                module.raw.size_of_image,
+            )
+        })
+        .collect();
+    assert_eq!(
+        modules,
+        [
+            ("/usr/lib/libsecond.so", 0x8000, 0x1000),
+            ("/usr/lib/libfirst.so", 0x1000, 0x2000)
+        ]
+    );
+}
+
+#[test]
+fn dump_limits() {
+    let mut child = start_child_and_wait_for_threads(4);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_max_full_stacks(1)
+        .set_max_modules(2)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 4);
+    // The blamed thread and one other have their full stack, the other two
+    // are truncated
+    let truncated = threads
+        .threads
+        .iter()
+        .filter(|thread| thread.raw.stack.memory.data_size <= 2048)
+        .count();
+    assert!(truncated >= 2);
+
+    let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find module list");
+    assert_eq!(modules.iter().count(), 2);
+}
+
+#[test]
+fn max_thread_stacks() {
+    let mut child = start_child_and_wait_for_threads(4);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_max_thread_stacks(1)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 4);
+    // The blamed thread and the most relevant other one have their stack,
+    // only the registers of the other two are written
+    let blamed = threads
+        .threads
+        .iter()
+        .find(|thread| thread.raw.thread_id == pid as u32)
+        .expect("Couldn't find the blamed thread");
+    assert_ne!(blamed.raw.stack.memory.data_size, 0);
+    let registers_only = threads
+        .threads
+        .iter()
+        .filter(|thread| thread.raw.stack.memory.data_size == 0)
+        .count();
+    assert_eq!(registers_only, 2);
+
+    let soft_errors = dump
+        .get_raw_stream(MDExtraStreamType::LinuxSoftErrors as u32)
+        .expect("Couldn't find LinuxSoftErrors");
+    let soft_errors = std::str::from_utf8(soft_errors).expect("LinuxSoftErrors isn't UTF-8");
+    assert!(soft_errors.contains("2 thread stacks were left out because of the thread stack limit"));
+}
+
+#[test]
+fn max_stack_bytes_per_thread() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let (crashing_limit, others_limit) = (8 * 1024, 1024);
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_max_stack_bytes_per_thread(crashing_limit, others_limit)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    let stacks = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadStacks as u32)
+        .expect("Couldn't find LinuxThreadStacks");
+    let read_u32 = |off: usize| u32::from_ne_bytes(stacks[off..off + 4].try_into().unwrap());
+    let read_u64 = |off: usize| u64::from_ne_bytes(stacks[off..off + 8].try_into().unwrap());
+
+    // Each stack ends at most the limit past its stack pointer
+    for idx in 0..read_u32(0) as usize {
+        let entry = 4 + idx * 32;
+        let thread_id = read_u32(entry);
+        let stack_pointer = read_u64(entry + 8);
+        let thread = threads.get_thread(thread_id).unwrap();
+        let stack_end =
+            thread.raw.stack.start_of_memory_range + thread.raw.stack.memory.data_size as u64;
+        let limit = if thread_id == pid as u32 {
+            crashing_limit
+        } else {
+            others_limit
+        };
+        assert!(stack_end <= stack_pointer + limit as u64);
+        assert!(stack_end > stack_pointer);
+    }
+}
+
+#[test]
+fn deterministic_dump() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .deterministic(|| std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000))
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    assert_eq!(dump.header.time_date_stamp, 1_000_000);
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    let tids: Vec<_> = threads.threads.iter().map(|t| t.raw.thread_id).collect();
+    let mut sorted = tids.clone();
+    sorted.sort_unstable();
+    assert_eq!(tids, sorted);
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn dump_with_digest() {
+    use sha2::Digest;
+
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("dump_with_digest")
+        .tempfile()
+        .unwrap();
+    let result = MinidumpWriter::new(pid, pid)
+        .dump_with_digest(&mut tmpfile)
+        .expect("Could not write minidump");
+    // Only dump_with_digest computes it
+    let without_digest = MinidumpWriter::new(pid, pid)
+        .dump(&mut std::io::Cursor::new(Vec::new()))
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let written = std::fs::read(tmpfile.path()).expect("Failed to read minidump");
+    assert_eq!(result.size, written.len() as u64);
+    assert_eq!(
+        result.sha256,
+        Some(<[u8; 32]>::from(sha2::Sha256::digest(&written)))
+    );
+    assert_eq!(without_digest.sha256, None);
+}
+
+#[test]
+fn crash_summary() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut writer = MinidumpWriter::new(pid, pid);
+    writer.set_annotation("channel", "nightly");
+    writer.dump_to_vec().expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let summary = writer.crash_summary.expect("No crash summary");
+    assert_eq!(summary.crash_reason, "DUMP_REQUESTED");
+    assert_eq!(summary.crashing_thread, pid);
+    assert!(summary.top_module.is_some());
+    assert!(summary.cpu_count > 0);
+
+    let json = summary.to_json();
+    assert!(json.starts_with(r#"{"crash_reason":"DUMP_REQUESTED","#));
+    assert!(json.ends_with(r#""annotations":{"channel":"nightly"}}"#));
+}
+
+#[test]
+fn crash_reason() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut writer = MinidumpWriter::new(pid, pid);
+    let dump = writer
+        .set_crash_reason("MOZ_CRASH(out of widgets)")
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let summary = writer.crash_summary.expect("No crash summary");
+    assert_eq!(
+        summary.crash_message.as_deref(),
+        Some("MOZ_CRASH(out of widgets)")
+    );
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let reason = dump
+        .get_raw_stream(MDExtraStreamType::CrashReason as u32)
+        .expect("Couldn't find CrashReason");
+    assert_eq!(reason, b"MOZ_CRASH(out of widgets)");
+}
+
+#[test]
+fn log_ring_buffer() {
+    let mut child = start_child_and_return(&["spawn_log_ring_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read addresses provided by child");
+    let output: Vec<_> = buf.split_whitespace().collect();
+    let parse_address = |field: &str| {
+        usize::from_str_radix(field.trim_start_matches("0x"), 16).expect("unable to parse address")
+    };
+    let ring_addr = parse_address(output[0]);
+    let ring_len = output[1].parse().expect("unable to parse len");
+    let head_addr = parse_address(output[2]);
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .register_log_ring_buffer(LogRingBuffer::new(
+            "test",
+            ring_addr,
+            ring_len,
+            head_addr,
+            OffsetWidth::U64,
+        ))
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let lines = dump
+        .get_raw_stream(MDExtraStreamType::LogLines as u32)
+        .expect("Couldn't find LogLines");
+    // The ring wrapped around and overwrote the first line
+    assert_eq!(lines, b"# test\ntwo\nthree\nfour\n");
+}
+
+#[test]
+fn registered_blocks() {
+    let mut child = start_child_and_return(&["spawn_registry_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read the address of the registry");
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump_array = dump.clone();
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let stream = dump
+        .get_raw_stream(MDExtraStreamType::LinuxRegisteredBlocks as u32)
+        .expect("Couldn't find RegisteredBlocks");
+    let read_u32 = |bytes: &[u8], offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    };
+    assert_eq!(read_u32(stream, 0), 1);
+    // The name and the contents of the block are stored outside of the stream
+    let contents = |offset: usize| {
+        let size = read_u32(stream, offset) as usize;
+        let rva = read_u32(stream, offset + 4) as usize;
+        &dump_array[rva..rva + size]
+    };
+    assert_eq!(contents(4 + 16), b"version");
+    assert_eq!(contents(4 + 24), b"1.2.3");
+}
+
+#[test]
+fn alternate_signal_stack() {
+    let mut child = start_child_and_return(&["spawn_altstack_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read the alternate stack provided by child");
+    let mut output = buf.split_whitespace();
+    let altstack_addr = usize::from_str_radix(output.next().unwrap().trim_start_matches("0x"), 16)
+        .expect("unable to parse the alternate stack address");
+    let altstack_size: usize = output
+        .next()
+        .unwrap()
+        .parse()
+        .expect("unable to parse the alternate stack size");
+
+    // The child is parked in a signal handler running on its alternate
+    // stack, as a crash handler would be
+    let mut context = get_crash_context(pid);
+    context.inner.context.uc_stack = crash_context::stack_t {
+        ss_sp: altstack_addr as *mut _,
+        ss_flags: 0,
+        ss_size: altstack_size,
+    };
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_crash_context(context)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let memory_list: MinidumpMemoryList =
+        dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+    for address in [altstack_addr, altstack_addr + altstack_size - 1] {
+        assert!(
+            memory_list.memory_at_address(address as u64).is_some(),
+            "the alternate stack isn't captured at {address:#x}"
+        );
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn shadow_stacks() {
+    let mut child = start_child_and_return(&["spawn_shadow_stack_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read the shadow stack status provided by child");
+    if buf.trim() != "enabled" {
+        child.kill().expect("Failed to kill process");
+        child.wait().expect("Failed to wait for child");
+        eprintln!("shadow stacks aren't supported on this machine, skipping");
+        return;
+    }
+
+    let write_dump = |max_stack_bytes| {
+        let mut writer = MinidumpWriter::new(pid, pid);
+        if let Some(max_stack_bytes) = max_stack_bytes {
+            writer.set_max_stack_bytes_per_thread(max_stack_bytes, max_stack_bytes);
+        }
+        let dump = writer.dump_to_vec().expect("Could not write minidump");
+        Minidump::read(dump).expect("Failed to parse minidump")
+    };
+    let full = write_dump(None);
+    let capped = write_dump(Some(16));
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let shadow_stack = |dump: &Minidump<'_, Vec<u8>>| {
+        let stacks = dump
+            .get_raw_stream(MDExtraStreamType::LinuxShadowStacks as u32)
+            .expect("Couldn't find LinuxShadowStacks");
+        // <count: u32><thread_id: u32, padding: u32, ssp: u64, start: u64, size: u64>...
+        let read_u32 = |off: usize| u32::from_ne_bytes(stacks[off..off + 4].try_into().unwrap());
+        let read_u64 = |off: usize| u64::from_ne_bytes(stacks[off..off + 8].try_into().unwrap());
+        assert_eq!(read_u32(0), 1);
+        assert_eq!(stacks.len(), 4 + 32);
+        assert_eq!(read_u32(4), pid as u32);
+        let (ssp, start, size) = (read_u64(12), read_u64(20), read_u64(28));
+        assert!(start <= ssp && ssp < start + size);
+
+        let memory_list: MinidumpMemoryList =
+            dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+        let memory = memory_list
+            .memory_at_address(ssp)
+            .expect("the shadow stack isn't captured");
+        assert_eq!(memory.base_address, ssp);
+        (memory.bytes.to_vec(), start + size - ssp)
+    };
+
+    // The part of the shadow stack in use is captured, it holds the return
+    // addresses of the active frames
+    let (bytes, in_use) = shadow_stack(&full);
+    assert_eq!(bytes.len() as u64, in_use);
+    let modules: MinidumpModuleList = full.get_stream().expect("Couldn't find module list");
+    assert!(bytes
+        .chunks_exact(8)
+        .map(|word| u64::from_ne_bytes(word.try_into().unwrap()))
+        .any(|address| modules.module_at_address(address).is_some()));
+
+    // It's capped like the regular stacks
+    let (capped_bytes, _) = shadow_stack(&capped);
+    assert_eq!(capped_bytes, bytes[..16]);
+}
+
+#[test]
+fn jit_symbols() {
+    let mut child = start_child_and_return(&["spawn_perf_map_wait"]);
+    let pid = child.id() as i32;
+
+    let mut f = BufReader::new(child.stdout.as_mut().expect("Can't open stdout"));
+    let mut buf = String::new();
+    let _ = f
+        .read_line(&mut buf)
+        .expect("Couldn't read the address of the JIT code");
+    let code = usize::from_str_radix(buf.trim().trim_start_matches("0x"), 16)
+        .expect("unable to parse the address of the JIT code");
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .capture_jit_symbols()
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    let _ = std::fs::remove_file(format!("/tmp/perf-{pid}.map"));
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let symbols = dump
+        .get_raw_stream(MDExtraStreamType::LinuxJitSymbols as u32)
+        .expect("Couldn't find LinuxJitSymbols");
+    let symbols = std::str::from_utf8(symbols).expect("LinuxJitSymbols isn't UTF-8");
+    let lines: Vec<_> = symbols.lines().collect();
+    assert_eq!(lines[0], format!("SOURCE /tmp/perf-{pid}.map"));
+    assert!(lines[1].starts_with(&format!("MODULE {code:#x} ")));
+    assert_eq!(lines[2], format!("FUNC {code:#x} 0x40 jitted_function"));
+    assert_eq!(
+        lines[3],
+        format!("FUNC {:#x} 0x20 other_function", code + 0x40)
+    );
+}
+
+#[test]
+fn writer_info() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_max_threads(5)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let info = dump
+        .get_raw_stream(MDExtraStreamType::LinuxWriterInfo as u32)
+        .expect("Couldn't find LinuxWriterInfo");
+    let info = std::str::from_utf8(info).expect("Writer info isn't UTF-8");
+    let lines: Vec<_> = info.lines().collect();
+    assert_eq!(lines[0], format!("version: {}", env!("CARGO_PKG_VERSION")));
+    assert_eq!(lines[1], "backend: ptrace");
+    assert!(lines[2].contains("max_threads: Some(5)"), "{}", lines[2]);
+    assert!(lines[3].starts_with("phase attach: "), "{}", lines[3]);
+    assert!(
+        lines[4].starts_with("phase write_streams: "),
+        "{}",
+        lines[4]
+    );
+}
+
+#[test]
+fn namespaces() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let namespaces = dump
+        .get_raw_stream(MDExtraStreamType::LinuxNamespaces as u32)
+        .expect("Couldn't find LinuxNamespaces");
+    let namespaces = std::str::from_utf8(namespaces).expect("Namespaces aren't UTF-8");
+    // The child shares our namespaces
+    let ours = std::fs::read_link("/proc/self/ns/net").expect("Couldn't read our namespace");
+    let ours = ours.to_string_lossy();
+    let inode = ours.trim_start_matches("net:[").trim_end_matches(']');
+    assert!(
+        namespaces
+            .lines()
+            .any(|line| line == format!("net: {inode}")),
+        "{namespaces}"
+    );
+}
+
+#[test]
+fn host_ids() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    let omitted = MinidumpWriter::new(pid, pid)
+        .set_host_ids(minidump_writer::host_ids::HostIds::Omitted)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let ids = dump
+        .get_raw_stream(MDExtraStreamType::HostIds as u32)
+        .expect("Couldn't find HostIds");
+    let ids = std::str::from_utf8(ids).expect("Host ids aren't UTF-8");
+    let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .expect("Couldn't read the boot id");
+    assert!(
+        ids.lines()
+            .any(|line| line == format!("boot_id: {}", boot_id.trim())),
+        "{ids}"
+    );
+
+    let omitted = Minidump::read(omitted).expect("Failed to parse minidump");
+    assert!(omitted
+        .get_raw_stream(MDExtraStreamType::HostIds as u32)
+        .is_err());
+}
+
+#[test]
+fn crashpad_annotations() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_annotation("channel", "nightly")
+        .set_annotation("version", "1.2.3")
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let info: MinidumpCrashpadInfo = dump.get_stream().expect("Couldn't find CrashpadInfo");
+    assert_eq!(info.raw.version, 1);
+    assert_eq!(
+        info.simple_annotations.get("channel").map(String::as_str),
+        Some("nightly")
+    );
+    assert_eq!(
+        info.simple_annotations.get("version").map(String::as_str),
+        Some("1.2.3")
+    );
+}
+
+#[test]
+fn process_tree() {
+    use minidump_writer::process_tree::{self, INCIDENT_ID_ANNOTATION};
+
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "sleep 30 & wait"])
+        .spawn()
+        .expect("failed to spawn shell");
+    let pid = child.id() as i32;
+    let descendants = loop {
+        let descendants = process_tree::descendants(pid).expect("failed to list descendants");
+        if !descendants.is_empty() {
+            break descendants;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+
+    let directory = tempfile::tempdir().unwrap();
+    let tree = MinidumpWriter::new(pid, pid)
+        .dump_process_tree(directory.path())
+        .expect("Could not list the process tree");
+    for pid in [pid].iter().chain(&descendants) {
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(*pid), nix::sys::signal::SIGKILL)
+            .expect("Failed to kill process");
+    }
+    child.wait().expect("Failed to wait for child");
+
+    assert_eq!(tree.dumps.len(), 2);
+    assert_eq!(tree.dumps[0].0, pid);
+    assert_eq!(tree.dumps[1].0, descendants[0]);
+    for (pid, dump) in &tree.dumps {
+        let path = dump.as_ref().expect("Could not write minidump");
+        let dump = Minidump::read_path(path).expect("Failed to read minidump");
+        let misc: MinidumpMiscInfo = dump.get_stream().expect("Couldn't find misc info");
+        assert_eq!(misc.raw.process_id(), Some(&(*pid as u32)));
+        let info: MinidumpCrashpadInfo = dump.get_stream().expect("Couldn't find CrashpadInfo");
+        assert_eq!(
+            info.simple_annotations.get(INCIDENT_ID_ANNOTATION),
+            Some(&tree.incident_id)
+        );
+    }
+}
+
+#[test]
+fn progress_callback() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::<DumpProgress>::new()));
+    let dump = {
+        let reports = reports.clone();
+        MinidumpWriter::new(pid, pid)
+            .on_progress(move |progress| reports.lock().unwrap().push(*progress))
+            .dump_to_vec()
+            .expect("Could not write minidump")
+    };
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let reports = reports.lock().unwrap();
+    let first = reports.first().expect("No progress reported");
+    assert_eq!(first.phase, DumpPhase::Attached);
+    assert_eq!(first.threads, 3);
+    let last = reports.last().unwrap();
+    assert_eq!(last.phase, DumpPhase::Finished);
+    assert_eq!(last.streams_written, last.stream_count);
+    assert_eq!(last.bytes_written, dump.len() as u64);
+
+    assert!(reports.iter().any(|progress| progress.phase
+        == DumpPhase::StreamWritten {
+            stream_type: ModuleListStream as u32
+        }));
+    // Progress only ever moves forward
+    assert!(reports.windows(2).all(|pair| {
+        pair[0].streams_written <= pair[1].streams_written
+            && pair[0].bytes_written <= pair[1].bytes_written
+    }));
+}
+
+#[test]
+fn cancelled_dump() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    // Cancel the dump once the thread list has been written
+    let token = CancellationToken::new();
+    let result = {
+        let token = token.clone();
+        MinidumpWriter::new(pid, pid)
+            .set_cancellation_token(token.clone())
+            .on_progress(move |progress| {
+                if progress.phase
+                    == (DumpPhase::StreamWritten {
+                        stream_type: ThreadListStream as u32,
+                    })
+                {
+                    token.cancel();
+                }
+            })
+            .dump_to_vec()
+    };
+    assert!(matches!(
+        result,
+        Err(WriterError::Cancelled(CancelledError::Cancelled))
+    ));
+    assert!(token.is_cancelled());
+
+    // The process is resumed and can be dumped again
+    let result = MinidumpWriter::new(pid, pid)
+        .set_deadline(std::time::Instant::now())
+        .dump_to_vec();
+    assert!(matches!(
+        result,
+        Err(WriterError::Cancelled(CancelledError::DeadlineExceeded))
+    ));
+    MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+}
+
+#[test]
+fn dump_timeout() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+
+    // Run out of time right after attaching, the next stream stops early and
+    // the minidump is finished with what was written so far
+    let timeout = std::time::Duration::from_millis(50);
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_timeout(timeout)
+        .set_annotation("product", "test")
+        .on_progress(move |progress| {
+            if progress.phase == DumpPhase::Attached {
+                std::thread::sleep(timeout * 2);
+            }
+        })
+        .dump_to_vec()
+        .expect("The truncated minidump should have been written");
+    let dump = Minidump::read(dump).expect("Failed to read minidump");
+    let _: MinidumpException = dump.get_stream().expect("Couldn't find the exception");
+    let _: MinidumpSystemInfo = dump.get_stream().expect("Couldn't find the system info");
+    assert!(dump.get_stream::<MinidumpModuleList>().is_err());
+    let crashpad_info: MinidumpCrashpadInfo = dump
+        .get_stream()
+        .expect("Couldn't find MinidumpCrashpadInfo");
+    assert_eq!(
+        crashpad_info.simple_annotations.get(TRUNCATED_ANNOTATION),
+        Some(&CancelledError::DeadlineExceeded.to_string())
+    );
+    assert_eq!(
+        crashpad_info.simple_annotations.get("product"),
+        Some(&"test".to_owned())
+    );
+    let soft_errors = dump
+        .get_raw_stream(MDExtraStreamType::LinuxSoftErrors as u32)
+        .expect("Couldn't find the soft errors");
+    assert!(String::from_utf8_lossy(soft_errors).contains("the remaining streams were left out"));
+
+    // The process is resumed, and dumps which take less time succeed
+    MinidumpWriter::new(pid, pid)
+        .set_timeout(std::time::Duration::from_secs(60))
+        .dump_to_vec()
+        .expect("Could not write minidump");
+
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+}
+
+#[test]
+fn referenced_memory() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+
+    let memory_size = |dump: Vec<u8>| -> u64 {
+        let dump = Minidump::read(dump).expect("Failed to read minidump");
+        let memory_list: MinidumpMemoryList =
+            dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+        memory_list.iter().map(|region| region.size).sum()
+    };
+    let plain = memory_size(
+        MinidumpWriter::new(pid, pid)
+            .dump_to_vec()
+            .expect("Could not write minidump"),
+    );
+
+    // The stacks hold pointers to data, e.g. to the heap and the stacks of
+    // other threads, and no more than the budget is captured around them
+    let budget = 16 * 1024;
+    let referenced = memory_size(
+        MinidumpWriter::new(pid, pid)
+            .capture_referenced_memory(budget)
+            .dump_to_vec()
+            .expect("Could not write minidump"),
+    );
+    assert!(referenced > plain);
+    assert!(referenced <= plain + budget as u64);
+
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+}
+
+#[test]
+fn memory_denylist() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    // Deny the stack of the main thread, which is captured both as a stack
+    // and with the full memory
+    let mut denylist = MemoryDenylist::default();
+    denylist.deny_mapping("[stack]");
+    let dump = MinidumpWriter::new(pid, pid)
+        .full_memory()
+        .set_memory_denylist(denylist)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    let maps = std::fs::read_to_string(format!("/proc/{pid}/maps")).expect("Failed to read maps");
+    let stack = maps
+        .lines()
+        .find(|line| line.ends_with("[stack]"))
+        .and_then(|line| line.split_once(' '))
+        .and_then(|(range, _)| range.split_once('-'))
+        .map(|(start, end)| {
+            u64::from_str_radix(start, 16).unwrap()..u64::from_str_radix(end, 16).unwrap()
+        })
+        .expect("Couldn't find the stack");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to read minidump");
+    let thread_list: MinidumpThreadList =
+        dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    let memory_list: MinidumpMemoryList =
+        dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+    let main_thread = thread_list
+        .get_thread(pid as u32)
+        .expect("Couldn't find the main thread");
+    let stack_memory = memory_list
+        .memory_at_address(main_thread.raw.stack.start_of_memory_range)
+        .expect("Couldn't find the stack memory");
+    assert!(stack_memory.bytes.iter().all(|&b| b == 0));
+
+    let memory64: MinidumpMemory64List = dump.get_stream().expect("Couldn't find memory64 list");
+    assert!(memory64.iter().next().is_some());
+    assert!(memory64
+        .iter()
+        .all(|region| region.base_address + region.size <= stack.start
+            || region.base_address >= stack.end));
+}
+
+#[test]
+fn module_images() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .capture_module_images(64 * 1024 * 1024)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    let maps = std::fs::read_to_string(format!("/proc/{pid}/maps")).expect("Failed to read maps");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The executable segments of the files mapped by the process
+    let segments: Vec<_> = maps
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields.next()?.split_once('-')?;
+            let perms = fields.next()?;
+            let path = fields.nth(3)?;
+            (perms.starts_with("r-x") && path.starts_with('/')).then(|| {
+                u64::from_str_radix(start, 16).unwrap()..u64::from_str_radix(end, 16).unwrap()
+            })
+        })
+        .collect();
+    assert!(!segments.is_empty());
+
+    let dump = Minidump::read(dump).expect("Failed to read minidump");
+    let memory64: MinidumpMemory64List = dump.get_stream().expect("Couldn't find memory64 list");
+    let regions: Vec<_> = memory64.iter().collect();
+    assert!(!regions.is_empty());
+    for region in regions {
+        let range = region.base_address..region.base_address + region.size;
+        assert!(segments.contains(&range));
+    }
+
+    // Nothing is copied unless asked to
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    let dump = Minidump::read(dump).expect("Failed to read minidump");
+    assert!(dump.get_stream::<MinidumpMemory64List>().is_err());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn thread_local_storage() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .capture_thread_local_storage()
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The thread pointer of each thread is recorded in place of its TEB, and
+    // the memory around it is captured
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    let memory: MinidumpMemoryList = dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+    for thread in &threads.threads {
+        assert_ne!(thread.raw.teb, 0);
+        assert!(memory.memory_at_address(thread.raw.teb).is_some());
+    }
+}
+
+#[test]
+fn thread_ip_memory() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .capture_thread_ip_memory(64)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The code around the instruction pointer of every thread is captured
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let system_info: MinidumpSystemInfo = dump.get_stream().expect("Couldn't find system info");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    let memory: MinidumpMemoryList = dump.get_stream().expect("Couldn't find MinidumpMemoryList");
+    for thread in &threads.threads {
+        let context = thread
+            .context(&system_info, None)
+            .expect("Couldn't read the thread context");
+        assert!(memory
+            .memory_at_address(context.get_instruction_pointer())
+            .is_some());
+    }
+}
+
+#[test]
+fn stack_red_zone() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+
+    let red_zone = 16 * 1024;
+    let dump = MinidumpWriter::new(pid, pid)
+        .set_stack_red_zone(red_zone)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // The threads are parked near the top of their stacks, the red zone
+    // below their stack pointers fits in the stack mappings
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let system_info: MinidumpSystemInfo = dump.get_stream().expect("Couldn't find system info");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    for thread in &threads.threads {
+        let context = thread
+            .context(&system_info, None)
+            .expect("Couldn't read the thread context");
+        let stack_pointer = context.get_stack_pointer();
+        let stack = &thread.raw.stack;
+        assert!(stack.start_of_memory_range <= stack_pointer - red_zone as u64);
+        assert!(stack.start_of_memory_range + stack.memory.data_size as u64 > stack_pointer);
+    }
+}
+
+#[test]
+fn microdump() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let product = minidump_writer::microdump::ProductInfo {
+        name: "Test".to_owned(),
+        version: "1.2.3".to_owned(),
+    };
+    let mut output = Vec::new();
+    MinidumpWriter::new(pid, pid)
+        .dump_microdump(&product, &mut output)
+        .expect("Could not write microdump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let output = String::from_utf8(output).expect("microdump isn't valid UTF-8");
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(
+        lines.first(),
+        Some(&minidump_writer::microdump::BEGIN_MARKER)
+    );
+    assert_eq!(lines.last(), Some(&minidump_writer::microdump::END_MARKER));
+    assert!(lines.contains(&"V Test:1.2.3"));
+    assert!(lines.iter().any(|line| line.starts_with("O L ")));
+    assert!(lines.iter().any(|line| line.starts_with("S 0 ")));
+    assert!(lines.iter().any(|line| line.starts_with("C ")));
+    assert!(lines.iter().any(|line| line.starts_with("M ")));
+}
+
+#[test]
+fn dump_to_pipe() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let (mut reader, mut writer) = std::os::unix::net::UnixStream::pair().unwrap();
+    let receiver = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).map(|_| received)
+    });
+
+    let result = MinidumpWriter::new(pid, pid)
+        .dump_to_writer(&mut writer)
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    drop(writer);
+
+    let received = receiver.join().unwrap().expect("Failed to read minidump");
+    assert_eq!(result.size, received.len() as u64);
+    Minidump::read(received).expect("Failed to parse minidump");
+}
+
+#[test]
+fn dump_to_socket() {
+    use minidump_writer::socket_writer::SocketWriter;
+    use std::os::fd::AsFd;
+
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let (mut reader, socket) = std::os::unix::net::UnixStream::pair().unwrap();
+    let receiver = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).map(|_| received)
+    });
+
+    let result = MinidumpWriter::new(pid, pid)
+        .dump_to_socket(SocketWriter::new(socket.as_fd()).set_chunk_size(4096))
+        .expect("Could not write minidump");
+    drop(socket);
+    let received = receiver.join().unwrap().expect("Failed to read minidump");
+    assert_eq!(result.size, received.len() as u64);
+
+    // Nothing is dumped once the peer is gone
+    let (reader, socket) = std::os::unix::net::UnixStream::pair().unwrap();
+    drop(reader);
+    assert!(MinidumpWriter::new(pid, pid)
+        .dump_to_socket(&mut SocketWriter::new(socket.as_fd()))
+        .is_err());
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+}
+
+#[test]
+fn user_streams() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let in_memory = MinidumpWriter::new(pid, pid)
+        .add_user_stream(0x4142_0000, b"feature flags")
+        .add_deferred_user_stream(0x4142_0001, |pid| pid.to_string().into_bytes())
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(in_memory).expect("Failed to parse minidump");
+    assert_eq!(
+        dump.get_raw_stream(0x4142_0000)
+            .expect("Couldn't find user stream"),
+        b"feature flags"
+    );
+    assert_eq!(
+        dump.get_raw_stream(0x4142_0001)
+            .expect("Couldn't find deferred user stream"),
+        pid.to_string().as_bytes()
+    );
+}
+
+#[test]
+fn stream_hooks() {
+    use minidump_writer::{
+        dir_section::DumpBuf, mem_writer::MemoryArrayWriter, minidump_format::MDRawDirectory,
+        minidump_writer::StreamHook,
+    };
+    use std::sync::{Arc, Mutex};
+
+    struct Hook(Arc<Mutex<Vec<u32>>>);
+
+    impl StreamHook for Hook {
+        fn before_write(
+            &mut self,
+            dirent: &mut MDRawDirectory,
+            buffer: &mut DumpBuf,
+            _dumper: &PtraceDumper,
+        ) -> bool {
+            if dirent.stream_type == LinuxCpuInfo as u32 {
+                return false;
+            }
+            if dirent.stream_type == LinuxCmdLine as u32 {
+                dirent.location = MemoryArrayWriter::write_bytes(buffer, b"redacted")
+                    .unwrap()
+                    .location();
+            }
+            true
+        }
+
+        fn after_write(
+            &mut self,
+            dirent: &MDRawDirectory,
+            _buffer: &DumpBuf,
+            _dumper: &PtraceDumper,
+        ) {
+            self.0.lock().unwrap().push(dirent.stream_type);
+        }
+    }
+
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let dump = MinidumpWriter::new(pid, pid)
+        .add_stream_hook(Hook(written.clone()))
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let written = written.lock().unwrap();
+    assert!(written.contains(&(ThreadListStream as u32)));
+    assert!(!written.contains(&(LinuxCpuInfo as u32)));
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    assert!(dump.get_raw_stream(LinuxCpuInfo as u32).is_err());
+    assert_eq!(
+        dump.get_raw_stream(LinuxCmdLine as u32)
+            .expect("Couldn't find LinuxCmdLine"),
+        b"redacted"
+    );
+}
+
+#[test]
+fn reused_writer() {
+    let mut first = start_child_and_wait_for_threads(2);
+    let mut second = start_child_and_wait_for_threads(1);
+    let first_pid = first.id() as i32;
+    let second_pid = second.id() as i32;
+
+    let mut writer = MinidumpWriter::new(first_pid, first_pid);
+    writer.set_annotation("process", "first");
+    let first_dump = writer.dump_to_vec().expect("Could not write minidump");
+    let first_blocks = writer.memory_blocks.len();
+
+    writer.reset(second_pid, second_pid);
+    assert!(writer.annotations.is_empty());
+    let second_dump = writer.dump_to_vec().expect("Could not write minidump");
+    for child in [&mut first, &mut second] {
+        child.kill().expect("Failed to kill process");
+        child.wait().expect("Failed to wait for child");
+    }
+
+    // Nothing is carried over from the first dump
+    let dump = Minidump::read(first_dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 2);
+    let dump = Minidump::read(second_dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 1);
+    assert_eq!(threads.threads[0].raw.thread_id, second_pid as u32);
+    assert!(writer.memory_blocks.len() < first_blocks);
+    let summary = writer.crash_summary.expect("No crash summary");
+    assert!(summary.annotations.is_empty());
+}
+
+#[test]
+fn dump_to_vec() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+
+    let in_memory = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(in_memory).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 2);
+}
+
+#[test]
+fn dump_to_memfd() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let fd = MinidumpWriter::new(pid, pid)
+        .dump_to_memfd()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let mut contents = Vec::new();
+    std::fs::File::from(fd)
+        .read_to_end(&mut contents)
+        .expect("Failed to read minidump");
+    let dump = Minidump::read(contents).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 1);
+}
+
+#[test]
+fn builder() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("builder")
+        .tempfile()
+        .unwrap();
+
+    let result = MinidumpWriter::builder()
+        .process(pid, pid)
+        .sanitize_stacks()
+        .write(tmpfile.as_file_mut());
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    result.expect("Could not write minidump");
+
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 1);
+
+    let mut tmpfile = tempfile::tempfile().unwrap();
+    assert!(matches!(
+        MinidumpWriter::builder().write(&mut tmpfile),
+        Err(WriterError::InitError(InitError::NoProcessToDump))
+    ));
+}
+
+#[test]
+fn builder_async() {
+    let mut children: Vec<_> = (0..2)
+        .map(|_| start_child_and_wait_for_threads(1))
+        .collect();
+    let dir = tempfile::tempdir().unwrap();
+
+    let dumps = children.iter().map(|child| {
+        let pid = child.id() as i32;
+        let file = std::fs::File::create(dir.path().join(format!("{pid}.dmp"))).unwrap();
+        MinidumpWriter::builder()
+            .process(pid, pid)
+            .write_async(file)
+    });
+    let results = futures::executor::block_on(futures::future::join_all(dumps));
+    for child in &mut children {
+        child.kill().expect("Failed to kill process");
+        child.wait().expect("Failed to wait for child");
+    }
+
+    for (child, result) in children.iter().zip(results) {
+        result.expect("Could not write minidump");
+        let dump = Minidump::read_path(dir.path().join(format!("{}.dmp", child.id())))
+            .expect("Failed to read minidump");
+        let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+        assert_eq!(threads.threads.len(), 1);
+    }
+}
+
+#[test]
+fn builder_to_cursor() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    let result = MinidumpWriter::builder()
+        .process(pid, pid)
+        .write(&mut cursor);
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    let result = result.expect("Could not write minidump");
+
+    let dump = cursor.into_inner();
+    assert_eq!(result.size, dump.len() as u64);
+    assert!(result.memory_bytes > 0);
+    let phases: Vec<_> = result.phases.iter().map(|(phase, _)| *phase).collect();
+    assert_eq!(phases, ["attach", "write_streams", "resume"]);
+    let thread_list = result
+        .streams
+        .iter()
+        .find(|stream| stream.stream_type == ThreadListStream as u32)
+        .expect("Couldn't find thread list");
+    assert!(thread_list.size > 0);
+    assert!(result.streams.iter().all(|stream| stream.stream_type != 0));
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 1);
+}
+
+#[test]
+fn cli() {
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cli.dmp");
+    let output = Command::new(env!("CARGO_BIN_EXE_minidump-writer"))
+        .arg(pid.to_string())
+        .args(["--max-threads", "1", "--output"])
+        .arg(&path)
+        .output()
+        .expect("Failed to run minidump-writer");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    assert!(output.status.success(), "{output:?}");
+
+    let dump = Minidump::read_path(&path).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find thread list");
+    assert_eq!(threads.threads.len(), 1);
+    assert_eq!(threads.threads[0].raw.thread_id, pid as u32);
+}
+
+#[cfg(feature = "verify")]
+#[test]
+fn verify_dump() {
+    use minidump_writer::verify::{verify_dump, Problem};
+
+    let mut child = start_child_and_wait_for_threads(2);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("verify_dump")
+        .tempfile()
+        .unwrap();
+    MinidumpWriter::new(pid, pid)
+        .dump_to_file(tmpfile.as_file_mut())
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let written = std::fs::read(tmpfile.path()).expect("Failed to read minidump");
+    let mut report = verify_dump(tmpfile.path()).expect("Failed to verify minidump");
+    report.compare_with_written(&written).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(report.threads, 2);
+    assert!(report.modules > 0);
+
+    // A truncated minidump is caught
+    let truncated = tmpfile.as_file().metadata().unwrap().len() - 1;
+    tmpfile.as_file().set_len(truncated).unwrap();
+    if let Ok(report) = verify_dump(tmpfile.path()) {
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| matches!(problem, Problem::StreamOutOfBounds(_))));
+    }
+}
+
+#[test]
+fn procfs_streams() {
+    let approximate_proc_start_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("procfs_streams")
+        .tempfile()
+        .unwrap();
+
+    let mut tmp = MinidumpWriter::new(pid, pid);
+    let _ = tmp.dump(&mut tmpfile).expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+
+    // Reap child
+    let waitres = child.wait().expect("Failed to wait for child");
+    let status = waitres.signal().expect("Child did not die due to signal");
+    assert_eq!(waitres.code(), None);
+    assert_eq!(status, Signal::SIGKILL as i32);
+
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let meminfo = dump
+        .get_raw_stream(MDExtraStreamType::LinuxMemInfo as u32)
+        .expect("Couldn't find LinuxMemInfo");
+    let meminfo = std::str::from_utf8(meminfo).unwrap();
+    assert!(meminfo.contains("MemTotal:"));
+    assert!(meminfo.contains("SwapFree:"));
+
+    let misc_info: MinidumpMiscInfo = dump.get_stream().expect("Couldn't find MinidumpMiscInfo");
+    // The extended register state adds the XSTATE configuration of
+    // MINIDUMP_MISC_INFO_5
+    assert!(matches!(
+        misc_info.raw,
+        RawMiscInfo::MiscInfo(_) | RawMiscInfo::MiscInfo5(_)
+    ));
+    assert_eq!(misc_info.raw.process_id(), Some(&(pid as u32)));
+    // Both the boot time and the process start time are truncated to seconds
+    let process_create_time = *misc_info.raw.process_create_time().unwrap() as u64;
+    assert!(
+        process_create_time + 2 >= approximate_proc_start_time
+            && process_create_time <= approximate_proc_start_time + 2
+    );
+
+    let timestamps = dump
+        .get_raw_stream(MDExtraStreamType::LinuxDumpTimestamps as u32)
+        .expect("Couldn't find LinuxDumpTimestamps");
+    assert_eq!(timestamps.len(), 48);
+    let read_u64 = |off: usize| u64::from_ne_bytes(timestamps[off..off + 8].try_into().unwrap());
+    let (realtime, monotonic, boottime, boot_time) =
+        (read_u64(0), read_u64(8), read_u64(16), read_u64(24));
+    assert!(realtime / 1_000_000_000 >= approximate_proc_start_time);
+    assert!(monotonic > 0 && boottime >= monotonic);
+    assert!(boot_time > 0 && boot_time <= approximate_proc_start_time);
+    // The child shares our time namespace
+    assert_eq!((read_u64(32), read_u64(40)), (0, 0));
+}
+
+#[test]
+fn thread_stacks() {
+    let num_of_threads = 5;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("thread_stacks")
+        .tempfile()
+        .unwrap();
+
+    // No crash context, so that every thread's stack is looked up from its own
+    // registers and gets an entry. The associations of a previous dump with
+    // the same writer must not be carried over.
+    let mut tmp = MinidumpWriter::new(pid, pid);
+    let _ = tmp.dump_to_vec().expect("Could not write minidump");
+    let _ = tmp.dump(&mut tmpfile).expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+
+    // Reap child
+    let waitres = child.wait().expect("Failed to wait for child");
+    let status = waitres.signal().expect("Child did not die due to signal");
+    assert_eq!(waitres.code(), None);
+    assert_eq!(status, Signal::SIGKILL as i32);
+
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    let stacks = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadStacks as u32)
+        .expect("Couldn't find LinuxThreadStacks");
+
+    // <count: u32><thread_id: u32, padding: u32, sp: u64, start: u64, size: u64>...
+    let read_u32 = |off: usize| u32::from_ne_bytes(stacks[off..off + 4].try_into().unwrap());
+    let read_u64 = |off: usize| u64::from_ne_bytes(stacks[off..off + 8].try_into().unwrap());
+    let count = read_u32(0) as usize;
+    assert_eq!(count, threads.threads.len());
+    assert_eq!(stacks.len(), 4 + count * 32);
+
+    for idx in 0..count {
+        let entry = 4 + idx * 32;
+        let thread_id = read_u32(entry);
+        let (start, size) = (read_u64(entry + 16), read_u64(entry + 24));
+        assert!(threads.threads.iter().any(|t| t.raw.thread_id == thread_id));
+        assert!(size > 0);
+
+        // The captured stack must lie within the stack mapping
+        let thread = threads.get_thread(thread_id).unwrap();
+        let stack_start = thread.raw.stack.start_of_memory_range;
+        let stack_end = stack_start + thread.raw.stack.memory.data_size as u64;
+        assert!(stack_start >= start && stack_end <= start + size);
+    }
+
+    // The stacks of the threads other than the main one are labeled with
+    // their thread id in the mappings, like the kernel's [stack] for the
+    // main thread
+    let maps = dump
+        .get_raw_stream(LinuxMaps as u32)
+        .expect("Couldn't find LinuxMaps");
+    let maps = std::str::from_utf8(maps).expect("LinuxMaps isn't UTF-8");
+    for thread in threads
+        .threads
+        .iter()
+        .filter(|t| t.raw.thread_id != pid as u32)
+    {
+        let label = format!("[stack:{}]", thread.raw.thread_id);
+        assert_eq!(maps.matches(&label).count(), 1, "{label} not found");
+    }
+}
+
+#[test]
+fn thread_info_list() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    let infos: MinidumpThreadInfoList = dump
+        .get_stream()
+        .expect("Couldn't find MinidumpThreadInfoList");
+    assert_eq!(infos.thread_infos.len(), threads.threads.len());
+    for thread in &threads.threads {
+        let info = infos
+            .get_thread_info(thread.raw.thread_id)
+            .expect("Missing thread info");
+        assert_eq!(info.raw.dump_flags, 0);
+        // Started after 2020, the creation time being a FILETIME
+        assert!(info.raw.create_time > 132_223_104_000_000_000);
+        assert_ne!(info.raw.affinity, 0);
+    }
+}
+
+#[test]
+fn thread_signals() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let signals = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadSignals as u32)
+        .expect("Couldn't find LinuxThreadSignals");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    // A count followed by 48 byte entries starting with the thread id
+    let count = u32::from_le_bytes(signals[..4].try_into().unwrap()) as usize;
+    assert_eq!(count, threads.threads.len());
+    assert_eq!(signals.len(), 4 + count * 48);
+    for entry in signals[4..].chunks_exact(48) {
+        let tid = u32::from_le_bytes(entry[..4].try_into().unwrap());
+        assert!(threads.get_thread(tid).is_some());
+    }
+}
+
+#[test]
+fn native_process_is_not_emulated() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    // All the modules are built for the host, and listed
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    assert!(dump
+        .get_raw_stream(MDExtraStreamType::LinuxEmulation as u32)
+        .is_err());
+    let modules: MinidumpModuleList = dump.get_stream().expect("Couldn't find ModuleList");
+    assert!(modules.main_module().is_some());
+}
+
+#[test]
+fn thread_stops() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let stops = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadStops as u32)
+        .expect("Couldn't find LinuxThreadStops");
+    let stops = std::str::from_utf8(stops).expect("LinuxThreadStops isn't UTF-8");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    // Every thread was stopped, either in a group-stop or by the SIGSTOP of
+    // the attach
+    for thread in &threads.threads {
+        let prefix = format!("{} stop ", thread.raw.thread_id);
+        let line = stops
+            .lines()
+            .find(|line| line.starts_with(&prefix))
+            .expect("Missing thread stop");
+        let reason = &line[prefix.len()..];
+        assert!(reason == "group_stop" || reason.starts_with("signal=SIGSTOP "));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn thread_tls_registers() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let registers = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadTlsRegisters as u32)
+        .expect("Couldn't find LinuxThreadTlsRegisters");
+    let registers = std::str::from_utf8(registers).expect("LinuxThreadTlsRegisters isn't UTF-8");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    // The fs base is the thread pointer, which the thread list holds as well
+    for thread in &threads.threads {
+        let prefix = format!("{} ", thread.raw.thread_id);
+        let line = registers
+            .lines()
+            .find(|line| line.starts_with(&prefix))
+            .expect("Missing thread registers");
+        assert_ne!(thread.raw.teb, 0);
+        assert!(line.contains(&format!(" fs_base={:#x} ", thread.raw.teb)));
+        assert!(line.contains(" gs_base="));
+    }
+}
+
+#[test]
+fn heap_summary() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let summary = dump
+        .get_raw_stream(MDExtraStreamType::LinuxHeapSummary as u32)
+        .expect("Couldn't find LinuxHeapSummary");
+    let summary = std::str::from_utf8(summary).expect("LinuxHeapSummary isn't UTF-8");
+    let value = |key: &str| {
+        summary
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .unwrap_or_else(|| panic!("Missing {key}"))
+    };
+    let brk_start = u64::from_str_radix(value("brk_start").trim_start_matches("0x"), 16).unwrap();
+    let brk_end = u64::from_str_radix(value("brk_end").trim_start_matches("0x"), 16).unwrap();
+    assert!(brk_start <= brk_end);
+    value("heap_mappings").parse::<usize>().unwrap();
+    value("heap_size").parse::<u64>().unwrap();
+    assert!(value("rss_anon").parse::<u64>().unwrap() > 0);
+    // The memory footprint, from smaps_rollup
+    let rss: u64 = value("rss").parse().unwrap();
+    let pss: u64 = value("pss").parse().unwrap();
+    assert!(pss > 0 && pss <= rss);
+    value("private_dirty").parse::<u64>().unwrap();
+    value("swap").parse::<u64>().unwrap();
+}
+
+#[test]
+fn backtraces() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let bytes = MinidumpWriter::new(pid, pid)
+        .capture_backtraces(8)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(bytes.as_slice()).expect("Failed to parse minidump");
+    let backtraces = dump
+        .get_raw_stream(MDExtraStreamType::LinuxBacktraces as u32)
+        .expect("Couldn't find LinuxBacktraces");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    // A count followed by 16 byte entries: the thread id, the number of
+    // frames and the location of the frames
+    let read_u32 = |bytes: &[u8], offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    };
+    let count = read_u32(backtraces, 0);
+    assert_eq!(count, threads.threads.len());
+    assert_eq!(backtraces.len(), 4 + count * 16);
+    for entry in backtraces[4..].chunks_exact(16) {
+        assert!(threads.get_thread(read_u32(entry, 0) as u32).is_some());
+        let frame_count = read_u32(entry, 4);
+        assert!((1..=8).contains(&frame_count));
+        assert_eq!(read_u32(entry, 8), frame_count * 8);
+        // The first frame is the instruction pointer
+        let rva = read_u32(entry, 12);
+        assert_ne!(
+            u64::from_le_bytes(bytes[rva..rva + 8].try_into().unwrap()),
+            0
+        );
+    }
+
+    // Nothing is recorded unless asked to
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    assert!(dump
+        .get_raw_stream(MDExtraStreamType::LinuxBacktraces as u32)
+        .is_err());
+}
+
+#[test]
+fn signal_dispositions() {
+    use minidump_writer::signal_dispositions::SignalDisposition;
+
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let mut writer = MinidumpWriter::new(pid, pid);
+    let dump = writer.dump_to_vec().expect("Could not write minidump");
+    let supplied_dump = writer
+        .set_signal_dispositions(vec![SignalDisposition::handled_by(
+            libc::SIGSEGV as u32,
+            0x1234,
+        )])
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let read_dispositions = |dump: Vec<u8>| {
+        let dump = Minidump::read(dump).expect("Failed to parse minidump");
+        let dispositions = dump
+            .get_raw_stream(MDExtraStreamType::SignalDispositions as u32)
+            .expect("Couldn't find SignalDispositions");
+        String::from_utf8(dispositions.to_vec()).expect("Invalid dispositions")
+    };
+    // The Rust runtime handles SIGSEGV to report stack overflows, and
+    // ignores SIGPIPE
+    let dispositions = read_dispositions(dump);
+    assert!(dispositions.lines().any(|line| line == "11 handled"));
+    assert!(dispositions.lines().any(|line| line == "13 ignored"));
+
+    assert_eq!(read_dispositions(supplied_dump), "11 handled 0x1234\n");
+}
+
+#[test]
+fn hang_dump() {
+    use minidump_writer::hang_dump::{Hang, HANG_ANNOTATION, HANG_DURATION_ANNOTATION};
+
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let mut writer = MinidumpWriter::new(pid, pid);
+    let hang = Hang {
+        reason: "main thread event loop".to_owned(),
+        unresponsive_for: std::time::Duration::from_secs(5),
+    };
+    let mut dump = std::io::Cursor::new(Vec::new());
+    writer
+        .dump_hang(&hang, &mut dump)
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+    // The writer is left as it was
+    assert!(writer.annotations.is_empty());
+    assert!(writer.thread_wait_states.is_empty());
+
+    let dump = Minidump::read(dump.into_inner()).expect("Failed to parse minidump");
+    let exception: MinidumpException = dump.get_stream().expect("Couldn't find MinidumpException");
+    assert_eq!(
+        exception.raw.exception_record.exception_code,
+        minidump_common::errors::ExceptionCodeLinux::DUMP_REQUESTED as u32
+    );
+    let info: MinidumpCrashpadInfo = dump.get_stream().expect("Couldn't find CrashpadInfo");
+    assert_eq!(
+        info.simple_annotations
+            .get(HANG_ANNOTATION)
+            .map(String::as_str),
+        Some("main thread event loop")
+    );
+    assert_eq!(
+        info.simple_annotations
+            .get(HANG_DURATION_ANNOTATION)
+            .map(String::as_str),
+        Some("5000")
+    );
+
+    // A line per thread, the threads of the child being asleep
+    let states = dump
+        .get_raw_stream(MDExtraStreamType::LinuxThreadWaitStates as u32)
+        .expect("Couldn't find LinuxThreadWaitStates");
+    let states = std::str::from_utf8(states).expect("Invalid wait states");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    assert_eq!(states.lines().count(), threads.threads.len());
+    for line in states.lines() {
+        let mut fields = line.split(' ');
+        let tid: u32 = fields.next().unwrap().parse().expect("Invalid thread id");
+        assert!(threads.get_thread(tid).is_some());
+        assert_eq!(fields.next(), Some("S"));
+    }
+}
+
+#[cfg(feature = "panic-dump")]
+#[test]
+fn panic_dump() {
+    use minidump_writer::panic_dump::{
+        install, PANIC_ANNOTATION, PANIC_EXCEPTION_CODE, PANIC_LOCATION_ANNOTATION,
+    };
+
+    let directory = tempfile::tempdir().expect("Failed to create directory");
+    install(directory.path(), |writer| {
+        writer.set_annotation("channel", "nightly");
+    });
+    let (tx, rx) = std::sync::mpsc::channel();
+    let panicking = std::thread::spawn(move || {
+        tx.send(nix::unistd::gettid().as_raw() as u32).unwrap();
+        panic!("out of {}", "widgets");
+    });
+    assert!(panicking.join().is_err());
+    // Restore the default hook
+    let _ = std::panic::take_hook();
+    let tid = rx.recv().unwrap();
+
+    let path = std::fs::read_dir(directory.path())
+        .expect("Failed to list minidumps")
+        .next()
+        .expect("No minidump was written")
+        .unwrap()
+        .path();
+    let dump = Minidump::read_path(path).expect("Failed to parse minidump");
+    let exception: MinidumpException = dump.get_stream().expect("Couldn't find MinidumpException");
+    assert_eq!(
+        exception.raw.exception_record.exception_code,
+        PANIC_EXCEPTION_CODE
+    );
+    assert_eq!(exception.get_crashing_thread_id(), tid);
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    assert!(threads.get_thread(tid).is_some());
+
+    let assertion: MinidumpAssertion = dump.get_stream().expect("Couldn't find MinidumpAssertion");
+    assert_eq!(assertion.expression().as_deref(), Some("out of widgets"));
+    assert_eq!(assertion.file().as_deref(), Some(file!()));
+    let info: MinidumpCrashpadInfo = dump.get_stream().expect("Couldn't find CrashpadInfo");
+    let annotations = &info.simple_annotations;
+    assert_eq!(
+        annotations.get(PANIC_ANNOTATION).map(String::as_str),
+        Some("out of widgets")
+    );
+    let reason = dump
+        .get_raw_stream(MDExtraStreamType::CrashReason as u32)
+        .expect("Couldn't find CrashReason");
+    assert_eq!(reason, b"out of widgets");
+    let location = format!("{}:{}:", file!(), assertion.raw.line);
+    assert!(annotations[PANIC_LOCATION_ANNOTATION].starts_with(&location));
+    assert_eq!(
+        annotations.get("channel").map(String::as_str),
+        Some("nightly")
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn xstate() {
+    let num_of_threads = 3;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let mut tmpfile = tempfile::Builder::new()
+        .prefix("xstate")
+        .tempfile()
+        .unwrap();
+
+    let mut tmp = MinidumpWriter::new(pid, pid);
+    let _ = tmp.dump(&mut tmpfile).expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+    let threads: MinidumpThreadList = dump.get_stream().expect("Couldn't find MinidumpThreadList");
+    let xstate = dump
+        .get_raw_stream(MDExtraStreamType::LinuxXState as u32)
+        .expect("Couldn't find LinuxXState");
+
+    // <enabled_features: u64><xsave_size: u32><thread_count: u32><features: [(u32, u32); 64]>
+    // followed by <thread_id: u32, data_size: u32, rva: u32>...
+    let read_u32 = |off: usize| u32::from_ne_bytes(xstate[off..off + 4].try_into().unwrap());
+    let enabled_features = u64::from_ne_bytes(xstate[0..8].try_into().unwrap());
+    // x87 and SSE state are always enabled
+    assert_eq!(enabled_features & 0b11, 0b11);
+    let xsave_size = read_u32(8);
+    assert!(xsave_size >= 512);
+    let count = read_u32(12) as usize;
+    assert_eq!(count, threads.threads.len());
+    assert_eq!(xstate.len(), 528 + count * 12);
+
+    for idx in 0..count {
+        let entry = 528 + idx * 12;
+        assert!(threads.get_thread(read_u32(entry)).is_some());
+        let data_size = read_u32(entry + 4);
+        assert!(data_size >= 512 && data_size <= xsave_size);
+    }
+
+    // The layout of the XSAVE areas is described in the misc info as well
+    let misc_info: MinidumpMiscInfo = dump.get_stream().expect("Couldn't find MinidumpMiscInfo");
+    let xstate_data = misc_info
+        .raw
+        .xstate_data()
+        .expect("Couldn't find the XSTATE configuration");
+    assert_eq!(xstate_data.enabled_features, enabled_features);
+    assert_eq!(xstate_data.context_size, xsave_size);
+    for (idx, feature) in xstate_data.iter() {
+        let offset = 16 + idx * 8;
+        assert_eq!(
+            (feature.offset, feature.size),
+            (read_u32(offset), read_u32(offset + 4))
+        );
+    }
+}
+
+contextual_test! {
+    fn file_descriptors(context: Context) {
+        let num_of_files = 5;
+        let mut child = start_child_and_wait_for_create_files(num_of_files);
+        let pid = child.id() as i32;
+
+        let mut tmpfile = tempfile::Builder::new()
+            .prefix("testfiles")
+            .tempfile()
+            .unwrap();
+
+        let mut tmp = context.minidump_writer(pid);
+        let _ = tmp.dump(&mut tmpfile).expect("Could not write minidump");
+        child.kill().expect("Failed to kill process");
+
+        // Reap child
+        let waitres = child.wait().expect("Failed to wait for child");
+        let status = waitres.signal().expect("Child did not die due to signal");
+        assert_eq!(waitres.code(), None);
+        assert_eq!(status, Signal::SIGKILL as i32);
+
+        // Read dump file and check its contents. There should be a truncated minidump available
+        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+        let fds: MinidumpHandleDataStream = dump.get_stream().expect("Couldn't find MinidumpHandleDataStream");
+        // We check that we create num_of_files plus stdin, stdout and stderr
+        for i in 0..3 {
+            let descriptor = fds.handles.get(i).expect("Descriptor should be present");
+            let fd = *descriptor.raw.handle().expect("Handle should be populated");
+            assert_eq!(fd, i as u64);
+        }
+
+        let non_std_files = &fds.handles[3..];
+
+        // We need to handle the android case where additional pipes might be opened and
+        // interspersed with the test_files (emulator? adb?) so that CI doesn't sporadically fail
+        for i in 0..num_of_files {
+            if !non_std_files.iter().any(|descriptor| {
+                let Some(name) = &descriptor.object_name else { return false; };
+                let Some(file_name) = name.rsplit_once('/').map(|(_, fname)| fname) else { return false; };
+                if !file_name.starts_with("test_file") {
+                    return false;
+                }
+
+                file_name.ends_with(&i.to_string())
+            }) {
+                panic!("unable to locate expected file `test_file{i}` in file handle stream");
+            }
+        }
+    }
+}
+
+#[test]
+fn minidump_size_limit() {
+    let num_of_threads = 40;
+    let mut child = start_child_and_wait_for_threads(num_of_threads);
+    let pid = child.id() as i32;
+
+    let mut total_normal_stack_size = 0;
+    let normal_file_size;
+    // First, write a minidump with no size limit.
+    {
+        let mut tmpfile = tempfile::Builder::new()
+            .prefix("write_dump_unlimited")
+            .tempfile()
+            .unwrap();
+
+        MinidumpWriter::new(pid, pid)
+            .dump(&mut tmpfile)
+            .expect("Could not write minidump");
+
+        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
+        assert!(meta.len() > 0);
+
+        normal_file_size = meta.len();
+
+        // Read dump file and check its contents
+        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
+        let thread_list: MinidumpThreadList =
+            dump.get_stream().expect("Couldn't find MinidumpThreadList");
+        for thread in thread_list.threads {
+            assert!(thread.raw.thread_id > 0);
+            assert!(thread.raw.stack.memory.data_size > 0);
+            total_normal_stack_size += thread.raw.stack.memory.data_size;
+        }
+    }
+
+    // Second, write a minidump with a size limit big enough to not trigger
+    // anything.
+    {
+        // Set size limit arbitrarily 2MiB larger than the normal file size -- such
+        // that the limiting code will not kick in.
+        let minidump_size_limit = normal_file_size + 2 * 1024 * 1024;
+
+        let mut tmpfile = tempfile::Builder::new()
+            .prefix("write_dump_pseudolimited")
+            .tempfile()
+            .unwrap();
+
+        MinidumpWriter::new(pid, pid)
+            .set_minidump_size_limit(minidump_size_limit)
+            .dump(&mut tmpfile)
+            .expect("Could not write minidump");
+
+        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
+
+        // Make sure limiting wasn't actually triggered.  NOTE: If you fail this,
+        // first make sure that "minidump_size_limit" above is indeed set to a
+        // large enough value -- the limit-checking code in minidump_writer.rs
+        // does just a rough estimate.
+        // TODO: Fix this properly
+        //assert_eq!(meta.len(), normal_file_size);
+        let min = std::cmp::min(meta.len(), normal_file_size);
+        let max = std::cmp::max(meta.len(), normal_file_size);
+
+        // Setting a stack limit limits the size of non-main stacks even before
+        // the limit is reached. This will cause slight variations in size
+        // between a limited and an unlimited minidump.
+        assert!(max - min < 1024, "max = {max:} min = {min:}");
+    }
+
+    // Third, write a minidump with a size limit small enough to be triggered.
+    {
+        // Set size limit to some arbitrary amount, such that the limiting code
+        // will kick in.  The equation used to set this value was determined by
+        // simply reversing the size-limit logic a little bit in order to pick a
+        // size we know will trigger it.
+
+        // Copyied from sections/thread_list_stream.rs
+        const LIMIT_AVERAGE_THREAD_STACK_LENGTH: u64 = 8 * 1024;
+        let mut minidump_size_limit = LIMIT_AVERAGE_THREAD_STACK_LENGTH * 40;
+
+        // If, in reality, each of the threads' stack is *smaller* than
+        // kLimitAverageThreadStackLength, the normal file size could very well be
+        // smaller than the arbitrary limit that was just set.  In that case,
+        // either of these numbers should trigger the size-limiting code, but we
+        // might as well pick the smallest.
+        if normal_file_size < minidump_size_limit {
+            minidump_size_limit = normal_file_size;
+        }
+
+        let mut tmpfile = tempfile::Builder::new()
+            .prefix("write_dump_limited")
+            .tempfile()
+            .unwrap();
+
+        MinidumpWriter::new(pid, pid)
+            .set_minidump_size_limit(minidump_size_limit)
+            .dump(&mut tmpfile)
+            .expect("Could not write minidump");
+
+        let meta = std::fs::metadata(tmpfile.path()).expect("Couldn't get metadata for tempfile");
+        assert!(meta.len() > 0);
+        // Make sure the file size is at least smaller than the original.  If this
+        // fails because it's the same size, then the size-limit logic didn't kick
+        // in like it was supposed to.
+        assert!(meta.len() < normal_file_size);
+
+        let mut total_limit_stack_size = 0;
+        // Read dump file and check its contents
+        let dump = Minidump::read_path(tmpfile.path()).expect("Failed to read minidump");
         let thread_list: MinidumpThreadList =
             dump.get_stream().expect("Couldn't find MinidumpThreadList");
         for thread in thread_list.threads {
@@ -773,6 +3318,15 @@ fn with_deleted_binary() {
 
     // The 'age'/appendix, always 0 on non-windows targets
     assert_eq!(did.appendix(), 0);
+
+    // The main module is flagged as replaced on disk
+    let replaced = dump
+        .get_raw_stream(MDExtraStreamType::LinuxReplacedModules as u32)
+        .expect("Couldn't find LinuxReplacedModules");
+    let replaced = String::from_utf8_lossy(replaced);
+    assert!(replaced
+        .lines()
+        .any(|line| line.split('\t').nth(2) == Some(binary_copy.to_string_lossy().as_ref())));
 }
 
 #[test]
@@ -796,3 +3350,44 @@ fn memory_info_list_stream() {
     let list: MinidumpMemoryInfoList = dump.get_stream().expect("no memory info list");
     assert!(list.iter().count() > 1);
 }
+
+#[test]
+fn list_modules() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+
+    let modules = minidump_writer::module_list::list_modules(pid).expect("Could not list modules");
+    let dump = MinidumpWriter::new(pid, pid)
+        .dump_to_vec()
+        .expect("Could not write minidump");
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+
+    let dump = Minidump::read(dump).expect("Failed to parse minidump");
+    let module_list: MinidumpModuleList = dump
+        .get_stream()
+        .expect("Couldn't find stream MinidumpModuleList");
+    assert!(!modules.is_empty());
+    // The modules are identified like they are in dumps
+    for module in &modules {
+        let dumped = module_list
+            .module_at_address(module.base_address)
+            .expect("Couldn't find module in the dump");
+        assert_eq!(dumped.code_file(), module.path);
+        assert_eq!(dumped.size(), module.size);
+        assert_eq!(
+            dumped.debug_identifier(),
+            Some(module.debug_id.parse().unwrap())
+        );
+        assert_eq!(
+            dumped.code_identifier().map(|id| id.as_str().to_owned()),
+            Some(module.code_id.clone())
+        );
+    }
+    assert_eq!(
+        module_list
+            .main_module()
+            .map(|module| module.base_address()),
+        Some(modules[0].base_address)
+    );
+}
@@ -99,6 +99,7 @@ fn test_thread_list_from_parent() {
     .expect("Couldn't init dumper");
     assert_eq!(dumper.threads.len(), num_of_threads);
     dumper.suspend_threads().expect("Could not suspend threads");
+    dumper.capture_thread_infos();
 
     // let mut matching_threads = 0;
     for (idx, curr_thread) in dumper.threads.iter().enumerate() {
@@ -106,6 +107,14 @@ fn test_thread_list_from_parent() {
         let info = dumper
             .get_thread_info_by_index(idx)
             .expect("Could not get thread info by index");
+        let captured = dumper
+            .captured_thread_info(idx)
+            .expect("Could not get captured thread info");
+        assert_eq!(captured.stack_pointer, info.stack_pointer);
+        assert_eq!(
+            captured.get_instruction_pointer(),
+            info.get_instruction_pointer()
+        );
         let (_valid_stack_ptr, stack_len) = dumper
             .get_stack_info(info.stack_pointer)
             .expect("Could not get stack_pointer");
@@ -161,6 +170,33 @@ fn test_thread_list_from_parent() {
     // assert_eq!(matching_threads, num_of_threads);
 }
 
+#[test]
+fn test_same_namespaces() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+    let dumper = PtraceDumper::new(
+        pid,
+        minidump_writer::minidump_writer::STOP_TIMEOUT,
+        Default::default(),
+    )
+    .expect("Couldn't init dumper");
+
+    // The child shares our namespaces, so nothing needs translating
+    assert_eq!(dumper.root, None);
+    assert_eq!(
+        dumper.resolve_path(std::path::Path::new("/usr/lib/libc.so.6")),
+        std::path::Path::new("/usr/lib/libc.so.6")
+    );
+    for thread in &dumper.threads {
+        assert_eq!(dumper.translate_ns_tid(thread.tid), Some(thread.tid));
+    }
+    assert_eq!(dumper.translate_ns_tid(i32::MAX), None);
+
+    drop(dumper);
+    child.kill().expect("Failed to kill process");
+    child.wait().expect("Failed to wait for child");
+}
+
 // #[cfg(not(any(target_arch = "mips", target_arch = "arm-eabi"))]
 #[cfg(not(target_arch = "mips"))]
 #[test]
@@ -175,6 +211,120 @@ fn test_linux_gate_mapping_id() {
     spawn_child("linux_gate_mapping_id", &[]);
 }
 
+#[test]
+fn test_thread_info_of_exited_thread() {
+    let mut child = start_child_and_wait_for_threads(3);
+    let pid = child.id() as i32;
+    let mut dumper = PtraceDumper::new(
+        pid,
+        minidump_writer::minidump_writer::STOP_TIMEOUT,
+        Default::default(),
+    )
+    .expect("Couldn't init dumper");
+    dumper.suspend_threads().expect("Could not suspend threads");
+
+    // Simulate a thread which exited after being enumerated with the pid of
+    // a process which is gone
+    let mut exited = std::process::Command::new("true")
+        .spawn()
+        .expect("Failed to spawn process");
+    let tid = exited.id() as i32;
+    exited.wait().expect("Failed to wait for process");
+    dumper
+        .threads
+        .push(minidump_writer::ptrace_dumper::Thread { tid, name: None });
+
+    let Err(error) = dumper.get_thread_info_by_index(dumper.threads.len() - 1) else {
+        panic!("Got thread info of an exited thread");
+    };
+    assert!(error.is_thread_gone(), "{error:?}");
+
+    dumper.threads.pop();
+    dumper.resume_threads().expect("Failed to resume threads");
+    child.kill().expect("Failed to kill process");
+
+    // Reap child
+    let waitres = child.wait().expect("Failed to wait for child");
+    let status = waitres.signal().expect("Child did not die due to signal");
+    assert_eq!(waitres.code(), None);
+    assert_eq!(status, Signal::SIGKILL as i32);
+}
+
+#[test]
+fn test_select_threads() {
+    let mut child = start_child_and_wait_for_threads(5);
+    let pid = child.id() as i32;
+    let mut dumper = PtraceDumper::new(
+        pid,
+        minidump_writer::minidump_writer::STOP_TIMEOUT,
+        Default::default(),
+    )
+    .expect("Couldn't init dumper");
+
+    assert_eq!(dumper.select_threads(5, pid), 0);
+    assert_eq!(dumper.threads.len(), 5);
+
+    // The blamed thread comes first, followed by the main thread
+    let blamed_thread = dumper.threads.iter().find(|t| t.tid != pid).unwrap().tid;
+    assert_eq!(dumper.select_threads(2, blamed_thread), 3);
+    let tids: Vec<_> = dumper.threads.iter().map(|t| t.tid).collect();
+    assert_eq!(tids, [blamed_thread, pid]);
+
+    child.kill().expect("Failed to kill process");
+
+    // Reap child
+    let waitres = child.wait().expect("Failed to wait for child");
+    let status = waitres.signal().expect("Child did not die due to signal");
+    assert_eq!(waitres.code(), None);
+    assert_eq!(status, Signal::SIGKILL as i32);
+}
+
+#[test]
+fn test_unmapped_regions() {
+    let mut child = start_child_and_wait_for_threads(1);
+    let pid = child.id() as i32;
+    let dumper = PtraceDumper::new(
+        pid,
+        minidump_writer::minidump_writer::STOP_TIMEOUT,
+        Default::default(),
+    )
+    .expect("Couldn't init dumper");
+
+    let mut ranges: Vec<_> = dumper
+        .mappings
+        .iter()
+        .map(|m| (m.start_address, m.start_address + m.size))
+        .collect();
+    ranges.sort_unstable();
+    let (hole_start, hole_end) = ranges
+        .windows(2)
+        .map(|w| (w[0].1, w[1].0))
+        .find(|(start, end)| start < end)
+        .expect("No hole in the address space");
+
+    // A stack pointer pointing in the middle of the hole
+    let address = hole_start + (hole_end - hole_start) / 2;
+    assert_eq!(
+        dumper.find_unmapped_region(address),
+        Some(hole_start..hole_end)
+    );
+    assert_eq!(dumper.find_unmapped_region(ranges[0].0), None);
+
+    let (start, len) = dumper
+        .find_nearest_readable(address, 16)
+        .expect("No readable memory found");
+    assert!(len > 0 && len <= 16);
+    assert!(start + len <= hole_start || start >= hole_end);
+
+    child.kill().expect("Failed to kill process");
+
+    // Reap child
+    let waitres = child.wait().expect("Failed to wait for child");
+    let status = waitres.signal().expect("Child did not die due to signal");
+    assert_eq!(waitres.code(), None);
+    assert_eq!(status, Signal::SIGKILL as i32);
+}
+
 #[test]
 fn test_merged_mappings() {
     let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE).unwrap();
@@ -1,7 +1,10 @@
 //! All of these tests are specific to the MacOS task dumper
 #![cfg(target_os = "macos")]
 
-use minidump_writer::{mach::LoadCommand, task_dumper::TaskDumper};
+use minidump_writer::{
+    mach::LoadCommand,
+    task_dumper::{ProcessArgs, TaskDumper},
+};
 use std::fmt::Write;
 
 fn call_otool(args: &[&str]) -> String {
@@ -156,3 +159,122 @@ fn iterates_load_commands() {
 
     similar_asserts::assert_eq!(expected, actual);
 }
+
+/// Validates the threads of the task are listed once, so they stay the same
+/// when threads come and go
+#[test]
+fn snapshots_threads() {
+    // SAFETY: syscall
+    let dumper = TaskDumper::new(unsafe { mach2::traps::mach_task_self() });
+    let threads = dumper.threads().expect("failed to list threads").to_vec();
+    assert!(!threads.is_empty());
+
+    let spawned = std::thread::spawn(|| std::thread::sleep(std::time::Duration::from_millis(100)));
+    assert_eq!(
+        *dumper.threads().expect("failed to list threads"),
+        threads[..]
+    );
+    spawned.join().expect("failed to join thread");
+}
+
+/// Validates the shared cache read from dyld matches the one dyld reports
+#[test]
+fn reads_shared_cache() {
+    extern "C" {
+        fn _dyld_get_shared_cache_uuid(uuid: *mut u8) -> bool;
+        fn _dyld_get_shared_cache_range(length: *mut usize) -> *const std::ffi::c_void;
+    }
+
+    // SAFETY: syscall
+    let dumper = TaskDumper::new(unsafe { mach2::traps::mach_task_self() });
+    let shared_cache = dumper
+        .read_all_images_info()
+        .expect("failed to read dyld_all_image_infos")
+        .shared_cache()
+        .expect("the process doesn't use a shared cache");
+
+    let mut uuid = [0u8; 16];
+    let mut length = 0;
+    // SAFETY: the UUID buffer is 16 bytes, as expected
+    unsafe {
+        assert!(_dyld_get_shared_cache_uuid(uuid.as_mut_ptr()));
+        assert_eq!(
+            _dyld_get_shared_cache_range(&mut length) as u64,
+            shared_cache.base_address
+        );
+    }
+    assert_eq!(shared_cache.uuid, uuid);
+    assert!(length > 0);
+}
+
+/// Validates the memory map covers the code of the test, mapped from its
+/// executable
+#[test]
+fn lists_memory_map() {
+    // SAFETY: syscall
+    let dumper = TaskDumper::new(unsafe { mach2::traps::mach_task_self() });
+    let mappings = dumper.memory_map();
+    assert!(mappings
+        .windows(2)
+        .all(|pair| pair[0].range.end <= pair[1].range.start));
+
+    let code = lists_memory_map as usize as u64;
+    let mapping = mappings
+        .iter()
+        .find(|mapping| mapping.range.contains(&code))
+        .expect("the code of the test isn't mapped");
+    assert!(mapping.protection.read && mapping.protection.execute);
+    assert!(!mapping.protection.write);
+    let backing_file = mapping
+        .backing_file
+        .as_ref()
+        .expect("the code isn't mapped from a file");
+    assert_eq!(
+        std::fs::canonicalize(backing_file).unwrap(),
+        std::fs::canonicalize(std::env::current_exe().unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn reads_process_args() {
+    use std::os::unix::ffi::OsStrExt;
+
+    // SAFETY: syscall
+    let dumper = TaskDumper::new(unsafe { mach2::traps::mach_task_self() });
+    let process_args = dumper
+        .read_process_args()
+        .expect("failed to read the arguments");
+
+    let args: Vec<_> = std::env::args_os()
+        .map(|arg| arg.as_bytes().to_vec())
+        .collect();
+    assert_eq!(process_args.args, args);
+    let env: Vec<_> = std::env::vars_os()
+        .map(|(name, value)| [name.as_bytes(), b"=", value.as_bytes()].concat())
+        .collect();
+    for variable in &env {
+        assert!(process_args.env.contains(variable));
+    }
+}
+
+#[test]
+fn parses_process_args() {
+    let mut buffer = 2i32.to_ne_bytes().to_vec();
+    buffer.extend_from_slice(
+        b"/bin/app\0\0\0\0app\0\0HOME=/Users/me\0LANG=C\0\0executable_path=/bin/app\0",
+    );
+    assert_eq!(
+        ProcessArgs::parse(&buffer),
+        Some(ProcessArgs {
+            executable_path: b"/bin/app".to_vec(),
+            args: vec![b"app".to_vec(), Vec::new()],
+            env: vec![b"HOME=/Users/me".to_vec(), b"LANG=C".to_vec()],
+        })
+    );
+
+    // Fewer arguments than argc
+    let mut buffer = 3i32.to_ne_bytes().to_vec();
+    buffer.extend_from_slice(b"/bin/app\0app\0");
+    assert_eq!(ProcessArgs::parse(&buffer), None);
+    assert_eq!(ProcessArgs::parse(b"\0\0"), None);
+}